@@ -0,0 +1,29 @@
+//! Changes of the index the bot notifies about
+
+/// What happened to a version in the index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionKind {
+    NewVersion,
+    Yanked,
+    Unyanked,
+}
+
+impl ActionKind {
+    /// Name of the action stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActionKind::NewVersion => "new",
+            ActionKind::Yanked => "yanked",
+            ActionKind::Unyanked => "unyanked",
+        }
+    }
+
+    pub fn parse(action: &str) -> Option<Self> {
+        match action {
+            "new" => Some(ActionKind::NewVersion),
+            "yanked" => Some(ActionKind::Yanked),
+            "unyanked" => Some(ActionKind::Unyanked),
+            _ => None,
+        }
+    }
+}
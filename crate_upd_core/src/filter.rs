@@ -0,0 +1,289 @@
+//! Per-subscription filters of versions to notify about
+use std::{convert::TryFrom, fmt};
+
+use kacl_parser::VersionScheme;
+
+use crate::glob_match;
+
+/// Magnitude of a version bump, from the smallest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Bump {
+    Patch,
+    Minor,
+    Major,
+}
+
+impl Bump {
+    /// Bump from `previous` to `new` compared by `scheme`, judged by the first differing component
+    /// (so `0.3.1 -> 0.4.0` is a minor bump, even though it's breaking). `None` if it can't be
+    /// told, e.g. for versions compared as strings.
+    pub fn between(scheme: VersionScheme, previous: &str, new: &str) -> Option<Self> {
+        scheme.bump(previous, new).map(|component| match component {
+            0 => Bump::Major,
+            1 => Bump::Minor,
+            _ => Bump::Patch,
+        })
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "patch" => Some(Bump::Patch),
+            "minor" => Some(Bump::Minor),
+            "major" => Some(Bump::Major),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Bump::Patch => "patch",
+            Bump::Minor => "minor",
+            Bump::Major => "major",
+        }
+    }
+}
+
+/// Which releases of a crate a subscriber is notified about.
+/// In the config it's a list of [`Filter::WORDS`], e.g. `["minor", "skip-prerelease"]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "Vec<String>", into = "Vec<String>")]
+pub struct Filter {
+    /// Releases with smaller bumps are skipped
+    pub min_bump: Bump,
+    pub skip_prerelease: bool,
+    /// Notifications list changes of dependency requirements
+    pub show_deps: bool,
+    /// Notifications tell if the README changed materially
+    pub readme: bool,
+}
+
+impl Default for Filter {
+    /// Notify about everything
+    fn default() -> Self {
+        Filter {
+            min_bump: Bump::Patch,
+            skip_prerelease: false,
+            show_deps: false,
+            readme: false,
+        }
+    }
+}
+
+impl Filter {
+    /// Words accepted by [`Filter::apply`]
+    pub const WORDS: [&'static str; 9] = [
+        "major",
+        "minor",
+        "patch",
+        "skip-prerelease",
+        "include-prerelease",
+        "show-deps",
+        "hide-deps",
+        "readme",
+        "no-readme",
+    ];
+
+    pub fn is_word(s: &str) -> bool {
+        Self::WORDS.contains(&s)
+    }
+
+    /// Changes the filter according to `word` (one of [`Filter::WORDS`]).
+    /// Returns `false` if the word is unknown.
+    pub fn apply(&mut self, word: &str) -> bool {
+        match word {
+            "skip-prerelease" => self.skip_prerelease = true,
+            "include-prerelease" => self.skip_prerelease = false,
+            "show-deps" => self.show_deps = true,
+            "hide-deps" => self.show_deps = false,
+            "readme" => self.readme = true,
+            "no-readme" => self.readme = false,
+            _ => match Bump::parse(word) {
+                Some(bump) => self.min_bump = bump,
+                None => return false,
+            },
+        }
+
+        true
+    }
+
+    /// Whether to notify about `new`, `previous` is the newest version older than `new`.
+    /// Releases with a bump which can't be told aren't filtered by it.
+    pub fn matches(&self, scheme: VersionScheme, new: &str, previous: Option<&str>) -> bool {
+        if self.skip_prerelease && scheme.is_prerelease(new) {
+            return false;
+        }
+
+        previous
+            .and_then(|previous| Bump::between(scheme, previous, new))
+            .map_or(true, |bump| bump >= self.min_bump)
+    }
+}
+
+/// Which updates a target configured by the operator (a hook or a matrix room) gets
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct Selector {
+    /// Globs of crate names (`myreg:*` for crates of an alternative registry), all crates if empty
+    #[serde(default)]
+    pub crates: Vec<String>,
+    /// Words of `/filter`, e.g. `["minor", "skip-prerelease"]`
+    #[serde(default)]
+    pub filter: Filter,
+    /// Don't send yanks and unyanks
+    #[serde(default)]
+    pub skip_yanks: bool,
+}
+
+impl Selector {
+    /// Whether the update of the crate with `key` should be sent, `previous` is the newest
+    /// version older than `version` by the crate's `scheme`
+    pub fn matches(
+        &self,
+        key: &str,
+        is_yank: bool,
+        scheme: VersionScheme,
+        version: &str,
+        previous: Option<&str>,
+    ) -> bool {
+        if self.skip_yanks && is_yank {
+            return false;
+        }
+        if !self.crates.is_empty() && !self.crates.iter().any(|glob| glob_match(glob, key)) {
+            return false;
+        }
+
+        self.filter.matches(scheme, version, previous)
+    }
+}
+
+impl TryFrom<Vec<String>> for Filter {
+    type Error = String;
+
+    fn try_from(words: Vec<String>) -> Result<Self, Self::Error> {
+        let mut filter = Filter::default();
+        for word in &words {
+            if !filter.apply(word) {
+                return Err(format!("unknown filter word: {}", word));
+            }
+        }
+
+        Ok(filter)
+    }
+}
+
+impl From<Filter> for Vec<String> {
+    /// The shortest list of words giving `filter`, empty for the default one
+    fn from(filter: Filter) -> Self {
+        let mut words = Vec::new();
+        if filter.min_bump != Bump::Patch {
+            words.push(filter.min_bump.as_str().to_owned());
+        }
+        if filter.skip_prerelease {
+            words.push(String::from("skip-prerelease"));
+        }
+        if filter.show_deps {
+            words.push(String::from("show-deps"));
+        }
+        if filter.readme {
+            words.push(String::from("readme"));
+        }
+
+        words
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self.min_bump {
+            Bump::Patch => "all releases",
+            Bump::Minor => "minor and major releases",
+            Bump::Major => "major releases",
+        })?;
+        if self.skip_prerelease {
+            f.write_str(" except prereleases")?;
+        }
+        if self.show_deps {
+            f.write_str(" with dependency changes")?;
+        }
+        if self.readme {
+            f.write_str(if self.show_deps {
+                " and README changes"
+            } else {
+                " with README changes"
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use VersionScheme::{Lenient, Lexicographic, Semver};
+
+    #[test]
+    fn bumps() {
+        assert_eq!(Bump::between(Semver, "1.2.3", "1.2.4"), Some(Bump::Patch));
+        assert_eq!(Bump::between(Semver, "1.2.3", "1.3.0"), Some(Bump::Minor));
+        assert_eq!(Bump::between(Semver, "0.3.1", "0.4.0"), Some(Bump::Minor));
+        assert_eq!(
+            Bump::between(Semver, "1.2.3", "2.0.0-rc.1"),
+            Some(Bump::Major)
+        );
+        assert_eq!(
+            Bump::between(Lenient, "2021.06.01", "2021.07.01"),
+            Some(Bump::Minor)
+        );
+        assert_eq!(Bump::between(Lexicographic, "a", "b"), None);
+    }
+
+    #[test]
+    fn matches() {
+        let mut filter = Filter::default();
+        assert!(filter.matches(Semver, "1.2.4-alpha", Some("1.2.3")));
+
+        assert!(filter.apply("minor"));
+        assert!(filter.apply("skip-prerelease"));
+        assert!(!filter.apply("sometimes"));
+        assert!(!filter.matches(Semver, "1.2.4", Some("1.2.3")));
+        assert!(filter.matches(Semver, "1.3.0", Some("1.2.3")));
+        assert!(filter.matches(Semver, "2.0.0", Some("1.2.3")));
+        assert!(!filter.matches(Semver, "2.0.0-rc.1", Some("1.2.3")));
+        // the first release
+        assert!(filter.matches(Semver, "0.1.0", None));
+
+        // date-based versions
+        assert!(!filter.matches(Lenient, "2021.06.02", Some("2021.06.01")));
+        assert!(filter.matches(Lenient, "2021.07.01", Some("2021.06.01")));
+        assert!(!filter.matches(Lenient, "2021.07.01-rc1", Some("2021.06.01")));
+        // the bump is unknown
+        assert!(filter.matches(Lexicographic, "nightly-b", Some("nightly-a")));
+    }
+
+    #[test]
+    fn from_words() {
+        let words = |words: &[&str]| words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        assert_eq!(Filter::try_from(words(&[])), Ok(Filter::default()));
+        assert_eq!(
+            Filter::try_from(words(&["major", "skip-prerelease", "show-deps", "readme"])),
+            Ok(Filter {
+                min_bump: Bump::Major,
+                skip_prerelease: true,
+                show_deps: true,
+                readme: true,
+            })
+        );
+        assert!(Filter::try_from(words(&["sometimes"])).is_err());
+
+        let filter = Filter {
+            min_bump: Bump::Minor,
+            skip_prerelease: true,
+            show_deps: false,
+            readme: false,
+        };
+        assert_eq!(Vec::from(filter), words(&["minor", "skip-prerelease"]));
+        assert_eq!(Filter::try_from(Vec::from(filter)), Ok(filter));
+        assert!(Vec::from(Filter::default()).is_empty());
+    }
+}
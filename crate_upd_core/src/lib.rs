@@ -0,0 +1,16 @@
+//! Core of crate_upd_bot without telegram: events of the index, filters of versions, semver
+//! requirements, and the traits of notification backends and of the storage of releases. The
+//! index watcher of the bot is written against [`Storage`], announcing releases to subscribers
+//! isn't part of the library.
+pub use event::ActionKind;
+pub use glob::glob_match;
+pub use notifier::Notifier;
+pub use storage::Storage;
+pub use version_req::VersionReq;
+
+pub mod event;
+pub mod filter;
+mod glob;
+pub mod notifier;
+pub mod storage;
+pub mod version_req;
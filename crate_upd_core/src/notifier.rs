@@ -0,0 +1,11 @@
+//! Backends delivering notifications
+
+/// Backend delivering notifications to its chats.
+/// Messages are queued and sent in the background, paced to the backend's limits.
+pub trait Notifier {
+    /// Chat id, room id, etc.
+    type Target;
+
+    /// Queues a notification in telegram html, `quiet` ones don't make a sound
+    fn push(&self, target: Self::Target, html: String, quiet: bool);
+}
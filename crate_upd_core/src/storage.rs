@@ -0,0 +1,50 @@
+//! Storage of the releases seen in the index
+
+use std::fmt;
+
+use crate::event::ActionKind;
+
+/// Record of index entries and releases, which keeps the watcher from announcing a release twice
+/// and lets releases left pending by a crash or a restart be announced again
+#[async_trait::async_trait]
+pub trait Storage: Send + Sync {
+    type Error: fmt::Display + Send;
+
+    /// Records the index entry of the version (`hash` is a hash of the entry), returns its
+    /// yanked status when it was seen before. `None` if the version is new or its notifications
+    /// aren't all sent yet.
+    async fn see_version(
+        &self,
+        krate: &str,
+        version: &str,
+        yanked: bool,
+        hash: &str,
+    ) -> Result<Option<bool>, Self::Error>;
+
+    /// Adds the release to the archive (or updates its yanked status), `action` is pending until
+    /// [`Storage::finish_release`]
+    async fn record_release(
+        &self,
+        krate: &str,
+        version: &str,
+        yanked: bool,
+        published_at: i64,
+        action: ActionKind,
+    ) -> Result<(), Self::Error>;
+
+    /// Marks the pending action of the release as done
+    async fn finish_release(&self, krate: &str, version: &str) -> Result<(), Self::Error>;
+
+    /// Releases whose notifications aren't all sent: crate, version, action and unix time of
+    /// the release, oldest first
+    async fn pending_releases(&self) -> Result<Vec<(String, String, String, i64)>, Self::Error>;
+
+    /// Recorded versions of the crate: version, yanked and the pending action, if any
+    async fn release_states(
+        &self,
+        krate: &str,
+    ) -> Result<Vec<(String, bool, Option<String>)>, Self::Error>;
+
+    /// Names of crates which have at least one subscriber, the ones polled in sparse indexes
+    async fn list_subscribed_crates(&self) -> Result<Vec<String>, Self::Error>;
+}
@@ -0,0 +1,213 @@
+//! Semver requirements of versions, read like cargo reads the ones of dependencies
+use std::fmt;
+
+use versions::SemVer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+}
+
+/// Semver requirement, e.g. `>=2.0`, `^1.4.2`, `~0.3`, `1.*` or `>=1.2, <1.5`
+#[derive(Debug, PartialEq, Eq)]
+pub struct VersionReq {
+    /// Comparators the version has to match, all of them
+    bounds: Vec<(Op, SemVer)>,
+    /// `major.minor.patch` of prereleases in the requirement, a prerelease matches only if it's
+    /// one of them (`>=2.0.0-rc.1` matches `2.0.0-rc.2`, but not `2.1.0-alpha`)
+    prereleases: Vec<(u32, u32, u32)>,
+    /// Comparators as written
+    text: Vec<String>,
+}
+
+fn version(major: u32, minor: u32, patch: u32, pre: Option<&str>) -> Option<SemVer> {
+    match pre {
+        Some(pre) => SemVer::new(&format!("{}.{}.{}-{}", major, minor, patch, pre)),
+        None => SemVer::new(&format!("{}.{}.{}", major, minor, patch)),
+    }
+}
+
+impl VersionReq {
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut req = VersionReq {
+            bounds: Vec::new(),
+            prereleases: Vec::new(),
+            text: Vec::new(),
+        };
+        for comparator in s.split(',') {
+            let comparator = comparator.trim();
+            if comparator.is_empty() {
+                return None;
+            }
+            req.push(comparator)?;
+            req.text.push(comparator.split_whitespace().collect());
+        }
+
+        Some(req)
+    }
+
+    fn push(&mut self, comparator: &str) -> Option<()> {
+        let (op, rest) = [">=", "<=", ">", "<", "=", "^", "~"]
+            .iter()
+            .find_map(|&op| Some((op, comparator.strip_prefix(op)?)))
+            .unwrap_or(("", comparator));
+        let rest = rest.trim();
+        let (numbers, pre) = match rest.split_once('-') {
+            Some((numbers, pre)) if !pre.is_empty() => (numbers, Some(pre)),
+            Some(_) => return None,
+            None => (rest.split('+').next().unwrap_or(rest), None),
+        };
+
+        let mut parts = Vec::new();
+        let mut wildcard = false;
+        for part in numbers.split('.') {
+            match part {
+                "*" | "x" | "X" => wildcard = true,
+                _ if wildcard => return None,
+                _ => parts.push(part.parse::<u32>().ok()?),
+            }
+        }
+        if parts.len() > 3 || (pre.is_some() && parts.len() != 3) {
+            return None;
+        }
+        // `*` matches everything (but prereleases)
+        let major = match parts.first() {
+            Some(&major) => major,
+            None if op.is_empty() => return Some(()),
+            None => return None,
+        };
+        let minor = parts.get(1).copied();
+        let patch = parts.get(2).copied();
+        let lower = version(major, minor.unwrap_or(0), patch.unwrap_or(0), pre)?;
+        // the version after the ones the partial requirement covers, e.g. `1.3.0` for `1.2`
+        let next_partial = match (minor, patch) {
+            (None, _) => version(major.checked_add(1)?, 0, 0, None)?,
+            (Some(minor), None) => version(major, minor.checked_add(1)?, 0, None)?,
+            (Some(_), Some(_)) => lower.clone(),
+        };
+        if pre.is_some() {
+            self.prereleases
+                .push((major, minor.unwrap_or(0), patch.unwrap_or(0)));
+        }
+
+        match op {
+            ">=" => self.bounds.push((Op::GreaterEq, lower)),
+            "<" => self.bounds.push((Op::Less, lower)),
+            ">" if patch.is_some() => self.bounds.push((Op::Greater, lower)),
+            ">" => self.bounds.push((Op::GreaterEq, next_partial)),
+            "<=" if patch.is_some() => self.bounds.push((Op::LessEq, lower)),
+            "<=" => self.bounds.push((Op::Less, next_partial)),
+            "=" if patch.is_some() => {
+                self.bounds.push((Op::GreaterEq, lower.clone()));
+                self.bounds.push((Op::LessEq, lower));
+            }
+            "=" => {
+                self.bounds.push((Op::GreaterEq, lower));
+                self.bounds.push((Op::Less, next_partial));
+            }
+            "~" | "" if wildcard || op == "~" => {
+                let upper = match minor {
+                    Some(minor) => version(major, minor.checked_add(1)?, 0, None)?,
+                    None => version(major.checked_add(1)?, 0, 0, None)?,
+                };
+                self.bounds.push((Op::GreaterEq, lower));
+                self.bounds.push((Op::Less, upper));
+            }
+            // `^` and bare versions: changes compatible by cargo's rules
+            _ => {
+                let upper = match (major, minor, patch) {
+                    (0, Some(0), Some(patch)) => version(0, 0, patch.checked_add(1)?, None)?,
+                    (0, Some(minor), _) => version(0, minor.checked_add(1)?, 0, None)?,
+                    _ => version(major.checked_add(1)?, 0, 0, None)?,
+                };
+                self.bounds.push((Op::GreaterEq, lower));
+                self.bounds.push((Op::Less, upper));
+            }
+        }
+
+        Some(())
+    }
+
+    pub fn matches(&self, version: &SemVer) -> bool {
+        if version.pre_rel.is_some()
+            && !self
+                .prereleases
+                .contains(&(version.major, version.minor, version.patch))
+        {
+            return false;
+        }
+
+        self.bounds.iter().all(|(op, bound)| match op {
+            Op::Less => version < bound,
+            Op::LessEq => version <= bound,
+            Op::Greater => version > bound,
+            Op::GreaterEq => version >= bound,
+        })
+    }
+}
+
+impl fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(req: &str, version: &str) -> bool {
+        VersionReq::parse(req)
+            .unwrap()
+            .matches(&SemVer::new(version).unwrap())
+    }
+
+    #[test]
+    fn requirements() {
+        assert!(matches(">=2.0", "2.0.0"));
+        assert!(matches(">=2.0", "3.1.4"));
+        assert!(!matches(">=2.0", "1.9.9"));
+        assert!(!matches(">=2.0", "2.0.0-rc.1"));
+        assert!(matches(">= 2.0.0-rc.1", "2.0.0-rc.2"));
+        assert!(!matches(">=2.0.0-rc.1", "2.1.0-alpha"));
+
+        assert!(matches(">1.2", "1.3.0"));
+        assert!(!matches(">1.2", "1.2.9"));
+        assert!(matches(">1.2.3", "1.2.4"));
+        assert!(matches("<=1.2", "1.2.9"));
+        assert!(!matches("<=1.2", "1.3.0"));
+        assert!(matches("=1.2", "1.2.5"));
+        assert!(!matches("=1.2.3", "1.2.4"));
+
+        assert!(matches("1.2.3", "1.9.0"));
+        assert!(!matches("^1.2.3", "2.0.0"));
+        assert!(matches("^0.3", "0.3.7"));
+        assert!(!matches("^0.3", "0.4.0"));
+        assert!(!matches("^0.0.3", "0.0.4"));
+        assert!(matches("~1.2", "1.2.7"));
+        assert!(!matches("~1.2", "1.3.0"));
+        assert!(matches("1.2.*", "1.2.7"));
+        assert!(!matches("1.2.*", "1.3.0"));
+        assert!(matches("*", "0.0.1"));
+        assert!(matches(">=1.2, <1.5", "1.4.0"));
+        assert!(!matches(">=1.2, <1.5", "1.5.0"));
+    }
+
+    #[test]
+    fn invalid() {
+        for req in [
+            "", "foo", ">=", "1.2.3.4", "1.*.3", ">=1.2,", "1.2-rc.1", "=>1.2",
+        ]
+        .iter()
+        {
+            assert_eq!(VersionReq::parse(req), None, "{}", req);
+        }
+        assert_eq!(
+            VersionReq::parse(">= 1.2 ,<2").unwrap().to_string(),
+            ">=1.2, <2"
+        );
+    }
+}
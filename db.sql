@@ -6,7 +6,10 @@ create table if not exists crates
   name varchar(64) not null
 );
 
-comment on column crates.name is 'crate names are limited to 64 characters, see https://github.com/rust-lang/crates.io/pull/718';
+alter table crates
+  alter column name type varchar(128);
+
+comment on column crates.name is 'crate names are limited to 64 characters (see https://github.com/rust-lang/crates.io/pull/718), names of crates of alternative registries are prefixed with the registry: `myreg:internal-crate`';
 
 create unique index if not exists crates_name_uindex
   on crates (name);
@@ -25,12 +28,523 @@ create index if not exists subscriptions_user_id_index
   on subscriptions (user_id)
     include (crate_id);
 
+alter table subscriptions
+  add column if not exists min_bump varchar(5) not null default 'patch';
+
+alter table subscriptions
+  add column if not exists skip_prerelease bool not null default false;
+
+alter table subscriptions
+  add column if not exists baseline varchar(128);
+
+alter table subscriptions
+  add column if not exists show_deps bool not null default false;
+
+comment on column subscriptions.show_deps is 'notifications list changes of dependency requirements';
+
+alter table subscriptions
+  add column if not exists readme bool not null default false;
+
+comment on column subscriptions.readme is 'notifications tell if the README of a new version changed materially';
+
+comment on column subscriptions.baseline is 'version from a lockfile, only newer versions are notified about';
+
+comment on column subscriptions.min_bump is 'smallest version bump to notify about: patch, minor or major';
+
 -- will error if executed twice
 alter table subscriptions
   add constraint subscriptions_crates_id_fk
     foreign key (crate_id) references crates
       on delete cascade;
 
+create table if not exists chat_settings
+(
+  user_id bigint not null
+    constraint chat_settings_pk
+      primary key,
+  mute_yanks bool not null default false
+);
+
+comment on column chat_settings.mute_yanks is 'don''t notify about (un)yanked versions';
+
+alter table chat_settings
+  add column if not exists digest_at time;
+
+alter table chat_settings
+  add column if not exists digest_sent_on date;
+
+comment on column chat_settings.digest_at is 'time of the daily digest (in the chat''s timezone), null if notifications are sent immediately';
+
+alter table chat_settings
+  add column if not exists template text;
+
+comment on column chat_settings.template is 'template of notifications about new versions, null for the default one';
+
+alter table chat_settings
+  add column if not exists banned bool not null default false;
+
+comment on column chat_settings.banned is 'banned by an operator: commands are ignored and nothing is sent to the chat';
+
+-- `verbose` is a keyword of postgres, so the column is quoted
+alter table chat_settings
+  add column if not exists "verbose" bool not null default false;
+
+comment on column chat_settings."verbose" is 'show crates.io metadata (downloads, license, msrv, features) in notifications';
+
+alter table chat_settings
+  add column if not exists timezone varchar(64);
+
+comment on column chat_settings.timezone is 'IANA name of the chat''s timezone (e.g. Europe/Berlin) for the digest and quiet hours, null for UTC';
+
+alter table chat_settings
+  add column if not exists quiet_from time;
+
+alter table chat_settings
+  add column if not exists quiet_to time;
+
+comment on column chat_settings.quiet_from is 'start of quiet hours (local time), notifications are deferred till quiet_to; null if there are none';
+
+alter table chat_settings
+  add column if not exists msrv varchar(16);
+
+comment on column chat_settings.msrv is 'Rust version of the chat''s toolchain, notifications warn about releases requiring a newer one';
+
+alter table chat_settings
+  add column if not exists trending bool not null default false;
+
+comment on column chat_settings.trending is 'notify about download milestones and surges of followed crates';
+
+alter table chat_settings
+  add column if not exists threads bool not null default false;
+
+comment on column chat_settings.threads is 'send notifications as replies to the previous message about the crate';
+
+alter table chat_settings
+  add column if not exists index_message bigint;
+
+comment on column chat_settings.index_message is 'pinned message listing threads of crates, null if there is none yet';
+
+alter table chat_settings
+  add column if not exists onboarding varchar(16);
+
+comment on column chat_settings.onboarding is 'current step of the setup started by /start, null if there is none';
+
+alter table chat_settings
+  add column if not exists disabled_at timestamptz;
+
+alter table chat_settings
+  add column if not exists disabled_reason text;
+
+comment on column chat_settings.disabled_at is 'notifications were paused after repeated failed deliveries, null if they are sent; a command of the chat resumes them';
+comment on column chat_settings.disabled_reason is 'the last telegram error before notifications were paused';
+
+alter table chat_settings
+  add column if not exists security_alerts bool not null default false;
+
+comment on column chat_settings.security_alerts is 'annotate notifications about suspicious releases (lookalike names, version jumps, new publishers)';
+
+alter table chat_settings
+  add column if not exists notes_verbosity varchar(16) not null default 'full';
+
+comment on column chat_settings.notes_verbosity is 'how much of release notes notifications include: full, first-section, headline-only or link-only';
+
+alter table chat_settings
+  add column if not exists weekly_report bool not null default false;
+
+alter table chat_settings
+  add column if not exists weekly_report_sent_at timestamptz;
+
+comment on column chat_settings.weekly_report is 'send a weekly summary of releases of subscribed crates';
+comment on column chat_settings.weekly_report_sent_at is 'when the last weekly report was sent (or the report was turned on)';
+
+alter table chat_settings
+  add column if not exists paused_until timestamptz;
+
+comment on column chat_settings.paused_until is 'notifications are paused (/pause) till then and queued for a catch-up digest, null once it''s sent';
+
+alter table chat_settings
+  add column if not exists language varchar(8);
+
+alter table chat_settings
+  add column if not exists translate bool not null default false;
+
+comment on column chat_settings.language is 'language of the chat (/language), ISO 639-1 code';
+comment on column chat_settings.translate is 'translate release notes in another language to the chat''s language';
+
+create table if not exists deferred_notifications
+(
+  id serial not null
+    constraint deferred_notifications_pk
+      primary key,
+  user_id bigint not null,
+  crate_id int not null,
+  version varchar(128) not null,
+  action varchar(8) not null,
+  text text not null,
+  constraint deferred_notifications_key
+    unique (user_id, crate_id, version, action)
+);
+
+comment on table deferred_notifications is 'notifications which came during quiet hours of the chat, sent after them';
+
+create table if not exists digest_queue
+(
+  id serial not null
+    constraint digest_queue_pk
+      primary key,
+  user_id bigint not null,
+  crate_id int not null,
+  version varchar(128) not null,
+  action varchar(8) not null
+);
+
+comment on table digest_queue is 'notifications waiting for the next digest of the chat';
+
+create index if not exists digest_queue_user_id_index
+  on digest_queue (user_id);
+
+-- will error if executed twice
+alter table digest_queue
+  add constraint digest_queue_crates_id_fk
+    foreign key (crate_id) references crates
+      on delete cascade;
+
+create table if not exists owner_subscriptions
+(
+  user_id bigint not null,
+  owner varchar(64) not null,
+  crates varchar(64)[] not null default '{}',
+  constraint owner_subscriptions_pk
+    primary key (user_id, owner)
+);
+
+comment on table owner_subscriptions is 'chats subscribed to all crates of a crates.io user or team';
+comment on column owner_subscriptions.crates is 'crates of the owner at the last refresh, only newer ones are subscribed to';
+
+create table if not exists dep_groups
+(
+  user_id bigint not null,
+  parent varchar(128) not null,
+  crates varchar(128)[] not null default '{}',
+  constraint dep_groups_pk
+    primary key (user_id, parent)
+);
+
+comment on table dep_groups is 'chats subscribed to the direct dependencies of a crate (`/subscribe_deps`)';
+comment on column dep_groups.crates is 'dependencies of the latest release of the crate at the last refresh';
+
+alter table subscriptions
+  add column if not exists dep_group varchar(128);
+
+comment on column subscriptions.dep_group is 'crate of the dependency group which added the subscription, null if it was subscribed to otherwise';
+
+create table if not exists tag_subscriptions
+(
+  user_id bigint not null,
+  kind varchar(8) not null,
+  tag varchar(64) not null,
+  constraint tag_subscriptions_pk
+    primary key (user_id, kind, tag)
+);
+
+comment on table tag_subscriptions is 'chats subscribed to all crates with a keyword or in a category';
+comment on column tag_subscriptions.kind is 'keyword or category';
+
+create table if not exists tag_crates
+(
+  kind varchar(8) not null,
+  tag varchar(64) not null,
+  crate_name varchar(64) not null,
+  constraint tag_crates_pk
+    primary key (kind, tag, crate_name)
+);
+
+create index if not exists tag_crates_crate_name_index
+  on tag_crates (crate_name);
+
+comment on table tag_crates is 'crates of subscribed tags from crates.io, refreshed periodically';
+
+create table if not exists name_watches
+(
+  user_id bigint not null,
+  name varchar(64) not null,
+  constraint name_watches_pk
+    primary key (user_id, name)
+);
+
+comment on table name_watches is 'chats waiting for the first publish of a crate name, removed when it''s published';
+comment on column name_watches.name is 'lowercase with `_` replaced by `-`, crates.io treats such names as the same';
+
+create table if not exists version_waits
+(
+  user_id bigint not null,
+  crate varchar(64) not null,
+  requirement varchar(64) not null,
+  constraint version_waits_pk
+    primary key (user_id, crate, requirement)
+);
+
+comment on table version_waits is 'chats waiting for a release of the crate matching the semver requirement (`/notify_when`), removed when it''s published';
+
+create table if not exists new_crate_subscriptions
+(
+  user_id bigint not null
+    constraint new_crate_subscriptions_pk
+      primary key,
+  topics varchar(64)[] not null default '{}'
+);
+
+comment on table new_crate_subscriptions is 'chats notified about first publishes of all crates (`/subscribe_new`)';
+comment on column new_crate_subscriptions.topics is 'keywords or category slugs, only crates with one of them are announced, all crates if empty';
+
+create table if not exists crate_threads
+(
+  user_id bigint not null,
+  crate varchar(128) not null,
+  message_id bigint not null,
+  constraint crate_threads_pk
+    primary key (user_id, crate)
+);
+
+comment on table crate_threads is 'the last notification about a crate in a chat with threads, the next one replies to it';
+
+create table if not exists download_samples
+(
+  crate varchar(64) not null,
+  day date not null default current_date,
+  downloads bigint not null,
+  trending bool not null default false,
+  constraint download_samples_pk
+    primary key (crate, day)
+);
+
+comment on table download_samples is 'daily total downloads of crates followed by chats with trending notifications, kept for 30 days';
+comment on column download_samples.trending is 'a surge of downloads was announced on that day';
+
+create table if not exists releases
+(
+  crate_id int not null,
+  version varchar(128) not null,
+  yanked bool not null default false,
+  published_at timestamptz not null,
+  constraint releases_pk
+    primary key (crate_id, version)
+);
+
+comment on table releases is 'archive of releases seen by the bot (the index doesn''t store publish dates)';
+
+-- will error if executed twice
+alter table releases
+  add constraint releases_crates_id_fk
+    foreign key (crate_id) references crates
+      on delete cascade;
+
+alter table releases
+  add column if not exists notes text;
+
+comment on column releases.notes is 'release notes from the changelog as telegram html, shown in atom feeds';
+
+alter table releases
+  add column if not exists pending varchar(8);
+
+comment on column releases.pending is 'action of the release while its notifications aren''t all sent, replayed after a restart';
+
+create table if not exists seen_versions
+(
+  crate varchar(128) not null,
+  version varchar(128) not null,
+  yanked bool not null,
+  hash varchar(64),
+  constraint seen_versions_pk
+    primary key (crate, version)
+);
+
+comment on table seen_versions is 'index entries seen by the bot, rewrites of an entry (e.g. metadata fixes) aren''t announced again';
+comment on column seen_versions.hash is 'sha256 of the entry, null for versions seen before the table was added';
+
+-- versions announced before the table was added
+insert into seen_versions (crate, version, yanked)
+    select c.name, r.version, r.yanked
+        from releases as r
+             inner join crates as c on c.id = r.crate_id
+    on conflict do nothing;
+
+create table if not exists bootstrap_progress
+(
+  source varchar(16) not null
+    constraint bootstrap_progress_pk
+      primary key,
+  progress varchar(128) not null,
+  finished bool not null default false,
+  updated_at timestamptz not null default now()
+);
+
+comment on table bootstrap_progress is 'seeding of `seen_versions` from a crates.io database dump or an index snapshot, resumed after a restart';
+comment on column bootstrap_progress.progress is 'rows of `versions.csv` seeded (`dump`) or the last seeded crate (`snapshot`)';
+
+create table if not exists deliveries
+(
+  crate_id int not null,
+  version varchar(128) not null,
+  action varchar(8) not null,
+  user_id bigint not null,
+  constraint deliveries_pk
+    primary key (crate_id, version, action, user_id)
+);
+
+comment on table deliveries is 'chats already notified about a pending release, a replay of the release skips them';
+
+create table if not exists delivery_jobs
+(
+  id bigserial not null
+    constraint delivery_jobs_pk
+      primary key,
+  crate_id int not null,
+  version varchar(128) not null,
+  action varchar(8) not null,
+  user_id bigint not null,
+  text text not null,
+  quiet bool not null,
+  attempts int not null default 0,
+  leased_by varchar(64),
+  leased_until timestamptz,
+  constraint delivery_jobs_key
+    unique (crate_id, version, action, user_id)
+);
+
+comment on table delivery_jobs is 'notifications about releases waiting for an instance to send them (cluster mode), the unique key makes enqueueing idempotent';
+
+create table if not exists lagging_notes
+(
+  crate varchar(128) not null,
+  version varchar(128) not null,
+  announced_at timestamptz not null default now(),
+  checked_at timestamptz not null default now(),
+  constraint lagging_notes_pk
+    primary key (crate, version)
+);
+
+comment on table lagging_notes is 'new versions announced without release notes, rechecked for them for `changelog.notes_lag_secs`';
+
+create table if not exists announcements
+(
+  crate varchar(128) not null,
+  version varchar(128) not null,
+  user_id bigint not null,
+  message_id bigint not null,
+  constraint announcements_pk
+    primary key (crate, version, user_id),
+  constraint announcements_lagging_notes_fk
+    foreign key (crate, version) references lagging_notes
+      on delete cascade
+);
+
+comment on table announcements is 'telegram messages announcing versions of `lagging_notes`, edited once the notes are found';
+
+create table if not exists sent_notifications
+(
+  user_id bigint not null,
+  crate varchar(128) not null,
+  version varchar(128) not null,
+  message_id bigint not null,
+  sent_at timestamptz not null default now(),
+  constraint sent_notifications_pk
+    primary key (user_id, message_id)
+);
+
+comment on table sent_notifications is 'the last telegram messages of chats announcing a single new version, rendered again by `/rerender`';
+
+create table if not exists dead_letters
+(
+  id bigserial not null
+    constraint dead_letters_pk
+      primary key,
+  user_id bigint not null,
+  crate varchar(128),
+  version varchar(128),
+  action varchar(8),
+  text text not null,
+  error text not null,
+  failed_at timestamptz not null default now()
+);
+
+create index if not exists dead_letters_user_id_index
+  on dead_letters (user_id);
+
+comment on table dead_letters is 'messages which couldn''t be sent to chats, kept for 30 days';
+comment on column dead_letters.crate is 'null for messages which aren''t about a release';
+
+create table if not exists feed_tokens
+(
+  user_id bigint not null
+    constraint feed_tokens_pk
+      primary key,
+  token varchar(64) not null
+);
+
+create unique index if not exists feed_tokens_token_uindex
+  on feed_tokens (token);
+
+comment on table feed_tokens is 'secret tokens of atom feeds of chats'' subscriptions';
+
+create table if not exists api_tokens
+(
+  user_id bigint not null
+    constraint api_tokens_pk
+      primary key,
+  token varchar(64) not null
+);
+
+create unique index if not exists api_tokens_token_uindex
+  on api_tokens (token);
+
+comment on table api_tokens is 'secret tokens of the http api, requests with a token act on behalf of its chat';
+
+create table if not exists emails
+(
+  user_id bigint not null
+    constraint emails_pk
+      primary key,
+  address varchar(254) not null,
+  frequency varchar(8) not null default 'instant',
+  token varchar(64) not null,
+  sent_at timestamptz
+);
+
+create unique index if not exists emails_token_uindex
+  on emails (token);
+
+comment on table emails is 'e-mail addresses of chats, updates are mailed in addition to telegram messages';
+comment on column emails.frequency is 'instant, daily or weekly';
+comment on column emails.token is 'secret of the unsubscribe link';
+
+-- addresses encrypted with the operator's storage key are longer
+alter table emails alter column address type text;
+
+comment on column emails.address is 'encrypted (`enc1:` and hex) if the operator set a storage key';
+
+create table if not exists email_queue
+(
+  id serial not null
+    constraint email_queue_pk
+      primary key,
+  user_id bigint not null,
+  crate_id int not null,
+  version varchar(128) not null,
+  action varchar(8) not null
+);
+
+comment on table email_queue is 'updates waiting for the next e-mail to the chat';
+
+create index if not exists email_queue_user_id_index
+  on email_queue (user_id);
+
+-- will error if executed twice
+alter table email_queue
+  add constraint email_queue_crates_id_fk
+    foreign key (crate_id) references crates
+      on delete cascade;
+
 create or replace procedure subscribe(_user_id bigint, _crate varchar(64))
     LANGUAGE plpgsql
 AS $$
@@ -42,7 +556,7 @@ begin
     insert into subscriptions (user_id, crate_id)
         select _user_id, id from crates
             where crates.name = _crate
-        on conflict do nothing;
+        on conflict (crate_id, user_id) do update set dep_group = null;
 end
 $$;
 
@@ -56,6 +570,42 @@ begin
 end
 $$;
 
+create or replace procedure subscribe_many(_user_id bigint, _crates varchar(64)[])
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into crates (name) select unnest(_crates) on conflict do nothing;
+
+    insert into subscriptions (user_id, crate_id)
+        select _user_id, id from crates
+            where crates.name = any(_crates)
+        on conflict (crate_id, user_id) do update set dep_group = null;
+end
+$$;
+
+create or replace procedure set_baselines(_user_id bigint, _crates varchar(64)[], _versions varchar(128)[])
+    LANGUAGE plpgsql
+AS $$
+begin
+    update subscriptions as s
+        set baseline = b.version
+        from unnest(_crates, _versions) as b(name, version)
+            inner join crates as c on c.name = b.name
+        where s.crate_id = c.id
+            and s.user_id = _user_id;
+end
+$$;
+
+create or replace procedure unsubscribe_many(_user_id bigint, _crates varchar(64)[])
+    LANGUAGE plpgsql
+AS $$
+begin
+    delete from subscriptions
+        where crate_id in (select id from crates where name = any(_crates))
+            and user_id = _user_id;
+end
+$$;
+
 create or replace function list_subscriptions(_user_id bigint)
 RETURNS TABLE(crate_name varchar(64))
     LANGUAGE plpgsql
@@ -68,14 +618,1938 @@ begin
 end
 $$;
 
-create or replace function list_subscribers(_crate varchar(64))
-    RETURNS TABLE(user_id bigint)
+-- the return type has changed (`readme` was added)
+drop function if exists list_filters(bigint);
+
+create or replace function list_filters(_user_id bigint)
+RETURNS TABLE(crate_name varchar(64), min_bump varchar(5), skip_prerelease bool, show_deps bool, readme bool)
     LANGUAGE plpgsql
 AS $$
 begin
-    RETURN QUERY select s.user_id as user_id
-         from subscriptions as s
-              inner join crates as c on c.id = s.crate_id
-         where c.name = _crate;
+    RETURN QUERY select c.name as crate_name, s.min_bump as min_bump, s.skip_prerelease as skip_prerelease,
+                        s.show_deps as show_deps, s.readme as readme
+        from subscriptions as s
+            inner join crates as c on c.id = s.crate_id
+        where s.user_id = _user_id
+        order by c.name;
 end
 $$;
+
+create or replace function list_subscribed_crates()
+RETURNS TABLE(crate_name varchar(64))
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select c.name as crate_name
+        from crates as c
+        where exists (select * from subscriptions as s where s.crate_id = c.id)
+    union
+    select tc.crate_name as crate_name
+        from tag_crates as tc
+        where exists (select * from tag_subscriptions as t where t.kind = tc.kind and t.tag = tc.tag)
+    union
+    select w.crate as crate_name
+        from version_waits as w;
+end
+$$;
+
+-- local time of a chat, `_tz` is null for UTC
+create or replace function chat_now(_tz varchar(64))
+    RETURNS timestamp
+    LANGUAGE sql
+    STABLE
+AS $$
+    select now() at time zone coalesce(_tz, 'utc');
+$$;
+
+-- whether quiet hours are now, they may span midnight (e.g. 23:00-08:00)
+create or replace function in_quiet_hours(_from time, _to time, _tz varchar(64))
+    RETURNS bool
+    LANGUAGE sql
+    STABLE
+AS $$
+    select case
+        when _from is null or _to is null then false
+        when _from <= _to then chat_now(_tz)::time >= _from and chat_now(_tz)::time < _to
+        else chat_now(_tz)::time >= _from or chat_now(_tz)::time < _to
+    end;
+$$;
+
+-- the return type has changed (filters, chat settings, tag subscriptions, e-mails, verbosity, deps, quiet hours, readme,
+-- security alerts, notes verbosity, pauses, languages and translation were added)
+drop function if exists list_subscribers(varchar);
+
+-- explicit subscribers and subscribers of the crate's tags (if they aren't subscribed explicitly), except banned
+-- chats and ones with paused notifications
+create or replace function list_subscribers(_crate varchar(64))
+    RETURNS TABLE(user_id bigint, min_bump varchar(5), skip_prerelease bool, show_deps bool, readme bool,
+                  mute_yanks bool, digest bool, baseline varchar(128), template text, tagged bool, email bool, verbose bool,
+                  quiet bool, msrv varchar(16), security_alerts bool, notes_verbosity varchar(16), paused bool,
+                  language varchar(8), translate bool)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select s.user_id as user_id, s.min_bump as min_bump, s.skip_prerelease as skip_prerelease,
+                        s.show_deps as show_deps, s.readme as readme,
+                        coalesce(cs.mute_yanks, false) as mute_yanks,
+                        cs.digest_at is not null as digest,
+                        s.baseline as baseline,
+                        cs.template as template,
+                        false as tagged,
+                        exists (select * from emails as e where e.user_id = s.user_id) as email,
+                        coalesce(cs.verbose, false) as verbose,
+                        in_quiet_hours(cs.quiet_from, cs.quiet_to, cs.timezone) as quiet,
+                        cs.msrv as msrv,
+                        coalesce(cs.security_alerts, false) as security_alerts,
+                        coalesce(cs.notes_verbosity, 'full') as notes_verbosity,
+                        coalesce(cs.paused_until > now(), false) as paused,
+                        cs.language as language,
+                        coalesce(cs.translate, false) as translate
+         from subscriptions as s
+              inner join crates as c on c.id = s.crate_id
+              left join chat_settings as cs on cs.user_id = s.user_id
+         where c.name = _crate
+             and not coalesce(cs.banned, false)
+             and cs.disabled_at is null
+    union all
+    select distinct on (t.user_id)
+                        t.user_id as user_id, 'patch'::varchar(5) as min_bump, false as skip_prerelease,
+                        false as show_deps, false as readme,
+                        coalesce(cs.mute_yanks, false) as mute_yanks,
+                        cs.digest_at is not null as digest,
+                        null::varchar(128) as baseline,
+                        cs.template as template,
+                        true as tagged,
+                        exists (select * from emails as e where e.user_id = t.user_id) as email,
+                        coalesce(cs.verbose, false) as verbose,
+                        in_quiet_hours(cs.quiet_from, cs.quiet_to, cs.timezone) as quiet,
+                        cs.msrv as msrv,
+                        coalesce(cs.security_alerts, false) as security_alerts,
+                        coalesce(cs.notes_verbosity, 'full') as notes_verbosity,
+                        coalesce(cs.paused_until > now(), false) as paused,
+                        cs.language as language,
+                        coalesce(cs.translate, false) as translate
+         from tag_subscriptions as t
+              inner join tag_crates as tc on tc.kind = t.kind and tc.tag = t.tag
+              left join chat_settings as cs on cs.user_id = t.user_id
+         where tc.crate_name = _crate
+             and not coalesce(cs.banned, false)
+             and cs.disabled_at is null
+             and not exists (select * from subscriptions as s
+                                 inner join crates as c on c.id = s.crate_id
+                             where s.user_id = t.user_id and c.name = _crate);
+end
+$$;
+
+-- what brings updates of the crate to the chat (`/why`) as (kind, name): `subscription`, `owner` or `dep_group` (with
+-- the owner or the parent crate which added the subscription), `tag` (`keyword:async`), `wait` (the requirement),
+-- `name_watch` and `new_crates` (space separated topics)
+create or replace function subscription_sources(_user_id bigint, _crate varchar(64))
+    RETURNS TABLE(kind varchar(16), name text)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select (case when s.dep_group is not null then 'dep_group'
+                              when o.owner is not null then 'owner'
+                              else 'subscription' end)::varchar(16),
+                        coalesce(s.dep_group, o.owner, '')::text
+         from subscriptions as s
+              inner join crates as c on c.id = s.crate_id
+              left join owner_subscriptions as o on o.user_id = s.user_id and c.name = any(o.crates)
+         where s.user_id = _user_id and c.name = _crate
+    union all
+    select 'tag'::varchar(16), (t.kind || ':' || t.tag)::text
+         from tag_subscriptions as t
+              inner join tag_crates as tc on tc.kind = t.kind and tc.tag = t.tag
+         where t.user_id = _user_id and tc.crate_name = _crate
+    union all
+    select 'wait'::varchar(16), w.requirement::text
+         from version_waits as w
+         where w.user_id = _user_id and w.crate = _crate
+    union all
+    select 'name_watch'::varchar(16), n.name::text
+         from name_watches as n
+         where n.user_id = _user_id and n.name = lower(replace(_crate, '_', '-'))
+    union all
+    select 'new_crates'::varchar(16), array_to_string(n.topics, ' ')
+         from new_crate_subscriptions as n
+         where n.user_id = _user_id;
+end
+$$;
+
+create or replace procedure set_digest(_user_id bigint, _at varchar(5))
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into chat_settings (user_id, digest_at) values (_user_id, _at::time)
+        on conflict (user_id) do update set digest_at = _at::time;
+end
+$$;
+
+create or replace procedure queue_digest(_user_id bigint, _crate varchar(64), _version varchar(128), _action varchar(8))
+    LANGUAGE plpgsql
+AS $$
+begin
+    -- a replayed release is queued once
+    insert into digest_queue (user_id, crate_id, version, action)
+        select _user_id, id, _version, _action from crates
+            where crates.name = _crate
+                and not exists (select * from digest_queue as q
+                                    where q.user_id = _user_id and q.crate_id = crates.id
+                                        and q.version = _version and q.action = _action);
+end
+$$;
+
+-- canonical name of the timezone (names are case-insensitive), null if there is no such timezone
+create or replace function find_timezone(_name varchar(64))
+    RETURNS varchar(64)
+    LANGUAGE plpgsql
+AS $$
+begin
+    return (select tz.name from pg_timezone_names as tz where lower(tz.name) = lower(_name) limit 1);
+end
+$$;
+
+create or replace procedure set_timezone(_user_id bigint, _tz varchar(64))
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into chat_settings (user_id, timezone) values (_user_id, _tz)
+        on conflict (user_id) do update set timezone = _tz;
+end
+$$;
+
+-- timezone, digest time and quiet hours of the chat
+create or replace function get_schedule(_user_id bigint)
+    RETURNS TABLE(timezone varchar(64), digest_at varchar(5), quiet_from varchar(5), quiet_to varchar(5))
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select cs.timezone, to_char(cs.digest_at, 'HH24:MI')::varchar(5),
+                        to_char(cs.quiet_from, 'HH24:MI')::varchar(5), to_char(cs.quiet_to, 'HH24:MI')::varchar(5)
+         from chat_settings as cs
+         where cs.user_id = _user_id;
+end
+$$;
+
+-- null `_from` and `_to` turn quiet hours off
+create or replace procedure set_quiet_hours(_user_id bigint, _from varchar(5), _to varchar(5))
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into chat_settings (user_id, quiet_from, quiet_to) values (_user_id, _from::time, _to::time)
+        on conflict (user_id) do update set quiet_from = _from::time, quiet_to = _to::time;
+end
+$$;
+
+-- a replayed release is deferred once
+create or replace procedure defer_notification(_user_id bigint, _crate varchar(64), _version varchar(128),
+                                               _action varchar(8), _text text)
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into deferred_notifications (user_id, crate_id, version, action, text)
+        select _user_id, id, _version, _action, _text from crates
+            where crates.name = _crate
+        on conflict do nothing;
+end
+$$;
+
+-- removes deferred notifications of chats whose quiet hours are over (or were turned off), except paused chats
+create or replace function take_deferred()
+    RETURNS TABLE(user_id bigint, text text)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY with taken as (
+            delete from deferred_notifications as d
+                where not coalesce((select in_quiet_hours(cs.quiet_from, cs.quiet_to, cs.timezone)
+                                               or coalesce(cs.paused_until > now(), false)
+                                        from chat_settings as cs where cs.user_id = d.user_id), false)
+                returning d.id, d.user_id, d.text
+        )
+        select taken.user_id, taken.text from taken order by taken.id;
+end
+$$;
+
+-- chats which haven't got the digest today, though its time has come, except paused chats
+create or replace function due_digests()
+    RETURNS TABLE(user_id bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select cs.user_id as user_id
+         from chat_settings as cs
+         where cs.digest_at <= chat_now(cs.timezone)::time
+             and (cs.digest_sent_on is null or cs.digest_sent_on < chat_now(cs.timezone)::date)
+             and not coalesce(cs.paused_until > now(), false);
+end
+$$;
+
+-- pauses notifications of the chat for `_seconds` (`/pause`), returns the end of the pause in the chat's timezone
+create or replace function pause_chat(_user_id bigint, _seconds bigint)
+    RETURNS varchar(32)
+    LANGUAGE plpgsql
+AS $$
+declare
+    _until timestamptz := now() + _seconds * interval '1 second';
+begin
+    insert into chat_settings (user_id, paused_until) values (_user_id, _until)
+        on conflict (user_id) do update set paused_until = _until;
+    return (select to_char(_until at time zone coalesce(cs.timezone, 'utc'), 'YYYY-MM-DD HH24:MI')
+                || ' ' || coalesce(cs.timezone, 'UTC')
+            from chat_settings as cs where cs.user_id = _user_id);
+end
+$$;
+
+-- ends the pause of the chat now (`/resume`), returns whether it was paused. The catch-up digest is sent by
+-- `take_resumed`.
+create or replace function resume_paused(_user_id bigint)
+    RETURNS bool
+    LANGUAGE plpgsql
+AS $$
+begin
+    update chat_settings set paused_until = now()
+        where chat_settings.user_id = _user_id and paused_until > now();
+    return found;
+end
+$$;
+
+-- chats whose pause is over, their queued notifications are sent as a catch-up digest
+create or replace function take_resumed()
+    RETURNS TABLE(user_id bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY with resumed as (
+            update chat_settings as cs set paused_until = null
+                where cs.paused_until <= now()
+                returning cs.user_id
+        )
+        select resumed.user_id from resumed;
+end
+$$;
+
+-- marks the digest as sent today and removes its entries from the queue
+create or replace function take_digest(_user_id bigint)
+    RETURNS TABLE(crate_name varchar(64), version varchar(128), action varchar(8))
+    LANGUAGE plpgsql
+AS $$
+begin
+    update chat_settings set digest_sent_on = chat_now(chat_settings.timezone)::date
+        where chat_settings.user_id = _user_id;
+
+    RETURN QUERY with taken as (
+            delete from digest_queue as q where q.user_id = _user_id
+                returning q.id, q.crate_id, q.version, q.action
+        )
+        select c.name as crate_name, taken.version as version, taken.action as action
+            from taken
+                inner join crates as c on c.id = taken.crate_id
+            order by c.name, taken.id;
+end
+$$;
+
+create or replace function toggle_mute_yanks(_user_id bigint)
+    RETURNS bool
+    LANGUAGE plpgsql
+AS $$
+declare
+    _muted bool;
+begin
+    insert into chat_settings (user_id, mute_yanks) values (_user_id, true)
+        on conflict (user_id) do update set mute_yanks = not chat_settings.mute_yanks
+        returning mute_yanks into _muted;
+
+    return _muted;
+end
+$$;
+
+create or replace procedure set_verbose(_user_id bigint, _verbose bool)
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into chat_settings (user_id, "verbose") values (_user_id, _verbose)
+        on conflict (user_id) do update set "verbose" = _verbose;
+end
+$$;
+
+create or replace function get_verbose(_user_id bigint)
+    RETURNS bool
+    LANGUAGE plpgsql
+AS $$
+begin
+    return coalesce((select "verbose" from chat_settings where chat_settings.user_id = _user_id), false);
+end
+$$;
+
+create or replace procedure set_weekly_report(_user_id bigint, _enabled bool)
+    LANGUAGE plpgsql
+AS $$
+begin
+    -- the first report comes a week after it was turned on
+    insert into chat_settings (user_id, weekly_report, weekly_report_sent_at) values (_user_id, _enabled, now())
+        on conflict (user_id) do update set weekly_report = _enabled, weekly_report_sent_at = now();
+end
+$$;
+
+-- chats whose weekly report is due
+create or replace function due_weekly_reports()
+    RETURNS TABLE(user_id bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select cs.user_id
+         from chat_settings as cs
+         where cs.weekly_report
+             and not cs.banned
+             and cs.disabled_at is null
+             and not coalesce(cs.paused_until > now(), false)
+             and (cs.weekly_report_sent_at is null or cs.weekly_report_sent_at <= now() - interval '7 days');
+end
+$$;
+
+-- releases of the last 7 days of crates the chat is subscribed to, with the version published
+-- before each one and the chat's filter of the crate; marks the report as sent
+create or replace function take_weekly_report(_user_id bigint)
+    RETURNS TABLE(crate_name varchar(128), version varchar(128), previous varchar(128), yanked bool,
+                  min_bump varchar(5), skip_prerelease bool, show_deps bool, readme bool)
+    LANGUAGE plpgsql
+AS $$
+begin
+    update chat_settings set weekly_report_sent_at = now() where chat_settings.user_id = _user_id;
+
+    RETURN QUERY with ordered as (
+            select r.crate_id, r.version, r.yanked, r.published_at,
+                   lag(r.version) over (partition by r.crate_id order by r.published_at) as previous
+                from releases as r
+                where r.crate_id in (select s.crate_id from subscriptions as s where s.user_id = _user_id)
+        )
+        select c.name as crate_name, o.version as version, o.previous as previous, o.yanked as yanked,
+               s.min_bump as min_bump, s.skip_prerelease as skip_prerelease, s.show_deps as show_deps,
+               s.readme as readme
+            from ordered as o
+                inner join crates as c on c.id = o.crate_id
+                inner join subscriptions as s on s.crate_id = o.crate_id and s.user_id = _user_id
+            where o.published_at > now() - interval '7 days'
+            order by c.name, o.published_at;
+end
+$$;
+
+create or replace procedure set_notes_verbosity(_user_id bigint, _verbosity varchar(16))
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into chat_settings (user_id, notes_verbosity) values (_user_id, _verbosity)
+        on conflict (user_id) do update set notes_verbosity = _verbosity;
+end
+$$;
+
+create or replace function get_notes_verbosity(_user_id bigint)
+    RETURNS varchar(16)
+    LANGUAGE plpgsql
+AS $$
+begin
+    return coalesce((select notes_verbosity from chat_settings where chat_settings.user_id = _user_id), 'full');
+end
+$$;
+
+create or replace procedure set_language(_user_id bigint, _language varchar(8))
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into chat_settings (user_id, language) values (_user_id, _language)
+        on conflict (user_id) do update set language = _language;
+    -- there's nothing to translate to
+    if _language is null then
+        update chat_settings set translate = false where user_id = _user_id;
+    end if;
+end
+$$;
+
+create or replace procedure set_translate(_user_id bigint, _translate bool)
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into chat_settings (user_id, translate) values (_user_id, _translate)
+        on conflict (user_id) do update set translate = _translate;
+end
+$$;
+
+-- language of the chat and whether release notes are translated to it
+create or replace function get_language(_user_id bigint)
+    RETURNS TABLE(language varchar(8), translate bool)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select (select cs.language from chat_settings as cs where cs.user_id = _user_id),
+                        coalesce((select cs.translate from chat_settings as cs where cs.user_id = _user_id), false);
+end
+$$;
+
+create or replace procedure set_security_alerts(_user_id bigint, _alerts bool)
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into chat_settings (user_id, security_alerts) values (_user_id, _alerts)
+        on conflict (user_id) do update set security_alerts = _alerts;
+end
+$$;
+
+create or replace procedure set_msrv(_user_id bigint, _msrv varchar(16))
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into chat_settings (user_id, msrv) values (_user_id, _msrv)
+        on conflict (user_id) do update set msrv = _msrv;
+end
+$$;
+
+create or replace function get_msrv(_user_id bigint)
+    RETURNS varchar(16)
+    LANGUAGE plpgsql
+AS $$
+begin
+    return (select msrv from chat_settings where chat_settings.user_id = _user_id);
+end
+$$;
+
+-- the return type has changed (`show_deps` and `readme` were added)
+drop function if exists get_filter(bigint, varchar);
+
+create or replace function get_filter(_user_id bigint, _crate varchar(64))
+    RETURNS TABLE(min_bump varchar(5), skip_prerelease bool, show_deps bool, readme bool)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select s.min_bump as min_bump, s.skip_prerelease as skip_prerelease, s.show_deps as show_deps,
+                        s.readme as readme
+         from subscriptions as s
+              inner join crates as c on c.id = s.crate_id
+         where c.name = _crate and s.user_id = _user_id;
+end
+$$;
+
+drop procedure if exists set_filter(bigint, varchar, varchar, bool);
+drop procedure if exists set_filter(bigint, varchar, varchar, bool, bool);
+
+create or replace procedure set_filter(_user_id bigint, _crate varchar(64), _min_bump varchar(5), _skip_prerelease bool,
+                                       _show_deps bool, _readme bool)
+    LANGUAGE plpgsql
+AS $$
+begin
+    update subscriptions
+        set min_bump = _min_bump, skip_prerelease = _skip_prerelease, show_deps = _show_deps, readme = _readme
+        where crate_id = (select id from crates where name = _crate)
+            and user_id = _user_id;
+end
+$$;
+
+-- `_action` is pending until `finish_release`
+drop procedure if exists record_release(varchar, varchar, bool, bigint);
+
+create or replace procedure record_release(_crate varchar(64), _version varchar(128), _yanked bool, _published_at bigint,
+                                           _action varchar(8))
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into crates (name) values (_crate) on conflict do nothing;
+
+    insert into releases (crate_id, version, yanked, published_at, pending)
+        select id, _version, _yanked, to_timestamp(_published_at), _action from crates
+            where crates.name = _crate
+        on conflict (crate_id, version) do update set yanked = excluded.yanked, pending = excluded.pending;
+end
+$$;
+
+-- records the index entry of the version, returns its yanked status when it was seen before; null if the
+-- version is new or its notifications aren't all sent yet (e.g. it's replayed after a crash)
+create or replace function see_version(_crate varchar(128), _version varchar(128), _yanked bool, _hash varchar(64))
+    RETURNS bool
+    LANGUAGE plpgsql
+AS $$
+declare
+    _previous bool;
+begin
+    select s.yanked into _previous from seen_versions as s where s.crate = _crate and s.version = _version;
+    if exists (select * from releases as r
+                   inner join crates as c on c.id = r.crate_id
+                   where c.name = _crate and r.version = _version and r.pending is not null) then
+        _previous := null;
+    end if;
+
+    insert into seen_versions (crate, version, yanked, hash) values (_crate, _version, _yanked, _hash)
+        on conflict (crate, version) do update set yanked = _yanked, hash = _hash;
+    return _previous;
+end
+$$;
+
+-- seeds a batch of index entries along with the progress, entries seen by the bot itself are kept
+create or replace procedure seed_seen_versions(_source varchar(16), _progress varchar(128), _crates varchar(128)[],
+                                               _versions varchar(128)[], _yanked bool[], _hashes varchar(64)[])
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into seen_versions (crate, version, yanked, hash)
+        select * from unnest(_crates, _versions, _yanked, _hashes)
+        on conflict do nothing;
+    insert into bootstrap_progress (source, progress) values (_source, _progress)
+        on conflict (source) do update set progress = _progress, updated_at = now();
+end
+$$;
+
+create or replace procedure finish_bootstrap(_source varchar(16))
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into bootstrap_progress (source, progress, finished) values (_source, '', true)
+        on conflict (source) do update set finished = true, updated_at = now();
+end
+$$;
+
+create or replace function get_bootstrap_progress(_source varchar(16))
+    RETURNS TABLE(progress varchar(128), finished bool)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select p.progress, p.finished from bootstrap_progress as p where p.source = _source;
+end
+$$;
+
+create or replace procedure add_lagging_notes(_crate varchar(128), _version varchar(128))
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into lagging_notes (crate, version) values (_crate, _version) on conflict do nothing;
+end
+$$;
+
+-- messages are recorded only for versions waiting for their release notes
+create or replace procedure record_announcement(_crate varchar(128), _version varchar(128), _user_id bigint,
+                                                _message_id bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into announcements (crate, version, user_id, message_id)
+        select _crate, _version, _user_id, _message_id from lagging_notes
+            where crate = _crate and version = _version
+        on conflict (crate, version, user_id) do update set message_id = _message_id;
+end
+$$;
+
+-- only the newest `_keep` messages of the chat are kept
+create or replace procedure record_notification(_user_id bigint, _crate varchar(128), _version varchar(128),
+                                                _message_id bigint, _keep int)
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into sent_notifications (user_id, crate, version, message_id)
+        values (_user_id, _crate, _version, _message_id)
+        on conflict (user_id, message_id) do nothing;
+    delete from sent_notifications as s
+        where s.user_id = _user_id
+          and s.message_id not in (select n.message_id from sent_notifications as n
+                                       where n.user_id = _user_id
+                                       order by n.sent_at desc, n.message_id desc
+                                       limit _keep);
+end
+$$;
+
+-- the newest `_limit` messages of the chat announcing new versions, newest first
+create or replace function last_notifications(_user_id bigint, _limit bigint)
+    RETURNS TABLE(crate varchar(128), version varchar(128), message_id bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select s.crate, s.version, s.message_id from sent_notifications as s
+        where s.user_id = _user_id
+        order by s.sent_at desc, s.message_id desc
+        limit _limit;
+end
+$$;
+
+-- versions not checked for release notes for `_check_secs`, marked as checked; `expired` ones are waiting for
+-- longer than `_lag_secs`
+create or replace function due_lagging_notes(_check_secs int, _lag_secs int)
+    RETURNS TABLE(crate varchar(128), version varchar(128), expired bool)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY update lagging_notes as l set checked_at = now()
+        where l.checked_at <= now() - make_interval(secs => _check_secs)
+        returning l.crate, l.version, l.announced_at <= now() - make_interval(secs => _lag_secs);
+end
+$$;
+
+-- messages announcing the version, which isn't waiting for its release notes anymore
+create or replace function take_announcements(_crate varchar(128), _version varchar(128))
+    RETURNS TABLE(user_id bigint, message_id bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select a.user_id, a.message_id from announcements as a
+        where a.crate = _crate and a.version = _version;
+    delete from lagging_notes as l where l.crate = _crate and l.version = _version;
+end
+$$;
+
+-- all notifications about the release are sent
+create or replace procedure finish_release(_crate varchar(64), _version varchar(128))
+    LANGUAGE plpgsql
+AS $$
+declare
+    _crate_id int;
+begin
+    select id into _crate_id from crates where name = _crate;
+    update releases set pending = null where crate_id = _crate_id and version = _version;
+    delete from deliveries where crate_id = _crate_id and version = _version;
+end
+$$;
+
+-- also acknowledges the delivery job, if the notification was one
+create or replace procedure record_delivery(_crate varchar(64), _version varchar(128), _action varchar(8), _user_id bigint)
+    LANGUAGE plpgsql
+AS $$
+declare
+    _crate_id int;
+begin
+    select id into _crate_id from crates where name = _crate;
+    -- jobs may be sent after the release is finished, there's nothing to replay then
+    insert into deliveries (crate_id, version, action, user_id)
+        select _crate_id, _version, _action, _user_id from releases
+            where crate_id = _crate_id and version = _version and pending is not null
+        on conflict do nothing;
+    delete from delivery_jobs
+        where crate_id = _crate_id and version = _version and action = _action and user_id = _user_id;
+end
+$$;
+
+create or replace procedure enqueue_delivery(_crate varchar(64), _version varchar(128), _action varchar(8), _user_id bigint,
+                                             _text text, _quiet bool)
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into delivery_jobs (crate_id, version, action, user_id, text, quiet)
+        select id, _version, _action, _user_id, _text, _quiet from crates
+            where crates.name = _crate
+        on conflict do nothing;
+end
+$$;
+
+-- leases jobs of up to `_chats` chats to the instance, jobs of a chat are claimed together to keep
+-- their order. Jobs claimed 5 times without an acknowledgement are dropped.
+create or replace function claim_deliveries(_instance varchar(64), _chats int, _lease_secs int)
+    RETURNS TABLE(id bigint, crate varchar(64), version varchar(128), action varchar(8), user_id bigint, text text, quiet bool)
+    LANGUAGE plpgsql
+AS $$
+begin
+    delete from delivery_jobs as j
+        where j.attempts >= 5 and (j.leased_until is null or j.leased_until < now());
+
+    RETURN QUERY with free as (
+        select j.user_id from delivery_jobs as j
+            where j.leased_until is null or j.leased_until < now()
+            order by j.id
+            for update skip locked
+    ), chats as (
+        select distinct f.user_id from free as f limit _chats
+    ), claimed as (
+        update delivery_jobs as j
+            set leased_by = _instance,
+                leased_until = now() + make_interval(secs => _lease_secs),
+                attempts = j.attempts + 1
+            where j.user_id in (select ch.user_id from chats as ch)
+              and (j.leased_until is null or j.leased_until < now())
+            returning j.id, j.crate_id, j.version, j.action, j.user_id, j.text, j.quiet
+    )
+    select cl.id, c.name, cl.version, cl.action, cl.user_id, cl.text, cl.quiet
+         from claimed as cl
+              inner join crates as c on c.id = cl.crate_id
+         order by cl.id;
+end
+$$;
+
+create or replace function delivered_chats(_crate varchar(64), _version varchar(128), _action varchar(8))
+    RETURNS TABLE(user_id bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select d.user_id
+         from deliveries as d
+              inner join crates as c on c.id = d.crate_id
+         where c.name = _crate and d.version = _version and d.action = _action;
+end
+$$;
+
+-- releases whose notifications aren't all sent, e.g. held in a version train before a restart
+create or replace function pending_releases()
+    RETURNS TABLE(crate varchar(64), version varchar(128), action varchar(8), published_at bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select c.name, r.version, r.pending, extract(epoch from r.published_at)::bigint
+         from releases as r
+              inner join crates as c on c.id = r.crate_id
+         where r.pending is not null
+         order by r.published_at;
+end
+$$;
+
+-- recorded versions of the crate with their yank status and pending action, for checkpoints of the sparse index
+create or replace function release_states(_crate varchar(64))
+    RETURNS TABLE(version varchar(128), yanked bool, pending varchar(8))
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select r.version, r.yanked, r.pending
+         from releases as r
+              inner join crates as c on c.id = r.crate_id
+         where c.name = _crate;
+end
+$$;
+
+create or replace function list_releases(_crate varchar(64))
+    RETURNS TABLE(version varchar(128), yanked bool, published_on text)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select r.version, r.yanked, to_char(r.published_at, 'YYYY-MM-DD')
+         from releases as r
+              inner join crates as c on c.id = r.crate_id
+         where c.name = _crate
+         order by r.published_at desc;
+end
+$$;
+
+-- archived releases published since the unix time, oldest first (for replays of index events)
+create or replace function releases_since(_since bigint)
+    RETURNS TABLE(crate varchar(64), version varchar(128), yanked bool, published_at bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select c.name, r.version, r.yanked, extract(epoch from r.published_at)::bigint
+         from releases as r
+              inner join crates as c on c.id = r.crate_id
+         where r.published_at >= to_timestamp(_since)
+         order by r.published_at;
+end
+$$;
+
+create or replace function get_template(_user_id bigint)
+    RETURNS text
+    LANGUAGE plpgsql
+AS $$
+begin
+    return (select template from chat_settings where chat_settings.user_id = _user_id);
+end
+$$;
+
+create or replace procedure set_template(_user_id bigint, _template text)
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into chat_settings (user_id, template) values (_user_id, _template)
+        on conflict (user_id) do update set template = _template;
+end
+$$;
+
+create or replace procedure subscribe_owner(_user_id bigint, _owner varchar(64), _crates varchar(64)[])
+    LANGUAGE plpgsql
+AS $$
+begin
+    call subscribe_many(_user_id, _crates);
+
+    insert into owner_subscriptions (user_id, owner, crates) values (_user_id, _owner, _crates)
+        on conflict (user_id, owner) do update set crates = _crates;
+end
+$$;
+
+create or replace function unsubscribe_owner(_user_id bigint, _owner varchar(64))
+    RETURNS bool
+    LANGUAGE plpgsql
+AS $$
+begin
+    delete from owner_subscriptions as o where o.user_id = _user_id and o.owner = _owner;
+
+    return found;
+end
+$$;
+
+-- subscribes to the dependencies of the crate, except ones the chat is already subscribed to, and unsubscribes from
+-- ones the group added before which aren't dependencies anymore
+create or replace procedure subscribe_deps(_user_id bigint, _parent varchar(128), _crates varchar(128)[])
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into crates (name) select unnest(_crates) on conflict do nothing;
+
+    insert into subscriptions (user_id, crate_id, dep_group)
+        select _user_id, id, _parent from crates
+            where crates.name = any(_crates)
+        on conflict do nothing;
+
+    delete from subscriptions as s
+        using crates as c
+        where s.user_id = _user_id and s.dep_group = _parent
+            and c.id = s.crate_id and not c.name = any(_crates);
+
+    insert into dep_groups (user_id, parent, crates) values (_user_id, _parent, _crates)
+        on conflict (user_id, parent) do update set crates = _crates;
+end
+$$;
+
+-- removes the group and the subscriptions it added, `false` if there is no such group
+create or replace function drop_dep_group(_user_id bigint, _parent varchar(128))
+    RETURNS bool
+    LANGUAGE plpgsql
+AS $$
+begin
+    delete from dep_groups as g where g.user_id = _user_id and g.parent = _parent;
+    if not found then
+        return false;
+    end if;
+
+    delete from subscriptions as s where s.user_id = _user_id and s.dep_group = _parent;
+
+    return true;
+end
+$$;
+
+create or replace function list_dep_groups(_user_id bigint)
+    RETURNS TABLE(parent varchar(128), crates varchar(128)[])
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select g.parent, g.crates from dep_groups as g where g.user_id = _user_id order by g.parent;
+end
+$$;
+
+create or replace function has_dep_groups(_parent varchar(128))
+    RETURNS bool
+    LANGUAGE plpgsql
+AS $$
+begin
+    return exists (select * from dep_groups as g where g.parent = _parent);
+end
+$$;
+
+-- subscribes chats following the dependencies of the crate to new ones and unsubscribes them from dropped ones
+-- (only if the subscription was added by the group)
+create or replace procedure refresh_dep_groups(_parent varchar(128), _crates varchar(128)[])
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into crates (name) select unnest(_crates) on conflict do nothing;
+
+    insert into subscriptions (user_id, crate_id, dep_group)
+        select g.user_id, c.id, _parent
+            from dep_groups as g
+                inner join crates as c on c.name = any(_crates) and not c.name = any(g.crates)
+            where g.parent = _parent
+        on conflict do nothing;
+
+    delete from subscriptions as s
+        using dep_groups as g, crates as c
+        where g.parent = _parent and s.user_id = g.user_id and s.dep_group = _parent
+            and c.id = s.crate_id and not c.name = any(_crates);
+
+    update dep_groups set crates = _crates where parent = _parent;
+end
+$$;
+
+create or replace procedure watch_name(_user_id bigint, _name varchar(64))
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into name_watches (user_id, name) values (_user_id, _name) on conflict do nothing;
+end
+$$;
+
+create or replace function unwatch_name(_user_id bigint, _name varchar(64))
+    RETURNS bool
+    LANGUAGE plpgsql
+AS $$
+begin
+    delete from name_watches as w where w.user_id = _user_id and w.name = _name;
+
+    return found;
+end
+$$;
+
+create or replace function list_name_watches(_user_id bigint)
+    RETURNS TABLE(name varchar(64))
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select w.name from name_watches as w where w.user_id = _user_id order by w.name;
+end
+$$;
+
+-- removes watches of the published name, returning the chats (except banned ones)
+create or replace function take_name_watchers(_name varchar(64))
+    RETURNS TABLE(user_id bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY with taken as (
+            delete from name_watches as w where w.name = _name returning w.user_id
+        )
+        select taken.user_id
+            from taken
+                left join chat_settings as cs on cs.user_id = taken.user_id
+            where not coalesce(cs.banned, false);
+end
+$$;
+
+create or replace procedure wait_for_version(_user_id bigint, _crate varchar(64), _requirement varchar(64))
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into version_waits (user_id, crate, requirement) values (_user_id, _crate, _requirement)
+        on conflict do nothing;
+end
+$$;
+
+-- removes all waits of the chat for releases of the crate
+create or replace function unwait_for_version(_user_id bigint, _crate varchar(64))
+    RETURNS bool
+    LANGUAGE plpgsql
+AS $$
+begin
+    delete from version_waits as w where w.user_id = _user_id and w.crate = _crate;
+
+    return found;
+end
+$$;
+
+create or replace function list_version_waits(_user_id bigint)
+    RETURNS TABLE(crate varchar(64), requirement varchar(64))
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select w.crate, w.requirement
+        from version_waits as w
+        where w.user_id = _user_id
+        order by w.crate, w.requirement;
+end
+$$;
+
+-- chats waiting for releases of the crate with their requirements, except banned ones
+create or replace function list_version_waiters(_crate varchar(64))
+    RETURNS TABLE(user_id bigint, requirement varchar(64))
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select w.user_id, w.requirement
+        from version_waits as w
+            left join chat_settings as cs on cs.user_id = w.user_id
+        where w.crate = _crate and not coalesce(cs.banned, false);
+end
+$$;
+
+-- removes the wait once it fired, `false` if it was already removed (e.g. by another lane)
+create or replace function take_version_wait(_user_id bigint, _crate varchar(64), _requirement varchar(64))
+    RETURNS bool
+    LANGUAGE plpgsql
+AS $$
+begin
+    delete from version_waits as w
+        where w.user_id = _user_id and w.crate = _crate and w.requirement = _requirement;
+
+    return found;
+end
+$$;
+
+create or replace procedure subscribe_new(_user_id bigint, _topics varchar(64)[])
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into new_crate_subscriptions (user_id, topics) values (_user_id, _topics)
+        on conflict (user_id) do update set topics = excluded.topics;
+end
+$$;
+
+create or replace function unsubscribe_new(_user_id bigint)
+    RETURNS bool
+    LANGUAGE plpgsql
+AS $$
+begin
+    delete from new_crate_subscriptions as n where n.user_id = _user_id;
+
+    return found;
+end
+$$;
+
+-- chats subscribed to new crates (except banned ones)
+create or replace function list_new_crate_subscribers()
+    RETURNS TABLE(user_id bigint, topics varchar(64)[])
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select n.user_id, n.topics
+        from new_crate_subscriptions as n
+            left join chat_settings as cs on cs.user_id = n.user_id
+        where not coalesce(cs.banned, false);
+end
+$$;
+
+-- crates.io crates followed by chats with trending notifications (except banned ones)
+create or replace function list_trending_crates()
+    RETURNS TABLE(crate_name varchar(64))
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select distinct c.name as crate_name
+        from subscriptions as s
+            inner join crates as c on c.id = s.crate_id
+            inner join chat_settings as cs on cs.user_id = s.user_id
+        where cs.trending and not cs.banned and position(':' in c.name) = 0;
+end
+$$;
+
+create or replace function list_trending_subscribers(_crate varchar(64))
+    RETURNS TABLE(user_id bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select s.user_id
+        from subscriptions as s
+            inner join crates as c on c.id = s.crate_id
+            inner join chat_settings as cs on cs.user_id = s.user_id
+        where c.name = _crate and cs.trending and not cs.banned;
+end
+$$;
+
+-- samples of the crate, the newest first
+create or replace function list_download_samples(_crate varchar(64))
+    RETURNS TABLE(age integer, downloads bigint, trending bool)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select current_date - d.day as age, d.downloads, d.trending
+        from download_samples as d
+        where d.crate = _crate
+        order by d.day desc;
+end
+$$;
+
+-- records today's sample of the crate and forgets samples older than 30 days
+create or replace procedure add_download_sample(_crate varchar(64), _downloads bigint, _trending bool)
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into download_samples (crate, downloads, trending) values (_crate, _downloads, _trending)
+        on conflict (crate, day) do update set downloads = _downloads,
+                                               trending = download_samples.trending or _trending;
+    delete from download_samples where crate = _crate and day < current_date - 30;
+end
+$$;
+
+create or replace function get_onboarding(_user_id bigint)
+    RETURNS varchar(16)
+    LANGUAGE plpgsql
+AS $$
+begin
+    return (select onboarding from chat_settings where chat_settings.user_id = _user_id);
+end
+$$;
+
+create or replace procedure set_onboarding(_user_id bigint, _step varchar(16))
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into chat_settings (user_id, onboarding) values (_user_id, _step)
+        on conflict (user_id) do update set onboarding = _step;
+end
+$$;
+
+-- turning threads off forgets them
+create or replace procedure set_threads(_user_id bigint, _threads bool)
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into chat_settings (user_id, threads) values (_user_id, _threads)
+        on conflict (user_id) do update set threads = _threads;
+    if not _threads then
+        delete from crate_threads where user_id = _user_id;
+        update chat_settings set index_message = null where user_id = _user_id;
+    end if;
+end
+$$;
+
+-- whether the chat has threads on and the last message about the crate
+create or replace function get_thread(_user_id bigint, _crate varchar(128))
+    RETURNS TABLE(enabled bool, message_id bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select coalesce((select cs.threads from chat_settings as cs where cs.user_id = _user_id), false),
+                        (select t.message_id from crate_threads as t where t.user_id = _user_id and t.crate = _crate);
+end
+$$;
+
+create or replace procedure set_thread(_user_id bigint, _crate varchar(128), _message_id bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into crate_threads (user_id, crate, message_id) values (_user_id, _crate, _message_id)
+        on conflict (user_id, crate) do update set message_id = _message_id;
+end
+$$;
+
+create or replace procedure delete_thread(_user_id bigint, _crate varchar(128))
+    LANGUAGE plpgsql
+AS $$
+begin
+    delete from crate_threads where user_id = _user_id and crate = _crate;
+end
+$$;
+
+create or replace function list_threads(_user_id bigint)
+    RETURNS TABLE(crate_name varchar(128), message_id bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select t.crate, t.message_id from crate_threads as t where t.user_id = _user_id order by t.crate;
+end
+$$;
+
+create or replace function get_index_message(_user_id bigint)
+    RETURNS bigint
+    LANGUAGE plpgsql
+AS $$
+begin
+    return (select index_message from chat_settings where chat_settings.user_id = _user_id);
+end
+$$;
+
+create or replace procedure set_index_message(_user_id bigint, _message_id bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    update chat_settings set index_message = _message_id where user_id = _user_id;
+end
+$$;
+
+create or replace procedure set_trending(_user_id bigint, _trending bool)
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into chat_settings (user_id, trending) values (_user_id, _trending)
+        on conflict (user_id) do update set trending = _trending;
+end
+$$;
+
+create or replace function list_owners()
+    RETURNS TABLE(owner varchar(64))
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select distinct o.owner from owner_subscriptions as o;
+end
+$$;
+
+-- subscribes watchers of the owner to crates which weren't there at the last refresh
+create or replace procedure refresh_owner(_owner varchar(64), _crates varchar(64)[])
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into crates (name) select unnest(_crates) on conflict do nothing;
+
+    insert into subscriptions (user_id, crate_id)
+        select o.user_id, c.id
+            from owner_subscriptions as o
+                inner join crates as c on c.name = any(_crates) and not c.name = any(o.crates)
+            where o.owner = _owner
+        on conflict do nothing;
+
+    update owner_subscriptions set crates = _crates where owner = _owner;
+end
+$$;
+
+create or replace procedure subscribe_tag(_user_id bigint, _kind varchar(8), _tag varchar(64))
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into tag_subscriptions (user_id, kind, tag) values (_user_id, _kind, _tag)
+        on conflict do nothing;
+end
+$$;
+
+create or replace function unsubscribe_tag(_user_id bigint, _kind varchar(8), _tag varchar(64))
+    RETURNS bool
+    LANGUAGE plpgsql
+AS $$
+begin
+    delete from tag_subscriptions as t where t.user_id = _user_id and t.kind = _kind and t.tag = _tag;
+
+    return found;
+end
+$$;
+
+create or replace function list_tags()
+    RETURNS TABLE(kind varchar(8), tag varchar(64))
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select distinct t.kind, t.tag from tag_subscriptions as t;
+end
+$$;
+
+create or replace procedure set_tag_crates(_kind varchar(8), _tag varchar(64), _crates varchar(64)[])
+    LANGUAGE plpgsql
+AS $$
+begin
+    delete from tag_crates as tc
+        where tc.kind = _kind and tc.tag = _tag and not tc.crate_name = any(_crates);
+
+    insert into tag_crates (kind, tag, crate_name)
+        select _kind, _tag, unnest(_crates)
+        on conflict do nothing;
+end
+$$;
+
+create or replace function count_subscriptions()
+    RETURNS TABLE(subscriptions bigint, chats bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select count(*) as subscriptions, count(distinct s.user_id) as chats
+        from subscriptions as s;
+end
+$$;
+
+-- numbers of items waiting to be handled, for `/health` and `/status`
+create or replace function queue_depths()
+    RETURNS TABLE(deliveries bigint, digest bigint, email bigint, pending_releases bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select (select count(*) from delivery_jobs),
+                        (select count(*) from digest_queue),
+                        (select count(*) from email_queue),
+                        (select count(*) from releases as r where r.pending is not null);
+end
+$$;
+
+-- pauses notifications of the chat after failed deliveries
+create or replace procedure disable_chat(_user_id bigint, _reason text)
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into chat_settings (user_id, disabled_at, disabled_reason) values (_user_id, now(), _reason)
+        on conflict (user_id) do update set disabled_at = now(), disabled_reason = _reason;
+end
+$$;
+
+-- resumes notifications of the chat, returns whether they were paused
+create or replace function resume_chat(_user_id bigint)
+    RETURNS bool
+    LANGUAGE plpgsql
+AS $$
+begin
+    update chat_settings set disabled_at = null, disabled_reason = null
+        where chat_settings.user_id = _user_id and disabled_at is not null;
+    return found;
+end
+$$;
+
+create or replace procedure add_dead_letter(_user_id bigint, _crate varchar(128), _version varchar(128),
+                                            _action varchar(8), _text text, _error text)
+    LANGUAGE plpgsql
+AS $$
+begin
+    delete from dead_letters where failed_at < now() - interval '30 days';
+    insert into dead_letters (user_id, crate, version, action, text, error)
+        values (_user_id, _crate, _version, _action, _text, _error);
+end
+$$;
+
+-- moves everything of the chat to its new id, after a group was upgraded to a supergroup. Settings and
+-- tokens of the old chat win, subscriptions and queued notifications of both are kept.
+create or replace procedure migrate_chat(_from bigint, _to bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    if exists (select * from chat_settings where user_id = _from) then
+        delete from chat_settings where user_id = _to;
+        update chat_settings set user_id = _to, index_message = null where user_id = _from;
+    end if;
+    if exists (select * from feed_tokens where user_id = _from) then
+        delete from feed_tokens where user_id = _to;
+        update feed_tokens set user_id = _to where user_id = _from;
+    end if;
+    if exists (select * from api_tokens where user_id = _from) then
+        delete from api_tokens where user_id = _to;
+        update api_tokens set user_id = _to where user_id = _from;
+    end if;
+    if exists (select * from emails where user_id = _from) then
+        delete from emails where user_id = _to;
+        update emails set user_id = _to where user_id = _from;
+    end if;
+    if exists (select * from new_crate_subscriptions where user_id = _from) then
+        delete from new_crate_subscriptions where user_id = _to;
+        update new_crate_subscriptions set user_id = _to where user_id = _from;
+    end if;
+
+    update subscriptions as s set user_id = _to
+        where s.user_id = _from
+          and not exists (select * from subscriptions as o where o.user_id = _to and o.crate_id = s.crate_id);
+    update owner_subscriptions as s set user_id = _to
+        where s.user_id = _from
+          and not exists (select * from owner_subscriptions as o where o.user_id = _to and o.owner = s.owner);
+    update dep_groups as s set user_id = _to
+        where s.user_id = _from
+          and not exists (select * from dep_groups as o where o.user_id = _to and o.parent = s.parent);
+    update tag_subscriptions as s set user_id = _to
+        where s.user_id = _from
+          and not exists (select * from tag_subscriptions as o
+                              where o.user_id = _to and o.kind = s.kind and o.tag = s.tag);
+    update name_watches as s set user_id = _to
+        where s.user_id = _from
+          and not exists (select * from name_watches as o where o.user_id = _to and o.name = s.name);
+    update version_waits as s set user_id = _to
+        where s.user_id = _from
+          and not exists (select * from version_waits as o
+                              where o.user_id = _to and o.crate = s.crate and o.requirement = s.requirement);
+    update deferred_notifications as s set user_id = _to
+        where s.user_id = _from
+          and not exists (select * from deferred_notifications as o
+                              where o.user_id = _to and o.crate_id = s.crate_id and o.version = s.version
+                                and o.action = s.action);
+    update deliveries as s set user_id = _to
+        where s.user_id = _from
+          and not exists (select * from deliveries as o
+                              where o.user_id = _to and o.crate_id = s.crate_id and o.version = s.version
+                                and o.action = s.action);
+    update delivery_jobs as s set user_id = _to
+        where s.user_id = _from
+          and not exists (select * from delivery_jobs as o
+                              where o.user_id = _to and o.crate_id = s.crate_id and o.version = s.version
+                                and o.action = s.action);
+    update digest_queue set user_id = _to where user_id = _from;
+    update email_queue set user_id = _to where user_id = _from;
+    update dead_letters set user_id = _to where user_id = _from;
+
+    -- leftovers duplicate rows of the new chat, messages of the old one can't be replied to
+    delete from subscriptions where user_id = _from;
+    delete from owner_subscriptions where user_id = _from;
+    delete from dep_groups where user_id = _from;
+    delete from tag_subscriptions where user_id = _from;
+    delete from name_watches where user_id = _from;
+    delete from version_waits where user_id = _from;
+    delete from deferred_notifications where user_id = _from;
+    delete from deliveries where user_id = _from;
+    delete from delivery_jobs where user_id = _from;
+    delete from crate_threads where user_id = _from;
+    delete from announcements where user_id = _from;
+    delete from sent_notifications where user_id = _from;
+end
+$$;
+
+-- everything stored about the chat (`/my_data`, `export-chat`), crates are referred to by name. Tables with rows of
+-- chats are listed here, in `forget_chat`, in `migrate_chat` and in `import_chat`. The e-mail address is left out
+-- since it may be encrypted.
+create or replace function chat_data(_user_id bigint)
+    RETURNS text
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN jsonb_build_object(
+        'chat_id', _user_id,
+        'settings', (select to_jsonb(t) - 'user_id' from chat_settings as t where t.user_id = _user_id),
+        'subscriptions', (select coalesce(jsonb_agg((to_jsonb(t) - 'user_id' - 'crate_id') || jsonb_build_object('crate', c.name)), '[]')
+            from subscriptions as t
+                inner join crates as c on c.id = t.crate_id
+            where t.user_id = _user_id),
+        'owner_subscriptions', (select coalesce(jsonb_agg(to_jsonb(t) - 'user_id'), '[]') from owner_subscriptions as t where t.user_id = _user_id),
+        'dep_groups', (select coalesce(jsonb_agg(to_jsonb(t) - 'user_id'), '[]') from dep_groups as t where t.user_id = _user_id),
+        'tag_subscriptions', (select coalesce(jsonb_agg(to_jsonb(t) - 'user_id'), '[]') from tag_subscriptions as t where t.user_id = _user_id),
+        'name_watches', (select coalesce(jsonb_agg(to_jsonb(t) - 'user_id'), '[]') from name_watches as t where t.user_id = _user_id),
+        'version_waits', (select coalesce(jsonb_agg(to_jsonb(t) - 'user_id'), '[]') from version_waits as t where t.user_id = _user_id),
+        'new_crate_subscription', (select to_jsonb(t) - 'user_id' from new_crate_subscriptions as t where t.user_id = _user_id),
+        'threads', (select coalesce(jsonb_agg(to_jsonb(t) - 'user_id'), '[]') from crate_threads as t where t.user_id = _user_id),
+        'announcements', (select coalesce(jsonb_agg(to_jsonb(t) - 'user_id'), '[]') from announcements as t where t.user_id = _user_id),
+        'sent_notifications', (select coalesce(jsonb_agg(to_jsonb(t) - 'user_id'), '[]') from sent_notifications as t where t.user_id = _user_id),
+        'feed_token', (select to_jsonb(t) - 'user_id' from feed_tokens as t where t.user_id = _user_id),
+        'api_token', (select to_jsonb(t) - 'user_id' from api_tokens as t where t.user_id = _user_id),
+        'email', (select to_jsonb(t) - 'user_id' - 'address' from emails as t where t.user_id = _user_id),
+        'email_queue', (select coalesce(jsonb_agg((to_jsonb(t) - 'user_id' - 'crate_id') || jsonb_build_object('crate', c.name)), '[]')
+            from email_queue as t
+                inner join crates as c on c.id = t.crate_id
+            where t.user_id = _user_id),
+        'digest_queue', (select coalesce(jsonb_agg((to_jsonb(t) - 'user_id' - 'crate_id') || jsonb_build_object('crate', c.name)), '[]')
+            from digest_queue as t
+                inner join crates as c on c.id = t.crate_id
+            where t.user_id = _user_id),
+        'deferred_notifications', (select coalesce(jsonb_agg((to_jsonb(t) - 'user_id' - 'crate_id') || jsonb_build_object('crate', c.name)), '[]')
+            from deferred_notifications as t
+                inner join crates as c on c.id = t.crate_id
+            where t.user_id = _user_id),
+        'deliveries', (select coalesce(jsonb_agg((to_jsonb(t) - 'user_id' - 'crate_id') || jsonb_build_object('crate', c.name)), '[]')
+            from deliveries as t
+                inner join crates as c on c.id = t.crate_id
+            where t.user_id = _user_id),
+        'delivery_jobs', (select coalesce(jsonb_agg((to_jsonb(t) - 'user_id' - 'crate_id') || jsonb_build_object('crate', c.name)), '[]')
+            from delivery_jobs as t
+                inner join crates as c on c.id = t.crate_id
+            where t.user_id = _user_id),
+        'dead_letters', (select coalesce(jsonb_agg(to_jsonb(t) - 'user_id'), '[]') from dead_letters as t where t.user_id = _user_id)
+    )::text;
+end
+$$;
+
+-- `/forget_me`: removes everything stored about the chat, the tables of `chat_data`
+create or replace procedure forget_chat(_user_id bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    delete from subscriptions where user_id = _user_id;
+    delete from owner_subscriptions where user_id = _user_id;
+    delete from dep_groups where user_id = _user_id;
+    delete from tag_subscriptions where user_id = _user_id;
+    delete from name_watches where user_id = _user_id;
+    delete from version_waits where user_id = _user_id;
+    delete from new_crate_subscriptions where user_id = _user_id;
+    delete from crate_threads where user_id = _user_id;
+    delete from announcements where user_id = _user_id;
+    delete from sent_notifications where user_id = _user_id;
+    delete from feed_tokens where user_id = _user_id;
+    delete from api_tokens where user_id = _user_id;
+    delete from emails where user_id = _user_id;
+    delete from email_queue where user_id = _user_id;
+    delete from digest_queue where user_id = _user_id;
+    delete from deferred_notifications where user_id = _user_id;
+    delete from deliveries where user_id = _user_id;
+    delete from delivery_jobs where user_id = _user_id;
+    delete from dead_letters where user_id = _user_id;
+    delete from chat_settings where user_id = _user_id;
+end
+$$;
+
+-- `import-chat`: restores `chat_data` exported by another instance of the bot, rows the chat already has are kept.
+-- Left out are the e-mail (its address isn't exported) with its queue, and messages of the old instance: threads,
+-- announcements, sent notifications and the pinned index.
+create or replace procedure import_chat(_user_id bigint, _data text)
+    LANGUAGE plpgsql
+AS $$
+declare
+    d jsonb := _data::jsonb;
+    chat jsonb := jsonb_build_object('user_id', _user_id);
+begin
+    insert into crates (name)
+        select distinct r ->> 'crate'
+            from jsonb_array_elements((d -> 'subscriptions') || (d -> 'digest_queue') || (d -> 'deferred_notifications')
+                                      || (d -> 'deliveries') || (d -> 'delivery_jobs')) as t(r)
+        on conflict do nothing;
+
+    if jsonb_typeof(d -> 'settings') = 'object' then
+        insert into chat_settings
+            select * from jsonb_populate_record(null::chat_settings, (d -> 'settings') - 'index_message' || chat)
+            on conflict do nothing;
+    end if;
+    if jsonb_typeof(d -> 'new_crate_subscription') = 'object' then
+        insert into new_crate_subscriptions
+            select * from jsonb_populate_record(null::new_crate_subscriptions, (d -> 'new_crate_subscription') || chat)
+            on conflict do nothing;
+    end if;
+    if jsonb_typeof(d -> 'feed_token') = 'object' then
+        insert into feed_tokens
+            select * from jsonb_populate_record(null::feed_tokens, (d -> 'feed_token') || chat)
+            on conflict do nothing;
+    end if;
+    if jsonb_typeof(d -> 'api_token') = 'object' then
+        insert into api_tokens
+            select * from jsonb_populate_record(null::api_tokens, (d -> 'api_token') || chat)
+            on conflict do nothing;
+    end if;
+
+    insert into subscriptions
+        select p.*
+            from jsonb_array_elements(d -> 'subscriptions') as t(r)
+                inner join crates as c on c.name = r ->> 'crate',
+                jsonb_populate_record(null::subscriptions, r || chat || jsonb_build_object('crate_id', c.id)) as p
+        on conflict do nothing;
+    insert into owner_subscriptions
+        select p.* from jsonb_array_elements(d -> 'owner_subscriptions') as t(r),
+                        jsonb_populate_record(null::owner_subscriptions, r || chat) as p
+        on conflict do nothing;
+    insert into dep_groups
+        select p.* from jsonb_array_elements(d -> 'dep_groups') as t(r),
+                        jsonb_populate_record(null::dep_groups, r || chat) as p
+        on conflict do nothing;
+    insert into tag_subscriptions
+        select p.* from jsonb_array_elements(d -> 'tag_subscriptions') as t(r),
+                        jsonb_populate_record(null::tag_subscriptions, r || chat) as p
+        on conflict do nothing;
+    insert into name_watches
+        select p.* from jsonb_array_elements(d -> 'name_watches') as t(r),
+                        jsonb_populate_record(null::name_watches, r || chat) as p
+        on conflict do nothing;
+    insert into version_waits
+        select p.* from jsonb_array_elements(d -> 'version_waits') as t(r),
+                        jsonb_populate_record(null::version_waits, r || chat) as p
+        on conflict do nothing;
+
+    -- queued notifications and history, ids are taken from the sequences of this instance and leases of the old
+    -- instance don't hold here
+    insert into digest_queue
+        select p.*
+            from jsonb_array_elements(d -> 'digest_queue') as t(r)
+                inner join crates as c on c.name = r ->> 'crate',
+                jsonb_populate_record(null::digest_queue, r || chat || jsonb_build_object(
+                    'crate_id', c.id, 'id', nextval(pg_get_serial_sequence('digest_queue', 'id')))) as p
+            where not exists (select * from digest_queue as o
+                                  where o.user_id = _user_id and o.crate_id = c.id and o.version = p.version
+                                    and o.action = p.action);
+    insert into deferred_notifications
+        select p.*
+            from jsonb_array_elements(d -> 'deferred_notifications') as t(r)
+                inner join crates as c on c.name = r ->> 'crate',
+                jsonb_populate_record(null::deferred_notifications, r || chat || jsonb_build_object(
+                    'crate_id', c.id, 'id', nextval(pg_get_serial_sequence('deferred_notifications', 'id')))) as p
+        on conflict do nothing;
+    insert into deliveries
+        select p.*
+            from jsonb_array_elements(d -> 'deliveries') as t(r)
+                inner join crates as c on c.name = r ->> 'crate',
+                jsonb_populate_record(null::deliveries, r || chat || jsonb_build_object('crate_id', c.id)) as p
+        on conflict do nothing;
+    insert into delivery_jobs
+        select p.*
+            from jsonb_array_elements(d -> 'delivery_jobs') as t(r)
+                inner join crates as c on c.name = r ->> 'crate',
+                jsonb_populate_record(null::delivery_jobs, r || chat || jsonb_build_object(
+                    'crate_id', c.id, 'id', nextval(pg_get_serial_sequence('delivery_jobs', 'id')),
+                    'leased_by', null, 'leased_until', null)) as p
+        on conflict do nothing;
+    insert into dead_letters
+        select p.*
+            from jsonb_array_elements(d -> 'dead_letters') as t(r),
+                jsonb_populate_record(null::dead_letters, r || chat || jsonb_build_object(
+                    'id', nextval(pg_get_serial_sequence('dead_letters', 'id')))) as p
+            where not exists (select * from dead_letters as o
+                                  where o.user_id = _user_id and o.failed_at = p.failed_at and o.text = p.text);
+end
+$$;
+
+create or replace procedure set_banned(_user_id bigint, _banned bool)
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into chat_settings (user_id, banned) values (_user_id, _banned)
+        on conflict (user_id) do update set banned = _banned;
+end
+$$;
+
+create or replace function is_banned(_user_id bigint)
+    RETURNS bool
+    LANGUAGE plpgsql
+AS $$
+begin
+    return coalesce((select banned from chat_settings where chat_settings.user_id = _user_id), false);
+end
+$$;
+
+create or replace function top_crates(_limit bigint)
+    RETURNS TABLE(crate_name varchar(64), subscribers bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select c.name as crate_name, count(*) as subscribers
+        from subscriptions as s
+             inner join crates as c on c.id = s.crate_id
+        group by c.name
+        order by subscribers desc, c.name
+        limit _limit;
+end
+$$;
+
+-- chats with any subscription, except banned ones
+create or replace function list_chats()
+    RETURNS TABLE(user_id bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select chats.user_id
+        from (select s.user_id from subscriptions as s
+              union select t.user_id from tag_subscriptions as t
+              union select o.user_id from owner_subscriptions as o
+              union select g.user_id from dep_groups as g
+              union select w.user_id from name_watches as w
+              union select v.user_id from version_waits as v) as chats
+             left join chat_settings as cs on cs.user_id = chats.user_id
+        where not coalesce(cs.banned, false)
+        order by chats.user_id;
+end
+$$;
+
+create or replace procedure set_release_notes(_crate varchar(128), _version varchar(128), _notes text)
+    LANGUAGE plpgsql
+AS $$
+begin
+    update releases set notes = _notes
+        from crates as c
+        where c.id = releases.crate_id and c.name = _crate and releases.version = _version;
+end
+$$;
+
+-- release notes of a recorded release, null if there are none
+create or replace function get_release_notes(_crate varchar(128), _version varchar(128))
+    RETURNS text
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN (select r.notes from releases as r
+                inner join crates as c on c.id = r.crate_id
+            where c.name = _crate and r.version = _version);
+end
+$$;
+
+create or replace function get_feed_token(_user_id bigint)
+    RETURNS varchar(64)
+    LANGUAGE plpgsql
+AS $$
+begin
+    return (select token from feed_tokens where feed_tokens.user_id = _user_id);
+end
+$$;
+
+create or replace procedure set_feed_token(_user_id bigint, _token varchar(64))
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into feed_tokens (user_id, token) values (_user_id, _token)
+        on conflict (user_id) do update set token = _token;
+end
+$$;
+
+create or replace function crate_feed(_crate varchar(128), _limit bigint)
+    RETURNS TABLE(crate_name varchar(128), version varchar(128), yanked bool, published_at text, notes text)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select c.name, r.version, r.yanked,
+                        to_char(r.published_at at time zone 'utc', 'YYYY-MM-DD"T"HH24:MI:SS"Z"'), r.notes
+        from releases as r
+             inner join crates as c on c.id = r.crate_id
+        where c.name = _crate
+        order by r.published_at desc
+        limit _limit;
+end
+$$;
+
+-- chat with the feed token, null if there is none
+create or replace function feed_user(_token varchar(64))
+    RETURNS bigint
+    LANGUAGE plpgsql
+AS $$
+begin
+    return (select user_id from feed_tokens where feed_tokens.token = _token);
+end
+$$;
+
+-- releases of crates the chat is subscribed to
+create or replace function user_feed(_user_id bigint, _limit bigint)
+    RETURNS TABLE(crate_name varchar(128), version varchar(128), yanked bool, published_at text, notes text)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select c.name, r.version, r.yanked,
+                        to_char(r.published_at at time zone 'utc', 'YYYY-MM-DD"T"HH24:MI:SS"Z"'), r.notes
+        from subscriptions as s
+             inner join crates as c on c.id = s.crate_id
+             inner join releases as r on r.crate_id = c.id
+        where s.user_id = _user_id
+        order by r.published_at desc
+        limit _limit;
+end
+$$;
+
+-- releases of crates the chat is subscribed to published after the unix time, the oldest first
+create or replace function user_events(_user_id bigint, _since bigint, _limit bigint)
+    RETURNS TABLE(crate_name varchar(128), version varchar(128), yanked bool, published_at text, notes text,
+                  ts bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select c.name, r.version, r.yanked,
+                        to_char(r.published_at at time zone 'utc', 'YYYY-MM-DD"T"HH24:MI:SS"Z"'), r.notes,
+                        extract(epoch from r.published_at)::bigint
+        from subscriptions as s
+             inner join crates as c on c.id = s.crate_id
+             inner join releases as r on r.crate_id = c.id
+        where s.user_id = _user_id and r.published_at > to_timestamp(_since)
+        order by r.published_at
+        limit _limit;
+end
+$$;
+
+create or replace function get_api_token(_user_id bigint)
+    RETURNS varchar(64)
+    LANGUAGE plpgsql
+AS $$
+begin
+    return (select token from api_tokens where api_tokens.user_id = _user_id);
+end
+$$;
+
+create or replace procedure set_api_token(_user_id bigint, _token varchar(64))
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into api_tokens (user_id, token) values (_user_id, _token)
+        on conflict (user_id) do update set token = _token;
+end
+$$;
+
+-- chat with the api token, null if there is none (or the chat is banned)
+create or replace function api_user(_token varchar(64))
+    RETURNS bigint
+    LANGUAGE plpgsql
+AS $$
+begin
+    return (select t.user_id
+                from api_tokens as t
+                    left join chat_settings as cs on cs.user_id = t.user_id
+                where t.token = _token and not coalesce(cs.banned, false));
+end
+$$;
+
+-- the address is text since it may be encrypted
+drop procedure if exists set_email(bigint, varchar, varchar);
+
+-- registers the address (a new one gets a new token), the frequency is kept
+create or replace procedure set_email(_user_id bigint, _address text, _token varchar(64))
+    LANGUAGE plpgsql
+AS $$
+begin
+    insert into emails (user_id, address, token) values (_user_id, _address, _token)
+        on conflict (user_id) do update set address = _address, token = _token;
+end
+$$;
+
+-- false if the chat has no e-mail address
+create or replace function set_email_frequency(_user_id bigint, _frequency varchar(8))
+    RETURNS bool
+    LANGUAGE plpgsql
+AS $$
+begin
+    update emails set frequency = _frequency where emails.user_id = _user_id;
+    return found;
+end
+$$;
+
+-- the return type has changed (the address may be encrypted)
+drop function if exists get_email(bigint);
+
+-- address, frequency and unsubscribe token of the chat
+create or replace function get_email(_user_id bigint)
+    RETURNS TABLE(address text, frequency varchar(8), token varchar(64))
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select e.address, e.frequency, e.token from emails as e where e.user_id = _user_id;
+end
+$$;
+
+-- removes the address and updates waiting for it, false if there is no such address
+create or replace function remove_email(_user_id bigint)
+    RETURNS bool
+    LANGUAGE plpgsql
+AS $$
+begin
+    delete from email_queue where email_queue.user_id = _user_id;
+    delete from emails where emails.user_id = _user_id;
+    return found;
+end
+$$;
+
+-- the return type has changed (the address may be encrypted)
+drop function if exists unsubscribe_email(varchar);
+
+-- unsubscribe link: removes the address with the token, null if there is none
+create or replace function unsubscribe_email(_token varchar(64))
+    RETURNS text
+    LANGUAGE plpgsql
+AS $$
+declare
+    _user_id bigint;
+    _address text;
+begin
+    delete from emails where emails.token = _token returning user_id, address into _user_id, _address;
+    delete from email_queue where email_queue.user_id = _user_id;
+    return _address;
+end
+$$;
+
+-- stored addresses of all chats, for `encrypt-storage`
+create or replace function list_stored_emails()
+    RETURNS TABLE(user_id bigint, address text)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select e.user_id, e.address from emails as e order by e.user_id;
+end
+$$;
+
+-- replaces the stored form of the address, keeping the token and the frequency
+create or replace procedure set_stored_email(_user_id bigint, _address text)
+    LANGUAGE plpgsql
+AS $$
+begin
+    update emails set address = _address where emails.user_id = _user_id;
+end
+$$;
+
+create or replace procedure queue_email(_user_id bigint, _crate varchar(128), _version varchar(128), _action varchar(8))
+    LANGUAGE plpgsql
+AS $$
+begin
+    -- a replayed release is queued once
+    insert into email_queue (user_id, crate_id, version, action)
+        select _user_id, id, _version, _action from crates
+            where crates.name = _crate
+                and not exists (select * from email_queue as q
+                                    where q.user_id = _user_id and q.crate_id = crates.id
+                                        and q.version = _version and q.action = _action);
+end
+$$;
+
+-- chats with queued updates whose next e-mail is due
+create or replace function due_emails()
+    RETURNS TABLE(user_id bigint)
+    LANGUAGE plpgsql
+AS $$
+begin
+    RETURN QUERY select e.user_id
+         from emails as e
+         where exists (select * from email_queue as q where q.user_id = e.user_id)
+             and (e.frequency = 'instant'
+                  or e.sent_at is null
+                  or (e.frequency = 'daily' and e.sent_at <= now() - interval '1 day')
+                  or (e.frequency = 'weekly' and e.sent_at <= now() - interval '7 days'));
+end
+$$;
+
+-- marks the e-mail as sent now and removes its updates from the queue,
+-- updates are returned with release notes from the archive
+create or replace function take_email(_user_id bigint)
+    RETURNS TABLE(crate_name varchar(128), version varchar(128), action varchar(8), notes text)
+    LANGUAGE plpgsql
+AS $$
+begin
+    update emails set sent_at = now() where emails.user_id = _user_id;
+
+    RETURN QUERY with taken as (
+            delete from email_queue as q where q.user_id = _user_id
+                returning q.id, q.crate_id, q.version, q.action
+        )
+        select c.name as crate_name, taken.version as version, taken.action as action, r.notes as notes
+            from taken
+                inner join crates as c on c.id = taken.crate_id
+                left join releases as r on r.crate_id = taken.crate_id and r.version = taken.version
+            order by c.name, taken.id;
+end
+$$;
+
@@ -0,0 +1,59 @@
+//! Parsing of a large (~500 KB) changelog: `cargo bench -p kacl-parser`
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use kacl_parser::{parse_strict, ChangelogBuilder, Limits, ReleaseStream};
+
+/// Changelog of `releases` releases with a few sections of entries each, the newest first
+fn changelog(releases: u32) -> String {
+    let mut src = String::from("# Changelog\n\n## [Unreleased]\n\n### Added\n\n- Something new\n");
+    for i in (0..releases).rev() {
+        let (major, minor, patch) = (i / 100, i / 10 % 10, i % 10);
+        src.push_str(&format!(
+            "\n## [{}.{}.{}] - 2021-{:02}-{:02}\n",
+            major,
+            minor,
+            patch,
+            i % 12 + 1,
+            i % 28 + 1
+        ));
+        for section in &["Added", "Changed", "Fixed"] {
+            src.push_str(&format!("\n### {}\n\n", section));
+            for entry in 0..5 {
+                src.push_str(&format!(
+                    "- `item_{}` now handles **edge case** number {} ([#{}](https://example.com/pull/{}))\n",
+                    entry, i, entry, i
+                ));
+            }
+        }
+    }
+    for i in 0..releases {
+        src.push_str(&format!(
+            "\n[{0}.{1}.{2}]: https://example.com/compare/v{0}.{1}.{2}",
+            i / 100,
+            i / 10 % 10,
+            i % 10
+        ));
+    }
+    src.push('\n');
+    src
+}
+
+fn parse(c: &mut Criterion) {
+    let src = changelog(350);
+    let limits = Limits::unlimited();
+
+    let mut group = c.benchmark_group("500 KB changelog");
+    group.throughput(Throughput::Bytes(src.len() as u64));
+    group.bench_function("builder", |b| {
+        b.iter(|| ChangelogBuilder::from_markdown(black_box(&src)))
+    });
+    group.bench_function("strict", |b| {
+        b.iter(|| parse_strict(black_box(&src), &limits).expect("generated changelog is valid"))
+    });
+    group.bench_function("stream, the latest release", |b| {
+        b.iter(|| ReleaseStream::from_markdown(black_box(&src)).nth(1))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
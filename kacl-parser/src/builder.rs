@@ -0,0 +1,493 @@
+use crate::{
+    conventional,
+    links::{normalize_label, parse_definition, reference_definitions},
+    to_commonmark, Changelog, Date, Format, ParseOptions, Version, VersionScheme,
+};
+use comrak::nodes::{AstNode, NodeHeading, NodeValue};
+use std::{collections::HashMap, fmt, ops::Range};
+use versions::SemVer;
+
+/// Section names in the order recommended by keepachangelog
+pub const SECTIONS: [&str; 6] = [
+    "Added",
+    "Changed",
+    "Deprecated",
+    "Removed",
+    "Fixed",
+    "Security",
+];
+
+/// Markers of breaking changes in entry text, matched case-sensitively
+const BREAKING_MARKERS: [&str; 2] = ["BREAKING", "⚠"];
+
+/// "breaking change" in any case, as a separate phrase: `non-breaking change` isn't one
+fn has_breaking_change(text: &str) -> bool {
+    const PHRASE: &str = "breaking change";
+    let text = text.to_lowercase();
+    text.match_indices(PHRASE).any(|(start, _)| {
+        let word = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+        let before = text[..start].chars().next_back();
+        // `changes` and `changed` are fine, `changelog` isn't
+        let after = text[start + PHRASE.len()..].chars().next();
+        !before.map_or(false, word) && !after.map_or(false, |c| word(c) && c != 's' && c != 'd')
+    })
+}
+
+fn is_breaking(text: &str) -> bool {
+    BREAKING_MARKERS.iter().any(|m| text.contains(m)) || has_breaking_change(text)
+}
+
+/// Markdown of a block of a list item. comrak keeps the indentation of nested lists, it's cut,
+/// and lines of whitespace become empty.
+fn item_block<'a>(block: &'a AstNode<'a>) -> String {
+    let markdown = to_commonmark(block);
+    let blank = |line: &str| line.trim().is_empty();
+    let indent = markdown
+        .lines()
+        .filter(|line| !blank(line))
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let lines: Vec<_> = markdown
+        .lines()
+        .map(|line| if blank(line) { "" } else { &line[indent..] })
+        .collect();
+    lines.join("\n").trim().to_owned()
+}
+
+/// Cuts trailing blank lines and link reference definitions, which are usually at the end of the
+/// document after the last release
+fn trim_raw(raw: &str) -> &str {
+    let mut raw = raw.trim_end();
+    while let Some(line) = raw
+        .rsplit('\n')
+        .next()
+        .filter(|line| parse_definition(line).is_some())
+    {
+        raw = raw[..raw.len() - line.len()].trim_end();
+    }
+    raw
+}
+
+/// Single `### <name>` section of a release
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    /// Markdown of list entries (without the leading `- `)
+    pub entries: Vec<String>,
+    /// Markdown of other blocks of the section (paragraphs, code blocks, ...), emitted before
+    /// the entries
+    pub blocks: Vec<String>,
+}
+
+impl Section {
+    pub fn new(name: impl Into<String>) -> Self {
+        Section {
+            name: name.into(),
+            entries: Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+
+    pub fn entry(mut self, entry: impl Into<String>) -> Self {
+        self.entries.push(entry.into());
+        self
+    }
+
+    /// Position of the section in the canonical order, unknown sections go last. The unnamed
+    /// section goes first: after a heading its entries would be parsed as entries of that section.
+    pub(crate) fn order(&self) -> usize {
+        if self.name.is_empty() {
+            return 0;
+        }
+        SECTIONS
+            .iter()
+            .position(|s| s.eq_ignore_ascii_case(&self.name))
+            .map_or(SECTIONS.len() + 1, |idx| idx + 1)
+    }
+}
+
+/// Owned version of a single release: its heading and sections
+#[derive(Debug, Clone)]
+pub struct Release {
+    pub version: Version,
+    pub sections: Vec<Section>,
+    /// Url from the `[x.y.z]: url` reference definition, usually a compare url
+    pub link: Option<String>,
+    /// Markdown of the release as the author wrote it, from its heading up to the next version
+    /// heading (trailing blank lines and reference definitions dropped). `None` unless the release
+    /// was parsed from markdown by [`ChangelogBuilder::from_markdown`] or
+    /// [`ReleaseStream`](crate::ReleaseStream).
+    pub raw: Option<String>,
+    /// Byte range of `raw` in the parsed markdown
+    pub span: Option<Range<usize>>,
+}
+
+impl Release {
+    pub fn new(version: Version) -> Self {
+        Release {
+            version,
+            sections: Vec::new(),
+            link: None,
+            raw: None,
+            span: None,
+        }
+    }
+
+    pub fn link(mut self, link: impl Into<String>) -> Self {
+        self.link = Some(link.into());
+        self
+    }
+
+    pub fn section(mut self, section: Section) -> Self {
+        self.sections.push(section);
+        self
+    }
+
+    /// Entries which look like breaking changes: containing "BREAKING", "breaking change",
+    /// "⚠" or listed in a section named like that (e.g. `### Breaking Changes`).
+    ///
+    /// This is a heuristic, breaking changes which aren't marked in any way are missed.
+    pub fn breaking_entries(&self) -> impl Iterator<Item = &str> {
+        self.sections.iter().flat_map(|section| {
+            let whole_section = is_breaking(&section.name);
+            section
+                .entries
+                .iter()
+                .filter(move |e| whole_section || is_breaking(e))
+                .map(String::as_str)
+        })
+    }
+
+    /// Collects `### Section` headers and list items following them.
+    ///
+    /// Other blocks are kept as [`Section::blocks`], list items and blocks which don't follow any
+    /// header go to a section with empty name.
+    pub fn from_nodes<'a>(
+        version: Version,
+        nodes: impl IntoIterator<Item = &'a AstNode<'a>>,
+    ) -> Self {
+        let mut sections: Vec<Section> = Vec::new();
+
+        for node in nodes {
+            match node.data.borrow().value {
+                NodeValue::Heading(NodeHeading { level: 3, .. }) => {
+                    // inline nodes rendered alone end with a newline each
+                    let heading = to_commonmark(node);
+                    sections.push(Section::new(heading.trim_start_matches('#').trim()));
+                }
+                NodeValue::List(_) => {
+                    if sections.is_empty() {
+                        sections.push(Section::new(""));
+                    }
+                    let section = sections.last_mut().unwrap();
+                    section.entries.extend(node.children().map(|item| {
+                        item.children()
+                            .map(item_block)
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    }));
+                }
+                _ => {
+                    if sections.is_empty() {
+                        sections.push(Section::new(""));
+                    }
+                    let section = sections.last_mut().unwrap();
+                    section
+                        .blocks
+                        .push(to_commonmark(node).trim_end().to_owned());
+                }
+            }
+        }
+
+        Release {
+            version,
+            sections,
+            link: None,
+            raw: None,
+            span: None,
+        }
+    }
+
+    /// Sets `raw` and `span` from the source of the release starting at byte `start`
+    pub(crate) fn with_raw(mut self, src: &str, start: usize) -> Self {
+        let raw = trim_raw(src);
+        self.span = Some(start..start + raw.len());
+        self.raw = Some(raw.to_owned());
+        self
+    }
+
+    /// Like [`Release::from_nodes`], releases of conventional changelogs are normalized
+    pub(crate) fn from_nodes_as<'a>(
+        version: Version,
+        nodes: impl IntoIterator<Item = &'a AstNode<'a>>,
+        format: Format,
+    ) -> Self {
+        let release = Self::from_nodes(version, nodes);
+        match format {
+            Format::KeepAChangelog => release,
+            Format::Conventional => conventional::normalize(release),
+        }
+    }
+}
+
+impl fmt::Display for Release {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Version::Unreleased => f.write_str("## [Unreleased]")?,
+            Version::Released(v, None, _) => write!(f, "## [{}]", v)?,
+            Version::Released(v, Some(date), _) => write!(f, "## [{}] - {}", v, date)?,
+            Version::Other(v, None, _) => write!(f, "## [{}]", v)?,
+            Version::Other(v, Some(date), _) => write!(f, "## [{}] - {}", v, date)?,
+        }
+        if self.version.is_yanked() {
+            f.write_str(" [YANKED]")?;
+        }
+        f.write_str("\n")?;
+
+        let mut sections: Vec<_> = self.sections.iter().collect();
+        // stable sort keeps the original order of unknown sections
+        sections.sort_by_key(|s| s.order());
+
+        for section in sections {
+            if !section.name.is_empty() {
+                write!(f, "\n### {}\n", section.name)?;
+            }
+            for block in &section.blocks {
+                write!(f, "\n{}\n", block)?;
+            }
+            if !section.entries.is_empty() {
+                f.write_str("\n")?;
+            }
+            for entry in &section.entries {
+                // blank lines aren't indented, whitespace would change the entry when reparsed
+                let mut lines = entry.lines();
+                write!(f, "- {}", lines.next().unwrap_or(""))?;
+                for line in lines {
+                    match line {
+                        "" => f.write_str("\n")?,
+                        line => write!(f, "\n  {}", line)?,
+                    }
+                }
+                f.write_str("\n")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Emits canonical keepachangelog markdown
+#[derive(Debug, Clone)]
+pub struct ChangelogBuilder {
+    preamble: String,
+    releases: Vec<Release>,
+}
+
+impl Default for ChangelogBuilder {
+    fn default() -> Self {
+        ChangelogBuilder {
+            preamble: String::from(
+                "All notable changes to this project will be documented in this file.\n\n\
+                 The format is based on [Keep a Changelog](https://keepachangelog.com/en/1.0.0/),\n\
+                 and this project adheres to [Semantic Versioning](https://semver.org/spec/v2.0.0.html).",
+            ),
+            releases: Vec::new(),
+        }
+    }
+}
+
+impl ChangelogBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collects all releases and the preamble from a parsed changelog
+    pub fn from_changelog<'a, I>(changelog: Changelog<I>) -> Self
+    where
+        I: Iterator<Item = &'a AstNode<'a>>,
+    {
+        let format = changelog.options.format;
+        let this = Self::new().preamble(changelog.preamble());
+        changelog.fold(this, |this, (version, nodes)| {
+            this.release(Release::from_nodes_as(version, nodes, format))
+        })
+    }
+
+    /// Parses markdown and collects the preamble and all releases with their reference links
+    pub fn from_markdown(src: &str) -> Self {
+        Self::from_markdown_with(src, ParseOptions::default())
+    }
+
+    /// Like [`ChangelogBuilder::from_markdown`], but accepts version headings allowed by `options`
+    pub fn from_markdown_with(src: &str, options: ParseOptions) -> Self {
+        let arena = comrak::Arena::new();
+        let root = comrak::parse_document(&arena, src, &comrak::ComrakOptions::default());
+        let format = options.format;
+        let line_starts: Vec<usize> = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(idx, _)| idx + 1))
+            .collect();
+        let offset = |line: u32| {
+            let idx = line.saturating_sub(1) as usize;
+            line_starts.get(idx).copied().unwrap_or(src.len())
+        };
+
+        // releases with byte offsets of their headings, a release ends where the next one starts
+        let mut changelog = Changelog::with_options(root.children(), options);
+        let mut releases = Vec::new();
+        while let Some((version, nodes)) = changelog.next() {
+            let release = Release::from_nodes_as(version, nodes, format);
+            releases.push((release, offset(changelog.line)));
+        }
+        let ends: Vec<usize> = releases
+            .iter()
+            .skip(1)
+            .map(|&(_, start)| start)
+            .chain(Some(src.len()))
+            .collect();
+
+        let this = Self::new().preamble(changelog.preamble());
+        releases
+            .into_iter()
+            .zip(ends)
+            .fold(this, |this, ((release, start), end)| {
+                this.release(release.with_raw(&src[start..end], start))
+            })
+            .resolve_links(&reference_definitions(src))
+    }
+
+    /// Sets links of releases from reference definitions
+    /// (see [`reference_definitions`](crate::reference_definitions))
+    pub fn resolve_links(mut self, definitions: &HashMap<String, String>) -> Self {
+        for release in &mut self.releases {
+            let label = normalize_label(&release.version.label());
+            if let Some(url) = definitions.get(&label) {
+                release.link = Some(url.clone());
+            }
+        }
+        self
+    }
+
+    /// Text between `# Changelog` and the first release
+    pub fn preamble(mut self, preamble: impl Into<String>) -> Self {
+        self.preamble = preamble.into();
+        self
+    }
+
+    pub fn release(mut self, release: Release) -> Self {
+        self.releases.push(release);
+        self
+    }
+
+    pub fn releases(&self) -> &[Release] {
+        &self.releases
+    }
+
+    pub fn releases_mut(&mut self) -> &mut Vec<Release> {
+        &mut self.releases
+    }
+
+    /// Finds release with the given version
+    pub fn find_release(&self, version: &SemVer) -> Option<&Release> {
+        self.releases
+            .iter()
+            .find(|r| r.version.semver() == Some(version))
+    }
+
+    /// Finds release with the given version compared by `scheme`, e.g. `2021.6.1` finds
+    /// `## [2021.06.01]` parsed with [`VersionScheme::Lenient`]
+    pub fn find_release_in(&self, scheme: VersionScheme, version: &str) -> Option<&Release> {
+        self.releases
+            .iter()
+            .find(|r| r.version.is_version(scheme, version))
+    }
+
+    /// Returns all releases strictly after `from` up to and including `to`
+    pub fn releases_between<'s>(
+        &'s self,
+        from: &'s SemVer,
+        to: &'s SemVer,
+    ) -> impl Iterator<Item = &'s Release> {
+        self.releases
+            .iter()
+            .filter(move |r| r.version.is_between(from, to))
+    }
+
+    /// Turns `[Unreleased]` into the release of `version`: its entries move to the new release
+    /// (empty sections are dropped) and a fresh `[Unreleased]` with empty [`SECTIONS`] is added.
+    ///
+    /// If the old `[Unreleased]` link is a compare view (`.../compare/v1.1.0...HEAD`), the new
+    /// release gets `.../compare/v1.1.0...v1.2.0` and `[Unreleased]` gets `.../compare/v1.2.0...HEAD`,
+    /// the tag is made like the tag of the previous release.
+    pub fn release_unreleased(&mut self, version: SemVer, date: Date) {
+        let unreleased = match self
+            .releases
+            .iter()
+            .position(|r| matches!(r.version, Version::Unreleased))
+        {
+            Some(idx) => self.releases.remove(idx),
+            None => Release::new(Version::Unreleased),
+        };
+        let previous = self.releases.iter().find_map(|r| r.version.semver());
+
+        let mut release = Release::new(Version::Released(version.clone(), Some(date), false));
+        release.sections = unreleased
+            .sections
+            .into_iter()
+            .filter(|s| !s.entries.is_empty())
+            .collect();
+        let mut fresh = SECTIONS
+            .iter()
+            .fold(Release::new(Version::Unreleased), |r, name| {
+                r.section(Section::new(*name))
+            });
+
+        // `.../compare/v1.1.0...HEAD`: the base url and the tag of the previous release
+        let compare = unreleased.link.as_deref().and_then(|link| {
+            let from = link.strip_suffix("...HEAD")?;
+            let slash = from.rfind('/')?;
+            Some((&from[..slash], &from[slash + 1..]))
+        });
+        if let (Some((base, from)), Some(previous)) = (compare, previous) {
+            let previous = previous.to_string();
+            if from.contains(&previous) {
+                let tag = from.replace(&previous, &version.to_string());
+                release.link = Some(format!("{}/{}...{}", base, from, tag));
+                fresh.link = Some(format!("{}/{}...HEAD", base, tag));
+            }
+        }
+
+        self.releases.insert(0, release);
+        self.releases.insert(0, fresh);
+    }
+
+    pub fn build(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for ChangelogBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("# Changelog\n")?;
+        if !self.preamble.is_empty() {
+            write!(f, "\n{}\n", self.preamble.trim_end())?;
+        }
+        for release in &self.releases {
+            write!(f, "\n{}", release)?;
+        }
+
+        let mut links = self
+            .releases
+            .iter()
+            .filter_map(|r| r.link.as_ref().map(|link| (r.version.label(), link)))
+            .peekable();
+        if links.peek().is_some() {
+            f.write_str("\n")?;
+        }
+        for (label, link) in links {
+            writeln!(f, "[{}]: {}", label, link)?;
+        }
+
+        Ok(())
+    }
+}
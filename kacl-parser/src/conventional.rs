@@ -0,0 +1,241 @@
+//! Changelogs generated from conventional commits (git-cliff, standard-version, release-please):
+//! headings like `## [1.2.3](compare-url) (2023-01-01)` (`###` for patches of standard-version),
+//! sections like `### Bug Fixes` and entries like `* **parser:** fix … ([#12](…)) ([abc1234](…))`.
+//!
+//! Releases of such changelogs are normalized to keepachangelog: sections get keepachangelog names
+//! (`Features` → `Added`, `Bug Fixes` → `Fixed`, ...) and links to commits are dropped.
+use crate::{Release, Section, SECTIONS};
+
+/// Format of a changelog, see [`Format::detect`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    KeepAChangelog,
+    /// Generated from conventional commits
+    Conventional,
+}
+
+/// Conventional section names (lowercase, without emojis) and the keepachangelog sections their
+/// entries go to. Other sections (`Documentation`, `Miscellaneous Tasks`, `BREAKING CHANGES`, ...)
+/// keep their names.
+const SECTION_NAMES: [(&str, &str); 12] = [
+    ("features", "Added"),
+    ("feature", "Added"),
+    ("bug fixes", "Fixed"),
+    ("bug fix", "Fixed"),
+    ("fixes", "Fixed"),
+    ("performance improvements", "Changed"),
+    ("performance", "Changed"),
+    ("refactor", "Changed"),
+    ("code refactoring", "Changed"),
+    ("reverts", "Changed"),
+    ("deprecations", "Deprecated"),
+    ("removals", "Removed"),
+];
+
+/// Section name without git-cliff's ordering comments (`<!-- 0 -->`) and leading emojis
+fn clean_name(name: &str) -> &str {
+    let mut name = name.trim();
+    while let Some(rest) = name.strip_prefix("<!--") {
+        name = match rest.find("-->") {
+            Some(end) => rest[end + 3..].trim_start(),
+            None => break,
+        };
+    }
+    name.trim_start_matches(|c: char| !c.is_alphanumeric())
+}
+
+/// Keepachangelog section the conventional section goes to
+fn kacl_section(name: &str) -> Option<&'static str> {
+    let name = clean_name(name).to_lowercase();
+    SECTION_NAMES
+        .iter()
+        .find(|(conventional, _)| *conventional == name)
+        .map(|(_, kacl)| *kacl)
+}
+
+impl Format {
+    /// Guesses the format by `### ...` section headings: conventional names (`Features`,
+    /// `Bug Fixes`, ...) against keepachangelog ones (`Added`, `Fixed`, ...)
+    pub fn detect(src: &str) -> Self {
+        let (mut conventional, mut kacl) = (0, 0);
+        for line in src.lines() {
+            let name = match line.trim_start().strip_prefix("### ") {
+                Some(name) => name,
+                None => continue,
+            };
+            if kacl_section(name).is_some() {
+                conventional += 1;
+            } else if SECTIONS.iter().any(|s| s.eq_ignore_ascii_case(name.trim())) {
+                kacl += 1;
+            }
+        }
+
+        if conventional > kacl {
+            Format::Conventional
+        } else {
+            Format::KeepAChangelog
+        }
+    }
+}
+
+/// Whether `s` looks like an abbreviated or full commit hash
+fn is_hash(s: &str) -> bool {
+    (7..=40).contains(&s.len()) && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Removes links to commits: `([abc1234](url))`, `[abc1234](url)` and `(abc1234)`
+fn strip_commits(entry: &str) -> String {
+    let mut out = String::with_capacity(entry.len());
+    let mut rest = entry;
+    while !rest.is_empty() {
+        let (parenthesized, inner) = match rest.strip_prefix('(') {
+            Some(inner) => (true, inner),
+            None => (false, rest),
+        };
+        let link = inner.strip_prefix('[').and_then(|link| {
+            let end = link.find("](")?;
+            let url_end = link[end..].find(')')? + end;
+            Some((&link[..end], &link[url_end + 1..]))
+        });
+        let bare = if parenthesized {
+            inner
+                .find(')')
+                .map(|end| (&inner[..end], &inner[end + 1..]))
+        } else {
+            None
+        };
+        let commit = match (link, bare) {
+            (Some((hash, after)), _) if is_hash(hash) => match after.strip_prefix(')') {
+                Some(after) if parenthesized => Some(after),
+                _ if !parenthesized => Some(after),
+                _ => None,
+            },
+            (_, Some((hash, after))) if is_hash(hash) => Some(after),
+            _ => None,
+        };
+        match commit {
+            Some(after) => {
+                // the space before the link goes too
+                out.truncate(out.trim_end().len());
+                rest = after;
+            }
+            None => {
+                let c = rest.chars().next().expect("rest isn't empty");
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    out
+}
+
+/// Renames sections to keepachangelog names (merging sections which get the same name) and
+/// removes links to commits from entries
+pub(crate) fn normalize(mut release: Release) -> Release {
+    let mut sections: Vec<Section> = Vec::new();
+    for section in release.sections {
+        let name = match kacl_section(&section.name) {
+            Some(kacl) => kacl.to_owned(),
+            None => clean_name(&section.name).to_owned(),
+        };
+        let entries = section.entries.iter().map(|entry| strip_commits(entry));
+        match sections.iter_mut().find(|s| s.name == name) {
+            Some(existing) => {
+                existing.entries.extend(entries);
+                existing.blocks.extend(section.blocks);
+            }
+            None => sections.push(Section {
+                name,
+                entries: entries.collect(),
+                blocks: section.blocks,
+            }),
+        }
+    }
+    release.sections = sections;
+    release
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChangelogBuilder, ParseOptions, ReleaseStream};
+
+    const SRC: &str = "# Changelog\n\
+        \n\
+        ## [1.3.0](https://github.com/a/b/compare/v1.2.0...v1.3.0) (2023-01-01)\n\
+        \n\
+        ### Features\n\
+        \n\
+        * **parser:** accept tabs ([#12](https://github.com/a/b/issues/12)) ([abc1234](https://github.com/a/b/commit/abc1234))\n\
+        \n\
+        ### <!-- 1 -->🐛 Bug Fixes\n\
+        \n\
+        * handle empty input (def5678)\n\
+        \n\
+        ### Performance Improvements\n\
+        \n\
+        * faster lexer ([0123abc](https://github.com/a/b/commit/0123abc))\n\
+        \n\
+        ### [1.2.1](https://github.com/a/b/compare/v1.2.0...v1.2.1) (2022-12-01)\n\
+        \n\
+        ### Bug Fixes\n\
+        \n\
+        * typo in docs\n";
+
+    #[test]
+    fn detection() {
+        assert_eq!(Format::detect(SRC), Format::Conventional);
+        assert_eq!(
+            Format::detect("## 1.0.0\n\n### Added\n\n- a\n\n### Fixed\n\n- b\n"),
+            Format::KeepAChangelog
+        );
+        assert_eq!(Format::detect(""), Format::KeepAChangelog);
+    }
+
+    #[test]
+    fn commits() {
+        assert_eq!(
+            strip_commits("fix ([#1](u)) ([abc1234](u/commit/abc1234)), closes [#2](u)"),
+            "fix ([#1](u)), closes [#2](u)"
+        );
+        assert_eq!(strip_commits("fix (abc1234)"), "fix");
+        assert_eq!(strip_commits("fix [abc1234](u)"), "fix");
+        // not hashes
+        assert_eq!(
+            strip_commits("add (cafe) [face](u)"),
+            "add (cafe) [face](u)"
+        );
+    }
+
+    #[test]
+    fn releases() {
+        let changelog = ChangelogBuilder::from_markdown_with(SRC, ParseOptions::detect(SRC));
+        let releases = changelog.releases();
+        assert_eq!(releases.len(), 2);
+        assert_eq!(releases[0].version.label(), "1.3.0");
+        let sections: Vec<(&str, &[String])> = releases[0]
+            .sections
+            .iter()
+            .map(|s| (s.name.as_str(), s.entries.as_slice()))
+            .collect();
+        assert_eq!(
+            sections,
+            [
+                (
+                    "Added",
+                    &[String::from(
+                        "**parser:** accept tabs ([\\#12](https://github.com/a/b/issues/12))"
+                    )][..]
+                ),
+                ("Fixed", &[String::from("handle empty input")][..]),
+                ("Changed", &[String::from("faster lexer")][..]),
+            ]
+        );
+        assert_eq!(releases[1].version.label(), "1.2.1");
+
+        let streamed: Vec<Release> =
+            ReleaseStream::with_options(SRC.as_bytes(), ParseOptions::detect(SRC)).collect();
+        assert_eq!(streamed.len(), 2);
+        assert_eq!(streamed[1].sections[0].name, "Fixed");
+    }
+}
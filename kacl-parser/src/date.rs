@@ -1,8 +1,10 @@
 use nom::{
-    bytes::complete::{tag, take},
-    combinator::map,
+    branch::alt,
+    bytes::complete::{tag, tag_no_case, take, take_while_m_n},
+    character::complete::{alpha1, char, one_of, satisfy, space1},
+    combinator::{map_opt, map_res, not, opt},
     error::{Error, ErrorKind},
-    sequence::tuple,
+    sequence::{delimited, terminated, tuple},
     Err, IResult,
 };
 use std::{
@@ -11,7 +13,8 @@ use std::{
     str,
 };
 
-#[derive(Clone, Copy, Debug)]
+/// Calendar date. Fields are ordered so that derived `Ord` is chronological.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Date {
     pub year: u16,
     pub month: u8,
@@ -20,7 +23,7 @@ pub struct Date {
 
 impl fmt::Display for Date {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}-{}-{}", self.year, self.month, self.day)
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
     }
 }
 
@@ -42,9 +45,152 @@ where
     Ok((i, decimal_from_bytes(i, digits)?))
 }
 
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+/// `min..=max` digits not followed by another digit
+fn number<O: str::FromStr>(min: usize, max: usize, i: &str) -> IResult<&str, O> {
+    terminated(
+        map_res(
+            take_while_m_n(min, max, |c: char| c.is_ascii_digit()),
+            str::parse::<O>,
+        ),
+        not(satisfy(|c| c.is_ascii_digit())),
+    )(i)
+}
+
+fn year(i: &str) -> IResult<&str, u16> {
+    number(4, 4, i)
+}
+
+fn day_or_month(i: &str) -> IResult<&str, u8> {
+    number(1, 2, i)
+}
+
+/// Day with an optional ordinal suffix: `1`, `1st`, `22nd`
+fn ordinal_day(i: &str) -> IResult<&str, u8> {
+    let suffix = alt((
+        tag_no_case("st"),
+        tag_no_case("nd"),
+        tag_no_case("rd"),
+        tag_no_case("th"),
+    ));
+    terminated(day_or_month, opt(suffix))(i)
+}
+
+/// English name of a month or its prefix of at least 3 letters (`Jun`, `Sept`), case-insensitive
+fn month_name(i: &str) -> IResult<&str, u8> {
+    map_opt(alpha1, |word: &str| {
+        let word = word.to_lowercase();
+        MONTHS
+            .iter()
+            .position(|month| word.len() >= 3 && month.starts_with(&word))
+            .map(|n| n as u8 + 1)
+    })(i)
+}
+
+fn separator(i: &str) -> IResult<&str, char> {
+    one_of("-./")(i)
+}
+
+/// Dates of [`Date::parse_lenient`] without parentheses
+fn lenient(i: &str) -> IResult<&str, Date> {
+    alt((
+        // 2021-06-01, 2021.06.01, 2021/6/1
+        map_opt(
+            tuple((year, separator, day_or_month, separator, day_or_month)),
+            |(year, sep1, month, sep2, day)| {
+                if sep1 == sep2 {
+                    Date::new(year, month, day)
+                } else {
+                    None
+                }
+            },
+        ),
+        // 01-06-2021, 01.06.2021, 06/15/2021
+        map_opt(
+            tuple((day_or_month, separator, day_or_month, separator, year)),
+            |(a, sep1, b, sep2, year)| {
+                if sep1 == sep2 {
+                    Date::new(year, b, a).or_else(|| Date::new(year, a, b))
+                } else {
+                    None
+                }
+            },
+        ),
+        // June 1, 2021
+        map_opt(
+            tuple((
+                month_name,
+                space1,
+                ordinal_day,
+                opt(char(',')),
+                space1,
+                year,
+            )),
+            |(month, _, day, _, _, year)| Date::new(year, month, day),
+        ),
+        // 1 June 2021
+        map_opt(
+            tuple((
+                ordinal_day,
+                space1,
+                month_name,
+                opt(char(',')),
+                space1,
+                year,
+            )),
+            |(day, _, month, _, _, year)| Date::new(year, month, day),
+        ),
+    ))(i)
+}
+
 impl Date {
+    /// Creates a date, returning `None` if the month or the day is out of range
+    pub fn new(year: u16, month: u8, day: u8) -> Option<Self> {
+        let date = Date { year, month, day };
+        if date.is_valid() {
+            Some(date)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_leap_year(year: u16) -> bool {
+        (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+    }
+
+    pub fn days_in_month(year: u16, month: u8) -> u8 {
+        match month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if Self::is_leap_year(year) => 29,
+            2 => 28,
+            _ => 0,
+        }
+    }
+
+    /// `true` if the month and the day are in range (leap years are accounted)
+    pub fn is_valid(&self) -> bool {
+        (1..=12).contains(&self.month)
+            && (1..=Self::days_in_month(self.year, self.month)).contains(&self.day)
+    }
+
+    /// Parses `YYYY-MM-DD`, rejecting non-existing dates like `2023-02-31`
     pub fn parse(i: &str) -> IResult<&str, Date> {
-        map(
+        map_opt(
             tuple((
                 |i| decimal_n(4, i),
                 tag(b"-"),
@@ -52,7 +198,7 @@ impl Date {
                 tag(b"-"),
                 |i| decimal_n(2, i),
             )),
-            |(year, _, month, _, day)| Date { year, month, day },
+            |(year, _, month, _, day)| Date::new(year, month, day),
         )(i.as_bytes())
         .map(|(i, d)| (str::from_utf8(i).unwrap(), d))
         .map_err(|e| {
@@ -62,4 +208,153 @@ impl Date {
             })
         })
     }
+
+    /// Parses `YYYY-MM-DD` and common non-ISO formats, optionally in parentheses:
+    /// - `2021.06.01`, `2021/6/1`
+    /// - `01-06-2021`, `01.06.2021`: the day goes first, unless only the month first makes a
+    ///   valid date (`06/15/2021`)
+    /// - `June 1, 2021`, `Jun 1st 2021`, `1 June 2021`
+    pub fn parse_lenient(i: &str) -> IResult<&str, Date> {
+        alt((delimited(char('('), lenient, char(')')), lenient))(i)
+    }
+}
+
+/// Error of `Date` -> `NaiveDate` conversion: the date doesn't exist (see [`Date::is_valid`])
+#[cfg(feature = "chrono")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct InvalidDate(pub Date);
+
+#[cfg(feature = "chrono")]
+impl std::fmt::Display for InvalidDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} isn't a valid date", self.0)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl std::error::Error for InvalidDate {}
+
+#[cfg(feature = "chrono")]
+impl std::convert::TryFrom<Date> for chrono::NaiveDate {
+    type Error = InvalidDate;
+
+    fn try_from(date: Date) -> Result<Self, Self::Error> {
+        chrono::NaiveDate::from_ymd_opt(date.year.into(), date.month.into(), date.day.into())
+            .ok_or(InvalidDate(date))
+    }
+}
+
+/// Error of `NaiveDate` -> `Date` conversion: the year doesn't fit into `0..=9999`
+#[cfg(feature = "chrono")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct YearOutOfRange(pub i32);
+
+#[cfg(feature = "chrono")]
+impl std::fmt::Display for YearOutOfRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "year {} doesn't fit into 0..=9999", self.0)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl std::error::Error for YearOutOfRange {}
+
+#[cfg(feature = "chrono")]
+impl std::convert::TryFrom<chrono::NaiveDate> for Date {
+    type Error = YearOutOfRange;
+
+    fn try_from(date: chrono::NaiveDate) -> Result<Self, Self::Error> {
+        use chrono::Datelike;
+        use std::convert::TryInto;
+
+        match date.year() {
+            year @ 0..=9999 => Ok(Date {
+                year: year.try_into().expect("checked above"),
+                month: date.month().try_into().expect("months are in 1..=12"),
+                day: date.day().try_into().expect("days are in 1..=31"),
+            }),
+            year => Err(YearOutOfRange(year)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Date::parse("2020-02-29"),
+            Ok(("", Date::new(2020, 2, 29).unwrap()))
+        );
+        assert!(Date::parse("2023-02-29").is_err());
+        assert!(Date::parse("2023-02-31").is_err());
+        assert!(Date::parse("2023-13-01").is_err());
+        assert!(Date::parse("2023-00-01").is_err());
+        assert!(Date::parse("1900-02-29").is_err());
+        assert!(Date::parse("2000-02-29").is_ok());
+    }
+
+    #[test]
+    fn parse_lenient() {
+        let parsed = |s| Date::parse_lenient(s).map(|(rest, date)| (rest, date.to_string()));
+        let june = |rest| Ok((rest, String::from("2021-06-01")));
+        for s in &[
+            "2021-06-01",
+            "2021.06.01",
+            "2021/6/1",
+            "01-06-2021",
+            "01.06.2021",
+            "June 1, 2021",
+            "june 1st 2021",
+            "Jun 1, 2021",
+            "1 June 2021",
+            "(2021-06-01)",
+            "(June 1, 2021)",
+        ] {
+            assert_eq!(parsed(s), june(""), "{}", s);
+        }
+        assert_eq!(parsed("06/15/2021"), Ok(("", String::from("2021-06-15"))));
+        assert_eq!(parsed("2021.06.01 [YANKED]"), june(" [YANKED]"));
+
+        assert!(Date::parse_lenient("2021-06.01").is_err());
+        assert!(Date::parse_lenient("2021-06-011").is_err());
+        assert!(Date::parse_lenient("31.02.2021").is_err());
+        assert!(Date::parse_lenient("Ju 1, 2021").is_err());
+        assert!(Date::parse_lenient("(2021-06-01").is_err());
+    }
+
+    #[test]
+    fn ord() {
+        let date = |y, m, d| Date::new(y, m, d).unwrap();
+        assert!(date(2020, 1, 31) < date(2020, 2, 1));
+        assert!(date(2019, 12, 31) < date(2020, 1, 1));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn chrono() {
+        use std::convert::TryFrom;
+
+        let date = Date::new(2021, 6, 1).unwrap();
+        let naive = chrono::NaiveDate::try_from(date).unwrap();
+        assert_eq!(naive, chrono::NaiveDate::from_ymd_opt(2021, 6, 1).unwrap());
+        let invalid = Date {
+            year: 2023,
+            month: 2,
+            day: 31,
+        };
+        assert_eq!(
+            chrono::NaiveDate::try_from(invalid),
+            Err(InvalidDate(invalid))
+        );
+        assert_eq!(Date::try_from(naive), Ok(date));
+        assert_eq!(
+            Date::try_from(chrono::NaiveDate::from_ymd_opt(10000, 1, 1).unwrap()),
+            Err(YearOutOfRange(10000))
+        );
+    }
 }
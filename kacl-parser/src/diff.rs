@@ -0,0 +1,158 @@
+use crate::{ChangelogBuilder, Release, Section, Version};
+
+/// Difference between two revisions of a changelog, see [`diff`]
+#[derive(Debug, Clone)]
+pub struct ChangelogDelta {
+    /// Releases which aren't in the old changelog
+    pub new_releases: Vec<Release>,
+    /// Entries of `new_releases` which were already listed in the old `Unreleased` section
+    pub moved_from_unreleased: Vec<Section>,
+    /// Releases present in both changelogs, with only the entries added in the new one
+    pub edited_releases: Vec<Release>,
+}
+
+impl ChangelogDelta {
+    pub fn is_empty(&self) -> bool {
+        self.new_releases.is_empty() && self.edited_releases.is_empty()
+    }
+}
+
+fn same_version(a: &Version, b: &Version) -> bool {
+    match (a, b) {
+        (Version::Unreleased, Version::Unreleased) => true,
+        (Version::Released(a, ..), Version::Released(b, ..)) => a == b,
+        (Version::Other(a, ..), Version::Other(b, ..)) => a == b,
+        _ => false,
+    }
+}
+
+fn contains_entry(release: &Release, entry: &str) -> bool {
+    release
+        .sections
+        .iter()
+        .any(|s| s.entries.iter().any(|e| e == entry))
+}
+
+/// Keeps only entries of `release` for which `keep` returns `true`, dropping empty sections
+fn filter_entries(release: &Release, mut keep: impl FnMut(&str) -> bool) -> Vec<Section> {
+    release
+        .sections
+        .iter()
+        .map(|section| Section {
+            name: section.name.clone(),
+            entries: section
+                .entries
+                .iter()
+                .filter(|e| keep(e))
+                .cloned()
+                .collect(),
+            blocks: Vec::new(),
+        })
+        .filter(|section| !section.entries.is_empty())
+        .collect()
+}
+
+/// Compares two revisions of a changelog (e.g. before and after a release).
+///
+/// Entries are compared by their text, so reworded entries are reported as new.
+pub fn diff(old: &ChangelogBuilder, new: &ChangelogBuilder) -> ChangelogDelta {
+    let old_unreleased = old
+        .releases()
+        .iter()
+        .find(|r| matches!(r.version, Version::Unreleased));
+
+    let mut delta = ChangelogDelta {
+        new_releases: Vec::new(),
+        moved_from_unreleased: Vec::new(),
+        edited_releases: Vec::new(),
+    };
+
+    for release in new.releases() {
+        let old_release = old
+            .releases()
+            .iter()
+            .find(|r| same_version(&r.version, &release.version));
+
+        match old_release {
+            None => {
+                if let Some(unreleased) = old_unreleased {
+                    delta
+                        .moved_from_unreleased
+                        .extend(filter_entries(release, |e| contains_entry(unreleased, e)));
+                }
+                delta.new_releases.push(release.clone());
+            }
+            Some(old_release) => {
+                let sections = filter_entries(release, |e| !contains_entry(old_release, e));
+                if !sections.is_empty() {
+                    delta.edited_releases.push(Release {
+                        sections,
+                        raw: None,
+                        span: None,
+                        ..release.clone()
+                    });
+                }
+            }
+        }
+    }
+
+    delta
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn release() {
+        let old = ChangelogBuilder::from_markdown(
+            "## [Unreleased]\n\
+             \n\
+             ### Added\n\
+             \n\
+             - Feature\n\
+             \n\
+             ## [1.0.0] - 2020-01-01\n\
+             \n\
+             ### Fixed\n\
+             \n\
+             - Bug\n",
+        );
+        let new = ChangelogBuilder::from_markdown(
+            "## [Unreleased]\n\
+             \n\
+             ## [1.1.0] - 2020-02-01\n\
+             \n\
+             ### Added\n\
+             \n\
+             - Feature\n\
+             - Another feature\n\
+             \n\
+             ## [1.0.0] - 2020-01-01\n\
+             \n\
+             ### Fixed\n\
+             \n\
+             - Bug\n\
+             - Forgotten bug\n",
+        );
+
+        let delta = diff(&old, &new);
+        assert_eq!(delta.new_releases.len(), 1);
+        assert_eq!(delta.new_releases[0].version.label(), "1.1.0");
+        assert_eq!(
+            delta.moved_from_unreleased,
+            [Section::new("Added").entry("Feature")]
+        );
+        assert_eq!(delta.edited_releases.len(), 1);
+        assert_eq!(
+            delta.edited_releases[0].sections,
+            [Section::new("Fixed").entry("Forgotten bug")]
+        );
+    }
+
+    #[test]
+    fn same() {
+        let changelog = ChangelogBuilder::from_markdown(include_str!("../../CHANGELOG.md"));
+        assert!(diff(&changelog, &changelog).is_empty());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::{IssueRef, Release, Section};
+
+/// Scopes longer than this are more likely emphasized text than a component name
+const MAX_SCOPE_LEN: usize = 40;
+
+/// List entry of a section with its leading scope: `**net:** fix …`, `**net**: fix …`,
+/// `` `io`: fix …`` or `` `io:` fix …``
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Component the entry is about, e.g. `net` (conventional commits' scope)
+    pub scope: Option<String>,
+    /// Markdown of the entry without the scope
+    pub text: String,
+    /// References in the entry, see [`IssueRef::parse_all`]
+    pub refs: Vec<IssueRef>,
+}
+
+/// `scope` and the rest of `s` if `s` starts with `<delim>scope:<delim>` or `<delim>scope<delim>:`
+fn delimited_scope<'s>(s: &'s str, delim: &str) -> Option<(&'s str, &'s str)> {
+    let inner = s.strip_prefix(delim)?;
+    let end = inner.find(delim)?;
+    let (scope, rest) = (&inner[..end], &inner[end + delim.len()..]);
+    let (scope, rest) = match scope.strip_suffix(':') {
+        Some(scope) => (scope, rest),
+        None => (scope, rest.strip_prefix(':')?),
+    };
+    let scope = scope.trim();
+    let valid = !scope.is_empty()
+        && scope.len() <= MAX_SCOPE_LEN
+        && !scope.contains(|c: char| c == '\n' || c == '*' || c == '`');
+    if valid && (rest.is_empty() || rest.starts_with(char::is_whitespace)) {
+        Some((scope, rest))
+    } else {
+        None
+    }
+}
+
+impl Entry {
+    /// Splits the leading scope off the entry's markdown (as stored in [`Section::entries`])
+    pub fn parse(markdown: &str) -> Self {
+        let (scope, text) = match delimited_scope(markdown, "**")
+            .or_else(|| delimited_scope(markdown, "__"))
+            .or_else(|| delimited_scope(markdown, "`"))
+        {
+            Some((scope, text)) => (Some(scope.to_owned()), text.trim_start()),
+            None => (None, markdown),
+        };
+        Entry {
+            scope,
+            text: text.to_owned(),
+            refs: IssueRef::parse_all(text),
+        }
+    }
+
+    /// Whether the entry has the scope, compared case-insensitively
+    pub fn in_scope(&self, scope: &str) -> bool {
+        self.scope
+            .as_deref()
+            .map_or(false, |s| s.eq_ignore_ascii_case(scope))
+    }
+}
+
+/// What an entry is about, see [`EntryKind::classify`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum EntryKind {
+    Breaking,
+    Security,
+    Feature,
+    Fix,
+    Other,
+}
+
+/// `feat!: …` or `fix(parser)!: …` of conventional commits
+fn is_breaking_commit(text: &str) -> bool {
+    text.find(':')
+        .map(|end| &text[..end])
+        .map_or(false, |kind| kind.ends_with('!') && !kind.contains(' '))
+}
+
+impl EntryKind {
+    /// Classifies the markdown of an entry in the section named `section`. Security fixes
+    /// (the `Security` section, `CVE-…` and `RUSTSEC-…` ids) come first, then breaking changes
+    /// (`Removed` and `BREAKING CHANGES` sections, `BREAKING` or `feat!:` in the entry), then
+    /// features and fixes by their sections (`Added`/`Features`, `Fixed`/`Bug Fixes`) or by
+    /// the first word of the entry.
+    pub fn classify(section: &str, entry: &str) -> Self {
+        let section = section.trim().to_lowercase();
+        let ids = entry.to_lowercase();
+        if section == "security" || ids.contains("cve-") || ids.contains("rustsec-") {
+            return EntryKind::Security;
+        }
+
+        // the marker may be taken for a scope, e.g. `**BREAKING:** …`
+        let lower = Entry::parse(entry).text.to_lowercase();
+        if section == "removed"
+            || section.contains("breaking")
+            || entry.contains("BREAKING")
+            || is_breaking_commit(&lower)
+        {
+            return EntryKind::Breaking;
+        }
+
+        match section.as_str() {
+            "added" | "features" | "new features" => return EntryKind::Feature,
+            "fixed" | "fixes" | "bug fixes" | "bugfixes" => return EntryKind::Fix,
+            _ => {}
+        }
+        let first_word = lower
+            .trim_start_matches(|c: char| !c.is_alphanumeric())
+            .split(|c: char| !c.is_alphanumeric())
+            .next()
+            .unwrap_or("");
+        match first_word {
+            "fix" | "fixed" | "fixes" => EntryKind::Fix,
+            "add" | "added" | "adds" | "feat" | "new" => EntryKind::Feature,
+            _ => EntryKind::Other,
+        }
+    }
+}
+
+impl Section {
+    /// Entries with their scopes and references
+    pub fn parsed_entries(&self) -> impl Iterator<Item = Entry> + '_ {
+        self.entries.iter().map(|entry| Entry::parse(entry))
+    }
+}
+
+impl Release {
+    /// Scopes of all entries in order of appearance, without duplicates
+    pub fn scopes(&self) -> Vec<String> {
+        let mut scopes: Vec<String> = Vec::new();
+        for entry in self.sections.iter().flat_map(Section::parsed_entries) {
+            if let Some(scope) = entry.scope {
+                if !scopes.iter().any(|s| s.eq_ignore_ascii_case(&scope)) {
+                    scopes.push(scope);
+                }
+            }
+        }
+        scopes
+    }
+
+    /// Copy of the release with only entries of the scope, sections left empty are dropped.
+    /// The copy has no `raw` markdown, it would have all entries.
+    pub fn only_scope(&self, scope: &str) -> Release {
+        let mut release = self.clone();
+        release.raw = None;
+        release.span = None;
+        for section in &mut release.sections {
+            section
+                .entries
+                .retain(|entry| Entry::parse(entry).in_scope(scope));
+        }
+        release.sections.retain(|s| !s.entries.is_empty());
+        release
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChangelogBuilder;
+
+    #[test]
+    fn scopes() {
+        let parse = |s| Entry::parse(s).scope;
+        assert_eq!(parse("**net:** fix a leak"), Some(String::from("net")));
+        assert_eq!(parse("**net**: fix a leak"), Some(String::from("net")));
+        assert_eq!(parse("`io`: faster reads"), Some(String::from("io")));
+        assert_eq!(parse("`io:` faster reads"), Some(String::from("io")));
+        // not scopes
+        assert_eq!(parse("**Breaking** change"), None);
+        assert_eq!(parse("`Vec::new` is const"), None);
+        assert_eq!(parse("**a**:b"), None);
+        assert_eq!(parse("fix: typo"), None);
+    }
+
+    #[test]
+    fn text_and_refs() {
+        let entry = Entry::parse("**parser:** accept tabs (#12)");
+        assert_eq!(entry.text, "accept tabs (#12)");
+        assert_eq!(entry.refs, [IssueRef::new(12)]);
+        assert!(entry.in_scope("Parser"));
+    }
+
+    #[test]
+    fn kinds() {
+        let kind = EntryKind::classify;
+        assert_eq!(kind("Security", "Bump openssl"), EntryKind::Security);
+        assert_eq!(
+            kind("Fixed", "Overflow (RUSTSEC-2021-0001)"),
+            EntryKind::Security
+        );
+        assert_eq!(kind("Removed", "`Foo::bar`"), EntryKind::Breaking);
+        assert_eq!(
+            kind("Changed", "**BREAKING:** new api"),
+            EntryKind::Breaking
+        );
+        assert_eq!(kind("", "feat(parser)!: drop tabs"), EntryKind::Breaking);
+        assert_eq!(kind("Added", "ipv6"), EntryKind::Feature);
+        assert_eq!(kind("Bug Fixes", "short reads"), EntryKind::Fix);
+        assert_eq!(kind("", "**net:** fixed a leak"), EntryKind::Fix);
+        assert_eq!(kind("", "Add `Vec::new`"), EntryKind::Feature);
+        assert_eq!(kind("Changed", "Faster reads"), EntryKind::Other);
+        assert_eq!(kind("Documentation", "fix: typo"), EntryKind::Fix);
+    }
+
+    #[test]
+    fn filtering() {
+        let changelog = ChangelogBuilder::from_markdown(
+            "## [1.0.0]\n\n### Added\n\n- **net:** ipv6\n- `io`: uring\n\n\
+             ### Fixed\n\n- **IO:** short reads\n- typo\n",
+        );
+        let release = &changelog.releases()[0];
+        assert_eq!(release.scopes(), ["net", "io"]);
+
+        let io = release.only_scope("io");
+        let entries: Vec<(&str, &[String])> = io
+            .sections
+            .iter()
+            .map(|s| (s.name.as_str(), s.entries.as_slice()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("Added", &[String::from("`io`: uring")][..]),
+                ("Fixed", &[String::from("**IO:** short reads")][..]),
+            ]
+        );
+        assert!(release.only_scope("fs").sections.is_empty());
+    }
+}
@@ -1,38 +1,209 @@
 //! KACL stands for for [keepachangelog](https://keepachangelog.com/en/1.0.0/)
-use comrak::nodes::AstNode;
+pub use builder::{ChangelogBuilder, Release, Section, SECTIONS};
+use comrak::nodes::{AstNode, NodeHeading, NodeValue};
+pub use conventional::Format;
 pub use date::Date;
-use std::convert::TryFrom;
+#[cfg(feature = "chrono")]
+pub use date::{InvalidDate, YearOutOfRange};
+pub use diff::{diff, ChangelogDelta};
+pub use entry::{Entry, EntryKind};
+pub use limits::Limits;
+pub use links::{generate_compare_links, normalize_label, reference_definitions};
+pub use lint::{validate, Lint};
+pub use merge::{MemberRelease, MergedChangelog};
+pub use options::{DateFormat, DateStyle, ParseOptions};
+pub use refs::IssueRef;
+pub use scheme::VersionScheme;
+use std::fmt;
+pub use stream::ReleaseStream;
+pub use strict::{parse_strict, ParseError};
 pub use version::{Version, VersionParseError};
+use versions::SemVer;
 
+mod builder;
+mod conventional;
 mod date;
+mod diff;
+mod entry;
+mod limits;
+mod links;
+mod lint;
+mod merge;
+mod options;
+mod refs;
+pub mod render;
+mod scheme;
+mod stream;
+mod strict;
 mod version;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 const IO_VEC_ERR: &str = "IO errors shouldn't be possible when writing to Vec";
 
-#[derive(Debug, Clone)]
-pub struct Changelog<I>(Option<(Version, I)>);
+/// Renders node back to markdown
+fn to_commonmark<'a>(node: &'a AstNode<'a>) -> String {
+    let mut s = Vec::new();
+    comrak::format_commonmark(node, &comrak::ComrakOptions::default(), &mut s).expect(IO_VEC_ERR);
+    String::from_utf8(s).expect("comrak produces valid utf-8")
+}
+
+/// Level-2 heading which looks like a version heading, but couldn't be parsed
+#[derive(Debug)]
+pub struct Warning {
+    /// 1-based line number of the heading
+    pub line: u32,
+    pub error: VersionParseError,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "malformed version heading on line {}: {}",
+            self.line, self.error
+        )
+    }
+}
+
+impl Warning {
+    /// Byte offset of the heading in the source
+    pub fn offset(&self, src: &str) -> usize {
+        src.split_inclusive('\n')
+            .take(self.line.saturating_sub(1) as usize)
+            .map(str::len)
+            .sum()
+    }
+}
+
+#[derive(Debug)]
+pub struct Changelog<I> {
+    /// The next release and the line of its heading
+    state: Option<(Version, u32, I)>,
+    /// 1-based line of the heading of the release yielded last
+    line: u32,
+    options: ParseOptions,
+    warnings: Vec<Warning>,
+    /// Markdown of the blocks before the first release, but the title
+    preamble: String,
+}
+
+/// Parses version heading, records a warning if the block is a level-2 heading
+/// which looks like a version, but can't be parsed
+fn parse_version<'a>(
+    block: &'a AstNode<'a>,
+    options: &ParseOptions,
+    warnings: &mut Vec<Warning>,
+) -> Option<Version> {
+    match Version::parse_heading(block, options) {
+        Ok(version) => Some(version),
+        Err(VersionParseError::Header) => None,
+        Err(error) => {
+            let text = to_commonmark(block).to_lowercase();
+            if text.contains(|c: char| c.is_ascii_digit()) || text.contains("unreleased") {
+                warnings.push(Warning {
+                    line: block.data.borrow().start_line,
+                    error,
+                });
+            }
+            None
+        }
+    }
+}
 
 impl<'a, I: Iterator<Item = &'a AstNode<'a>>> Changelog<I> {
     /// Parses top-level AST node until `Version` parser succeeds,
-    /// ignoring all other problems (e.g. not `# Changelog` as first header)
-    pub fn new(mut blocks: I) -> Self {
+    /// ignoring all other problems (e.g. not `# Changelog` as first header).
+    ///
+    /// Malformed version headings are recorded, see [`Changelog::warnings`].
+    pub fn new(blocks: I) -> Self {
+        Self::with_options(blocks, ParseOptions::default())
+    }
+
+    /// Like [`Changelog::new`], but accepts version headings allowed by `options`
+    pub fn with_options(mut blocks: I, options: ParseOptions) -> Self {
+        let mut warnings = Vec::new();
+        let mut preamble = Vec::new();
         loop {
             let block = match blocks.next() {
                 Some(block) => block,
-                None => return Changelog(None),
+                None => {
+                    return Changelog {
+                        state: None,
+                        line: 0,
+                        options,
+                        warnings,
+                        preamble: preamble.join("\n"),
+                    }
+                }
             };
-            if let Ok(version) = Version::try_from(block) {
-                return Changelog(Some((version, blocks)));
+            if let Some(version) = parse_version(block, &options, &mut warnings) {
+                let line = block.data.borrow().start_line;
+                return Changelog {
+                    state: Some((version, line, blocks)),
+                    line: 0,
+                    options,
+                    warnings,
+                    preamble: preamble.join("\n"),
+                };
+            }
+            // `# Changelog`
+            let title = matches!(
+                block.data.borrow().value,
+                NodeValue::Heading(NodeHeading { level: 1, .. })
+            );
+            if !(title && preamble.is_empty()) {
+                preamble.push(to_commonmark(block));
             }
         }
     }
+
+    /// Parses all releases, failing if there are malformed version headings
+    #[allow(clippy::type_complexity)]
+    pub fn new_strict(blocks: I) -> Result<Vec<(Version, Vec<&'a AstNode<'a>>)>, Vec<Warning>> {
+        let mut changelog = Self::new(blocks);
+        let releases: Vec<_> = changelog.by_ref().collect();
+        if changelog.warnings.is_empty() {
+            Ok(releases)
+        } else {
+            Err(changelog.warnings)
+        }
+    }
+
+    /// Markdown between the title (`# Changelog`) and the first release
+    pub fn preamble(&self) -> &str {
+        &self.preamble
+    }
+
+    /// Malformed version headings met so far. Headings which failed to parse
+    /// are treated as contents of the previous release.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Finds release with the given version
+    pub fn find_release(mut self, version: &SemVer) -> Option<(Version, Vec<&'a AstNode<'a>>)> {
+        self.find(|(v, _)| v.semver() == Some(version))
+    }
+
+    /// Returns all releases strictly after `from` up to and including `to`
+    /// (e.g. everything published between two index polls)
+    pub fn releases_between(
+        self,
+        from: &SemVer,
+        to: &SemVer,
+    ) -> impl Iterator<Item = (Version, Vec<&'a AstNode<'a>>)> {
+        let (from, to) = (from.clone(), to.clone());
+        self.filter(move |(v, _)| v.is_between(&from, &to))
+    }
 }
 
 impl<'a, I: Iterator<Item = &'a AstNode<'a>>> Iterator for Changelog<I> {
     type Item = (Version, Vec<&'a AstNode<'a>>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let (version, mut blocks) = self.0.take()?;
+        let (version, line, mut blocks) = self.state.take()?;
+        self.line = line;
 
         let mut contents = Vec::new();
 
@@ -41,8 +212,9 @@ impl<'a, I: Iterator<Item = &'a AstNode<'a>>> Iterator for Changelog<I> {
                 Some(block) => block,
                 None => return Some((version, contents)),
             };
-            if let Ok(new_version) = Version::try_from(block) {
-                self.0 = Some((new_version, blocks));
+            if let Some(new_version) = parse_version(block, &self.options, &mut self.warnings) {
+                let line = block.data.borrow().start_line;
+                self.state = Some((new_version, line, blocks));
                 return Some((version, contents));
             }
             contents.push(block);
@@ -100,4 +272,398 @@ mod tests {
 
         println!("{}", String::from_utf8(s).unwrap());
     }
+
+    #[test]
+    fn find_release() {
+        let src = include_str!("../../CHANGELOG.md");
+        let arena = comrak::Arena::new();
+        let doc = comrak::parse_document(&arena, src, &comrak::ComrakOptions::default());
+
+        let (v, _) = Changelog::new(doc.children())
+            .find_release(&SemVer::new("0.1.2").unwrap())
+            .unwrap();
+        assert_eq!(v.semver(), SemVer::new("0.1.2").as_ref());
+
+        assert!(Changelog::new(doc.children())
+            .find_release(&SemVer::new("100.0.0").unwrap())
+            .is_none());
+    }
+
+    #[test]
+    fn releases_between() {
+        let src = include_str!("../../CHANGELOG.md");
+        let arena = comrak::Arena::new();
+        let doc = comrak::parse_document(&arena, src, &comrak::ComrakOptions::default());
+
+        let versions: Vec<_> = Changelog::new(doc.children())
+            .releases_between(
+                &SemVer::new("0.1.1").unwrap(),
+                &SemVer::new("0.1.3").unwrap(),
+            )
+            .map(|(v, _)| v.semver().unwrap().to_string())
+            .collect();
+        assert_eq!(versions, ["0.1.3", "0.1.2"]);
+    }
+
+    #[test]
+    fn yanked() {
+        let src =
+            "## [1.2.3] - 2020-01-01 [YANKED]\n\n## 1.2.2 - 2019-12-31\n\n## [1.2.1] [yanked]\n";
+        let arena = comrak::Arena::new();
+        let doc = comrak::parse_document(&arena, src, &comrak::ComrakOptions::default());
+
+        let yanked: Vec<_> = Changelog::new(doc.children())
+            .map(|(v, _)| v.is_yanked())
+            .collect();
+        assert_eq!(yanked, [true, false, true]);
+
+        let emitted = ChangelogBuilder::from_changelog(Changelog::new(doc.children()))
+            .preamble("")
+            .build();
+        assert!(emitted.contains("## [1.2.3] - 2020-01-01 [YANKED]\n"));
+        assert!(emitted.contains("## [1.2.1] [YANKED]\n"));
+    }
+
+    #[test]
+    fn reference_links() {
+        let src = "## [Unreleased]\n\
+                   \n\
+                   ## [1.2.0] - 2020-01-01\n\
+                   \n\
+                   ## 1.1.0\n\
+                   \n\
+                   [Unreleased]: https://github.com/x/y/compare/v1.2.0...HEAD\n\
+                   [1.2.0]: https://github.com/x/y/compare/v1.1.0...v1.2.0\n";
+
+        let changelog = ChangelogBuilder::from_markdown(src);
+        let links: Vec<_> = changelog
+            .releases()
+            .iter()
+            .map(|r| r.link.as_deref())
+            .collect();
+        assert_eq!(
+            links,
+            [
+                Some("https://github.com/x/y/compare/v1.2.0...HEAD"),
+                Some("https://github.com/x/y/compare/v1.1.0...v1.2.0"),
+                None
+            ]
+        );
+
+        let emitted = changelog.build();
+        assert!(emitted.ends_with(
+            "\n[Unreleased]: https://github.com/x/y/compare/v1.2.0...HEAD\n\
+             [1.2.0]: https://github.com/x/y/compare/v1.1.0...v1.2.0\n"
+        ));
+    }
+
+    #[test]
+    fn round_trip_keeps_blocks_and_preamble() {
+        let src = "# Changelog\n\
+                   \n\
+                   All notable changes to this project will be documented in this file.\n\
+                   \n\
+                   ## [1.1.0] - 2020-02-01\n\
+                   \n\
+                   - unsorted entry\n\
+                   \n\
+                   ### Fixed\n\
+                   \n\
+                   The fix needs a migration:\n\
+                   \n\
+                   ```sh\n\
+                   migrate --all\n\
+                   ```\n\
+                   \n\
+                   - crash on start\n\
+                   \n\
+                   ### Added\n\
+                   \n\
+                   - `--all` flag\n\
+                   \n\
+                   ## 1.0.0 - 2020-01-01\n\
+                   \n\
+                   First release.\n\
+                   \n\
+                   [1.1.0]: https://github.com/x/y/compare/v1.0.0...v1.1.0\n";
+
+        let parsed = ChangelogBuilder::from_markdown(src);
+        let built = parsed.build();
+        assert!(built.contains("All notable changes"));
+        assert!(built.contains("First release."));
+        assert!(built.find("- unsorted entry") < built.find("### Added"));
+
+        let reparsed = ChangelogBuilder::from_markdown(&built);
+        assert_eq!(reparsed.releases().len(), parsed.releases().len());
+        for (a, b) in parsed.releases().iter().zip(reparsed.releases()) {
+            assert_eq!(a.version.label(), b.version.label());
+            assert_eq!(a.version.date(), b.version.date());
+            assert_eq!(a.link, b.link);
+            let mut sections = a.sections.clone();
+            sections.sort_by_key(|s| s.order());
+            assert_eq!(sections, b.sections);
+        }
+        assert_eq!(reparsed.build(), built);
+    }
+
+    #[test]
+    fn warnings() {
+        let src = "# Changelog\n\
+                   \n\
+                   ## [1.0.0]\n\
+                   \n\
+                   ## Notes\n\
+                   \n\
+                   ## [0.9] whatever\n\
+                   \n\
+                   ## 0.8.0\n";
+        let arena = comrak::Arena::new();
+        let doc = comrak::parse_document(&arena, src, &comrak::ComrakOptions::default());
+
+        let mut changelog = Changelog::new(doc.children());
+        assert_eq!(changelog.by_ref().count(), 2);
+        let lines: Vec<_> = changelog.warnings().iter().map(|w| w.line).collect();
+        assert_eq!(lines, [7]);
+        assert_eq!(
+            changelog.warnings()[0].offset(src),
+            src.find("## [0.9]").unwrap()
+        );
+
+        assert!(Changelog::new_strict(doc.children()).is_err());
+    }
+
+    #[test]
+    fn tolerant() {
+        let src = "# v1.2.3 (2021-06-01)\n\
+                   \n\
+                   ## 1.2.2 / 2021-05-01\n\
+                   \n\
+                   ## [v1.2.1] - 2021-04-01\n";
+        let arena = comrak::Arena::new();
+        let doc = comrak::parse_document(&arena, src, &comrak::ComrakOptions::default());
+
+        // only `1.2.2`, the rest of a heading after the version and the date is ignored
+        let strict: Vec<_> = Changelog::new(doc.children())
+            .map(|(v, _)| v.into_released().unwrap())
+            .collect();
+        assert_eq!(strict, [(SemVer::new("1.2.2").unwrap(), None)]);
+
+        let dates: Vec<_> = Changelog::with_options(doc.children(), ParseOptions::tolerant())
+            .map(|(v, _)| v.into_released().unwrap().1.unwrap().to_string())
+            .collect();
+        assert_eq!(dates, ["2021-06-01", "2021-05-01", "2021-04-01"]);
+    }
+
+    #[test]
+    fn builder() {
+        let changelog = ChangelogBuilder::new()
+            .preamble("")
+            .release(Release::new(Version::Unreleased))
+            .release(
+                Release::new(Version::Released(
+                    versions::SemVer::new("0.1.0").unwrap(),
+                    Some(Date {
+                        year: 2020,
+                        month: 7,
+                        day: 1,
+                    }),
+                    false,
+                ))
+                .section(Section::new("Fixed").entry("Something"))
+                .section(Section::new("Added").entry("Everything").entry("Nothing")),
+            )
+            .build();
+
+        assert_eq!(
+            changelog,
+            "# Changelog\n\
+             \n\
+             ## [Unreleased]\n\
+             \n\
+             ## [0.1.0] - 2020-07-01\n\
+             \n\
+             ### Added\n\
+             \n\
+             - Everything\n\
+             - Nothing\n\
+             \n\
+             ### Fixed\n\
+             \n\
+             - Something\n"
+        );
+    }
+
+    #[test]
+    fn release_unreleased() {
+        let mut changelog = ChangelogBuilder::from_markdown(
+            "## [Unreleased]\n\
+             \n\
+             ### Added\n\
+             \n\
+             - Promotion\n\
+             \n\
+             ### Fixed\n\
+             \n\
+             ## [1.1.0] - 2021-01-01\n\
+             \n\
+             [Unreleased]: https://github.com/x/y/compare/v1.1.0...HEAD\n\
+             [1.1.0]: https://github.com/x/y/compare/v1.0.0...v1.1.0\n",
+        );
+        changelog.release_unreleased(
+            SemVer::new("1.2.0").unwrap(),
+            Date {
+                year: 2021,
+                month: 2,
+                day: 1,
+            },
+        );
+
+        assert_eq!(
+            changelog.preamble("").build(),
+            "# Changelog\n\
+             \n\
+             ## [Unreleased]\n\
+             \n\
+             ### Added\n\
+             \n\
+             ### Changed\n\
+             \n\
+             ### Deprecated\n\
+             \n\
+             ### Removed\n\
+             \n\
+             ### Fixed\n\
+             \n\
+             ### Security\n\
+             \n\
+             ## [1.2.0] - 2021-02-01\n\
+             \n\
+             ### Added\n\
+             \n\
+             - Promotion\n\
+             \n\
+             ## [1.1.0] - 2021-01-01\n\
+             \n\
+             [Unreleased]: https://github.com/x/y/compare/v1.2.0...HEAD\n\
+             [1.2.0]: https://github.com/x/y/compare/v1.1.0...v1.2.0\n\
+             [1.1.0]: https://github.com/x/y/compare/v1.0.0...v1.1.0\n"
+        );
+    }
+
+    #[test]
+    fn breaking_entries() {
+        let release = Release::new(Version::Unreleased)
+            .section(
+                Section::new("Changed")
+                    .entry("**BREAKING:** renamed `foo` to `bar`")
+                    .entry("Faster parsing")
+                    .entry("⚠ `Config` is no longer `Clone`")
+                    .entry("Non-breaking refactor of the parser")
+                    .entry("A non-breaking change to `Limits`")
+                    .entry("Breaking change: `parse` takes options"),
+            )
+            .section(Section::new("Breaking changes").entry("Removed `baz`"));
+
+        assert_eq!(
+            release.breaking_entries().collect::<Vec<_>>(),
+            [
+                "**BREAKING:** renamed `foo` to `bar`",
+                "⚠ `Config` is no longer `Clone`",
+                "Breaking change: `parse` takes options",
+                "Removed `baz`"
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let src = include_str!("../../CHANGELOG.md");
+        let arena = comrak::Arena::new();
+        let builder = ChangelogBuilder::from_changelog(Changelog::new(
+            comrak::parse_document(&arena, src, &comrak::ComrakOptions::default()).children(),
+        ));
+        let emitted = builder.build();
+
+        let arena = comrak::Arena::new();
+        let reparsed = ChangelogBuilder::from_changelog(Changelog::new(
+            comrak::parse_document(&arena, &emitted, &comrak::ComrakOptions::default()).children(),
+        ));
+
+        assert_eq!(reparsed.build(), emitted);
+        assert_eq!(reparsed.releases().len(), builder.releases().len());
+    }
+
+    #[test]
+    fn other_version_schemes() {
+        let src =
+            "## [2021.06] - 2021-06-01\n### Added\n- Dates\n\n## 2021-05-20\n### Fixed\n- Bugs\n";
+        let options = ParseOptions {
+            version_scheme: VersionScheme::Lenient,
+            ..ParseOptions::tolerant()
+        };
+        let builder = ChangelogBuilder::from_markdown_with(src, options);
+        let release = builder
+            .find_release_in(VersionScheme::Lenient, "2021.6")
+            .unwrap();
+        assert_eq!(release.version.label(), "2021.06");
+        assert_eq!(release.version.date().unwrap().to_string(), "2021-06-01");
+        assert_eq!(release.sections[0].entries, ["Dates"]);
+        let older = builder.releases().iter().filter(|r| {
+            r.version
+                .is_between_in(VersionScheme::Lenient, "2021.1.1", "2021.6")
+        });
+        assert_eq!(older.count(), 2);
+
+        // semver only by default
+        let semver = ChangelogBuilder::from_markdown_with(src, ParseOptions::tolerant());
+        assert!(semver.releases().is_empty());
+    }
+
+    #[test]
+    fn lenient_dates() {
+        let src = "## 1.2.0 - June 1, 2021\n### Added\n- Dates\n\n## v1.1.0 (20.05.2021)\n### Fixed\n- Bugs\n";
+        let dates = |options| {
+            ChangelogBuilder::from_markdown_with(src, options)
+                .releases()
+                .iter()
+                .map(|release| release.version.date().map(|date| date.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            dates(ParseOptions::tolerant()),
+            [
+                Some(String::from("2021-06-01")),
+                Some(String::from("2021-05-20"))
+            ]
+        );
+        let strict = ParseOptions {
+            date_style: DateStyle::Strict,
+            ..ParseOptions::tolerant()
+        };
+        assert_eq!(dates(strict)[0], None);
+    }
+
+    #[test]
+    fn raw_markdown() {
+        let src = "# Changelog\n\n## [1.1.0] - 2021-06-01\n### Added\n* __Bold__ `code`\n  continued\n\n\n\
+                   1.0.0\n-----\n\nFirst release\n\n[1.1.0]: https://example.com/1.1.0\n[1.0.0]: https://example.com/1.0.0\n";
+        let builder = ChangelogBuilder::from_markdown_with(src, ParseOptions::tolerant());
+        let releases = builder.releases();
+
+        assert_eq!(
+            releases[0].raw.as_deref(),
+            Some("## [1.1.0] - 2021-06-01\n### Added\n* __Bold__ `code`\n  continued")
+        );
+        assert_eq!(releases[0].span, Some(13..76));
+        assert_eq!(
+            releases[1].raw.as_deref(),
+            Some("1.0.0\n-----\n\nFirst release")
+        );
+        for release in releases {
+            let span = release.span.clone().unwrap();
+            assert_eq!(release.raw.as_deref(), Some(&src[span]));
+        }
+        assert_eq!(releases[0].only_scope("x").raw, None);
+    }
 }
@@ -0,0 +1,321 @@
+use comrak::{nodes::AstNode, Arena, ComrakOptions};
+use std::borrow::Cow;
+
+/// Hard limits protecting from pathological or malicious changelogs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum size of the input in bytes, the rest is cut off
+    pub max_input: usize,
+    /// Maximum number of AST nodes, nodes after the limit is reached are dropped
+    pub max_nodes: usize,
+    /// Maximum nesting depth of AST nodes, deeper nodes are dropped
+    pub max_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_input: 512 * 1024, // 512 KiB
+            max_nodes: 100_000,
+            max_depth: 32,
+        }
+    }
+}
+
+impl Limits {
+    pub const fn unlimited() -> Self {
+        Limits {
+            max_input: usize::MAX,
+            max_nodes: usize::MAX,
+            max_depth: usize::MAX,
+        }
+    }
+
+    /// Cuts `src` to at most `max_input` bytes, at the last line break if possible
+    pub fn truncate_input<'s>(&self, src: &'s str) -> &'s str {
+        if src.len() <= self.max_input {
+            return src;
+        }
+
+        let mut end = self.max_input;
+        while !src.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        match src[..end].rfind('\n') {
+            Some(nl) => &src[..=nl],
+            None => &src[..end],
+        }
+    }
+
+    /// Flattens lines whose block containers (block quotes, list items and their indentation)
+    /// would be nested deeper than `max_depth`: containers over the limit are removed, so their
+    /// text goes to the deepest allowed one. Lines of top-level fenced code are kept as they are.
+    ///
+    /// The depth is estimated from the line alone (a list level per 2 columns of indentation),
+    /// it's at least the depth of the nodes comrak builds for the containers.
+    pub fn cap_nesting<'s>(&self, src: &'s str) -> Cow<'s, str> {
+        let mut out = String::new();
+        // bytes of `src` which are already in `out`
+        let mut copied = 0;
+        let mut offset = 0;
+        // opening fence of the top-level code block the line is in
+        let mut fence: Option<&str> = None;
+
+        for line in src.split_inclusive('\n') {
+            let start = offset;
+            offset += line.len();
+
+            let (kept, end) = containers(line, self.max_depth);
+            let text = &line[end..];
+            let top_level = end <= 3 && line[..end].bytes().all(|b| b == b' ');
+            match fence {
+                Some(open) => {
+                    if top_level && closes(text, open) {
+                        fence = None;
+                    }
+                    continue;
+                }
+                None if top_level => fence = opening_fence(text),
+                None => {}
+            }
+
+            if kept < end {
+                out.push_str(&src[copied..start + kept]);
+                copied = start + end;
+            }
+        }
+
+        if copied == 0 {
+            Cow::Borrowed(src)
+        } else {
+            out.push_str(&src[copied..]);
+            Cow::Owned(out)
+        }
+    }
+
+    /// Parses (truncated) `src` with capped nesting and drops nodes exceeding the limits.
+    ///
+    /// comrak walks all open containers for every line, so the nesting is capped before parsing.
+    pub fn parse_document<'a>(
+        &self,
+        arena: &'a Arena<AstNode<'a>>,
+        src: &str,
+        options: &ComrakOptions,
+    ) -> &'a AstNode<'a> {
+        let src = self.cap_nesting(self.truncate_input(src));
+        let root = comrak::parse_document(arena, &src, options);
+        self.prune(root);
+        root
+    }
+
+    /// Detaches nodes that are nested too deep or go after the node limit.
+    ///
+    /// Returns `true` if anything was dropped.
+    pub fn prune<'a>(&self, root: &'a AstNode<'a>) -> bool {
+        let mut count = 1;
+        let mut stack = vec![(root, 0)];
+        let mut dropped = Vec::new();
+
+        // Pre-order traversal without recursion, so deep trees don't overflow the stack
+        while let Some((node, depth)) = stack.pop() {
+            let children: Vec<_> = node.children().collect();
+            for child in children.into_iter().rev() {
+                if depth + 1 > self.max_depth {
+                    dropped.push(child);
+                } else {
+                    stack.push((child, depth + 1));
+                }
+            }
+
+            if count >= self.max_nodes {
+                dropped.extend(stack.drain(..).map(|(node, _)| node));
+                break;
+            }
+            count += 1;
+        }
+
+        let pruned = !dropped.is_empty();
+        dropped.into_iter().for_each(|node| node.detach());
+        pruned
+    }
+}
+
+/// Container markers at the start of `line`. Returns where the containers within `max_depth`
+/// end and where all of them end (the start of the text).
+fn containers(line: &str, max_depth: usize) -> (usize, usize) {
+    let mut depth = 0;
+    let mut pos = 0;
+    let mut kept = None;
+    loop {
+        let start = pos;
+        let mut indent = 0;
+        for b in line[pos..].bytes() {
+            match b {
+                b' ' => indent += 1,
+                b'\t' => indent += 4,
+                _ => break,
+            }
+            pos += 1;
+        }
+        if kept.is_none() && depth + indent > max_depth {
+            kept = Some(start);
+        }
+        depth += indent;
+
+        let (len, nodes) = match marker(&line[pos..]) {
+            Some(marker) => marker,
+            None => return (kept.unwrap_or(pos), pos),
+        };
+        if kept.is_none() && depth + nodes > max_depth {
+            kept = Some(pos);
+        }
+        depth += nodes;
+        pos += len;
+    }
+}
+
+/// Length of the block quote or list item marker `s` starts with (with a space after it) and the
+/// number of nodes it opens
+fn marker(s: &str) -> Option<(usize, usize)> {
+    let bytes = s.as_bytes();
+    let (len, nodes) = match *bytes.first()? {
+        b'>' => (1, 1),
+        c @ b'-' | c @ b'+' | c @ b'*' => {
+            // `- - -` and `***` are thematic breaks
+            let line = s.trim_end();
+            let count = line.bytes().filter(|&b| b == c).count();
+            if count >= 3 && line.bytes().all(|b| b == c || b == b' ' || b == b'\t') {
+                return None;
+            }
+            (1, 2)
+        }
+        _ => {
+            let digits = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+            match bytes.get(digits).copied() {
+                Some(b'.') | Some(b')') if (1..=9).contains(&digits) => (digits + 1, 2),
+                _ => return None,
+            }
+        }
+    };
+
+    match bytes.get(len).copied() {
+        Some(b' ') => Some((len + 1, nodes)),
+        Some(b'\t') | Some(b'\n') | Some(b'\r') | None => Some((len, nodes)),
+        // `>` doesn't need a space
+        Some(_) if nodes == 1 => Some((len, nodes)),
+        Some(_) => None,
+    }
+}
+
+/// Code fence (```` ``` ```` or `~~~`) `text` starts with
+fn opening_fence(text: &str) -> Option<&str> {
+    let c = *text.as_bytes().first()?;
+    if c != b'`' && c != b'~' {
+        return None;
+    }
+    let len = text.bytes().take_while(|&b| b == c).count();
+    if len < 3 || (c == b'`' && text[len..].contains('`')) {
+        return None;
+    }
+    Some(&text[..len])
+}
+
+/// Whether `text` is a fence closing the code block opened by `open`
+fn closes(text: &str, open: &str) -> bool {
+    let c = open.as_bytes()[0];
+    let len = text.bytes().take_while(|&b| b == c).count();
+    len >= open.len() && text[len..].trim().is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_input() {
+        let limits = Limits {
+            max_input: 10,
+            ..Limits::default()
+        };
+
+        assert_eq!(limits.truncate_input("short"), "short");
+        assert_eq!(limits.truncate_input("line 1\nline 2\n"), "line 1\n");
+        assert_eq!(limits.truncate_input("ääääääääää"), "äääää");
+
+        let limits = Limits {
+            max_input: 11,
+            ..Limits::default()
+        };
+        // doesn't cut a char in half
+        assert_eq!(limits.truncate_input("ääääääääää"), "äääää");
+    }
+
+    #[test]
+    fn depth() {
+        let limits = Limits {
+            max_depth: 3,
+            ..Limits::default()
+        };
+        let arena = Arena::new();
+        // document > list > item > paragraph > text (depth 4)
+        let root = limits.parse_document(&arena, "- a\n", &ComrakOptions::default());
+
+        assert_eq!(
+            root.descendants()
+                .map(|node| node.ancestors().count() - 1)
+                .max(),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn cap_nesting() {
+        let limits = Limits {
+            max_depth: 4,
+            ..Limits::default()
+        };
+
+        let lists = "- a\n  - b\n    - c\n";
+        assert_eq!(limits.cap_nesting(lists), "- a\n  - b\n    c\n");
+        assert_eq!(limits.cap_nesting("- - - x\n"), "- - x\n");
+        assert_eq!(limits.cap_nesting("> > > > > x\n> y\n"), "> > > > x\n> y\n");
+        assert_eq!(limits.cap_nesting("1. 2. 3) x"), "1. 2. x");
+
+        // within the limit or not containers
+        for src in &["- - -\n", "***\n", ">quote\n", "-1\n", "2021. x\n"] {
+            assert!(
+                matches!(limits.cap_nesting(src), Cow::Borrowed(_)),
+                "{}",
+                src
+            );
+        }
+        let code = "```rust\n          deep code\n```\n- - - x\n";
+        assert_eq!(
+            limits.cap_nesting(code),
+            "```rust\n          deep code\n```\n- - x\n"
+        );
+    }
+
+    #[test]
+    fn deep_input() {
+        let src = format!("{}x\n", "> ".repeat(100_000));
+        let arena = Arena::new();
+        let root = Limits::default().parse_document(&arena, &src, &ComrakOptions::default());
+
+        let depth = root.descendants().map(|node| node.ancestors().count() - 1);
+        assert!(depth.max() <= Some(32));
+    }
+
+    #[test]
+    fn nodes() {
+        let limits = Limits {
+            max_nodes: 3,
+            ..Limits::default()
+        };
+        let arena = Arena::new();
+        let root = limits.parse_document(&arena, "a\n\nb\n\nc\n", &ComrakOptions::default());
+
+        assert_eq!(root.descendants().count(), 3);
+    }
+}
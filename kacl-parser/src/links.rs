@@ -0,0 +1,145 @@
+use crate::{ChangelogBuilder, Version};
+use std::collections::HashMap;
+
+/// Collects link reference definitions (`[1.2.0]: https://github.com/x/y/compare/v1.1.0...v1.2.0`)
+/// from the markdown source.
+///
+/// comrak consumes definitions while parsing, so they are collected from the
+/// source directly. Keys are normalized labels (see [`normalize_label`]).
+pub fn reference_definitions(src: &str) -> HashMap<String, String> {
+    src.lines().filter_map(parse_definition).collect()
+}
+
+/// Labels are matched case-insensitively with collapsed whitespace
+pub fn normalize_label(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+pub(crate) fn parse_definition(line: &str) -> Option<(String, String)> {
+    // up to 3 spaces of indentation are allowed
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+
+    let rest = trimmed.strip_prefix('[')?;
+    let end = rest.find("]:")?;
+    let (label, rest) = (&rest[..end], &rest[end + 2..]);
+    if label.trim().is_empty() || label.contains('[') {
+        return None;
+    }
+
+    // the title (if any) is ignored
+    let url = rest.split_whitespace().next()?;
+    let url = url
+        .strip_prefix('<')
+        .and_then(|u| u.strip_suffix('>'))
+        .unwrap_or(url);
+
+    Some((normalize_label(label), url.to_owned()))
+}
+
+/// Fills in missing links of releases with compare views of the repository, as recommended by
+/// keepachangelog: `[1.2.0]` compares the previous release's tag with `v1.2.0`, `[Unreleased]`
+/// compares the newest tag with `HEAD` and the oldest release links to its tag.
+///
+/// `tag_format` makes the tag from the version, e.g. `v{version}`. GitLab urls get GitLab's paths,
+/// any other repository gets GitHub's ones (`/compare/a...b`, `/releases/tag/a`).
+pub fn generate_compare_links(changelog: &mut ChangelogBuilder, repo: &str, tag_format: &str) {
+    let repo = repo.trim_end_matches('/');
+    let repo = repo.strip_suffix(".git").unwrap_or(repo);
+    let gitlab = repo.contains("://gitlab.");
+    let tag = |version: &Version| match version {
+        Version::Unreleased => None,
+        released => Some(tag_format.replace("{version}", &released.label())),
+    };
+
+    let releases = changelog.releases_mut();
+    for idx in 0..releases.len() {
+        if releases[idx].link.is_some() {
+            continue;
+        }
+
+        // the next released version below, unreleased sections in the middle are skipped
+        let previous = releases[idx + 1..].iter().find_map(|r| tag(&r.version));
+        let link = match (tag(&releases[idx].version), previous) {
+            (Some(tag), Some(previous)) => Some(compare(repo, gitlab, &previous, &tag)),
+            (Some(tag), None) if gitlab => Some(format!("{}/-/tags/{}", repo, tag)),
+            (Some(tag), None) => Some(format!("{}/releases/tag/{}", repo, tag)),
+            (None, Some(previous)) => Some(compare(repo, gitlab, &previous, "HEAD")),
+            (None, None) => None,
+        };
+        releases[idx].link = link;
+    }
+}
+
+fn compare(repo: &str, gitlab: bool, from: &str, to: &str) -> String {
+    let path = if gitlab { "/-/compare" } else { "/compare" };
+    format!("{}{}/{}...{}", repo, path, from, to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn definitions() {
+        let defs = reference_definitions(
+            "## [1.2.0]\n\
+             \n\
+             [Unreleased]: https://github.com/x/y/compare/v1.2.0...HEAD\n\
+             [1.2.0]: <https://github.com/x/y/compare/v1.1.0...v1.2.0> \"title\"\n\
+             \x20   [1.1.0]: https://indented.too/much\n\
+             not a [definition]: here\n",
+        );
+
+        assert_eq!(defs.len(), 2);
+        assert_eq!(
+            defs["unreleased"],
+            "https://github.com/x/y/compare/v1.2.0...HEAD"
+        );
+        assert_eq!(
+            defs["1.2.0"],
+            "https://github.com/x/y/compare/v1.1.0...v1.2.0"
+        );
+    }
+
+    #[test]
+    fn compare_links() {
+        let mut changelog = ChangelogBuilder::from_markdown(
+            "## [Unreleased]\n\n## [1.2.0]\n\n## [1.1.0]\n\n## [1.0.0]\n\n\
+             [1.1.0]: https://example.com/custom\n",
+        );
+        generate_compare_links(&mut changelog, "https://github.com/x/y.git", "v{version}");
+
+        let links: Vec<_> = changelog
+            .releases()
+            .iter()
+            .map(|r| r.link.as_deref().unwrap())
+            .collect();
+        assert_eq!(
+            links,
+            [
+                "https://github.com/x/y/compare/v1.2.0...HEAD",
+                "https://github.com/x/y/compare/v1.1.0...v1.2.0",
+                "https://example.com/custom",
+                "https://github.com/x/y/releases/tag/v1.0.0",
+            ]
+        );
+
+        let mut changelog = ChangelogBuilder::from_markdown("## [0.2.0]\n\n## [0.1.0]\n");
+        generate_compare_links(&mut changelog, "https://gitlab.com/x/y/", "foo-{version}");
+        assert_eq!(
+            changelog.releases()[0].link.as_deref(),
+            Some("https://gitlab.com/x/y/-/compare/foo-0.1.0...foo-0.2.0")
+        );
+        assert_eq!(
+            changelog.releases()[1].link.as_deref(),
+            Some("https://gitlab.com/x/y/-/tags/foo-0.1.0")
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{ChangelogBuilder, Version, SECTIONS};
+use std::fmt;
+use versions::SemVer;
+
+/// Problem found in a changelog by [`validate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lint {
+    /// Versions must go in descending order
+    OutOfOrder { version: SemVer, previous: SemVer },
+    /// The same version appears more than once
+    Duplicate(SemVer),
+    /// Released version without a date
+    MissingDate(SemVer),
+    /// Section is not one of [`SECTIONS`]
+    UnknownSection { version: String, section: String },
+    /// There is no `## [Unreleased]` section
+    MissingUnreleased,
+    /// Released version without any entries
+    EmptyRelease(SemVer),
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lint::OutOfOrder { version, previous } => write!(
+                f,
+                "version {} goes after {}, but versions must be in descending order",
+                version, previous
+            ),
+            Lint::Duplicate(v) => write!(f, "version {} appears more than once", v),
+            Lint::MissingDate(v) => write!(f, "version {} has no release date", v),
+            Lint::UnknownSection { version, section } => write!(
+                f,
+                "unknown section `{}` in {}, expected one of: {}",
+                section,
+                version,
+                SECTIONS.join(", ")
+            ),
+            Lint::MissingUnreleased => f.write_str("there is no `Unreleased` section"),
+            Lint::EmptyRelease(v) => write!(f, "version {} has no entries", v),
+        }
+    }
+}
+
+/// Checks that the changelog follows keepachangelog
+pub fn validate(src: &str) -> Vec<Lint> {
+    let changelog = ChangelogBuilder::from_markdown(src);
+    let mut lints = Vec::new();
+
+    if !changelog
+        .releases()
+        .iter()
+        .any(|r| matches!(r.version, Version::Unreleased))
+    {
+        lints.push(Lint::MissingUnreleased);
+    }
+
+    let mut seen = Vec::new();
+    let mut previous: Option<&SemVer> = None;
+    for release in changelog.releases() {
+        for section in &release.sections {
+            if !section.name.is_empty()
+                && !SECTIONS
+                    .iter()
+                    .any(|s| s.eq_ignore_ascii_case(&section.name))
+            {
+                lints.push(Lint::UnknownSection {
+                    version: release.version.label(),
+                    section: section.name.clone(),
+                });
+            }
+        }
+
+        let (version, date) = match &release.version {
+            // order and dates are checked only for semver versions
+            Version::Unreleased | Version::Other(..) => continue,
+            Version::Released(version, date, _) => (version, date),
+        };
+
+        if seen.contains(&version) {
+            lints.push(Lint::Duplicate(version.clone()));
+        } else if let Some(previous) = previous.filter(|&p| version >= p) {
+            lints.push(Lint::OutOfOrder {
+                version: version.clone(),
+                previous: previous.clone(),
+            });
+        }
+        previous = Some(version);
+        seen.push(version);
+
+        if date.is_none() {
+            lints.push(Lint::MissingDate(version.clone()));
+        }
+
+        if release.sections.iter().all(|s| s.entries.is_empty()) {
+            lints.push(Lint::EmptyRelease(version.clone()));
+        }
+    }
+
+    lints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> SemVer {
+        SemVer::new(s).unwrap()
+    }
+
+    #[test]
+    fn valid() {
+        let src = "# Changelog\n\
+                   \n\
+                   ## [Unreleased]\n\
+                   \n\
+                   ## [1.1.0] - 2020-02-01\n\
+                   \n\
+                   ### Added\n\
+                   \n\
+                   - Thing\n\
+                   \n\
+                   ## [1.0.0] - 2020-01-01\n\
+                   \n\
+                   ### Fixed\n\
+                   \n\
+                   - Bug\n";
+
+        assert_eq!(validate(src), Vec::new());
+    }
+
+    #[test]
+    fn invalid() {
+        let src = "## [1.0.0] - 2020-01-01\n\
+                   \n\
+                   ### Fixes\n\
+                   \n\
+                   - Bug\n\
+                   \n\
+                   ## [1.1.0]\n\
+                   \n\
+                   ### Added\n\
+                   \n\
+                   - Thing\n\
+                   \n\
+                   ## [1.1.0] - 2020-02-01\n";
+
+        assert_eq!(
+            validate(src),
+            [
+                Lint::MissingUnreleased,
+                Lint::UnknownSection {
+                    version: String::from("1.0.0"),
+                    section: String::from("Fixes")
+                },
+                Lint::OutOfOrder {
+                    version: v("1.1.0"),
+                    previous: v("1.0.0")
+                },
+                Lint::MissingDate(v("1.1.0")),
+                Lint::Duplicate(v("1.1.0")),
+                Lint::EmptyRelease(v("1.1.0")),
+            ]
+        );
+    }
+}
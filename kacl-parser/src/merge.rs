@@ -0,0 +1,186 @@
+use crate::{ChangelogBuilder, Release, Section, Version};
+use std::fmt;
+use versions::SemVer;
+
+/// Release of one crate of a workspace
+#[derive(Debug, Clone)]
+pub struct MemberRelease {
+    pub krate: String,
+    pub release: Release,
+}
+
+/// Changelogs of workspace members merged into one view, releases are keyed by
+/// `(crate, version)`. Useful for announcing a release of several crates of a workspace at once
+/// or for a root changelog of a monorepo.
+#[derive(Debug, Clone, Default)]
+pub struct MergedChangelog {
+    releases: Vec<MemberRelease>,
+}
+
+impl MergedChangelog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds releases of the crate, replacing ones with the same version added before
+    pub fn add(&mut self, krate: &str, changelog: &ChangelogBuilder) {
+        for release in changelog.releases() {
+            let label = release.version.label();
+            self.releases
+                .retain(|r| r.krate != krate || r.release.version.label() != label);
+            self.releases.push(MemberRelease {
+                krate: krate.to_owned(),
+                release: release.clone(),
+            });
+        }
+    }
+
+    pub fn get(&self, krate: &str, version: &SemVer) -> Option<&Release> {
+        self.releases
+            .iter()
+            .find(|r| r.krate == krate && r.release.version.semver() == Some(version))
+            .map(|r| &r.release)
+    }
+
+    /// All releases, unreleased and then the newest first, releases of the same day by crate
+    pub fn releases(&self) -> Vec<&MemberRelease> {
+        let mut releases: Vec<_> = self.releases.iter().collect();
+        releases.sort_by(|a, b| {
+            let date = |r: &MemberRelease| match &r.release.version {
+                Version::Unreleased => None,
+                released => Some(released.date()),
+            };
+            // `None` (unreleased) is the smallest, so the order is reversed
+            date(a)
+                .map(std::cmp::Reverse)
+                .cmp(&date(b).map(std::cmp::Reverse))
+                .then_with(|| a.krate.cmp(&b.krate))
+        });
+        releases
+    }
+
+    /// Sections of the given releases merged by name (in the canonical order), every entry is
+    /// prefixed with its crate: `` `tokio-util`: entry ``. Missing releases are skipped.
+    pub fn combined<'a>(
+        &self,
+        keys: impl IntoIterator<Item = (&'a str, &'a SemVer)>,
+    ) -> Vec<Section> {
+        let mut sections: Vec<Section> = Vec::new();
+        for (krate, version) in keys {
+            let release = match self.get(krate, version) {
+                Some(release) => release,
+                None => continue,
+            };
+            for section in &release.sections {
+                let idx = match sections.iter().position(|s| s.name == section.name) {
+                    Some(idx) => idx,
+                    None => {
+                        sections.push(Section::new(section.name.clone()));
+                        sections.len() - 1
+                    }
+                };
+                sections[idx].entries.extend(
+                    section
+                        .entries
+                        .iter()
+                        .map(|entry| format!("`{}`: {}", krate, entry)),
+                );
+            }
+        }
+
+        // stable sort keeps the original order of unknown sections
+        sections.sort_by_key(|s| s.order());
+        sections
+    }
+
+    pub fn build(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for MergedChangelog {
+    /// Root changelog: a `## [crate x.y.z] - date` section per release
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("# Changelog\n")?;
+        for MemberRelease { krate, release } in self.releases() {
+            // the heading of the crate's changelog with the crate name added
+            let release = release.to_string();
+            let heading = release.strip_prefix("## [").unwrap_or(&release);
+            write!(f, "\n## [{} {}", krate, heading)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace() -> MergedChangelog {
+        let mut merged = MergedChangelog::new();
+        merged.add(
+            "tokio",
+            &ChangelogBuilder::from_markdown(
+                "## [1.2.0] - 2021-06-01\n\n### Fixed\n\n- A race\n\n### Added\n\n- Timers\n\n\
+                 ## [1.1.0] - 2021-05-01\n\n### Added\n\n- Channels\n",
+            ),
+        );
+        merged.add(
+            "tokio-util",
+            &ChangelogBuilder::from_markdown(
+                "## [Unreleased]\n\n## [0.7.0] - 2021-06-01\n\n### Added\n\n- Codecs\n",
+            ),
+        );
+        merged
+    }
+
+    #[test]
+    fn combined() {
+        let merged = workspace();
+        let (v1, v2) = (SemVer::new("1.2.0").unwrap(), SemVer::new("0.7.0").unwrap());
+        let sections = merged.combined(vec![("tokio", &v1), ("tokio-util", &v2)]);
+
+        assert_eq!(
+            sections,
+            [
+                Section::new("Added")
+                    .entry("`tokio`: Timers")
+                    .entry("`tokio-util`: Codecs"),
+                Section::new("Fixed").entry("`tokio`: A race"),
+            ]
+        );
+    }
+
+    #[test]
+    fn root_changelog() {
+        assert_eq!(
+            workspace().build(),
+            "# Changelog\n\
+             \n\
+             ## [tokio-util Unreleased]\n\
+             \n\
+             ## [tokio 1.2.0] - 2021-06-01\n\
+             \n\
+             ### Added\n\
+             \n\
+             - Timers\n\
+             \n\
+             ### Fixed\n\
+             \n\
+             - A race\n\
+             \n\
+             ## [tokio-util 0.7.0] - 2021-06-01\n\
+             \n\
+             ### Added\n\
+             \n\
+             - Codecs\n\
+             \n\
+             ## [tokio 1.1.0] - 2021-05-01\n\
+             \n\
+             ### Added\n\
+             \n\
+             - Channels\n"
+        );
+    }
+}
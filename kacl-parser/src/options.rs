@@ -0,0 +1,95 @@
+use crate::{Format, VersionScheme};
+
+/// How the release date is attached to the version in a heading
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateFormat {
+    /// `1.2.3 - 2021-06-01` (keepachangelog)
+    Dash,
+    /// `1.2.3 / 2021-06-01`
+    Slash,
+    /// `1.2.3 (2021-06-01)`
+    Parenthesized,
+}
+
+/// Which dates are accepted in version headings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateStyle {
+    /// `2021-06-01` only (keepachangelog)
+    Strict,
+    /// Also `2021.06.01`, `01-06-2021`, `June 1, 2021` and `(2021-06-01)`, see
+    /// [`Date::parse_lenient`](crate::Date::parse_lenient)
+    Lenient,
+}
+
+/// Configures which version headings are recognized
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Levels of version headings (`2` is `## ...`)
+    pub heading_levels: Vec<u32>,
+    /// Accepted ways to attach the date
+    pub date_formats: Vec<DateFormat>,
+    /// Accepted dates, normalized to [`Date`](crate::Date). With [`DateStyle::Strict`] a heading
+    /// with a non-ISO date isn't a version heading.
+    pub date_style: DateStyle,
+    /// Accept `v1.2.3` in addition to `1.2.3`
+    pub allow_v_prefix: bool,
+    /// Releases of [`Format::Conventional`] changelogs are normalized to keepachangelog
+    pub format: Format,
+    /// Versions which aren't semver are accepted by other schemes, e.g. `## 2021.06.01` by
+    /// [`VersionScheme::Lenient`], they are parsed as [`Version::Other`](crate::Version::Other)
+    pub version_scheme: VersionScheme,
+}
+
+impl Default for ParseOptions {
+    /// Strict keepachangelog
+    fn default() -> Self {
+        ParseOptions {
+            heading_levels: vec![2],
+            date_formats: vec![DateFormat::Dash],
+            date_style: DateStyle::Strict,
+            allow_v_prefix: false,
+            format: Format::KeepAChangelog,
+            version_scheme: VersionScheme::Semver,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Accepts common variants found in the wild, like `# v1.2.3 (2021-06-01)` and
+    /// `## 1.2.3 - June 1, 2021`
+    pub fn tolerant() -> Self {
+        ParseOptions {
+            heading_levels: vec![1, 2],
+            date_formats: vec![
+                DateFormat::Dash,
+                DateFormat::Slash,
+                DateFormat::Parenthesized,
+            ],
+            date_style: DateStyle::Lenient,
+            allow_v_prefix: true,
+            format: Format::KeepAChangelog,
+            version_scheme: VersionScheme::Semver,
+        }
+    }
+
+    /// Changelogs generated from conventional commits, like `## [1.2.3](url) (2021-06-01)` and
+    /// `### [1.2.4](url) (2021-06-02)` for patches (see [`Format::Conventional`])
+    pub fn conventional() -> Self {
+        ParseOptions {
+            heading_levels: vec![1, 2, 3],
+            date_formats: vec![DateFormat::Parenthesized, DateFormat::Dash],
+            date_style: DateStyle::Strict,
+            allow_v_prefix: true,
+            format: Format::Conventional,
+            version_scheme: VersionScheme::Semver,
+        }
+    }
+
+    /// [`ParseOptions::conventional`] or [`ParseOptions::tolerant`], by the detected format of `src`
+    pub fn detect(src: &str) -> Self {
+        match Format::detect(src) {
+            Format::Conventional => Self::conventional(),
+            Format::KeepAChangelog => Self::tolerant(),
+        }
+    }
+}
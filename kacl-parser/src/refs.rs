@@ -0,0 +1,221 @@
+use crate::{ChangelogBuilder, Release};
+use std::fmt;
+
+/// Path segments of issue and pull/merge request urls (GitHub, GitLab, Gitea)
+const KINDS: [&str; 4] = ["issues", "pull", "pulls", "merge_requests"];
+
+/// Reference to an issue or a pull request in an entry: `#1234`, `GH-1234` or a full url like
+/// `https://github.com/owner/repo/issues/1234`. Issues and pull requests share numbers, so
+/// they aren't told apart.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IssueRef {
+    /// `owner/repo` (`group/subgroup/repo` on GitLab) of a url, `None` for `#1234` and `GH-1234`,
+    /// which refer to the changelog's own repository
+    pub repo: Option<String>,
+    pub number: u64,
+}
+
+impl fmt::Display for IssueRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.repo {
+            Some(repo) => write!(f, "{}#{}", repo, self.number),
+            None => write!(f, "#{}", self.number),
+        }
+    }
+}
+
+impl IssueRef {
+    /// Issue of the changelog's own repository
+    pub fn new(number: u64) -> Self {
+        IssueRef { repo: None, number }
+    }
+
+    /// Issue of another repository
+    pub fn in_repo(repo: impl Into<String>, number: u64) -> Self {
+        IssueRef {
+            repo: Some(repo.into()),
+            number,
+        }
+    }
+
+    /// Whether both refer to the same issue. Numbers have to be equal, repositories only if
+    /// both are known (`#12` matches `https://github.com/a/b/issues/12`).
+    pub fn matches(&self, other: &IssueRef) -> bool {
+        self.number == other.number
+            && match (&self.repo, &other.repo) {
+                (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+                _ => true,
+            }
+    }
+
+    /// All references in the markdown text, in order of appearance and without duplicates.
+    /// A short reference to the number of a url is dropped, e.g. the text of
+    /// `[#12](https://github.com/a/b/pull/12)`.
+    pub fn parse_all(text: &str) -> Vec<IssueRef> {
+        let mut found: Vec<(usize, IssueRef)> =
+            short_refs(text).into_iter().chain(url_refs(text)).collect();
+        found.sort_by_key(|(offset, _)| *offset);
+
+        let mut refs: Vec<IssueRef> = Vec::new();
+        for (_, issue) in found {
+            if !refs.contains(&issue) {
+                refs.push(issue);
+            }
+        }
+        let urls: Vec<u64> = refs
+            .iter()
+            .filter(|r| r.repo.is_some())
+            .map(|r| r.number)
+            .collect();
+        refs.retain(|r| r.repo.is_some() || !urls.contains(&r.number));
+        refs
+    }
+}
+
+/// Leading digits of `s`, if they're a whole word
+fn number(s: &str) -> Option<u64> {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let rest = &s[end..];
+    if end == 0 || rest.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+    s[..end].parse().ok()
+}
+
+/// `#1234` and `GH-1234` which don't follow a word char, `&` (html entities) or `/` (url fragments)
+fn short_refs(text: &str) -> Vec<(usize, IssueRef)> {
+    let mut refs = Vec::new();
+    for (offset, _) in text.match_indices(|c: char| c == '#' || c == 'G') {
+        let before = text[..offset].chars().next_back();
+        if before.map_or(false, |c| c.is_alphanumeric() || "_&/".contains(c)) {
+            continue;
+        }
+        let rest = &text[offset..];
+        let digits = rest.strip_prefix('#').or_else(|| rest.strip_prefix("GH-"));
+        if let Some(n) = digits.and_then(number) {
+            refs.push((offset, IssueRef::new(n)));
+        }
+    }
+    refs
+}
+
+/// Issue and pull/merge request urls
+fn url_refs(text: &str) -> Vec<(usize, IssueRef)> {
+    let mut refs = Vec::new();
+    for (offset, _) in text.match_indices("http") {
+        let url = &text[offset..];
+        let url = match url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("http://"))
+        {
+            Some(url) => url,
+            None => continue,
+        };
+        let end = url
+            .find(|c: char| c.is_whitespace() || "()<>[]\"'".contains(c))
+            .unwrap_or(url.len());
+        let segments: Vec<&str> = url[..end].split('/').skip(1).collect();
+        let kind = match segments.iter().position(|s| KINDS.contains(s)) {
+            Some(kind) => kind,
+            None => continue,
+        };
+        // GitLab's `group/repo/-/issues/12`
+        let repo = &segments[..kind];
+        let repo = repo.strip_suffix(&["-"]).unwrap_or(repo);
+        let n = segments.get(kind + 1).and_then(|s| number(s));
+        if let (true, Some(n)) = (repo.len() >= 2, n) {
+            refs.push((offset, IssueRef::in_repo(repo.join("/"), n)));
+        }
+    }
+    refs
+}
+
+impl Release {
+    /// Entries referring to the issue (see [`IssueRef::matches`]), e.g. to find which release
+    /// fixed `#4521`
+    pub fn entries_referencing<'s>(&'s self, issue: &'s IssueRef) -> impl Iterator<Item = &'s str> {
+        self.sections
+            .iter()
+            .flat_map(|section| &section.entries)
+            .filter(move |entry| IssueRef::parse_all(entry).iter().any(|r| r.matches(issue)))
+            .map(String::as_str)
+    }
+
+    /// References in all entries of the release, without duplicates
+    pub fn issue_refs(&self) -> Vec<IssueRef> {
+        let mut refs: Vec<IssueRef> = Vec::new();
+        for entry in self.sections.iter().flat_map(|section| &section.entries) {
+            for issue in IssueRef::parse_all(entry) {
+                if !refs.contains(&issue) {
+                    refs.push(issue);
+                }
+            }
+        }
+        refs
+    }
+}
+
+impl ChangelogBuilder {
+    /// Releases with entries referring to the issue, newest first (in the order of the changelog)
+    pub fn releases_referencing<'s>(
+        &'s self,
+        issue: &'s IssueRef,
+    ) -> impl Iterator<Item = &'s Release> {
+        self.releases()
+            .iter()
+            .filter(move |r| r.entries_referencing(issue).next().is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short() {
+        assert_eq!(
+            IssueRef::parse_all("Fix a panic (#12, GH-34), see #12"),
+            [IssueRef::new(12), IssueRef::new(34)]
+        );
+        // not references
+        assert!(IssueRef::parse_all("C# 2 &#123; a#1 #12a #").is_empty());
+    }
+
+    #[test]
+    fn urls() {
+        assert_eq!(
+            IssueRef::parse_all(
+                "Fix [#12](https://github.com/a/b/pull/12) and \
+                 https://gitlab.com/g/s/r/-/issues/7#note_1 (https://github.com/a/b/issues/new)"
+            ),
+            [IssueRef::in_repo("a/b", 12), IssueRef::in_repo("g/s/r", 7)]
+        );
+        assert!(IssueRef::parse_all("https://github.com/a/b/compare/v1...v2").is_empty());
+    }
+
+    #[test]
+    fn matching() {
+        let url = IssueRef::in_repo("a/b", 12);
+        assert!(IssueRef::new(12).matches(&url));
+        assert!(IssueRef::in_repo("A/B", 12).matches(&url));
+        assert!(!IssueRef::in_repo("a/c", 12).matches(&url));
+        assert!(!IssueRef::new(13).matches(&url));
+    }
+
+    #[test]
+    fn releases() {
+        let changelog = ChangelogBuilder::from_markdown(
+            "## [1.1.0]\n\n### Fixed\n\n- Leak (#4521)\n- Typo\n\n\
+             ## [1.0.0]\n\n### Added\n\n- Everything, closes GH-1\n",
+        );
+        let issue = IssueRef::new(4521);
+        let found: Vec<_> = changelog.releases_referencing(&issue).collect();
+        assert_eq!(found.len(), 1);
+        // entries are markdown, where `#` is escaped
+        assert_eq!(
+            found[0].entries_referencing(&issue).collect::<Vec<_>>(),
+            ["Leak (\\#4521)"]
+        );
+        assert_eq!(changelog.releases()[1].issue_refs(), [IssueRef::new(1)]);
+    }
+}
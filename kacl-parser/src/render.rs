@@ -0,0 +1,369 @@
+//! Rendering of releases for channels which can't display markdown, and as sanitized HTML
+use crate::{Limits, Release, Section, Version, SECTIONS};
+use comrak::nodes::{AstNode, NodeValue};
+
+/// Text of the markdown without formatting, links are replaced by their text. The markdown is
+/// parsed within the default [`Limits`].
+pub fn strip_markdown(src: &str) -> String {
+    let arena = comrak::Arena::new();
+    let root = Limits::default().parse_document(&arena, src, &comrak::ComrakOptions::default());
+
+    fn collect<'a>(node: &'a AstNode<'a>, out: &mut String) {
+        match &node.data.borrow().value {
+            NodeValue::Text(text) => out.push_str(&String::from_utf8_lossy(text)),
+            NodeValue::Code(code) => out.push_str(&String::from_utf8_lossy(&code.literal)),
+            NodeValue::SoftBreak | NodeValue::LineBreak => out.push(' '),
+            NodeValue::Paragraph if !out.is_empty() => out.push(' '),
+            _ => {}
+        }
+        for child in node.children() {
+            collect(child, out);
+        }
+    }
+
+    let mut out = String::new();
+    collect(root, &mut out);
+    out
+}
+
+/// Splits `text` into lines of at most `width` chars, breaking at whitespace.
+/// Words longer than `width` are truncated with `…`.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_len = 0;
+
+    for word in text.split_whitespace() {
+        let word: String = if word.chars().count() > width {
+            word.chars()
+                .take(width - 1)
+                .chain(std::iter::once('…'))
+                .collect()
+        } else {
+            word.to_owned()
+        };
+        let word_len = word.chars().count();
+
+        if line_len > 0 && line_len + 1 + word_len > width {
+            lines.push(std::mem::take(&mut line));
+            line_len = 0;
+        }
+        if line_len > 0 {
+            line.push(' ');
+            line_len += 1;
+        }
+        line.push_str(&word);
+        line_len += word_len;
+    }
+    if line_len > 0 {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Renders release as plain text: no markdown, `•` bullets, lines are at most `width` chars.
+/// Entries are parsed within the default [`Limits`].
+pub fn plain_text(release: &Release, width: usize) -> String {
+    let mut heading = match &release.version {
+        Version::Unreleased => String::from("Unreleased"),
+        Version::Released(v, None, _) => v.to_string(),
+        Version::Released(v, Some(date), _) => format!("{} - {}", v, date),
+        Version::Other(v, None, _) => v.clone(),
+        Version::Other(v, Some(date), _) => format!("{} - {}", v, date),
+    };
+    if release.version.is_yanked() {
+        heading.push_str(" [YANKED]");
+    }
+
+    let mut out = wrap(&heading, width).join("\n");
+    out.push('\n');
+
+    for section in release.sections.iter().filter(|s| !is_blank(s)) {
+        out.push('\n');
+        if !section.name.is_empty() {
+            for line in wrap(&section.name, width) {
+                out.push_str(&line);
+                out.push('\n');
+            }
+        }
+        for entry in &section.entries {
+            let lines = wrap(&strip_markdown(entry), width.saturating_sub(2));
+            for (i, line) in lines.iter().enumerate() {
+                out.push_str(if i == 0 { "• " } else { "  " });
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+    }
+
+    out
+}
+
+/// Configures [`html`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HtmlOptions {
+    /// Prepended to all CSS classes, e.g. `kacl-` gives `kacl-release` and `kacl-section-added`
+    pub class_prefix: String,
+    /// Level of the version heading (`2` is `<h2>`), section headings are one level lower
+    pub heading_level: u8,
+    /// `rel` of links in entries and of the version link, e.g. `nofollow noopener`
+    pub link_rel: Option<String>,
+    /// Limits of the markdown of every entry
+    pub limits: Limits,
+}
+
+impl Default for HtmlOptions {
+    fn default() -> Self {
+        HtmlOptions {
+            class_prefix: String::from("kacl-"),
+            heading_level: 2,
+            link_rel: Some(String::from("nofollow noopener")),
+            limits: Limits::default(),
+        }
+    }
+}
+
+/// Escapes text for html element content and attribute values
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Whether the section is the unnamed one which holds only other blocks (which aren't rendered)
+fn is_blank(section: &Section) -> bool {
+    section.name.is_empty() && section.entries.is_empty()
+}
+
+/// Class of the section: its name for keepachangelog sections, `other` for the rest
+fn section_class(name: &str) -> String {
+    match SECTIONS
+        .iter()
+        .find(|known| known.eq_ignore_ascii_case(name.trim()))
+    {
+        Some(known) => known.to_ascii_lowercase(),
+        None => String::from("other"),
+    }
+}
+
+/// Html of a markdown entry. Raw html is omitted and links with dangerous schemes (like
+/// `javascript:`) lose their urls, a lone paragraph is unwrapped.
+fn entry_html(entry: &str, link_rel: Option<&str>, limits: &Limits) -> String {
+    let arena = comrak::Arena::new();
+    let options = comrak::ComrakOptions::default();
+    let root = limits.parse_document(&arena, entry, &options);
+    let mut html = Vec::new();
+    comrak::format_html(root, &options, &mut html).expect(crate::IO_VEC_ERR);
+    let html = String::from_utf8(html).expect("comrak produces valid utf-8");
+    let html = html.trim_end();
+    let html = match html
+        .strip_prefix("<p>")
+        .and_then(|h| h.strip_suffix("</p>"))
+    {
+        Some(inner) if !inner.contains("<p>") => inner,
+        _ => html,
+    };
+    match link_rel {
+        // text can't contain `<a href="`, comrak escapes `<` outside of tags
+        Some(rel) => {
+            let tag = format!("<a rel=\"{}\" href=\"", escape_html(rel));
+            html.replace("<a href=\"", &tag)
+        }
+        None => html.to_owned(),
+    }
+}
+
+/// Renders the release as sanitized HTML with stable CSS classes (with `class_prefix`):
+///
+/// - `release` on the `<section>` of the release, plus `yanked` for yanked ones
+/// - `version` on its heading, `date` on the `<time>` and `yanked-mark` on the `[YANKED]` mark
+/// - `section` and `section-added`, `section-changed`, ... (`section-other` for names which
+///   aren't keepachangelog's) on `<section>`s of the sections, `entries` on their lists
+///
+/// Markdown of entries may come from anyone publishing a crate, so raw html in it is omitted,
+/// links with dangerous schemes lose their urls and the version links only to http(s) urls.
+pub fn html(release: &Release, options: &HtmlOptions) -> String {
+    let class = |name: &str| format!("{}{}", escape_html(&options.class_prefix), name);
+    let rel = options.link_rel.as_deref();
+    let rel_attr = rel.map_or_else(String::new, |rel| format!(" rel=\"{}\"", escape_html(rel)));
+    let level = options.heading_level.clamp(1, 5);
+
+    let mut release_class = class("release");
+    if release.version.is_yanked() {
+        release_class.push(' ');
+        release_class.push_str(&class("yanked"));
+    }
+    let mut out = format!("<section class=\"{}\">\n", release_class);
+
+    let label = escape_html(&release.version.label());
+    let label = match &release.link {
+        Some(link) if link.starts_with("https://") || link.starts_with("http://") => format!(
+            "<a{} href=\"{}\">{}</a>",
+            rel_attr,
+            escape_html(link),
+            label
+        ),
+        _ => label,
+    };
+    out.push_str(&format!(
+        "<h{} class=\"{}\">{}",
+        level,
+        class("version"),
+        label
+    ));
+    if let Some(date) = release.version.date() {
+        out.push_str(&format!(
+            " <time class=\"{}\" datetime=\"{date}\">{date}</time>",
+            class("date"),
+            date = date
+        ));
+    }
+    if release.version.is_yanked() {
+        out.push_str(&format!(
+            " <span class=\"{}\">[YANKED]</span>",
+            class("yanked-mark")
+        ));
+    }
+    out.push_str(&format!("</h{}>\n", level));
+
+    for section in release.sections.iter().filter(|s| !is_blank(s)) {
+        let kind = class(&format!("section-{}", section_class(&section.name)));
+        out.push_str(&format!(
+            "<section class=\"{} {}\">\n",
+            class("section"),
+            kind
+        ));
+        if !section.name.is_empty() {
+            out.push_str(&format!(
+                "<h{level}>{}</h{level}>\n",
+                escape_html(&section.name),
+                level = level + 1
+            ));
+        }
+        out.push_str(&format!("<ul class=\"{}\">\n", class("entries")));
+        for entry in &section.entries {
+            let item = entry_html(entry, rel, &options.limits);
+            out.push_str(&format!("<li>{}</li>\n", item));
+        }
+        out.push_str("</ul>\n</section>\n");
+    }
+
+    out.push_str("</section>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Date, Section};
+    use versions::SemVer;
+
+    #[test]
+    fn plain() {
+        let release = Release::new(Version::Released(
+            SemVer::new("1.2.3").unwrap(),
+            Date::new(2021, 6, 1),
+            false,
+        ))
+        .section(
+            Section::new("Added")
+                .entry("**Bold** feature with [a link](https://example.com)")
+                .entry("`code` and a rather long entry which has to be wrapped"),
+        );
+
+        assert_eq!(
+            plain_text(&release, 24),
+            "1.2.3 - 2021-06-01\n\
+             \n\
+             Added\n\
+             • Bold feature with a\n\
+             \x20 link\n\
+             • code and a rather long\n\
+             \x20 entry which has to be\n\
+             \x20 wrapped\n"
+        );
+    }
+
+    #[test]
+    fn html_release() {
+        let mut release = Release::new(Version::Released(
+            SemVer::new("1.2.3").unwrap(),
+            Date::new(2021, 6, 1),
+            true,
+        ))
+        .section(Section::new("Added").entry("**Bold** [link](https://example.com)"))
+        .section(Section::new("Upgrade <notes>").entry("See the guide"));
+        release.link = Some(String::from("https://example.com/compare/v1.2.2...v1.2.3"));
+
+        assert_eq!(
+            html(&release, &HtmlOptions::default()),
+            "<section class=\"kacl-release kacl-yanked\">\n\
+             <h2 class=\"kacl-version\"><a rel=\"nofollow noopener\" \
+             href=\"https://example.com/compare/v1.2.2...v1.2.3\">1.2.3</a> \
+             <time class=\"kacl-date\" datetime=\"2021-06-01\">2021-06-01</time> \
+             <span class=\"kacl-yanked-mark\">[YANKED]</span></h2>\n\
+             <section class=\"kacl-section kacl-section-added\">\n\
+             <h3>Added</h3>\n\
+             <ul class=\"kacl-entries\">\n\
+             <li><strong>Bold</strong> \
+             <a rel=\"nofollow noopener\" href=\"https://example.com\">link</a></li>\n\
+             </ul>\n</section>\n\
+             <section class=\"kacl-section kacl-section-other\">\n\
+             <h3>Upgrade &lt;notes&gt;</h3>\n\
+             <ul class=\"kacl-entries\">\n<li>See the guide</li>\n</ul>\n</section>\n\
+             </section>\n"
+        );
+    }
+
+    #[test]
+    fn html_sanitized() {
+        let mut release = Release::new(Version::Unreleased).section(
+            Section::new("Fixed")
+                .entry("<script>alert(1)</script> and [click](javascript:alert(1))")
+                .entry("<img src=x onerror=alert(1)>"),
+        );
+        release.link = Some(String::from("javascript:alert(1)"));
+        let options = HtmlOptions {
+            class_prefix: String::new(),
+            heading_level: 3,
+            link_rel: None,
+            ..HtmlOptions::default()
+        };
+
+        let html = html(&release, &options);
+        assert!(
+            html.starts_with("<section class=\"release\">\n<h3 class=\"version\">Unreleased</h3>")
+        );
+        assert!(html.contains("<section class=\"section section-fixed\">\n<h4>Fixed</h4>"));
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("<img"));
+        assert!(!html.contains("javascript:"));
+    }
+
+    #[test]
+    fn deep_entry() {
+        let entry = format!("{}deep", "> ".repeat(100_000));
+        let release = Release::new(Version::Unreleased).section(Section::new("Fixed").entry(entry));
+
+        let html = html(&release, &HtmlOptions::default());
+        assert!(html.matches("<blockquote>").count() <= Limits::default().max_depth);
+        // the text is nested deeper than the limit
+        assert_eq!(plain_text(&release, 80), "Unreleased\n\nFixed\n");
+    }
+
+    #[test]
+    fn long_words() {
+        assert_eq!(wrap("abcdef gh", 4), ["abc…", "gh"]);
+    }
+}
@@ -0,0 +1,229 @@
+//! How versions are parsed and ordered. Besides semver there are date-based versions like
+//! `2021.06.01` or `20210601` and loose ones like `1.2` or `1.0-rc1`, found in changelogs and in
+//! indexes of alternative registries.
+use std::{cmp::Ordering, fmt};
+use versions::SemVer;
+
+/// Parsing and ordering of versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionScheme {
+    /// `1.2.3-rc.1+build`
+    Semver,
+    /// Numbers separated by `.` (or by `-`/`_` before a number) with an optional suffix:
+    /// `2021.06.01`, `2021-06-01`, `1.2`, `1.0-rc1`. Numbers are compared as numbers, missing
+    /// ones are zeros, a suffix makes a prerelease of the numbers (except `+build` ones).
+    Lenient,
+    /// Any version, compared as strings
+    Lexicographic,
+}
+
+impl Default for VersionScheme {
+    fn default() -> Self {
+        VersionScheme::Semver
+    }
+}
+
+/// Numbers of a [`VersionScheme::Lenient`] version and the suffix after them, `None` if the
+/// version doesn't start with a number
+fn lenient(version: &str) -> Option<(Vec<u64>, &str)> {
+    let mut rest = version.trim();
+    let mut numbers = Vec::new();
+    loop {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if digits == 0 {
+            break;
+        }
+        numbers.push(rest[..digits].parse().ok()?);
+        rest = &rest[digits..];
+
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (Some('.'), Some(next)) | (Some('-'), Some(next)) | (Some('_'), Some(next))
+                if next.is_ascii_digit() =>
+            {
+                rest = &rest[1..];
+            }
+            _ => break,
+        }
+    }
+
+    if numbers.is_empty() {
+        None
+    } else {
+        Some((numbers, rest))
+    }
+}
+
+/// Whether the suffix of a lenient version makes it a prerelease, build metadata doesn't
+fn is_pre_suffix(suffix: &str) -> bool {
+    !suffix.is_empty() && !suffix.starts_with('+')
+}
+
+fn compare_lenient(a: &str, b: &str) -> Option<Ordering> {
+    let ((a, a_suffix), (b, b_suffix)) = (lenient(a)?, lenient(b)?);
+    let len = a.len().max(b.len());
+    let number = |numbers: &[u64], idx: usize| numbers.get(idx).copied().unwrap_or(0);
+    let numbers = (0..len)
+        .map(|idx| number(&a, idx).cmp(&number(&b, idx)))
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal);
+
+    let suffixes = match (is_pre_suffix(a_suffix), is_pre_suffix(b_suffix)) {
+        (true, true) => a_suffix.cmp(b_suffix),
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => Ordering::Equal,
+    };
+    Some(numbers.then(suffixes))
+}
+
+impl VersionScheme {
+    /// Names of the schemes, as accepted by [`VersionScheme::parse`]
+    pub const NAMES: [&'static str; 3] = ["semver", "lenient", "lexicographic"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "semver" => Some(VersionScheme::Semver),
+            "lenient" => Some(VersionScheme::Lenient),
+            "lexicographic" => Some(VersionScheme::Lexicographic),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            VersionScheme::Semver => "semver",
+            VersionScheme::Lenient => "lenient",
+            VersionScheme::Lexicographic => "lexicographic",
+        }
+    }
+
+    /// The strictest scheme accepting all of `versions`
+    pub fn detect<'v>(versions: impl IntoIterator<Item = &'v str>) -> Self {
+        let mut scheme = VersionScheme::Semver;
+        for version in versions {
+            if scheme == VersionScheme::Semver && SemVer::new(version).is_none() {
+                scheme = VersionScheme::Lenient;
+            }
+            if scheme == VersionScheme::Lenient && lenient(version).is_none() {
+                return VersionScheme::Lexicographic;
+            }
+        }
+
+        scheme
+    }
+
+    /// Whether the version can be compared by the scheme
+    pub fn accepts(self, version: &str) -> bool {
+        match self {
+            VersionScheme::Semver => SemVer::new(version).is_some(),
+            VersionScheme::Lenient => lenient(version).is_some(),
+            VersionScheme::Lexicographic => !version.trim().is_empty(),
+        }
+    }
+
+    /// Order of the versions, `None` if one of them isn't accepted by the scheme
+    pub fn compare(self, a: &str, b: &str) -> Option<Ordering> {
+        match self {
+            VersionScheme::Semver => Some(SemVer::new(a)?.cmp(&SemVer::new(b)?)),
+            VersionScheme::Lenient => compare_lenient(a, b),
+            VersionScheme::Lexicographic => Some(a.cmp(b)),
+        }
+    }
+
+    /// Whether the version is a prerelease, versions compared as strings never are
+    pub fn is_prerelease(self, version: &str) -> bool {
+        match self {
+            VersionScheme::Semver => SemVer::new(version).map_or(false, |v| v.pre_rel.is_some()),
+            VersionScheme::Lenient => lenient(version).map_or(false, |(_, s)| is_pre_suffix(s)),
+            VersionScheme::Lexicographic => false,
+        }
+    }
+
+    /// Which of the major (`0`), minor (`1`) and patch (`2`) numbers is the first to differ
+    /// between the versions, `2` if none of them does (e.g. only the prerelease changed).
+    /// `None` if a version isn't accepted or the versions are compared as strings.
+    pub fn bump(self, previous: &str, new: &str) -> Option<usize> {
+        let (previous, new) = match self {
+            VersionScheme::Semver => {
+                let (previous, new) = (SemVer::new(previous)?, SemVer::new(new)?);
+                let numbers = |v: SemVer| vec![u64::from(v.major), v.minor.into(), v.patch.into()];
+                (numbers(previous), numbers(new))
+            }
+            VersionScheme::Lenient => (lenient(previous)?.0, lenient(new)?.0),
+            VersionScheme::Lexicographic => return None,
+        };
+
+        let number = |numbers: &[u64], idx: usize| numbers.get(idx).copied().unwrap_or(0);
+        Some(
+            (0..2)
+                .find(|&idx| number(&previous, idx) != number(&new, idx))
+                .unwrap_or(2),
+        )
+    }
+}
+
+impl fmt::Display for VersionScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use VersionScheme::*;
+
+    #[test]
+    fn detect() {
+        assert_eq!(VersionScheme::detect(vec!["0.1.0", "1.0.0-rc.1"]), Semver);
+        assert_eq!(VersionScheme::detect(vec!["0.1.0", "2021.6"]), Lenient);
+        assert_eq!(
+            VersionScheme::detect(vec!["2021-06-01", "1.0-rc1"]),
+            Lenient
+        );
+        assert_eq!(
+            VersionScheme::detect(vec!["0.1.0", "nightly"]),
+            Lexicographic
+        );
+        assert_eq!(VersionScheme::detect(Vec::new()), Semver);
+    }
+
+    #[test]
+    fn lenient_order() {
+        let cmp = |a, b| Lenient.compare(a, b).unwrap();
+        assert_eq!(cmp("2021.06.01", "2021.6.1"), Ordering::Equal);
+        assert_eq!(cmp("2021.06.01", "2021.10.01"), Ordering::Less);
+        assert_eq!(cmp("2021-12-31", "2022-01-01"), Ordering::Less);
+        assert_eq!(cmp("1.2", "1.2.0"), Ordering::Equal);
+        assert_eq!(cmp("1.2", "1.10"), Ordering::Less);
+        assert_eq!(cmp("1.0-rc1", "1.0"), Ordering::Less);
+        assert_eq!(cmp("1.0-rc1", "1.0-rc2"), Ordering::Less);
+        assert_eq!(cmp("1.0+build.5", "1.0"), Ordering::Equal);
+        assert_eq!(Lenient.compare("1.0", "nightly"), None);
+        assert_eq!(Semver.compare("1.0.0", "2021.6"), None);
+        assert_eq!(
+            Lexicographic.compare("20210601", "20210602"),
+            Some(Ordering::Less)
+        );
+    }
+
+    #[test]
+    fn bumps() {
+        assert_eq!(Semver.bump("1.2.3", "1.3.0"), Some(1));
+        assert_eq!(Semver.bump("1.0.0-rc.1", "1.0.0"), Some(2));
+        assert_eq!(Lenient.bump("2021.06.01", "2021.06.02"), Some(2));
+        assert_eq!(Lenient.bump("2021.06.01", "2021.07.01"), Some(1));
+        assert_eq!(Lenient.bump("2021.12.31", "2022.01.01"), Some(0));
+        assert_eq!(Lenient.bump("1", "1.1"), Some(1));
+        assert_eq!(Lexicographic.bump("a", "b"), None);
+
+        assert!(Lenient.is_prerelease("1.0-beta"));
+        assert!(!Lenient.is_prerelease("1.0+build"));
+        assert!(!Lexicographic.is_prerelease("1.0-beta"));
+        assert_eq!(VersionScheme::parse("lenient"), Some(Lenient));
+        assert_eq!(VersionScheme::parse("dates"), None);
+    }
+}
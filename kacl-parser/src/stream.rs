@@ -0,0 +1,285 @@
+use crate::{parse_version, Limits, ParseOptions, Release, Version, Warning};
+use comrak::{Arena, ComrakOptions};
+use std::io::BufRead;
+
+/// Fence of an open code block: its char (`` ` `` or `~`) and length
+type Fence = (char, usize);
+
+/// Releases of a changelog parsed lazily, newest (topmost) first.
+///
+/// Lines are only scanned for version headings, a release is parsed when it's yielded, and the
+/// input is read no further than the next version heading. So taking the first release of a
+/// huge changelog costs about as much as parsing that release alone.
+///
+/// Unlike [`Changelog`](crate::Changelog), only ATX headings (`## 1.2.3`) are recognized and links
+/// of releases aren't resolved, as reference definitions are at the end of the document.
+/// An I/O error or invalid UTF-8 ends the stream like the end of input.
+#[derive(Debug)]
+pub struct ReleaseStream<R> {
+    reader: R,
+    options: ParseOptions,
+    limits: Limits,
+    /// Version of the release being read, `None` before the first version heading
+    version: Option<Version>,
+    /// Line of the heading of `version` and its byte offset
+    raw_heading: String,
+    heading_offset: usize,
+    fence: Option<Fence>,
+    /// Number of lines read
+    line: u32,
+    /// Number of bytes read
+    read: usize,
+    done: bool,
+    warnings: Vec<Warning>,
+}
+
+impl<'s> ReleaseStream<&'s [u8]> {
+    pub fn from_markdown(src: &'s str) -> Self {
+        Self::new(src.as_bytes())
+    }
+}
+
+impl<R: BufRead> ReleaseStream<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, ParseOptions::default())
+    }
+
+    /// Like [`ReleaseStream::new`], but accepts version headings allowed by `options`
+    pub fn with_options(reader: R, options: ParseOptions) -> Self {
+        ReleaseStream {
+            reader,
+            options,
+            limits: Limits::default(),
+            version: None,
+            raw_heading: String::new(),
+            heading_offset: 0,
+            fence: None,
+            line: 0,
+            read: 0,
+            done: false,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Limits of the whole input (`max_input`) and of every release (`max_nodes`, `max_depth`)
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Malformed version headings met so far, see [`Changelog::warnings`](crate::Changelog::warnings)
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// The next line, `None` at the end of input or when `max_input` is reached
+    fn next_line(&mut self) -> Option<String> {
+        if self.done {
+            return None;
+        }
+
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(len) if len > 0 && self.read + len <= self.limits.max_input => {
+                self.read += len;
+                self.line += 1;
+                Some(line)
+            }
+            _ => {
+                self.done = true;
+                None
+            }
+        }
+    }
+
+    /// Version of the heading on the line, tracks code blocks so `## ...` in them is ignored
+    fn heading(&mut self, line: &str) -> Option<Version> {
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        let trimmed = line[indent..].trim_end();
+        if indent > 3 {
+            return None;
+        }
+
+        if let Some((c, len)) = fence(trimmed) {
+            match self.fence {
+                None => self.fence = Some((c, len)),
+                Some((open, open_len)) if open == c && len >= open_len && closes(trimmed, c) => {
+                    self.fence = None
+                }
+                Some(_) => {}
+            }
+            return None;
+        }
+        if self.fence.is_some() {
+            return None;
+        }
+
+        let level = trimmed.chars().take_while(|&c| c == '#').count();
+        let is_heading =
+            trimmed.len() == level || trimmed[level..].starts_with(char::is_whitespace);
+        if !is_heading || !self.options.heading_levels.contains(&(level as u32)) {
+            return None;
+        }
+
+        let arena = Arena::new();
+        let root = comrak::parse_document(&arena, trimmed, &ComrakOptions::default());
+        let heading = root.first_child()?;
+        // line numbers of warnings are the ones in the whole input
+        heading.data.borrow_mut().start_line = self.line;
+        parse_version(heading, &self.options, &mut self.warnings)
+    }
+}
+
+/// Fence of a code block opened or closed by the line
+fn fence(line: &str) -> Option<Fence> {
+    let c = line.chars().next().filter(|&c| c == '`' || c == '~')?;
+    let len = line.chars().take_while(|&ch| ch == c).count();
+    Some((c, len)).filter(|_| len >= 3)
+}
+
+/// Closing fences have nothing but the fence chars
+fn closes(line: &str, c: char) -> bool {
+    line.chars().all(|ch| ch == c)
+}
+
+impl<R: BufRead> Iterator for ReleaseStream<R> {
+    type Item = Release;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // skip the preamble
+        while self.version.is_none() {
+            let line = self.next_line()?;
+            self.version = self.heading(&line);
+            self.heading_offset = self.read - line.len();
+            self.raw_heading = line;
+        }
+
+        let mut raw = std::mem::take(&mut self.raw_heading);
+        let offset = self.heading_offset;
+        let mut body = String::new();
+        let next = loop {
+            match self.next_line() {
+                Some(line) => match self.heading(&line) {
+                    Some(version) => {
+                        self.heading_offset = self.read - line.len();
+                        self.raw_heading = line;
+                        break Some(version);
+                    }
+                    None => body.push_str(&line),
+                },
+                None => break None,
+            }
+        };
+        let version = std::mem::replace(&mut self.version, next)?;
+
+        let arena = Arena::new();
+        let root = self
+            .limits
+            .parse_document(&arena, &body, &ComrakOptions::default());
+        raw.push_str(&body);
+        Some(
+            Release::from_nodes_as(version, root.children(), self.options.format)
+                .with_raw(&raw, offset),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Read};
+
+    const SRC: &str = "# Changelog\n\
+                       \n\
+                       ## [Unreleased]\n\
+                       \n\
+                       ## [1.1.0] - 2021-02-01\n\
+                       \n\
+                       ### Added\n\
+                       \n\
+                       - Streaming\n\
+                       \n\
+                       ```md\n\
+                       ## 9.9.9\n\
+                       ```\n\
+                       \n\
+                       ## [1.0.0] - 2021-01-01\n\
+                       \n\
+                       ### Fixed\n\
+                       \n\
+                       - Everything\n";
+
+    #[test]
+    fn same_as_changelog() {
+        let streamed: Vec<_> = ReleaseStream::from_markdown(SRC)
+            .map(|r| r.to_string())
+            .collect();
+        let parsed: Vec<_> = crate::ChangelogBuilder::from_markdown(SRC)
+            .releases()
+            .iter()
+            .map(|r| r.to_string())
+            .collect();
+
+        assert_eq!(streamed.len(), 3);
+        assert_eq!(streamed, parsed);
+
+        let raw = |releases: Vec<Release>| {
+            releases
+                .into_iter()
+                .map(|r| (r.raw.unwrap(), r.span.unwrap()))
+                .collect::<Vec<_>>()
+        };
+        let streamed = raw(ReleaseStream::from_markdown(SRC).collect());
+        assert_eq!(
+            streamed,
+            raw(crate::ChangelogBuilder::from_markdown(SRC)
+                .releases()
+                .to_vec())
+        );
+        assert_eq!(streamed[1].0, &SRC[streamed[1].1.clone()]);
+        assert!(streamed[1].0.ends_with("## 9.9.9\n```"));
+    }
+
+    /// Reader failing the test if the last release is read
+    struct Guard<'s> {
+        src: &'s [u8],
+        end: usize,
+    }
+
+    impl Read for Guard<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            assert!(self.end < SRC.find("- Everything").unwrap(), "read too far");
+            let len = buf.len().min(self.src.len() - self.end).min(8);
+            buf[..len].copy_from_slice(&self.src[self.end..self.end + len]);
+            self.end += len;
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn lazy() {
+        let reader = BufReader::with_capacity(
+            8,
+            Guard {
+                src: SRC.as_bytes(),
+                end: 0,
+            },
+        );
+        let release = ReleaseStream::new(reader)
+            .find(|r| r.version.semver().is_some())
+            .unwrap();
+
+        assert_eq!(release.version.label(), "1.1.0");
+        assert_eq!(release.sections[0].entries, ["Streaming"]);
+    }
+
+    #[test]
+    fn warnings() {
+        let src = "## [1.0.0]\n\n## [0.9] whatever\n\n## 0.8.0\n";
+        let mut stream = ReleaseStream::from_markdown(src);
+
+        assert_eq!(stream.by_ref().count(), 2);
+        let lines: Vec<_> = stream.warnings().iter().map(|w| w.line).collect();
+        assert_eq!(lines, [3]);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{
+    reference_definitions, Changelog, ChangelogBuilder, Limits, Release, VersionParseError,
+};
+use comrak::{Arena, ComrakOptions};
+use std::{borrow::Cow, fmt};
+
+/// Why [`parse_strict`] rejected a changelog
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseError {
+    /// The input is larger than `Limits::max_input`
+    TooLarge { len: usize, max: usize },
+    /// The document has more nodes or deeper nesting than the limits allow
+    TooComplex,
+    /// Level-2 heading which looks like a version, but can't be parsed
+    Heading {
+        /// 1-based line number of the heading
+        line: u32,
+        error: VersionParseError,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::TooLarge { len, max } => write!(
+                f,
+                "changelog is too large: {} bytes, at most {} are allowed",
+                len, max
+            ),
+            ParseError::TooComplex => f.write_str("changelog is nested too deep or is too long"),
+            ParseError::Heading { line, error } => {
+                write!(f, "malformed version heading on line {}: {}", line, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Heading { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Parses all releases of a strict keepachangelog changelog with their links.
+///
+/// Unlike the lenient parsers, which cut the input and skip what they can't parse, this fails on
+/// the first problem: input exceeding `limits` or a malformed version heading.
+pub fn parse_strict(src: &str, limits: &Limits) -> Result<Vec<Release>, ParseError> {
+    if src.len() > limits.max_input {
+        return Err(ParseError::TooLarge {
+            len: src.len(),
+            max: limits.max_input,
+        });
+    }
+
+    // too deep nesting is rejected before parsing, comrak is slow on it
+    if let Cow::Owned(_) = limits.cap_nesting(src) {
+        return Err(ParseError::TooComplex);
+    }
+    let arena = Arena::new();
+    let root = comrak::parse_document(&arena, src, &ComrakOptions::default());
+    if limits.prune(root) {
+        return Err(ParseError::TooComplex);
+    }
+
+    let mut changelog = Changelog::new(root.children());
+    let releases: Vec<_> = changelog
+        .by_ref()
+        .map(|(version, nodes)| Release::from_nodes(version, nodes))
+        .collect();
+    if let Some(warning) = changelog.warnings.drain(..).next() {
+        return Err(ParseError::Heading {
+            line: warning.line,
+            error: warning.error,
+        });
+    }
+
+    Ok(releases
+        .into_iter()
+        .fold(ChangelogBuilder::new(), ChangelogBuilder::release)
+        .resolve_links(&reference_definitions(src))
+        .releases()
+        .to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors() {
+        let limits = Limits::default();
+        assert!(matches!(
+            parse_strict("## [1.0.0]\n\n## [0.9] whatever\n", &limits),
+            Err(ParseError::Heading {
+                line: 3,
+                error: VersionParseError::Format { .. },
+            })
+        ));
+        assert!(parse_strict("## [0.9] whatever\n", &limits)
+            .unwrap_err()
+            .to_string()
+            .starts_with("malformed version heading on line 1: "));
+
+        let limits = Limits {
+            max_input: 4,
+            ..Limits::default()
+        };
+        assert_eq!(
+            parse_strict("## [1.0.0]\n", &limits).unwrap_err(),
+            ParseError::TooLarge { len: 11, max: 4 }
+        );
+
+        let limits = Limits {
+            max_depth: 2,
+            ..Limits::default()
+        };
+        assert_eq!(
+            parse_strict("## [1.0.0]\n\n- > - deep\n", &limits).unwrap_err(),
+            ParseError::TooComplex
+        );
+        let deep = format!("## [1.0.0]\n\n{}deep\n", "> ".repeat(100_000));
+        assert_eq!(
+            parse_strict(&deep, &Limits::default()).unwrap_err(),
+            ParseError::TooComplex
+        );
+    }
+
+    #[test]
+    fn releases() {
+        let src = "# Changelog\n\n## [Unreleased]\n\n## [1.0.0] - 2021-01-01\n\n### Added\n\n- Everything\n\n\
+                   [1.0.0]: https://example.com/v1.0.0\n";
+        let releases = parse_strict(src, &Limits::default()).unwrap();
+
+        assert_eq!(releases.len(), 2);
+        assert_eq!(releases[1].sections[0].entries, ["Everything"]);
+        assert_eq!(
+            releases[1].link.as_deref(),
+            Some("https://example.com/v1.0.0")
+        );
+    }
+}
@@ -1,35 +1,106 @@
-use crate::{date::Date, IO_VEC_ERR};
+use crate::{
+    date::Date,
+    options::{DateFormat, DateStyle, ParseOptions},
+    scheme::VersionScheme,
+};
 use comrak::nodes::{AstNode, NodeHeading, NodeValue};
-use itertools::Itertools;
-use std::convert::TryFrom;
+use std::{cmp::Ordering, convert::TryFrom, fmt};
 use versions::SemVer;
 
 #[derive(Debug, Clone)]
 pub enum Version {
     Unreleased,
-    Released(SemVer, Option<Date>),
+    /// Version, release date and whether the release is marked as `[YANKED]`
+    Released(SemVer, Option<Date>, bool),
+    /// Release with a version which isn't semver, like `2021.06.01`. Only parsed with another
+    /// [`ParseOptions::version_scheme`] than semver.
+    Other(String, Option<Date>, bool),
 }
 
 impl Version {
+    /// Semver version and date of a release
     pub fn into_released(self) -> Option<(SemVer, Option<Date>)> {
         match self {
+            Version::Released(v, d, _) => Some((v, d)),
+            Version::Unreleased | Version::Other(..) => None,
+        }
+    }
+
+    pub fn semver(&self) -> Option<&SemVer> {
+        match self {
+            Version::Released(v, _, _) => Some(v),
+            Version::Unreleased | Version::Other(..) => None,
+        }
+    }
+
+    /// Release date, if the heading has one
+    pub fn date(&self) -> Option<Date> {
+        match self {
+            Version::Released(_, date, _) | Version::Other(_, date, _) => *date,
             Version::Unreleased => None,
-            Version::Released(v, d) => Some((v, d)),
         }
     }
+
+    /// `true` if the heading is marked with `[YANKED]`
+    pub fn is_yanked(&self) -> bool {
+        matches!(
+            self,
+            Version::Released(_, _, true) | Version::Other(_, _, true)
+        )
+    }
+
+    /// Label of the version in reference links: `Unreleased` or the version itself
+    pub fn label(&self) -> String {
+        match self {
+            Version::Unreleased => String::from("Unreleased"),
+            Version::Released(v, _, _) => v.to_string(),
+            Version::Other(v, _, _) => v.clone(),
+        }
+    }
+
+    /// `true` if this is a released version in the `(from, to]` range
+    pub fn is_between(&self, from: &SemVer, to: &SemVer) -> bool {
+        self.semver().map_or(false, |v| from < v && v <= to)
+    }
+
+    /// `true` if this is the release of `version` compared by `scheme`
+    pub fn is_version(&self, scheme: VersionScheme, version: &str) -> bool {
+        match self {
+            Version::Unreleased => false,
+            _ => scheme.compare(&self.label(), version) == Some(Ordering::Equal),
+        }
+    }
+
+    /// `true` if this is a released version in the `(from, to]` range compared by `scheme`
+    pub fn is_between_in(&self, scheme: VersionScheme, from: &str, to: &str) -> bool {
+        if matches!(self, Version::Unreleased) {
+            return false;
+        }
+        let label = self.label();
+        scheme.compare(from, &label) == Some(Ordering::Less)
+            && scheme
+                .compare(&label, to)
+                .map_or(false, |ordering| ordering != Ordering::Greater)
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum VersionParseError {
-    /// Block has to be header of 2nd level:
+    /// Block has to be header of one of the configured levels (2nd by default):
     /// - `## ...`
     Header,
-    /// Header contents must be a single AST node
+    /// Header must not be empty
     SingleSpan,
     /// Header contents have to match one of following (case-insensitive):
     /// - `[\[] "unreleased" [\]]`
-    /// - `[\[] semver::Version [\]] [ "-" chrono::NaiveDate ]`
-    Format(nom::Err<nom::error::Error<String>>),
+    /// - `[\[] semver::Version [\]] [ "-" chrono::NaiveDate ] [ "[YANKED]" ]`
+    ///
+    /// (the date format and `v` prefix can be configured via [`ParseOptions`])
+    Format {
+        /// The part of the heading which couldn't be parsed, empty if the heading ended too early
+        rest: String,
+    },
     /// For `&[u8] -> &str` conversions
     Utf8(std::str::Utf8Error),
 }
@@ -41,17 +112,11 @@ where
     fn from(err: nom::Err<nom::error::Error<S>>) -> Self {
         use nom::{error::Error, Err::*};
 
-        VersionParseError::Format(match err {
-            Incomplete(needed) => Incomplete(needed),
-            Error(Error { input, code }) => Error(Error {
-                input: input.into(),
-                code,
-            }),
-            Failure(Error { input, code }) => Failure(Error {
-                input: input.into(),
-                code,
-            }),
-        })
+        let rest = match err {
+            Incomplete(_) => String::new(),
+            Error(Error { input, .. }) | Failure(Error { input, .. }) => input.into(),
+        };
+        VersionParseError::Format { rest }
     }
 }
 
@@ -61,6 +126,33 @@ impl From<std::str::Utf8Error> for VersionParseError {
     }
 }
 
+impl fmt::Display for VersionParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionParseError::Header => f.write_str("not a version heading"),
+            VersionParseError::SingleSpan => f.write_str("empty heading"),
+            VersionParseError::Format { rest } if rest.trim().is_empty() => f.write_str(
+                "incomplete version heading, expected `[Unreleased]` or `[x.y.z] - YYYY-MM-DD`",
+            ),
+            VersionParseError::Format { rest } => write!(
+                f,
+                "unexpected `{}` in version heading, expected `[Unreleased]` or `[x.y.z] - YYYY-MM-DD`",
+                rest.trim()
+            ),
+            VersionParseError::Utf8(err) => write!(f, "heading isn't valid utf-8: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for VersionParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VersionParseError::Utf8(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
 fn between<I, O, V, LO, L, RO, R>(left: L, value: V, right: R, i: I) -> nom::IResult<I, O>
 where
     L: FnOnce(I) -> nom::IResult<I, LO>,
@@ -73,27 +165,68 @@ where
     Ok((i, v))
 }
 
+/// Collects text of the heading from its text and code nodes, formatting is dropped. Links
+/// (`[x.y.z]` becomes a link if there is a matching reference definition) are replaced by their
+/// bracketed text.
+fn heading_text<'a>(node: &'a AstNode<'a>) -> Result<String, VersionParseError> {
+    fn collect<'a>(node: &'a AstNode<'a>, out: &mut Vec<u8>) {
+        match &node.data.borrow().value {
+            NodeValue::Text(text) => out.extend_from_slice(text),
+            NodeValue::Code(code) => out.extend_from_slice(&code.literal),
+            NodeValue::SoftBreak | NodeValue::LineBreak => out.push(b' '),
+            NodeValue::Link(_) => {
+                out.push(b'[');
+                for child in node.children() {
+                    collect(child, out);
+                }
+                out.push(b']');
+                return;
+            }
+            _ => {}
+        }
+        for child in node.children() {
+            collect(child, out);
+        }
+    }
+
+    if node.first_child().is_none() {
+        return Err(VersionParseError::SingleSpan);
+    }
+
+    let mut s = Vec::new();
+    for child in node.children() {
+        collect(child, &mut s);
+    }
+
+    Ok(String::from_utf8(s).map_err(|e| e.utf8_error())?)
+}
+
 impl<'a> TryFrom<&'a AstNode<'a>> for Version {
     type Error = VersionParseError;
 
+    /// Parses strict keepachangelog heading
     fn try_from(node: &'a AstNode<'a>) -> Result<Self, Self::Error> {
-        use nom::{named, opt};
+        Version::parse_heading(node, &ParseOptions::default())
+    }
+}
 
+impl Version {
+    /// Parses version heading accepting variants allowed by `options`
+    pub fn parse_heading<'a>(
+        node: &'a AstNode<'a>,
+        options: &ParseOptions,
+    ) -> Result<Self, VersionParseError> {
         let data = match node.data.borrow().value {
-            NodeValue::Heading(NodeHeading { level: 2, .. }) => node
-                .children()
-                .exactly_one()
-                .map_err(|_| VersionParseError::SingleSpan)?,
+            NodeValue::Heading(NodeHeading { level, .. })
+                if options.heading_levels.contains(&level) =>
+            {
+                heading_text(node)?
+            }
             _ => return Err(VersionParseError::Header),
         };
-        let data = {
-            let mut s = Vec::new();
-            comrak::format_html(data, &comrak::ComrakOptions::default(), &mut s).expect(IO_VEC_ERR);
-            String::from_utf8(s).map_err(|e| e.utf8_error())?
-        };
 
         fn parse_unreleased(i: &str) -> nom::IResult<&[u8], ()> {
-            use nom::{character::complete::char, tag_no_case};
+            use nom::{character::complete::char, named, tag_no_case};
 
             named!(unreleased, tag_no_case!("unreleased"));
 
@@ -103,11 +236,22 @@ impl<'a> TryFrom<&'a AstNode<'a>> for Version {
             Ok((i, ()))
         }
 
-        fn parse_released(i: &str) -> nom::IResult<&str, SemVer> {
-            let (i, version) = SemVer::parse(i).or_else(|_| {
+        fn parse_semver(i: &str, allow_v_prefix: bool) -> nom::IResult<&str, SemVer> {
+            use nom::character::complete::one_of;
+
+            let i = if allow_v_prefix {
+                one_of::<_, _, nom::error::Error<&str>>("vV")(i).map_or(i, |(i, _)| i)
+            } else {
+                i
+            };
+            SemVer::parse(i)
+        }
+
+        fn parse_released(i: &str, allow_v_prefix: bool) -> nom::IResult<&str, SemVer> {
+            let (i, version) = parse_semver(i, allow_v_prefix).or_else(|_| {
                 between(
                     nom::character::complete::char('['),
-                    SemVer::parse,
+                    |i| parse_semver(i, allow_v_prefix),
                     nom::character::complete::char(']'),
                     i,
                 )
@@ -116,26 +260,95 @@ impl<'a> TryFrom<&'a AstNode<'a>> for Version {
             Ok((i, version))
         }
 
-        fn parse_date(i: &str) -> nom::IResult<&str, Date> {
+        fn parse_date(i: &str, format: DateFormat, style: DateStyle) -> nom::IResult<&str, Date> {
             use nom::character::complete::{char, space0};
 
+            let date: fn(&str) -> nom::IResult<&str, Date> = match style {
+                DateStyle::Strict => Date::parse,
+                DateStyle::Lenient => Date::parse_lenient,
+            };
             let (i, _) = space0(i)?;
-            let (i, _) = char('-')(i)?;
+            match format {
+                DateFormat::Dash | DateFormat::Slash => {
+                    let sep = if format == DateFormat::Dash { '-' } else { '/' };
+                    let (i, _) = char(sep)(i)?;
+                    let (i, _) = space0(i)?;
+                    date(i)
+                }
+                DateFormat::Parenthesized => between(char('('), date, char(')'), i),
+            }
+        }
+
+        fn parse_date_opt<'i>(
+            i: &'i str,
+            formats: &[DateFormat],
+            style: DateStyle,
+        ) -> nom::IResult<&'i str, Option<Date>> {
+            Ok(formats
+                .iter()
+                .find_map(|&format| parse_date(i, format, style).ok())
+                .map_or((i, None), |(i, date)| (i, Some(date))))
+        }
+
+        fn parse_yanked(i: &str) -> nom::IResult<&str, bool> {
+            use nom::{
+                branch::alt, bytes::complete::tag_no_case, character::complete::space0,
+                combinator::opt,
+            };
+
             let (i, _) = space0(i)?;
-            let (i, date) = Date::parse(i)?;
+            let (i, yanked) = opt(alt((tag_no_case("[yanked]"), tag_no_case("yanked"))))(i)?;
 
-            Ok((i, date))
+            Ok((i, yanked.is_some()))
         }
 
-        named!(parse_date_opt<&str, Option<Date>>, opt!(parse_date));
+        /// Version of another scheme, up to a space, `]` or `(`
+        fn parse_other(
+            i: &str,
+            allow_v_prefix: bool,
+            scheme: VersionScheme,
+        ) -> Option<(&str, String)> {
+            let (bracketed, i) = match i.strip_prefix('[') {
+                Some(i) => (true, i),
+                None => (false, i),
+            };
+            let end = i
+                .find(|c: char| c.is_whitespace() || c == ']' || c == '(')
+                .unwrap_or(i.len());
+            let (version, mut i) = i.split_at(end);
+            if bracketed {
+                i = i.strip_prefix(']')?;
+            }
+            let version = match version.strip_prefix(&['v', 'V'][..]) {
+                Some(version) if allow_v_prefix => version,
+                _ => version,
+            };
+
+            if version.is_empty() || !scheme.accepts(version) {
+                return None;
+            }
+            Some((i, version.to_owned()))
+        }
 
         if let Ok((_, ())) = parse_unreleased(&data) {
             return Ok(Version::Unreleased);
         }
 
-        let (data, version) = parse_released(&data)?;
-        let (_, opt_date) = parse_date_opt(data)?;
+        // semver versions are parsed as such by any scheme
+        let (data, version) = match parse_released(&data, options.allow_v_prefix) {
+            Ok((data, version)) => (data, Ok(version)),
+            Err(err) if options.version_scheme == VersionScheme::Semver => return Err(err.into()),
+            Err(err) => match parse_other(&data, options.allow_v_prefix, options.version_scheme) {
+                Some((data, version)) => (data, Err(version)),
+                None => return Err(err.into()),
+            },
+        };
+        let (data, opt_date) = parse_date_opt(data, &options.date_formats, options.date_style)?;
+        let (_, yanked) = parse_yanked(data)?;
 
-        Ok(Version::Released(version, opt_date))
+        Ok(match version {
+            Ok(version) => Version::Released(version, opt_date, yanked),
+            Err(version) => Version::Other(version, opt_date, yanked),
+        })
     }
 }
@@ -0,0 +1,104 @@
+//! JS bindings (the `wasm` feature): functions take markdown of a changelog and return JSON.
+//!
+//! A release is `{"version": "1.2.0", "date": "2021-06-01", "yanked": false, "link": null,
+//! "sections": [{"name": "Added", "entries": ["..."]}]}`, `version` is `"Unreleased"` and `date`
+//! is `null` for the unreleased section.
+use crate::{
+    reference_definitions, validate as lint, Changelog, ChangelogBuilder, Limits, Release, Warning,
+};
+use comrak::{Arena, ComrakOptions};
+use serde_json::{json, Value};
+use wasm_bindgen::prelude::*;
+
+fn release_json(release: &Release) -> Value {
+    let date = release.version.date().map(|date| date.to_string());
+    let sections: Vec<Value> = release
+        .sections
+        .iter()
+        .map(|section| json!({ "name": section.name, "entries": section.entries }))
+        .collect();
+
+    json!({
+        "version": release.version.label(),
+        "date": date,
+        "yanked": release.version.is_yanked(),
+        "link": release.link,
+        "sections": sections,
+    })
+}
+
+fn warning_json(warning: &Warning) -> Value {
+    json!({ "line": warning.line, "message": warning.error.to_string() })
+}
+
+fn parse_json(src: &str) -> Value {
+    let arena = Arena::new();
+    let root = Limits::default().parse_document(&arena, src, &ComrakOptions::default());
+    let mut changelog = Changelog::new(root.children());
+    let releases = changelog
+        .by_ref()
+        .map(|(version, nodes)| Release::from_nodes(version, nodes))
+        .fold(ChangelogBuilder::new(), ChangelogBuilder::release)
+        .resolve_links(&reference_definitions(src));
+
+    json!({
+        "releases": releases.releases().iter().map(release_json).collect::<Vec<_>>(),
+        "warnings": changelog.warnings().iter().map(warning_json).collect::<Vec<_>>(),
+    })
+}
+
+/// All releases, the newest first, and malformed version headings:
+/// `{"releases": [...], "warnings": [{"line": 3, "message": "..."}]}`
+#[wasm_bindgen]
+pub fn parse(src: &str) -> String {
+    parse_json(src).to_string()
+}
+
+/// Array of releases like [`parse`], throws on a malformed version heading or input exceeding
+/// the default [`Limits`]
+#[wasm_bindgen(js_name = parseStrict)]
+pub fn parse_strict(src: &str) -> Result<String, JsValue> {
+    let releases = crate::parse_strict(src, &Limits::default())
+        .map_err(|err| JsValue::from_str(&err.to_string()))?;
+    Ok(Value::from(releases.iter().map(release_json).collect::<Vec<_>>()).to_string())
+}
+
+/// Array of messages about problems of the changelog, see [`validate`](crate::validate)
+#[wasm_bindgen]
+pub fn validate(src: &str) -> String {
+    let lints: Vec<String> = lint(src).iter().map(ToString::to_string).collect();
+    Value::from(lints).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json() {
+        let src = "# Changelog\n\n## [Unreleased]\n\n## [1.0.0] - 2021-01-01\n\n### Added\n\n- Everything\n\n\
+                   ## [0.9] whatever\n\n[1.0.0]: https://example.com/v1.0.0\n";
+
+        let parsed = parse_json(src);
+        assert_eq!(
+            parsed["releases"],
+            json!([
+                {
+                    "version": "Unreleased",
+                    "date": null,
+                    "yanked": false,
+                    "link": null,
+                    "sections": [],
+                },
+                {
+                    "version": "1.0.0",
+                    "date": "2021-01-01",
+                    "yanked": false,
+                    "link": "https://example.com/v1.0.0",
+                    "sections": [{ "name": "Added", "entries": ["Everything"] }],
+                },
+            ])
+        );
+        assert_eq!(parsed["warnings"][0]["line"], 11);
+    }
+}
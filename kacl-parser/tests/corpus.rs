@@ -0,0 +1,102 @@
+//! Runs the parser over changelogs in `tests/corpus`: samples in the formats of popular crates
+//! (keepachangelog, tokio, clap, rand, ...) and tricky documents. Nothing may panic, and emitting
+//! a parsed changelog must be stable: parsing the output gives the same output again.
+use kacl_parser::{
+    diff, parse_strict, validate, ChangelogBuilder, Limits, ParseOptions, ReleaseStream,
+};
+use std::{fs, path::Path};
+
+fn corpus() -> Vec<(String, String)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut files: Vec<_> = fs::read_dir(dir)
+        .expect("corpus directory exists")
+        .map(|entry| entry.expect("corpus is readable").path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "md"))
+        .map(|path| {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            (
+                name,
+                fs::read_to_string(&path).expect("corpus files are utf-8"),
+            )
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Checks `src` parsed with `options`, returns the number of releases
+fn check(name: &str, src: &str, options: ParseOptions) -> usize {
+    let parsed = ChangelogBuilder::from_markdown_with(src, options);
+    let emitted = parsed.build();
+    let reparsed = ChangelogBuilder::from_markdown(&emitted);
+
+    assert_eq!(reparsed.build(), emitted, "{}: emitting isn't stable", name);
+    let labels = |c: &ChangelogBuilder| -> Vec<String> {
+        c.releases().iter().map(|r| r.version.label()).collect()
+    };
+    assert_eq!(
+        labels(&reparsed),
+        labels(&parsed),
+        "{}: releases changed",
+        name
+    );
+    assert!(
+        diff(&parsed, &reparsed).is_empty(),
+        "{}: diff isn't empty",
+        name
+    );
+
+    parsed.releases().len()
+}
+
+#[test]
+fn corpus_round_trip() {
+    let corpus = corpus();
+    assert!(corpus.len() >= 5, "corpus is missing");
+
+    for (name, src) in &corpus {
+        let strict = check(name, src, ParseOptions::default());
+        let tolerant = check(name, src, ParseOptions::tolerant());
+        assert!(tolerant >= strict, "{}: tolerant parsing found less", name);
+        check(name, src, ParseOptions::detect(src));
+
+        validate(src);
+        let _ = parse_strict(src, &Limits::default());
+        ReleaseStream::from_markdown(src).count();
+    }
+}
+
+#[test]
+fn corpus_releases() {
+    let corpus = corpus();
+    let releases = |name: &str, options: ParseOptions| {
+        let (_, src) = corpus.iter().find(|(n, _)| n == name).unwrap();
+        ChangelogBuilder::from_markdown_with(src, options)
+            .releases()
+            .iter()
+            .map(|r| r.version.label())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        releases("keepachangelog.md", ParseOptions::default()),
+        ["Unreleased", "1.1.0", "1.0.0", "0.3.0"]
+    );
+    assert_eq!(
+        releases("rand-style.md", ParseOptions::default()),
+        ["0.8.4", "0.8.3", "0.8.2", "0.8.1"]
+    );
+    // `# 1.9.0 (July 22, 2021)` has a date in an unsupported format
+    assert!(releases("tokio-style.md", ParseOptions::default()).is_empty());
+    // `### [2.0.1]` is a patch release of standard-version
+    let (_, src) = corpus
+        .iter()
+        .find(|(n, _)| n == "conventional-style.md")
+        .unwrap();
+    assert_eq!(
+        releases("conventional-style.md", ParseOptions::detect(src)),
+        ["2.1.0", "2.0.1", "2.0.0"]
+    );
+    // the fenced `## [9.9.9]` isn't a release
+    assert!(!releases("tricky.md", ParseOptions::tolerant()).contains(&String::from("9.9.9")));
+}
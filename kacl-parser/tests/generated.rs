@@ -0,0 +1,81 @@
+//! Property tests on generated changelogs: parsing never panics, and emitting is stable for
+//! documents made of valid releases
+use kacl_parser::{ChangelogBuilder, Limits, ParseOptions, ReleaseStream};
+use proptest::prelude::*;
+
+fn heading() -> impl Strategy<Value = String> {
+    let version = (
+        0u8..3,
+        0u8..20,
+        0u8..20,
+        prop::option::of("(alpha|beta|rc)\\.[0-9]"),
+    )
+        .prop_map(|(major, minor, patch, pre)| match pre {
+            Some(pre) => format!("{}.{}.{}-{}", major, minor, patch, pre),
+            None => format!("{}.{}.{}", major, minor, patch),
+        });
+    let date = (2000u16..2030, 1u8..13, 1u8..29)
+        .prop_map(|(y, m, d)| format!("{:04}-{:02}-{:02}", y, m, d));
+
+    prop_oneof![
+        Just(String::from("## [Unreleased]")),
+        (version.clone(), date).prop_map(|(v, d)| format!("## [{}] - {}", v, d)),
+        version.prop_map(|v| format!("## [{}]", v)),
+    ]
+}
+
+fn section() -> impl Strategy<Value = String> {
+    let name = prop_oneof![
+        Just("Added"),
+        Just("Changed"),
+        Just("Deprecated"),
+        Just("Removed"),
+        Just("Fixed"),
+        Just("Security"),
+        Just("Internal"),
+    ];
+    let entries = prop::collection::vec("[a-z][a-z `_]{0,30}[a-z]", 1..4);
+
+    (name, entries).prop_map(|(name, entries)| {
+        let entries: Vec<String> = entries.iter().map(|e| format!("- {}", e)).collect();
+        format!("### {}\n\n{}", name, entries.join("\n"))
+    })
+}
+
+fn release() -> impl Strategy<Value = String> {
+    (heading(), prop::collection::vec(section(), 0..4))
+        .prop_map(|(heading, sections)| format!("{}\n\n{}", heading, sections.join("\n\n")))
+}
+
+fn changelog() -> impl Strategy<Value = String> {
+    prop::collection::vec(release(), 0..6)
+        .prop_map(|releases| format!("# Changelog\n\n{}\n", releases.join("\n\n")))
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_input_doesnt_panic(src in "(#{1,3} |- |\\[|\\]| - |```|\n|[0-9.]{1,6}|[a-zA-Z ]{1,8})*") {
+        ChangelogBuilder::from_markdown(&src).build();
+        ChangelogBuilder::from_markdown_with(&src, ParseOptions::tolerant()).build();
+        ReleaseStream::from_markdown(&src).count();
+        let _ = kacl_parser::parse_strict(&src, &Limits::default());
+        kacl_parser::validate(&src);
+    }
+
+    #[test]
+    fn emitting_is_stable(src in changelog()) {
+        let emitted = ChangelogBuilder::from_markdown(&src).build();
+        prop_assert_eq!(ChangelogBuilder::from_markdown(&emitted).build(), emitted);
+    }
+
+    #[test]
+    fn stream_matches_builder(src in changelog()) {
+        let streamed: Vec<_> = ReleaseStream::from_markdown(&src).map(|r| r.to_string()).collect();
+        let parsed: Vec<_> = ChangelogBuilder::from_markdown(&src)
+            .releases()
+            .iter()
+            .map(|r| r.to_string())
+            .collect();
+        prop_assert_eq!(streamed, parsed);
+    }
+}
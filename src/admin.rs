@@ -0,0 +1,90 @@
+//! Commands of the bot operators: `/admin stats|broadcast|ban|unban|reload`
+use crate::{
+    cfg::{Config, SharedConfig},
+    db::Database,
+    metrics,
+    notifier::Notifier,
+    render::escape,
+    send::SendQueue,
+};
+
+/// Number of crates in the stats
+const TOP_CRATES: i64 = 10;
+
+const USAGE: &str = "Usage: <code>/admin stats</code>, <code>/admin broadcast &lt;text&gt;</code>, \
+                     <code>/admin ban &lt;chat_id&gt;</code>, <code>/admin unban &lt;chat_id&gt;</code>, \
+                     <code>/admin reload</code>";
+
+/// `true` if the user is listed in `admins` of the config
+pub fn is_admin(cfg: &Config, user_id: i64) -> bool {
+    cfg.admins.contains(&user_id)
+}
+
+async fn stats(db: &Database) -> Result<String, tokio_postgres::Error> {
+    let (subscriptions, chats) = db.count_subscriptions().await?;
+    let top: Vec<String> = db
+        .top_crates(TOP_CRATES)
+        .await?
+        .into_iter()
+        .map(|(krate, subscribers)| format!("— <code>{}</code>: {}", krate, subscribers))
+        .collect();
+
+    let uptime = metrics::STARTED.elapsed();
+    let sent = metrics::NOTIFICATIONS_SENT.get();
+    let hours = (uptime.as_secs_f64() / 3600.0).max(1.0 / 60.0);
+    Ok(format!(
+        "Subscriptions: {} in {} chats\n\
+         \n\
+         Top crates:\n{}\n\
+         \n\
+         Messages since the start ({}h ago): {} sent ({:.1} per hour), {} failed, {} queued",
+        subscriptions,
+        chats,
+        top.join("\n"),
+        uptime.as_secs() / 3600,
+        sent,
+        sent as f64 / hours,
+        metrics::NOTIFICATIONS_FAILED.get(),
+        metrics::SEND_QUEUE_DEPTH.get(),
+    ))
+}
+
+/// Executes the command, `rest` is the text after `/admin`. Returns the reply.
+pub async fn run(
+    db: &Database,
+    queue: &SendQueue,
+    cfg: &SharedConfig,
+    rest: &str,
+) -> Result<String, tokio_postgres::Error> {
+    let (subcommand, arg) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let arg = arg.trim();
+
+    let reply = match (subcommand, arg.parse::<i64>()) {
+        ("stats", _) => stats(db).await?,
+        ("broadcast", _) if !arg.is_empty() => {
+            let chats = db.list_chats().await?;
+            for &chat_id in &chats {
+                queue.push(chat_id, escape(arg), false);
+            }
+            format!("The message is queued for {} chats.", chats.len())
+        }
+        ("ban", Ok(chat_id)) => {
+            db.set_banned(chat_id, true).await?;
+            format!(
+                "Chat <code>{}</code> is banned: its commands are ignored and it isn't notified.",
+                chat_id
+            )
+        }
+        ("unban", Ok(chat_id)) => {
+            db.set_banned(chat_id, false).await?;
+            format!("Chat <code>{}</code> is unbanned.", chat_id)
+        }
+        ("reload", _) => match cfg.reload() {
+            Ok(()) => String::from("The config is reloaded."),
+            Err(err) => format!("The config isn't reloaded: {}.", escape(&err.to_string())),
+        },
+        _ => String::from(USAGE),
+    };
+
+    Ok(reply)
+}
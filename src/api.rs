@@ -0,0 +1,214 @@
+//! JSON api of the http server, for dashboards and scripts. Served if `[api]` is enabled in the
+//! config. Requests carry a chat's token (`/api_token` in the bot) as `Authorization: Bearer {token}`
+//! and act on behalf of that chat:
+//!
+//! - `GET /api/crates/{crate}/releases` — releases of the crate seen by the bot, the newest first
+//! - `GET /api/subscriptions` — subscriptions with their filters, like `/export`
+//! - `POST /api/subscriptions` — subscribes to `{"name": "serde", "filter": ["minor"]}`
+//! - `DELETE /api/subscriptions/{crate}` — unsubscribes
+//! - `GET /api/events?since={unix time}` — releases of subscribed crates published after the time,
+//!   the oldest first, at most [`LIMIT`] of them (pass the last `timestamp` to get the next ones)
+use hyper::{
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    Body, Method, Request, Response, StatusCode,
+};
+use tokio_postgres::Error;
+
+use crate::{
+    cfg::Config,
+    db::{Database, FeedEntry},
+    watchlist::{Entry, Watchlist},
+};
+
+/// Most releases returned at once
+pub const LIMIT: i64 = 100;
+
+/// Largest accepted request body
+const MAX_BODY: usize = 16 * 1024;
+
+#[derive(serde::Serialize)]
+struct Release<'a> {
+    #[serde(rename = "crate")]
+    krate: &'a str,
+    version: &'a str,
+    yanked: bool,
+    /// RFC 3339 time, UTC
+    published_at: &'a str,
+    /// Release notes as telegram html
+    notes_html: Option<&'a str>,
+    /// Unix time of the publish, for `/api/events?since=`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<i64>,
+}
+
+impl<'a> Release<'a> {
+    fn new(entry: &'a FeedEntry, timestamp: Option<i64>) -> Self {
+        Release {
+            krate: &entry.krate,
+            version: &entry.version,
+            yanked: entry.yanked,
+            published_at: &entry.published_at,
+            notes_html: entry.notes.as_deref(),
+            timestamp,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ErrorBody<'a> {
+    error: &'a str,
+}
+
+fn json(status: StatusCode, body: &impl serde::Serialize) -> Response<Body> {
+    let mut response = Response::new(Body::from(serde_json::to_vec(body).unwrap_or_default()));
+    *response.status_mut() = status;
+    if let Ok(content_type) = "application/json".parse() {
+        response.headers_mut().insert(CONTENT_TYPE, content_type);
+    }
+    response
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    json(status, &ErrorBody { error: message })
+}
+
+/// Token from the `Authorization: Bearer {token}` header
+fn token(request: &Request<Body>) -> Option<&str> {
+    request
+        .headers()
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+/// `since` parameter of the query, 0 if there is none and `None` if it isn't a number
+fn since(query: Option<&str>) -> Option<i64> {
+    let param = query
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|param| param.strip_prefix("since="));
+    match param {
+        Some(since) => since.parse().ok(),
+        None => Some(0),
+    }
+}
+
+async fn route(
+    request: Request<Body>,
+    db: &Database,
+    cfg: &Config,
+) -> Result<Response<Body>, Error> {
+    let chat_id = match token(&request) {
+        Some(token) => db.api_user(token).await?,
+        None => None,
+    };
+    let chat_id = match chat_id {
+        Some(chat_id) => chat_id,
+        None => return Ok(error(StatusCode::UNAUTHORIZED, "missing or unknown token")),
+    };
+
+    let method = request.method().clone();
+    let path = request.uri().path().to_owned();
+    let query = request.uri().query().map(str::to_owned);
+    let segments: Vec<&str> = path
+        .trim_start_matches("/api/")
+        .trim_end_matches('/')
+        .split('/')
+        .collect();
+    let response = match (&method, &segments[..]) {
+        (&Method::GET, ["crates", krate, "releases"]) => {
+            let entries = db.crate_feed(krate, LIMIT).await?;
+            let releases: Vec<_> = entries
+                .iter()
+                .map(|entry| Release::new(entry, None))
+                .collect();
+            json(StatusCode::OK, &releases)
+        }
+        (&Method::GET, ["subscriptions"]) => {
+            json(StatusCode::OK, &Watchlist::of_chat(db, chat_id).await?)
+        }
+        (&Method::POST, ["subscriptions"]) => {
+            let body = match hyper::body::to_bytes(request.into_body()).await {
+                Ok(body) if body.len() <= MAX_BODY => body,
+                Ok(_) => {
+                    return Ok(error(
+                        StatusCode::PAYLOAD_TOO_LARGE,
+                        "the body is too large",
+                    ))
+                }
+                Err(_) => return Ok(error(StatusCode::BAD_REQUEST, "couldn't read the body")),
+            };
+            let entry: Entry = match serde_json::from_slice(&body) {
+                Ok(entry) => entry,
+                Err(_) => {
+                    return Ok(error(
+                        StatusCode::BAD_REQUEST,
+                        "expected {\"name\": \"crate\", \"filter\": [...]}",
+                    ))
+                }
+            };
+            let watchlist = Watchlist {
+                crates: vec![entry],
+            };
+            let (_, unknown) = watchlist.import(db, cfg, chat_id).await?;
+            if unknown.is_empty() {
+                json(StatusCode::CREATED, &watchlist.crates[0])
+            } else {
+                error(StatusCode::NOT_FOUND, "there is no such crate")
+            }
+        }
+        (&Method::DELETE, ["subscriptions", krate]) => {
+            db.unsubscribe(chat_id, krate).await?;
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::NO_CONTENT;
+            response
+        }
+        (&Method::GET, ["events"]) => match since(query.as_deref()) {
+            Some(since) => {
+                let events = db.user_events(chat_id, since, LIMIT).await?;
+                let releases: Vec<_> = events
+                    .iter()
+                    .map(|(entry, timestamp)| Release::new(entry, Some(*timestamp)))
+                    .collect();
+                json(StatusCode::OK, &releases)
+            }
+            None => error(StatusCode::BAD_REQUEST, "`since` must be a unix time"),
+        },
+        _ => error(StatusCode::NOT_FOUND, "no such endpoint"),
+    };
+
+    Ok(response)
+}
+
+/// Handles a request to `/api/...`
+pub async fn handle(request: Request<Body>, db: &Database, cfg: &Config) -> Response<Body> {
+    match route(request, db, cfg).await {
+        Ok(response) => response,
+        Err(err) => {
+            tracing::error!("db error while handling an api request: {}", err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "internal error")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params() {
+        assert_eq!(since(None), Some(0));
+        assert_eq!(since(Some("since=1622505600")), Some(1_622_505_600));
+        assert_eq!(since(Some("x=1&since=5")), Some(5));
+        assert_eq!(since(Some("since=yesterday")), None);
+
+        let request = Request::builder()
+            .header(AUTHORIZATION, "Bearer abc")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(token(&request), Some("abc"));
+        assert_eq!(token(&Request::new(Body::empty())), None);
+    }
+}
@@ -0,0 +1,322 @@
+//! Bootstrap of the index entries known to the bot (`seen_versions`) on the first run, so rewrites
+//! of old entries (e.g. metadata fixes) aren't announced as new versions by a new deployment. They
+//! are seeded from a crates.io database dump or a snapshot of the index (e.g. a shallow clone)
+//! instead of walking a full clone. The progress is saved with each batch of entries, so an
+//! interrupted bootstrap continues where it stopped.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use tokio::{
+    fs::{self, File},
+    io::{self, AsyncBufRead, AsyncBufReadExt, BufReader, Lines},
+    stream::StreamExt,
+};
+
+use crate::{cfg::Config, db::Database, krate::Crate, shutdown::Shutdown};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BootstrapSource {
+    /// crates.io database dump (https://static.crates.io/db-dump.tar.gz), extracted
+    Dump,
+    /// Checkout of the index without its history, e.g. made by `git clone --depth 1`
+    Snapshot,
+}
+
+impl BootstrapSource {
+    fn as_str(self) -> &'static str {
+        match self {
+            BootstrapSource::Dump => "dump",
+            BootstrapSource::Snapshot => "snapshot",
+        }
+    }
+}
+
+#[derive(Debug, derive_more::Display, derive_more::From)]
+pub enum Error {
+    Io(io::Error),
+    Db(tokio_postgres::Error),
+    #[display(fmt = "invalid dump: {}", _0)]
+    Dump(String),
+}
+
+/// Crate key, version, yanked status and hash of the index entry (unknown in dumps)
+type Entry = (String, String, bool, Option<String>);
+
+/// Entries waiting to be seeded
+struct Seeder<'a> {
+    db: &'a Database,
+    source: BootstrapSource,
+    batch: usize,
+    entries: Vec<Entry>,
+    seeded: usize,
+}
+
+impl Seeder<'_> {
+    /// Seeds the entries if there's a batch of them, `progress` covers all of them
+    async fn checkpoint(&mut self, progress: &str) -> Result<(), Error> {
+        if self.entries.len() >= self.batch {
+            self.flush(progress).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self, progress: &str) -> Result<(), Error> {
+        self.db
+            .seed_seen_versions(self.source.as_str(), progress, &self.entries)
+            .await?;
+        self.seeded += self.entries.len();
+        tracing::info!("bootstrap: {} entries seeded", self.seeded);
+        self.entries.clear();
+        Ok(())
+    }
+}
+
+/// Reader of csv records (RFC 4180: quoted fields may have commas, `""` and line breaks)
+struct Csv<R> {
+    lines: Lines<R>,
+}
+
+impl<R: AsyncBufRead + Unpin> Csv<R> {
+    async fn record(&mut self) -> Result<Option<Vec<String>>, Error> {
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        loop {
+            let line = match self.lines.next().await.transpose()? {
+                Some(line) => line,
+                None if quoted => return Err(Error::Dump(String::from("unterminated quote"))),
+                None => return Ok(None),
+            };
+            let mut chars = line.chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' if quoted && chars.peek() == Some(&'"') => {
+                        field.push('"');
+                        chars.next();
+                    }
+                    '"' => quoted = !quoted,
+                    ',' if !quoted => fields.push(std::mem::take(&mut field)),
+                    c => field.push(c),
+                }
+            }
+            if !quoted {
+                fields.push(field);
+                return Ok(Some(fields));
+            }
+            field.push('\n');
+        }
+    }
+
+    /// Positions of the `columns` in the header
+    async fn header(&mut self, columns: &[&str]) -> Result<Vec<usize>, Error> {
+        let header = self.record().await?.unwrap_or_default();
+        columns
+            .iter()
+            .map(|column| {
+                header
+                    .iter()
+                    .position(|name| name == column)
+                    .ok_or_else(|| Error::Dump(format!("no `{}` column", column)))
+            })
+            .collect()
+    }
+}
+
+async fn csv(path: &Path) -> Result<Csv<BufReader<File>>, Error> {
+    let file = File::open(path).await?;
+    Ok(Csv {
+        lines: BufReader::new(file).lines(),
+    })
+}
+
+/// Seeds versions of the dump, `progress` is the number of rows of `versions.csv` seeded before.
+/// `false` if it was stopped by shutdown.
+async fn dump(
+    seeder: &mut Seeder<'_>,
+    path: &Path,
+    progress: Option<&str>,
+    shutdown: &Shutdown,
+) -> Result<bool, Error> {
+    let mut crates = csv(&path.join("data/crates.csv")).await?;
+    let columns = crates.header(&["id", "name"]).await?;
+    let mut names = HashMap::new();
+    while let Some(mut record) = crates.record().await? {
+        if record.len() > columns[0].max(columns[1]) {
+            let name = std::mem::take(&mut record[columns[1]]);
+            names.insert(std::mem::take(&mut record[columns[0]]), name);
+        }
+    }
+
+    let skip: usize = progress.and_then(|rows| rows.parse().ok()).unwrap_or(0);
+    let mut versions = csv(&path.join("data/versions.csv")).await?;
+    let columns = versions.header(&["crate_id", "num", "yanked"]).await?;
+    let mut rows = 0;
+    while let Some(record) = versions.record().await? {
+        rows += 1;
+        if rows <= skip {
+            continue;
+        }
+        let field = |column: usize| record.get(columns[column]).map(String::as_str);
+        if let (Some(name), Some(version), Some(yanked)) =
+            (field(0).and_then(|id| names.get(id)), field(1), field(2))
+        {
+            let entry = (name.clone(), version.to_owned(), yanked == "t", None);
+            seeder.entries.push(entry);
+        }
+
+        seeder.checkpoint(&rows.to_string()).await?;
+        if shutdown.is_requested() {
+            seeder.flush(&rows.to_string()).await?;
+            return Ok(false);
+        }
+    }
+    seeder.flush(&rows.to_string()).await?;
+
+    Ok(true)
+}
+
+/// Files of crates in the index checkout, `.git` and `config.json` are skipped
+async fn crate_files(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with('.') || (dir == root && name == "config.json") {
+                continue;
+            }
+            if entry.file_type().await?.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+
+    Ok(files)
+}
+
+/// Seeds versions of the snapshot, crates are seeded in the order of their names and `progress`
+/// is the last one seeded before. `false` if it was stopped by shutdown.
+async fn snapshot(
+    seeder: &mut Seeder<'_>,
+    path: &Path,
+    progress: Option<&str>,
+    shutdown: &Shutdown,
+) -> Result<bool, Error> {
+    let mut last = String::new();
+    for file in crate_files(path).await? {
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if progress.map_or(false, |progress| name.as_str() <= progress) {
+            continue;
+        }
+
+        let mut lines = BufReader::new(File::open(&file).await?).lines();
+        while let Some(line) = lines.next().await.transpose()? {
+            match serde_json::from_str::<Crate>(&line) {
+                Ok(krate) => {
+                    let hash = krate.content_hash();
+                    let entry = (krate.key(), krate.id.vers, krate.yanked, Some(hash));
+                    seeder.entries.push(entry);
+                }
+                Err(err) => tracing::warn!("bootstrap: invalid entry of {}: {}", name, err),
+            }
+        }
+
+        seeder.checkpoint(&name).await?;
+        last = name;
+        if shutdown.is_requested() {
+            seeder.flush(&last).await?;
+            return Ok(false);
+        }
+    }
+    seeder.flush(&last).await?;
+
+    Ok(true)
+}
+
+/// Seeds the index entries known to the bot if `[bootstrap]` is configured and it hasn't
+/// finished yet, stops early on shutdown
+pub async fn run(db: &Database, cfg: &Config, shutdown: &Shutdown) {
+    let bootstrap = match &cfg.bootstrap {
+        Some(bootstrap) => bootstrap,
+        None => return,
+    };
+    let source = bootstrap.source;
+    let progress = match db.get_bootstrap_progress(source.as_str()).await {
+        Ok(Some((_, true))) => return,
+        Ok(progress) => progress.map(|(progress, _)| progress),
+        Err(err) => {
+            tracing::error!("db error while getting bootstrap progress: {}", err);
+            return;
+        }
+    };
+    tracing::info!(
+        "bootstrapping index entries from the {} at {}",
+        source.as_str(),
+        bootstrap.path
+    );
+
+    let mut seeder = Seeder {
+        db,
+        source,
+        batch: bootstrap.batch.max(1),
+        entries: Vec::new(),
+        seeded: 0,
+    };
+    let path = Path::new(&bootstrap.path);
+    let progress = progress.as_deref();
+    let finished = match source {
+        BootstrapSource::Dump => dump(&mut seeder, path, progress, shutdown).await,
+        BootstrapSource::Snapshot => snapshot(&mut seeder, path, progress, shutdown).await,
+    };
+    match finished {
+        Ok(true) => match db.finish_bootstrap(source.as_str()).await {
+            Ok(()) => tracing::info!("bootstrap finished, {} entries seeded", seeder.seeded),
+            Err(err) => tracing::error!("db error while finishing bootstrap: {}", err),
+        },
+        Ok(false) => tracing::info!("bootstrap is stopped, it continues after a restart"),
+        Err(err) => tracing::error!("bootstrap failed, it continues after a restart: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn csv_records() {
+        let data = "id,name,readme\n1,serde,\"a \"\"quoted\"\", multi-line\n\nreadme\"\n2,tokio,\n";
+        let mut csv = Csv {
+            lines: BufReader::new(data.as_bytes()).lines(),
+        };
+        assert_eq!(csv.header(&["name", "id"]).await.unwrap(), [1, 0]);
+        assert_eq!(
+            csv.record().await.unwrap(),
+            Some(vec![
+                String::from("1"),
+                String::from("serde"),
+                String::from("a \"quoted\", multi-line\n\nreadme"),
+            ])
+        );
+        assert_eq!(
+            csv.record().await.unwrap(),
+            Some(vec![
+                String::from("2"),
+                String::from("tokio"),
+                String::new()
+            ])
+        );
+        assert_eq!(csv.record().await.unwrap(), None);
+        assert!(csv.header(&["version"]).await.is_err());
+    }
+}
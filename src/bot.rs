@@ -1,58 +1,403 @@
-use std::{future::Future, path::PathBuf, pin::Pin, sync::Arc, time::Duration};
+use std::{
+    future::Future,
+    io::Cursor,
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures::StreamExt;
 
 use carapax::{
     longpoll::LongPoll,
-    methods::SendMessage,
-    types::{Command, ParseMode},
-    Api, Dispatcher, ExecuteError, Handler,
+    methods::{
+        AnswerCallbackQuery, DeleteWebhook, EditMessageText, GetChatMember, GetFile, SendDocument,
+        SendMessage, SetWebhook,
+    },
+    types::{
+        CallbackQuery, Chat, ChatMember, Command, InlineKeyboardMarkup, InputFile, InputFileReader,
+        Message, MessageData, ParseMode,
+    },
+    webhook, Api, Dispatcher, ExecuteError, Handler,
 };
-use fntools::value::ValueExt;
 
 use crate::{
-    cfg::Config,
-    db::Database,
-    krate::Crate,
-    util::{crate_path, tryn},
-    VERSION,
+    admin, branches,
+    cfg::{BotMode, Config, SharedConfig},
+    compare,
+    db::{Database, Schedule},
+    digest,
+    email::{self, Frequency},
+    feed,
+    filter::Filter,
+    firehose, groups, health,
+    history::{self, Since},
+    http,
+    index::IndexKind,
+    inline::{Inline, NameIndex},
+    krate::{is_valid_name, normalize_name, Crate, Versions},
+    limits::{self, Limiter, Verdict},
+    list, manifest, msrv, notification,
+    onboarding::{self, Step},
+    owners, preview, privacy, releases, render, rerender,
+    send::SendQueue,
+    share,
+    tags::{self, TagKind},
+    template::{Placeholder, Template},
+    translate::LANGUAGES,
+    util::{glob_match, http_client, random_token, tryn},
+    verbosity::Verbosity,
+    waits::{self, VersionReq},
+    watchlist::{self, Format, Watchlist},
+    weekly, why, ActionKind, VERSION,
 };
 
-pub fn setup(
+fn dispatcher(
     bot: Api,
     db: Database,
-    cfg: Arc<Config>,
-) -> LongPoll<Dispatcher<(Api, Database, Arc<Config>)>> {
-    let mut dp = Dispatcher::new((bot.clone(), db, cfg));
-    dp.add_handler(Handlers);
-    LongPoll::new(bot, dp) // TODO: allowed_update
+    cfg: SharedConfig,
+    queue: SendQueue,
+) -> Dispatcher<(Api, Database, SharedConfig, SendQueue)> {
+    // the index is a setting read only at startup
+    let index = cfg.get();
+    let names = match index.index.kind {
+        IndexKind::Git => NameIndex::from_dir(Path::new(&index.index_path))
+            .map_err(|err| tracing::error!("couldn't collect crate names for inline mode: {}", err))
+            .unwrap_or_default(),
+        IndexKind::Sparse => NameIndex::default(),
+    };
+
+    let names = Arc::new(names);
+    let limiter = Arc::new(Limiter::default());
+
+    let mut dp = Dispatcher::new((bot, db, cfg, queue));
+    dp.add_handler(Handlers {
+        names: Arc::clone(&names),
+        limiter: Arc::clone(&limiter),
+    });
+    dp.add_handler(Callbacks { limiter });
+    dp.add_handler(Manifests);
+    dp.add_handler(Inline::new(names));
+    dp
+}
+
+/// Receives updates via webhook or long polling (also if the webhook couldn't be set)
+pub async fn run(bot: Api, db: Database, shared: SharedConfig, queue: SendQueue) {
+    let cfg = shared.get();
+    let dp = dispatcher(bot.clone(), db, shared, queue);
+
+    if cfg.bot.mode == BotMode::Webhook {
+        let webhook_cfg = &cfg.bot.webhook;
+        let registered = match &webhook_cfg.url {
+            Some(url) => bot
+                .execute(SetWebhook::new(url.as_str()))
+                .await
+                .map_err(|err| {
+                    tracing::error!("couldn't set webhook, falling back to polling: {}", err)
+                })
+                .is_ok(),
+            None => {
+                tracing::error!("`bot.webhook.url` isn't set, falling back to polling");
+                false
+            }
+        };
+
+        if registered {
+            tracing::info!("listening for webhook updates on {}", webhook_cfg.listen);
+            if let Err(err) = webhook::run_server(webhook_cfg.listen, &webhook_cfg.path, dp).await {
+                tracing::error!("webhook server error: {}", err);
+            }
+            return;
+        }
+    }
+
+    // `getUpdates` doesn't work while a webhook is set
+    if let Err(err) = bot.execute(DeleteWebhook).await {
+        tracing::warn!("couldn't delete webhook: {}", err);
+    }
+    LongPoll::new(bot, dp).run().await // TODO: allowed_update
+}
+
+struct Handlers {
+    /// Crate names for suggestions when there is no such crate
+    names: Arc<NameIndex>,
+    /// Per-chat limits of commands and subscription changes, shared with [`Callbacks`]
+    limiter: Arc<Limiter>,
+}
+
+/// Suggestions for misspelled crate names with buttons subscribing to them
+fn suggestions(names: &NameIndex, missing: &[&str]) -> (String, Option<InlineKeyboardMarkup>) {
+    let mut suggested = Vec::new();
+    for krate in missing {
+        for name in names.suggest(krate, 3) {
+            if !suggested.contains(&name) {
+                suggested.push(name);
+            }
+        }
+    }
+    if suggested.is_empty() {
+        return (String::new(), None);
+    }
+
+    let text = format!(" Did you mean {}?", code_list(&suggested));
+    let keyboard: Vec<Vec<_>> = suggested
+        .iter()
+        .filter_map(|name| history::button(&format!("➕ {}", name), format!("sub {}", name)))
+        .map(|button| vec![button])
+        .collect();
+    (text, Some(InlineKeyboardMarkup::from(keyboard)))
+}
+
+/// Removes `flag` from `args`, returning whether it was there
+fn take_flag(args: &[String], flag: &str) -> (bool, Vec<String>) {
+    let rest: Vec<_> = args.iter().filter(|arg| *arg != flag).cloned().collect();
+    (rest.len() != args.len(), rest)
+}
+
+/// Splits trailing filter words (`/subscribe serde minor`) from crate names.
+/// If all arguments are filter words, they are treated as crate names.
+fn take_filter(args: &[String]) -> (Option<Filter>, &[String]) {
+    let split = args
+        .iter()
+        .rposition(|arg| !Filter::is_word(arg))
+        .map_or(0, |i| i + 1);
+    match args.split_at(split) {
+        (krates, []) | ([], krates) => (None, krates),
+        (krates, words) => {
+            let mut filter = Filter::default();
+            for word in words {
+                filter.apply(word);
+            }
+            (Some(filter), krates)
+        }
+    }
+}
+
+/// ` (notifying about ...)` if the filter is set
+fn filter_text(filter: Option<Filter>) -> String {
+    filter
+        .map(|filter| format!(" (notifying about {})", filter))
+        .unwrap_or_default()
 }
 
-struct Handlers;
+/// `<code>a</code>, <code>b</code>, ...`
+fn code_list(krates: &[&str]) -> String {
+    krates
+        .iter()
+        .map(|krate| format!("<code>{}</code>", krate))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Commands changing subscriptions or settings of the chat
+const ADMIN_COMMANDS: [&str; 39] = [
+    "/subscribe",
+    "/unsubscribe",
+    "/subscribe_owner",
+    "/unsubscribe_owner",
+    "/subscribe_deps",
+    "/group",
+    "/subscribe_keyword",
+    "/unsubscribe_keyword",
+    "/subscribe_category",
+    "/unsubscribe_category",
+    "/watch_name",
+    "/unwatch_name",
+    "/notify_when",
+    "/unnotify_when",
+    "/subscribe_new",
+    "/unsubscribe_new",
+    "/filter",
+    "/digest",
+    "/weekly",
+    "/timezone",
+    "/quiet",
+    "/pause",
+    "/resume",
+    "/email",
+    "/verbose",
+    "/verbosity",
+    "/security_alerts",
+    "/language",
+    "/translate",
+    "/trending",
+    "/threads",
+    "/api_token",
+    "/my_data",
+    "/forget_me",
+    "/msrv",
+    "/mute-yanks",
+    "/mute_yanks",
+    "/set_template",
+    "/rerender",
+];
+
+/// Anyone can manage subscriptions of a private chat, but only administrators of a group
+async fn can_manage(bot: &Api, message: &Message, user_id: i64) -> Result<bool, HErr> {
+    if let Chat::Private(_) = message.chat {
+        return Ok(true);
+    }
+
+    let member = bot
+        .execute(GetChatMember::new(message.get_chat_id(), user_id))
+        .await?;
+    Ok(matches!(
+        member,
+        ChatMember::Administrator(_) | ChatMember::Creator(_)
+    ))
+}
 
 #[derive(Debug, derive_more::Display, derive_more::From, derive_more::Error)]
-enum HErr {
+pub(crate) enum HErr {
     Tg(ExecuteError),
     Bd(tokio_postgres::Error),
+    Json(serde_json::Error),
+    Io(std::io::Error),
     GetUser,
 }
 
-impl Handler<(Api, Database, Arc<Config>)> for Handlers {
+impl Handler<(Api, Database, SharedConfig, SendQueue)> for Handlers {
     type Input = Command;
     type Output = Result<(), HErr>;
 
     fn handle<'s: 'async_trait, 'a: 'async_trait, 'async_trait>(
         &'s mut self,
-        context: &'a (Api, Database, Arc<Config>),
+        context: &'a (Api, Database, SharedConfig, SendQueue),
         input: Self::Input,
     ) -> Pin<Box<dyn Future<Output = Self::Output> + Send + 'async_trait>> {
         async fn handle_(
-            _: &mut Handlers,
-            (bot, db, cfg): &(Api, Database, Arc<Config>),
+            this: &mut Handlers,
+            (bot, db, shared, queue): &(Api, Database, SharedConfig, SendQueue),
             command: Command,
         ) -> Result<(), HErr> {
+            let cfg = &shared.get();
             let retry_delay = &cfg.retry_delay;
-            let chat_id = command.get_message().get_user().ok_or(HErr::GetUser)?.id;
-            match command.get_name() {
+            let message = command.get_message();
+            let chat_id = message.get_chat_id();
+            let user_id = message.get_user().ok_or(HErr::GetUser)?.id;
+            // `/subscribe@crates_upd_bot` in groups
+            let name = command.get_name().split('@').next().unwrap_or_default();
+            if db.is_banned(chat_id).await? {
+                return Ok(());
+            }
+            let limited = !admin::is_admin(cfg, user_id);
+            if limited {
+                match this.limiter.command(chat_id, &cfg.limits, Instant::now()) {
+                    Verdict::Allowed => {}
+                    Verdict::Warn => {
+                        tryn(5, retry_delay.0, || {
+                            bot.execute(SendMessage::new(chat_id, limits::TOO_FAST))
+                        })
+                        .await?;
+                        return Ok(());
+                    }
+                    Verdict::Ignore => return Ok(()),
+                }
+            }
+            // the chat is reachable again
+            if db.resume_chat(chat_id).await? {
+                tryn(5, retry_delay.0, || {
+                    bot.execute(SendMessage::new(
+                        chat_id,
+                        "Notifications were paused because messages to this chat couldn't be sent. They are resumed now.",
+                    ))
+                })
+                .await?;
+            }
+            if name == "/admin" {
+                // not a command of the bot for anyone else, so others are ignored silently
+                if admin::is_admin(cfg, user_id) {
+                    let rest = message
+                        .get_text()
+                        .and_then(|text| text.data.trim().split_once(char::is_whitespace))
+                        .map_or("", |(_, rest)| rest.trim());
+                    let text = admin::run(db, queue, shared, rest).await?;
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                return Ok(());
+            }
+            if ADMIN_COMMANDS.contains(&name) && !can_manage(bot, message, user_id).await? {
+                tryn(5, retry_delay.0, || {
+                    bot.execute(SendMessage::new(
+                        chat_id,
+                        "Only administrators can change subscriptions of the group.",
+                    ))
+                })
+                .await?;
+                return Ok(());
+            }
+            if limits::CHANGES.contains(&name) && limited {
+                if let Err(left) = this.limiter.change(chat_id, &cfg.limits, Instant::now()) {
+                    let text = limits::blocked_text(left);
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(SendMessage::new(chat_id, text.as_str()))
+                    })
+                    .await?;
+                    return Ok(());
+                }
+            }
+            let (include_yanked, args) = take_flag(command.get_args(), "--include-yanked");
+            match name {
                 "/start" => {
+                    // opened with a link of a weekly report
+                    if let Some(krate) = args
+                        .first()
+                        .and_then(|payload| payload.strip_prefix(weekly::HISTORY_PREFIX))
+                    {
+                        let (text, markup) = match history_page(db, cfg, krate, None, 0).await? {
+                            Some(page) => page,
+                            None => (
+                                format!(
+                                    "Error: there is no such crate <code>{}</code>.",
+                                    render::escape(krate)
+                                ),
+                                None,
+                            ),
+                        };
+                        tryn(5, retry_delay.0, || {
+                            let mut msg = SendMessage::new(chat_id, text.as_str())
+                                .parse_mode(ParseMode::Html);
+                            if let Some(markup) = &markup {
+                                msg = msg.reply_markup(markup.clone());
+                            }
+                            bot.execute(msg)
+                        })
+                        .await?;
+                        return Ok(());
+                    }
+                    // opened with a `/share` link
+                    if let Some(names) = args.first().and_then(|payload| share::decode(payload)) {
+                        let found = shared_crates(names, cfg).await;
+                        let found: Vec<&str> = found.iter().map(String::as_str).collect();
+                        let (text, markup) = match share::encode(&found).first() {
+                            Some(payload) => (
+                                format!("Subscribe to {}?", code_list(&found)),
+                                history::button("✅ Subscribe", payload.clone())
+                                    .map(|button| InlineKeyboardMarkup::from(vec![vec![button]])),
+                            ),
+                            None => (
+                                String::from("Error: the crates of this link don't exist."),
+                                None,
+                            ),
+                        };
+                        tryn(5, retry_delay.0, || {
+                            let mut msg = SendMessage::new(chat_id, text.as_str())
+                                .parse_mode(ParseMode::Html);
+                            if let Some(markup) = &markup {
+                                msg = msg.reply_markup(markup.clone());
+                            }
+                            bot.execute(msg)
+                        })
+                        .await?;
+                        return Ok(());
+                    }
+
                     tryn(5, Duration::from_millis(10000 /* 10 secs */), || {
                         bot.execute(
                             SendMessage::new(chat_id, format!("Hi! I will notify you about updates of crates. Use /subscribe to subscribe for updates of crates you want to be notified about.\n\nIn case you want to see <b>all</b> updates go to @crates_updates\n\nAuthor: @wafflelapkin\nHis channel [ru]: @ihatereality\nMy source: <a href='https://github.com/WaffleLapkin/crate_upd_bot'>[github]</a>\nVersion: <code>{version}</code>", version = VERSION))
@@ -60,54 +405,414 @@ impl Handler<(Api, Database, Arc<Config>)> for Handlers {
                         )
                     })
                     .await?;
+
+                    // the setup changes settings of the chat
+                    if can_manage(bot, message, user_id).await? {
+                        let (text, markup) = onboarding::start(db, chat_id).await?.render();
+                        tryn(5, retry_delay.0, || {
+                            bot.execute(
+                                SendMessage::new(chat_id, text.as_str())
+                                    .parse_mode(ParseMode::Html)
+                                    .reply_markup(markup.clone()),
+                            )
+                        })
+                        .await?;
+                    }
                 }
-                "/subscribe" => match command.get_args() {
-                    [krate, ..] => {
-                        if PathBuf::from(cfg.index_path.as_str())
-                            .also(|p| p.push(crate_path(krate)))
-                            .exists()
-                        {
+                "/subscribe" => match take_filter(&args) {
+                    (filter, [krate]) => {
+                        let full = limits::check_subscriptions(db, cfg, chat_id, &[krate.as_str()])
+                            .await?;
+                        if let Some(text) = full {
+                            tryn(5, retry_delay.0, || {
+                                bot.execute(SendMessage::new(chat_id, text.as_str()))
+                            })
+                            .await?;
+                        } else if Crate::exists(krate, cfg).await {
                             db.subscribe(chat_id, krate).await?;
-                            let v = match Crate::read_last(krate, cfg).await {
-                                Ok(krate) => format!(
-                                    " (current version <code>{}</code> {})",
-                                    krate.id.vers,
-                                    krate.html_links()
-                                ),
+                            if let Some(filter) = &filter {
+                                db.set_filter(chat_id, krate, filter).await?;
+                            }
+                            let v = match Versions::read(krate, cfg).await {
+                                Ok(versions) => {
+                                    format!(" (current version {})", versions.html(include_yanked))
+                                }
                                 Err(_) => String::new(),
                             };
                             tryn(5, retry_delay.0, || bot.execute(
                                     SendMessage::new(
                                         chat_id,
-                                        format!("You've successfully subscribed for updates on <code>{}</code>{} crate. Use /unsubscribe to unsubscribe.", krate, v))
+                                        format!("You've successfully subscribed for updates on <code>{}</code>{} crate{}. Use /unsubscribe to unsubscribe.", krate, v, filter_text(filter)))
                                         .parse_mode(ParseMode::Html)
                                         .disable_web_page_preview(true)
                                 )).await?;
                         } else {
+                            let (suggestion, markup) = suggestions(&this.names, &[krate]);
+                            let text = format!(
+                                "Error: there is no such crate <code>{}</code>.{}",
+                                krate, suggestion
+                            );
                             tryn(5, retry_delay.0, || {
-                                bot.execute(
-                                    SendMessage::new(
-                                        chat_id,
-                                        format!(
-                                            "Error: there is no such crate <code>{}</code>.",
-                                            krate
-                                        ),
-                                    )
-                                    .parse_mode(ParseMode::Html),
-                                )
+                                let mut msg = SendMessage::new(chat_id, text.as_str())
+                                    .parse_mode(ParseMode::Html);
+                                if let Some(markup) = &markup {
+                                    msg = msg.reply_markup(markup.clone());
+                                }
+                                bot.execute(msg)
                             })
                             .await?;
                         }
                     }
-                    [] => {
+                    (_, []) => {
                         tryn(5, retry_delay.0, || bot.execute(
                                 SendMessage::new(chat_id, "You need to specify the crate you want to subscribe. Like this: <pre>/subscribe serde</pre>")
                                     .parse_mode(ParseMode::Html)
                             )).await?;
                     }
+                    (filter, krates) => {
+                        let mut existing = Vec::new();
+                        let mut missing = Vec::new();
+                        for krate in krates {
+                            if Crate::exists(krate, cfg).await {
+                                existing.push(krate.as_str());
+                            } else {
+                                missing.push(krate.as_str());
+                            }
+                        }
+                        if let Some(text) =
+                            limits::check_subscriptions(db, cfg, chat_id, &existing).await?
+                        {
+                            tryn(5, retry_delay.0, || {
+                                bot.execute(SendMessage::new(chat_id, text.as_str()))
+                            })
+                            .await?;
+                            return Ok(());
+                        }
+
+                        if !existing.is_empty() {
+                            db.subscribe_many(chat_id, &existing).await?;
+                            if let Some(filter) = &filter {
+                                for krate in &existing {
+                                    db.set_filter(chat_id, krate, filter).await?;
+                                }
+                            }
+                        }
+
+                        let mut text = String::new();
+                        if !existing.is_empty() {
+                            text.push_str(&format!(
+                                "You've successfully subscribed for updates on: {}{}.",
+                                code_list(&existing),
+                                filter_text(filter)
+                            ));
+                        }
+                        let mut markup = None;
+                        if !missing.is_empty() {
+                            let (suggestion, keyboard) = suggestions(&this.names, &missing);
+                            text.push_str(&format!(
+                                "\nError: there are no such crates: {}.{}",
+                                code_list(&missing),
+                                suggestion
+                            ));
+                            markup = keyboard;
+                        }
+                        tryn(5, retry_delay.0, || {
+                            let mut msg = SendMessage::new(chat_id, text.trim_start())
+                                .parse_mode(ParseMode::Html);
+                            if let Some(markup) = &markup {
+                                msg = msg.reply_markup(markup.clone());
+                            }
+                            bot.execute(msg)
+                        })
+                        .await?;
+                    }
                 },
-                "/unsubscribe" => match command.get_args() {
-                    [krate, ..] => {
+                "/watch_name" => {
+                    let text = match &args[..] {
+                        [] => {
+                            let names = db.list_name_watches(chat_id).await?;
+                            if names.is_empty() {
+                                String::from("You need to specify the name. Like this: <code>/watch_name mycoolname</code>, you'll be notified when a crate with this name is published.")
+                            } else {
+                                let names: Vec<&str> = names.iter().map(String::as_str).collect();
+                                format!("You are waiting for crates named {}. Use /unwatch_name to stop.", code_list(&names))
+                            }
+                        }
+                        [name] if !is_valid_name(name) => format!("Error: <code>{}</code> isn't a valid crate name.", render::escape(name)),
+                        [name] if Crate::exists(name, cfg).await => format!("<code>{}</code> is already published. Use /subscribe to subscribe to it.", name),
+                        [name] => {
+                            db.watch_name(chat_id, &normalize_name(name)).await?;
+                            format!("You'll be notified when <code>{}</code> is published. Use /unwatch_name to stop.", name)
+                        }
+                        _ => String::from("Error: specify a single name. Like this: <code>/watch_name mycoolname</code>"),
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/unwatch_name" => {
+                    let text = match &args[..] {
+                        [name] => {
+                            if db.unwatch_name(chat_id, &normalize_name(name)).await? {
+                                format!("You won't be notified when <code>{}</code> is published.", render::escape(name))
+                            } else {
+                                format!("You aren't waiting for <code>{}</code>.", render::escape(name))
+                            }
+                        }
+                        _ => String::from("You need to specify the name. Like this: <code>/unwatch_name mycoolname</code>"),
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/notify_when" => {
+                    let requirement = args.get(1..).unwrap_or_default().join(" ");
+                    let text = match (args.first(), VersionReq::parse(&requirement)) {
+                        (None, _) => {
+                            let waits = db.list_version_waits(chat_id).await?;
+                            if waits.is_empty() {
+                                String::from("You need to specify the crate and the version requirement. Like this: <code>/notify_when foo >=2.0</code>, you'll be notified once when a release of <code>foo</code> matching it is published.")
+                            } else {
+                                let waits: Vec<String> = waits.iter().map(|(krate, req)| format!("<code>{} {}</code>", krate, render::escape(req))).collect();
+                                format!("You are waiting for {}. Use /unnotify_when to stop.", waits.join(", "))
+                            }
+                        }
+                        (Some(_), None) => format!("Error: <code>{}</code> isn't a valid version requirement. Use one like <code>>=2.0</code>, <code>^1.4.2</code> or <code>>=1.2, <1.5</code>.", render::escape(&requirement)),
+                        (Some(_), Some(req)) if req.to_string().len() > waits::MAX_LENGTH => String::from("Error: the version requirement is too long."),
+                        (Some(krate), Some(_)) if !Crate::exists(krate, cfg).await => format!("Error: there is no such crate: <code>{}</code>. Use /watch_name to wait for its first publish.", render::escape(krate)),
+                        (Some(krate), Some(req)) => {
+                            let requirement = req.to_string();
+                            match limits::check_version_waits(db, cfg, chat_id, krate, &requirement).await? {
+                                Some(text) => text,
+                                None => {
+                                    db.wait_for_version(chat_id, krate, &requirement).await?;
+                                    format!("You'll be notified once when a release of <code>{}</code> matching <code>{}</code> is published. Use /unnotify_when to stop.", krate, render::escape(&requirement))
+                                }
+                            }
+                        }
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/unnotify_when" => {
+                    let text = match &args[..] {
+                        [krate] => {
+                            if db.unwait_for_version(chat_id, krate).await? {
+                                format!("You won't be notified about releases of <code>{}</code> you waited for.", render::escape(krate))
+                            } else {
+                                format!("You aren't waiting for releases of <code>{}</code>.", render::escape(krate))
+                            }
+                        }
+                        _ => String::from("You need to specify the crate. Like this: <code>/unnotify_when foo</code>"),
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/subscribe_new" => {
+                    let topics: Vec<String> = args
+                        .iter()
+                        .map(|topic| firehose::normalize_topic(topic))
+                        .collect();
+                    let text = match topics.iter().find(|topic| !firehose::is_valid_topic(topic)) {
+                        Some(topic) => format!(
+                            "Error: <code>{}</code> isn't a valid keyword or category.",
+                            render::escape(topic)
+                        ),
+                        None => {
+                            let topics: Vec<&str> = topics.iter().map(String::as_str).collect();
+                            db.subscribe_new(chat_id, &topics).await?;
+                            if topics.is_empty() {
+                                String::from("You'll be notified about every new crate published on crates.io. Use <code>/subscribe_new keyword-or-category ...</code> to get only some of them and /unsubscribe_new to stop.")
+                            } else {
+                                format!("You'll be notified about new crates with keywords or categories {}. Use /unsubscribe_new to stop.", code_list(&topics))
+                            }
+                        }
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/unsubscribe_new" => {
+                    let text = if db.unsubscribe_new(chat_id).await? {
+                        "You won't be notified about new crates anymore."
+                    } else {
+                        "You aren't subscribed to new crates."
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(SendMessage::new(chat_id, text).parse_mode(ParseMode::Html))
+                    })
+                    .await?;
+                }
+                "/subscribe_owner" => {
+                    let text = match &args[..] {
+                        [owner] => {
+                            let krates = match http_client() {
+                                Ok(client) => owners::crates(&client, owner).await,
+                                Err(err) => Err(err),
+                            };
+                            match krates {
+                                Ok(Some(krates)) => {
+                                    let krates: Vec<&str> = krates.iter().map(String::as_str).collect();
+                                    if let Some(text) = limits::check_subscriptions(db, cfg, chat_id, &krates).await? {
+                                        text
+                                    } else {
+                                        db.subscribe_owner(chat_id, owner, &krates).await?;
+                                        format!("You've successfully subscribed for updates on {} crates of <code>{}</code>: {}. Crates they publish later will be added automatically. Use <code>/unsubscribe_owner {}</code> to stop that.", krates.len(), owner, code_list(&krates), owner)
+                                    }
+                                }
+                                Ok(None) => format!("Error: there is no such user or team <code>{}</code> on crates.io.", owner),
+                                Err(err) => {
+                                    tracing::warn!("couldn't get crates of {}: {}", owner, err);
+                                    String::from("Error: couldn't get crates from crates.io, try again later.")
+                                }
+                            }
+                        }
+                        _ => String::from("You need to specify the crates.io user or team. Like this: <code>/subscribe_owner dtolnay</code> or <code>/subscribe_owner github:rust-lang:libs</code>"),
+                    };
+                    // owners may have hundreds of crates
+                    let text = render::fit_message(&text);
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/unsubscribe_owner" => {
+                    let text = match &args[..] {
+                        [owner] if db.unsubscribe_owner(chat_id, owner).await? => format!("You won't be subscribed to new crates of <code>{}</code> anymore. Subscriptions to their existing crates are kept, use /unsubscribe to remove them.", owner),
+                        [owner] => format!("You aren't subscribed to crates of <code>{}</code>.", owner),
+                        _ => String::from("You need to specify the crates.io user or team. Like this: <code>/unsubscribe_owner dtolnay</code>"),
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/subscribe_deps" => {
+                    let text = match &args[..] {
+                        [krate] => match groups::dependencies(krate, cfg).await {
+                            Ok(Some(deps)) if deps.is_empty() => format!("<code>{}</code> has no dependencies.", render::escape(krate)),
+                            Ok(Some(deps)) => {
+                                let deps: Vec<&str> = deps.iter().map(String::as_str).collect();
+                                if let Some(text) = limits::check_subscriptions(db, cfg, chat_id, &deps).await? {
+                                    text
+                                } else {
+                                    db.subscribe_deps(chat_id, krate, &deps).await?;
+                                    format!("You've successfully subscribed for updates on {} dependencies of <code>{krate}</code>: {}. When a release of <code>{krate}</code> changes its dependencies, the subscriptions will follow. Use <code>/group drop {krate}</code> to unsubscribe from them.", deps.len(), code_list(&deps), krate = krate)
+                                }
+                            }
+                            Ok(None) => format!("Error: all versions of <code>{}</code> are yanked.", krate),
+                            Err(_) => format!("Error: there is no such crate <code>{}</code>.", render::escape(krate)),
+                        },
+                        _ => String::from("You need to specify the crate. Like this: <code>/subscribe_deps axum</code>"),
+                    };
+                    // crates may have dozens of dependencies
+                    let text = render::fit_message(&text);
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/group" => {
+                    let text = match &args[..] {
+                        [action] if action == "list" => {
+                            groups::list_html(&db.list_dep_groups(chat_id).await?)
+                        }
+                        [action, krate] if action == "drop" => {
+                            if db.drop_dep_group(chat_id, krate).await? {
+                                format!("You've unsubscribed from the dependencies of <code>{}</code>. Subscriptions you've added yourself are kept.", render::escape(krate))
+                            } else {
+                                format!("You don't follow the dependencies of <code>{}</code>. Use <code>/group list</code> to see the groups.", render::escape(krate))
+                            }
+                        }
+                        _ => String::from("Use <code>/group list</code> to see the crates whose dependencies you follow and <code>/group drop axum</code> to unsubscribe from the dependencies of <code>axum</code>."),
+                    };
+                    let text = render::fit_message(&text);
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/subscribe_keyword" | "/subscribe_category" => {
+                    let kind = if name == "/subscribe_keyword" {
+                        TagKind::Keyword
+                    } else {
+                        TagKind::Category
+                    };
+                    let text = match &args[..] {
+                        [tag] => {
+                            let tag = tag.to_lowercase();
+                            let krates = match http_client() {
+                                Ok(client) => tags::crates(&client, kind, &tag).await,
+                                Err(err) => Err(err),
+                            };
+                            match krates {
+                                Ok(krates) if krates.is_empty() => format!("Error: there are no crates with {} <code>{}</code>.", kind.as_str(), tag),
+                                Ok(krates) => {
+                                    let krates: Vec<&str> = krates.iter().map(String::as_str).collect();
+                                    db.set_tag_crates(kind.as_str(), &tag, &krates).await?;
+                                    db.subscribe_tag(chat_id, kind.as_str(), &tag).await?;
+                                    format!("You've successfully subscribed for new versions of {} crates with {} <code>{}</code>. Use <code>/unsubscribe_{} {}</code> to unsubscribe.", krates.len(), kind.as_str(), tag, kind.as_str(), tag)
+                                }
+                                Err(err) => {
+                                    tracing::warn!("couldn't get crates of {} {}: {}", kind.as_str(), tag, err);
+                                    String::from("Error: couldn't get crates from crates.io, try again later.")
+                                }
+                            }
+                        }
+                        _ => format!("You need to specify the {kind}. Like this: <code>/subscribe_{kind} {example}</code>", kind = kind.as_str(), example = if kind == TagKind::Keyword { "async" } else { "embedded" }),
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/unsubscribe_keyword" | "/unsubscribe_category" => {
+                    let kind = if name == "/unsubscribe_keyword" {
+                        TagKind::Keyword
+                    } else {
+                        TagKind::Category
+                    };
+                    let text = match &args[..] {
+                        [tag] if db.unsubscribe_tag(chat_id, kind.as_str(), &tag.to_lowercase()).await? => format!("You've successfully unsubscribed from crates with {} <code>{}</code>.", kind.as_str(), tag),
+                        [tag] => format!("You aren't subscribed to crates with {} <code>{}</code>.", kind.as_str(), tag),
+                        _ => format!("You need to specify the {kind}. Like this: <code>/unsubscribe_{kind} {example}</code>", kind = kind.as_str(), example = if kind == TagKind::Keyword { "async" } else { "embedded" }),
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/unsubscribe" => match &args[..] {
+                    [krate] => {
                         db.unsubscribe(chat_id, krate).await?;
                         tryn(5, retry_delay.0, || bot.execute(
                                 SendMessage::new(
@@ -122,50 +827,1372 @@ impl Handler<(Api, Database, Arc<Config>)> for Handlers {
                                     .parse_mode(ParseMode::Html)
                             )).await?;
                     }
-                },
-                "/list" => {
-                    let mut subscriptions = db.list_subscriptions(chat_id).await?;
-                    for sub in &mut subscriptions {
-                        match Crate::read_last(sub, cfg).await {
-                            Ok(krate) => {
-                                sub.push('#');
-                                sub.push_str(&krate.id.vers);
-                                sub.push_str("</code> ");
-                                sub.push_str(&krate.html_links());
+                    args => {
+                        let subscriptions;
+                        let krates: Vec<&str> = match args {
+                            [all, matching, pattern] if all == "all" && matching == "matching" => {
+                                let pattern = pattern.trim_matches(|c| c == '"' || c == '\'');
+                                subscriptions = db.list_subscriptions(chat_id).await?;
+                                subscriptions
+                                    .iter()
+                                    .map(String::as_str)
+                                    .filter(|krate| glob_match(pattern, krate))
+                                    .collect()
                             }
-                            Err(_) => {
-                                sub.push_str(" </code>");
-                                /* silently ignore error & just don't add links */
-                            }
-                        }
-                    }
+                            krates => krates.iter().map(String::as_str).collect(),
+                        };
 
-                    if subscriptions.is_empty() {
-                        tryn(5, retry_delay.0, || bot.execute(
-                            SendMessage::new(
-                                chat_id,
-                                String::from("Currently you aren't subscribed to anything. Use /subscribe to subscribe to some crate."))
-                                .parse_mode(ParseMode::Html)
-                        )).await?;
-                    } else {
+                        db.unsubscribe_many(chat_id, &krates).await?;
+
+                        let text = if krates.is_empty() {
+                            String::from("There is nothing to unsubscribe from.")
+                        } else {
+                            format!("You've successfully unsubscribed for updates on: {}. Use /subscribe to subscribe back.", code_list(&krates))
+                        };
                         tryn(5, retry_delay.0, || {
                             bot.execute(
-                                SendMessage::new(
-                                    chat_id,
-                                    format!(
-                                        "You are currently subscribed to:\n— <code>{}",
-                                        subscriptions.join("\n— <code>")
-                                    ),
-                                )
-                                .parse_mode(ParseMode::Html)
-                                .disable_web_page_preview(true),
+                                SendMessage::new(chat_id, text.as_str())
+                                    .parse_mode(ParseMode::Html),
                             )
                         })
                         .await?;
                     }
+                },
+                "/digest" => {
+                    let text = match &args[..] {
+                        [daily, time] if daily == "daily" && digest::parse_time(time).is_some() => {
+                            db.set_digest(chat_id, Some(time)).await?;
+                            let timezone = db.get_schedule(chat_id).await?.timezone;
+                            format!("Now you'll get one message with all updates daily at {} {}. Use <code>/digest off</code> to get updates immediately.", time, render::escape(timezone.as_deref().unwrap_or("UTC")))
+                        }
+                        [off] if off == "off" => {
+                            db.set_digest(chat_id, None).await?;
+                            String::from("Now you'll get updates immediately.")
+                        }
+                        _ => String::from("You need to specify the time of the digest (in your /timezone, UTC by default). Like this: <code>/digest daily 09:00</code>. Use <code>/digest off</code> to get updates immediately."),
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
                 }
-                _ => {}
+                "/timezone" => {
+                    let text = match &args[..] {
+                        [] => {
+                            let timezone = db.get_schedule(chat_id).await?.timezone;
+                            format!("Your timezone is {}. Change it like this: <code>/timezone Europe/Berlin</code>.", render::escape(timezone.as_deref().unwrap_or("UTC")))
+                        }
+                        [name] => match db.find_timezone(name).await? {
+                            Some(timezone) => {
+                                // UTC is the default
+                                let stored = Some(timezone.as_str()).filter(|tz| *tz != "UTC");
+                                db.set_timezone(chat_id, stored).await?;
+                                format!("Your timezone is {} now, times of the digest and quiet hours are in it.", render::escape(&timezone))
+                            }
+                            None => format!("There is no timezone <code>{}</code>. Use names like <code>Europe/Berlin</code> or <code>America/New_York</code>.", render::escape(name)),
+                        },
+                        _ => String::from("Use <code>/timezone Europe/Berlin</code> to set your timezone."),
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/quiet" => {
+                    const USAGE: &str = "Use <code>/quiet 23:00-08:00</code> to get notifications which come at night in the morning (times are in your /timezone) and <code>/quiet off</code> to get them immediately.";
+                    let text = match &args[..] {
+                        [] => match db.get_schedule(chat_id).await? {
+                            Schedule {
+                                quiet: Some((from, to)),
+                                timezone,
+                                ..
+                            } => format!(
+                                "Your quiet hours are {}-{} {}.\n\n{}",
+                                from,
+                                to,
+                                render::escape(timezone.as_deref().unwrap_or("UTC")),
+                                USAGE
+                            ),
+                            _ => String::from(USAGE),
+                        },
+                        [off] if off == "off" => {
+                            db.set_quiet_hours(chat_id, None).await?;
+                            String::from("Now you'll get updates immediately, notifications deferred till the end of quiet hours are sent shortly.")
+                        }
+                        [hours] => match hours.split_once('-') {
+                            Some((from, to))
+                                if from != to
+                                    && digest::parse_time(from).is_some()
+                                    && digest::parse_time(to).is_some() =>
+                            {
+                                db.set_quiet_hours(chat_id, Some((from, to))).await?;
+                                format!("Notifications which come between {} and {} will be sent at {}. Use <code>/quiet off</code> to get them immediately.", from, to, to)
+                            }
+                            _ => String::from(USAGE),
+                        },
+                        _ => String::from(USAGE),
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/pause" => {
+                    let text = match &args[..] {
+                        [duration] => match digest::parse_pause(duration) {
+                            Some(duration) => {
+                                let until = db.pause_chat(chat_id, duration.as_secs() as i64).await?;
+                                format!("Notifications are paused till {}. Updates which come meanwhile will be sent in one message then, or earlier if you /resume.", render::escape(&until))
+                            }
+                            None => String::from("Pauses are from <code>1h</code> to a year, use hours, days or weeks: <code>/pause 12h</code>, <code>/pause 3d</code>, <code>/pause 2w</code>."),
+                        },
+                        _ => String::from("Use <code>/pause 2w</code> to get no notifications for two weeks (or <code>12h</code>, <code>3d</code>), updates which come meanwhile are sent in one message afterwards. /resume ends the pause earlier."),
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/resume" => {
+                    let text = if db.resume_paused(chat_id).await? {
+                        "Updates which came during the pause will be sent in one message shortly, then you'll get notifications as usual."
+                    } else {
+                        "Notifications aren't paused. Use <code>/pause 2w</code> to pause them."
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(SendMessage::new(chat_id, text).parse_mode(ParseMode::Html))
+                    })
+                    .await?;
+                }
+                "/email" => {
+                    const USAGE: &str = "Use <code>/email you@example.com</code> to get updates by e-mail too, <code>/email instant|daily|weekly</code> to change how often e-mails are sent and <code>/email off</code> to stop them.";
+                    let text = if cfg.email.is_none() {
+                        String::from("E-mails aren't enabled on this instance of the bot.")
+                    } else {
+                        match &args[..] {
+                            [] => match db.get_email(chat_id).await? {
+                                Some(email) => format!(
+                                    "Updates are mailed to <code>{}</code> ({}).\n\n{}",
+                                    render::escape(&email.address),
+                                    email.frequency.as_str(),
+                                    USAGE
+                                ),
+                                None => String::from(USAGE),
+                            },
+                            [off] if off == "off" => {
+                                if db.remove_email(chat_id).await? {
+                                    String::from("Updates won't be mailed anymore.")
+                                } else {
+                                    String::from("You haven't registered an e-mail address.")
+                                }
+                            }
+                            [arg] => {
+                                match Frequency::parse(arg) {
+                                    Some(frequency) => {
+                                        if db.set_email_frequency(chat_id, frequency).await? {
+                                            format!(
+                                                "E-mails will be sent {}.",
+                                                match frequency {
+                                                    Frequency::Instant => "right after updates",
+                                                    Frequency::Daily => "once a day",
+                                                    Frequency::Weekly => "once a week",
+                                                }
+                                            )
+                                        } else {
+                                            format!("You haven't registered an e-mail address yet.\n\n{}", USAGE)
+                                        }
+                                    }
+                                    None if email::is_address(arg) => {
+                                        db.set_email(chat_id, arg, &random_token()?).await?;
+                                        format!("Updates of your crates will be mailed to <code>{}</code> too. Every e-mail has an unsubscribe link.", render::escape(arg))
+                                    }
+                                    None => String::from(USAGE),
+                                }
+                            }
+                            _ => String::from(USAGE),
+                        }
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/verbose" => {
+                    let text = match &args[..] {
+                        [on] if on == "on" => {
+                            db.set_verbose(chat_id, true).await?;
+                            "Notifications about new versions will include downloads, license and MSRV. Use <code>/verbose off</code> to hide them."
+                        }
+                        [off] if off == "off" => {
+                            db.set_verbose(chat_id, false).await?;
+                            "Notifications won't include crates.io metadata anymore."
+                        }
+                        _ => "Use <code>/verbose on</code> to see downloads, license and MSRV in notifications about new versions, <code>/verbose off</code> to hide them.",
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(SendMessage::new(chat_id, text).parse_mode(ParseMode::Html))
+                    })
+                    .await?;
+                }
+                "/weekly" => {
+                    let text = match &args[..] {
+                        [on] if on == "on" => {
+                            db.set_weekly_report(chat_id, true).await?;
+                            "You'll get a report of the week's releases of your crates every 7 days, starting a week from now. Use <code>/weekly off</code> to stop it."
+                        }
+                        [off] if off == "off" => {
+                            db.set_weekly_report(chat_id, false).await?;
+                            "Weekly reports are turned off."
+                        }
+                        _ => "Use <code>/weekly on</code> to get a weekly report of releases of your crates grouped by their semver impact, <code>/weekly off</code> to stop it.",
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(SendMessage::new(chat_id, text).parse_mode(ParseMode::Html))
+                    })
+                    .await?;
+                }
+                "/verbosity" => {
+                    let text = match &args[..] {
+                        [] => format!(
+                            "Release notes in notifications: <b>{}</b>. Use <code>/verbosity full|first-section|headline-only|link-only</code> to change that.",
+                            db.get_notes_verbosity(chat_id).await?.as_str()
+                        ),
+                        [level] => match Verbosity::parse(level) {
+                            Some(verbosity) => {
+                                db.set_notes_verbosity(chat_id, verbosity).await?;
+                                let shown = match verbosity {
+                                    Verbosity::Full => "all release notes that fit into the message",
+                                    Verbosity::FirstSection => "the first section of release notes",
+                                    Verbosity::HeadlineOnly => "the first entry of release notes",
+                                    Verbosity::LinkOnly => "no release notes, only links",
+                                };
+                                format!("Notifications about new versions will include {}. Use <code>/rerender</code> to change the last ones too.", shown)
+                            }
+                            None => String::from("Error: unknown verbosity. Use one of <code>full</code>, <code>first-section</code>, <code>headline-only</code> or <code>link-only</code>."),
+                        },
+                        _ => String::from("Use <code>/verbosity full|first-section|headline-only|link-only</code> to choose how much of release notes notifications include."),
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/rerender" => {
+                    let count = match &args[..] {
+                        [] => Some(rerender::DEFAULT),
+                        [count] => count
+                            .parse()
+                            .ok()
+                            .filter(|count| (1..=i64::from(rerender::KEPT)).contains(count)),
+                        _ => None,
+                    };
+                    let text = match count {
+                        Some(count) => match rerender::run(bot, db, cfg, chat_id, count).await? {
+                            (_, 0) => String::from("There are no notifications about new versions to render again."),
+                            (edited, found) => format!(
+                                "Edited {} of the last {} notifications about new versions, the rest are unchanged or were deleted.",
+                                edited, found
+                            ),
+                        },
+                        None => format!(
+                            "Use <code>/rerender [&lt;n&gt;]</code> to render the last n (up to {}, {} by default) notifications about new versions again with the current settings.",
+                            rerender::KEPT,
+                            rerender::DEFAULT
+                        ),
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/security_alerts" => {
+                    let text = match &args[..] {
+                        [on] if on == "on" => {
+                            db.set_security_alerts(chat_id, true).await?;
+                            "Notifications will warn about suspicious releases: new crates named like popular ones, unusual version jumps and new publishers after years without releases. Use <code>/security_alerts off</code> to stop that."
+                        }
+                        [off] if off == "off" => {
+                            db.set_security_alerts(chat_id, false).await?;
+                            "Notifications won't warn about suspicious releases anymore."
+                        }
+                        _ => "Use <code>/security_alerts on</code> to get warnings about suspicious releases (new crates named like popular ones, unusual version jumps, new publishers after years without releases) in notifications, <code>/security_alerts off</code> to stop that.",
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(SendMessage::new(chat_id, text).parse_mode(ParseMode::Html))
+                    })
+                    .await?;
+                }
+                "/language" => {
+                    let text = match &args[..] {
+                        [] => match db.get_language(chat_id).await? {
+                            (Some(language), translate) => format!(
+                                "The language of this chat is <b>{}</b>, release notes in other languages {} translated, untranslated ones are tagged with their language. Use <code>/language off</code> to unset it.",
+                                language,
+                                if translate { "are" } else { "aren't" }
+                            ),
+                            (None, _) => format!("Use <code>/language &lt;code&gt;</code> to set the language of this chat, one of {}.", LANGUAGES.join(", ")),
+                        },
+                        [off] if off == "off" => {
+                            db.set_language(chat_id, None).await?;
+                            String::from("The language of this chat is unset, release notes won't be translated or tagged with their language.")
+                        }
+                        [language] if LANGUAGES.contains(&language.to_lowercase().as_str()) => {
+                            let language = language.to_lowercase();
+                            db.set_language(chat_id, Some(&language)).await?;
+                            format!("The language of this chat is <b>{}</b>, release notes in other languages are tagged with theirs. Use <code>/translate on</code> to get them translated to it.", language)
+                        }
+                        _ => format!("Error: unknown language. Use <code>/language &lt;code&gt;</code> with one of {}, or <code>/language off</code>.", LANGUAGES.join(", ")),
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/translate" => {
+                    let text = match &args[..] {
+                        [_] if cfg.translation.is_none() => "Translation of release notes is turned off on this instance of the bot.",
+                        [on] if on == "on" => match db.get_language(chat_id).await? {
+                            (Some(_), _) => {
+                                db.set_translate(chat_id, true).await?;
+                                "Release notes in other languages than the language of this chat will be translated. Use <code>/translate off</code> to stop that."
+                            }
+                            (None, _) => "Error: set the language of this chat with <code>/language &lt;code&gt;</code> first.",
+                        },
+                        [off] if off == "off" => {
+                            db.set_translate(chat_id, false).await?;
+                            "Release notes won't be translated anymore."
+                        }
+                        _ => "Use <code>/translate on</code> to get release notes in other languages translated to the language of this chat (set by <code>/language</code>), <code>/translate off</code> to stop that.",
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(SendMessage::new(chat_id, text).parse_mode(ParseMode::Html))
+                    })
+                    .await?;
+                }
+                "/threads" => {
+                    let text = match &args[..] {
+                        [on] if on == "on" => {
+                            db.set_threads(chat_id, true).await?;
+                            "Updates of a crate will be sent as replies to the previous update of the crate, and a pinned message will list the crates (pinning needs the admin rights in groups). Use <code>/threads off</code> to undo."
+                        }
+                        [off] if off == "off" => {
+                            db.set_threads(chat_id, false).await?;
+                            "Updates won't be sent as replies anymore."
+                        }
+                        _ => "Use <code>/threads on</code> to get updates of a crate as replies to its previous update, so they make a thread per crate, <code>/threads off</code> to undo.",
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(SendMessage::new(chat_id, text).parse_mode(ParseMode::Html))
+                    })
+                    .await?;
+                }
+                "/trending" => {
+                    let text = match &args[..] {
+                        [_] if cfg.trending.is_none() => "Trending notifications are turned off on this instance of the bot.",
+                        [on] if on == "on" => {
+                            db.set_trending(chat_id, true).await?;
+                            "You'll be notified when a crate you follow reaches a download milestone or its downloads surge. Use <code>/trending off</code> to stop that."
+                        }
+                        [off] if off == "off" => {
+                            db.set_trending(chat_id, false).await?;
+                            "You won't get trending notifications anymore."
+                        }
+                        _ => "Use <code>/trending on</code> to be notified when a crate you follow reaches a download milestone or its weekly downloads surge, <code>/trending off</code> to stop that.",
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(SendMessage::new(chat_id, text).parse_mode(ParseMode::Html))
+                    })
+                    .await?;
+                }
+                "/msrv" => {
+                    let text = match &args[..] {
+                        [off] if off == "off" => {
+                            db.set_msrv(chat_id, None).await?;
+                            String::from("You won't be warned about releases requiring newer Rust anymore.")
+                        }
+                        [version] if msrv::is_valid(version) => {
+                            db.set_msrv(chat_id, Some(version)).await?;
+                            format!("You'll be warned when a new version of a crate you follow requires Rust newer than <code>{}</code>. Use <code>/msrv off</code> to stop that.", version)
+                        }
+                        [] => match db.get_msrv(chat_id).await? {
+                            Some(version) => format!("You are warned about releases requiring Rust newer than <code>{}</code>. Use <code>/msrv off</code> to stop that.", version),
+                            None => String::from("Set the Rust version you use, like this: <code>/msrv 1.65</code>, to be warned when a new version of a crate you follow requires a newer one."),
+                        },
+                        _ => String::from("Error: that's not a Rust version. Use it like this: <code>/msrv 1.65</code>."),
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/mute-yanks" | "/mute_yanks" => {
+                    let text = if db.toggle_mute_yanks(chat_id).await? {
+                        "You won't be notified about yanked and unyanked versions anymore. Use /mute-yanks again to undo."
+                    } else {
+                        "You will be notified about yanked and unyanked versions again."
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(SendMessage::new(chat_id, text))
+                    })
+                    .await?;
+                }
+                "/set_template" => {
+                    // the template may contain spaces and newlines, so the raw text is used
+                    let src = message
+                        .get_text()
+                        .and_then(|text| text.data.trim().split_once(char::is_whitespace))
+                        .map_or("", |(_, src)| src.trim());
+                    let placeholders = Placeholder::ALL
+                        .iter()
+                        .map(|p| format!("<code>{{{}}}</code>", p.name()))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let text = match src {
+                        "" => {
+                            let current = match db.get_template(chat_id).await? {
+                                Some(template) => {
+                                    format!("<code>{}</code>", render::escape(&template))
+                                }
+                                None => String::from("the default one"),
+                            };
+                            format!("Your template of notifications about new versions is {}.\n\nSet a new one like this: <code>/set_template {{crate}} {{version}} is out! {{diff_url}}</code>\nPlaceholders: {}. Use <code>{{{{</code> and <code>}}}}</code> for literal braces, <code>/set_template default</code> to reset the template.", current, placeholders)
+                        }
+                        "default" => {
+                            db.set_template(chat_id, None).await?;
+                            String::from("Now you get notifications in the default format.")
+                        }
+                        src => match Template::parse(src) {
+                            Ok(_) => {
+                                db.set_template(chat_id, Some(src)).await?;
+                                String::from("Template is set. Use <code>/test_notify serde</code> to see how notifications look now, <code>/rerender</code> to change the last ones too.")
+                            }
+                            Err(err) => format!(
+                                "Error: {}. Placeholders: {}.",
+                                render::escape(&err.to_string()),
+                                placeholders
+                            ),
+                        },
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/filter" => match &args[..] {
+                    [krate, words @ ..] => {
+                        let text = match db.get_filter(chat_id, krate).await? {
+                            None => format!("You aren't subscribed to <code>{}</code>. Use /subscribe to subscribe.", krate),
+                            Some(mut filter) => {
+                                let unknown: Vec<&str> = words
+                                    .iter()
+                                    .map(String::as_str)
+                                    .filter(|word| !filter.apply(word))
+                                    .collect();
+                                if !unknown.is_empty() {
+                                    format!("Error: unknown filters: {}. Use some of: {}.", code_list(&unknown), code_list(&Filter::WORDS))
+                                } else if words.is_empty() {
+                                    format!("You are notified about {} of <code>{}</code>.", filter, krate)
+                                } else {
+                                    db.set_filter(chat_id, krate, &filter).await?;
+                                    format!("Now you are notified about {} of <code>{}</code>.", filter, krate)
+                                }
+                            }
+                        };
+                        tryn(5, retry_delay.0, || {
+                            bot.execute(
+                                SendMessage::new(chat_id, text.as_str())
+                                    .parse_mode(ParseMode::Html),
+                            )
+                        })
+                        .await?;
+                    }
+                    [] => {
+                        tryn(5, retry_delay.0, || bot.execute(
+                                SendMessage::new(chat_id, "You need to specify the crate and filters. Like this: <code>/filter tokio minor skip-prerelease</code>")
+                                    .parse_mode(ParseMode::Html)
+                            )).await?;
+                    }
+                },
+                "/test_notify" => match &args[..] {
+                    [krate, ..] => match Versions::read(krate, cfg).await {
+                        Ok(versions) => {
+                            let template = db
+                                .get_template(chat_id)
+                                .await?
+                                .and_then(|template| Template::parse(&template).ok());
+                            let verbose = db.get_verbose(chat_id).await?;
+                            let verbosity = db.get_notes_verbosity(chat_id).await?;
+                            let filter = db.get_filter(chat_id, krate).await?.unwrap_or_default();
+                            let message = match versions.get(include_yanked) {
+                                Some(krate) => notification(krate, &ActionKind::NewVersion, template.as_ref(), verbose, verbosity, filter, None, None, cfg).await.0,
+                                None => format!("All versions of <code>{}</code> are yanked, use <code>--include-yanked</code> to see the notification anyway.", krate),
+                            };
+                            tryn(5, retry_delay.0, || {
+                                bot.execute(
+                                    SendMessage::new(chat_id, message.as_str())
+                                        .parse_mode(ParseMode::Html)
+                                        .disable_web_page_preview(true),
+                                )
+                            })
+                            .await?;
+                        }
+                        Err(_) => {
+                            tryn(5, retry_delay.0, || {
+                                bot.execute(
+                                    SendMessage::new(
+                                        chat_id,
+                                        format!(
+                                            "Error: there is no such crate <code>{}</code>.",
+                                            render::escape(krate)
+                                        ),
+                                    )
+                                    .parse_mode(ParseMode::Html),
+                                )
+                            })
+                            .await?;
+                        }
+                    },
+                    [] => {
+                        tryn(5, retry_delay.0, || bot.execute(
+                                SendMessage::new(chat_id, "You need to specify the crate. Like this: <code>/test_notify serde</code>")
+                                    .parse_mode(ParseMode::Html)
+                            )).await?;
+                    }
+                },
+                "/preview" => {
+                    let text = match &args[..] {
+                        [krate, version] => match preview::render(db, cfg, Some(chat_id), krate, version).await? {
+                            Some(text) => text,
+                            None => format!(
+                                "Error: there is no version <code>{}</code> of <code>{}</code>.",
+                                render::escape(version),
+                                render::escape(krate)
+                            ),
+                        },
+                        _ => String::from("You need to specify the crate and the version. Like this: <code>/preview serde 1.0.130</code>"),
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str())
+                                .parse_mode(ParseMode::Html)
+                                .disable_web_page_preview(true),
+                        )
+                    })
+                    .await?;
+                }
+                "/feed" => {
+                    let text = if !feed::is_public(cfg) {
+                        String::from("Feeds aren't enabled on this instance of the bot.")
+                    } else {
+                        let token = match (db.get_feed_token(chat_id).await?, &args[..]) {
+                            (Some(token), []) => Some(token),
+                            (None, []) => Some(random_token()?),
+                            (_, [reset]) if reset == "reset" => Some(random_token()?),
+                            _ => None,
+                        };
+                        match token {
+                            Some(token) => {
+                                db.set_feed_token(chat_id, &token).await?;
+                                format!("Atom feed of releases of crates you are subscribed to: {}\n\nKeep the url secret, <code>/feed reset</code> replaces it with a new one.", feed::user_url(&token, cfg))
+                            }
+                            None => String::from("Use <code>/feed</code> to get the url of your feed, <code>/feed reset</code> to replace it with a new one."),
+                        }
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str())
+                                .parse_mode(ParseMode::Html)
+                                .disable_web_page_preview(true),
+                        )
+                    })
+                    .await?;
+                }
+                "/api_token" => {
+                    let text = if !cfg.api.enabled {
+                        String::from("The api isn't enabled on this instance of the bot.")
+                    } else {
+                        let token = match (db.get_api_token(chat_id).await?, &args[..]) {
+                            (Some(token), []) => Some(token),
+                            (None, []) => Some(random_token()?),
+                            (_, [reset]) if reset == "reset" => Some(random_token()?),
+                            _ => None,
+                        };
+                        match token {
+                            Some(token) => {
+                                db.set_api_token(chat_id, &token).await?;
+                                let url = http::public_url("/api/", cfg)
+                                    .map(|url| format!(" to {}", url))
+                                    .unwrap_or_default();
+                                format!("Token of the http api: <code>{}</code>\n\nSend it as <code>Authorization: Bearer {}</code> with requests{}. Requests act on behalf of this chat, so keep the token secret, <code>/api_token reset</code> replaces it with a new one.", token, token, url)
+                            }
+                            None => String::from("Use <code>/api_token</code> to get the token of the http api, <code>/api_token reset</code> to replace it with a new one."),
+                        }
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str())
+                                .parse_mode(ParseMode::Html)
+                                .disable_web_page_preview(true),
+                        )
+                    })
+                    .await?;
+                }
+                "/my_data" => {
+                    let json = privacy::export(db, chat_id).await?;
+                    let file = InputFileReader::new(Cursor::new(json))
+                        .info(("my_data.json", mime::APPLICATION_JSON));
+                    bot.execute(SendDocument::new(chat_id, InputFile::reader(file)).caption(
+                        "Everything the bot stores about this chat. Use /forget_me to delete it.",
+                    ))
+                    .await?;
+                }
+                "/forget_me" => {
+                    let (text, markup) = privacy::confirmation();
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(SendMessage::new(chat_id, text).reply_markup(markup.clone()))
+                    })
+                    .await?;
+                }
+                "/status" => {
+                    let text = health::check(db).await.html();
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/why" => match &args[..] {
+                    [krate, ..] => {
+                        let text = why::explain(db, cfg, chat_id, krate).await?;
+                        tryn(5, retry_delay.0, || {
+                            bot.execute(
+                                SendMessage::new(chat_id, text.as_str())
+                                    .parse_mode(ParseMode::Html),
+                            )
+                        })
+                        .await?;
+                    }
+                    [] => {
+                        tryn(5, retry_delay.0, || bot.execute(
+                                SendMessage::new(chat_id, "You need to specify the crate. Like this: <code>/why serde</code>")
+                                    .parse_mode(ParseMode::Html)
+                            )).await?;
+                    }
+                },
+                "/latest" => {
+                    let (text, markup) = match &args[..] {
+                        [krate] => match latest_message(db, cfg, krate, false).await? {
+                            Some(message) => message,
+                            None => (format!("Error: there is no such crate <code>{}</code>.", render::escape(krate)), None),
+                        },
+                        _ => (String::from("You need to specify the crate. Like this: <code>/latest serde</code>"), None),
+                    };
+                    tryn(5, retry_delay.0, || {
+                        let mut msg = SendMessage::new(chat_id, text.as_str())
+                            .parse_mode(ParseMode::Html)
+                            .disable_web_page_preview(true);
+                        if let Some(markup) = &markup {
+                            msg = msg.reply_markup(markup.clone());
+                        }
+                        bot.execute(msg)
+                    })
+                    .await?;
+                }
+                "/compare" => {
+                    let text = match &args[..] {
+                        [a, b] if a != b => compare::text(cfg, a, b).await,
+                        _ => String::from("You need to specify two crates. Like this: <code>/compare serde miniserde</code>"),
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html),
+                        )
+                    })
+                    .await?;
+                }
+                "/history" => {
+                    let (krate, since) = match &args[..] {
+                        [krate] => (krate, None),
+                        [krate, kw, since] if kw == "since" => (krate, Some(since.as_str())),
+                        [krate, n] if n.parse::<usize>().is_ok() => (krate, Some(n.as_str())),
+                        _ => {
+                            tryn(5, retry_delay.0, || bot.execute(
+                                SendMessage::new(chat_id, "You need to specify the crate. Like this: <code>/history serde</code>, <code>/history serde 10</code> (the last 10 versions), <code>/history serde since 2020-01-01</code> or <code>/history serde since 1.0.100</code>")
+                                    .parse_mode(ParseMode::Html)
+                            )).await?;
+                            return Ok(());
+                        }
+                    };
+
+                    let (text, markup) = match history_page(db, cfg, krate, since, 0).await? {
+                        Some(page) => page,
+                        None => (format!("Error: there is no such crate <code>{}</code> or <code>{}</code> is neither a date (<code>YYYY-MM-DD</code>) nor a version.", krate, since.unwrap_or_default()), None),
+                    };
+                    tryn(5, retry_delay.0, || {
+                        let mut msg =
+                            SendMessage::new(chat_id, text.as_str()).parse_mode(ParseMode::Html);
+                        if let Some(markup) = &markup {
+                            msg = msg.reply_markup(markup.clone());
+                        }
+                        bot.execute(msg)
+                    })
+                    .await?;
+                }
+                "/export" => {
+                    let format = match &args[..] {
+                        [] => Some(Format::Json),
+                        [format] => Format::parse(format),
+                        _ => None,
+                    };
+                    let format = match format {
+                        Some(format) => format,
+                        None => {
+                            tryn(5, retry_delay.0, || bot.execute(
+                                SendMessage::new(chat_id, "Error: unknown format. Use <code>/export</code> for JSON or <code>/export toml</code> for TOML.")
+                                    .parse_mode(ParseMode::Html)
+                            )).await?;
+                            return Ok(());
+                        }
+                    };
+
+                    let list = Watchlist::of_chat(db, chat_id).await?;
+                    if list.crates.is_empty() {
+                        tryn(5, retry_delay.0, || {
+                            bot.execute(SendMessage::new(
+                                chat_id,
+                                "Currently you aren't subscribed to anything. Use /subscribe to subscribe to some crate.",
+                            ))
+                        })
+                        .await?;
+                        return Ok(());
+                    }
+                    let mime = match format {
+                        Format::Json => mime::APPLICATION_JSON,
+                        Format::Toml => mime::TEXT_PLAIN,
+                    };
+                    let name = format!("subscriptions.{}", format.extension());
+                    let file = InputFileReader::new(Cursor::new(list.to_text(format).into_bytes()))
+                        .info((name.as_str(), mime));
+                    bot.execute(
+                        SendDocument::new(chat_id, InputFile::reader(file))
+                            .caption("Send this file to the bot to subscribe to these crates (e.g. from another account)."),
+                    )
+                    .await?;
+                }
+                "/import" => {
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(SendMessage::new(
+                            chat_id,
+                            "Send me a file exported with /export (or paste its contents) and I'll subscribe you to the crates from it with their filters.",
+                        ))
+                    })
+                    .await?;
+                }
+                "/share" => {
+                    let krates = if args.is_empty() {
+                        db.list_subscriptions(chat_id).await?
+                    } else {
+                        args.clone()
+                    };
+                    let krates: Vec<&str> = krates.iter().map(String::as_str).collect();
+                    let links: Vec<String> = share::encode(&krates)
+                        .iter()
+                        .map(|payload| share::link(&cfg.bot.username, payload))
+                        .collect();
+                    let text = if links.is_empty() {
+                        String::from("There is nothing to share. Use /subscribe to subscribe to some crates, or list them: <code>/share tokio serde</code>.")
+                    } else {
+                        format!(
+                            "Whoever opens {} gets asked to subscribe to the same crates:\n{}",
+                            if links.len() == 1 {
+                                "this link"
+                            } else {
+                                "these links"
+                            },
+                            links.join("\n")
+                        )
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(
+                            SendMessage::new(chat_id, text.as_str())
+                                .parse_mode(ParseMode::Html)
+                                .disable_web_page_preview(true),
+                        )
+                    })
+                    .await?;
+                }
+                "/list" => {
+                    let (text, markup) = list::page(db, cfg, chat_id, 0, include_yanked).await?;
+                    tryn(5, retry_delay.0, || {
+                        let mut msg = SendMessage::new(chat_id, render::fit_message(&text))
+                            .parse_mode(ParseMode::Html)
+                            .disable_web_page_preview(true);
+                        if let Some(markup) = &markup {
+                            msg = msg.reply_markup(markup.clone());
+                        }
+                        bot.execute(msg)
+                    })
+                    .await?;
+                }
+                _ => {}
+            }
+
+            Ok(())
+        }
+
+        Box::pin(handle_(self, context, input))
+    }
+}
+
+/// Published names of crates of a `/share` link, crates which don't exist are dropped
+async fn shared_crates(names: Vec<&str>, cfg: &Config) -> Vec<String> {
+    let mut krates = Vec::new();
+    for name in names {
+        for candidate in share::candidates(name) {
+            if Crate::exists(&candidate, cfg).await {
+                krates.push(candidate);
+                break;
+            }
+        }
+    }
+    krates
+}
+
+/// `None` if there is no such crate or `since` can't be parsed
+async fn history_page(
+    db: &Database,
+    cfg: &Config,
+    krate: &str,
+    since: Option<&str>,
+    page: usize,
+) -> Result<Option<(String, Option<InlineKeyboardMarkup>)>, HErr> {
+    let parsed = match since.map(Since::parse) {
+        Some(None) => return Ok(None),
+        parsed => parsed.flatten(),
+    };
+
+    Ok(history::entries(db, cfg, krate, parsed.as_ref())
+        .await?
+        .map(|entries| history::render_page(krate, since, &entries, page)))
+}
+
+/// Release notes shown by `/latest` until "show full" is pressed
+const LATEST_PREVIEW: usize = 600;
+
+/// `/latest`: the notification about the newest version of the crate with its publish date, yank
+/// status and the status of its branch (see [`branches`]). Release notes are cut unless `full`, then the button shows all of them.
+/// `None` if there is no such crate.
+async fn latest_message(
+    db: &Database,
+    cfg: &Config,
+    krate: &str,
+    full: bool,
+) -> Result<Option<(String, Option<InlineKeyboardMarkup>)>, HErr> {
+    let (newest, stale) = match Versions::read(krate, cfg).await {
+        Ok(versions) => (versions.newest, versions.stale),
+        Err(_) => return Ok(None),
+    };
+    let limit = if full { None } else { Some(LATEST_PREVIEW) };
+    let (mut text, cut) = notification(
+        &newest,
+        &ActionKind::NewVersion,
+        None,
+        false,
+        Verbosity::Full,
+        Filter::default(),
+        None,
+        limit,
+        cfg,
+    )
+    .await;
+
+    let published = db
+        .list_releases(krate)
+        .await?
+        .into_iter()
+        .find(|(version, _, _)| *version == newest.id.vers)
+        .map(|(_, _, date)| date);
+    if let Some(date) = published {
+        text.push_str(&format!("\n\nPublished on {}.", date));
+    }
+    if newest.yanked {
+        text.push_str("\n\n⚠ This version is yanked.");
+    }
+    // a release on an EOL branch is warned about by the notification
+    let manifest = match http_client() {
+        Ok(client) if newest.registry.is_none() => branches::fetch(&client, &newest.id.name).await,
+        _ => None,
+    };
+    let status = manifest
+        .as_ref()
+        .and_then(|manifest| manifest.status(&newest.id.vers));
+    if let Some((branch, branches::Status::Maintained)) = status {
+        text.push_str(&format!("\n{}", branches::maintained_html(branch)));
+    }
+    if let Some(fetched_at) = stale {
+        text.push_str(&format!("\n\n{}", releases::note(fetched_at)));
+    }
+
+    let markup = if cut {
+        history::button("Show full", format!("latest_full {}", krate))
+            .map(|button| InlineKeyboardMarkup::from(vec![vec![button]]))
+    } else {
+        None
+    };
+    Ok(Some((render::fit_message(&text), markup)))
+}
+
+/// Replaces the `/list` message with another page
+async fn edit_list(
+    bot: &Api,
+    message: &Message,
+    text: &str,
+    markup: Option<InlineKeyboardMarkup>,
+    retry_delay: Duration,
+) -> Result<(), HErr> {
+    let text = render::fit_message(text);
+    tryn(5, retry_delay, || {
+        let mut msg = EditMessageText::new(message.get_chat_id(), message.id, text.as_str())
+            .parse_mode(ParseMode::Html)
+            .disable_web_page_preview(true);
+        if let Some(markup) = &markup {
+            msg = msg.reply_markup(markup.clone());
+        }
+        bot.execute(msg)
+    })
+    .await?;
+
+    Ok(())
+}
+
+struct Callbacks {
+    limiter: Arc<Limiter>,
+}
+
+impl Handler<(Api, Database, SharedConfig, SendQueue)> for Callbacks {
+    type Input = CallbackQuery;
+    type Output = Result<(), HErr>;
+
+    fn handle<'s: 'async_trait, 'a: 'async_trait, 'async_trait>(
+        &'s mut self,
+        context: &'a (Api, Database, SharedConfig, SendQueue),
+        input: Self::Input,
+    ) -> Pin<Box<dyn Future<Output = Self::Output> + Send + 'async_trait>> {
+        async fn handle_(
+            this: &mut Callbacks,
+            (bot, db, shared, _): &(Api, Database, SharedConfig, SendQueue),
+            query: CallbackQuery,
+        ) -> Result<(), HErr> {
+            let cfg = &shared.get();
+            let retry_delay = &cfg.retry_delay;
+            let message = match &query.message {
+                Some(message) => message,
+                None => return Ok(()),
+            };
+            let chat_id = message.get_chat_id();
+            if db.is_banned(chat_id).await? {
+                return Ok(());
+            }
+            let data = query.data.as_deref().unwrap_or_default();
+            let args: Vec<_> = data.split_whitespace().collect();
+            // shown to the user who pressed the button
+            let mut notice = None;
+            let can_change = match args[..] {
+                ["list_unsub", ..] | ["list_set", ..] | ["sub", ..] | ["onb", ..] => {
+                    can_manage(bot, message, query.from.id).await?
+                }
+                [privacy::CONFIRM] => can_manage(bot, message, query.from.id).await?,
+                [payload] if payload.starts_with(share::PREFIX) => {
+                    can_manage(bot, message, query.from.id).await?
+                }
+                _ => true,
+            };
+            // buttons changing subscriptions count like the commands
+            let changes = match args[..] {
+                ["list_unsub", ..] | ["sub", ..] => can_change,
+                [payload] => payload.starts_with(share::PREFIX) && can_change,
+                _ => false,
+            };
+            let blocked = changes
+                && !admin::is_admin(cfg, query.from.id)
+                && this
+                    .limiter
+                    .change(chat_id, &cfg.limits, Instant::now())
+                    .is_err();
+
+            match args[..] {
+                ["history", krate, since_arg, page] => {
+                    let since_arg = if since_arg == "-" {
+                        None
+                    } else {
+                        Some(since_arg)
+                    };
+                    let page = page.parse().unwrap_or(0);
+                    if let Some((text, markup)) =
+                        history_page(db, cfg, krate, since_arg, page).await?
+                    {
+                        tryn(5, retry_delay.0, || {
+                            let mut msg = EditMessageText::new(chat_id, message.id, text.as_str())
+                                .parse_mode(ParseMode::Html);
+                            if let Some(markup) = &markup {
+                                msg = msg.reply_markup(markup.clone());
+                            }
+                            bot.execute(msg)
+                        })
+                        .await?;
+                    }
+                }
+                ["latest_full", krate] => {
+                    if let Some((text, _)) = latest_message(db, cfg, krate, true).await? {
+                        tryn(5, retry_delay.0, || {
+                            bot.execute(
+                                EditMessageText::new(chat_id, message.id, text.as_str())
+                                    .parse_mode(ParseMode::Html)
+                                    .disable_web_page_preview(true),
+                            )
+                        })
+                        .await?;
+                    }
+                }
+                ["history_json", krate, since_arg] => {
+                    let since_arg = if since_arg == "-" {
+                        None
+                    } else {
+                        Some(since_arg)
+                    };
+                    let parsed = since_arg.and_then(Since::parse);
+                    if let Some(entries) = history::entries(db, cfg, krate, parsed.as_ref()).await?
+                    {
+                        let json = serde_json::to_vec_pretty(&entries)?;
+                        let file = InputFileReader::new(Cursor::new(json))
+                            .info((format!("{}.json", krate).as_str(), mime::APPLICATION_JSON));
+                        bot.execute(SendDocument::new(chat_id, InputFile::reader(file)))
+                            .await?;
+                    }
+                }
+                ["list", page, y] => {
+                    let (text, markup) =
+                        list::page(db, cfg, chat_id, page.parse().unwrap_or(0), y == "1").await?;
+                    edit_list(bot, message, &text, markup, retry_delay.0).await?;
+                }
+                ["list_filter", page, y, krate] => {
+                    let page = page.parse().unwrap_or(0);
+                    if let Some((text, markup)) =
+                        list::filter_page(db, chat_id, krate, page, y == "1").await?
+                    {
+                        edit_list(bot, message, &text, Some(markup), retry_delay.0).await?;
+                    }
+                }
+                ["list_unsub", ..] | ["list_set", ..] | ["sub", ..] | ["onb", ..]
+                    if !can_change =>
+                {
+                    notice = Some("Only administrators can change subscriptions of the group.");
+                }
+                [payload] if payload.starts_with(share::PREFIX) && !can_change => {
+                    notice = Some("Only administrators can change subscriptions of the group.");
+                }
+                ["list_unsub", ..] | ["sub", ..] if blocked => {
+                    notice = Some(limits::BLOCKED_NOTICE);
+                }
+                [payload] if payload.starts_with(share::PREFIX) && blocked => {
+                    notice = Some(limits::BLOCKED_NOTICE);
+                }
+                [privacy::CONFIRM] if !can_change => {
+                    notice = Some("Only administrators can delete data of the group.");
+                }
+                [privacy::CONFIRM] | [privacy::CANCEL] => {
+                    let text = if args[0] == privacy::CONFIRM {
+                        db.forget_chat(chat_id).await?;
+                        "Everything stored about this chat was deleted. Use /start to begin again."
+                    } else {
+                        "Nothing was deleted."
+                    };
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(EditMessageText::new(chat_id, message.id, text))
+                    })
+                    .await?;
+                }
+                // the confirmation of a `/share` link
+                [payload] if payload.starts_with(share::PREFIX) => {
+                    if let Some(names) = share::decode(payload) {
+                        let krates = shared_crates(names, cfg).await;
+                        let krates: Vec<&str> = krates.iter().map(String::as_str).collect();
+                        let full = limits::check_subscriptions(db, cfg, chat_id, &krates).await?;
+                        if full.is_some() {
+                            notice = Some(limits::FULL_NOTICE);
+                        } else {
+                            db.subscribe_many(chat_id, &krates).await?;
+                            let text = format!(
+                                "You've successfully subscribed for updates on: {}. Use /list to see all subscriptions.",
+                                code_list(&krates)
+                            );
+                            tryn(5, retry_delay.0, || {
+                                bot.execute(
+                                    EditMessageText::new(chat_id, message.id, text.as_str())
+                                        .parse_mode(ParseMode::Html),
+                                )
+                            })
+                            .await?;
+                        }
+                    }
+                }
+                ["onb", step, choice] => {
+                    if let Some(step) = Step::parse(step) {
+                        let (text, markup) =
+                            match onboarding::choose(db, chat_id, step, choice).await? {
+                                Some(next) => {
+                                    let (text, markup) = next.render();
+                                    (text, Some(markup))
+                                }
+                                None => (String::from(onboarding::DONE), None),
+                            };
+                        tryn(5, retry_delay.0, || {
+                            let mut msg = EditMessageText::new(chat_id, message.id, text.as_str())
+                                .parse_mode(ParseMode::Html);
+                            if let Some(markup) = &markup {
+                                msg = msg.reply_markup(markup.clone());
+                            }
+                            bot.execute(msg)
+                        })
+                        .await?;
+                    }
+                }
+                ["sub", krate] => {
+                    let full = limits::check_subscriptions(db, cfg, chat_id, &[krate]).await?;
+                    notice = Some(if full.is_some() {
+                        limits::FULL_NOTICE
+                    } else if Crate::exists(krate, cfg).await {
+                        db.subscribe(chat_id, krate).await?;
+                        "Subscribed."
+                    } else {
+                        "There is no such crate."
+                    });
+                }
+                ["list_unsub", page, y, krate] => {
+                    db.unsubscribe(chat_id, krate).await?;
+                    let (text, markup) =
+                        list::page(db, cfg, chat_id, page.parse().unwrap_or(0), y == "1").await?;
+                    edit_list(bot, message, &text, markup, retry_delay.0).await?;
+                    notice = Some("Unsubscribed.");
+                }
+                ["list_set", page, y, krate, word] => {
+                    list::set_filter(db, chat_id, krate, word).await?;
+                    let page = page.parse().unwrap_or(0);
+                    if let Some((text, markup)) =
+                        list::filter_page(db, chat_id, krate, page, y == "1").await?
+                    {
+                        edit_list(bot, message, &text, Some(markup), retry_delay.0).await?;
+                    }
+                }
+                _ => {}
+            }
+
+            let mut answer = AnswerCallbackQuery::new(query.id.as_str());
+            if let Some(notice) = notice {
+                answer = answer.text(notice);
+            }
+            bot.execute(answer).await?;
+
+            Ok(())
+        }
+
+        Box::pin(handle_(self, context, input))
+    }
+}
+
+/// Largest `Cargo.lock`/`Cargo.toml` the bot downloads
+const MAX_MANIFEST_SIZE: i64 = 1024 * 1024;
+
+/// Subscribes to dependencies from `Cargo.lock`/`Cargo.toml` or to crates of a watch list from
+/// `/export`, sent as a file or pasted as text
+struct Manifests;
+
+impl Manifests {
+    /// Contents of the sent file, `None` if it's too big or couldn't be downloaded
+    async fn download(bot: &Api, file_id: &str) -> Result<Option<String>, HErr> {
+        let file = bot.execute(GetFile::new(file_id)).await?;
+        let path = match file.file_path {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let mut stream = match bot.download_file(path).await {
+            Ok(stream) => Box::pin(stream),
+            Err(err) => {
+                tracing::warn!("couldn't download file {}: {}", file_id, err);
+                return Ok(None);
+            }
+        };
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            match chunk {
+                Ok(chunk) => bytes.extend_from_slice(&chunk),
+                Err(err) => {
+                    tracing::warn!("couldn't download file {}: {}", file_id, err);
+                    return Ok(None);
+                }
+            }
+        }
+
+        Ok(String::from_utf8(bytes).ok())
+    }
+}
+
+impl Handler<(Api, Database, SharedConfig, SendQueue)> for Manifests {
+    type Input = Message;
+    type Output = Result<(), HErr>;
+
+    fn handle<'s: 'async_trait, 'a: 'async_trait, 'async_trait>(
+        &'s mut self,
+        context: &'a (Api, Database, SharedConfig, SendQueue),
+        input: Self::Input,
+    ) -> Pin<Box<dyn Future<Output = Self::Output> + Send + 'async_trait>> {
+        async fn handle_(
+            _: &mut Manifests,
+            (bot, db, shared, _): &(Api, Database, SharedConfig, SendQueue),
+            message: Message,
+        ) -> Result<(), HErr> {
+            let cfg = &shared.get();
+            let retry_delay = &cfg.retry_delay;
+            let chat_id = message.get_chat_id();
+            let src = match &message.data {
+                MessageData::Document { data, .. }
+                    if data
+                        .file_size
+                        .map_or(false, |size| size <= MAX_MANIFEST_SIZE) =>
+                {
+                    match Manifests::download(bot, &data.file_id).await? {
+                        Some(src) => src,
+                        None => return Ok(()),
+                    }
+                }
+                // commands are handled by `Handlers`
+                MessageData::Text(text) if !text.data.starts_with('/') => text.data.clone(),
+                _ => return Ok(()),
+            };
+            if let Some(list) = Watchlist::parse(&src) {
+                let user_id = message.get_user().ok_or(HErr::GetUser)?.id;
+                if db.is_banned(chat_id).await? || !can_manage(bot, &message, user_id).await? {
+                    return Ok(());
+                }
+
+                let names: Vec<&str> = list
+                    .crates
+                    .iter()
+                    .take(watchlist::MAX_CRATES)
+                    .map(|entry| entry.name.as_str())
+                    .collect();
+                if let Some(text) = limits::check_subscriptions(db, cfg, chat_id, &names).await? {
+                    tryn(5, retry_delay.0, || {
+                        bot.execute(SendMessage::new(chat_id, text.as_str()))
+                    })
+                    .await?;
+                    return Ok(());
+                }
+
+                let (krates, unknown) = list.import(db, cfg, chat_id).await?;
+                let mut text = if krates.is_empty() {
+                    String::from("There are no crates to subscribe to in the file.")
+                } else {
+                    format!(
+                        "You've successfully subscribed for updates on: {}.",
+                        code_list(&krates)
+                    )
+                };
+                if !unknown.is_empty() {
+                    text.push_str(&format!(
+                        " There are no such crates: {}.",
+                        code_list(&unknown)
+                    ));
+                }
+                if list.crates.len() > watchlist::MAX_CRATES {
+                    text.push_str(&format!(
+                        " Only the first {} crates were imported.",
+                        watchlist::MAX_CRATES
+                    ));
+                }
+                if onboarding::imported(db, chat_id).await? {
+                    text.push_str("\n\n");
+                    text.push_str(onboarding::DONE);
+                }
+                tryn(5, retry_delay.0, || {
+                    bot.execute(
+                        SendMessage::new(chat_id, render::fit_message(&text))
+                            .parse_mode(ParseMode::Html),
+                    )
+                })
+                .await?;
+                return Ok(());
+            }
+            let deps = match manifest::parse(&src) {
+                Some(deps) => deps,
+                None => return Ok(()),
+            };
+            let user_id = message.get_user().ok_or(HErr::GetUser)?.id;
+            if db.is_banned(chat_id).await? || !can_manage(bot, &message, user_id).await? {
+                return Ok(());
+            }
+
+            let krates: Vec<&str> = deps.iter().map(|dep| dep.name.as_str()).collect();
+            let (locked_krates, locked_versions): (Vec<&str>, Vec<&str>) = deps
+                .iter()
+                .filter_map(|dep| Some((dep.name.as_str(), dep.locked.as_deref()?)))
+                .unzip();
+            if let Some(text) = limits::check_subscriptions(db, cfg, chat_id, &krates).await? {
+                tryn(5, retry_delay.0, || {
+                    bot.execute(SendMessage::new(chat_id, text.as_str()))
+                })
+                .await?;
+                return Ok(());
+            }
+            if !krates.is_empty() {
+                db.subscribe_many(chat_id, &krates).await?;
+                db.set_baselines(chat_id, &locked_krates, &locked_versions)
+                    .await?;
+            }
+
+            let mut text = if krates.is_empty() {
+                String::from("There are no dependencies from crates.io to subscribe to.")
+            } else if locked_krates.is_empty() {
+                format!(
+                    "You've successfully subscribed for updates on: {}.",
+                    code_list(&krates)
+                )
+            } else {
+                format!("You've successfully subscribed for updates on: {}. You'll be notified only about versions newer than the locked ones.", code_list(&krates))
+            };
+            if onboarding::imported(db, chat_id).await? {
+                text.push_str("\n\n");
+                text.push_str(onboarding::DONE);
             }
+            tryn(5, retry_delay.0, || {
+                bot.execute(
+                    SendMessage::new(chat_id, render::fit_message(&text))
+                        .parse_mode(ParseMode::Html),
+                )
+            })
+            .await?;
 
             Ok(())
         }
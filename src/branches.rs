@@ -0,0 +1,126 @@
+//! Release branches a crate's maintainers support, declared in `release-branches.toml` (or
+//! `.github/release-branches.toml`) of the crate's repository:
+//!
+//! ```toml
+//! maintained = ["0.4", "0.5"]
+//! eol = ["0.3", "0.2"]
+//! ```
+//!
+//! A branch is the first components of versions, `0.3` (or `0.3.x`) covers `0.3.0`, `0.3.1`, ...
+//! Notifications about releases on an EOL branch get a warning, `/latest` shows the status.
+use reqwest::Client;
+
+use crate::{
+    cache::{self, Source},
+    cratesio,
+    render::escape,
+    repo::Repo,
+};
+
+/// Places of the manifest tried in order
+const FILENAMES: [&str; 2] = ["release-branches.toml", ".github/release-branches.toml"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Maintained,
+    /// End of life, maintainers don't fix anything there anymore
+    Eol,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub maintained: Vec<String>,
+    #[serde(default)]
+    pub eol: Vec<String>,
+}
+
+/// `0.3` of `0.3`, `0.3.x` or `0.3.*`
+fn prefix(branch: &str) -> &str {
+    branch.trim().trim_end_matches(".*").trim_end_matches(".x")
+}
+
+/// Whether `version` is on `branch`, i.e. the branch's components are the first ones of the version
+fn on_branch(branch: &str, version: &str) -> bool {
+    let branch = prefix(branch);
+    let version = version.split(&['-', '+'][..]).next().unwrap_or("");
+    let mut components = version.split('.');
+    !branch.is_empty() && branch.split('.').all(|b| components.next() == Some(b))
+}
+
+impl Manifest {
+    /// `None` if it isn't a valid manifest
+    pub fn parse(src: &str) -> Option<Self> {
+        toml::from_str(src).ok()
+    }
+
+    /// The branch of `version` and its status, the longest listed branch wins (`1.2` over `1`).
+    /// `None` if the branch isn't listed.
+    pub fn status(&self, version: &str) -> Option<(&str, Status)> {
+        let maintained = self.maintained.iter().map(|b| (b, Status::Maintained));
+        let eol = self.eol.iter().map(|b| (b, Status::Eol));
+        maintained
+            .chain(eol)
+            .filter(|(branch, _)| on_branch(branch, version))
+            .max_by_key(|(branch, _)| prefix(branch).split('.').count())
+            .map(|(branch, status)| (branch.trim(), status))
+    }
+}
+
+/// Manifest of the crate's repository (from its crates.io metadata), `None` if there is none or
+/// the forge isn't supported
+pub async fn fetch(client: &Client, krate: &str) -> Option<Manifest> {
+    let repository = cratesio::repository(client, krate)
+        .await
+        .map_err(|err| tracing::warn!("couldn't get repository of {}: {}", krate, err))
+        .ok()??;
+    let repo = Repo::parse(&repository)?;
+    for file in &FILENAMES {
+        let request = client.get(&repo.raw_url(file));
+        let src = match cache::get(client, request, Source::Changelog).await {
+            Ok(response) if response.status.is_success() => response.body,
+            _ => continue,
+        };
+        let manifest = Manifest::parse(&src);
+        if manifest.is_none() {
+            tracing::debug!("invalid {} of {}", file, krate);
+        }
+        return manifest;
+    }
+
+    None
+}
+
+/// Telegram html warning about a release on an EOL branch
+pub fn eol_html(branch: &str) -> String {
+    format!(
+        "⚰️ Branch <code>{}</code> reached its end of life, its maintainers don't support it anymore",
+        escape(branch)
+    )
+}
+
+/// Telegram html line about a maintained branch
+pub fn maintained_html(branch: &str) -> String {
+    format!("Branch <code>{}</code> is maintained.", escape(branch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statuses() {
+        let manifest = Manifest::parse(
+            "maintained = [\"0.4\", \"1\"]\n\
+             eol = [\"0.3.x\", \"1.0\"]\n",
+        )
+        .unwrap();
+        assert_eq!(manifest.status("0.4.2"), Some(("0.4", Status::Maintained)));
+        assert_eq!(manifest.status("0.3.9"), Some(("0.3.x", Status::Eol)));
+        assert_eq!(manifest.status("0.30.0"), None);
+        assert_eq!(manifest.status("1.2.0"), Some(("1", Status::Maintained)));
+        assert_eq!(manifest.status("1.0.5-rc.1"), Some(("1.0", Status::Eol)));
+        assert_eq!(manifest.status("2.0.0"), None);
+        assert!(Manifest::parse("eol = \"0.3\"").is_none());
+    }
+}
@@ -0,0 +1,230 @@
+//! Disk cache of responses of external apis: crates.io, changelog files and forges. A response is
+//! used for the TTL of its source, then revalidated with its ETag, so a release of a crate with
+//! many subscribers (or several lookups of the same crate) doesn't repeat the requests.
+use std::{
+    path::{Path, PathBuf},
+    sync::RwLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use lazy_static::lazy_static;
+use reqwest::{
+    header::{HeaderValue, ETAG, IF_NONE_MATCH},
+    Client, RequestBuilder, StatusCode,
+};
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+
+use crate::{cfg::CacheConfig, metrics};
+
+lazy_static! {
+    /// Set by [`init`], responses aren't cached without it (e.g. in tests)
+    static ref CONFIG: RwLock<Option<CacheConfig>> = RwLock::new(None);
+}
+
+/// Enables the cache
+pub fn init(cfg: &CacheConfig) {
+    if let Ok(mut config) = CONFIG.write() {
+        *config = Some(cfg.clone());
+    }
+}
+
+/// Kind of a cached api, with its own TTL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    CratesIo,
+    /// Changelog files and READMEs
+    Changelog,
+    /// Apis of GitHub, GitLab and Gitea
+    Forge,
+}
+
+impl Source {
+    fn as_str(self) -> &'static str {
+        match self {
+            Source::CratesIo => "crates_io",
+            Source::Changelog => "changelog",
+            Source::Forge => "forge",
+        }
+    }
+}
+
+#[derive(Debug, derive_more::Display, derive_more::From)]
+pub enum Error {
+    Http(reqwest::Error),
+    #[display(fmt = "http status {}", _0)]
+    Status(StatusCode),
+    Json(serde_json::Error),
+}
+
+/// Response with the whole body, possibly from the cache
+#[derive(Debug)]
+pub struct Response {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+impl Response {
+    pub fn error_for_status(self) -> Result<Self, Error> {
+        if self.status.is_success() {
+            Ok(self)
+        } else {
+            Err(Error::Status(self.status))
+        }
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        Ok(serde_json::from_str(&self.body)?)
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Entry {
+    url: String,
+    etag: Option<String>,
+    /// Unix time of the response or of its last revalidation
+    fetched_at: u64,
+    status: u16,
+    body: String,
+}
+
+impl Entry {
+    fn response(self) -> Response {
+        Response {
+            status: StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK),
+            body: self.body,
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Directory and TTL of the source, `None` if it isn't cached
+fn settings(source: Source) -> Option<(PathBuf, u64)> {
+    let config = CONFIG.read().ok()?;
+    let config = config.as_ref()?;
+    let ttl = match source {
+        Source::CratesIo => config.crates_io_ttl_secs,
+        Source::Changelog => config.changelog_ttl_secs,
+        Source::Forge => config.forge_ttl_secs,
+    };
+    Some((PathBuf::from(&config.dir).join(source.as_str()), ttl)).filter(|_| ttl > 0)
+}
+
+/// File name of the url's entry
+fn key(url: &str) -> String {
+    format!("{}.json", hex::encode(Sha256::digest(url.as_bytes())))
+}
+
+async fn read(path: &Path) -> Option<Entry> {
+    let bytes = tokio::fs::read(path).await.ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+async fn try_write(path: &Path, entry: &Entry) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::write(path, serde_json::to_vec(entry)?).await
+}
+
+async fn write(path: &Path, entry: &Entry) {
+    if let Err(err) = try_write(path, entry).await {
+        tracing::warn!("couldn't write cache entry {}: {}", path.display(), err);
+    }
+}
+
+/// Sends the request unless a response of the url younger than the source's TTL is cached.
+/// Successes and "not found" responses are cached, other errors (e.g. rate limits) aren't.
+pub async fn get(
+    client: &Client,
+    request: RequestBuilder,
+    source: Source,
+) -> Result<Response, Error> {
+    fetch(client, request, source, false).await
+}
+
+/// Like [`get`], but the cached response is revalidated even if it's younger than the TTL, e.g.
+/// when it lacks a just published version
+pub async fn revalidate(
+    client: &Client,
+    request: RequestBuilder,
+    source: Source,
+) -> Result<Response, Error> {
+    fetch(client, request, source, true).await
+}
+
+async fn fetch(
+    client: &Client,
+    request: RequestBuilder,
+    source: Source,
+    force: bool,
+) -> Result<Response, Error> {
+    let mut request = request.build()?;
+    let (dir, ttl) = match settings(source) {
+        Some(settings) => settings,
+        None => {
+            let response = client.execute(request).await?;
+            let status = response.status();
+            return Ok(Response {
+                status,
+                body: response.text().await?,
+            });
+        }
+    };
+    let result = |name: &str| {
+        metrics::HTTP_CACHE
+            .with_label_values(&[source.as_str(), name])
+            .inc()
+    };
+
+    let url = request.url().to_string();
+    let path = dir.join(key(&url));
+    let cached = read(&path).await.filter(|entry| entry.url == url);
+    if let Some(entry) = &cached {
+        if !force && now().saturating_sub(entry.fetched_at) < ttl {
+            result("hit");
+            return Ok(cached.expect("checked above").response());
+        }
+        if let Some(etag) = entry
+            .etag
+            .as_deref()
+            .and_then(|e| HeaderValue::from_str(e).ok())
+        {
+            request.headers_mut().insert(IF_NONE_MATCH, etag);
+        }
+    }
+
+    let response = client.execute(request).await?;
+    let status = response.status();
+    if let (StatusCode::NOT_MODIFIED, Some(mut entry)) = (status, cached) {
+        result("revalidated");
+        entry.fetched_at = now();
+        write(&path, &entry).await;
+        return Ok(entry.response());
+    }
+
+    result("miss");
+    let etag = response
+        .headers()
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(str::to_owned);
+    let body = response.text().await?;
+    let entry = Entry {
+        url,
+        etag,
+        fetched_at: now(),
+        status: status.as_u16(),
+        body,
+    };
+    if status.is_success() || status == StatusCode::NOT_FOUND {
+        write(&path, &entry).await;
+    }
+
+    Ok(entry.response())
+}
@@ -1,39 +1,157 @@
+use crate::{
+    bootstrap::BootstrapSource, changelog::SourceKind, filter::Selector, index::IndexKind,
+    rules::Condition, template::Template, translate::TranslatorKind,
+};
 use fntools::value::ValueExt;
-use std::{collections::HashSet, error::Error, fs::File, io::Read, time::Duration};
+use kacl_parser::VersionScheme;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs::File,
+    io::Read,
+    net::SocketAddr,
+    sync::{Arc, PoisonError, RwLock},
+    time::Duration,
+};
+use tracing_subscriber::filter::LevelFilter;
 
 #[derive(Debug, serde::Deserialize)]
 pub struct Config {
     /// Channel to post **ALL** updates
     #[serde(default)]
     pub channel: Option<i64>,
+    /// Telegram channels of the operator getting selected updates, e.g. only new crates
+    #[serde(default, rename = "telegram_channel")]
+    pub telegram_channels: Vec<TelegramChannelConfig>,
     /// Delay between index fetches
     #[serde(default = "defaults::pull_delay")]
     pub pull_delay: Duration,
     /// Logging level
     #[serde(default = "defaults::loglevel")]
     pub loglevel: log::LevelFilter,
+    /// Format of logs: `text` or `json` (one object per line, with fields of spans)
+    #[serde(default)]
+    pub log_format: LogFormat,
     /// Url of crates.io index (git repo)
     #[serde(default = "defaults::index_url")]
     pub index_url: String,
     /// The path to the local crates.io index git repository
     #[serde(default = "defaults::index_path")]
     pub index_path: String,
+    /// Which index to watch
+    #[serde(default)]
+    pub index: IndexConfig,
+    /// Seeding of the index entries known to the bot on the first run, `None` starts without them
+    #[serde(default)]
+    pub bootstrap: Option<BootstrapConfig>,
+    /// Alternative registries watched in addition to crates.io
+    #[serde(default, rename = "registry")]
+    pub registries: Vec<Arc<RegistryConfig>>,
     /// Delay after which bot will retry telegram-request
     #[serde(default)]
     pub retry_delay: RetryDelay,
-    /// Delay between broadcast send messages
+    /// Pacing of sent notifications
     #[serde(default)]
-    pub broadcast_delay_millis: BroadcastDelay,
+    pub send: SendConfig,
     /// Delay between notifying about updates
     #[serde(default)]
     pub update_delay_millis: UpdateDelay,
+    /// New versions of a crate published within this many seconds of each other are announced
+    /// by one notification, `0` announces every version right away
+    #[serde(default)]
+    pub train_window_secs: u64,
+    /// Releases announced at once and queued for announcing
+    #[serde(default)]
+    pub pipeline: PipelineConfig,
+    /// Grouping of releases of crates from the same repository (e.g. the `futures-*` family)
+    #[serde(default)]
+    pub workspace: WorkspaceConfig,
     /// Token of the telegram bot
     pub bot_token: String,
+    /// How the bot receives updates
+    #[serde(default)]
+    pub bot: BotConfig,
     /// Database configuration
     pub db: DbConfig,
+    /// Append release notes (from the crate's `CHANGELOG.md` or its releases, see `changelog`) to
+    /// notifications about new versions
+    #[serde(default = "defaults::fetch_changelogs")]
+    pub fetch_changelogs: bool,
+    /// Where release notes are looked up
+    #[serde(default)]
+    pub changelog: ChangelogConfig,
+    /// Backend translating release notes for chats with `/translate on`, `None` turns translation
+    /// off
+    #[serde(default)]
+    pub translation: Option<TranslationConfig>,
+    /// Crates without release notes and overrides of how they're looked up
+    #[serde(default)]
+    pub crates: CratesConfig,
+    /// Disk cache of crates.io api responses, changelog files and forge api responses
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// Link compare views of release tags on supported forges instead of diff.rs in notifications
+    /// about new versions
+    #[serde(default = "defaults::source_diffs")]
+    pub source_diffs: bool,
+    /// Token for the GitHub api (checks of release tags), raises its rate limit
+    #[serde(default)]
+    pub github_token: Option<String>,
+    /// Template of notifications about new versions for chats which haven't set their own
+    #[serde(default)]
+    pub template: Option<Template>,
+    /// Percent of chats whose notifications start with a summary of the release notes
+    /// ("3 fixes, 1 new feature"), the other chats are a control group
+    #[serde(default = "defaults::summary_share")]
+    pub summary_share: u8,
+    /// Http endpoints receiving JSON payloads about updates
+    #[serde(default, rename = "hook")]
+    pub hooks: Vec<HookConfig>,
+    /// Routing rules evaluated for every update before it's sent anywhere
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<RuleConfig>,
     /// Ban configuration
     #[serde(default)]
     pub ban: BanConfig,
+    /// Per-chat limits of subscriptions and commands
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    /// Telegram user ids of the bot operators, allowed to use `/admin`
+    #[serde(default)]
+    pub admins: HashSet<i64>,
+    /// Prometheus metrics endpoint
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    /// Http server of feeds and unsubscribe links
+    #[serde(default)]
+    pub http: HttpConfig,
+    /// Atom feeds of releases
+    #[serde(default)]
+    pub feed: FeedConfig,
+    #[serde(default)]
+    pub api: ApiConfig,
+    /// SMTP server mailing updates to chats with an e-mail address
+    #[serde(default)]
+    pub email: Option<EmailConfig>,
+    /// Matrix account of the bot and rooms it notifies
+    #[serde(default)]
+    pub matrix: Option<MatrixConfig>,
+    /// Discord bot and channels it notifies
+    #[serde(default)]
+    pub discord: Option<DiscordConfig>,
+    /// Download milestones and surges of followed crates, `None` turns the sampling off
+    #[serde(default)]
+    pub trending: Option<TrendingConfig>,
+    /// Several instances sharing the database, `None` for a single instance
+    #[serde(default)]
+    pub cluster: Option<ClusterConfig>,
+    /// Secret used to sign chat bundles (`export-chat`/`import-chat` subcommands)
+    #[serde(default)]
+    pub migration_key: Option<String>,
+    /// Hex of the 32 byte key encrypting personal data at rest (see `crypt`), overridden by the
+    /// `CRATE_UPD_BOT_STORAGE_KEY` environment variable
+    #[serde(default)]
+    pub storage_key: Option<String>,
 }
 
 impl Config {
@@ -42,6 +160,141 @@ impl Config {
         File::open("./config.toml")?.read_to_string(&mut str)?;
         Ok(toml::from_str(&str)?)
     }
+
+    /// Alternative registry with the name
+    pub fn registry(&self, name: &str) -> Option<&Arc<RegistryConfig>> {
+        self.registries
+            .iter()
+            .find(|registry| registry.name == name)
+    }
+
+    /// Selectors of updates of hooks, matrix rooms, discord and telegram channels
+    pub fn selectors(&self) -> impl Iterator<Item = &Selector> {
+        let rooms = self.matrix.iter().flat_map(|matrix| &matrix.rooms);
+        let channels = self.discord.iter().flat_map(|discord| &discord.channels);
+        self.hooks
+            .iter()
+            .map(|hook| &hook.selector)
+            .chain(rooms.map(|room| &room.selector))
+            .chain(channels.map(|channel| &channel.selector))
+            .chain(
+                self.telegram_channels
+                    .iter()
+                    .map(|channel| &channel.selector),
+            )
+    }
+
+    /// Settings read only at startup: credentials, addresses, storage paths and the like.
+    /// Values are compared as their `Debug` representation and never shown.
+    fn fixed(&self) -> Vec<(&'static str, String)> {
+        let matrix = self
+            .matrix
+            .as_ref()
+            .map(|matrix| (&matrix.homeserver, &matrix.user, &matrix.password));
+        let discord = self.discord.as_ref().map(|discord| &discord.token);
+        vec![
+            ("bot_token", format!("{:?}", self.bot_token)),
+            ("bot", format!("{:?}", self.bot)),
+            ("db", format!("{:?}", self.db)),
+            ("loglevel", format!("{:?}", self.loglevel)),
+            ("log_format", format!("{:?}", self.log_format)),
+            ("index_url", format!("{:?}", self.index_url)),
+            ("index_path", format!("{:?}", self.index_path)),
+            ("index", format!("{:?}", self.index)),
+            ("registry", format!("{:?}", self.registries)),
+            ("send", format!("{:?}", self.send)),
+            ("pipeline", format!("{:?}", self.pipeline)),
+            ("metrics", format!("{:?}", self.metrics)),
+            ("http", format!("{:?}", self.http)),
+            ("feed", format!("{:?}", self.feed)),
+            ("email", format!("{:?}", self.email)),
+            ("matrix", format!("{:?}", matrix)),
+            ("discord", format!("{:?}", discord)),
+            ("cluster", format!("{:?}", self.cluster)),
+            ("cache", format!("{:?}", self.cache)),
+            ("github_token", format!("{:?}", self.github_token)),
+            ("migration_key", format!("{:?}", self.migration_key)),
+            ("storage_key", format!("{:?}", self.storage_key)),
+        ]
+    }
+
+    /// `loglevel` for the tracing subscriber
+    pub fn tracing_level(&self) -> LevelFilter {
+        match self.loglevel {
+            log::LevelFilter::Off => LevelFilter::OFF,
+            log::LevelFilter::Error => LevelFilter::ERROR,
+            log::LevelFilter::Warn => LevelFilter::WARN,
+            log::LevelFilter::Info => LevelFilter::INFO,
+            log::LevelFilter::Debug => LevelFilter::DEBUG,
+            log::LevelFilter::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+#[derive(Debug, derive_more::Display)]
+pub enum ReloadError {
+    #[display(fmt = "couldn't read the config: {}", _0)]
+    Read(String),
+    /// Names of changed settings which are read only at startup
+    #[display(fmt = "changes of {} need a restart", "_0.join(\", \")")]
+    Fixed(Vec<&'static str>),
+}
+
+fn rejected(err: ReloadError) -> ReloadError {
+    tracing::warn!("config isn't reloaded: {}", err);
+    err
+}
+
+/// Config shared by the tasks of the bot which can be reloaded while it runs.
+/// Tasks take a snapshot with [`SharedConfig::get`] for each update or poll.
+#[derive(Clone)]
+pub struct SharedConfig(Arc<RwLock<Arc<Config>>>);
+
+impl SharedConfig {
+    pub fn new(cfg: Arc<Config>) -> Self {
+        Self(Arc::new(RwLock::new(cfg)))
+    }
+
+    pub fn get(&self) -> Arc<Config> {
+        Arc::clone(&self.0.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Re-reads `config.toml`. Filters, templates, admins, delays and the like are applied,
+    /// but the whole new config is rejected if any of the settings read only at startup changed.
+    pub fn reload(&self) -> Result<(), ReloadError> {
+        let new = match Config::read() {
+            Ok(new) => new,
+            Err(err) => return Err(rejected(ReloadError::Read(err.to_string()))),
+        };
+        let old = self.get();
+        let changed: Vec<&'static str> = old
+            .fixed()
+            .into_iter()
+            .zip(new.fixed())
+            .filter(|(old, new)| old.1 != new.1)
+            .map(|(old, _)| old.0)
+            .collect();
+        if !changed.is_empty() {
+            return Err(rejected(ReloadError::Fixed(changed)));
+        }
+
+        *self.0.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(new);
+        tracing::info!("config reloaded");
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl Default for LogFormat {
+    fn default() -> Self {
+        LogFormat::Text
+    }
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -59,6 +312,460 @@ impl DbConfig {
     }
 }
 
+#[derive(Debug, serde::Deserialize)]
+pub struct IndexConfig {
+    /// `git` (clone of `index_url` at `index_path`) or `sparse` (http index at `sparse_url`)
+    #[serde(default)]
+    pub kind: IndexKind,
+    /// Url of the sparse crates.io index
+    #[serde(default = "defaults::sparse_url")]
+    pub sparse_url: String,
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self {
+            kind: IndexKind::default(),
+            sparse_url: defaults::sparse_url(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct BootstrapConfig {
+    /// `dump` (crates.io database dump) or `snapshot` (checkout of the index without its history)
+    pub source: BootstrapSource,
+    /// Directory of the extracted dump (with `data/crates.csv` and `data/versions.csv`) or of the
+    /// snapshot
+    pub path: String,
+    /// Entries seeded at once, the progress is saved with each batch
+    #[serde(default = "defaults::bootstrap_batch")]
+    pub batch: usize,
+}
+
+/// Registry following the crates.io index protocol, e.g. a company-internal one
+#[derive(Debug, serde::Deserialize)]
+pub struct RegistryConfig {
+    /// Prefix of names of the registry's crates in commands, e.g. `myreg` for `myreg:internal-crate`
+    pub name: String,
+    /// `git` (clone of `index_url` at `index_path`) or `sparse` (http index at `index_url`)
+    #[serde(default)]
+    pub kind: IndexKind,
+    /// Url of the git repository or of the sparse index
+    pub index_url: String,
+    /// The path to the local clone of a git index, `./index-{name}` by default
+    #[serde(default)]
+    pub index_path: Option<String>,
+    /// Page of a crate, `{crate}` is replaced with its name
+    pub crate_url: String,
+    /// Documentation of a version, `{crate}` and `{version}` are replaced. No docs links if not set
+    #[serde(default)]
+    pub docs_url: Option<String>,
+}
+
+impl RegistryConfig {
+    pub fn index_path(&self) -> String {
+        self.index_path
+            .clone()
+            .unwrap_or_else(|| format!("./index-{}", self.name))
+    }
+}
+
+/// Http endpoint receiving a JSON payload about every matching update
+#[derive(Debug, serde::Deserialize)]
+pub struct HookConfig {
+    /// Url the payload is `POST`ed to
+    pub url: String,
+    /// Which updates are sent
+    #[serde(flatten)]
+    pub selector: Selector,
+}
+
+/// Routing rule (see `rules`): what to do with updates matching the condition
+#[derive(Debug, serde::Deserialize)]
+pub struct RuleConfig {
+    /// Condition, e.g. `yanked and crate tokio-*`
+    pub when: Condition,
+    /// Chats and channels getting matching updates, besides the usual ones
+    #[serde(default)]
+    pub route: Vec<i64>,
+    /// Template of notifications about matching updates, for chats without their own
+    #[serde(default)]
+    pub template: Option<Template>,
+    /// Don't send matching updates anywhere but to the routes of the rules
+    #[serde(default)]
+    pub drop: bool,
+}
+
+/// Broadcast telegram channel getting the updates it selects, whether or not it subscribed to
+/// anything
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct TelegramChannelConfig {
+    /// Chat id of the channel, the bot must be an admin allowed to post in it
+    pub id: i64,
+    /// Only first versions of crates
+    #[serde(default)]
+    pub new_crates: bool,
+    /// Only crates with at least this many downloads (of all versions, on crates.io)
+    #[serde(default)]
+    pub min_downloads: Option<u64>,
+    /// Which updates are sent to the channel
+    #[serde(flatten)]
+    pub selector: Selector,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct MatrixConfig {
+    /// Url of the homeserver, e.g. `https://matrix.org`
+    pub homeserver: String,
+    /// Matrix user of the bot, e.g. `@crate_upd_bot:matrix.org`
+    pub user: String,
+    pub password: String,
+    /// Rooms notified about updates, the bot must be joined to them
+    #[serde(default, rename = "room")]
+    pub rooms: Vec<MatrixRoomConfig>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct MatrixRoomConfig {
+    /// Room id, e.g. `!abcdef:matrix.org`
+    pub id: String,
+    /// Which updates are sent to the room
+    #[serde(flatten)]
+    pub selector: Selector,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct DiscordConfig {
+    /// Token of the discord bot, needed for channels without a webhook
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Channels notified about updates
+    #[serde(default, rename = "channel")]
+    pub channels: Vec<DiscordChannelConfig>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct DiscordChannelConfig {
+    /// Webhook url of the channel, messages are sent by the bot if there is none
+    #[serde(default)]
+    pub webhook: Option<String>,
+    /// Channel id, the bot must be allowed to post in the channel
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Which updates are sent to the channel
+    #[serde(flatten)]
+    pub selector: Selector,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct BotConfig {
+    #[serde(default)]
+    pub mode: BotMode,
+    #[serde(default)]
+    pub webhook: WebhookConfig,
+    /// Username of the bot (without `@`), used in links to it
+    #[serde(default = "defaults::bot_username")]
+    pub username: String,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            mode: BotMode::default(),
+            webhook: WebhookConfig::default(),
+            username: defaults::bot_username(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BotMode {
+    /// Long polling
+    Polling,
+    /// Updates are sent by telegram to `webhook.url`
+    Webhook,
+}
+
+impl Default for BotMode {
+    fn default() -> Self {
+        BotMode::Polling
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct WebhookConfig {
+    /// Public https url telegram sends updates to (e.g. a reverse proxy terminating TLS)
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Address the webhook server listens on
+    #[serde(default = "defaults::webhook_listen")]
+    pub listen: SocketAddr,
+    /// Path the webhook server accepts updates at, a hard to guess path works as a secret
+    #[serde(default = "defaults::webhook_path")]
+    pub path: String,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            listen: defaults::webhook_listen(),
+            path: defaults::webhook_path(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct MetricsConfig {
+    /// Serve `/metrics`
+    #[serde(default)]
+    pub enabled: bool,
+    /// Address the metrics server listens on
+    #[serde(default = "defaults::metrics_listen")]
+    pub listen: SocketAddr,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: defaults::metrics_listen(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct CacheConfig {
+    /// Directory of cached responses
+    #[serde(default = "defaults::cache_dir")]
+    pub dir: String,
+    /// How long responses are used before they're revalidated (with their ETag), by source;
+    /// 0 turns caching of the source off
+    #[serde(default = "defaults::crates_io_ttl_secs")]
+    pub crates_io_ttl_secs: u64,
+    /// Changelog files and READMEs
+    #[serde(default = "defaults::changelog_ttl_secs")]
+    pub changelog_ttl_secs: u64,
+    /// GitHub/GitLab/Gitea apis: releases, tags and comparisons
+    #[serde(default = "defaults::forge_ttl_secs")]
+    pub forge_ttl_secs: u64,
+    /// How long cached releases of a crate (see `releases`) are answered from before they're
+    /// fetched again
+    #[serde(default = "defaults::releases_ttl_secs")]
+    pub releases_ttl_secs: u64,
+    /// Never refetch cached releases, e.g. while crates.io is down
+    #[serde(default)]
+    pub offline: bool,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            dir: defaults::cache_dir(),
+            crates_io_ttl_secs: defaults::crates_io_ttl_secs(),
+            changelog_ttl_secs: defaults::changelog_ttl_secs(),
+            forge_ttl_secs: defaults::forge_ttl_secs(),
+            releases_ttl_secs: defaults::releases_ttl_secs(),
+            offline: false,
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct HttpConfig {
+    /// Address the http server (feeds, unsubscribe links) listens on
+    #[serde(default = "defaults::http_listen")]
+    pub listen: SocketAddr,
+    /// Public url of the http server (e.g. of a reverse proxy), used in links sent to users
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Serve `/health`: state of the bot as json, 503 if it's stuck or the database is unreachable
+    #[serde(default)]
+    pub health: bool,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            listen: defaults::http_listen(),
+            url: None,
+            health: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct FeedConfig {
+    /// Serve `/feed/crate/{crate}.xml` and `/feed/user/{token}.xml`
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct ApiConfig {
+    /// Serve the JSON api at `/api/...`
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct EmailConfig {
+    /// SMTP relay, e.g. `smtp.example.com`
+    pub smtp_host: String,
+    pub smtp_user: String,
+    pub smtp_password: String,
+    /// Sender of e-mails, e.g. `crate_upd_bot <bot@example.com>`
+    pub from: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ChangelogConfig {
+    /// Sources of release notes, tried in order until one has notes of the version
+    #[serde(default = "defaults::changelog_sources")]
+    pub sources: Vec<SourceKind>,
+    /// Orders of sources for particular crates, replacing `sources`
+    #[serde(default)]
+    pub crates: HashMap<String, Vec<SourceKind>>,
+    /// Commits listed by the `commits` source
+    #[serde(default = "defaults::max_commits")]
+    pub max_commits: usize,
+    /// New versions announced without release notes are rechecked for them this long, and the
+    /// notifications are edited once they're found; 0 turns it off
+    #[serde(default = "defaults::notes_lag_secs")]
+    pub notes_lag_secs: u64,
+}
+
+impl Default for ChangelogConfig {
+    fn default() -> Self {
+        Self {
+            sources: defaults::changelog_sources(),
+            crates: HashMap::new(),
+            max_commits: defaults::max_commits(),
+            notes_lag_secs: defaults::notes_lag_secs(),
+        }
+    }
+}
+
+impl ChangelogConfig {
+    /// Sources tried for the crate
+    pub fn sources(&self, krate: &str) -> &[SourceKind] {
+        self.crates.get(krate).unwrap_or(&self.sources)
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct TranslationConfig {
+    pub backend: TranslatorKind,
+    /// Url of the api, the public one of the backend by default
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub api_key: Option<String>,
+}
+
+impl TranslationConfig {
+    pub fn url(&self) -> &str {
+        self.url
+            .as_deref()
+            .unwrap_or_else(|| self.backend.default_url())
+            .trim_end_matches('/')
+    }
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct CratesConfig {
+    /// Crates whose release notes and release tags aren't looked up (e.g. noisy ones publishing
+    /// every day)
+    #[serde(default)]
+    pub ignore: HashSet<String>,
+    #[serde(default)]
+    pub overrides: HashMap<String, CrateOverride>,
+}
+
+/// How release notes and tags of a crate are found, instead of guessing
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct CrateOverride {
+    /// Url of the raw changelog file, used by the `file` source instead of the repository's files
+    #[serde(default)]
+    pub changelog_url: Option<String>,
+    /// Release tag with `{version}` (and `{crate}`) placeholders, e.g. `release-{version}`
+    #[serde(default)]
+    pub tag_format: Option<String>,
+    /// The only source of release notes, replacing the orders of `[changelog]`
+    #[serde(default)]
+    pub changelog_source: Option<SourceKind>,
+    /// How versions of the crate are compared, detected from its versions by default
+    #[serde(default, deserialize_with = "version_scheme")]
+    pub version_scheme: Option<VersionScheme>,
+}
+
+fn version_scheme<'de, D>(deserializer: D) -> Result<Option<VersionScheme>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let name = <String as serde::Deserialize>::deserialize(deserializer)?;
+    VersionScheme::parse(&name).map(Some).ok_or_else(|| {
+        serde::de::Error::custom(format_args!(
+            "unknown version scheme `{}`, expected one of {}",
+            name,
+            VersionScheme::NAMES.join(", ")
+        ))
+    })
+}
+
+impl CratesConfig {
+    pub fn is_ignored(&self, krate: &str) -> bool {
+        self.ignore.contains(krate)
+    }
+
+    pub fn overrides(&self, krate: &str) -> Option<&CrateOverride> {
+        self.overrides.get(krate)
+    }
+
+    /// Tag format of the crate's releases, `None` if they're guessed
+    pub fn tag_format(&self, krate: &str) -> Option<&str> {
+        self.overrides(krate)?.tag_format.as_deref()
+    }
+
+    /// Configured version scheme of the crate, `None` if it's detected
+    pub fn version_scheme(&self, krate: &str) -> Option<VersionScheme> {
+        self.overrides(krate)?.version_scheme
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ClusterConfig {
+    /// Whether this instance watches the index and runs the bot. Exactly one instance should,
+    /// the others only send notifications about releases.
+    #[serde(default = "defaults::cluster_watch")]
+    pub watch: bool,
+    /// Name of the instance in leases of delivery jobs, random by default
+    #[serde(default)]
+    pub instance: Option<String>,
+    /// How long claimed jobs belong to the instance, they're claimed again by any instance after
+    #[serde(default = "defaults::lease_secs")]
+    pub lease_secs: u32,
+    /// How many chats an instance claims jobs of at once
+    #[serde(default = "defaults::claim_chats")]
+    pub claim_chats: u32,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct TrendingConfig {
+    /// A crate is trending when its downloads of the last week are this many times the ones of
+    /// the week before
+    #[serde(default = "defaults::trending_growth")]
+    pub growth: f64,
+    /// ...and are at least this many
+    #[serde(default = "defaults::trending_min_weekly")]
+    pub min_weekly: u64,
+    /// Total downloads announced when a crate reaches them
+    #[serde(default = "defaults::trending_milestones")]
+    pub milestones: Vec<u64>,
+}
+
 #[derive(Debug, Default, serde::Deserialize)]
 pub struct BanConfig {
     /// Names of banned crates (they won't show up in the channel)
@@ -66,21 +773,114 @@ pub struct BanConfig {
     pub crates: HashSet<String>,
 }
 
-#[derive(Clone, Copy, Debug, serde::Deserialize)]
-#[serde(transparent)]
-pub struct BroadcastDelay {
-    pub millis: u64,
+#[derive(Debug, serde::Deserialize)]
+pub struct LimitsConfig {
+    /// Crate subscriptions of a chat, 0 for no limit
+    #[serde(default = "defaults::max_subscriptions")]
+    pub max_subscriptions: usize,
+    /// Version waits (`/notify_when`) of a chat, 0 for no limit
+    #[serde(default = "defaults::max_version_waits")]
+    pub max_version_waits: usize,
+    /// Commands of a chat per minute, more are ignored; 0 for no limit
+    #[serde(default = "defaults::commands_per_minute")]
+    pub commands_per_minute: usize,
+    /// Subscribe and unsubscribe commands of a chat in `churn_window_secs`, more block changes of
+    /// subscriptions for `churn_block_secs`; 0 for no limit
+    #[serde(default = "defaults::max_subscription_changes")]
+    pub max_subscription_changes: usize,
+    #[serde(default = "defaults::churn_window_secs")]
+    pub churn_window_secs: u64,
+    #[serde(default = "defaults::churn_block_secs")]
+    pub churn_block_secs: u64,
 }
 
-impl Default for BroadcastDelay {
+impl Default for LimitsConfig {
     fn default() -> Self {
-        Self { millis: 250 } // quoter of a sec
+        Self {
+            max_subscriptions: defaults::max_subscriptions(),
+            max_version_waits: defaults::max_version_waits(),
+            commands_per_minute: defaults::commands_per_minute(),
+            max_subscription_changes: defaults::max_subscription_changes(),
+            churn_window_secs: defaults::churn_window_secs(),
+            churn_block_secs: defaults::churn_block_secs(),
+        }
     }
 }
 
-impl From<BroadcastDelay> for Duration {
-    fn from(bd: BroadcastDelay) -> Self {
-        Duration::from_millis(bd.millis)
+#[derive(Debug, serde::Deserialize)]
+pub struct PipelineConfig {
+    /// Releases announced at once (release notes and api lookups, rendering), releases of a crate
+    /// are announced one after another
+    #[serde(default = "defaults::lanes")]
+    pub lanes: usize,
+    /// Releases waiting in each lane, index watchers wait while it's full
+    #[serde(default = "defaults::lane_queue")]
+    pub queue: usize,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            lanes: defaults::lanes(),
+            queue: defaults::lane_queue(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct WorkspaceConfig {
+    /// New versions of crates sharing a repository published within this many seconds of each
+    /// other are announced by one message per chat, `0` announces every crate on its own
+    #[serde(default)]
+    pub window_secs: u64,
+    /// Longest time releases are held from the first of them, for repositories publishing
+    /// crates without a pause as long as the window
+    #[serde(default = "defaults::max_hold_secs")]
+    pub max_hold_secs: u64,
+    /// How long the repository of a crate is remembered
+    #[serde(default = "defaults::repository_ttl_secs")]
+    pub repository_ttl_secs: u64,
+}
+
+impl Default for WorkspaceConfig {
+    fn default() -> Self {
+        Self {
+            window_secs: 0,
+            max_hold_secs: defaults::max_hold_secs(),
+            repository_ttl_secs: defaults::repository_ttl_secs(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct SendConfig {
+    /// Messages per second to all chats (telegram allows about 30)
+    #[serde(default = "defaults::messages_per_second")]
+    pub messages_per_second: u32,
+    /// Minimal interval between messages to the same private chat
+    #[serde(default = "defaults::chat_interval_millis")]
+    pub chat_interval_millis: u64,
+    /// Minimal interval between messages to the same group or channel
+    #[serde(default = "defaults::group_interval_millis")]
+    pub group_interval_millis: u64,
+    /// Notifications of a chat are paused after this many messages in a row couldn't be sent
+    #[serde(default = "defaults::max_failures")]
+    pub max_failures: u32,
+    /// Notifications about releases waiting to be sent, announcing waits while there are this
+    /// many, digests are put off while there are half as many
+    #[serde(default = "defaults::send_queue")]
+    pub queue: usize,
+}
+
+impl Default for SendConfig {
+    fn default() -> Self {
+        Self {
+            messages_per_second: defaults::messages_per_second(),
+            chat_interval_millis: defaults::chat_interval_millis(),
+            group_interval_millis: defaults::group_interval_millis(),
+            max_failures: defaults::max_failures(),
+            queue: defaults::send_queue(),
+        }
     }
 }
 
@@ -113,7 +913,9 @@ impl Default for RetryDelay {
 }
 
 mod defaults {
-    use std::time::Duration;
+    use std::{net::SocketAddr, time::Duration};
+
+    use crate::changelog::SourceKind;
 
     pub(super) const fn pull_delay() -> Duration {
         Duration::from_secs(60 * 5) // 5 min
@@ -130,4 +932,165 @@ mod defaults {
     pub(super) fn index_path() -> String {
         String::from("./index")
     }
+
+    pub(super) fn changelog_sources() -> Vec<SourceKind> {
+        vec![
+            SourceKind::File,
+            SourceKind::GithubReleases,
+            SourceKind::GitlabReleases,
+            SourceKind::Commits,
+        ]
+    }
+
+    pub(super) const fn max_commits() -> usize {
+        10
+    }
+
+    pub(super) const fn notes_lag_secs() -> u64 {
+        60 * 60
+    }
+
+    pub(super) const fn summary_share() -> u8 {
+        100
+    }
+
+    pub(super) const fn cluster_watch() -> bool {
+        true
+    }
+
+    pub(super) const fn lease_secs() -> u32 {
+        60 * 5 // 5 min
+    }
+
+    pub(super) const fn claim_chats() -> u32 {
+        100
+    }
+
+    pub(super) const fn trending_growth() -> f64 {
+        2.0
+    }
+
+    pub(super) const fn trending_min_weekly() -> u64 {
+        1000
+    }
+
+    pub(super) fn trending_milestones() -> Vec<u64> {
+        vec![10_000, 100_000, 1_000_000, 10_000_000, 100_000_000]
+    }
+
+    pub(super) fn webhook_listen() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 8080))
+    }
+
+    pub(super) fn metrics_listen() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 9090))
+    }
+
+    pub(super) fn http_listen() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 8081))
+    }
+
+    pub(super) fn webhook_path() -> String {
+        String::from("/")
+    }
+
+    pub(super) fn bot_username() -> String {
+        String::from("crates_upd_bot")
+    }
+
+    pub(super) const fn fetch_changelogs() -> bool {
+        true
+    }
+
+    pub(super) const fn source_diffs() -> bool {
+        true
+    }
+
+    pub(super) fn sparse_url() -> String {
+        String::from("https://index.crates.io")
+    }
+
+    pub(super) const fn messages_per_second() -> u32 {
+        25
+    }
+
+    pub(super) const fn chat_interval_millis() -> u64 {
+        1000
+    }
+
+    pub(super) const fn group_interval_millis() -> u64 {
+        3000 // 20 messages per minute
+    }
+
+    pub(super) const fn max_failures() -> u32 {
+        5
+    }
+
+    pub(super) const fn send_queue() -> usize {
+        1000
+    }
+
+    pub(super) const fn lanes() -> usize {
+        4
+    }
+
+    pub(super) const fn lane_queue() -> usize {
+        16
+    }
+
+    pub(super) const fn bootstrap_batch() -> usize {
+        5000
+    }
+
+    pub(super) const fn max_hold_secs() -> u64 {
+        30 * 60 // 30 minutes
+    }
+
+    pub(super) const fn repository_ttl_secs() -> u64 {
+        24 * 60 * 60 // 1 day
+    }
+
+    pub(super) fn cache_dir() -> String {
+        String::from("./cache")
+    }
+
+    pub(super) const fn crates_io_ttl_secs() -> u64 {
+        60 * 10 // 10 min
+    }
+
+    pub(super) const fn changelog_ttl_secs() -> u64 {
+        60 * 5 // 5 min
+    }
+
+    pub(super) const fn forge_ttl_secs() -> u64 {
+        60 * 5 // 5 min
+    }
+
+    pub(super) const fn releases_ttl_secs() -> u64 {
+        60 * 10 // 10 min
+    }
+
+    pub(super) const fn max_subscriptions() -> usize {
+        1000
+    }
+
+    pub(super) const fn max_version_waits() -> usize {
+        100
+    }
+
+    pub(super) const fn commands_per_minute() -> usize {
+        20
+    }
+
+    pub(super) const fn max_subscription_changes() -> usize {
+        30
+    }
+
+    pub(super) const fn churn_window_secs() -> u64 {
+        60 * 10 // 10 min
+    }
+
+    pub(super) const fn churn_block_secs() -> u64 {
+        60 * 60 // 1 hour
+    }
 }
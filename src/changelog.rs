@@ -0,0 +1,699 @@
+//! Release notes of new versions: from `CHANGELOG.md` of the crate's repository, from releases
+//! on GitHub/GitLab or from commits between the release tags, tried in the order of the config
+use std::time::Instant;
+
+use kacl_parser::{render::strip_markdown, ParseOptions, Release, ReleaseStream, Section};
+use reqwest::{header::AUTHORIZATION, Client};
+use tracing::Instrument;
+use versions::SemVer;
+
+use crate::{
+    cache::{self, Source},
+    cfg::Config,
+    cratesio::repository,
+    metrics,
+    render::escape,
+    repo::{self, ForgeKind, Repo},
+    util::http_client,
+};
+
+/// Names of changelog files tried in order
+const FILENAMES: [&str; 5] = [
+    "CHANGELOG.md",
+    "Changelog.md",
+    "changelog.md",
+    "CHANGES.md",
+    "HISTORY.md",
+];
+
+/// Url of the raw `file` from the default branch, `None` if the forge isn't supported
+fn raw_url(repository: &str, file: &str) -> Option<String> {
+    Some(Repo::parse(repository)?.raw_url(file))
+}
+
+/// Finds the release in the changelog, accepting common deviations from keepachangelog and
+/// changelogs generated from conventional commits. Only releases down to the wanted one are
+/// parsed, new versions are usually at the top.
+fn find_release(krate: &str, src: &str, version: &SemVer) -> Option<Release> {
+    let span = tracing::debug_span!(
+        "changelog_parse",
+        krate = %krate,
+        bytes = src.len(),
+        releases = tracing::field::Empty,
+        sections = tracing::field::Empty,
+    );
+    let _enter = span.enter();
+    let start = Instant::now();
+    let mut changelog = ReleaseStream::with_options(src.as_bytes(), ParseOptions::detect(src));
+    let mut parsed: usize = 0;
+    let release = changelog
+        .by_ref()
+        .inspect(|_| parsed += 1)
+        .find(|release| release.version.semver() == Some(version));
+    let elapsed = start.elapsed();
+
+    metrics::CHANGELOG_PARSE_DURATION.observe(elapsed.as_secs_f64());
+    metrics::CHANGELOG_RELEASES_PARSED.observe(parsed as f64);
+    span.record("releases", &parsed);
+    if let Some(release) = &release {
+        metrics::CHANGELOG_SECTIONS.observe(release.sections.len() as f64);
+        span.record("sections", &release.sections.len());
+    }
+    tracing::debug!(
+        "parsed changelog of {} ({} bytes) in {:?}: release {} {}, {} warnings",
+        krate,
+        src.len(),
+        elapsed,
+        version,
+        if release.is_some() {
+            "found"
+        } else {
+            "not found"
+        },
+        changelog.warnings().len(),
+    );
+    for warning in changelog.warnings() {
+        tracing::trace!("changelog of {}: {}", krate, warning);
+    }
+
+    release
+}
+
+/// Telegram html of the release sections
+fn html(release: &Release) -> String {
+    let mut out = String::new();
+    for section in &release.sections {
+        if !section.name.is_empty() {
+            out.push_str(&format!("\n<b>{}</b>\n", escape(&section.name)));
+        }
+        for entry in &section.entries {
+            out.push_str(&format!("• {}\n", escape(&strip_markdown(entry))));
+        }
+    }
+
+    out.trim().to_owned()
+}
+
+/// Kind of [`ChangelogSource`] in the config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SourceKind {
+    /// `CHANGELOG.md` (or a similar file) on the default branch
+    File,
+    /// Body of the GitHub release of the version's tag
+    GithubReleases,
+    /// Description of the GitLab release of the version's tag
+    GitlabReleases,
+    /// Summary of commits between the tags of the previous and the new version
+    Commits,
+}
+
+impl SourceKind {
+    /// Name in the config and in metrics
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SourceKind::File => "file",
+            SourceKind::GithubReleases => "github-releases",
+            SourceKind::GitlabReleases => "gitlab-releases",
+            SourceKind::Commits => "commits",
+        }
+    }
+
+    fn source(self) -> &'static (dyn ChangelogSource + Sync) {
+        match self {
+            SourceKind::File => &ChangelogFile,
+            SourceKind::GithubReleases => &GitHubReleases,
+            SourceKind::GitlabReleases => &GitLabReleases,
+            SourceKind::Commits => &CommitLog,
+        }
+    }
+}
+
+/// The version notes are looked up for
+pub struct Lookup<'a> {
+    pub krate: &'a str,
+    /// The newest of `versions`
+    pub version: &'a SemVer,
+    /// Versions released in a row (a train), oldest first; usually only `version`
+    pub versions: &'a [SemVer],
+    /// The newest version older than all of `versions`, if any
+    pub previous: Option<&'a SemVer>,
+    /// Repository url from crates.io metadata, empty if the crate has none
+    pub repository: &'a str,
+    /// Changelog file from the crate's overrides, replacing the files of the repository
+    pub changelog_url: Option<&'a str>,
+    /// Release tag format from the crate's overrides, see [`repo::tags`]
+    pub tag_format: Option<&'a str>,
+    pub client: &'a Client,
+    pub github_token: Option<&'a str>,
+    /// Commits listed by [`CommitLog`]
+    pub max_commits: usize,
+}
+
+/// Place release notes are found in
+#[async_trait::async_trait]
+pub trait ChangelogSource {
+    /// Notes of the version as telegram html, `None` if the source doesn't have them
+    async fn notes(&self, lookup: &Lookup<'_>) -> Option<String>;
+}
+
+/// Changelog file of the repository (see [`FILENAMES`]) or the one from the crate's overrides
+pub struct ChangelogFile;
+
+#[async_trait::async_trait]
+impl ChangelogSource for ChangelogFile {
+    async fn notes(&self, lookup: &Lookup<'_>) -> Option<String> {
+        let urls: Vec<String> = match lookup.changelog_url {
+            Some(url) => vec![url.to_owned()],
+            None => FILENAMES
+                .iter()
+                .map(|file| raw_url(lookup.repository, file))
+                .collect::<Option<_>>()?,
+        };
+        for url in urls {
+            let request = lookup.client.get(&url);
+            let src = match cache::get(lookup.client, request, Source::Changelog).await {
+                Ok(response) if response.status.is_success() => response.body,
+                _ => continue,
+            };
+
+            let releases = lookup
+                .versions
+                .iter()
+                .filter_map(|version| find_release(lookup.krate, &src, version))
+                .collect();
+            return merged_html(releases);
+        }
+
+        None
+    }
+}
+
+/// Releases of a GitHub repository, found by the tag of the version
+pub struct GitHubReleases;
+
+#[derive(serde::Deserialize)]
+struct GitHubRelease {
+    body: Option<String>,
+}
+
+impl GitHubReleases {
+    async fn release(lookup: &Lookup<'_>, path: &str, version: &SemVer) -> Option<Release> {
+        for tag in repo::tags(lookup.krate, &version.to_string(), lookup.tag_format).iter() {
+            let request = lookup.client.get(&format!(
+                "https://api.github.com/repos/{}/releases/tags/{}",
+                path,
+                repo::encode(tag)
+            ));
+            let request = match lookup.github_token {
+                Some(token) => request.header(AUTHORIZATION, format!("token {}", token)),
+                None => request,
+            };
+            let release: GitHubRelease =
+                match cache::get(lookup.client, request, Source::Forge).await {
+                    Ok(response) if response.status.is_success() => match response.json() {
+                        Ok(release) => release,
+                        Err(_) => continue,
+                    },
+                    _ => continue,
+                };
+
+            return release_from_body(lookup.krate, &release.body?, version);
+        }
+
+        None
+    }
+}
+
+#[async_trait::async_trait]
+impl ChangelogSource for GitHubReleases {
+    async fn notes(&self, lookup: &Lookup<'_>) -> Option<String> {
+        let repo = Repo::parse(lookup.repository)?;
+        if repo.kind != ForgeKind::GitHub {
+            return None;
+        }
+
+        let mut releases = Vec::new();
+        for version in lookup.versions {
+            releases.extend(Self::release(lookup, &repo.path, version).await);
+        }
+        merged_html(releases)
+    }
+}
+
+/// Releases of a GitLab project (on gitlab.com or a self-hosted instance), found by the tag of
+/// the version
+pub struct GitLabReleases;
+
+#[derive(serde::Deserialize)]
+struct GitLabRelease {
+    description: Option<String>,
+}
+
+impl GitLabReleases {
+    async fn release(lookup: &Lookup<'_>, repo: &Repo, version: &SemVer) -> Option<Release> {
+        for tag in repo::tags(lookup.krate, &version.to_string(), lookup.tag_format).iter() {
+            let url = format!("{}/releases/{}", repo.gitlab_api(), repo::encode(tag));
+            let request = lookup.client.get(&url);
+            let release: GitLabRelease =
+                match cache::get(lookup.client, request, Source::Forge).await {
+                    Ok(response) if response.status.is_success() => match response.json() {
+                        Ok(release) => release,
+                        Err(_) => continue,
+                    },
+                    _ => continue,
+                };
+
+            return release_from_body(lookup.krate, &release.description?, version);
+        }
+
+        None
+    }
+}
+
+#[async_trait::async_trait]
+impl ChangelogSource for GitLabReleases {
+    async fn notes(&self, lookup: &Lookup<'_>) -> Option<String> {
+        let repo = Repo::parse(lookup.repository)?;
+        if repo.kind != ForgeKind::GitLab {
+            return None;
+        }
+
+        let mut releases = Vec::new();
+        for version in lookup.versions {
+            releases.extend(Self::release(lookup, &repo, version).await);
+        }
+        merged_html(releases)
+    }
+}
+
+/// First lines of commits between the release tags, for crates without any changelog
+pub struct CommitLog;
+
+#[derive(serde::Deserialize)]
+struct GitHubComparison {
+    commits: Vec<GitHubCommit>,
+}
+
+#[derive(serde::Deserialize)]
+struct GitHubCommit {
+    commit: GitHubCommitData,
+}
+
+#[derive(serde::Deserialize)]
+struct GitHubCommitData {
+    message: String,
+}
+
+#[derive(serde::Deserialize)]
+struct GitLabComparison {
+    commits: Vec<GitLabCommit>,
+}
+
+#[derive(serde::Deserialize)]
+struct GitLabCommit {
+    title: String,
+}
+
+impl CommitLog {
+    /// Messages of commits between the tags, oldest first. `None` if a tag doesn't exist.
+    async fn messages(
+        repo: &Repo,
+        lookup: &Lookup<'_>,
+        from: &str,
+        to: &str,
+    ) -> Option<Vec<String>> {
+        match repo.kind {
+            // both respond in the format of GitHub, Gitea since 1.22
+            ForgeKind::GitHub | ForgeKind::Gitea => {
+                let request = lookup.client.get(&match repo.kind {
+                    ForgeKind::GitHub => format!(
+                        "https://api.github.com/repos/{}/compare/{}...{}",
+                        repo.path,
+                        repo::encode(from),
+                        repo::encode(to)
+                    ),
+                    _ => format!(
+                        "https://{}/api/v1/repos/{}/compare/{}...{}",
+                        repo.host,
+                        repo.path,
+                        repo::encode(from),
+                        repo::encode(to)
+                    ),
+                });
+                let request = match (repo.kind, lookup.github_token) {
+                    (ForgeKind::GitHub, Some(token)) => {
+                        request.header(AUTHORIZATION, format!("token {}", token))
+                    }
+                    _ => request,
+                };
+                let response = cache::get(lookup.client, request, Source::Forge)
+                    .await
+                    .ok()?;
+                if !response.status.is_success() {
+                    return None;
+                }
+                let comparison: GitHubComparison = response.json().ok()?;
+                Some(
+                    comparison
+                        .commits
+                        .into_iter()
+                        .map(|commit| commit.commit.message)
+                        .collect(),
+                )
+            }
+            ForgeKind::GitLab => {
+                let request = lookup
+                    .client
+                    .get(&format!("{}/repository/compare", repo.gitlab_api()))
+                    .query(&[("from", from), ("to", to)]);
+                let response = cache::get(lookup.client, request, Source::Forge)
+                    .await
+                    .ok()?;
+                if !response.status.is_success() {
+                    return None;
+                }
+                let comparison: GitLabComparison = response.json().ok()?;
+                Some(
+                    comparison
+                        .commits
+                        .into_iter()
+                        .map(|commit| commit.title)
+                        .collect(),
+                )
+            }
+            // sourcehut has no compare api
+            ForgeKind::SourceHut => None,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ChangelogSource for CommitLog {
+    async fn notes(&self, lookup: &Lookup<'_>) -> Option<String> {
+        let repo = Repo::parse(lookup.repository)?;
+        let previous = lookup.previous?.to_string();
+        let version = lookup.version.to_string();
+
+        let pairs = repo::tags(lookup.krate, &previous, lookup.tag_format);
+        let tags = repo::tags(lookup.krate, &version, lookup.tag_format);
+        for (from, to) in pairs.iter().zip(&tags) {
+            if let Some(messages) = Self::messages(&repo, lookup, from, to).await {
+                return commit_summary(&messages, lookup.max_commits);
+            }
+        }
+
+        None
+    }
+}
+
+/// Telegram html list of the first lines of the newest `max` commits, labeled as generated
+fn commit_summary(messages: &[String], max: usize) -> Option<String> {
+    let lines: Vec<&str> = messages
+        .iter()
+        .filter_map(|message| message.lines().next())
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    if lines.is_empty() || max == 0 {
+        return None;
+    }
+
+    let mut out = String::from("<i>No changelog, generated from commits:</i>\n");
+    let skipped = lines.len().saturating_sub(max);
+    for line in lines.iter().rev().take(max) {
+        out.push_str(&format!("• {}\n", escape(line)));
+    }
+    if skipped > 0 {
+        out.push_str(&format!("…and {} more\n", skipped));
+    }
+
+    Some(out.trim().to_owned())
+}
+
+/// Parses the markdown body of a forge release like a changelog entry of the version.
+/// Headings of any level become sections, lists outside of them go to an unnamed one.
+fn release_from_body(krate: &str, body: &str, version: &SemVer) -> Option<Release> {
+    let mut src = format!("## {}\n\n", version);
+    let mut fenced = false;
+    for line in body.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            fenced = !fenced;
+        }
+        let heading = trimmed.trim_start_matches('#');
+        if !fenced && heading.len() < trimmed.len() && heading.starts_with(' ') {
+            src.push_str("### ");
+            src.push_str(heading.trim());
+        } else {
+            src.push_str(line);
+        }
+        src.push('\n');
+    }
+
+    find_release(krate, &src, version)
+}
+
+/// Entries of releases (oldest first) in sections of the same name, the newest entries first
+fn merge(releases: Vec<Release>) -> Option<Release> {
+    let mut releases = releases.into_iter().rev();
+    let mut merged = releases.next()?;
+    for release in releases {
+        for section in release.sections {
+            match merged.sections.iter_mut().find(|s| s.name == section.name) {
+                Some(Section { entries, .. }) => entries.extend(section.entries),
+                None => merged.sections.push(section),
+            }
+        }
+    }
+
+    Some(merged)
+}
+
+/// Telegram html of the merged releases, `None` if there are no entries
+fn merged_html(releases: Vec<Release>) -> Option<String> {
+    merge(releases)
+        .map(|release| html(&release))
+        .filter(|html| !html.is_empty())
+}
+
+/// Notes of the version in a changelog file as telegram html, `None` if there are none.
+/// `name` of the file is only used in logs.
+pub fn file_notes(name: &str, src: &str, version: &SemVer) -> Option<String> {
+    merged_html(find_release(name, src, version).into_iter().collect())
+}
+
+/// Notes of the `versions` (a train of releases, oldest first, usually one version) from the
+/// first of the configured sources which has them, as telegram html.
+///
+/// `None` if the repository isn't on a supported forge (and there is no changelog url in the crate's
+/// overrides), the crate is ignored or no source has notes of the versions.
+/// `previous` is the newest version before them, commits since its tag are a last resort.
+/// The span gets the source the notes were found in.
+#[tracing::instrument(name = "changelog_fetch", skip(cfg), fields(source = tracing::field::Empty))]
+pub async fn release_notes(
+    krate: &str,
+    versions: &[&str],
+    previous: Option<&SemVer>,
+    cfg: &Config,
+) -> Option<String> {
+    let notes = find_notes(krate, versions, previous, cfg).await;
+    let result = if notes.is_some() {
+        "found"
+    } else {
+        "not_found"
+    };
+    metrics::CHANGELOG_FETCHES
+        .with_label_values(&[result])
+        .inc();
+    notes
+}
+
+async fn find_notes(
+    krate: &str,
+    versions: &[&str],
+    previous: Option<&SemVer>,
+    cfg: &Config,
+) -> Option<String> {
+    let versions: Vec<SemVer> = versions
+        .iter()
+        .map(|version| SemVer::new(version))
+        .collect::<Option<_>>()?;
+    let version = versions.last()?.clone();
+    if cfg.crates.is_ignored(krate) {
+        tracing::debug!("{} is ignored", krate);
+        return None;
+    }
+    let overrides = cfg.crates.overrides(krate);
+    let changelog_url = overrides.and_then(|o| o.changelog_url.as_deref());
+    let client = http_client()
+        .map_err(|err| tracing::error!("couldn't create http client: {}", err))
+        .ok()?;
+    let repository = repository(&client, krate)
+        .await
+        .map_err(|err| tracing::warn!("couldn't get repository of {}: {}", krate, err))
+        .ok()
+        .flatten();
+    let repository = match (repository, changelog_url) {
+        (Some(repository), _) => repository,
+        (None, Some(_)) => String::new(),
+        (None, None) => return None,
+    };
+    let lookup = Lookup {
+        krate,
+        version: &version,
+        versions: &versions,
+        previous,
+        repository: &repository,
+        changelog_url,
+        tag_format: cfg.crates.tag_format(krate),
+        client: &client,
+        github_token: cfg.github_token.as_deref(),
+        max_commits: cfg.changelog.max_commits,
+    };
+
+    let sources = match overrides.and_then(|o| o.changelog_source.as_ref()) {
+        Some(source) => std::slice::from_ref(source),
+        None => cfg.changelog.sources(krate),
+    };
+    for kind in sources {
+        let span = tracing::debug_span!("changelog_source", source = kind.as_str());
+        let notes = kind.source().notes(&lookup).instrument(span).await;
+        let result = if notes.is_some() {
+            "found"
+        } else {
+            "not_found"
+        };
+        metrics::CHANGELOG_SOURCES
+            .with_label_values(&[kind.as_str(), result])
+            .inc();
+        if let Some(notes) = notes {
+            tracing::debug!("notes of {} {} found in {:?}", krate, version, kind);
+            tracing::Span::current().record("source", &kind.as_str());
+            return Some(notes);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> SemVer {
+        SemVer::new(s).unwrap()
+    }
+
+    #[test]
+    fn raw_urls() {
+        assert_eq!(
+            raw_url("https://github.com/serde-rs/serde", "CHANGELOG.md").as_deref(),
+            Some("https://raw.githubusercontent.com/serde-rs/serde/HEAD/CHANGELOG.md")
+        );
+        assert_eq!(
+            raw_url(
+                "https://github.com/tokio-rs/tokio/tree/master/tokio/",
+                "CHANGELOG.md"
+            )
+            .as_deref(),
+            Some("https://raw.githubusercontent.com/tokio-rs/tokio/HEAD/CHANGELOG.md")
+        );
+        assert_eq!(
+            raw_url("https://gitlab.com/group/project.git", "CHANGES.md").as_deref(),
+            Some("https://gitlab.com/group/project/-/raw/HEAD/CHANGES.md")
+        );
+        assert_eq!(raw_url("https://example.com/repo", "CHANGELOG.md"), None);
+    }
+
+    #[test]
+    fn notes() {
+        let src = "# Changelog\n\
+                   \n\
+                   ## v1.1.0 (2021-06-01)\n\
+                   \n\
+                   ### Added\n\
+                   \n\
+                   - `Foo::bar` for *`a < b`*\n\
+                   \n\
+                   ## 1.0.0 - 2021-01-01\n";
+        let release = find_release("foo", src, &SemVer::new("1.1.0").unwrap()).unwrap();
+        assert_eq!(html(&release), "<b>Added</b>\n• Foo::bar for a &lt; b");
+    }
+
+    #[test]
+    fn commit_summaries() {
+        let messages = vec![
+            String::from("Fix <T as Trait> bound\n\nLong description"),
+            String::from("Bump deps"),
+            String::from("Release 1.1.0"),
+        ];
+        assert_eq!(
+            commit_summary(&messages, 2).as_deref(),
+            Some(
+                "<i>No changelog, generated from commits:</i>\n\
+                 • Release 1.1.0\n\
+                 • Bump deps\n\
+                 …and 1 more"
+            )
+        );
+        assert_eq!(commit_summary(&[], 10), None);
+    }
+
+    #[test]
+    fn release_bodies() {
+        let body = "## What's Changed\r\n\
+                    * Support `no_std` by @someone in #12\r\n\
+                    \r\n\
+                    ```rust\r\n\
+                    # fn main() {}\r\n\
+                    ```\r\n\
+                    **Full Changelog**: v1.0.0...v1.1.0";
+        let notes = |body| {
+            merged_html(
+                release_from_body("foo", body, &v("1.1.0"))
+                    .into_iter()
+                    .collect(),
+            )
+        };
+        assert_eq!(
+            notes(body).as_deref(),
+            Some("<b>What's Changed</b>\n• Support no_std by @someone in #12")
+        );
+        assert_eq!(notes("Bug fixes."), None);
+    }
+
+    #[test]
+    fn trains() {
+        let src = "## 1.2.3\n\
+                   \n\
+                   ### Fixed\n\
+                   \n\
+                   - Missing file in the package\n\
+                   \n\
+                   ## 1.2.2\n\
+                   \n\
+                   ### Added\n\
+                   \n\
+                   - `Foo::baz`\n\
+                   \n\
+                   ### Fixed\n\
+                   \n\
+                   - Panic on empty input\n";
+        let releases = vec![
+            find_release("foo", src, &v("1.2.2")).unwrap(),
+            find_release("foo", src, &v("1.2.3")).unwrap(),
+        ];
+        assert_eq!(
+            merged_html(releases).as_deref(),
+            Some(
+                "<b>Fixed</b>\n\
+                 • Missing file in the package\n\
+                 • Panic on empty input\n\
+                 \n\
+                 <b>Added</b>\n\
+                 • Foo::baz"
+            )
+        );
+    }
+}
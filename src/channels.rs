@@ -0,0 +1,99 @@
+//! Broadcast telegram channels of the operator (`[[telegram_channel]]` of the config), e.g. one
+//! with all new crates and ones per topic. They aren't subscribers: which updates a channel gets
+//! is set by the config only, and posts to it don't touch subscription records.
+use kacl_parser::VersionScheme;
+
+use crate::{
+    cfg::{Config, TelegramChannelConfig},
+    cratesio,
+    krate::Crate,
+    util::http_client,
+    ActionKind,
+};
+
+/// Whether the update passes the conditions of the channel besides its selector. `is_new` tells
+/// whether it's the first version of the crate, `downloads` are `None` if they're unknown.
+fn passes(channel: &TelegramChannelConfig, is_new: bool, downloads: Option<u64>) -> bool {
+    if channel.new_crates && !is_new {
+        return false;
+    }
+    match channel.min_downloads {
+        Some(min) => downloads.map_or(false, |downloads| downloads >= min),
+        None => true,
+    }
+}
+
+/// Downloads of the crate on crates.io, `None` if they couldn't be fetched
+async fn downloads(krate: &Crate) -> Option<u64> {
+    let client = http_client()
+        .map_err(|err| tracing::error!("couldn't create http client: {}", err))
+        .ok()?;
+    cratesio::downloads(&client, &krate.id.name)
+        .await
+        .map_err(|err| tracing::warn!("couldn't get downloads of {}: {}", krate.id.name, err))
+        .ok()
+}
+
+/// Channels getting the update of a crates.io crate, `previous` is the newest version older than
+/// `version` (`None` for the first version). Downloads are looked up only if a selected channel
+/// needs them.
+pub async fn selected<'a>(
+    cfg: &'a Config,
+    krate: &Crate,
+    action: &ActionKind,
+    scheme: VersionScheme,
+    previous: Option<&Crate>,
+) -> Vec<&'a TelegramChannelConfig> {
+    let key = krate.key();
+    let is_yank = matches!(action, ActionKind::Yanked | ActionKind::Unyanked);
+    let is_new = matches!(action, ActionKind::NewVersion) && previous.is_none();
+    let previous = previous.map(|previous| previous.id.vers.as_str());
+    let mut channels: Vec<&TelegramChannelConfig> = cfg
+        .telegram_channels
+        .iter()
+        .filter(|channel| {
+            (!channel.new_crates || is_new)
+                && channel
+                    .selector
+                    .matches(&key, is_yank, scheme, &krate.id.vers, previous)
+        })
+        .collect();
+
+    let downloads = if channels
+        .iter()
+        .any(|channel| channel.min_downloads.is_some())
+    {
+        downloads(krate).await
+    } else {
+        None
+    };
+    channels.retain(|channel| passes(channel, is_new, downloads));
+    channels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conditions() {
+        let all = TelegramChannelConfig::default();
+        assert!(passes(&all, false, None));
+
+        let new = TelegramChannelConfig {
+            new_crates: true,
+            ..Default::default()
+        };
+        assert!(passes(&new, true, None));
+        assert!(!passes(&new, false, None));
+
+        let popular = TelegramChannelConfig {
+            min_downloads: Some(10_000),
+            ..Default::default()
+        };
+        assert!(passes(&popular, false, Some(12_345)));
+        assert!(!passes(&popular, false, Some(9_999)));
+        // unknown downloads
+        assert!(!passes(&popular, false, None));
+    }
+}
@@ -0,0 +1,49 @@
+//! Cluster mode: several instances share the database. The watching instance turns telegram
+//! notifications about releases into delivery jobs, every instance claims jobs of some chats
+//! with a lease and sends them. A sent job is acknowledged by recording its delivery, jobs of
+//! a crashed instance are claimed again once their lease expires.
+use std::{sync::Arc, time::Duration};
+
+use crate::{cfg::Config, db::Database, send::SendQueue, shutdown::Shutdown, util::random_token};
+
+/// Delay between claims when there are no jobs
+const IDLE_DELAY: Duration = Duration::from_secs(5);
+
+/// Claims and sends delivery jobs until shutdown, does nothing outside of cluster mode
+pub async fn work(db: Database, queue: SendQueue, cfg: Arc<Config>, mut shutdown: Shutdown) {
+    let cluster = match &cfg.cluster {
+        Some(cluster) => cluster,
+        None => return,
+    };
+    let instance = match &cluster.instance {
+        Some(instance) => instance.clone(),
+        None => random_token().expect("couldn't generate the instance name"),
+    };
+    tracing::info!("sending delivery jobs as {}", instance);
+
+    loop {
+        let jobs = db
+            .claim_deliveries(&instance, cluster.claim_chats, cluster.lease_secs)
+            .await
+            .map_err(|err| tracing::error!("db error while claiming deliveries: {}", err))
+            .unwrap_or_default();
+        let idle = jobs.is_empty();
+        for job in jobs {
+            queue
+                .push_receipted(job.chat_id, job.text, job.quiet, job.receipt)
+                .await;
+        }
+        // the lease should outlast sending, jobs are acknowledged by the queue
+        queue.flush().await;
+
+        if idle {
+            tokio::select! {
+                _ = tokio::time::delay_for(IDLE_DELAY) => {}
+                _ = shutdown.requested() => {}
+            }
+        }
+        if shutdown.is_requested() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! `/compare foo bar`: latest versions, release cadence, downloads, MSRV, license and dependency
+//! counts of two crates side by side, from the index and the (cached) crates.io api
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use reqwest::Client;
+
+use crate::{
+    cfg::Config,
+    cratesio::{self, compact},
+    krate::{split_key, Versions},
+    releases,
+    render::escape,
+    util::http_client,
+};
+
+/// Values longer than this are cut, so the table fits the screen of a phone
+const MAX_CELL: usize = 14;
+
+const YEAR_SECS: i64 = 365 * 24 * 60 * 60;
+
+/// What is compared of a crate. Api data is `None` for crates of alternative registries and when
+/// crates.io couldn't be reached.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub name: String,
+    /// The newest version which isn't yanked (the newest one if all are)
+    pub latest: String,
+    /// Versions published in the last 365 days
+    pub releases_last_year: Option<usize>,
+    pub downloads: Option<u64>,
+    /// `rust-version` of the latest version
+    pub msrv: Option<String>,
+    pub license: Option<String>,
+    /// Required and optional dependencies of the latest version, dev-dependencies aren't counted
+    pub deps: (usize, usize),
+    /// Unix time the versions were fetched at if they're stale, see [`Versions::stale`]
+    pub stale: Option<u64>,
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// Summary of the crate (`myreg:name` for alternative registries), `None` if there is no such crate
+async fn summary(client: Option<&Client>, key: &str, cfg: &Config) -> Option<Summary> {
+    let versions = Versions::read(key, cfg)
+        .await
+        .map_err(|err| tracing::debug!("couldn't read versions of {}: {}", key, err))
+        .ok()?;
+    let stale = versions.stale;
+    let latest = versions.latest.unwrap_or(versions.newest);
+    let deps = latest.deps.iter().filter(|dep| !dep.is_dev());
+    let optional = deps.clone().filter(|dep| dep.optional).count();
+    let mut summary = Summary {
+        name: key.to_owned(),
+        deps: (deps.count() - optional, optional),
+        msrv: latest.rust_version.clone(),
+        latest: latest.id.vers,
+        stale,
+        ..Summary::default()
+    };
+
+    let client = match (client, split_key(key)) {
+        (Some(client), (None, _)) => client,
+        _ => return Some(summary),
+    };
+    match cratesio::metadata(client, key, &summary.latest).await {
+        Ok(Some(metadata)) => {
+            summary.downloads = Some(metadata.downloads);
+            summary.license = metadata.license;
+            summary.msrv = summary.msrv.or(metadata.rust_version);
+        }
+        Ok(None) => {}
+        Err(err) => tracing::warn!("couldn't get metadata of {}: {}", key, err),
+    }
+    match cratesio::publishes(client, key, &summary.latest).await {
+        Ok(publishes) => {
+            let since = now() - YEAR_SECS;
+            let releases = publishes.iter().filter(|p| p.created_at >= since).count();
+            summary.releases_last_year = Some(releases);
+        }
+        Err(err) => tracing::warn!("couldn't get publishes of {}: {}", key, err),
+    }
+
+    Some(summary)
+}
+
+/// The value cut to [`MAX_CELL`] chars
+fn cell(value: &str) -> String {
+    if value.chars().count() <= MAX_CELL {
+        value.to_owned()
+    } else {
+        let cut: String = value.chars().take(MAX_CELL - 1).collect();
+        format!("{}…", cut)
+    }
+}
+
+/// Telegram html table (monospace) of the crates' summaries, `—` for unknown values
+fn html(a: &Summary, b: &Summary) -> String {
+    let unknown = || String::from("—");
+    let row = |summary: &Summary| {
+        vec![
+            summary.name.clone(),
+            summary.latest.clone(),
+            summary
+                .releases_last_year
+                .map_or_else(unknown, |n| n.to_string()),
+            summary.downloads.map_or_else(unknown, compact),
+            summary.msrv.clone().unwrap_or_else(unknown),
+            summary.license.clone().unwrap_or_else(unknown),
+            match summary.deps {
+                (required, 0) => required.to_string(),
+                (required, optional) => format!("{} +{} opt", required, optional),
+            },
+        ]
+    };
+    let labels = [
+        "",
+        "latest",
+        "releases/yr",
+        "downloads",
+        "MSRV",
+        "license",
+        "deps",
+    ];
+    let (a, b) = (row(a), row(b));
+    let width = a
+        .iter()
+        .map(|value| cell(value).chars().count())
+        .max()
+        .unwrap_or(0);
+    let lines: Vec<String> = labels
+        .iter()
+        .zip(a.iter().zip(&b))
+        .map(|(label, (a, b))| {
+            let line = format!(
+                "{:<11} {:<width$}  {}",
+                label,
+                cell(a),
+                cell(b),
+                width = width
+            );
+            escape(line.trim_end())
+        })
+        .collect();
+
+    format!("<pre>{}</pre>", lines.join("\n"))
+}
+
+/// Reply to `/compare a b`
+pub async fn text(cfg: &Config, a: &str, b: &str) -> String {
+    let client = http_client()
+        .map_err(|err| tracing::error!("couldn't create http client: {}", err))
+        .ok();
+    let (first, second) = futures::join!(
+        summary(client.as_ref(), a, cfg),
+        summary(client.as_ref(), b, cfg)
+    );
+    match (first, second) {
+        (Some(first), Some(second)) => {
+            let mut text = format!(
+                "{}\nReleases/yr are versions published in the last 365 days, dev-dependencies aren't counted.",
+                html(&first, &second)
+            );
+            // the older of the fetches if versions of a crate are stale
+            if let Some(fetched_at) = [first.stale, second.stale].iter().flatten().min() {
+                text.push_str(&format!("\n\n{}", releases::note(*fetched_at)));
+            }
+            text
+        }
+        (None, _) => format!("Error: there is no such crate <code>{}</code>.", escape(a)),
+        (_, None) => format!("Error: there is no such crate <code>{}</code>.", escape(b)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table() {
+        let serde = Summary {
+            name: String::from("serde"),
+            latest: String::from("1.0.130"),
+            releases_last_year: Some(12),
+            downloads: Some(123_456_789),
+            msrv: Some(String::from("1.15")),
+            license: Some(String::from("MIT OR Apache-2.0")),
+            deps: (1, 1),
+            stale: None,
+        };
+        let internal = Summary {
+            name: String::from("myreg:internal"),
+            latest: String::from("0.3.0"),
+            deps: (4, 0),
+            ..Summary::default()
+        };
+
+        assert_eq!(
+            html(&serde, &internal),
+            "<pre>            serde           myreg:internal\n\
+             latest      1.0.130         0.3.0\n\
+             releases/yr 12              —\n\
+             downloads   123.5M          —\n\
+             MSRV        1.15            —\n\
+             license     MIT OR Apache…  —\n\
+             deps        1 +1 opt        4</pre>"
+        );
+    }
+}
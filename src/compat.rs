@@ -0,0 +1,82 @@
+//! Semver compatibility of a release with the previous one, shown in notifications
+use versions::SemVer;
+
+/// What a release changes compared to the previous version, by the rules cargo uses to pick
+/// compatible versions: the left-most non-zero component is the breaking one, so `0.3 → 0.4`
+/// and `0.0.1 → 0.0.2` are major changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Major,
+    Minor,
+    Patch,
+    Prerelease,
+    /// Only the build metadata (`+...`) differs
+    Build,
+}
+
+impl Change {
+    pub fn between(previous: &SemVer, new: &SemVer) -> Self {
+        let same_version =
+            (previous.major, previous.minor, previous.patch) == (new.major, new.minor, new.patch);
+        if same_version && previous.pre_rel == new.pre_rel {
+            Change::Build
+        } else if new.pre_rel.is_some() {
+            Change::Prerelease
+        } else if previous.major != new.major
+            || (new.major == 0 && previous.minor != new.minor)
+            || (new.major == 0 && new.minor == 0 && previous.patch != new.patch)
+        {
+            Change::Major
+        } else if new.major != 0 && previous.minor != new.minor {
+            Change::Minor
+        } else {
+            Change::Patch
+        }
+    }
+
+    pub fn emoji(self) -> &'static str {
+        match self {
+            Change::Major => "🟥",
+            Change::Minor | Change::Prerelease => "🟨",
+            Change::Patch | Change::Build => "🟩",
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Change::Major => "major",
+            Change::Minor => "minor",
+            Change::Patch => "patch",
+            Change::Prerelease => "prerelease",
+            Change::Build => "build-only",
+        }
+    }
+
+    /// `🟥 major`
+    pub fn label(self) -> String {
+        format!("{} {}", self.emoji(), self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(previous: &str, new: &str) -> Change {
+        Change::between(&SemVer::new(previous).unwrap(), &SemVer::new(new).unwrap())
+    }
+
+    #[test]
+    fn between() {
+        assert_eq!(change("1.2.3", "2.0.0"), Change::Major);
+        assert_eq!(change("1.2.3", "1.3.0"), Change::Minor);
+        assert_eq!(change("1.2.3", "1.2.4"), Change::Patch);
+        assert_eq!(change("1.2.3", "2.0.0-rc.1"), Change::Prerelease);
+        assert_eq!(change("2.0.0-rc.1", "2.0.0"), Change::Patch);
+        assert_eq!(change("1.2.3", "1.2.3+build.2"), Change::Build);
+        // 0.x caveats
+        assert_eq!(change("0.3.1", "0.4.0"), Change::Major);
+        assert_eq!(change("0.3.1", "0.3.2"), Change::Patch);
+        assert_eq!(change("0.0.1", "0.0.2"), Change::Major);
+    }
+}
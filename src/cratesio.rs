@@ -0,0 +1,298 @@
+//! Listing crates and reading their metadata via the crates.io api
+use std::time::Duration;
+
+use reqwest::Client;
+
+use kacl_parser::Date;
+
+use crate::{
+    cache::{self, Source},
+    render::escape,
+    replay::unix_time,
+};
+
+/// crates.io returns at most 100 crates per page
+const PER_PAGE: usize = 100;
+
+/// crates.io asks to make at most one request per second
+const PAGE_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(serde::Deserialize)]
+struct CratesResponse {
+    crates: Vec<CrateName>,
+}
+
+#[derive(serde::Deserialize)]
+struct CrateName {
+    name: String,
+}
+
+/// Names of all crates matching the `query` of the `/crates` endpoint (e.g. `keyword=async`)
+pub async fn list_crates(client: &Client, query: &str) -> reqwest::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for page in 1.. {
+        if page > 1 {
+            tokio::time::delay_for(PAGE_DELAY).await;
+        }
+        let response: CratesResponse = client
+            .get(&format!(
+                "https://crates.io/api/v1/crates?{}&per_page={}&page={}",
+                query, PER_PAGE, page
+            ))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let last = response.crates.len() < PER_PAGE;
+        names.extend(response.crates.into_iter().map(|krate| krate.name));
+        if last {
+            break;
+        }
+    }
+
+    Ok(names)
+}
+
+#[derive(serde::Deserialize)]
+struct CrateResponse {
+    #[serde(rename = "crate")]
+    krate: CrateInfo,
+    #[serde(default)]
+    versions: Vec<VersionInfo>,
+}
+
+#[derive(serde::Deserialize)]
+struct CrateInfo {
+    repository: Option<String>,
+    /// Description of the newest version
+    #[serde(default)]
+    description: Option<String>,
+    /// Keywords of the newest version
+    #[serde(default)]
+    keywords: Vec<String>,
+    /// Slugs of categories of the newest version, e.g. `web-programming::http-server`
+    #[serde(default)]
+    categories: Vec<String>,
+    #[serde(default)]
+    downloads: u64,
+}
+
+#[derive(serde::Deserialize)]
+struct VersionInfo {
+    num: String,
+    license: Option<String>,
+    #[serde(default)]
+    rust_version: Option<String>,
+    /// e.g. `2019-08-14T20:12:34.123456+00:00`
+    #[serde(default)]
+    created_at: Option<String>,
+    #[serde(default)]
+    published_by: Option<Publisher>,
+}
+
+#[derive(serde::Deserialize)]
+struct Publisher {
+    login: String,
+}
+
+/// Info of the crate (possibly cached), revalidated if it lacks `version`, e.g. a just published one
+async fn crate_info(
+    client: &Client,
+    krate: &str,
+    version: Option<&str>,
+) -> Result<CrateResponse, cache::Error> {
+    let url = format!("https://crates.io/api/v1/crates/{}", krate);
+    let response: CrateResponse = cache::get(client, client.get(&url), Source::CratesIo)
+        .await?
+        .error_for_status()?
+        .json()?;
+    match version {
+        Some(version) if !response.versions.iter().any(|v| v.num == version) => {
+            cache::revalidate(client, client.get(&url), Source::CratesIo)
+                .await?
+                .error_for_status()?
+                .json()
+        }
+        _ => Ok(response),
+    }
+}
+
+/// Repository url from the crate's metadata on crates.io
+pub async fn repository(client: &Client, krate: &str) -> Result<Option<String>, cache::Error> {
+    Ok(crate_info(client, krate, None).await?.krate.repository)
+}
+
+/// Description of the crate from the manifest of its newest version, revalidated if crates.io
+/// doesn't know `version` yet
+pub async fn description(
+    client: &Client,
+    krate: &str,
+    version: &str,
+) -> Result<Option<String>, cache::Error> {
+    Ok(crate_info(client, krate, Some(version))
+        .await?
+        .krate
+        .description)
+}
+
+/// What a crate is about, from the manifest of its newest version
+#[derive(Debug, Default)]
+pub struct About {
+    pub description: Option<String>,
+    pub keywords: Vec<String>,
+    /// Category slugs
+    pub categories: Vec<String>,
+}
+
+/// Description, keywords and categories of the crate, revalidated if crates.io doesn't know
+/// `version` yet
+pub async fn about(client: &Client, krate: &str, version: &str) -> Result<About, cache::Error> {
+    let info = crate_info(client, krate, Some(version)).await?.krate;
+    Ok(About {
+        description: info.description,
+        keywords: info.keywords,
+        categories: info.categories,
+    })
+}
+
+/// Downloads of all versions of the crate
+pub async fn downloads(client: &Client, krate: &str) -> Result<u64, cache::Error> {
+    Ok(crate_info(client, krate, None).await?.krate.downloads)
+}
+
+/// Publish of a version: when and by whom
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Publish {
+    pub version: String,
+    /// Unix time
+    pub created_at: i64,
+    /// crates.io login of the publisher, unknown for old versions
+    pub publisher: Option<String>,
+}
+
+/// Unix time of `2019-08-14T20:12:34.123456+00:00` (crates.io timestamps are in UTC)
+fn parse_timestamp(s: &str) -> Option<i64> {
+    let (rest, date) = Date::parse(s).ok()?;
+    let time = rest.strip_prefix('T')?;
+    let field = |range: std::ops::Range<usize>| time.get(range)?.parse::<i64>().ok();
+    let (hours, minutes, seconds) = (field(0..2)?, field(3..5)?, field(6..8)?);
+    Some(unix_time(date) + hours * 60 * 60 + minutes * 60 + seconds)
+}
+
+/// Publishes of all versions of the crate, revalidated if they lack `version`. Versions without
+/// a known publish date are skipped.
+pub async fn publishes(
+    client: &Client,
+    krate: &str,
+    version: &str,
+) -> Result<Vec<Publish>, cache::Error> {
+    let response = crate_info(client, krate, Some(version)).await?;
+    Ok(response
+        .versions
+        .into_iter()
+        .filter_map(|v| {
+            Some(Publish {
+                created_at: parse_timestamp(v.created_at.as_deref()?)?,
+                publisher: v.published_by.map(|p| p.login),
+                version: v.num,
+            })
+        })
+        .collect())
+}
+
+/// Metadata of a version from crates.io, shown in notifications of verbose chats
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// Downloads of all versions of the crate
+    pub downloads: u64,
+    pub license: Option<String>,
+    /// MSRV, `rust-version` from the manifest
+    pub rust_version: Option<String>,
+}
+
+/// `1234567` → `1.2M`
+pub fn compact(n: u64) -> String {
+    match n {
+        0..=999 => n.to_string(),
+        1_000..=999_999 => format!("{:.1}k", n as f64 / 1e3),
+        _ => format!("{:.1}M", n as f64 / 1e6),
+    }
+}
+
+impl Metadata {
+    /// Telegram html line of stats
+    pub fn html(&self) -> String {
+        let mut stats = vec![format!("⬇ {} downloads", compact(self.downloads))];
+        if let Some(license) = &self.license {
+            stats.push(format!("<code>{}</code>", escape(license)));
+        }
+        if let Some(rust_version) = &self.rust_version {
+            stats.push(format!("MSRV <code>{}</code>", escape(rust_version)));
+        }
+
+        stats.join(" · ")
+    }
+}
+
+/// Metadata of the version, `None` if crates.io doesn't know the version (yet)
+pub async fn metadata(
+    client: &Client,
+    krate: &str,
+    version: &str,
+) -> Result<Option<Metadata>, cache::Error> {
+    let response = crate_info(client, krate, Some(version)).await?;
+    let current = match response.versions.iter().find(|v| v.num == version) {
+        Some(current) => current,
+        None => return Ok(None),
+    };
+    Ok(Some(Metadata {
+        downloads: response.krate.downloads,
+        license: current.license.clone(),
+        rust_version: current.rust_version.clone(),
+    }))
+}
+
+/// Licenses (SPDX expressions) of the `old` and `new` versions, `None` if crates.io doesn't know one
+/// of the versions (yet)
+pub async fn licenses(
+    client: &Client,
+    krate: &str,
+    old: &str,
+    new: &str,
+) -> Result<Option<(Option<String>, Option<String>)>, cache::Error> {
+    let response = crate_info(client, krate, Some(new)).await?;
+    let license = |version: &str| {
+        response
+            .versions
+            .iter()
+            .find(|v| v.num == version)
+            .map(|v| v.license.clone())
+    };
+    Ok(license(old).and_then(|old| Some((old, license(new)?))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(parse_timestamp("1970-01-02T01:00:30.5+00:00"), Some(90_030));
+        assert_eq!(parse_timestamp("2019-08-14"), None);
+    }
+
+    #[test]
+    fn metadata_html() {
+        let metadata = Metadata {
+            downloads: 1_234_567,
+            license: Some(String::from("MIT OR Apache-2.0")),
+            rust_version: Some(String::from("1.56")),
+        };
+        assert_eq!(
+            metadata.html(),
+            "⬇ 1.2M downloads · <code>MIT OR Apache-2.0</code> · MSRV <code>1.56</code>"
+        );
+        assert_eq!(Metadata::default().html(), "⬇ 0 downloads");
+    }
+}
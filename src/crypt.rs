@@ -0,0 +1,157 @@
+//! Optional encryption of personal data at rest. With `storage_key` in the config (or the
+//! `CRATE_UPD_BOT_STORAGE_KEY` environment variable) e-mail addresses are stored encrypted with
+//! AES-256-GCM. Chat ids stay plain: they key every table and are needed to send messages.
+//! Values stored before the key was set are read as is, the `encrypt-storage` subcommand
+//! encrypts them.
+use std::{
+    io::Read,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use aes_gcm::{
+    aead::{generic_array::GenericArray, Aead, NewAead},
+    Aes256Gcm,
+};
+
+use crate::{cfg::Config, db::Database};
+
+/// Environment variable overriding `storage_key` of the config
+pub const KEY_VAR: &str = "CRATE_UPD_BOT_STORAGE_KEY";
+
+/// Marks encrypted values, followed by the hex of the nonce and the ciphertext
+const PREFIX: &str = "enc1:";
+
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, derive_more::Display)]
+pub enum Error {
+    #[display(fmt = "the storage key must be 64 hex digits (32 bytes)")]
+    Key,
+    #[display(fmt = "couldn't read random bytes: {}", _0)]
+    Random(std::io::Error),
+    #[display(fmt = "couldn't decrypt a stored value, is the storage key right?")]
+    Decrypt,
+}
+
+pub struct Cipher {
+    aead: Aes256Gcm,
+    /// Random for every start, nonces are it XOR-ed with a counter so they never repeat
+    nonce_base: [u8; NONCE_LEN],
+    counter: AtomicU64,
+}
+
+impl Cipher {
+    /// Cipher with the hex key
+    pub fn new(key: &str) -> Result<Self, Error> {
+        let key = hex::decode(key.trim()).map_err(|_| Error::Key)?;
+        if key.len() != 32 {
+            return Err(Error::Key);
+        }
+
+        let mut nonce_base = [0; NONCE_LEN];
+        std::fs::File::open("/dev/urandom")
+            .and_then(|mut random| random.read_exact(&mut nonce_base))
+            .map_err(Error::Random)?;
+        Ok(Self {
+            aead: Aes256Gcm::new(GenericArray::from_slice(&key)),
+            nonce_base,
+            counter: AtomicU64::new(0),
+        })
+    }
+
+    /// Cipher with the key from the environment or the config, `None` if neither has one
+    pub fn from_config(cfg: &Config) -> Result<Option<Self>, Error> {
+        match std::env::var(KEY_VAR)
+            .ok()
+            .or_else(|| cfg.storage_key.clone())
+        {
+            Some(key) => Self::new(&key).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn nonce(&self) -> [u8; NONCE_LEN] {
+        let count = self.counter.fetch_add(1, Ordering::Relaxed).to_le_bytes();
+        let mut nonce = self.nonce_base;
+        for (byte, count) in nonce.iter_mut().zip(&count) {
+            *byte ^= count;
+        }
+        nonce
+    }
+
+    /// Stored form of the value
+    pub fn encrypt(&self, plain: &str) -> String {
+        let nonce = self.nonce();
+        let sealed = self
+            .aead
+            .encrypt(GenericArray::from_slice(&nonce), plain.as_bytes())
+            .expect("stored values are short");
+        format!("{}{}{}", PREFIX, hex::encode(nonce), hex::encode(sealed))
+    }
+
+    /// The value from its stored form, values stored before the key was set are returned as is
+    pub fn decrypt(&self, stored: &str) -> Result<String, Error> {
+        let encoded = match stored.strip_prefix(PREFIX) {
+            Some(encoded) => encoded,
+            None => return Ok(stored.to_owned()),
+        };
+        let bytes = hex::decode(encoded).map_err(|_| Error::Decrypt)?;
+        if bytes.len() < NONCE_LEN {
+            return Err(Error::Decrypt);
+        }
+
+        let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+        let plain = self
+            .aead
+            .decrypt(GenericArray::from_slice(nonce), sealed)
+            .map_err(|_| Error::Decrypt)?;
+        String::from_utf8(plain).map_err(|_| Error::Decrypt)
+    }
+}
+
+/// Whether the stored value is encrypted
+pub fn is_encrypted(stored: &str) -> bool {
+    stored.starts_with(PREFIX)
+}
+
+/// The `encrypt-storage` subcommand: encrypts e-mail addresses stored in plain text, returns
+/// how many were encrypted. Encrypted ones are skipped, so it can be run again.
+pub async fn encrypt_storage(
+    db: &Database,
+    cipher: &Cipher,
+) -> Result<usize, tokio_postgres::Error> {
+    let mut encrypted = 0;
+    for (user_id, stored) in db.list_stored_emails().await? {
+        if !is_encrypted(&stored) {
+            db.set_stored_email(user_id, &cipher.encrypt(&stored))
+                .await?;
+            encrypted += 1;
+        }
+    }
+
+    Ok(encrypted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    #[test]
+    fn round_trip() {
+        let cipher = Cipher::new(KEY).unwrap();
+        let stored = cipher.encrypt("me@example.com");
+        assert!(is_encrypted(&stored));
+        assert!(!stored.contains("example"));
+        // nonces don't repeat
+        assert_ne!(cipher.encrypt("me@example.com"), stored);
+        assert_eq!(cipher.decrypt(&stored).unwrap(), "me@example.com");
+        // stored before the key was set
+        assert_eq!(cipher.decrypt("me@example.com").unwrap(), "me@example.com");
+
+        let other = Cipher::new(&KEY.replace("00", "ff")).unwrap();
+        assert!(matches!(other.decrypt(&stored), Err(Error::Decrypt)));
+        assert!(matches!(Cipher::new("abcd"), Err(Error::Key)));
+    }
+}
@@ -1,18 +1,196 @@
 use tokio_postgres::tls::MakeTlsConnect;
 use tokio_postgres::types::Type;
-use tokio_postgres::{Client, Config, Connection, Error, Socket};
+use tokio_postgres::{Client, Config, Connection, Error, Row, Socket};
+
+use crate_upd_core::{ActionKind, Storage};
+
+use crate::{
+    crypt::{self, Cipher},
+    email::Frequency,
+    filter::{Bump, Filter},
+    health::Queues,
+    send::Receipt,
+    verbosity::Verbosity,
+};
 
 use std::sync::Arc;
 
+/// Reads `min_bump`, `skip_prerelease`, `show_deps` and `readme` columns starting at `idx`
+fn filter_from_row(row: &Row, idx: usize) -> Filter {
+    Filter {
+        min_bump: Bump::parse(row.get(idx)).unwrap_or(Bump::Patch),
+        skip_prerelease: row.get(idx + 1),
+        show_deps: row.get(idx + 2),
+        readme: row.get(idx + 3),
+    }
+}
+
+/// Release of a subscribed crate in the weekly report
+#[derive(Debug)]
+pub struct WeeklyRelease {
+    pub krate: String,
+    pub version: String,
+    /// Version published before this one, `None` for the first release seen by the bot
+    pub previous: Option<String>,
+    pub yanked: bool,
+    /// The chat's filter of the crate
+    pub filter: Filter,
+}
+
+/// Subscriber of a crate with the chat's notification settings
+#[derive(Debug)]
+pub struct Subscriber {
+    pub chat_id: i64,
+    pub filter: Filter,
+    pub mute_yanks: bool,
+    /// The chat gets a daily digest instead of separate notifications
+    pub digest: bool,
+    /// Version from a lockfile, the chat is notified only about newer versions
+    pub baseline: Option<String>,
+    /// Template of notifications about new versions, `None` for the default one
+    pub template: Option<String>,
+    /// Subscribed via a keyword or category of the crate, not to the crate itself
+    pub tagged: bool,
+    /// The chat has an e-mail address updates are mailed to
+    pub email: bool,
+    /// Notifications include crates.io metadata of the version
+    pub verbose: bool,
+    /// It's quiet hours of the chat now, notifications are deferred
+    pub quiet: bool,
+    /// Rust version of the chat's toolchain, set by `/msrv`
+    pub msrv: Option<String>,
+    /// Notifications are annotated with alerts about suspicious releases
+    pub security_alerts: bool,
+    /// How much of release notes notifications include, set by `/verbosity`
+    pub verbosity: Verbosity,
+    /// Notifications are paused by `/pause`, they're queued for a catch-up digest
+    pub paused: bool,
+    /// Language of the chat, set by `/language`
+    pub language: Option<String>,
+    /// Release notes in another language are translated to the chat's one, set by `/translate`
+    pub translate: bool,
+}
+
+/// Total downloads of a crate on a day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    /// Days since the sample was taken
+    pub age: i32,
+    pub downloads: i64,
+    /// A surge of downloads was announced that day
+    pub trending: bool,
+}
+
+/// Timezone, digest time and quiet hours of a chat, times are `HH:MM` in the timezone
+#[derive(Debug, Default)]
+pub struct Schedule {
+    /// IANA name, `None` for UTC
+    pub timezone: Option<String>,
+    pub digest_at: Option<String>,
+    /// Start and end of quiet hours
+    pub quiet: Option<(String, String)>,
+}
+
+/// E-mail address of a chat
+#[derive(Debug)]
+pub struct Email {
+    pub address: String,
+    pub frequency: Frequency,
+    /// Secret of the unsubscribe link
+    pub token: String,
+}
+
+/// Update waiting for the next e-mail
+#[derive(Debug)]
+pub struct EmailEntry {
+    /// `myreg:name` for crates of alternative registries
+    pub krate: String,
+    pub version: String,
+    /// `new`, `yanked` or `unyanked`
+    pub action: String,
+    /// Release notes as telegram html
+    pub notes: Option<String>,
+}
+
+/// Notification about a release claimed by this instance (cluster mode)
+#[derive(Debug)]
+pub struct DeliveryJob {
+    pub chat_id: i64,
+    /// Telegram html
+    pub text: String,
+    pub quiet: bool,
+    /// The release, also the idempotency key of the job together with the chat
+    pub receipt: Receipt,
+}
+
+/// Release from the archive, shown in atom feeds
+#[derive(Debug)]
+pub struct FeedEntry {
+    /// `myreg:name` for crates of alternative registries
+    pub krate: String,
+    pub version: String,
+    pub yanked: bool,
+    /// RFC 3339 time, UTC
+    pub published_at: String,
+    /// Release notes as telegram html
+    pub notes: Option<String>,
+}
+
+impl FeedEntry {
+    fn from_row(row: &Row) -> Self {
+        Self {
+            krate: row.get(0),
+            version: row.get(1),
+            yanked: row.get(2),
+            published_at: row.get(3),
+            notes: row.get(4),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Database {
     inner: Arc<Client>, // TODO: WHy doesn't it implement clone?
+    /// Encrypts personal data at rest, if the operator set a storage key (see [`crypt`])
+    cipher: Option<Arc<Cipher>>,
 }
 
 impl Database {
     pub fn new(client: Client) -> Self {
         Self {
             inner: Arc::new(client),
+            cipher: None,
+        }
+    }
+
+    /// Stores personal data encrypted with the cipher
+    pub fn with_cipher(self, cipher: Option<Cipher>) -> Self {
+        Self {
+            cipher: cipher.map(Arc::new),
+            ..self
+        }
+    }
+
+    /// Stored form of personal data
+    fn seal(&self, plain: &str) -> String {
+        match &self.cipher {
+            Some(cipher) => cipher.encrypt(plain),
+            None => plain.to_owned(),
+        }
+    }
+
+    /// Personal data from its stored form, `None` if it can't be decrypted
+    fn open(&self, stored: String) -> Option<String> {
+        match &self.cipher {
+            Some(cipher) => cipher
+                .decrypt(&stored)
+                .map_err(|err| tracing::error!("{}", err))
+                .ok(),
+            None if crypt::is_encrypted(&stored) => {
+                tracing::error!("a stored value is encrypted, but there is no storage key");
+                None
+            }
+            None => Some(stored),
         }
     }
 
@@ -29,6 +207,12 @@ impl Database {
             .map(|(client, connection)| (Self::new(client), connection))
     }
 
+    /// Executes the statements, e.g. of `db.sql` creating the tables of a test
+    #[cfg(test)]
+    pub async fn batch_execute(&self, sql: &str) -> Result<(), Error> {
+        self.inner.batch_execute(sql).await
+    }
+
     pub async fn subscribe(&self, user_id: i64, krate: &str) -> Result<(), Error> {
         let stmt = self
             .inner
@@ -51,15 +235,157 @@ impl Database {
         Ok(())
     }
 
-    pub async fn list_subscribers(&self, krate: &str) -> Result<Vec<i64>, Error> {
+    /// Subscribes to all crates at once (either all or none subscriptions are added)
+    pub async fn subscribe_many(&self, user_id: i64, krates: &[&str]) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL subscribe_many($1, $2)",
+                &[Type::INT8, Type::VARCHAR_ARRAY],
+            )
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &krates]).await?;
+
+        Ok(())
+    }
+
+    /// Subscribes to all `krates` of the owner and to its crates published later
+    pub async fn subscribe_owner(
+        &self,
+        user_id: i64,
+        owner: &str,
+        krates: &[&str],
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL subscribe_owner($1, $2, $3)",
+                &[Type::INT8, Type::VARCHAR, Type::VARCHAR_ARRAY],
+            )
+            .await?;
+
+        self.inner
+            .execute(&stmt, &[&user_id, &owner, &krates])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Stops subscribing to new crates of the owner (existing subscriptions are kept),
+    /// `false` if the owner wasn't watched
+    pub async fn unsubscribe_owner(&self, user_id: i64, owner: &str) -> Result<bool, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT unsubscribe_owner($1, $2)",
+                &[Type::INT8, Type::VARCHAR],
+            )
+            .await?;
+
+        Ok(self
+            .inner
+            .query_one(&stmt, &[&user_id, &owner])
+            .await?
+            .get(0))
+    }
+
+    /// Subscribes to the dependencies of `parent` as a group, which follows changes of them
+    pub async fn subscribe_deps(
+        &self,
+        user_id: i64,
+        parent: &str,
+        krates: &[&str],
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL subscribe_deps($1, $2, $3)",
+                &[Type::INT8, Type::VARCHAR, Type::VARCHAR_ARRAY],
+            )
+            .await?;
+
+        self.inner
+            .execute(&stmt, &[&user_id, &parent, &krates])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Removes the dependency group and the subscriptions it added, `false` if there is no such
+    /// group
+    pub async fn drop_dep_group(&self, user_id: i64, parent: &str) -> Result<bool, Error> {
         let stmt = self
             .inner
-            .prepare_typed("SELECT user_id from list_subscribers($1)", &[Type::VARCHAR])
+            .prepare_typed(
+                "SELECT drop_dep_group($1, $2)",
+                &[Type::INT8, Type::VARCHAR],
+            )
+            .await?;
+
+        Ok(self
+            .inner
+            .query_one(&stmt, &[&user_id, &parent])
+            .await?
+            .get(0))
+    }
+
+    /// Dependency groups of the chat: the crates and their dependencies
+    pub async fn list_dep_groups(&self, user_id: i64) -> Result<Vec<(String, Vec<String>)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT parent, crates from list_dep_groups($1)",
+                &[Type::INT8],
+            )
             .await?;
 
         let res = self
             .inner
-            .query(&stmt, &[&krate])
+            .query(&stmt, &[&user_id])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Waits for the first publish of the crate name (normalized, see [`crate::krate::normalize_name`])
+    pub async fn watch_name(&self, user_id: i64, name: &str) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL watch_name($1, $2)", &[Type::INT8, Type::VARCHAR])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &name]).await?;
+
+        Ok(())
+    }
+
+    /// `false` if the name wasn't watched
+    pub async fn unwatch_name(&self, user_id: i64, name: &str) -> Result<bool, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT unwatch_name($1, $2)", &[Type::INT8, Type::VARCHAR])
+            .await?;
+
+        Ok(self
+            .inner
+            .query_one(&stmt, &[&user_id, &name])
+            .await?
+            .get(0))
+    }
+
+    pub async fn list_name_watches(&self, user_id: i64) -> Result<Vec<String>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT name from list_name_watches($1)", &[Type::INT8])
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&user_id])
             .await?
             .into_iter()
             .map(|row| row.get(0))
@@ -68,11 +394,98 @@ impl Database {
         Ok(res)
     }
 
-    pub async fn list_subscriptions(&self, user_id: i64) -> Result<Vec<String>, Error> {
+    /// Removes watches of the just published name, returning the chats which watched it
+    pub async fn take_name_watchers(&self, name: &str) -> Result<Vec<i64>, Error> {
         let stmt = self
             .inner
             .prepare_typed(
-                "SELECT crate_name from list_subscriptions($1)",
+                "SELECT user_id from take_name_watchers($1)",
+                &[Type::VARCHAR],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&name])
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Waits for a release of the crate matching the requirement (see [`crate::waits::VersionReq`])
+    pub async fn wait_for_version(
+        &self,
+        user_id: i64,
+        krate: &str,
+        requirement: &str,
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL wait_for_version($1, $2, $3)",
+                &[Type::INT8, Type::VARCHAR, Type::VARCHAR],
+            )
+            .await?;
+
+        self.inner
+            .execute(&stmt, &[&user_id, &krate, &requirement])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Stops waiting for releases of the crate, `false` if the chat didn't wait for them
+    pub async fn unwait_for_version(&self, user_id: i64, krate: &str) -> Result<bool, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT unwait_for_version($1, $2)",
+                &[Type::INT8, Type::VARCHAR],
+            )
+            .await?;
+
+        Ok(self
+            .inner
+            .query_one(&stmt, &[&user_id, &krate])
+            .await?
+            .get(0))
+    }
+
+    /// What brings updates of the crate to the chat as `(kind, name)`, see `subscription_sources`
+    /// in `db.sql`
+    pub async fn subscription_sources(
+        &self,
+        user_id: i64,
+        krate: &str,
+    ) -> Result<Vec<(String, String)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT kind, name from subscription_sources($1, $2)",
+                &[Type::INT8, Type::VARCHAR],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&user_id, &krate])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// `(crate, requirement)` the chat waits for
+    pub async fn list_version_waits(&self, user_id: i64) -> Result<Vec<(String, String)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT crate, requirement from list_version_waits($1)",
                 &[Type::INT8],
             )
             .await?;
@@ -82,9 +495,2227 @@ impl Database {
             .query(&stmt, &[&user_id])
             .await?
             .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// `(chat, requirement)` waiting for releases of the crate
+    pub async fn list_version_waiters(&self, krate: &str) -> Result<Vec<(i64, String)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT user_id, requirement from list_version_waiters($1)",
+                &[Type::VARCHAR],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&krate])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Removes the wait which fired, `false` if it was removed already
+    pub async fn take_version_wait(
+        &self,
+        user_id: i64,
+        krate: &str,
+        requirement: &str,
+    ) -> Result<bool, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT take_version_wait($1, $2, $3)",
+                &[Type::INT8, Type::VARCHAR, Type::VARCHAR],
+            )
+            .await?;
+
+        Ok(self
+            .inner
+            .query_one(&stmt, &[&user_id, &krate, &requirement])
+            .await?
+            .get(0))
+    }
+
+    /// Notifies the chat about first publishes of crates with one of the keywords or categories
+    /// (`topics`), of all crates if there are none
+    pub async fn subscribe_new(&self, user_id: i64, topics: &[&str]) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL subscribe_new($1, $2)",
+                &[Type::INT8, Type::VARCHAR_ARRAY],
+            )
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &topics]).await?;
+
+        Ok(())
+    }
+
+    /// `false` if the chat wasn't subscribed to new crates
+    pub async fn unsubscribe_new(&self, user_id: i64) -> Result<bool, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT unsubscribe_new($1)", &[Type::INT8])
+            .await?;
+
+        Ok(self.inner.query_one(&stmt, &[&user_id]).await?.get(0))
+    }
+
+    /// Chats subscribed to new crates and their topics
+    pub async fn list_new_crate_subscribers(&self) -> Result<Vec<(i64, Vec<String>)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT user_id, topics from list_new_crate_subscribers()",
+                &[],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// crates.io crates followed by chats with trending notifications
+    pub async fn list_trending_crates(&self) -> Result<Vec<String>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT crate_name from list_trending_crates()", &[])
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[])
+            .await?
+            .into_iter()
             .map(|row| row.get(0))
             .collect();
 
         Ok(res)
     }
+
+    /// Chats following the crate with trending notifications
+    pub async fn list_trending_subscribers(&self, krate: &str) -> Result<Vec<i64>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT user_id from list_trending_subscribers($1)",
+                &[Type::VARCHAR],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&krate])
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Download samples of the crate, the newest first
+    pub async fn list_download_samples(&self, krate: &str) -> Result<Vec<Sample>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT age, downloads, trending from list_download_samples($1)",
+                &[Type::VARCHAR],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&krate])
+            .await?
+            .into_iter()
+            .map(|row| Sample {
+                age: row.get(0),
+                downloads: row.get(1),
+                trending: row.get(2),
+            })
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Records today's total downloads of the crate, `trending` if a surge was announced
+    pub async fn add_download_sample(
+        &self,
+        krate: &str,
+        downloads: i64,
+        trending: bool,
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL add_download_sample($1, $2, $3)",
+                &[Type::VARCHAR, Type::INT8, Type::BOOL],
+            )
+            .await?;
+
+        self.inner
+            .execute(&stmt, &[&krate, &downloads, &trending])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Owners watched by at least one chat
+    pub async fn list_owners(&self) -> Result<Vec<String>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT owner from list_owners()", &[])
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[])
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Subscribes watchers of the owner to `krates` published since the last refresh
+    pub async fn refresh_owner(&self, owner: &str, krates: &[&str]) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL refresh_owner($1, $2)",
+                &[Type::VARCHAR, Type::VARCHAR_ARRAY],
+            )
+            .await?;
+
+        self.inner.execute(&stmt, &[&owner, &krates]).await?;
+
+        Ok(())
+    }
+
+    /// Whether a chat follows the dependencies of the crate
+    pub async fn has_dep_groups(&self, parent: &str) -> Result<bool, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT has_dep_groups($1)", &[Type::VARCHAR])
+            .await?;
+
+        Ok(self.inner.query_one(&stmt, &[&parent]).await?.get(0))
+    }
+
+    /// Moves groups following the dependencies of `parent` to its current dependencies `krates`
+    pub async fn refresh_dep_groups(&self, parent: &str, krates: &[&str]) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL refresh_dep_groups($1, $2)",
+                &[Type::VARCHAR, Type::VARCHAR_ARRAY],
+            )
+            .await?;
+
+        self.inner.execute(&stmt, &[&parent, &krates]).await?;
+
+        Ok(())
+    }
+
+    /// Subscribes to all crates with the keyword or in the category
+    pub async fn subscribe_tag(&self, user_id: i64, kind: &str, tag: &str) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL subscribe_tag($1, $2, $3)",
+                &[Type::INT8, Type::VARCHAR, Type::VARCHAR],
+            )
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &kind, &tag]).await?;
+
+        Ok(())
+    }
+
+    /// `false` if there was no such subscription
+    pub async fn unsubscribe_tag(
+        &self,
+        user_id: i64,
+        kind: &str,
+        tag: &str,
+    ) -> Result<bool, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT unsubscribe_tag($1, $2, $3)",
+                &[Type::INT8, Type::VARCHAR, Type::VARCHAR],
+            )
+            .await?;
+
+        Ok(self
+            .inner
+            .query_one(&stmt, &[&user_id, &kind, &tag])
+            .await?
+            .get(0))
+    }
+
+    /// `(kind, tag)` subscribed by at least one chat
+    pub async fn list_tags(&self) -> Result<Vec<(String, String)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT kind, tag from list_tags()", &[])
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Replaces cached crates of the tag
+    pub async fn set_tag_crates(
+        &self,
+        kind: &str,
+        tag: &str,
+        krates: &[&str],
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL set_tag_crates($1, $2, $3)",
+                &[Type::VARCHAR, Type::VARCHAR, Type::VARCHAR_ARRAY],
+            )
+            .await?;
+
+        self.inner.execute(&stmt, &[&kind, &tag, &krates]).await?;
+
+        Ok(())
+    }
+
+    /// Sets baseline versions of subscriptions, `krates` and `versions` are zipped
+    pub async fn set_baselines(
+        &self,
+        user_id: i64,
+        krates: &[&str],
+        versions: &[&str],
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL set_baselines($1, $2, $3)",
+                &[Type::INT8, Type::VARCHAR_ARRAY, Type::VARCHAR_ARRAY],
+            )
+            .await?;
+
+        self.inner
+            .execute(&stmt, &[&user_id, &krates, &versions])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Unsubscribes from all crates at once (either all or none subscriptions are removed)
+    pub async fn unsubscribe_many(&self, user_id: i64, krates: &[&str]) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL unsubscribe_many($1, $2)",
+                &[Type::INT8, Type::VARCHAR_ARRAY],
+            )
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &krates]).await?;
+
+        Ok(())
+    }
+
+    pub async fn list_subscribers(&self, krate: &str) -> Result<Vec<Subscriber>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT user_id, min_bump, skip_prerelease, show_deps, readme, mute_yanks, digest, \
+                 baseline, template, tagged, email, verbose, quiet, msrv, security_alerts, \
+                 notes_verbosity, paused, language, translate from list_subscribers($1)",
+                &[Type::VARCHAR],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&krate])
+            .await?
+            .into_iter()
+            .map(|row| Subscriber {
+                chat_id: row.get(0),
+                filter: filter_from_row(&row, 1),
+                mute_yanks: row.get(5),
+                digest: row.get(6),
+                baseline: row.get(7),
+                template: row.get(8),
+                tagged: row.get(9),
+                email: row.get(10),
+                verbose: row.get(11),
+                quiet: row.get(12),
+                msrv: row.get(13),
+                security_alerts: row.get(14),
+                verbosity: Verbosity::parse(row.get(15)).unwrap_or_default(),
+                paused: row.get(16),
+                language: row.get(17),
+                translate: row.get(18),
+            })
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Sets time (`HH:MM`, UTC) of the daily digest, `None` turns the digest off
+    pub async fn set_digest(&self, user_id: i64, at: Option<&str>) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL set_digest($1, $2)", &[Type::INT8, Type::VARCHAR])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &at]).await?;
+
+        Ok(())
+    }
+
+    /// Canonical name of the IANA timezone, `None` if there's no such timezone
+    pub async fn find_timezone(&self, name: &str) -> Result<Option<String>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT find_timezone($1)", &[Type::VARCHAR])
+            .await?;
+
+        Ok(self.inner.query_one(&stmt, &[&name]).await?.get(0))
+    }
+
+    /// `None` resets the timezone to UTC
+    pub async fn set_timezone(&self, user_id: i64, timezone: Option<&str>) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL set_timezone($1, $2)", &[Type::INT8, Type::VARCHAR])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &timezone]).await?;
+
+        Ok(())
+    }
+
+    pub async fn get_schedule(&self, user_id: i64) -> Result<Schedule, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT timezone, digest_at, quiet_from, quiet_to from get_schedule($1)",
+                &[Type::INT8],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query_opt(&stmt, &[&user_id])
+            .await?
+            .map(|row| Schedule {
+                timezone: row.get(0),
+                digest_at: row.get(1),
+                quiet: match (row.get(2), row.get(3)) {
+                    (Some(from), Some(to)) => Some((from, to)),
+                    _ => None,
+                },
+            })
+            .unwrap_or_default();
+
+        Ok(res)
+    }
+
+    /// Sets `(from, to)` quiet hours (`HH:MM`), `None` turns them off
+    pub async fn set_quiet_hours(
+        &self,
+        user_id: i64,
+        hours: Option<(&str, &str)>,
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL set_quiet_hours($1, $2, $3)",
+                &[Type::INT8, Type::VARCHAR, Type::VARCHAR],
+            )
+            .await?;
+
+        let (from, to) = match hours {
+            Some((from, to)) => (Some(from), Some(to)),
+            None => (None, None),
+        };
+        self.inner.execute(&stmt, &[&user_id, &from, &to]).await?;
+
+        Ok(())
+    }
+
+    /// Postpones the notification till the end of quiet hours of the chat
+    pub async fn defer_notification(
+        &self,
+        user_id: i64,
+        krate: &str,
+        version: &str,
+        action: &str,
+        text: &str,
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL defer_notification($1, $2, $3, $4, $5)",
+                &[
+                    Type::INT8,
+                    Type::VARCHAR,
+                    Type::VARCHAR,
+                    Type::VARCHAR,
+                    Type::TEXT,
+                ],
+            )
+            .await?;
+
+        self.inner
+            .execute(&stmt, &[&user_id, &krate, &version, &action, &text])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Removes deferred notifications of chats whose quiet hours are over, returning
+    /// `(chat_id, text)` in the order they came
+    pub async fn take_deferred(&self) -> Result<Vec<(i64, String)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT user_id, text from take_deferred()", &[])
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Postpones the notification till the next digest of the chat
+    pub async fn queue_digest(
+        &self,
+        user_id: i64,
+        krate: &str,
+        version: &str,
+        action: &str,
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL queue_digest($1, $2, $3, $4)",
+                &[Type::INT8, Type::VARCHAR, Type::VARCHAR, Type::VARCHAR],
+            )
+            .await?;
+
+        self.inner
+            .execute(&stmt, &[&user_id, &krate, &version, &action])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Turns the weekly report of the chat on or off, the next one is due in a week
+    pub async fn set_weekly_report(&self, user_id: i64, enabled: bool) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL set_weekly_report($1, $2)", &[Type::INT8, Type::BOOL])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &enabled]).await?;
+
+        Ok(())
+    }
+
+    /// Chats which should get their weekly report now
+    pub async fn due_weekly_reports(&self) -> Result<Vec<i64>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT user_id from due_weekly_reports()", &[])
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[])
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Releases of the last week of crates the chat is subscribed to (sorted by crate, oldest
+    /// first), marks the weekly report as sent
+    pub async fn take_weekly_report(&self, user_id: i64) -> Result<Vec<WeeklyRelease>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT crate_name, version, previous, yanked, min_bump, skip_prerelease, \
+                 show_deps, readme from take_weekly_report($1)",
+                &[Type::INT8],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&user_id])
+            .await?
+            .into_iter()
+            .map(|row| WeeklyRelease {
+                krate: row.get(0),
+                version: row.get(1),
+                previous: row.get(2),
+                yanked: row.get(3),
+                filter: filter_from_row(&row, 4),
+            })
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Pauses notifications of the chat for `secs`, returns the end of the pause
+    /// (`YYYY-MM-DD HH:MM` and the chat's timezone)
+    pub async fn pause_chat(&self, user_id: i64, secs: i64) -> Result<String, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT pause_chat($1, $2)", &[Type::INT8, Type::INT8])
+            .await?;
+
+        Ok(self
+            .inner
+            .query_one(&stmt, &[&user_id, &secs])
+            .await?
+            .get(0))
+    }
+
+    /// Ends the pause of the chat, returns whether it was paused.
+    /// The catch-up digest is sent by the digest loop.
+    pub async fn resume_paused(&self, user_id: i64) -> Result<bool, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT resume_paused($1)", &[Type::INT8])
+            .await?;
+
+        Ok(self.inner.query_one(&stmt, &[&user_id]).await?.get(0))
+    }
+
+    /// Chats whose pause is over, it's cleared
+    pub async fn take_resumed(&self) -> Result<Vec<i64>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT user_id from take_resumed()", &[])
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[])
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Chats which should get their digest now
+    pub async fn due_digests(&self) -> Result<Vec<i64>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT user_id from due_digests()", &[])
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[])
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Removes queued notifications of the chat, returning `(crate, version, action)`
+    /// sorted by crate
+    pub async fn take_digest(&self, user_id: i64) -> Result<Vec<(String, String, String)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT crate_name, version, action from take_digest($1)",
+                &[Type::INT8],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&user_id])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2)))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Toggles notifications about (un)yanked versions, returns whether they are muted now
+    pub async fn toggle_mute_yanks(&self, user_id: i64) -> Result<bool, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT toggle_mute_yanks($1)", &[Type::INT8])
+            .await?;
+
+        Ok(self.inner.query_one(&stmt, &[&user_id]).await?.get(0))
+    }
+
+    /// Turns crates.io metadata in notifications of the chat on or off
+    pub async fn set_verbose(&self, user_id: i64, verbose: bool) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL set_verbose($1, $2)", &[Type::INT8, Type::BOOL])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &verbose]).await?;
+
+        Ok(())
+    }
+
+    /// Current step of the setup started by `/start`, see [`crate::onboarding::Step`]
+    pub async fn get_onboarding(&self, user_id: i64) -> Result<Option<String>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT get_onboarding($1)", &[Type::INT8])
+            .await?;
+
+        Ok(self.inner.query_one(&stmt, &[&user_id]).await?.get(0))
+    }
+
+    /// Sets the step of the setup, `None` finishes it
+    pub async fn set_onboarding(&self, user_id: i64, step: Option<&str>) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL set_onboarding($1, $2)", &[Type::INT8, Type::VARCHAR])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &step]).await?;
+
+        Ok(())
+    }
+
+    /// Turns threads of crates on or off, turning them off forgets the threads
+    pub async fn set_threads(&self, user_id: i64, threads: bool) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL set_threads($1, $2)", &[Type::INT8, Type::BOOL])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &threads]).await?;
+
+        Ok(())
+    }
+
+    /// The last message about the crate in the chat. `None` if the chat has threads off,
+    /// `Some(None)` if there is no message about the crate yet.
+    pub async fn get_thread(
+        &self,
+        user_id: i64,
+        krate: &str,
+    ) -> Result<Option<Option<i64>>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT enabled, message_id from get_thread($1, $2)",
+                &[Type::INT8, Type::VARCHAR],
+            )
+            .await?;
+
+        let row = self.inner.query_one(&stmt, &[&user_id, &krate]).await?;
+        let enabled: bool = row.get(0);
+
+        Ok(if enabled { Some(row.get(1)) } else { None })
+    }
+
+    pub async fn set_thread(
+        &self,
+        user_id: i64,
+        krate: &str,
+        message_id: i64,
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL set_thread($1, $2, $3)",
+                &[Type::INT8, Type::VARCHAR, Type::INT8],
+            )
+            .await?;
+
+        self.inner
+            .execute(&stmt, &[&user_id, &krate, &message_id])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Forgets the thread, e.g. when its message was deleted
+    pub async fn delete_thread(&self, user_id: i64, krate: &str) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL delete_thread($1, $2)", &[Type::INT8, Type::VARCHAR])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &krate]).await?;
+
+        Ok(())
+    }
+
+    /// Crates with threads in the chat and their last messages, sorted by crate
+    pub async fn list_threads(&self, user_id: i64) -> Result<Vec<(String, i64)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT crate_name, message_id from list_threads($1)",
+                &[Type::INT8],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&user_id])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Pinned message listing threads of the chat
+    pub async fn get_index_message(&self, user_id: i64) -> Result<Option<i64>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT get_index_message($1)", &[Type::INT8])
+            .await?;
+
+        Ok(self.inner.query_one(&stmt, &[&user_id]).await?.get(0))
+    }
+
+    pub async fn set_index_message(&self, user_id: i64, message_id: i64) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL set_index_message($1, $2)", &[Type::INT8, Type::INT8])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &message_id]).await?;
+
+        Ok(())
+    }
+
+    /// Turns download milestones and surges of followed crates on or off
+    pub async fn set_trending(&self, user_id: i64, trending: bool) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL set_trending($1, $2)", &[Type::INT8, Type::BOOL])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &trending]).await?;
+
+        Ok(())
+    }
+
+    /// Turns alerts about suspicious releases in notifications of the chat on or off
+    pub async fn set_security_alerts(&self, user_id: i64, alerts: bool) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL set_security_alerts($1, $2)",
+                &[Type::INT8, Type::BOOL],
+            )
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &alerts]).await?;
+
+        Ok(())
+    }
+
+    /// Sets how much of release notes notifications of the chat include
+    pub async fn set_notes_verbosity(
+        &self,
+        user_id: i64,
+        verbosity: Verbosity,
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL set_notes_verbosity($1, $2)",
+                &[Type::INT8, Type::VARCHAR],
+            )
+            .await?;
+
+        self.inner
+            .execute(&stmt, &[&user_id, &verbosity.as_str()])
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_notes_verbosity(&self, user_id: i64) -> Result<Verbosity, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT get_notes_verbosity($1)", &[Type::INT8])
+            .await?;
+
+        let verbosity: String = self.inner.query_one(&stmt, &[&user_id]).await?.get(0);
+        Ok(Verbosity::parse(&verbosity).unwrap_or_default())
+    }
+
+    pub async fn get_verbose(&self, user_id: i64) -> Result<bool, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT get_verbose($1)", &[Type::INT8])
+            .await?;
+
+        Ok(self.inner.query_one(&stmt, &[&user_id]).await?.get(0))
+    }
+
+    /// Sets the Rust version of the chat's toolchain, `None` turns MSRV warnings off
+    pub async fn set_msrv(&self, user_id: i64, msrv: Option<&str>) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL set_msrv($1, $2)", &[Type::INT8, Type::VARCHAR])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &msrv]).await?;
+
+        Ok(())
+    }
+
+    /// Sets the language of the chat (ISO 639-1 code), `None` also turns translation off
+    pub async fn set_language(&self, user_id: i64, language: Option<&str>) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL set_language($1, $2)", &[Type::INT8, Type::VARCHAR])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &language]).await?;
+
+        Ok(())
+    }
+
+    pub async fn set_translate(&self, user_id: i64, translate: bool) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL set_translate($1, $2)", &[Type::INT8, Type::BOOL])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &translate]).await?;
+
+        Ok(())
+    }
+
+    /// Language of the chat and whether release notes are translated to it
+    pub async fn get_language(&self, user_id: i64) -> Result<(Option<String>, bool), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT language, translate FROM get_language($1)",
+                &[Type::INT8],
+            )
+            .await?;
+
+        let row = self.inner.query_one(&stmt, &[&user_id]).await?;
+        Ok((row.get(0), row.get(1)))
+    }
+
+    pub async fn get_msrv(&self, user_id: i64) -> Result<Option<String>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT get_msrv($1)", &[Type::INT8])
+            .await?;
+
+        Ok(self.inner.query_one(&stmt, &[&user_id]).await?.get(0))
+    }
+
+    /// Template of notifications about new versions, `None` if the chat uses the default one
+    pub async fn get_template(&self, user_id: i64) -> Result<Option<String>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT get_template($1)", &[Type::INT8])
+            .await?;
+
+        Ok(self.inner.query_one(&stmt, &[&user_id]).await?.get(0))
+    }
+
+    /// Sets template of notifications about new versions, `None` resets it to the default one
+    pub async fn set_template(&self, user_id: i64, template: Option<&str>) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL set_template($1, $2)", &[Type::INT8, Type::TEXT])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &template]).await?;
+
+        Ok(())
+    }
+
+    /// Filter of the subscription, `None` if there is no such subscription
+    pub async fn get_filter(&self, user_id: i64, krate: &str) -> Result<Option<Filter>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT min_bump, skip_prerelease, show_deps, readme from get_filter($1, $2)",
+                &[Type::INT8, Type::VARCHAR],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query_opt(&stmt, &[&user_id, &krate])
+            .await?
+            .map(|row| filter_from_row(&row, 0));
+
+        Ok(res)
+    }
+
+    /// Sets filter of the subscription (does nothing if there is no such subscription)
+    pub async fn set_filter(
+        &self,
+        user_id: i64,
+        krate: &str,
+        filter: &Filter,
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL set_filter($1, $2, $3, $4, $5, $6)",
+                &[
+                    Type::INT8,
+                    Type::VARCHAR,
+                    Type::VARCHAR,
+                    Type::BOOL,
+                    Type::BOOL,
+                    Type::BOOL,
+                ],
+            )
+            .await?;
+
+        self.inner
+            .execute(
+                &stmt,
+                &[
+                    &user_id,
+                    &krate,
+                    &filter.min_bump.as_str(),
+                    &filter.skip_prerelease,
+                    &filter.show_deps,
+                    &filter.readme,
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_subscriptions(&self, user_id: i64) -> Result<Vec<String>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT crate_name from list_subscriptions($1)",
+                &[Type::INT8],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&user_id])
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Subscriptions of the chat with their filters, sorted by crate name
+    pub async fn list_filters(&self, user_id: i64) -> Result<Vec<(String, Filter)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT crate_name, min_bump, skip_prerelease, show_deps, readme from list_filters($1)",
+                &[Type::INT8],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&user_id])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), filter_from_row(&row, 1)))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Names of crates which have at least one subscriber
+    pub async fn list_subscribed_crates(&self) -> Result<Vec<String>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT crate_name from list_subscribed_crates()", &[])
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[])
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Numbers of subscriptions and of chats with subscriptions
+    pub async fn count_subscriptions(&self) -> Result<(i64, i64), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT subscriptions, chats from count_subscriptions()",
+                &[],
+            )
+            .await?;
+
+        let row = self.inner.query_one(&stmt, &[]).await?;
+
+        Ok((row.get(0), row.get(1)))
+    }
+
+    /// Numbers of items in the queues of the database, the send queue isn't there
+    pub async fn queue_depths(&self) -> Result<Queues, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT deliveries, digest, email, pending_releases from queue_depths()",
+                &[],
+            )
+            .await?;
+
+        let row = self.inner.query_one(&stmt, &[]).await?;
+
+        Ok(Queues {
+            send: 0,
+            deliveries: row.get(0),
+            digest: row.get(1),
+            email: row.get(2),
+            pending_releases: row.get(3),
+        })
+    }
+
+    /// Crates with the most subscribers, with numbers of subscribers
+    pub async fn top_crates(&self, limit: i64) -> Result<Vec<(String, i64)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT crate_name, subscribers from top_crates($1)",
+                &[Type::INT8],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&limit])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Chats with any subscription, except banned ones
+    pub async fn list_chats(&self) -> Result<Vec<i64>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT user_id from list_chats()", &[])
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[])
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Pauses notifications of the chat, `reason` is the last error
+    pub async fn disable_chat(&self, user_id: i64, reason: &str) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL disable_chat($1, $2)", &[Type::INT8, Type::TEXT])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &reason]).await?;
+
+        Ok(())
+    }
+
+    /// Resumes paused notifications of the chat, returns whether they were paused
+    pub async fn resume_chat(&self, user_id: i64) -> Result<bool, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT resume_chat($1)", &[Type::INT8])
+            .await?;
+
+        Ok(self.inner.query_one(&stmt, &[&user_id]).await?.get(0))
+    }
+
+    /// Records a message which couldn't be sent
+    pub async fn add_dead_letter(
+        &self,
+        user_id: i64,
+        receipt: Option<&Receipt>,
+        text: &str,
+        error: &str,
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL add_dead_letter($1, $2, $3, $4, $5, $6)",
+                &[
+                    Type::INT8,
+                    Type::VARCHAR,
+                    Type::VARCHAR,
+                    Type::VARCHAR,
+                    Type::TEXT,
+                    Type::TEXT,
+                ],
+            )
+            .await?;
+
+        self.inner
+            .execute(
+                &stmt,
+                &[
+                    &user_id,
+                    &receipt.map(|r| r.krate.as_str()),
+                    &receipt.map(|r| r.version.as_str()),
+                    &receipt.map(|r| r.action.as_str()),
+                    &text,
+                    &error,
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Moves subscriptions, settings and queued notifications of the chat to its new id
+    pub async fn migrate_chat(&self, from: i64, to: i64) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL migrate_chat($1, $2)", &[Type::INT8, Type::INT8])
+            .await?;
+
+        self.inner.execute(&stmt, &[&from, &to]).await?;
+
+        Ok(())
+    }
+
+    /// Bans or unbans the chat: commands of banned chats are ignored and they aren't notified
+    pub async fn set_banned(&self, user_id: i64, banned: bool) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL set_banned($1, $2)", &[Type::INT8, Type::BOOL])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &banned]).await?;
+
+        Ok(())
+    }
+
+    /// JSON of everything stored about the chat (see `chat_data` in `db.sql`), without the
+    /// e-mail address
+    pub async fn chat_data(&self, user_id: i64) -> Result<String, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT chat_data($1)", &[Type::INT8])
+            .await?;
+
+        Ok(self.inner.query_one(&stmt, &[&user_id]).await?.get(0))
+    }
+
+    /// Restores `chat_data` of the chat exported by another instance, rows the chat already has
+    /// are kept
+    pub async fn import_chat(&self, user_id: i64, data: &str) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL import_chat($1, $2)", &[Type::INT8, Type::TEXT])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &data]).await?;
+
+        Ok(())
+    }
+
+    /// Removes everything stored about the chat
+    pub async fn forget_chat(&self, user_id: i64) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL forget_chat($1)", &[Type::INT8])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id]).await?;
+
+        Ok(())
+    }
+
+    pub async fn is_banned(&self, user_id: i64) -> Result<bool, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT is_banned($1)", &[Type::INT8])
+            .await?;
+
+        Ok(self.inner.query_one(&stmt, &[&user_id]).await?.get(0))
+    }
+
+    /// Records the index entry of the version (`hash` is [`Crate::content_hash`]), returns its
+    /// yanked status when it was seen before. `None` if the version is new or its notifications
+    /// aren't all sent yet.
+    ///
+    /// [`Crate::content_hash`]: crate::krate::Crate::content_hash
+    pub async fn see_version(
+        &self,
+        krate: &str,
+        version: &str,
+        yanked: bool,
+        hash: &str,
+    ) -> Result<Option<bool>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT see_version($1, $2, $3, $4)",
+                &[Type::VARCHAR, Type::VARCHAR, Type::BOOL, Type::VARCHAR],
+            )
+            .await?;
+
+        Ok(self
+            .inner
+            .query_one(&stmt, &[&krate, &version, &yanked, &hash])
+            .await?
+            .get(0))
+    }
+
+    /// Seeds index entries (`hashes` are `None` if unknown) and records the bootstrap's progress
+    /// along with them, entries seen by the bot itself are kept
+    pub async fn seed_seen_versions(
+        &self,
+        source: &str,
+        progress: &str,
+        entries: &[(String, String, bool, Option<String>)],
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL seed_seen_versions($1, $2, $3, $4, $5, $6)",
+                &[
+                    Type::VARCHAR,
+                    Type::VARCHAR,
+                    Type::VARCHAR_ARRAY,
+                    Type::VARCHAR_ARRAY,
+                    Type::BOOL_ARRAY,
+                    Type::VARCHAR_ARRAY,
+                ],
+            )
+            .await?;
+
+        let krates: Vec<&str> = entries.iter().map(|entry| entry.0.as_str()).collect();
+        let versions: Vec<&str> = entries.iter().map(|entry| entry.1.as_str()).collect();
+        let yanked: Vec<bool> = entries.iter().map(|entry| entry.2).collect();
+        let hashes: Vec<Option<&str>> = entries.iter().map(|entry| entry.3.as_deref()).collect();
+        self.inner
+            .execute(
+                &stmt,
+                &[&source, &progress, &krates, &versions, &yanked, &hashes],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn finish_bootstrap(&self, source: &str) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL finish_bootstrap($1)", &[Type::VARCHAR])
+            .await?;
+
+        self.inner.execute(&stmt, &[&source]).await?;
+
+        Ok(())
+    }
+
+    /// Progress of the bootstrap from `source` and whether it's finished, `None` if it hasn't
+    /// started
+    pub async fn get_bootstrap_progress(
+        &self,
+        source: &str,
+    ) -> Result<Option<(String, bool)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT progress, finished from get_bootstrap_progress($1)",
+                &[Type::VARCHAR],
+            )
+            .await?;
+
+        Ok(self
+            .inner
+            .query_opt(&stmt, &[&source])
+            .await?
+            .map(|row| (row.get(0), row.get(1))))
+    }
+
+    /// Adds release to the archive (or updates its yanked status),
+    /// `action` is pending until [`Database::finish_release`]
+    pub async fn record_release(
+        &self,
+        krate: &str,
+        version: &str,
+        yanked: bool,
+        published_at: i64,
+        action: ActionKind,
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL record_release($1, $2, $3, $4, $5)",
+                &[
+                    Type::VARCHAR,
+                    Type::VARCHAR,
+                    Type::BOOL,
+                    Type::INT8,
+                    Type::VARCHAR,
+                ],
+            )
+            .await?;
+
+        self.inner
+            .execute(
+                &stmt,
+                &[&krate, &version, &yanked, &published_at, &action.as_str()],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Marks notifications about the release as all sent
+    pub async fn finish_release(&self, krate: &str, version: &str) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL finish_release($1, $2)",
+                &[Type::VARCHAR, Type::VARCHAR],
+            )
+            .await?;
+
+        self.inner.execute(&stmt, &[&krate, &version]).await?;
+
+        Ok(())
+    }
+
+    /// Remembers that the chat got the notification about the pending release,
+    /// acknowledges its delivery job in cluster mode
+    pub async fn record_delivery(
+        &self,
+        krate: &str,
+        version: &str,
+        action: &str,
+        user_id: i64,
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL record_delivery($1, $2, $3, $4)",
+                &[Type::VARCHAR, Type::VARCHAR, Type::VARCHAR, Type::INT8],
+            )
+            .await?;
+
+        self.inner
+            .execute(&stmt, &[&krate, &version, &action, &user_id])
+            .await?;
+
+        Ok(())
+    }
+
+    /// The new version was announced without release notes, they're looked up again until
+    /// [`take_announcements`](Self::take_announcements)
+    pub async fn add_lagging_notes(&self, krate: &str, version: &str) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL add_lagging_notes($1, $2)",
+                &[Type::VARCHAR, Type::VARCHAR],
+            )
+            .await?;
+
+        self.inner.execute(&stmt, &[&krate, &version]).await?;
+
+        Ok(())
+    }
+
+    /// Records the message announcing the version, if it's waiting for its release notes
+    pub async fn record_announcement(
+        &self,
+        krate: &str,
+        version: &str,
+        user_id: i64,
+        message_id: i64,
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL record_announcement($1, $2, $3, $4)",
+                &[Type::VARCHAR, Type::VARCHAR, Type::INT8, Type::INT8],
+            )
+            .await?;
+
+        self.inner
+            .execute(&stmt, &[&krate, &version, &user_id, &message_id])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Records the message announcing the new version to the chat, only the newest `keep`
+    /// messages of the chat are kept
+    pub async fn record_notification(
+        &self,
+        user_id: i64,
+        krate: &str,
+        version: &str,
+        message_id: i64,
+        keep: i32,
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL record_notification($1, $2, $3, $4, $5)",
+                &[
+                    Type::INT8,
+                    Type::VARCHAR,
+                    Type::VARCHAR,
+                    Type::INT8,
+                    Type::INT4,
+                ],
+            )
+            .await?;
+
+        self.inner
+            .execute(&stmt, &[&user_id, &krate, &version, &message_id, &keep])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Crates, versions and ids of the newest `limit` messages announcing new versions to the
+    /// chat, newest first
+    pub async fn last_notifications(
+        &self,
+        user_id: i64,
+        limit: i64,
+    ) -> Result<Vec<(String, String, i64)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT crate, version, message_id from last_notifications($1, $2)",
+                &[Type::INT8, Type::INT8],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&user_id, &limit])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2)))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Crates and versions waiting for release notes which weren't checked for `check_secs`,
+    /// marked as checked. The flag tells that they're waiting for longer than `lag_secs`.
+    pub async fn due_lagging_notes(
+        &self,
+        check_secs: i32,
+        lag_secs: i32,
+    ) -> Result<Vec<(String, String, bool)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT crate, version, expired from due_lagging_notes($1, $2)",
+                &[Type::INT4, Type::INT4],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&check_secs, &lag_secs])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2)))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Chats and ids of messages announcing the version, which stops waiting for release notes
+    pub async fn take_announcements(
+        &self,
+        krate: &str,
+        version: &str,
+    ) -> Result<Vec<(i64, i64)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT user_id, message_id from take_announcements($1, $2)",
+                &[Type::VARCHAR, Type::VARCHAR],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&krate, &version])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Queues the notification for any instance to send, unless it's already queued
+    pub async fn enqueue_delivery(
+        &self,
+        chat_id: i64,
+        text: &str,
+        quiet: bool,
+        receipt: &Receipt,
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL enqueue_delivery($1, $2, $3, $4, $5, $6)",
+                &[
+                    Type::VARCHAR,
+                    Type::VARCHAR,
+                    Type::VARCHAR,
+                    Type::INT8,
+                    Type::TEXT,
+                    Type::BOOL,
+                ],
+            )
+            .await?;
+
+        self.inner
+            .execute(
+                &stmt,
+                &[
+                    &receipt.krate,
+                    &receipt.version,
+                    &receipt.action,
+                    &chat_id,
+                    &text,
+                    &quiet,
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Leases queued notifications of up to `chats` chats to the instance for `lease_secs`.
+    /// They're acknowledged by [`record_delivery`](Self::record_delivery).
+    pub async fn claim_deliveries(
+        &self,
+        instance: &str,
+        chats: u32,
+        lease_secs: u32,
+    ) -> Result<Vec<DeliveryJob>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT crate, version, action, user_id, text, quiet from claim_deliveries($1, $2, $3)",
+                &[Type::VARCHAR, Type::INT4, Type::INT4],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&instance, &(chats as i32), &(lease_secs as i32)])
+            .await?
+            .into_iter()
+            .map(|row| DeliveryJob {
+                chat_id: row.get(3),
+                text: row.get(4),
+                quiet: row.get(5),
+                receipt: Receipt {
+                    krate: row.get(0),
+                    version: row.get(1),
+                    action: row.get(2),
+                    // not recorded by other instances
+                    single: false,
+                },
+            })
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Chats which already got the notification about the pending release
+    pub async fn delivered_chats(
+        &self,
+        krate: &str,
+        version: &str,
+        action: &str,
+    ) -> Result<Vec<i64>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT user_id from delivered_chats($1, $2, $3)",
+                &[Type::VARCHAR, Type::VARCHAR, Type::VARCHAR],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&krate, &version, &action])
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Releases whose notifications aren't all sent: crate, version, action and unix time of
+    /// the release, oldest first
+    pub async fn pending_releases(&self) -> Result<Vec<(String, String, String, i64)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT crate, version, action, published_at from pending_releases()",
+                &[],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2), row.get(3)))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Recorded versions of the crate: version, yanked and the pending action, if any
+    pub async fn release_states(
+        &self,
+        krate: &str,
+    ) -> Result<Vec<(String, bool, Option<String>)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT version, yanked, pending from release_states($1)",
+                &[Type::VARCHAR],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&krate])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2)))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Archived releases of the crate (version, yanked, publish date as `YYYY-MM-DD`), newest first
+    pub async fn list_releases(&self, krate: &str) -> Result<Vec<(String, bool, String)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT version, yanked, published_on from list_releases($1)",
+                &[Type::VARCHAR],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&krate])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2)))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Archived releases (crate, version, yanked, unix publish time) published since the unix
+    /// time, oldest first
+    pub async fn releases_since(
+        &self,
+        since: i64,
+    ) -> Result<Vec<(String, String, bool, i64)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT crate, version, yanked, published_at from releases_since($1)",
+                &[Type::INT8],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&since])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2), row.get(3)))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Stores release notes of a recorded release
+    pub async fn set_release_notes(
+        &self,
+        krate: &str,
+        version: &str,
+        notes: &str,
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL set_release_notes($1, $2, $3)",
+                &[Type::VARCHAR, Type::VARCHAR, Type::TEXT],
+            )
+            .await?;
+
+        self.inner
+            .execute(&stmt, &[&krate, &version, &notes])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Stored release notes of a recorded release, see [`set_release_notes`](Self::set_release_notes)
+    pub async fn get_release_notes(
+        &self,
+        krate: &str,
+        version: &str,
+    ) -> Result<Option<String>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT get_release_notes($1, $2)",
+                &[Type::VARCHAR, Type::VARCHAR],
+            )
+            .await?;
+
+        Ok(self
+            .inner
+            .query_one(&stmt, &[&krate, &version])
+            .await?
+            .get(0))
+    }
+
+    /// Token of the chat's atom feed, `None` if it wasn't created yet
+    pub async fn get_feed_token(&self, user_id: i64) -> Result<Option<String>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT get_feed_token($1)", &[Type::INT8])
+            .await?;
+
+        Ok(self.inner.query_one(&stmt, &[&user_id]).await?.get(0))
+    }
+
+    /// Sets token of the chat's atom feed, replacing the previous one
+    pub async fn set_feed_token(&self, user_id: i64, token: &str) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL set_feed_token($1, $2)", &[Type::INT8, Type::VARCHAR])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &token]).await?;
+
+        Ok(())
+    }
+
+    /// Chat with the feed token
+    pub async fn feed_user(&self, token: &str) -> Result<Option<i64>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT feed_user($1)", &[Type::VARCHAR])
+            .await?;
+
+        Ok(self.inner.query_one(&stmt, &[&token]).await?.get(0))
+    }
+
+    /// The newest releases of the crate, newest first
+    pub async fn crate_feed(&self, krate: &str, limit: i64) -> Result<Vec<FeedEntry>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT crate_name, version, yanked, published_at, notes from crate_feed($1, $2)",
+                &[Type::VARCHAR, Type::INT8],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&krate, &limit])
+            .await?
+            .iter()
+            .map(FeedEntry::from_row)
+            .collect();
+
+        Ok(res)
+    }
+
+    /// The newest releases of crates the chat is subscribed to, newest first
+    pub async fn user_feed(&self, user_id: i64, limit: i64) -> Result<Vec<FeedEntry>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT crate_name, version, yanked, published_at, notes from user_feed($1, $2)",
+                &[Type::INT8, Type::INT8],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&user_id, &limit])
+            .await?
+            .iter()
+            .map(FeedEntry::from_row)
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Releases of crates the chat is subscribed to published after the unix time, the oldest
+    /// first, with their unix times
+    pub async fn user_events(
+        &self,
+        user_id: i64,
+        since: i64,
+        limit: i64,
+    ) -> Result<Vec<(FeedEntry, i64)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT crate_name, version, yanked, published_at, notes, ts from user_events($1, $2, $3)",
+                &[Type::INT8, Type::INT8, Type::INT8],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&user_id, &since, &limit])
+            .await?
+            .iter()
+            .map(|row| (FeedEntry::from_row(row), row.get(5)))
+            .collect();
+
+        Ok(res)
+    }
+
+    pub async fn get_api_token(&self, user_id: i64) -> Result<Option<String>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT get_api_token($1)", &[Type::INT8])
+            .await?;
+
+        Ok(self.inner.query_one(&stmt, &[&user_id]).await?.get(0))
+    }
+
+    /// Sets the chat's token of the http api, replacing the previous one
+    pub async fn set_api_token(&self, user_id: i64, token: &str) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL set_api_token($1, $2)", &[Type::INT8, Type::VARCHAR])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &token]).await?;
+
+        Ok(())
+    }
+
+    /// Chat with the api token, `None` for unknown tokens and banned chats
+    pub async fn api_user(&self, token: &str) -> Result<Option<i64>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT api_user($1)", &[Type::VARCHAR])
+            .await?;
+
+        Ok(self.inner.query_one(&stmt, &[&token]).await?.get(0))
+    }
+
+    /// Registers the e-mail address of the chat, replacing the previous one
+    pub async fn set_email(&self, user_id: i64, address: &str, token: &str) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL set_email($1, $2, $3)",
+                &[Type::INT8, Type::TEXT, Type::VARCHAR],
+            )
+            .await?;
+
+        let address = self.seal(address);
+        self.inner
+            .execute(&stmt, &[&user_id, &address, &token])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Sets how often e-mails are sent, `false` if the chat has no e-mail address
+    pub async fn set_email_frequency(
+        &self,
+        user_id: i64,
+        frequency: Frequency,
+    ) -> Result<bool, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT set_email_frequency($1, $2)",
+                &[Type::INT8, Type::VARCHAR],
+            )
+            .await?;
+
+        Ok(self
+            .inner
+            .query_one(&stmt, &[&user_id, &frequency.as_str()])
+            .await?
+            .get(0))
+    }
+
+    pub async fn get_email(&self, user_id: i64) -> Result<Option<Email>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT address, frequency, token from get_email($1)",
+                &[Type::INT8],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query_opt(&stmt, &[&user_id])
+            .await?
+            .and_then(|row| {
+                Some(Email {
+                    address: self.open(row.get(0))?,
+                    frequency: Frequency::parse(row.get(1)).unwrap_or(Frequency::Instant),
+                    token: row.get(2),
+                })
+            });
+
+        Ok(res)
+    }
+
+    /// Removes the e-mail address of the chat, `false` if there is none
+    pub async fn remove_email(&self, user_id: i64) -> Result<bool, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT remove_email($1)", &[Type::INT8])
+            .await?;
+
+        Ok(self.inner.query_one(&stmt, &[&user_id]).await?.get(0))
+    }
+
+    /// Removes the e-mail address with the unsubscribe token, returns the address
+    pub async fn unsubscribe_email(&self, token: &str) -> Result<Option<String>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT unsubscribe_email($1)", &[Type::VARCHAR])
+            .await?;
+
+        let address: Option<String> = self.inner.query_one(&stmt, &[&token]).await?.get(0);
+        Ok(address.and_then(|address| self.open(address)))
+    }
+
+    /// Stored (possibly encrypted) e-mail addresses of all chats
+    pub async fn list_stored_emails(&self) -> Result<Vec<(i64, String)>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT user_id, address from list_stored_emails()", &[])
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Replaces the stored form of the chat's e-mail address, the token and the frequency are kept
+    pub async fn set_stored_email(&self, user_id: i64, stored: &str) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("CALL set_stored_email($1, $2)", &[Type::INT8, Type::TEXT])
+            .await?;
+
+        self.inner.execute(&stmt, &[&user_id, &stored]).await?;
+
+        Ok(())
+    }
+
+    /// Postpones the update till the next e-mail to the chat
+    pub async fn queue_email(
+        &self,
+        user_id: i64,
+        krate: &str,
+        version: &str,
+        action: &str,
+    ) -> Result<(), Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "CALL queue_email($1, $2, $3, $4)",
+                &[Type::INT8, Type::VARCHAR, Type::VARCHAR, Type::VARCHAR],
+            )
+            .await?;
+
+        self.inner
+            .execute(&stmt, &[&user_id, &krate, &version, &action])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Chats which should get an e-mail now
+    pub async fn due_emails(&self) -> Result<Vec<i64>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed("SELECT user_id from due_emails()", &[])
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[])
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        Ok(res)
+    }
+
+    /// Takes queued updates of the chat (sorted by crate) and marks the e-mail as sent
+    pub async fn take_email(&self, user_id: i64) -> Result<Vec<EmailEntry>, Error> {
+        let stmt = self
+            .inner
+            .prepare_typed(
+                "SELECT crate_name, version, action, notes from take_email($1)",
+                &[Type::INT8],
+            )
+            .await?;
+
+        let res = self
+            .inner
+            .query(&stmt, &[&user_id])
+            .await?
+            .into_iter()
+            .map(|row| EmailEntry {
+                krate: row.get(0),
+                version: row.get(1),
+                action: row.get(2),
+                notes: row.get(3),
+            })
+            .collect();
+
+        Ok(res)
+    }
+}
+
+#[async_trait::async_trait]
+impl Storage for Database {
+    type Error = Error;
+
+    async fn see_version(
+        &self,
+        krate: &str,
+        version: &str,
+        yanked: bool,
+        hash: &str,
+    ) -> Result<Option<bool>, Error> {
+        Database::see_version(self, krate, version, yanked, hash).await
+    }
+
+    async fn record_release(
+        &self,
+        krate: &str,
+        version: &str,
+        yanked: bool,
+        published_at: i64,
+        action: ActionKind,
+    ) -> Result<(), Error> {
+        Database::record_release(self, krate, version, yanked, published_at, action).await
+    }
+
+    async fn finish_release(&self, krate: &str, version: &str) -> Result<(), Error> {
+        Database::finish_release(self, krate, version).await
+    }
+
+    async fn pending_releases(&self) -> Result<Vec<(String, String, String, i64)>, Error> {
+        Database::pending_releases(self).await
+    }
+
+    async fn release_states(
+        &self,
+        krate: &str,
+    ) -> Result<Vec<(String, bool, Option<String>)>, Error> {
+        Database::release_states(self, krate).await
+    }
+
+    async fn list_subscribed_crates(&self) -> Result<Vec<String>, Error> {
+        Database::list_subscribed_crates(self).await
+    }
 }
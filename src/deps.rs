@@ -0,0 +1,296 @@
+//! Changes of dependency requirements between versions, read from the `deps` of index entries.
+//! Shown to subscriptions with the `show-deps` filter.
+use versions::SemVer;
+
+use crate::{krate::Crate, render::escape};
+
+/// `registry` of crates.io dependencies in indexes of alternative registries
+const CRATES_IO_INDEX: &str = "https://github.com/rust-lang/crates.io-index";
+
+/// Dependency of a version in the index
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Dependency {
+    /// Name the dependency is used by (differs from the package name if renamed)
+    pub name: String,
+    pub req: String,
+    #[serde(default)]
+    pub optional: bool,
+    /// `cfg(...)` or a target triple, `None` for all targets
+    #[serde(default)]
+    pub target: Option<String>,
+    /// `normal`, `dev` or `build`, `None` means normal
+    #[serde(default)]
+    pub kind: Option<String>,
+    /// Name of the crate if the dependency is renamed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    /// Index url of the registry of the dependency, `None` for the registry of the dependent crate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>,
+}
+
+impl Dependency {
+    fn kind(&self) -> &str {
+        self.kind.as_deref().unwrap_or("normal")
+    }
+
+    pub fn is_dev(&self) -> bool {
+        self.kind() == "dev"
+    }
+
+    /// Name of the crate (the package name of renamed dependencies)
+    pub fn crate_name(&self) -> &str {
+        self.package.as_deref().unwrap_or(&self.name)
+    }
+
+    fn same(&self, other: &Dependency) -> bool {
+        self.name == other.name && self.target == other.target && self.kind() == other.kind()
+    }
+}
+
+/// Notable changes of dependencies (dev-dependencies are ignored)
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DepsDiff {
+    /// New required dependencies with their requirements
+    pub added: Vec<(String, String)>,
+    /// Requirements with a higher minimum version: name, old and new requirement
+    pub bumped: Vec<(String, String, String)>,
+    /// Required dependencies which became optional, i.e. moved behind features
+    pub made_optional: Vec<String>,
+    /// Optional dependencies which became required
+    pub made_required: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// The smallest version matching the requirement, e.g. `1.2` for `>=1.2, <2` and `^1.2`.
+/// `None` for `*` and other requirements without a lower bound.
+pub fn min_version(req: &str) -> Option<SemVer> {
+    req.split(',')
+        .filter_map(|comparator| {
+            let version = comparator
+                .trim()
+                .trim_start_matches(|c| matches!(c, '^' | '~' | '=' | '>'))
+                .trim();
+            if comparator.trim().starts_with('<') {
+                return None;
+            }
+            let parts: Vec<&str> = version
+                .split('.')
+                .take_while(|part| !matches!(*part, "*" | "x" | "X"))
+                .collect();
+            match parts.len() {
+                0 => None,
+                1 => SemVer::new(&format!("{}.0.0", parts[0])),
+                2 => SemVer::new(&format!("{}.{}.0", parts[0], parts[1])),
+                _ => SemVer::new(&parts.join(".")),
+            }
+        })
+        .max()
+}
+
+/// Keys of the crates the version depends on (`myreg:name` for alternative registries), sorted.
+/// Dev-dependencies aren't counted, dependencies of unknown registries are left out.
+pub fn keys(krate: &Crate) -> Vec<String> {
+    let mut keys: Vec<String> = krate
+        .deps
+        .iter()
+        .filter(|dep| !dep.is_dev())
+        .filter_map(|dep| match (&dep.registry, &krate.registry) {
+            (None, None) => Some(dep.crate_name().to_owned()),
+            (None, Some(registry)) => Some(format!("{}:{}", registry.name, dep.crate_name())),
+            (Some(index), Some(_)) if index == CRATES_IO_INDEX => Some(dep.crate_name().to_owned()),
+            (Some(_), _) => None,
+        })
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// Dependencies of `new` compared to the `old` version
+pub fn diff(old: &Crate, new: &Crate) -> DepsDiff {
+    let mut diff = DepsDiff::default();
+    let old_deps: Vec<&Dependency> = old.deps.iter().filter(|dep| !dep.is_dev()).collect();
+    let new_deps: Vec<&Dependency> = new.deps.iter().filter(|dep| !dep.is_dev()).collect();
+
+    for dep in &new_deps {
+        match old_deps.iter().find(|old| old.same(dep)) {
+            None if !dep.optional => diff.added.push((dep.name.clone(), dep.req.clone())),
+            None => {}
+            Some(old) => {
+                if !old.optional && dep.optional {
+                    diff.made_optional.push(dep.name.clone());
+                } else if old.optional && !dep.optional {
+                    diff.made_required.push(dep.name.clone());
+                }
+                if let (Some(from), Some(to)) = (min_version(&old.req), min_version(&dep.req)) {
+                    if to > from {
+                        diff.bumped
+                            .push((dep.name.clone(), old.req.clone(), dep.req.clone()));
+                    }
+                }
+            }
+        }
+    }
+    diff.removed = old_deps
+        .iter()
+        .filter(|dep| !new_deps.iter().any(|new| new.same(dep)))
+        .map(|dep| dep.name.clone())
+        .collect();
+
+    // the same dependency may be listed for several targets
+    diff.made_optional.dedup();
+    diff.made_required.dedup();
+    diff.removed.dedup();
+    diff
+}
+
+fn code_list(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|name| format!("<code>{}</code>", escape(name)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl DepsDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.bumped.is_empty()
+            && self.made_optional.is_empty()
+            && self.made_required.is_empty()
+            && self.removed.is_empty()
+    }
+
+    /// Telegram html line, `None` if nothing notable changed
+    pub fn html(&self) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut parts = Vec::new();
+        if !self.added.is_empty() {
+            let added: Vec<String> = self
+                .added
+                .iter()
+                .map(|(name, req)| {
+                    format!("<code>{}</code> <code>{}</code>", escape(name), escape(req))
+                })
+                .collect();
+            parts.push(format!("new dependencies: {}", added.join(", ")));
+        }
+        if !self.bumped.is_empty() {
+            let bumped: Vec<String> = self
+                .bumped
+                .iter()
+                .map(|(name, from, to)| {
+                    format!(
+                        "<code>{}</code> <code>{}</code> → <code>{}</code>",
+                        escape(name),
+                        escape(from),
+                        escape(to)
+                    )
+                })
+                .collect();
+            parts.push(format!("bumped: {}", bumped.join(", ")));
+        }
+        if !self.made_optional.is_empty() {
+            parts.push(format!(
+                "moved behind features: {}",
+                code_list(&self.made_optional)
+            ));
+        }
+        if !self.made_required.is_empty() {
+            parts.push(format!("now required: {}", code_list(&self.made_required)));
+        }
+        if !self.removed.is_empty() {
+            parts.push(format!("dropped: {}", code_list(&self.removed)));
+        }
+
+        Some(parts.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> SemVer {
+        SemVer::new(s).unwrap()
+    }
+
+    fn krate(vers: &str, deps: &str) -> Crate {
+        serde_json::from_str(&format!(
+            r#"{{"name":"foo","vers":"{}","yanked":false,"deps":[{}]}}"#,
+            vers, deps
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn min_versions() {
+        assert_eq!(min_version("^1.0.100"), Some(v("1.0.100")));
+        assert_eq!(min_version("~1.2"), Some(v("1.2.0")));
+        assert_eq!(min_version(">=0.4, <0.6"), Some(v("0.4.0")));
+        assert_eq!(min_version("1.*"), Some(v("1.0.0")));
+        assert_eq!(min_version("*"), None);
+    }
+
+    #[test]
+    fn dependency_keys() {
+        let krate = krate(
+            "0.1.0",
+            r#"{"name":"tokio","req":"^1","optional":true},
+               {"name":"serde","req":"^1"},
+               {"name":"serde","req":"^1","target":"cfg(unix)"},
+               {"name":"json","req":"^1","package":"serde_json"},
+               {"name":"internal","req":"^1","registry":"https://example.com/index"},
+               {"name":"tempfile","req":"^3","kind":"dev"}"#,
+        );
+        assert_eq!(keys(&krate), vec!["serde", "serde_json", "tokio"]);
+    }
+
+    #[test]
+    fn dependency_changes() {
+        let old = krate(
+            "0.1.0",
+            r#"{"name":"serde","req":"^1.0.100"},
+               {"name":"native-tls","req":"^0.2"},
+               {"name":"log","req":"^0.4","optional":true},
+               {"name":"atty","req":"^0.2"},
+               {"name":"tempfile","req":"^3","kind":"dev"}"#,
+        );
+        let new = krate(
+            "0.2.0",
+            r#"{"name":"serde","req":"^1.0.130"},
+               {"name":"native-tls","req":"^0.2","optional":true},
+               {"name":"log","req":"^0.4"},
+               {"name":"tokio","req":"^1.5","kind":"normal"},
+               {"name":"tracing","req":"^0.1","optional":true},
+               {"name":"tempfile","req":"^4","kind":"dev"}"#,
+        );
+
+        let diff = diff(&old, &new);
+        assert_eq!(
+            diff,
+            DepsDiff {
+                added: vec![(String::from("tokio"), String::from("^1.5"))],
+                bumped: vec![(
+                    String::from("serde"),
+                    String::from("^1.0.100"),
+                    String::from("^1.0.130")
+                )],
+                made_optional: vec![String::from("native-tls")],
+                made_required: vec![String::from("log")],
+                removed: vec![String::from("atty")],
+            }
+        );
+        assert_eq!(
+            diff.html().unwrap(),
+            "new dependencies: <code>tokio</code> <code>^1.5</code>; \
+             bumped: <code>serde</code> <code>^1.0.100</code> → <code>^1.0.130</code>; \
+             moved behind features: <code>native-tls</code>; now required: <code>log</code>; \
+             dropped: <code>atty</code>"
+        );
+    }
+}
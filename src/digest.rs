@@ -0,0 +1,210 @@
+//! Daily digests: notifications of a chat queued and sent as one message. Notifications which came
+//! during quiet hours of a chat are sent here too, once the hours are over, and so are
+//! catch-up digests of notifications queued while the chat was paused (`/pause`).
+use std::time::Duration;
+
+use crate::{db::Database, metrics, notifier::Notifier, render, send::SendQueue};
+
+/// How often due digests are checked
+const CHECK_DELAY: Duration = Duration::from_secs(60);
+
+/// Validates `HH:MM` time of the digest
+pub fn parse_time(s: &str) -> Option<(u32, u32)> {
+    let (hours, minutes) = s.split_once(':')?;
+    if hours.len() != 2 || minutes.len() != 2 {
+        return None;
+    }
+    let (hours, minutes) = (hours.parse().ok()?, minutes.parse().ok()?);
+    if hours < 24 && minutes < 60 {
+        Some((hours, minutes))
+    } else {
+        None
+    }
+}
+
+/// The longest pause, a year
+const MAX_PAUSE_HOURS: u64 = 365 * 24;
+
+/// Validates the duration of a pause like `12h`, `3d` or `2w`
+pub fn parse_pause(s: &str) -> Option<Duration> {
+    let unit = match s.chars().last()? {
+        'h' => 1,
+        'd' => 24,
+        'w' => 7 * 24,
+        _ => return None,
+    };
+    let count: u64 = s[..s.len() - 1].parse().ok()?;
+    let hours = count.checked_mul(unit)?;
+    if 0 < hours && hours <= MAX_PAUSE_HOURS {
+        Some(Duration::from_secs(hours * 60 * 60))
+    } else {
+        None
+    }
+}
+
+/// Telegram html of the digest with the `title` line, `entries` are `(crate, version, action)`
+/// sorted by crate
+fn list_html(title: &str, entries: &[(String, String, String)]) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut last_crate = None;
+    for (krate, version, action) in entries {
+        let version = match action.as_str() {
+            "yanked" => format!("⚠ {} (yanked)", version),
+            "unyanked" => format!("{} (unyanked)", version),
+            _ => version.clone(),
+        };
+        if last_crate == Some(krate) {
+            let line = lines.last_mut().expect("there is a line of the last crate");
+            line.push_str(", ");
+            line.push_str(&version);
+        } else {
+            lines.push(format!("— <code>{}</code>: {}", krate, version));
+            last_crate = Some(krate);
+        }
+    }
+
+    format!("{}\n{}", title, lines.join("\n"))
+}
+
+/// Telegram html of the daily digest, see [`list_html`]
+pub fn html(entries: &[(String, String, String)]) -> String {
+    list_html("Updates of your crates for the last day:", entries)
+}
+
+/// Telegram html of the catch-up digest, see [`list_html`]
+fn catch_up_html(entries: &[(String, String, String)]) -> String {
+    list_html(
+        "▶ Notifications are resumed. Updates of your crates while they were paused:",
+        entries,
+    )
+}
+
+async fn send(queue: &SendQueue, db: &Database, chat_id: i64) {
+    let entries = match db.take_digest(chat_id).await {
+        Ok(entries) => entries,
+        Err(err) => {
+            tracing::error!("db error while taking digest of {}: {}", chat_id, err);
+            return;
+        }
+    };
+    if entries.is_empty() {
+        return;
+    }
+
+    queue.push(chat_id, render::fit_message(&html(&entries)), false);
+}
+
+/// Sends notifications deferred till the end of quiet hours
+async fn send_deferred(queue: &SendQueue, db: &Database) {
+    match db.take_deferred().await {
+        Ok(notifications) => {
+            for (chat_id, text) in notifications {
+                queue.push(chat_id, text, false);
+            }
+        }
+        Err(err) => tracing::error!("db error while taking deferred notifications: {}", err),
+    }
+}
+
+/// Sends notifications queued during pauses which are over as catch-up digests
+async fn send_catch_up(queue: &SendQueue, db: &Database) {
+    let chats = match db.take_resumed().await {
+        Ok(chats) => chats,
+        Err(err) => {
+            tracing::error!("db error while taking resumed chats: {}", err);
+            return;
+        }
+    };
+    for chat_id in chats {
+        let text = match db.take_digest(chat_id).await {
+            Ok(entries) if entries.is_empty() => String::from(
+                "▶ Notifications are resumed, there were no updates of your crates while they were paused.",
+            ),
+            Ok(entries) => render::fit_message(&catch_up_html(&entries)),
+            Err(err) => {
+                tracing::error!("db error while taking digest of {}: {}", chat_id, err);
+                continue;
+            }
+        };
+        queue.push(chat_id, text, false);
+    }
+}
+
+/// Sends digests, catch-up digests and deferred notifications when their time comes, forever.
+/// They wait in the database while the send queue is busy, notifications about releases go first.
+pub async fn run(queue: SendQueue, db: Database) {
+    loop {
+        if queue.is_busy() {
+            metrics::DIGESTS_DEFERRED.inc();
+            tracing::info!("send queue is busy, digests are put off");
+            tokio::time::delay_for(CHECK_DELAY).await;
+            continue;
+        }
+
+        send_catch_up(&queue, &db).await;
+        send_deferred(&queue, &db).await;
+
+        match db.due_digests().await {
+            Ok(chats) => {
+                for chat_id in chats {
+                    send(&queue, &db, chat_id).await;
+                }
+            }
+            Err(err) => tracing::error!("db error while getting due digests: {}", err),
+        }
+
+        tokio::time::delay_for(CHECK_DELAY).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times() {
+        assert_eq!(parse_time("09:00"), Some((9, 0)));
+        assert_eq!(parse_time("23:59"), Some((23, 59)));
+        assert_eq!(parse_time("24:00"), None);
+        assert_eq!(parse_time("9:00"), None);
+        assert_eq!(parse_time("noon"), None);
+    }
+
+    #[test]
+    fn pauses() {
+        assert_eq!(parse_pause("12h"), Some(Duration::from_secs(12 * 60 * 60)));
+        assert_eq!(
+            parse_pause("2w"),
+            Some(Duration::from_secs(14 * 24 * 60 * 60))
+        );
+        assert_eq!(
+            parse_pause("365d"),
+            Some(Duration::from_secs(365 * 24 * 60 * 60))
+        );
+        assert_eq!(parse_pause("366d"), None);
+        assert_eq!(parse_pause("0d"), None);
+        assert_eq!(parse_pause("-1d"), None);
+        assert_eq!(parse_pause("2"), None);
+        assert_eq!(parse_pause("w"), None);
+        assert_eq!(parse_pause("2й"), None);
+    }
+
+    #[test]
+    fn grouped() {
+        let entry = |krate: &str, version: &str, action: &str| {
+            (krate.to_owned(), version.to_owned(), action.to_owned())
+        };
+        let entries = [
+            entry("serde", "1.0.1", "new"),
+            entry("serde", "1.0.0", "yanked"),
+            entry("tokio", "0.2.22", "new"),
+        ];
+
+        assert_eq!(
+            html(&entries),
+            "Updates of your crates for the last day:\n\
+             — <code>serde</code>: 1.0.1, ⚠ 1.0.0 (yanked)\n\
+             — <code>tokio</code>: 0.2.22"
+        );
+    }
+}
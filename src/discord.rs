@@ -0,0 +1,269 @@
+//! Discord backend: channels from the config get embed cards about updates selected like for
+//! http hooks. Channels are posted to via their webhooks or by the bot.
+use std::sync::Arc;
+
+use reqwest::{header::AUTHORIZATION, Client};
+use tokio::sync::mpsc;
+use versions::SemVer;
+
+use crate::{
+    cfg::{Config, DiscordChannelConfig},
+    krate::Crate,
+    notifier::Notifier,
+    render,
+    util::{http_client, rfc3339, tryn},
+    ActionKind,
+};
+
+/// How many times sending is retried after the first attempt
+const RETRIES: usize = 3;
+
+/// Maximum length of a message text
+const MAX_CONTENT: usize = 2000;
+
+/// Maximum length of an embed description
+const MAX_DESCRIPTION: usize = 4096;
+
+/// Maximum length of an embed field value
+const MAX_FIELD: usize = 1024;
+
+/// Message flag: the message doesn't trigger push and desktop notifications
+const SUPPRESS_NOTIFICATIONS: u32 = 1 << 12;
+
+const API: &str = "https://discord.com/api/v10";
+
+/// Where a message is posted, displayed without the secret webhook url
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum Channel {
+    /// Webhook url
+    #[display(fmt = "(webhook)")]
+    Webhook(String),
+    /// Channel id, posted by the bot
+    #[display(fmt = "{}", _0)]
+    Bot(String),
+}
+
+impl Channel {
+    /// Channel of the config, `None` if it has neither a webhook nor an id
+    pub fn of(channel: &DiscordChannelConfig) -> Option<Self> {
+        match (&channel.webhook, &channel.id) {
+            (Some(webhook), _) => Some(Channel::Webhook(webhook.clone())),
+            (None, Some(id)) => Some(Channel::Bot(id.clone())),
+            (None, None) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct Message {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    content: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    embeds: Vec<Embed>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flags: Option<u32>,
+    /// Changelogs may contain `@everyone` and the like, nobody is pinged
+    allowed_mentions: AllowedMentions,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+struct AllowedMentions {
+    parse: [&'static str; 0],
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct Embed {
+    title: String,
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    color: u32,
+    /// RFC 3339 date of the release
+    timestamp: String,
+    fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct Field {
+    name: &'static str,
+    value: String,
+    inline: bool,
+}
+
+/// Discord markdown of telegram html, fitted into `max_len` characters
+fn fitted_markdown(html: &str, max_len: usize) -> String {
+    // markup and link urls make the markdown longer than the text, so the text gets a half
+    let mut markdown = render::markdown(&render::fit(html, usize::MAX, max_len / 2));
+    if let Some((idx, _)) = markdown.char_indices().nth(max_len - 1) {
+        markdown.truncate(idx);
+        markdown.push('…');
+    }
+
+    markdown
+}
+
+/// Embed card about the update: version, release date, links, feature changes and release notes
+pub fn release_card(
+    krate: &Crate,
+    action: &ActionKind,
+    notes: Option<&str>,
+    previous: Option<&SemVer>,
+    features: Option<&str>,
+    published_at: i64,
+) -> Message {
+    let (title, color) = match action {
+        ActionKind::NewVersion => (format!("{} {}", krate.key(), krate.id.vers), 0xdea584),
+        ActionKind::Yanked => (
+            format!("⚠ {} {} was yanked", krate.key(), krate.id.vers),
+            0xe74c3c,
+        ),
+        ActionKind::Unyanked => (
+            format!("{} {} was unyanked", krate.key(), krate.id.vers),
+            0x2ecc71,
+        ),
+    };
+    let previous = previous.map(ToString::to_string);
+    let version = match &previous {
+        Some(previous) => format!("`{}` → `{}`", previous, krate.id.vers),
+        None => format!("`{}`", krate.id.vers),
+    };
+
+    let mut fields = vec![
+        Field {
+            name: "Version",
+            value: version,
+            inline: true,
+        },
+        Field {
+            name: "Links",
+            value: format!(
+                "[docs]({}) · [diff]({})",
+                krate.docsrs(),
+                krate.diff_url(previous.as_deref())
+            ),
+            inline: true,
+        },
+    ];
+    if let Some(features) = features {
+        fields.push(Field {
+            name: "Features",
+            value: fitted_markdown(features, MAX_FIELD),
+            inline: false,
+        });
+    }
+
+    let embed = Embed {
+        title,
+        url: krate.cratesio(),
+        description: notes.map(|notes| fitted_markdown(notes, MAX_DESCRIPTION)),
+        color,
+        timestamp: rfc3339(published_at),
+        fields,
+    };
+
+    Message {
+        embeds: vec![embed],
+        ..Message::default()
+    }
+}
+
+/// Handle of the queue, messages are sent by a background task
+#[derive(Clone)]
+pub struct DiscordQueue {
+    tx: mpsc::UnboundedSender<(Channel, Message)>,
+}
+
+impl DiscordQueue {
+    /// Spawns the task sending queued messages, `None` if discord isn't configured
+    pub fn start(cfg: Arc<Config>) -> Option<Self> {
+        let discord = cfg.discord.as_ref()?;
+        for channel in &discord.channels {
+            match Channel::of(channel) {
+                None => tracing::warn!("discord channel without a webhook and an id is skipped"),
+                Some(Channel::Bot(id)) if discord.token.is_none() => {
+                    tracing::warn!(
+                        "discord channel {} has no webhook and there is no bot token",
+                        id
+                    )
+                }
+                Some(_) => {}
+            }
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(run(cfg, rx));
+
+        Some(Self { tx })
+    }
+
+    pub fn push_message(&self, channel: Channel, message: Message) {
+        if self.tx.send((channel, message)).is_err() {
+            tracing::error!("discord queue is closed, message is lost");
+        }
+    }
+}
+
+impl Notifier for DiscordQueue {
+    type Target = Channel;
+
+    fn push(&self, channel: Channel, html: String, quiet: bool) {
+        let message = Message {
+            content: fitted_markdown(&html, MAX_CONTENT),
+            flags: if quiet {
+                Some(SUPPRESS_NOTIFICATIONS)
+            } else {
+                None
+            },
+            ..Message::default()
+        };
+        self.push_message(channel, message);
+    }
+}
+
+async fn send(
+    client: &Client,
+    channel: &Channel,
+    message: &Message,
+    token: Option<&str>,
+) -> reqwest::Result<()> {
+    let request = match channel {
+        Channel::Webhook(url) => client.post(url),
+        Channel::Bot(id) => client
+            .post(&format!("{}/channels/{}/messages", API, id))
+            .header(AUTHORIZATION, format!("Bot {}", token.unwrap_or_default())),
+    };
+    request.json(message).send().await?.error_for_status()?;
+
+    Ok(())
+}
+
+async fn run(cfg: Arc<Config>, mut rx: mpsc::UnboundedReceiver<(Channel, Message)>) {
+    let token = cfg
+        .discord
+        .as_ref()
+        .and_then(|discord| discord.token.as_deref());
+    let client = match http_client() {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::error!(
+                "couldn't create http client, discord won't be notified: {}",
+                err
+            );
+            return;
+        }
+    };
+
+    while let Some((channel, message)) = rx.recv().await {
+        let result = tryn(RETRIES, cfg.retry_delay.0, || {
+            send(&client, &channel, &message, token)
+        })
+        .await;
+        if let Err(err) = result {
+            tracing::error!(
+                "error while sending message to discord channel {}: {}",
+                channel,
+                err
+            );
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! E-mails: updates of chats with an e-mail address are queued and mailed via SMTP as html
+//! with release notes, instantly or as daily/weekly digests
+use std::{fmt::Write, sync::Arc, time::Duration};
+
+use lettre::{
+    message::{header::ContentType, Message},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, Tokio02Connector, Tokio02Transport,
+};
+
+use crate::{
+    cfg::{Config, EmailConfig},
+    db::{Database, EmailEntry},
+    http,
+    krate::Crate,
+    render::escape,
+};
+
+/// How often due e-mails are checked
+const CHECK_DELAY: Duration = Duration::from_secs(60);
+
+/// How often a chat gets e-mails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    /// An e-mail per update (updates of a minute are batched)
+    Instant,
+    Daily,
+    Weekly,
+}
+
+impl Frequency {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "instant" => Some(Frequency::Instant),
+            "daily" => Some(Frequency::Daily),
+            "weekly" => Some(Frequency::Weekly),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Frequency::Instant => "instant",
+            Frequency::Daily => "daily",
+            Frequency::Weekly => "weekly",
+        }
+    }
+}
+
+#[derive(Debug, derive_more::Display, derive_more::From)]
+enum Error {
+    Db(tokio_postgres::Error),
+    Address(lettre::address::AddressError),
+    Email(lettre::error::Error),
+    Smtp(lettre::transport::smtp::Error),
+}
+
+/// Rough check of an address from a user, the SMTP server has the last word
+pub fn is_address(s: &str) -> bool {
+    match s.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !domain.contains('@')
+                && s.len() <= 254
+                && !s.contains(|c: char| c.is_whitespace() || c == '<' || c == '>')
+        }
+        None => false,
+    }
+}
+
+fn subject(entries: &[EmailEntry]) -> String {
+    match entries {
+        [entry] => format!("{} {}", entry.krate, entry.version),
+        _ => {
+            let mut crates: Vec<&str> = entries.iter().map(|e| e.krate.as_str()).collect();
+            crates.dedup();
+            format!("Updates of {} crates", crates.len())
+        }
+    }
+}
+
+/// Html e-mail, `entries` are sorted by crate
+fn html(entries: &[EmailEntry], unsubscribe: Option<&str>, cfg: &Config) -> String {
+    let mut out =
+        String::from("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"></head><body>\n");
+    for entry in entries {
+        let title = match entry.action.as_str() {
+            "yanked" => format!("⚠ {} {} was yanked", entry.krate, entry.version),
+            "unyanked" => format!("{} {} was unyanked", entry.krate, entry.version),
+            _ => format!("{} {}", entry.krate, entry.version),
+        };
+        let _ = writeln!(out, "<h3>{}</h3>", escape(&title));
+        // release notes are telegram html, which is a subset of html with `\n` line breaks
+        if let Some(notes) = &entry.notes {
+            let _ = writeln!(out, "<div style=\"white-space: pre-wrap\">{}</div>", notes);
+        }
+        if let Some(krate) = Crate::from_key(&entry.krate, &entry.version, false, cfg) {
+            let _ = writeln!(out, "<p>{}</p>", krate.html_links());
+        }
+    }
+
+    out.push_str("<hr>\n<p><small>You get this e-mail because you've registered the address in crate_upd_bot. ");
+    match unsubscribe {
+        Some(url) => {
+            let _ = write!(out, "<a href=\"{}\">Unsubscribe</a>", escape(url));
+        }
+        None => out.push_str("Use <code>/email off</code> in the bot to unsubscribe."),
+    }
+    out.push_str("</small></p>\n</body></html>\n");
+    out
+}
+
+async fn send(
+    transport: &AsyncSmtpTransport<Tokio02Connector>,
+    db: &Database,
+    cfg: &Config,
+    email: &EmailConfig,
+    user_id: i64,
+) -> Result<(), Error> {
+    let recipient = match db.get_email(user_id).await? {
+        Some(recipient) => recipient,
+        None => return Ok(()),
+    };
+    let entries = db.take_email(user_id).await?;
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let unsubscribe = http::public_url(&format!("/email/unsubscribe/{}", recipient.token), cfg);
+    let message = Message::builder()
+        .from(email.from.parse()?)
+        .to(recipient.address.parse()?)
+        .subject(subject(&entries))
+        .header(ContentType(mime::TEXT_HTML_UTF_8))
+        .body(html(&entries, unsubscribe.as_deref(), cfg))?;
+    transport.send(message).await?;
+
+    Ok(())
+}
+
+/// Mails due updates, forever
+pub async fn run(db: Database, cfg: Arc<Config>) {
+    let email = match &cfg.email {
+        Some(email) => email,
+        None => return,
+    };
+    let transport = match AsyncSmtpTransport::<Tokio02Connector>::relay(&email.smtp_host) {
+        Ok(builder) => builder
+            .credentials(Credentials::new(
+                email.smtp_user.clone(),
+                email.smtp_password.clone(),
+            ))
+            .build(),
+        Err(err) => {
+            tracing::error!("invalid smtp relay, e-mails won't be sent: {}", err);
+            return;
+        }
+    };
+
+    loop {
+        match db.due_emails().await {
+            Ok(chats) => {
+                for user_id in chats {
+                    if let Err(err) = send(&transport, &db, &cfg, email, user_id).await {
+                        tracing::error!("error while mailing updates to {}: {}", user_id, err);
+                    }
+                }
+            }
+            Err(err) => tracing::error!("db error while getting due e-mails: {}", err),
+        }
+
+        tokio::time::delay_for(CHECK_DELAY).await;
+    }
+}
+
+/// Page of the unsubscribe link, `None` if the token is unknown
+pub async fn unsubscribe(
+    token: &str,
+    db: &Database,
+) -> Result<Option<String>, tokio_postgres::Error> {
+    let address = db.unsubscribe_email(token).await?;
+    Ok(address.map(|address| {
+        format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"></head><body>\
+             <p>{} is unsubscribed from crate_upd_bot e-mails.</p></body></html>\n",
+            escape(&address)
+        )
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses() {
+        assert!(is_address("me@example.com"));
+        assert!(!is_address("me@localhost"));
+        assert!(!is_address("@example.com"));
+        assert!(!is_address("me@exa@mple.com"));
+        assert!(!is_address("me <me@example.com>"));
+    }
+}
@@ -0,0 +1,205 @@
+//! `extract` subcommand: prints release notes of a version found like for notifications, e.g. for
+//! release scripts in CI
+//!
+//! ```text
+//! crate_upd_bot extract [--format markdown|html|json|text] <crate> <version>
+//! crate_upd_bot extract [--format markdown|html|json|text] --file CHANGELOG.md <version>
+//! ```
+//!
+//! Notes of a crate are looked up in the sources of `[changelog]` of `config.toml`, a file is
+//! parsed without the config. Exits with 1 if there are no notes and with 2 on invalid arguments.
+use versions::SemVer;
+
+use crate::{
+    cfg::Config,
+    changelog,
+    render::{markdown, plain},
+};
+
+const USAGE: &str = "usage: crate_upd_bot extract [--format markdown|html|json|text] <crate> <version>\n       \
+                     crate_upd_bot extract [--format markdown|html|json|text] --file <path> <version>";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Markdown,
+    /// Telegram html, as in notifications
+    Html,
+    /// `{"crate", "version", "html", "markdown", "text"}`
+    Json,
+    Text,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "markdown" | "md" => Some(Format::Markdown),
+            "html" => Some(Format::Html),
+            "json" => Some(Format::Json),
+            "text" | "plain" => Some(Format::Text),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Source {
+    Crate(String),
+    File(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Args {
+    format: Format,
+    source: Source,
+    version: String,
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut format = Format::Markdown;
+    let mut file = None;
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                let value = args.next().ok_or("--format needs a value")?;
+                format = Format::parse(value).ok_or_else(|| format!("unknown format {}", value))?;
+            }
+            "--file" => file = Some(args.next().ok_or("--file needs a path")?.clone()),
+            flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    let (source, version) = match (file, &positional[..]) {
+        (Some(path), [version]) => (Source::File(path), version.clone()),
+        (None, [krate, version]) => (Source::Crate(krate.clone()), version.clone()),
+        _ => return Err(String::from("wrong number of arguments")),
+    };
+    if SemVer::new(&version).is_none() {
+        return Err(format!("{} isn't a semver version", version));
+    }
+
+    Ok(Args {
+        format,
+        source,
+        version,
+    })
+}
+
+#[derive(serde::Serialize)]
+struct Notes<'a> {
+    #[serde(rename = "crate")]
+    krate: Option<&'a str>,
+    version: &'a str,
+    html: &'a str,
+    markdown: String,
+    text: String,
+}
+
+/// Notes in the format, `krate` is `None` for notes from a file
+fn render(format: Format, krate: Option<&str>, version: &str, html: &str) -> String {
+    match format {
+        Format::Markdown => markdown(html),
+        Format::Html => html.to_owned(),
+        Format::Text => plain(html),
+        Format::Json => serde_json::to_string_pretty(&Notes {
+            krate,
+            version,
+            html,
+            markdown: markdown(html),
+            text: plain(html),
+        })
+        .unwrap_or_default(),
+    }
+}
+
+/// Runs the subcommand with arguments after `extract`, returns the exit code
+pub async fn run(args: &[String]) -> i32 {
+    let args = match parse_args(args) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            return 2;
+        }
+    };
+
+    let (krate, notes) = match &args.source {
+        Source::File(path) => {
+            let src = match std::fs::read_to_string(path) {
+                Ok(src) => src,
+                Err(err) => {
+                    eprintln!("error: couldn't read {}: {}", path, err);
+                    return 2;
+                }
+            };
+            let version = SemVer::new(&args.version).expect("the version was validated");
+            (None, changelog::file_notes(path, &src, &version))
+        }
+        Source::Crate(krate) => {
+            let cfg = match Config::read() {
+                Ok(cfg) => cfg,
+                Err(err) => {
+                    eprintln!("error: couldn't read config.toml: {}", err);
+                    return 2;
+                }
+            };
+            let notes = changelog::release_notes(krate, &[&args.version], None, &cfg).await;
+            (Some(krate.as_str()), notes)
+        }
+    };
+
+    match notes {
+        Some(html) => {
+            println!("{}", render(args.format, krate, &args.version, &html));
+            0
+        }
+        None => {
+            eprintln!("no release notes of {} found", args.version);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        let args: Vec<String> = args.iter().map(|&arg| arg.to_owned()).collect();
+        parse_args(&args)
+    }
+
+    #[test]
+    fn arguments() {
+        assert_eq!(
+            args(&["serde", "1.0.130"]),
+            Ok(Args {
+                format: Format::Markdown,
+                source: Source::Crate(String::from("serde")),
+                version: String::from("1.0.130"),
+            })
+        );
+        assert_eq!(
+            args(&["--file", "CHANGELOG.md", "--format", "json", "0.2.0"]),
+            Ok(Args {
+                format: Format::Json,
+                source: Source::File(String::from("CHANGELOG.md")),
+                version: String::from("0.2.0"),
+            })
+        );
+        assert!(args(&["serde"]).is_err());
+        assert!(args(&["serde", "latest"]).is_err());
+        assert!(args(&["--format", "pdf", "serde", "1.0.0"]).is_err());
+    }
+
+    #[test]
+    fn formats() {
+        let html = "<b>Added</b>\n• Streaming &amp; more";
+        assert_eq!(
+            render(Format::Text, None, "1.0.0", html),
+            "Added\n• Streaming & more"
+        );
+        assert!(render(Format::Json, Some("foo"), "1.0.0", html).contains("\"crate\": \"foo\""));
+    }
+}
@@ -0,0 +1,152 @@
+//! Changes of cargo features between versions, read from the index entries of the versions
+use std::collections::BTreeMap;
+
+use crate::{krate::Crate, render::escape};
+
+/// Features added, removed and renamed by a release
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FeatureDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// `(old, new)` names: a removed feature enabling exactly the same as an added one
+    pub renamed: Vec<(String, String)>,
+}
+
+/// All features of the version, `features2` of the index included, with sorted values
+fn features(krate: &Crate) -> BTreeMap<&str, Vec<&str>> {
+    krate
+        .features
+        .iter()
+        .chain(&krate.features2)
+        .map(|(name, enables)| {
+            let mut enables: Vec<&str> = enables.iter().map(String::as_str).collect();
+            enables.sort_unstable();
+            (name.as_str(), enables)
+        })
+        .collect()
+}
+
+/// Features of `new` compared to the `old` version
+pub fn diff(old: &Crate, new: &Crate) -> FeatureDiff {
+    let old = features(old);
+    let new = features(new);
+    let mut added: Vec<&str> = new
+        .keys()
+        .filter(|f| !old.contains_key(*f))
+        .copied()
+        .collect();
+    let mut removed: Vec<&str> = old
+        .keys()
+        .filter(|f| !new.contains_key(*f))
+        .copied()
+        .collect();
+
+    // marker features (e.g. `std`) enable nothing, so they can't be told apart
+    let mut renamed = Vec::new();
+    removed.retain(|&from| {
+        let enables = &old[from];
+        if enables.is_empty() {
+            return true;
+        }
+        match added.iter().position(|to| &new[to] == enables) {
+            Some(idx) => {
+                renamed.push((from.to_owned(), added.remove(idx).to_owned()));
+                false
+            }
+            None => true,
+        }
+    });
+
+    FeatureDiff {
+        added: added.into_iter().map(str::to_owned).collect(),
+        removed: removed.into_iter().map(str::to_owned).collect(),
+        renamed,
+    }
+}
+
+fn code_list(features: &[String]) -> String {
+    features
+        .iter()
+        .map(|feature| format!("<code>{}</code>", escape(feature)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl FeatureDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.renamed.is_empty()
+    }
+
+    /// Telegram html line, e.g. `new feature: <code>rustls</code>; removed: <code>native-tls</code>`.
+    /// `None` if the features didn't change.
+    pub fn html(&self) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut parts = Vec::new();
+        match self.added.len() {
+            0 => {}
+            1 => parts.push(format!("new feature: {}", code_list(&self.added))),
+            _ => parts.push(format!("new features: {}", code_list(&self.added))),
+        }
+        if !self.removed.is_empty() {
+            parts.push(format!("removed: {}", code_list(&self.removed)));
+        }
+        if !self.renamed.is_empty() {
+            let renamed: Vec<String> = self
+                .renamed
+                .iter()
+                .map(|(from, to)| {
+                    format!(
+                        "<code>{}</code> → <code>{}</code>",
+                        escape(from),
+                        escape(to)
+                    )
+                })
+                .collect();
+            parts.push(format!("renamed: {}", renamed.join(", ")));
+        }
+
+        Some(parts.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn krate(line: &str) -> Crate {
+        serde_json::from_str(line).unwrap()
+    }
+
+    #[test]
+    fn feature_changes() {
+        let old = krate(
+            r#"{"name":"foo","vers":"0.1.0","yanked":false,
+                "features":{"default":["std"],"std":[],"tls":["native-tls"],"json":["serde_json"]}}"#,
+        );
+        let new = krate(
+            r#"{"name":"foo","vers":"0.2.0","yanked":false,
+                "features":{"default":["std"],"alloc":[],"native-tls":["native-tls"]},
+                "features2":{"tokio-rustls":["dep:tokio-rustls"]}}"#,
+        );
+
+        let diff = diff(&old, &new);
+        assert_eq!(
+            diff,
+            FeatureDiff {
+                added: vec![String::from("alloc"), String::from("tokio-rustls")],
+                removed: vec![String::from("json"), String::from("std")],
+                renamed: vec![(String::from("tls"), String::from("native-tls"))],
+            }
+        );
+        assert_eq!(
+            diff.html().unwrap(),
+            "new features: <code>alloc</code>, <code>tokio-rustls</code>; \
+             removed: <code>json</code>, <code>std</code>; \
+             renamed: <code>tls</code> → <code>native-tls</code>"
+        );
+        assert_eq!(super::diff(&new, &new).html(), None);
+    }
+}
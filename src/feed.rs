@@ -0,0 +1,152 @@
+//! Atom feeds of archived releases, served if enabled in the config:
+//! `/feed/crate/{crate}.xml` for a single crate and `/feed/user/{token}.xml` for subscriptions of a chat
+use std::fmt::Write;
+
+use crate::{
+    cfg::Config,
+    db::{Database, FeedEntry},
+    http,
+    krate::{split_key, Crate},
+    render::escape,
+};
+
+/// Number of entries in a feed
+const ENTRIES: i64 = 50;
+
+/// Escapes text for xml elements and double-quoted attributes
+fn xml_escape(s: &str) -> String {
+    escape(s).replace('"', "&quot;")
+}
+
+fn render(
+    id: &str,
+    title: &str,
+    link: Option<&str>,
+    entries: &[FeedEntry],
+    cfg: &Config,
+) -> String {
+    let updated = entries
+        .first()
+        .map_or("1970-01-01T00:00:00Z", |entry| entry.published_at.as_str());
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    out.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    let _ = writeln!(out, "<id>{}</id>", xml_escape(id));
+    let _ = writeln!(out, "<title>{}</title>", xml_escape(title));
+    let _ = writeln!(out, "<updated>{}</updated>", updated);
+    out.push_str("<author><name>crate_upd_bot</name></author>\n");
+    if let Some(link) = link {
+        let _ = writeln!(out, "<link href=\"{}\"/>", xml_escape(link));
+    }
+
+    for entry in entries {
+        let krate = Crate::from_key(&entry.krate, &entry.version, entry.yanked, cfg);
+        // release notes are telegram html, which is a subset of html
+        let mut content = entry.notes.clone().unwrap_or_default();
+        if let Some(krate) = &krate {
+            if !content.is_empty() {
+                content.push_str("<br/><br/>");
+            }
+            content.push_str(&krate.html_links());
+        }
+
+        out.push_str("<entry>\n");
+        let _ = writeln!(
+            out,
+            "<id>urn:crate-upd-bot:{}:{}</id>",
+            xml_escape(&entry.krate),
+            xml_escape(&entry.version)
+        );
+        let _ = writeln!(
+            out,
+            "<title>{} {}{}</title>",
+            xml_escape(&entry.krate),
+            xml_escape(&entry.version),
+            if entry.yanked { " (yanked)" } else { "" }
+        );
+        let _ = writeln!(out, "<updated>{}</updated>", entry.published_at);
+        if let Some(krate) = &krate {
+            let _ = writeln!(out, "<link href=\"{}\"/>", xml_escape(&krate.docsrs()));
+        }
+        let _ = writeln!(
+            out,
+            "<content type=\"html\">{}</content>",
+            xml_escape(&content)
+        );
+        out.push_str("</entry>\n");
+    }
+
+    out.push_str("</feed>\n");
+    out
+}
+
+/// Feed of the path (without `/feed/`), `None` if there is no such feed
+pub async fn feed(
+    path: &str,
+    db: &Database,
+    cfg: &Config,
+) -> Result<Option<String>, tokio_postgres::Error> {
+    let path = match path.strip_suffix(".xml") {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    if let Some(key) = path.strip_prefix("crate/") {
+        let key = key.replace("%3A", ":").replace("%3a", ":");
+        // crates of alternative registries may be private, they are only in feeds of users
+        if split_key(&key).0.is_some() {
+            return Ok(None);
+        }
+        let entries = db.crate_feed(&key, ENTRIES).await?;
+        if entries.is_empty() && !Crate::exists(&key, cfg).await {
+            return Ok(None);
+        }
+
+        let page = Crate::from_key(&key, "", false, cfg).map(|krate| krate.cratesio());
+        return Ok(Some(render(
+            &format!("urn:crate-upd-bot:crate:{}", key),
+            &format!("Releases of {}", key),
+            page.as_deref(),
+            &entries,
+            cfg,
+        )));
+    }
+
+    if let Some(token) = path.strip_prefix("user/") {
+        let user_id = match db.feed_user(token).await? {
+            Some(user_id) => user_id,
+            None => return Ok(None),
+        };
+        let entries = db.user_feed(user_id, ENTRIES).await?;
+        return Ok(Some(render(
+            &format!("urn:crate-upd-bot:user:{}", token),
+            "Releases of subscribed crates",
+            None,
+            &entries,
+            cfg,
+        )));
+    }
+
+    Ok(None)
+}
+
+/// `true` if feeds are served and their public url is known
+pub fn is_public(cfg: &Config) -> bool {
+    cfg.feed.enabled && cfg.http.url.is_some()
+}
+
+/// Public url of the chat's feed
+pub fn user_url(token: &str, cfg: &Config) -> String {
+    http::public_url(&format!("/feed/user/{}.xml", token), cfg).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaping() {
+        assert_eq!(
+            xml_escape(r#"<a href="x">&</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+        );
+    }
+}
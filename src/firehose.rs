@@ -0,0 +1,140 @@
+//! `/subscribe_new`: first publishes of every crate, for chats watching the ecosystem grow,
+//! optionally only of crates with some keywords or categories. New crates are told by their file
+//! being created in the git index ([`IndexEvent::created`](crate::index::IndexEvent::created)),
+//! not by comparing versions, so only the git index of crates.io feeds it.
+use crate::{
+    cratesio::{self, About},
+    db::Database,
+    krate::Crate,
+    notifier::Notifiers,
+    render::escape,
+    send::Receipt,
+    util::http_client,
+};
+
+/// Descriptions are cut to this many characters
+const MAX_DESCRIPTION: usize = 300;
+
+/// Whether a chat with `topics` wants the crate: one of them is a keyword of the crate, its
+/// category or the parent of its category. Chats without topics want every crate.
+pub fn wants(topics: &[String], about: &About) -> bool {
+    topics.is_empty()
+        || topics.iter().any(|topic| {
+            about.keywords.iter().any(|keyword| keyword == topic)
+                || about.categories.iter().any(|category| {
+                    category == topic
+                        || category
+                            .strip_prefix(topic.as_str())
+                            .map_or(false, |rest| rest.starts_with("::"))
+                })
+        })
+}
+
+/// Topic as written by a user (e.g. `#Async`) in the form crates.io uses: trimmed, lowercase,
+/// without `#`
+pub fn normalize_topic(topic: &str) -> String {
+    topic.trim().trim_start_matches('#').to_lowercase()
+}
+
+/// Whether the (normalized) topic can be a keyword or a category slug
+pub fn is_valid_topic(topic: &str) -> bool {
+    !topic.is_empty()
+        && topic.len() <= 64
+        && topic
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':'))
+}
+
+/// Telegram html of the announcement
+pub fn html(krate: &Crate, about: &About) -> String {
+    let mut text = format!(
+        "🌱 New crate: <code>{} {}</code> {}",
+        escape(&krate.id.name),
+        escape(&krate.id.vers),
+        krate.html_links()
+    );
+    if let Some(description) = about.description.as_deref().map(str::trim) {
+        let description = match description.char_indices().nth(MAX_DESCRIPTION) {
+            Some((end, _)) => format!("{}…", &description[..end]),
+            None => description.to_owned(),
+        };
+        text.push_str(&format!("\n{}", escape(&description)));
+    }
+    if !about.keywords.is_empty() {
+        let tags: Vec<String> = about
+            .keywords
+            .iter()
+            .map(|keyword| format!("#{}", escape(&keyword.replace('-', "_"))))
+            .collect();
+        text.push_str(&format!("\n{}", tags.join(" ")));
+    }
+    text.push_str(&format!(
+        "\nUse <code>/subscribe {}</code> to follow its releases.",
+        escape(&krate.id.name)
+    ));
+    text
+}
+
+/// Announces the first publish of the crates.io crate to chats subscribed to new crates.
+/// Keywords and categories are looked up only if there are subscribers.
+pub async fn announce(krate: &Crate, notifiers: &Notifiers, db: &Database) {
+    let subscribers = db
+        .list_new_crate_subscribers()
+        .await
+        .map_err(|err| tracing::error!("db error while getting new crate subscribers: {}", err))
+        .unwrap_or_default();
+    if subscribers.is_empty() {
+        return;
+    }
+
+    // without crates.io only chats without topics get the announcement
+    let about = match http_client() {
+        Ok(client) => cratesio::about(&client, &krate.id.name, &krate.id.vers)
+            .await
+            .map_err(|err| tracing::warn!("couldn't get info of {}: {}", krate.id.name, err))
+            .unwrap_or_default(),
+        Err(err) => {
+            tracing::error!("couldn't create http client: {}", err);
+            About::default()
+        }
+    };
+    let text = html(krate, &about);
+    let receipt = Receipt {
+        krate: krate.key(),
+        version: krate.id.vers.clone(),
+        action: String::from("new_crate"),
+        single: false,
+    };
+    for (chat_id, topics) in subscribers {
+        if wants(&topics, &about) {
+            notifiers
+                .deliver(db, chat_id, text.clone(), true, &receipt)
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topics() {
+        let about = About {
+            description: None,
+            keywords: vec![String::from("async"), String::from("http")],
+            categories: vec![String::from("web-programming::http-server")],
+        };
+        let topics = |topics: &[&str]| topics.iter().map(|&t| t.to_owned()).collect::<Vec<_>>();
+        assert!(wants(&[], &about));
+        assert!(wants(&topics(&["gamedev", "http"]), &about));
+        assert!(wants(&topics(&["web-programming"]), &about));
+        assert!(wants(&topics(&["web-programming::http-server"]), &about));
+        assert!(!wants(&topics(&["web"]), &about));
+        assert!(!wants(&topics(&["gamedev"]), &about));
+
+        assert_eq!(normalize_topic(" #Async "), "async");
+        assert!(is_valid_topic("web-programming::http-server"));
+        assert!(!is_valid_topic("a b"));
+    }
+}
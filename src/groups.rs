@@ -0,0 +1,119 @@
+//! Dependency groups (`/subscribe_deps axum`): subscriptions to the direct dependencies of a
+//! crate, moved to its new dependencies when a release changes them
+use std::{cmp::Ordering, io};
+
+use kacl_parser::VersionScheme;
+
+use crate::{
+    cfg::Config,
+    db::Database,
+    deps,
+    krate::{self, Crate},
+    render::escape,
+};
+
+/// The release dependencies are followed of: the greatest version which isn't yanked,
+/// prereleases count only if there are no other versions
+fn latest(all: &[Crate], scheme: VersionScheme) -> Option<&Crate> {
+    all.iter().filter(|krate| !krate.yanked).max_by(|a, b| {
+        let stable = |krate: &Crate| !scheme.is_prerelease(&krate.id.vers);
+        stable(a).cmp(&stable(b)).then_with(|| {
+            scheme
+                .compare(&a.id.vers, &b.id.vers)
+                .unwrap_or(Ordering::Equal)
+        })
+    })
+}
+
+/// Direct dependencies of the latest release of the crate (see [`deps::keys`]),
+/// `None` if all versions are yanked
+pub async fn dependencies(key: &str, cfg: &Config) -> io::Result<Option<Vec<String>>> {
+    let all = Crate::read_all(key, cfg).await?;
+    let scheme = krate::version_scheme(key, all.iter().map(|krate| krate.id.vers.as_str()), cfg);
+    Ok(latest(&all, scheme).map(deps::keys))
+}
+
+/// Moves groups following the dependencies of the crate to the dependencies of its latest
+/// release, called on every release and yank of the crate
+pub async fn refresh(key: &str, db: &Database, cfg: &Config) {
+    match db.has_dep_groups(key).await {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(err) => {
+            tracing::error!("db error while checking dependency groups: {}", err);
+            return;
+        }
+    }
+
+    let keys = match dependencies(key, cfg).await {
+        Ok(Some(keys)) => keys,
+        Ok(None) => return,
+        Err(err) => {
+            tracing::warn!("couldn't read dependencies of {}: {}", key, err);
+            return;
+        }
+    };
+    let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+    tracing::info!("refreshing dependency groups of {}", key);
+    db.refresh_dep_groups(key, &keys)
+        .await
+        .unwrap_or_else(|err| tracing::error!("db error while refreshing groups: {}", err));
+}
+
+/// Reply to `/group list`
+pub fn list_html(groups: &[(String, Vec<String>)]) -> String {
+    if groups.is_empty() {
+        return String::from("You don't follow dependencies of any crate. Use <code>/subscribe_deps axum</code> to subscribe to the dependencies of <code>axum</code>.");
+    }
+
+    let lines: Vec<String> = groups
+        .iter()
+        .map(|(parent, krates)| {
+            let krates: Vec<String> = krates
+                .iter()
+                .map(|krate| format!("<code>{}</code>", escape(krate)))
+                .collect();
+            format!(
+                "<b>{}</b> ({}): {}",
+                escape(parent),
+                krates.len(),
+                krates.join(", ")
+            )
+        })
+        .collect();
+    format!(
+        "Dependencies you follow:\n{}\n\nUse <code>/group drop &lt;crate&gt;</code> to unsubscribe from a group.",
+        lines.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn krate(vers: &str, yanked: bool) -> Crate {
+        serde_json::from_str(&format!(
+            r#"{{"name":"axum","vers":"{}","yanked":{}}}"#,
+            vers, yanked
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn latest_release() {
+        let vers =
+            |all: &[Crate]| latest(all, VersionScheme::Semver).map(|krate| krate.id.vers.clone());
+
+        let all = vec![
+            krate("0.6.0", false),
+            krate("0.7.0", false),
+            krate("0.8.0-rc.1", false),
+            // a backport is published after newer versions
+            krate("0.6.20", false),
+            krate("0.7.1", true),
+        ];
+        assert_eq!(vers(&all).as_deref(), Some("0.7.0"));
+        assert_eq!(vers(&all[2..3]).as_deref(), Some("0.8.0-rc.1"));
+        assert_eq!(vers(&all[4..]), None);
+    }
+}
@@ -0,0 +1,544 @@
+//! End-to-end tests of the notification pipeline: releases are committed to a temporary git index
+//! of an alternative registry (so no crates.io api is asked), pulled by the watcher's code and
+//! announced through the pipeline to a fake telegram bot api recording sent messages.
+//!
+//! They need a PostgreSQL database: set `TEST_DATABASE_URL` (e.g.
+//! `host=localhost user=postgres dbname=crate_upd_bot_test`), every test creates the tables of
+//! `db.sql` in its own schema. Without the variable the tests are skipped.
+use std::{
+    convert::Infallible,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use carapax::Api;
+use git2::{Repository, RepositoryInitOptions, Signature};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server,
+};
+use serde_json::json;
+use tokio_postgres::NoTls;
+
+use crate::{
+    cfg::{Config, SharedConfig},
+    db::Database,
+    filter::{Bump, Filter},
+    index::git::GitIndex,
+    late_notes, migrate,
+    notifier::Notifiers,
+    pipeline::Pipeline,
+    send::SendQueue,
+    shutdown::Shutdown,
+    train::Trains,
+    util::crate_path,
+};
+
+const REGISTRY: &str = "test";
+
+/// Message sent to the fake telegram
+#[derive(Debug, Clone)]
+struct Sent {
+    chat_id: i64,
+    text: String,
+}
+
+/// Fake telegram bot api, every request succeeds
+struct FakeTelegram {
+    url: String,
+    sent: Arc<Mutex<Vec<Sent>>>,
+}
+
+impl FakeTelegram {
+    /// Serves on a free local port
+    fn start() -> Self {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let make = {
+            let sent = Arc::clone(&sent);
+            make_service_fn(move |_| {
+                let sent = Arc::clone(&sent);
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request| {
+                        respond(Arc::clone(&sent), request)
+                    }))
+                }
+            })
+        };
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+
+        Self { url, sent }
+    }
+
+    /// Messages sent since the last call, in order
+    fn take(&self) -> Vec<Sent> {
+        std::mem::take(&mut *self.sent.lock().unwrap())
+    }
+}
+
+async fn respond(
+    sent: Arc<Mutex<Vec<Sent>>>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let method = request
+        .uri()
+        .path()
+        .rsplit('/')
+        .next()
+        .unwrap_or("")
+        .to_owned();
+    let body = hyper::body::to_bytes(request.into_body())
+        .await
+        .unwrap_or_default();
+    let params: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+
+    let result = match method.as_str() {
+        "sendMessage" => {
+            let chat_id = params["chat_id"].as_i64().unwrap_or_default();
+            let text = params["text"].as_str().unwrap_or_default().to_owned();
+            let mut sent = sent.lock().unwrap();
+            sent.push(Sent {
+                chat_id,
+                text: text.clone(),
+            });
+            json!({
+                "message_id": sent.len(),
+                "date": 0,
+                "chat": { "id": chat_id, "type": "private", "first_name": "Test" },
+                "from": { "id": 1, "is_bot": true, "first_name": "Bot" },
+                "text": text,
+            })
+        }
+        _ => json!(true),
+    };
+    let body = json!({ "ok": true, "result": result }).to_string();
+    Ok(Response::new(Body::from(body)))
+}
+
+/// Git index of the test registry in a temporary directory: releases are committed to `origin`,
+/// the watcher reads its clone
+struct Index {
+    dir: PathBuf,
+    origin: Repository,
+}
+
+impl Index {
+    /// Index with only `config.json`
+    fn new(test: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("crate-upd-bot-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let origin = Repository::init_opts(
+            dir.join("origin"),
+            RepositoryInitOptions::new().initial_head("master"),
+        )
+        .unwrap();
+        let index = Self { dir, origin };
+
+        fs::write(index.dir.join("origin/config.json"), "{}").unwrap();
+        index.commit(Path::new("config.json"), "init");
+        index
+    }
+
+    fn origin_path(&self) -> PathBuf {
+        self.dir.join("origin")
+    }
+
+    fn clone_path(&self) -> PathBuf {
+        self.dir.join("clone")
+    }
+
+    /// Commits the new version of the crate, as the registry's bot does on publish
+    fn publish(&self, name: &str, version: &str) {
+        let path = crate_path(name);
+        let mut lines = self.lines(&path);
+        lines.push(json!({ "name": name, "vers": version, "deps": [], "yanked": false }));
+        self.write(&path, &lines);
+        self.commit(&path, &format!("Updating crate `{}#{}`", name, version));
+    }
+
+    /// Commits the version of the crate as yanked
+    fn yank(&self, name: &str, version: &str) {
+        let path = crate_path(name);
+        let mut lines = self.lines(&path);
+        for line in lines.iter_mut().filter(|line| line["vers"] == version) {
+            line["yanked"] = json!(true);
+        }
+        self.write(&path, &lines);
+        self.commit(&path, &format!("Yanking crate `{}#{}`", name, version));
+    }
+
+    fn lines(&self, path: &Path) -> Vec<serde_json::Value> {
+        fs::read_to_string(self.origin_path().join(path))
+            .unwrap_or_default()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    fn write(&self, path: &Path, lines: &[serde_json::Value]) {
+        let file = self.origin_path().join(path);
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        let lines: Vec<String> = lines.iter().map(ToString::to_string).collect();
+        fs::write(file, lines.join("\n") + "\n").unwrap();
+    }
+
+    fn commit(&self, path: &Path, message: &str) {
+        let mut index = self.origin.index().unwrap();
+        index.add_path(path).unwrap();
+        index.write().unwrap();
+        let tree = self.origin.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("bors", "bors@rust-lang.com").unwrap();
+        let parent = self
+            .origin
+            .head()
+            .ok()
+            .and_then(|head| head.peel_to_commit().ok());
+        let parents: Vec<_> = parent.iter().collect();
+        self.origin
+            .commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                message,
+                &tree,
+                &parents,
+            )
+            .unwrap();
+    }
+}
+
+impl Drop for Index {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Database with the tables in a fresh schema named after the test, `None` if
+/// `TEST_DATABASE_URL` isn't set
+async fn database(test: &str) -> Option<Database> {
+    let url = match std::env::var("TEST_DATABASE_URL") {
+        Ok(url) => url,
+        Err(_) => {
+            eprintln!("TEST_DATABASE_URL isn't set, skipping {}", test);
+            return None;
+        }
+    };
+    let config: tokio_postgres::Config = url.parse().expect("invalid TEST_DATABASE_URL");
+    let (db, conn) = Database::connect(&config, NoTls)
+        .await
+        .expect("couldn't connect to the test database");
+    tokio::spawn(conn);
+
+    let schema = format!("e2e_{}", test);
+    db.batch_execute(&format!(
+        "drop schema if exists {0} cascade; create schema {0}; set search_path to {0};",
+        schema
+    ))
+    .await
+    .expect("couldn't create the schema");
+    db.batch_execute(include_str!("../db.sql"))
+        .await
+        .expect("couldn't create the tables");
+    Some(db)
+}
+
+/// The bot with a fake telegram and the index of the test registry, set up with the releases
+/// committed by `setup` before the index is cloned
+struct Harness {
+    telegram: FakeTelegram,
+    bot: Api,
+    index: Index,
+    git: GitIndex,
+    queue: SendQueue,
+    db: Database,
+    cfg: Arc<Config>,
+}
+
+impl Harness {
+    async fn start(test: &str, setup: impl FnOnce(&Index)) -> Option<Self> {
+        let db = database(test).await?;
+        let telegram = FakeTelegram::start();
+        let index = Index::new(test);
+        setup(&index);
+
+        let cfg: Config = toml::from_str(&format!(
+            r#"
+            bot_token = "test"
+            fetch_changelogs = false
+            source_diffs = false
+            update_delay_millis = 0
+
+            [db]
+            host = "localhost"
+            user = "test"
+            dbname = "test"
+
+            [send]
+            chat_interval_millis = 0
+            group_interval_millis = 0
+
+            [[registry]]
+            name = "{}"
+            index_url = {:?}
+            index_path = {:?}
+            crate_url = "https://registry.test/crates/{{crate}}"
+            "#,
+            REGISTRY,
+            index.origin_path().display().to_string(),
+            index.clone_path().display().to_string(),
+        ))
+        .expect("invalid test config");
+        let cfg = Arc::new(cfg);
+
+        let registry = cfg.registry(REGISTRY).cloned();
+        let git = GitIndex::open_or_clone(
+            &index.origin_path().display().to_string(),
+            &index.clone_path().display().to_string(),
+            registry,
+        );
+        let bot = Api::new(carapax::Config::new("test").host(telegram.url.as_str()))
+            .expect("couldn't create the api");
+        let queue = SendQueue::start(bot.clone(), Arc::clone(&cfg), db.clone());
+
+        Some(Self {
+            telegram,
+            bot,
+            index,
+            git,
+            queue,
+            db,
+            cfg,
+        })
+    }
+
+    /// Pulls new commits of the index like the watcher and waits until they're announced,
+    /// returns the sent messages
+    async fn poll(&self) -> Vec<Sent> {
+        let notifiers = Notifiers {
+            telegram: self.queue.clone(),
+            matrix: None,
+            discord: None,
+            jobs: false,
+            collector: None,
+        };
+        let (mut pipeline, lanes) = Pipeline::start(
+            &self.cfg.pipeline,
+            notifiers,
+            self.db.clone(),
+            SharedConfig::new(Arc::clone(&self.cfg)),
+            Shutdown::never(),
+        );
+        let mut trains = Trains::default();
+        crate::pull(
+            &self.git,
+            REGISTRY,
+            &mut trains,
+            &mut pipeline,
+            &self.db,
+            &self.cfg,
+            &Shutdown::never(),
+        )
+        .await
+        .expect("pull failed");
+
+        // lanes stop once their queues are empty, messages are sent before a release is finished
+        drop(pipeline);
+        futures::future::join_all(lanes).await;
+        self.telegram.take()
+    }
+
+    fn key(name: &str) -> String {
+        format!("{}:{}", REGISTRY, name)
+    }
+}
+
+#[tokio::test]
+async fn publish_is_announced() {
+    let harness = match Harness::start("publish", |index| index.publish("demo", "0.1.0")).await {
+        Some(harness) => harness,
+        None => return,
+    };
+    harness
+        .db
+        .subscribe(1, &Harness::key("demo"))
+        .await
+        .unwrap();
+    harness
+        .db
+        .subscribe(2, &Harness::key("other"))
+        .await
+        .unwrap();
+
+    harness.index.publish("demo", "0.2.0");
+    let sent = harness.poll().await;
+    assert_eq!(sent.len(), 1, "{:?}", sent);
+    assert_eq!(sent[0].chat_id, 1);
+    assert!(sent[0].text.contains("test:demo#0.2.0"), "{}", sent[0].text);
+
+    // the commit is acknowledged, nothing is announced twice
+    assert!(harness.poll().await.is_empty());
+}
+
+#[tokio::test]
+async fn yank_is_alerted() {
+    let harness = match Harness::start("yank", |index| {
+        index.publish("demo", "0.1.0");
+        index.publish("demo", "0.2.0");
+    })
+    .await
+    {
+        Some(harness) => harness,
+        None => return,
+    };
+    harness
+        .db
+        .subscribe(1, &Harness::key("demo"))
+        .await
+        .unwrap();
+
+    harness.index.yank("demo", "0.2.0");
+    let sent = harness.poll().await;
+    assert_eq!(sent.len(), 1, "{:?}", sent);
+    assert!(
+        sent[0]
+            .text
+            .contains("⚠ <code>test:demo 0.2.0</code> was yanked"),
+        "{}",
+        sent[0].text
+    );
+}
+
+#[tokio::test]
+async fn filter_suppresses_message() {
+    let harness = match Harness::start("filter", |index| index.publish("demo", "0.1.0")).await {
+        Some(harness) => harness,
+        None => return,
+    };
+    let key = Harness::key("demo");
+    harness.db.subscribe(1, &key).await.unwrap();
+    harness.db.subscribe(2, &key).await.unwrap();
+    let minor = Filter {
+        min_bump: Bump::Minor,
+        ..Filter::default()
+    };
+    harness.db.set_filter(2, &key, &minor).await.unwrap();
+
+    harness.index.publish("demo", "0.1.1");
+    let sent = harness.poll().await;
+    let chats: Vec<i64> = sent.iter().map(|sent| sent.chat_id).collect();
+    assert_eq!(chats, [1]);
+
+    harness.index.publish("demo", "0.2.0");
+    let mut chats: Vec<i64> = harness
+        .poll()
+        .await
+        .iter()
+        .map(|sent| sent.chat_id)
+        .collect();
+    chats.sort_unstable();
+    assert_eq!(chats, [1, 2]);
+}
+
+#[tokio::test]
+async fn pause_queues_catch_up() {
+    let harness = match Harness::start("pause", |index| index.publish("demo", "0.1.0")).await {
+        Some(harness) => harness,
+        None => return,
+    };
+    let key = Harness::key("demo");
+    harness.db.subscribe(1, &key).await.unwrap();
+    harness.db.pause_chat(1, 24 * 60 * 60).await.unwrap();
+
+    harness.index.publish("demo", "0.2.0");
+    assert!(harness.poll().await.is_empty());
+    assert!(harness.db.take_resumed().await.unwrap().is_empty());
+
+    assert!(harness.db.resume_paused(1).await.unwrap());
+    assert_eq!(harness.db.take_resumed().await.unwrap(), [1]);
+    assert_eq!(
+        harness.db.take_digest(1).await.unwrap(),
+        [(key, String::from("0.2.0"), String::from("new"))]
+    );
+}
+
+#[tokio::test]
+async fn late_notes_expire() {
+    let harness = match Harness::start("late_notes", |index| {
+        index.publish("demo", "0.1.0");
+        index.publish("demo", "0.2.0");
+    })
+    .await
+    {
+        Some(harness) => harness,
+        None => return,
+    };
+    let (db, key) = (&harness.db, Harness::key("demo"));
+    let waiting = |expired| vec![(key.clone(), String::from("0.2.0"), expired)];
+    db.add_lagging_notes(&key, "0.2.0").await.unwrap();
+    db.record_announcement(&key, "0.2.0", 1, 10).await.unwrap();
+
+    assert_eq!(
+        db.due_lagging_notes(0, 60 * 60).await.unwrap(),
+        waiting(false)
+    );
+    // no notes are found (changelogs aren't fetched), the version keeps waiting for them
+    late_notes::check(&harness.bot, db, &harness.cfg, &key, "0.2.0", false).await;
+    assert_eq!(db.due_lagging_notes(0, 0).await.unwrap(), waiting(true));
+
+    // the lag is over, the version and its announcements are forgotten
+    late_notes::check(&harness.bot, db, &harness.cfg, &key, "0.2.0", true).await;
+    assert!(db.due_lagging_notes(0, 0).await.unwrap().is_empty());
+    assert!(db
+        .take_announcements(&key, "0.2.0")
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn chat_bundle_round_trip() {
+    let db = match database("chat_bundle").await {
+        Some(db) => db,
+        None => return,
+    };
+    let cfg: Config = toml::from_str(
+        r#"
+        bot_token = "test"
+        migration_key = "test"
+
+        [db]
+        host = "localhost"
+        user = "test"
+        dbname = "test"
+        "#,
+    )
+    .expect("invalid test config");
+    let data = |data: String| serde_json::from_str::<serde_json::Value>(&data).unwrap();
+
+    db.subscribe(1, "demo").await.unwrap();
+    db.set_verbose(1, true).await.unwrap();
+    db.subscribe_owner(1, "alice", &["demo"]).await.unwrap();
+    db.wait_for_version(1, "demo", "^2").await.unwrap();
+    db.queue_digest(1, "demo", "0.2.0", "publish")
+        .await
+        .unwrap();
+    let before = data(db.chat_data(1).await.unwrap());
+    let bundle = migrate::export(&db, &cfg, 1).await.unwrap();
+
+    // the old instance forgets the chat, the new one gets it back (twice, nothing is duplicated)
+    db.forget_chat(1).await.unwrap();
+    for _ in 0..2 {
+        let imported = migrate::import(&db, &cfg, &bundle).await.unwrap();
+        assert_eq!(imported.subscriptions(), 1);
+    }
+    let mut after = data(db.chat_data(1).await.unwrap());
+    // queued rows get new ids
+    after["digest_queue"][0]["id"] = before["digest_queue"][0]["id"].clone();
+    assert_eq!(after, before);
+
+    let tampered = bundle.replace("alice", "mallory");
+    assert!(migrate::import(&db, &cfg, &tampered).await.is_err());
+}
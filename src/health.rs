@@ -0,0 +1,216 @@
+//! Self-diagnostics: `/health` of the http server and the `/status` command
+use std::{
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use lazy_static::lazy_static;
+
+use crate::{db::Database, metrics, releases, render::escape};
+
+/// The bot is stuck if the index wasn't polled for this long
+const MAX_POLL_AGE_SECS: i64 = 60 * 60;
+
+/// Unix time of the last finished poll of an index, 0 if there was none yet
+static LAST_POLL: AtomicI64 = AtomicI64::new(0);
+/// Unix time of the last handled commit of the git index or release of the sparse one
+static LAST_COMMIT: AtomicI64 = AtomicI64::new(0);
+
+lazy_static! {
+    /// Unix time and text of the last error of the telegram api
+    static ref TELEGRAM_ERROR: Mutex<Option<(i64, String)>> = Mutex::new(None);
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// Records a finished poll of an index
+pub fn polled() {
+    LAST_POLL.store(now(), Ordering::Relaxed);
+}
+
+/// Records a handled index event
+pub fn committed() {
+    LAST_COMMIT.store(now(), Ordering::Relaxed);
+}
+
+/// Records an error of the telegram api
+pub fn telegram_error(err: &impl std::fmt::Display) {
+    if let Ok(mut last) = TELEGRAM_ERROR.lock() {
+        *last = Some((now(), err.to_string()));
+    }
+}
+
+/// Items waiting to be handled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Queues {
+    /// Messages of this instance's send queue
+    pub send: i64,
+    /// Notifications leased to instances of a cluster
+    pub deliveries: i64,
+    pub digest: i64,
+    pub email: i64,
+    /// Releases held in version trains or with unsent notifications
+    pub pending_releases: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct TelegramError {
+    pub secs_ago: i64,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Report {
+    pub healthy: bool,
+    pub uptime_secs: u64,
+    /// Seconds since the last finished poll of an index, `None` before the first one
+    pub poll_age_secs: Option<i64>,
+    /// Seconds since the last handled index commit, `None` if there was none since the start
+    pub index_lag_secs: Option<i64>,
+    /// `None` if the database can't be reached
+    pub queues: Option<Queues>,
+    pub database: bool,
+    pub last_telegram_error: Option<TelegramError>,
+    /// Commands answer from stale cached releases since the index can't be reached
+    pub offline: bool,
+}
+
+fn age(timestamp: i64, now: i64) -> Option<i64> {
+    Some(now - timestamp).filter(|_| timestamp != 0)
+}
+
+/// Checks the database and collects the state of the bot
+pub async fn check(db: &Database) -> Report {
+    let queues = match db.queue_depths().await {
+        Ok(queues) => Some(Queues {
+            send: metrics::SEND_QUEUE_DEPTH.get(),
+            ..queues
+        }),
+        Err(err) => {
+            tracing::error!("db error while checking health: {}", err);
+            None
+        }
+    };
+
+    let now = now();
+    let uptime_secs = metrics::STARTED.elapsed().as_secs();
+    let poll_age_secs = age(LAST_POLL.load(Ordering::Relaxed), now);
+    let last_telegram_error = TELEGRAM_ERROR
+        .lock()
+        .ok()
+        .and_then(|last| last.clone())
+        .map(|(at, error)| TelegramError {
+            secs_ago: now - at,
+            error,
+        });
+    // the first poll (e.g. a clone of the index) may take a while
+    let stuck = poll_age_secs.unwrap_or(uptime_secs as i64) > MAX_POLL_AGE_SECS;
+
+    Report {
+        healthy: queues.is_some() && !stuck,
+        uptime_secs,
+        poll_age_secs,
+        index_lag_secs: age(LAST_COMMIT.load(Ordering::Relaxed), now),
+        database: queues.is_some(),
+        queues,
+        last_telegram_error,
+        offline: releases::is_offline(),
+    }
+}
+
+/// `1h 5m`, `42s`
+fn duration(secs: i64) -> String {
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 60 * 60 => format!("{}m", s / 60),
+        s if s < 24 * 60 * 60 => format!("{}h {}m", s / 3600, s % 3600 / 60),
+        s => format!("{}d {}h", s / 86400, s % 86400 / 3600),
+    }
+}
+
+fn ago(secs: Option<i64>) -> String {
+    secs.map_or_else(|| String::from("never"), |s| format!("{} ago", duration(s)))
+}
+
+impl Report {
+    /// Reply to `/status`, as telegram html
+    pub fn html(&self) -> String {
+        let mut text = format!(
+            "{}\n\
+             \n\
+             Up for {}\n\
+             Last index poll: {}\n\
+             Last index commit: {}\n\
+             Database: {}",
+            if self.healthy {
+                "✅ <b>Working</b>"
+            } else {
+                "⚠️ <b>Something is wrong</b>"
+            },
+            duration(self.uptime_secs as i64),
+            ago(self.poll_age_secs),
+            ago(self.index_lag_secs),
+            if self.database { "ok" } else { "unreachable" },
+        );
+        if let Some(q) = &self.queues {
+            text.push_str(&format!(
+                "\nQueues: {} messages, {} deliveries, {} digest entries, {} e-mails, {} pending releases",
+                q.send, q.deliveries, q.digest, q.email, q.pending_releases
+            ));
+        }
+        if self.offline {
+            text.push_str("\nIndex: unreachable, answering from cached releases");
+        }
+        match &self.last_telegram_error {
+            Some(err) => text.push_str(&format!(
+                "\nLast telegram error ({} ago): <code>{}</code>",
+                duration(err.secs_ago),
+                escape(&err.error)
+            )),
+            None => text.push_str("\nNo telegram errors"),
+        }
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html() {
+        let report = Report {
+            healthy: false,
+            uptime_secs: 3 * 3600 + 120,
+            poll_age_secs: Some(2 * 3600),
+            index_lag_secs: None,
+            queues: None,
+            database: false,
+            last_telegram_error: Some(TelegramError {
+                secs_ago: 30,
+                error: String::from("Bad Request: chat not found <x>"),
+            }),
+            offline: true,
+        };
+
+        assert_eq!(
+            report.html(),
+            "⚠️ <b>Something is wrong</b>\n\
+             \n\
+             Up for 3h 2m\n\
+             Last index poll: 2h 0m ago\n\
+             Last index commit: never\n\
+             Database: unreachable\n\
+             Index: unreachable, answering from cached releases\n\
+             Last telegram error (30s ago): <code>Bad Request: chat not found &lt;x&gt;</code>"
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Release history of a crate: versions from the index + publish dates from the release archive
+//! and crates.io
+use std::collections::HashMap;
+
+use carapax::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+use kacl_parser::Date;
+use versions::SemVer;
+
+use crate::{cfg::Config, db::Database, releases, util::rfc3339};
+
+/// Number of versions per page
+pub const PAGE_SIZE: usize = 20;
+
+/// Lower bound of `/history ... since <...>` or the number of versions of `/history ... <n>`
+pub enum Since {
+    Date(Date),
+    Version(SemVer),
+    /// The last `n` versions
+    Last(usize),
+}
+
+impl Since {
+    /// Parses `YYYY-MM-DD`, a semver version or a number of versions
+    pub fn parse(s: &str) -> Option<Self> {
+        if let Ok(n) = s.parse() {
+            return Some(Since::Last(n));
+        }
+        match Date::parse(s) {
+            Ok(("", date)) => Some(Since::Date(date)),
+            _ => SemVer::new(s).map(Since::Version),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Entry {
+    pub version: String,
+    pub yanked: bool,
+    /// `YYYY-MM-DD`, known for releases seen by the bot and for crates.io crates
+    pub published: Option<String>,
+}
+
+impl Entry {
+    fn matches(&self, since: &Since) -> bool {
+        match since {
+            Since::Version(since) => SemVer::new(&self.version).map_or(false, |v| &v > since),
+            Since::Last(_) => true,
+            Since::Date(since) => self
+                .published
+                .as_deref()
+                .and_then(|p| Date::parse(p).ok())
+                .map_or(false, |(_, date)| &date >= since),
+        }
+    }
+}
+
+/// Versions of the crate newer than `since`, newest first. `None` if there is no such crate.
+pub async fn entries(
+    db: &Database,
+    cfg: &Config,
+    krate: &str,
+    since: Option<&Since>,
+) -> Result<Option<Vec<Entry>>, tokio_postgres::Error> {
+    let releases = match releases::get(krate, cfg).await {
+        Ok(releases) => releases,
+        Err(_) => return Ok(None),
+    };
+    // the archive has dates of releases seen by the bot (also of alternative registries),
+    // crates.io knows all dates of its crates
+    let mut dates: HashMap<_, _> = db
+        .list_releases(krate)
+        .await?
+        .into_iter()
+        .map(|(version, _, date)| (version, date))
+        .collect();
+    dates.extend(
+        releases
+            .published
+            .iter()
+            .map(|(version, &time)| (version.clone(), rfc3339(time)[..10].to_owned())),
+    );
+
+    let last = match since {
+        Some(Since::Last(n)) => *n,
+        _ => usize::MAX,
+    };
+    let entries = releases
+        .versions
+        .into_iter()
+        .rev()
+        .map(|krate| Entry {
+            published: dates.get(&krate.id.vers).cloned(),
+            version: krate.id.vers,
+            yanked: krate.yanked,
+        })
+        .filter(|entry| since.map_or(true, |since| entry.matches(since)))
+        .take(last)
+        .collect();
+
+    Ok(Some(entries))
+}
+
+/// Button with callback data, `None` if the data is longer than 64 bytes (the telegram limit)
+pub fn button(text: &str, data: String) -> Option<InlineKeyboardButton> {
+    if data.len() <= 64 {
+        Some(InlineKeyboardButton::with_callback_data(text, data))
+    } else {
+        None
+    }
+}
+
+/// Text and navigation keyboard of the `page` (0-based)
+pub fn render_page(
+    krate: &str,
+    since: Option<&str>,
+    entries: &[Entry],
+    page: usize,
+) -> (String, Option<InlineKeyboardMarkup>) {
+    let since_text = match since {
+        Some(n) if n.parse::<usize>().is_ok() => format!(" (last {})", n),
+        Some(s) => format!(" since <code>{}</code>", s),
+        None => String::new(),
+    };
+    if entries.is_empty() {
+        return (
+            format!(
+                "There are no versions of <code>{}</code>{}.",
+                krate, since_text
+            ),
+            None,
+        );
+    }
+
+    let pages = (entries.len() + PAGE_SIZE - 1) / PAGE_SIZE;
+    let page = page.min(pages - 1);
+    let lines: Vec<_> = entries
+        .iter()
+        .skip(page * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .map(|e| {
+            format!(
+                "— <code>{}</code>{}{}",
+                e.version,
+                e.published
+                    .as_ref()
+                    .map(|p| format!(" {}", p))
+                    .unwrap_or_default(),
+                if e.yanked { " (yanked)" } else { "" }
+            )
+        })
+        .collect();
+    let text = format!(
+        "Versions of <code>{}</code>{} (page {}/{}):\n{}",
+        krate,
+        since_text,
+        page + 1,
+        pages,
+        lines.join("\n")
+    );
+
+    let since = since.unwrap_or("-");
+    let mut row = Vec::new();
+    if page > 0 {
+        row.extend(button(
+            "◀",
+            format!("history {} {} {}", krate, since, page - 1),
+        ));
+    }
+    if page + 1 < pages {
+        row.extend(button(
+            "▶",
+            format!("history {} {} {}", krate, since, page + 1),
+        ));
+    }
+    row.extend(button("JSON", format!("history_json {} {}", krate, since)));
+
+    (text, Some(InlineKeyboardMarkup::from(vec![row])))
+}
@@ -0,0 +1,100 @@
+//! Http hooks: JSON payloads about updates for CI systems, dashboards, etc.
+use kacl_parser::VersionScheme;
+use lazy_static::lazy_static;
+use reqwest::Client;
+use tracing::Instrument;
+
+use crate::{
+    cfg::Config,
+    krate::Crate,
+    metrics,
+    util::{http_client, tryn},
+    ActionKind,
+};
+
+/// How many times delivery is retried after the first attempt
+const RETRIES: usize = 3;
+
+lazy_static! {
+    static ref CLIENT: Client = http_client().expect("couldn't create http client");
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct Payload {
+    /// `myreg:name` for crates of alternative registries
+    #[serde(rename = "crate")]
+    krate: String,
+    version: String,
+    /// `new`, `yanked` or `unyanked`
+    action: &'static str,
+    yanked: bool,
+    /// Release notes as telegram html (a subset of html), only for new versions
+    changelog_html: Option<String>,
+    links: Links,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct Links {
+    docs: String,
+    #[serde(rename = "crate")]
+    krate: String,
+}
+
+/// Posts the update to all matching hooks, in the background
+pub fn send(
+    krate: &Crate,
+    action: &ActionKind,
+    notes: Option<&str>,
+    scheme: VersionScheme,
+    previous: Option<&str>,
+    cfg: &Config,
+) {
+    let is_yank = matches!(action, ActionKind::Yanked | ActionKind::Unyanked);
+    let payload = Payload {
+        krate: krate.key(),
+        version: krate.id.vers.clone(),
+        action: action.as_str(),
+        yanked: krate.yanked,
+        changelog_html: notes.map(str::to_owned),
+        links: Links {
+            docs: krate.docsrs(),
+            krate: krate.cratesio(),
+        },
+    };
+
+    for hook in &cfg.hooks {
+        if !hook
+            .selector
+            .matches(&payload.krate, is_yank, scheme, &payload.version, previous)
+        {
+            continue;
+        }
+
+        let url = hook.url.clone();
+        let payload = payload.clone();
+        let retry_delay = cfg.retry_delay.0;
+        tokio::spawn(
+            async move {
+                let result = tryn(RETRIES, retry_delay, || async {
+                    CLIENT
+                        .post(&url)
+                        .json(&payload)
+                        .send()
+                        .await?
+                        .error_for_status()
+                })
+                .await;
+                match result {
+                    Ok(_) => metrics::HOOK_DELIVERIES.with_label_values(&["ok"]).inc(),
+                    Err(err) => {
+                        tracing::warn!("couldn't deliver update to hook {}: {}", url, err);
+                        metrics::HOOK_DELIVERIES
+                            .with_label_values(&["failed"])
+                            .inc();
+                    }
+                }
+            }
+            .in_current_span(),
+        );
+    }
+}
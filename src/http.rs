@@ -0,0 +1,103 @@
+//! Http server of the bot: atom feeds (`/feed/...`), unsubscribe links of e-mails
+//! (`/email/unsubscribe/{token}`), the JSON api (`/api/...`) and `/health`, whichever are enabled
+//! in the config
+use std::{convert::Infallible, sync::Arc};
+
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+
+use crate::{api, cfg::Config, db::Database, email, feed, health};
+
+/// Content type and body of a page
+type Page = (&'static str, String);
+
+async fn page(
+    path: &str,
+    db: &Database,
+    cfg: &Config,
+) -> Result<Option<Page>, tokio_postgres::Error> {
+    if let (Some(path), true) = (path.strip_prefix("/feed/"), cfg.feed.enabled) {
+        let xml = feed::feed(path, db, cfg).await?;
+        return Ok(xml.map(|xml| ("application/atom+xml; charset=utf-8", xml)));
+    }
+    if let (Some(token), Some(_)) = (path.strip_prefix("/email/unsubscribe/"), &cfg.email) {
+        let html = email::unsubscribe(token, db).await?;
+        return Ok(html.map(|html| ("text/html; charset=utf-8", html)));
+    }
+
+    Ok(None)
+}
+
+async fn handle(
+    db: Database,
+    cfg: Arc<Config>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    if cfg.api.enabled && request.uri().path().starts_with("/api/") {
+        return Ok(api::handle(request, &db, &cfg).await);
+    }
+    if cfg.http.health && request.uri().path() == "/health" {
+        let report = health::check(&db).await;
+        let status = if report.healthy {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        let mut response =
+            Response::new(Body::from(serde_json::to_vec(&report).unwrap_or_default()));
+        *response.status_mut() = status;
+        if let Ok(content_type) = "application/json".parse() {
+            response.headers_mut().insert(CONTENT_TYPE, content_type);
+        }
+        return Ok(response);
+    }
+
+    let mut response = Response::new(Body::empty());
+    match page(request.uri().path(), &db, &cfg).await {
+        Ok(Some((content_type, body))) => {
+            *response.body_mut() = Body::from(body);
+            if let Ok(content_type) = content_type.parse() {
+                response.headers_mut().insert(CONTENT_TYPE, content_type);
+            }
+        }
+        Ok(None) => *response.status_mut() = StatusCode::NOT_FOUND,
+        Err(err) => {
+            tracing::error!("db error while rendering page: {}", err);
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+    Ok(response)
+}
+
+/// `true` if there is anything to serve
+pub fn is_enabled(cfg: &Config) -> bool {
+    cfg.feed.enabled || cfg.email.is_some() || cfg.api.enabled || cfg.http.health
+}
+
+/// Serves pages on `cfg.http.listen`, forever
+pub async fn serve(db: Database, cfg: Arc<Config>) {
+    let listen = cfg.http.listen;
+    let make_service = make_service_fn(move |_| {
+        let db = db.clone();
+        let cfg = Arc::clone(&cfg);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle(db.clone(), Arc::clone(&cfg), request)
+            }))
+        }
+    });
+
+    tracing::info!("serving http on http://{}/", listen);
+    if let Err(err) = Server::bind(&listen).serve(make_service).await {
+        tracing::error!("http server error: {}", err);
+    }
+}
+
+/// Public url of the path, `None` if the public url of the server isn't configured
+pub fn public_url(path: &str, cfg: &Config) -> Option<String> {
+    let url = cfg.http.url.as_deref()?;
+    Some(format!("{}{}", url.trim_end_matches('/'), path))
+}
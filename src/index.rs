@@ -0,0 +1,32 @@
+//! Backends for watching the crates.io index and indexes of alternative registries
+pub mod git;
+pub mod sparse;
+
+use crate::{krate::Crate, ActionKind};
+
+/// Change of a single crate version in the index
+pub struct IndexEvent {
+    pub krate: Crate,
+    pub kind: ActionKind,
+    /// Unix time of the change
+    pub published_at: i64,
+    /// The change created the index file of the crate: it's the first publish of the crate.
+    /// Only the git index tells this.
+    pub created: bool,
+}
+
+/// Which crates.io index the bot watches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexKind {
+    /// Local clone of the git index, every commit is announced
+    Git,
+    /// Sparse http index, only crates with subscribers are polled
+    Sparse,
+}
+
+impl Default for IndexKind {
+    fn default() -> Self {
+        IndexKind::Git
+    }
+}
@@ -0,0 +1,227 @@
+//! Local clone of the git index, releases are read from diffs of new commits
+use std::{
+    str,
+    sync::{Arc, Mutex},
+};
+
+use arraylib::Slice;
+use fntools::value::ValueExt;
+use git2::{Commit, Delta, Diff, DiffOptions, Oid, Repository, Sort};
+use tracing::info;
+
+use crate::{cfg::RegistryConfig, index::IndexEvent, krate::Crate, replay::Since, ActionKind};
+
+pub struct GitIndex {
+    /// `Repository` isn't `Sync`, the mutex lets watchers of the indexes run as separate tasks
+    repo: Mutex<Repository>,
+    /// Alternative registry of the index, `None` for crates.io
+    registry: Option<Arc<RegistryConfig>>,
+}
+
+impl GitIndex {
+    /// Opens the clone at `path`, cloning `url` if there is none
+    pub fn open_or_clone(url: &str, path: &str, registry: Option<Arc<RegistryConfig>>) -> Self {
+        let repo = Repository::open(path).unwrap_or_else(move |_| {
+            info!("start cloning");
+            Repository::clone(url, path)
+                .unwrap()
+                .also(|_| info!("cloning finished"))
+        });
+
+        Self {
+            repo: Mutex::new(repo),
+            registry,
+        }
+    }
+
+    /// Fetches the remote index and returns events of the commits after the local `HEAD`,
+    /// oldest first.
+    ///
+    /// The local `HEAD` is the last processed commit: it's only moved by [`GitIndex::ack`],
+    /// so after a restart unacknowledged events are returned again and none are missed.
+    pub fn fetch(&self) -> Result<Vec<(Oid, IndexEvent)>, git2::Error> {
+        let repo = self.repo.lock().expect("index lock is poisoned");
+        repo.find_remote("origin")
+            .expect("couldn't find 'origin' remote")
+            .fetch(&["master"], None, None)
+            .expect("couldn't fetch new version of the index");
+
+        let mut walk = repo.revwalk()?;
+        walk.push_range("HEAD~1..FETCH_HEAD")?;
+        walk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+        let commits: Result<Vec<_>, _> = walk.map(|oid| repo.find_commit(oid?)).collect();
+
+        self.events(&repo, &commits?)
+    }
+
+    /// Events of the processed commits (up to the local `HEAD`) after `since`, oldest first.
+    /// The index isn't fetched.
+    pub fn history(&self, since: &Since) -> Result<Vec<(Oid, IndexEvent)>, git2::Error> {
+        let repo = self.repo.lock().expect("index lock is poisoned");
+        let mut walk = repo.revwalk()?;
+        walk.set_sorting(Sort::TOPOLOGICAL)?;
+        walk.push_head()?;
+        if let Since::Commit(rev) = since {
+            let base = repo.revparse_single(rev)?.peel_to_commit()?;
+            for parent in base.parent_ids() {
+                walk.hide(parent)?;
+            }
+        }
+
+        // newest first, a walk by time stops at the first commit before it (the base)
+        let mut commits = Vec::new();
+        for oid in walk {
+            let commit = repo.find_commit(oid?)?;
+            let base = matches!(since, Since::Time(time) if commit.time().seconds() < *time);
+            commits.push(commit);
+            if base {
+                break;
+            }
+        }
+        commits.reverse();
+
+        self.events(&repo, &commits)
+    }
+
+    /// Events of `commits` (oldest first) compared to the commits before them, the first one is
+    /// the base
+    fn events(
+        &self,
+        repo: &Repository,
+        commits: &[Commit<'_>],
+    ) -> Result<Vec<(Oid, IndexEvent)>, git2::Error> {
+        let mut opts = DiffOptions::default();
+        let opts = opts.context_lines(0).minimal(true);
+
+        let mut events = Vec::new();
+        for [prev, next] in Slice::array_windows::<[_; 2]>(commits) {
+            // only bors commits to the crates.io index, alternative registries have their own bots
+            if self.registry.is_none() && next.author().name() != Some("bors") {
+                tracing::warn!(
+                    "Skip commit#{} from non-bors user@{}: {}",
+                    next.id(),
+                    next.author().name().unwrap_or("<invalid utf-8>"),
+                    next.message()
+                        .unwrap_or("<invalid utf-8>")
+                        .trim_end_matches('\n'),
+                );
+
+                continue;
+            }
+
+            let diff: Diff =
+                repo.diff_tree_to_tree(Some(&prev.tree()?), Some(&next.tree()?), Some(opts))?;
+            let (mut krate, kind, created) = diff_one(diff)?;
+            krate.registry = self.registry.clone();
+            events.push((
+                next.id(),
+                IndexEvent {
+                    krate,
+                    kind,
+                    published_at: next.time().seconds(),
+                    created,
+                },
+            ));
+        }
+
+        Ok(events)
+    }
+
+    /// Marks the event of the `commit` (and everything before it) as processed
+    pub fn ack(&self, commit: Oid) -> Result<(), git2::Error> {
+        fast_forward(&self.repo.lock().expect("index lock is poisoned"), commit)
+    }
+}
+
+// from https://stackoverflow.com/a/58778350
+fn fast_forward(repo: &Repository, commit: Oid) -> Result<(), git2::Error> {
+    let fetch_commit = repo.find_annotated_commit(commit)?;
+    let analysis = repo.merge_analysis(&[&fetch_commit])?;
+    if analysis.0.is_up_to_date() {
+        Ok(())
+    } else if analysis.0.is_fast_forward() {
+        let mut reference = repo.find_reference("refs/heads/master")?;
+        reference.set_target(fetch_commit.id(), "Fast-Forward")?;
+        repo.set_head(reference.name().unwrap())?;
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::default().force()))
+    } else {
+        Err(git2::Error::from_str("Fast-forward only!"))
+    }
+}
+
+/// The changed version, the change and whether the file of the crate was created by the diff
+fn diff_one(diff: Diff) -> Result<(Crate, ActionKind, bool), git2::Error> {
+    let mut prev = None;
+    let mut next = None;
+    let mut created = false;
+
+    diff.foreach(
+        &mut |_, _| true,
+        None,
+        None,
+        Some(&mut |delta, _hunk, line| {
+            match delta.status() {
+                // New version of a crate or (un)yanked old version
+                Delta::Modified | Delta::Added => {
+                    assert!(delta.nfiles() == 2 || delta.nfiles() == 1);
+                    created = delta.status() == Delta::Added;
+                    match line.origin() {
+                        '-' => {
+                            assert!(
+                                prev.is_none(),
+                                "Expected number of deletions <= 1 per commit"
+                            );
+                            let krate = str::from_utf8(line.content()).expect("non-utf8 diff");
+                            let krate = serde_json::from_str::<Crate>(krate)
+                                .expect("cound't deserialize crate");
+
+                            prev = Some(krate);
+                        }
+                        '+' => {
+                            assert!(
+                                next.is_none(),
+                                "Expected number of additions = 1 per commit"
+                            );
+                            let krate = str::from_utf8(line.content()).expect("non-utf8 diff");
+                            let krate = serde_json::from_str::<Crate>(krate)
+                                .expect("cound't deserialize crate");
+
+                            next = Some(krate);
+                        }
+                        _ => { /* don't care */ }
+                    }
+                }
+                delta => {
+                    tracing::warn!("Unexpected delta: {:?}", delta);
+                }
+            }
+
+            true
+        }),
+    )?;
+
+    let next = next.expect("Expected number of additions = 1 per commit");
+    match (prev.as_ref().map(|c| c.yanked), next.yanked) {
+        /* was yanked, is yanked */
+        (None, false) => {
+            // There were no deleted line & crate is not yanked.
+            // New version.
+            Ok((next, ActionKind::NewVersion, created))
+        }
+        (Some(false), true) => {
+            // The crate was not yanked and now is yanked.
+            // Crate yanked.
+            Ok((next, ActionKind::Yanked, false))
+        }
+        (Some(true), false) => {
+            // The crate was yanked and now is not yanked.
+            // Crate unyanked.
+            Ok((next, ActionKind::Unyanked, false))
+        }
+        _unexpected => {
+            // Something unexpected happened
+            tracing::warn!("Unexpected diff_one input: {:?}, {:?}", next, prev);
+            Err(git2::Error::from_str("Unexpected diff"))
+        }
+    }
+}
@@ -0,0 +1,292 @@
+//! Sparse http index (RFC 2789): every crate is a separate file, `{url}/{prefix}/{crate}`
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use kacl_parser::VersionScheme;
+use reqwest::{header, Client, StatusCode};
+
+use crate::{cfg::RegistryConfig, index::IndexEvent, krate::Crate, util::crate_path, ActionKind};
+
+#[derive(Debug, derive_more::From, derive_more::Display)]
+pub enum Error {
+    Http(reqwest::Error),
+    Json(serde_json::Error),
+}
+
+/// Url of the crate file, the layout is the same as in the git index
+pub fn url(base: &str, name: &str) -> String {
+    let path: Vec<_> = crate_path(name)
+        .iter()
+        .map(|part| part.to_string_lossy().into_owned())
+        .collect();
+    format!("{}/{}", base.trim_end_matches('/'), path.join("/"))
+}
+
+fn parse(file: &str) -> Result<Vec<Crate>, serde_json::Error> {
+    file.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect()
+}
+
+/// All versions of the crate, oldest first
+pub async fn fetch(base: &str, name: &str) -> Result<Vec<Crate>, Error> {
+    let file = Client::new()
+        .get(&url(base, name))
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    Ok(parse(&file)?)
+}
+
+/// New versions and yank status changes in `new` compared to `old`
+fn changes(old: &[Crate], new: &[Crate]) -> Vec<(Crate, ActionKind)> {
+    new.iter()
+        .filter_map(|krate| match old.iter().find(|o| o.id == krate.id) {
+            None if !krate.yanked => Some((krate.clone(), ActionKind::NewVersion)),
+            Some(o) if !o.yanked && krate.yanked => Some((krate.clone(), ActionKind::Yanked)),
+            Some(o) if o.yanked && !krate.yanked => Some((krate.clone(), ActionKind::Unyanked)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Versions of the crate as they were when the bot stopped, from `recorded` releases
+/// (version, yanked, pending action). A pending action wasn't fully notified about, so the
+/// version is in the state before it. Versions which aren't recorded are known if they
+/// are older than the newest recorded one (the archive may start later than the crate),
+/// versions are compared by the scheme detected from the current ones.
+fn checkpointed(current: &[Crate], recorded: &[(String, bool, Option<String>)]) -> Vec<Crate> {
+    let scheme = VersionScheme::detect(current.iter().map(|krate| krate.id.vers.as_str()));
+    let newest = recorded
+        .iter()
+        .filter(|(_, _, pending)| pending.as_deref() != Some("new"))
+        .map(|(version, _, _)| version.as_str())
+        .filter(|version| scheme.accepts(version))
+        .max_by(|a, b| scheme.compare(a, b).unwrap_or(Ordering::Equal));
+
+    current
+        .iter()
+        .filter_map(|krate| {
+            let recorded = recorded
+                .iter()
+                .find(|(version, _, _)| *version == krate.id.vers);
+            let yanked = match recorded {
+                Some((_, yanked, pending)) => match pending.as_deref() {
+                    Some("new") => return None,
+                    Some("yanked") => false,
+                    Some("unyanked") => true,
+                    _ => *yanked,
+                },
+                None => match newest {
+                    Some(newest)
+                        if scheme.compare(&krate.id.vers, newest) == Some(Ordering::Greater) =>
+                    {
+                        return None
+                    }
+                    _ => krate.yanked,
+                },
+            };
+            Some(Crate {
+                yanked,
+                ..krate.clone()
+            })
+        })
+        .collect()
+}
+
+/// Poller of the sparse index which remembers the last seen versions of crates
+pub struct SparseIndex {
+    client: Client,
+    base: String,
+    /// Alternative registry of the index, `None` for crates.io
+    registry: Option<Arc<RegistryConfig>>,
+    /// ETag (if the server sent one) and versions from the last fetch of a crate file
+    cache: HashMap<String, (Option<String>, Vec<Crate>)>,
+}
+
+impl SparseIndex {
+    pub fn new(base: impl Into<String>, registry: Option<Arc<RegistryConfig>>) -> Self {
+        Self {
+            client: Client::new(),
+            base: base.into(),
+            registry,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// `true` if the crate was polled before
+    pub fn is_cached(&self, name: &str) -> bool {
+        self.cache.contains_key(name)
+    }
+
+    /// Changes of the crate since the previous poll.
+    ///
+    /// Unchanged files aren't downloaded again (`If-None-Match`). The first poll of a crate
+    /// compares with its `recorded` releases (see [`checkpointed`]), so releases missed while
+    /// the bot was down are announced. Without recorded releases it only remembers the versions.
+    pub async fn poll(
+        &mut self,
+        name: &str,
+        recorded: Option<&[(String, bool, Option<String>)]>,
+    ) -> Result<Vec<IndexEvent>, Error> {
+        let mut request = self.client.get(&url(&self.base, name));
+        if let Some((Some(etag), _)) = self.cache.get(name) {
+            request = request.header(header::IF_NONE_MATCH, etag.as_str());
+        }
+
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(Vec::new());
+        }
+        let response = response.error_for_status()?;
+        let etag = response
+            .headers()
+            .get(header::ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(str::to_owned);
+        let versions = parse(&response.text().await?)?;
+
+        let changes = match (self.cache.get(name), recorded) {
+            (Some((_, old)), _) => changes(old, &versions),
+            (None, Some(recorded)) if !recorded.is_empty() => {
+                changes(&checkpointed(&versions, recorded), &versions)
+            }
+            (None, _) => Vec::new(),
+        };
+        self.cache.insert(name.to_owned(), (etag, versions));
+
+        // the sparse index doesn't store publish dates, the release was published
+        // at most `pull_delay` ago
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        Ok(changes
+            .into_iter()
+            .map(|(krate, kind)| IndexEvent {
+                krate: Crate {
+                    registry: self.registry.clone(),
+                    ..krate
+                },
+                kind,
+                published_at: now,
+                created: false,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn krate(vers: &str, yanked: bool) -> Crate {
+        serde_json::from_str(&format!(
+            r#"{{"name":"serde","vers":"{}","yanked":{}}}"#,
+            vers, yanked
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn urls() {
+        assert_eq!(
+            url("https://index.crates.io/", "a"),
+            "https://index.crates.io/1/a"
+        );
+        assert_eq!(
+            url("https://index.crates.io", "syn"),
+            "https://index.crates.io/3/s/syn"
+        );
+        assert_eq!(
+            url("https://index.crates.io", "Serde"),
+            "https://index.crates.io/se/rd/serde"
+        );
+    }
+
+    #[test]
+    fn diff() {
+        let old = [
+            krate("1.0.0", false),
+            krate("1.0.1", false),
+            krate("1.0.2", true),
+        ];
+        let new = [
+            krate("1.0.0", false),
+            krate("1.0.1", true),
+            krate("1.0.2", false),
+            krate("1.0.3", false),
+            krate("1.0.4", true),
+        ];
+
+        let changes: Vec<_> = changes(&old, &new)
+            .into_iter()
+            .map(|(krate, action)| (krate.id.vers, action))
+            .collect();
+        assert!(matches!(
+            &changes[..],
+            [
+                (v1, ActionKind::Yanked),
+                (v2, ActionKind::Unyanked),
+                (v3, ActionKind::NewVersion),
+            ] if v1 == "1.0.1" && v2 == "1.0.2" && v3 == "1.0.3"
+        ));
+    }
+
+    #[test]
+    fn checkpoints() {
+        let current = [
+            krate("0.9.0", false),
+            krate("1.0.0", true),
+            krate("1.0.1", false),
+            krate("1.0.2", false),
+            krate("1.0.3", false),
+        ];
+        let recorded = |version: &str, yanked: bool, pending: Option<&str>| {
+            (version.to_owned(), yanked, pending.map(str::to_owned))
+        };
+        // 0.9.0 isn't archived, 1.0.0 was yanked while the bot was down,
+        // notifications about 1.0.2 weren't all sent, 1.0.3 is new
+        let recorded = [
+            recorded("1.0.0", false, None),
+            recorded("1.0.1", false, None),
+            recorded("1.0.2", false, Some("new")),
+        ];
+
+        let changes: Vec<_> = changes(&checkpointed(&current, &recorded), &current)
+            .into_iter()
+            .map(|(krate, action)| (krate.id.vers, action))
+            .collect();
+        assert!(matches!(
+            &changes[..],
+            [
+                (v1, ActionKind::Yanked),
+                (v2, ActionKind::NewVersion),
+                (v3, ActionKind::NewVersion),
+            ] if v1 == "1.0.0" && v2 == "1.0.2" && v3 == "1.0.3"
+        ));
+
+        // date-based versions, 2021.5.20 isn't archived
+        let current = [
+            krate("2021.5.20", false),
+            krate("2021.06.01", false),
+            krate("2021.10.01", false),
+        ];
+        let recorded = [(String::from("2021.06.01"), false, None)];
+        let changes: Vec<_> = changes(&checkpointed(&current, &recorded), &current)
+            .into_iter()
+            .map(|(krate, action)| (krate.id.vers, action))
+            .collect();
+        assert!(matches!(
+            &changes[..],
+            [(v1, ActionKind::NewVersion)] if v1 == "2021.10.01"
+        ));
+    }
+}
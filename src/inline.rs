@@ -0,0 +1,279 @@
+//! Inline mode: `@crates_upd_bot tokio` shows the latest versions of matching crates
+use std::{fs, future::Future, io, path::Path, pin::Pin, sync::Arc};
+
+use carapax::{
+    methods::AnswerInlineQuery,
+    types::{
+        InlineQuery, InlineQueryResult, InlineQueryResultArticle, InputMessageContentText,
+        ParseMode,
+    },
+    Api, Handler,
+};
+
+use crate::{
+    bot::HErr,
+    cfg::{Config, SharedConfig},
+    db::Database,
+    krate::{normalize_name, Versions},
+    send::SendQueue,
+};
+
+/// Maximum number of results shown for a query
+const MAX_RESULTS: usize = 10;
+
+/// Sorted crate names for prefix search
+#[derive(Debug, Default)]
+pub struct NameIndex {
+    /// `(normalized name, name)` sorted by the normalized name
+    names: Vec<(String, String)>,
+}
+
+impl NameIndex {
+    pub fn new(names: impl IntoIterator<Item = String>) -> Self {
+        let mut names: Vec<_> = names
+            .into_iter()
+            .map(|name| (normalize_name(&name), name))
+            .collect();
+        names.sort();
+        Self { names }
+    }
+
+    /// Collects names of all crate files in the local git index
+    pub fn from_dir(index_path: &Path) -> io::Result<Self> {
+        fn walk(dir: &Path, depth: usize, names: &mut Vec<String>) -> io::Result<()> {
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name.starts_with('.') || name == "config.json" {
+                    continue;
+                }
+                // crate files are at most 3 levels deep (`ab/cd/abcd`)
+                if entry.file_type()?.is_dir() && depth < 3 {
+                    walk(&entry.path(), depth + 1, names)?;
+                } else if depth > 0 {
+                    names.push(name);
+                }
+            }
+
+            Ok(())
+        }
+
+        let mut names = Vec::new();
+        walk(index_path, 0, &mut names)?;
+        Ok(Self::new(names))
+    }
+
+    /// Crate names starting with `prefix`, the exact match goes first
+    pub fn search(&self, prefix: &str) -> Vec<&str> {
+        let prefix = normalize_name(prefix);
+        let start = self
+            .names
+            .partition_point(|(key, _)| key.as_str() < prefix.as_str());
+        self.names[start..]
+            .iter()
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .take(MAX_RESULTS)
+            .map(|(_, name)| name.as_str())
+            .collect()
+    }
+
+    /// Up to `max` crate names closest to the misspelled `name` (by edit distance of normalized
+    /// names), the closest first
+    pub fn suggest(&self, name: &str, max: usize) -> Vec<&str> {
+        let name = normalize_name(name);
+        let len = name.chars().count();
+        // short names are too similar to each other
+        let max_distance = if len < 5 { 1 } else { 2 };
+        let mut found: Vec<(usize, &str)> = self
+            .names
+            .iter()
+            .filter(|(key, _)| {
+                let key_len = key.chars().count();
+                key_len + max_distance >= len && key_len <= len + max_distance
+            })
+            .filter_map(|(key, krate)| {
+                let distance = levenshtein(key, &name);
+                if distance <= max_distance {
+                    Some((distance, krate.as_str()))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        found.sort_unstable();
+        found
+            .into_iter()
+            .take(max)
+            .map(|(_, krate)| krate)
+            .collect()
+    }
+}
+
+/// Number of single-char insertions, deletions and substitutions turning `a` into `b`
+pub(crate) fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + (ca != cb) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+/// Answers inline queries
+pub struct Inline {
+    names: Arc<NameIndex>,
+}
+
+impl Inline {
+    pub fn new(names: Arc<NameIndex>) -> Self {
+        Self { names }
+    }
+}
+
+/// Result for a single crate, `None` if it isn't in the index
+async fn article(
+    db: &Database,
+    cfg: &Config,
+    name: &str,
+) -> Result<Option<InlineQueryResult>, HErr> {
+    let versions = match Versions::read(name, cfg).await {
+        Ok(versions) => versions,
+        Err(_) => return Ok(None),
+    };
+    let krate = versions.get(false).unwrap_or(&versions.newest);
+    let published = db
+        .list_releases(&krate.id.name)
+        .await?
+        .into_iter()
+        .find(|(version, ..)| *version == krate.id.vers)
+        .map(|(.., published)| published);
+
+    let text = format!(
+        "<code>{} {}</code>{} {}",
+        krate.id.name,
+        krate.id.vers,
+        published
+            .as_ref()
+            .map(|p| format!(" (released {})", p))
+            .unwrap_or_default(),
+        krate.html_links()
+    );
+    let description = match &published {
+        Some(published) => format!("{}, released {}", krate.id.vers, published),
+        None => krate.id.vers.clone(),
+    };
+
+    Ok(Some(
+        InlineQueryResultArticle::new(
+            krate.id.name.as_str(),
+            format!("{} {}", krate.id.name, krate.id.vers),
+            InputMessageContentText::new(text)
+                .parse_mode(ParseMode::Html)
+                .disable_web_page_preview(true),
+        )
+        .description(description)
+        .url(krate.cratesio())
+        .into(),
+    ))
+}
+
+impl Handler<(Api, Database, SharedConfig, SendQueue)> for Inline {
+    type Input = InlineQuery;
+    type Output = Result<(), HErr>;
+
+    fn handle<'s: 'async_trait, 'a: 'async_trait, 'async_trait>(
+        &'s mut self,
+        context: &'a (Api, Database, SharedConfig, SendQueue),
+        input: Self::Input,
+    ) -> Pin<Box<dyn Future<Output = Self::Output> + Send + 'async_trait>> {
+        async fn handle_(
+            this: &mut Inline,
+            (bot, db, shared, _): &(Api, Database, SharedConfig, SendQueue),
+            query: InlineQuery,
+        ) -> Result<(), HErr> {
+            let cfg = &shared.get();
+            let prefix = query.query.trim();
+            if prefix.is_empty() {
+                return Ok(());
+            }
+
+            let mut names: Vec<String> = this
+                .names
+                .search(prefix)
+                .into_iter()
+                .map(str::to_owned)
+                .collect();
+            // crates published after the start (and all crates with the sparse index)
+            // are only found by the exact name
+            if !names
+                .iter()
+                .any(|name| normalize_name(name) == normalize_name(prefix))
+            {
+                names.insert(0, prefix.to_owned());
+                names.truncate(MAX_RESULTS);
+            }
+
+            let mut results = Vec::new();
+            for name in &names {
+                results.extend(article(db, cfg, name).await?);
+            }
+
+            bot.execute(AnswerInlineQuery::new(query.id, results).cache_time(60))
+                .await?;
+
+            Ok(())
+        }
+
+        Box::pin(handle_(self, context, input))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_search() {
+        let index = NameIndex::new(
+            ["tokio", "tokio-util", "tokio_macros", "serde", "tok"]
+                .iter()
+                .map(|s| s.to_string()),
+        );
+
+        assert_eq!(
+            index.search("tokio"),
+            ["tokio", "tokio_macros", "tokio-util"]
+        );
+        assert_eq!(index.search("Tokio-M"), ["tokio_macros"]);
+        assert_eq!(index.search("x"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn suggestions() {
+        let index = NameIndex::new(
+            [
+                "serde",
+                "serde_json",
+                "serde_yaml",
+                "serde-json-core",
+                "tokio",
+            ]
+            .iter()
+            .map(|s| s.to_string()),
+        );
+
+        assert_eq!(index.suggest("Serde-Json", 3), ["serde_json"]);
+        assert_eq!(index.suggest("serde_jsn", 3), ["serde_json"]);
+        assert_eq!(index.suggest("serdd", 3), ["serde"]);
+        assert_eq!(index.suggest("toki", 3), ["tokio"]);
+        assert_eq!(index.suggest("tk", 3), Vec::<&str>::new());
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+    }
+}
@@ -1,18 +1,44 @@
-use crate::cfg::Config;
+use crate::cfg::{Config, RegistryConfig};
+use crate::deps::Dependency;
+use crate::index::{sparse, IndexKind};
+use crate::releases;
 use crate::util::crate_path;
+use kacl_parser::VersionScheme;
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::path::Path;
+use std::sync::Arc;
 use tokio::fs::File;
 use tokio::io;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::stream::StreamExt;
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Crate {
     // TODO: stole from crates.io repo?
     #[serde(flatten)]
     pub id: CrateId,
     pub yanked: bool,
+    /// Cargo features and what they enable
+    #[serde(default)]
+    pub features: BTreeMap<String, Vec<String>>,
+    /// Features using the newer `dep:`/`?` syntax, stored apart from `features` in the index
+    #[serde(default)]
+    pub features2: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub deps: Vec<Dependency>,
+    /// MSRV, `rust-version` from the manifest
+    #[serde(default)]
+    pub rust_version: Option<String>,
+    /// Sha256 of the `.crate` file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cksum: Option<String>,
     // ignore all unrelated stuff :D
+    /// Alternative registry of the crate, `None` for crates.io
+    #[serde(skip)]
+    pub registry: Option<Arc<RegistryConfig>>,
 }
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
@@ -21,18 +47,120 @@ pub struct CrateId {
     pub vers: String,
 }
 
+/// Splits `myreg:internal-crate` into the registry and the name, crates.io crates have no registry
+pub fn split_key(key: &str) -> (Option<&str>, &str) {
+    match key.find(':') {
+        Some(idx) => (Some(&key[..idx]), &key[idx + 1..]),
+        None => (None, key),
+    }
+}
+
+/// crates.io treats names differing only in case or in `-`/`_` as the same name
+pub fn normalize_name(name: &str) -> String {
+    name.to_lowercase().replace('_', "-")
+}
+
+/// Whether crates.io accepts `name` as a name of a new crate
+pub fn is_valid_name(name: &str) -> bool {
+    name.len() <= 64
+        && name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// How versions of the crate with `key` are compared: the configured scheme, or the strictest one
+/// accepting all of `versions`
+pub fn version_scheme<'v>(
+    key: &str,
+    versions: impl IntoIterator<Item = &'v str>,
+    cfg: &Config,
+) -> VersionScheme {
+    cfg.crates
+        .version_scheme(key)
+        .unwrap_or_else(|| VersionScheme::detect(versions))
+}
+
+/// Registry of the crate (`None` for crates.io) and its name inside of the registry,
+/// `None` if the registry isn't configured
+fn resolve<'a>(
+    key: &'a str,
+    cfg: &'a Config,
+) -> Option<(Option<&'a Arc<RegistryConfig>>, &'a str)> {
+    match split_key(key) {
+        (Some(registry), name) => Some((Some(cfg.registry(registry)?), name)),
+        (None, name) => Some((None, name)),
+    }
+}
+
+fn unknown_registry(key: &str) -> io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("unknown registry of {}", key),
+    )
+}
+
 impl Crate {
     // TODO: struct: Display
 
+    /// Version of the crate by its key (`myreg:name` for alternative registries),
+    /// `None` if the registry isn't configured
+    pub fn from_key(key: &str, version: &str, yanked: bool, cfg: &Config) -> Option<Self> {
+        let (registry, name) = resolve(key, cfg)?;
+        Some(Self {
+            id: CrateId {
+                name: name.to_owned(),
+                vers: version.to_owned(),
+            },
+            yanked,
+            features: BTreeMap::new(),
+            features2: BTreeMap::new(),
+            deps: Vec::new(),
+            rust_version: None,
+            cksum: None,
+            registry: registry.cloned(),
+        })
+    }
+
+    /// Hex sha256 of the index entry (of the fields the bot reads), changes when the entry is
+    /// rewritten, e.g. by a metadata fix
+    pub fn content_hash(&self) -> String {
+        // serialization of the entry can't fail, maps are sorted
+        let entry = serde_json::to_vec(self).unwrap_or_default();
+        hex::encode(Sha256::digest(&entry))
+    }
+
+    /// Name of the crate in the database and in messages, `myreg:name` for alternative registries
+    pub fn key(&self) -> String {
+        match &self.registry {
+            Some(registry) => format!("{}:{}", registry.name, self.id.name),
+            None => self.id.name.clone(),
+        }
+    }
+
+    /// Page of the crate on crates.io or on its registry
     pub fn cratesio(&self) -> String {
-        format!("https://crates.io/crates/{krate}", krate = self.id.name)
+        match &self.registry {
+            Some(registry) => registry.crate_url.replace("{crate}", &self.id.name),
+            None => format!("https://crates.io/crates/{krate}", krate = self.id.name),
+        }
     }
 
     pub fn librs(&self) -> String {
         format!("https://lib.rs/crates/{krate}", krate = self.id.name)
     }
 
+    /// Documentation of the version, for alternative registries without docs it's the crate's page
     pub fn docsrs(&self) -> String {
+        if let Some(registry) = &self.registry {
+            return match &registry.docs_url {
+                Some(docs) => docs
+                    .replace("{crate}", &self.id.name)
+                    .replace("{version}", &self.id.vers),
+                None => self.cratesio(),
+            };
+        }
+
         // Note:
         // The full url is actually "https://docs.rs/{krate}/{version}/{krate}"
         // but for some crates it doesn't hold e.g.: https://docs.rs/lsk/0.2.0/ls_key/
@@ -47,6 +175,14 @@ impl Crate {
     }
 
     pub fn html_links(&self) -> String {
+        if let Some(registry) = &self.registry {
+            let page = format!("<a href='{}'>[{}]</a>", self.cratesio(), registry.name);
+            return match registry.docs_url {
+                Some(_) => format!("<a href='{}'>[docs]</a> {}", self.docsrs(), page),
+                None => page,
+            };
+        }
+
         format!(
             "<a href='{docs}'>[docs.rs]</a> \
              <a href='{crates}'>[crates.io]</a> \
@@ -57,14 +193,144 @@ impl Crate {
         )
     }
 
-    pub async fn read_last(name: &str, cfg: &Config) -> io::Result<Self> {
-        let file = File::open(Path::new(cfg.index_path.as_str()).join(crate_path(name))).await?;
-        let mut lines = BufReader::new(file).lines();
-        let mut last = None;
-        while let next @ Some(_) = lines.next().await.transpose()? {
-            last = next
+    /// Diff with the previous version on diff.rs, versions page if there is no previous version.
+    /// diff.rs knows only crates.io, for alternative registries it's the crate's page.
+    pub fn diff_url(&self, previous: Option<&str>) -> String {
+        match (previous, &self.registry) {
+            (Some(previous), None) => format!(
+                "https://diff.rs/{}/{}/{}",
+                self.id.name, previous, self.id.vers
+            ),
+            (None, None) => format!("{}/versions", self.cratesio()),
+            (_, Some(_)) => self.cratesio(),
+        }
+    }
+
+    /// All versions of the crate from the index, oldest first.
+    /// `key` is `myreg:name` for crates of alternative registries.
+    pub async fn read_all(key: &str, cfg: &Config) -> io::Result<Vec<Self>> {
+        let (registry, name) = resolve(key, cfg).ok_or_else(|| unknown_registry(key))?;
+        let (kind, location) = index_of(registry, cfg);
+        let mut all = if kind == IndexKind::Sparse {
+            sparse::fetch(&location, name).await.map_err(|err| {
+                let kind = match &err {
+                    sparse::Error::Http(err) if err.status() == Some(StatusCode::NOT_FOUND) => {
+                        std::io::ErrorKind::NotFound
+                    }
+                    _ => std::io::ErrorKind::Other,
+                };
+                std::io::Error::new(kind, err.to_string())
+            })?
+        } else {
+            let file = File::open(Path::new(location.as_ref()).join(crate_path(name))).await?;
+            let mut lines = BufReader::new(file).lines();
+            let mut all = Vec::new();
+            while let Some(line) = lines.next().await.transpose()? {
+                let krate = serde_json::from_str(&line)
+                    .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+                all.push(krate);
+            }
+            all
+        };
+
+        for krate in &mut all {
+            krate.registry = registry.cloned();
+        }
+        Ok(all)
+    }
+
+    /// `true` if the crate exists in the index (`key` is `myreg:name` for alternative registries)
+    pub async fn exists(key: &str, cfg: &Config) -> bool {
+        let (registry, name) = match resolve(key, cfg) {
+            Some(resolved) => resolved,
+            None => return false,
+        };
+        match index_of(registry, cfg) {
+            (IndexKind::Git, path) => Path::new(path.as_ref()).join(crate_path(name)).exists(),
+            (IndexKind::Sparse, url) => sparse::fetch(&url, name).await.is_ok(),
+        }
+    }
+}
+
+/// Kind of the index of crates.io or of the registry, and the path of its git clone or its sparse url
+fn index_of<'a>(
+    registry: Option<&'a Arc<RegistryConfig>>,
+    cfg: &'a Config,
+) -> (IndexKind, Cow<'a, str>) {
+    match registry {
+        Some(registry) if registry.kind == IndexKind::Git => {
+            (IndexKind::Git, Cow::Owned(registry.index_path()))
+        }
+        Some(registry) => (
+            IndexKind::Sparse,
+            Cow::Borrowed(registry.index_url.as_str()),
+        ),
+        None if cfg.index.kind == IndexKind::Git => {
+            (IndexKind::Git, Cow::Borrowed(cfg.index_path.as_str()))
+        }
+        None => (
+            IndexKind::Sparse,
+            Cow::Borrowed(cfg.index.sparse_url.as_str()),
+        ),
+    }
+}
+
+/// The newest versions of a crate, from the release cache (see [`releases`])
+#[derive(Debug)]
+pub struct Versions {
+    /// The newest version, possibly yanked
+    pub newest: Crate,
+    /// The newest version that isn't yanked
+    pub latest: Option<Crate>,
+    /// Unix time the versions were fetched at if the index couldn't be reached since
+    pub stale: Option<u64>,
+}
+
+impl Versions {
+    pub async fn read(name: &str, cfg: &Config) -> io::Result<Self> {
+        let releases = releases::get(name, cfg).await?;
+        let stale = releases.stale();
+        let all = releases.versions;
+        let latest = all.iter().rev().find(|krate| !krate.yanked).cloned();
+        let newest = all.into_iter().last().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "empty index file")
+        })?;
+
+        Ok(Self {
+            newest,
+            latest,
+            stale,
+        })
+    }
+
+    /// Version to display: the newest one if `include_yanked`, otherwise the newest not yanked
+    pub fn get(&self, include_yanked: bool) -> Option<&Crate> {
+        if include_yanked {
+            Some(&self.newest)
+        } else {
+            self.latest.as_ref()
+        }
+    }
+
+    /// `<code>1.2.3</code> [links]`, noting when the newest version is yanked
+    pub fn html(&self, include_yanked: bool) -> String {
+        match self.get(include_yanked) {
+            Some(krate) if krate.yanked => format!(
+                "<code>{}</code> (yanked) {}",
+                krate.id.vers,
+                krate.html_links()
+            ),
+            Some(krate) if self.newest.yanked => format!(
+                "<code>{}</code> {} (the newest version <code>{}</code> is yanked)",
+                krate.id.vers,
+                krate.html_links(),
+                self.newest.id.vers
+            ),
+            Some(krate) => format!("<code>{}</code> {}", krate.id.vers, krate.html_links()),
+            None => format!(
+                "all versions are yanked (the newest is <code>{}</code>)",
+                self.newest.id.vers
+            ),
         }
-        serde_json::from_str(&last.unwrap())
-            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))
     }
 }
@@ -0,0 +1,307 @@
+//! Release notes found after the new version was announced: many maintainers push the changelog
+//! minutes after publishing. Versions announced without notes are rechecked for them for
+//! `changelog.notes_lag_secs`, and telegram notifications about them are edited in place once the
+//! notes appear.
+use std::{convert::TryFrom, time::Duration};
+
+use carapax::{methods::EditMessageText, types::ParseMode, Api};
+use versions::SemVer;
+
+use crate::{
+    cfg::{Config, SharedConfig},
+    db::{Database, Subscriber},
+    filter::Filter,
+    krate::Crate,
+    msrv, previous_release, release_notes, rules, summary,
+    template::Template,
+    translate::Translations,
+    verbosity::Verbosity,
+    ActionKind, Announcement,
+};
+
+/// How often versions waiting for release notes are looked up
+const CHECK_DELAY: Duration = Duration::from_secs(60);
+
+/// How often a version is checked for release notes, changelog files are cached about that long
+const RECHECK_SECS: i32 = 5 * 60;
+
+/// Chat an announcement was sent to, its text depends on it
+#[derive(Debug)]
+enum Reader<'a> {
+    /// Subscriber of the crate, the text follows the chat's settings
+    Subscriber(&'a Subscriber),
+    /// Channel of the operator (with the channel's filter) or a route of the operator's rules
+    Channel(Filter),
+}
+
+/// Who the chat is, `users` are the subscribers among the chats
+fn reader<'a>(chat_id: i64, users: &'a [Subscriber], cfg: &Config) -> Reader<'a> {
+    match users
+        .iter()
+        .find(|subscriber| subscriber.chat_id == chat_id)
+    {
+        Some(subscriber) => Reader::Subscriber(subscriber),
+        None => Reader::Channel(
+            cfg.telegram_channels
+                .iter()
+                .find(|channel| channel.id == chat_id)
+                .map_or_else(Filter::default, |channel| channel.selector.filter),
+        ),
+    }
+}
+
+/// Edits the messages announcing the version, `users` are the subscribers among the chats.
+/// Returns how many messages were edited.
+async fn edit(
+    bot: &Api,
+    announced: Vec<(i64, i64)>,
+    users: &[Subscriber],
+    announcement: &Announcement<'_>,
+    notes: Option<&str>,
+    cfg: &Config,
+) -> usize {
+    let mut translations = Translations::new(notes);
+    let mut edited = 0;
+    for (chat_id, message_id) in announced {
+        let text = match reader(chat_id, users, cfg) {
+            Reader::Subscriber(subscriber) => {
+                let template = subscriber
+                    .template
+                    .as_deref()
+                    .and_then(|template| Template::parse(template).ok());
+                let toolchain = subscriber
+                    .msrv
+                    .as_deref()
+                    .and_then(|toolchain| msrv::exceeds(announcement.krate, toolchain));
+                let language = subscriber.language.as_deref();
+                let tag = translations.foreign(language);
+                let translation = if subscriber.translate {
+                    translations.get(language, cfg).await
+                } else {
+                    None
+                };
+                announcement.text(
+                    template.as_ref(),
+                    subscriber.verbose,
+                    subscriber.verbosity,
+                    subscriber.security_alerts,
+                    subscriber.filter,
+                    toolchain.as_deref(),
+                    summary::shown(chat_id, cfg.summary_share),
+                    translation,
+                    tag.filter(|_| translation.is_none()),
+                )
+            }
+            Reader::Channel(filter) => announcement.text(
+                None,
+                false,
+                Verbosity::Full,
+                false,
+                filter,
+                None,
+                true,
+                None,
+                None,
+            ),
+        };
+
+        let request = EditMessageText::new(chat_id, message_id, text.as_str())
+            .parse_mode(ParseMode::Html)
+            .disable_web_page_preview(true);
+        // e.g. the message was deleted or its text is the same
+        match bot.execute(request).await {
+            Ok(_) => edited += 1,
+            Err(err) => tracing::debug!("couldn't edit announcement in {}: {}", chat_id, err),
+        }
+    }
+    edited
+}
+
+/// Renders the messages announcing the new version again with `notes` (see [`Announcement`]) and
+/// edits them, `announced` are chats and ids of the messages. Returns how many were edited.
+pub async fn reannounce(
+    bot: &Api,
+    db: &Database,
+    cfg: &Config,
+    krate: &Crate,
+    notes: Option<&str>,
+    announced: Vec<(i64, i64)>,
+) -> usize {
+    let key = krate.key();
+    let action = ActionKind::NewVersion;
+    let (scheme, previous_release) = previous_release(&key, &krate.id.vers, cfg).await;
+    let previous_version = previous_release
+        .as_ref()
+        .map(|previous| previous.id.vers.as_str());
+    let previous = previous_version.and_then(SemVer::new);
+    let users: Vec<Subscriber> = db
+        .list_subscribers(&key)
+        .await
+        .map_err(|err| tracing::error!("db error while getting subscribers: {}", err))
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|subscriber| {
+            announced
+                .iter()
+                .any(|&(chat_id, _)| chat_id == subscriber.chat_id)
+        })
+        .collect();
+    let routing = rules::evaluate(cfg, krate, &action, scheme, previous_version, notes).await;
+    let announcement = Announcement::new(
+        krate,
+        &action,
+        &[],
+        previous_release.as_ref(),
+        previous.as_ref(),
+        notes,
+        routing.template.or_else(|| cfg.template.as_ref()),
+        &users,
+        &routing.routes,
+        db,
+        cfg,
+    )
+    .await;
+    edit(bot, announced, &users, &announcement, notes, cfg).await
+}
+
+/// Looks up release notes of the version and edits its announcements if they're found, forgets
+/// them if the version is waiting for longer than `notes_lag_secs` (`expired`)
+pub async fn check(
+    bot: &Api,
+    db: &Database,
+    cfg: &Config,
+    key: &str,
+    version: &str,
+    expired: bool,
+) {
+    let krate = Crate::read_all(key, cfg)
+        .await
+        .map_err(|err| tracing::warn!("couldn't read versions of {}: {}", key, err))
+        .ok()
+        .and_then(|all| all.into_iter().find(|krate| krate.id.vers == version));
+    let action = ActionKind::NewVersion;
+    let (_, previous_release) = previous_release(key, version, cfg).await;
+    let previous = previous_release
+        .as_ref()
+        .and_then(|previous| SemVer::new(&previous.id.vers));
+    let notes = match &krate {
+        Some(krate) => release_notes(krate, &action, &[], previous.as_ref(), cfg).await,
+        None => None,
+    };
+    let (krate, notes) = match (krate, notes) {
+        (Some(krate), Some(notes)) => (krate, notes),
+        _ => {
+            if expired {
+                tracing::debug!("no release notes of {} {} were found", key, version);
+                if let Err(err) = db.take_announcements(key, version).await {
+                    tracing::error!("db error while taking announcements: {}", err);
+                }
+            }
+            return;
+        }
+    };
+
+    tracing::info!(
+        "found release notes of {} {} after it was announced",
+        key,
+        version
+    );
+    db.set_release_notes(key, version, &notes)
+        .await
+        .unwrap_or_else(|err| tracing::error!("db error while saving release notes: {}", err));
+    let announced = db
+        .take_announcements(key, version)
+        .await
+        .map_err(|err| tracing::error!("db error while taking announcements: {}", err))
+        .unwrap_or_default();
+    if announced.is_empty() {
+        return;
+    }
+
+    reannounce(bot, db, cfg, &krate, Some(notes.as_str()), announced).await;
+}
+
+/// Rechecks versions announced without release notes, forever
+pub async fn run(bot: Api, db: Database, shared: SharedConfig) {
+    loop {
+        tokio::time::delay_for(CHECK_DELAY).await;
+        let cfg = shared.get();
+        let lag = i32::try_from(cfg.changelog.notes_lag_secs).unwrap_or(i32::MAX);
+        let due = match db.due_lagging_notes(RECHECK_SECS, lag).await {
+            Ok(due) => due,
+            Err(err) => {
+                tracing::error!("db error while getting versions waiting for notes: {}", err);
+                continue;
+            }
+        };
+        for (key, version, expired) in due {
+            check(&bot, &db, &cfg, &key, &version, expired).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::Bump;
+
+    fn subscriber(chat_id: i64) -> Subscriber {
+        Subscriber {
+            chat_id,
+            filter: Filter::default(),
+            mute_yanks: false,
+            digest: false,
+            baseline: None,
+            template: None,
+            tagged: false,
+            email: false,
+            verbose: true,
+            quiet: false,
+            msrv: None,
+            security_alerts: false,
+            verbosity: Verbosity::Full,
+            paused: false,
+            language: None,
+            translate: false,
+        }
+    }
+
+    #[test]
+    fn readers() {
+        let cfg: Config = toml::from_str(
+            r#"
+            bot_token = "test"
+
+            [db]
+            host = "localhost"
+            user = "test"
+            dbname = "test"
+
+            [[telegram_channel]]
+            id = -100
+            filter = ["minor"]
+            "#,
+        )
+        .unwrap();
+        let users = [subscriber(1), subscriber(-100)];
+
+        assert!(matches!(
+            reader(1, &users, &cfg),
+            Reader::Subscriber(subscriber) if subscriber.chat_id == 1
+        ));
+        // the operator's channel gets the text by its filter, unless it's subscribed to the crate
+        assert!(matches!(
+            reader(-100, &users[..1], &cfg),
+            Reader::Channel(filter) if filter.min_bump == Bump::Minor
+        ));
+        assert!(matches!(
+            reader(-100, &users, &cfg),
+            Reader::Subscriber(subscriber) if subscriber.chat_id == -100
+        ));
+        // a route of the operator's rules
+        assert!(matches!(
+            reader(-200, &users, &cfg),
+            Reader::Channel(filter) if filter == Filter::default()
+        ));
+    }
+}
@@ -0,0 +1,54 @@
+//! License changes between releases, from the metadata of versions on crates.io
+use crate::render::escape;
+
+/// `MIT/Apache-2.0` (the legacy syntax) → `MIT OR APACHE-2.0`, so only real changes are reported
+fn normalize(license: &str) -> String {
+    license
+        .replace('/', " OR ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_uppercase()
+}
+
+/// Warning about the release changing the license, as telegram html. `None` is a version without
+/// a license (e.g. with a `license-file` only).
+pub fn changed(old: Option<&str>, new: Option<&str>) -> Option<String> {
+    if old.map(normalize) == new.map(normalize) {
+        return None;
+    }
+
+    let html = |license: Option<&str>| {
+        license.map_or_else(
+            || String::from("none"),
+            |license| format!("<code>{}</code>", escape(license)),
+        )
+    };
+    Some(format!(
+        "⚠️ <b>License changed</b> from {} to {}",
+        html(old),
+        html(new)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes() {
+        assert_eq!(
+            changed(Some("MIT"), Some("BUSL-1.1")).as_deref(),
+            Some("⚠️ <b>License changed</b> from <code>MIT</code> to <code>BUSL-1.1</code>")
+        );
+        assert_eq!(
+            changed(Some("MIT OR Apache-2.0"), None).as_deref(),
+            Some("⚠️ <b>License changed</b> from <code>MIT OR Apache-2.0</code> to none")
+        );
+        assert_eq!(
+            changed(Some("MIT/Apache-2.0"), Some("MIT OR Apache-2.0")),
+            None
+        );
+        assert_eq!(changed(None, None), None);
+    }
+}
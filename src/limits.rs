@@ -0,0 +1,339 @@
+//! Per-chat limits (`[limits]` of the config), so a single misbehaving user or script can't blow
+//! up the storage or the crates.io api quota: subscriptions of a chat, its commands per minute and
+//! loops of subscribing and unsubscribing
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    cfg::{Config, LimitsConfig},
+    db::Database,
+    metrics,
+};
+
+/// Chats tracked before idle ones are forgotten
+const MAX_CHATS: usize = 10_000;
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// Told once when a chat sends commands faster than `commands_per_minute`
+pub const TOO_FAST: &str =
+    "You're sending commands too fast, the next ones in this minute will be ignored.";
+
+/// Answers to buttons changing subscriptions which were refused
+pub const BLOCKED_NOTICE: &str = "Subscriptions of this chat were changed too often, try later.";
+pub const FULL_NOTICE: &str = "This chat is subscribed to too many crates.";
+
+/// Commands changing subscriptions to crates (and waits for their versions), counted by
+/// `max_subscription_changes`
+pub const CHANGES: [&str; 7] = [
+    "/subscribe",
+    "/unsubscribe",
+    "/subscribe_owner",
+    "/unsubscribe_owner",
+    "/subscribe_deps",
+    "/notify_when",
+    "/unnotify_when",
+];
+
+/// Version waits of a chat for one crate
+pub const MAX_WAITS_PER_CRATE: usize = 5;
+
+/// What the chat did recently
+#[derive(Debug, Default)]
+struct Usage {
+    /// Times of commands in the last minute
+    commands: VecDeque<Instant>,
+    /// The chat was told it sends commands too fast
+    warned: bool,
+    /// Times of subscription changes in the churn window
+    changes: VecDeque<Instant>,
+    /// Subscription changes are refused until then
+    blocked_until: Option<Instant>,
+}
+
+/// Drops times older than `window`
+fn expire(times: &mut VecDeque<Instant>, now: Instant, window: Duration) {
+    while times
+        .front()
+        .map_or(false, |&time| now.duration_since(time) >= window)
+    {
+        times.pop_front();
+    }
+}
+
+impl Usage {
+    fn is_idle(&self, now: Instant) -> bool {
+        self.commands.is_empty()
+            && self.changes.is_empty()
+            && self.blocked_until.map_or(true, |until| until <= now)
+    }
+}
+
+/// Verdict on a command of a chat
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Allowed,
+    /// The chat went over `commands_per_minute` and has to be told so
+    Warn,
+    /// The chat is over `commands_per_minute` and was told so
+    Ignore,
+}
+
+/// Recent commands of chats, kept in memory (limits start over on restarts)
+#[derive(Debug, Default)]
+pub struct Limiter {
+    chats: Mutex<HashMap<i64, Usage>>,
+}
+
+impl Limiter {
+    fn with_usage<T>(&self, chat_id: i64, now: Instant, f: impl FnOnce(&mut Usage) -> T) -> T {
+        let mut chats = self.chats.lock().unwrap_or_else(|err| err.into_inner());
+        if chats.len() >= MAX_CHATS && !chats.contains_key(&chat_id) {
+            chats.retain(|_, usage| !usage.is_idle(now));
+        }
+        f(chats.entry(chat_id).or_default())
+    }
+
+    /// Counts a command of the chat
+    pub fn command(&self, chat_id: i64, cfg: &LimitsConfig, now: Instant) -> Verdict {
+        if cfg.commands_per_minute == 0 {
+            return Verdict::Allowed;
+        }
+
+        self.with_usage(chat_id, now, |usage| {
+            expire(&mut usage.commands, now, MINUTE);
+            if usage.commands.len() < cfg.commands_per_minute {
+                usage.commands.push_back(now);
+                usage.warned = false;
+                return Verdict::Allowed;
+            }
+
+            metrics::LIMITED.with_label_values(&["commands"]).inc();
+            if std::mem::replace(&mut usage.warned, true) {
+                Verdict::Ignore
+            } else {
+                tracing::warn!(
+                    "chat {} sends more than {} commands per minute",
+                    chat_id,
+                    cfg.commands_per_minute
+                );
+                Verdict::Warn
+            }
+        })
+    }
+
+    /// Counts a change of the chat's subscriptions, `Err` with the time left if changes are
+    /// blocked (also when this change goes over `max_subscription_changes`)
+    pub fn change(&self, chat_id: i64, cfg: &LimitsConfig, now: Instant) -> Result<(), Duration> {
+        if cfg.max_subscription_changes == 0 {
+            return Ok(());
+        }
+
+        let result = self.with_usage(chat_id, now, |usage| {
+            if let Some(until) = usage.blocked_until.filter(|&until| until > now) {
+                return Err(until - now);
+            }
+
+            expire(
+                &mut usage.changes,
+                now,
+                Duration::from_secs(cfg.churn_window_secs),
+            );
+            usage.changes.push_back(now);
+            if usage.changes.len() <= cfg.max_subscription_changes {
+                return Ok(());
+            }
+
+            tracing::warn!(
+                "chat {} changed subscriptions {} times in {} secs, blocking changes",
+                chat_id,
+                usage.changes.len(),
+                cfg.churn_window_secs
+            );
+            let block = Duration::from_secs(cfg.churn_block_secs);
+            usage.changes.clear();
+            usage.blocked_until = Some(now + block);
+            Err(block)
+        });
+        if result.is_err() {
+            metrics::LIMITED.with_label_values(&["churn"]).inc();
+        }
+
+        result
+    }
+}
+
+/// Reply to a subscription change refused by [`Limiter::change`]
+pub fn blocked_text(left: Duration) -> String {
+    let minutes = (left.as_secs() / 60).max(1);
+    format!(
+        "Error: subscriptions of this chat were changed too often. Try again in {} minute{}.",
+        minutes,
+        if minutes == 1 { "" } else { "s" }
+    )
+}
+
+/// Error text if subscribing to `krates` takes the chat over `max_subscriptions`
+pub async fn check_subscriptions(
+    db: &Database,
+    cfg: &Config,
+    chat_id: i64,
+    krates: &[&str],
+) -> Result<Option<String>, tokio_postgres::Error> {
+    let max = cfg.limits.max_subscriptions;
+    if max == 0 {
+        return Ok(None);
+    }
+
+    let current = db.list_subscriptions(chat_id).await?;
+    let current: HashSet<&str> = current.iter().map(String::as_str).collect();
+    let new: HashSet<&str> = krates
+        .iter()
+        .copied()
+        .filter(|krate| !current.contains(krate))
+        .collect();
+    if current.len() + new.len() <= max {
+        return Ok(None);
+    }
+
+    metrics::LIMITED.with_label_values(&["subscriptions"]).inc();
+    Ok(Some(format!(
+        "Error: a chat can be subscribed to at most {} crates, this one is subscribed to {}. Use /unsubscribe to remove some subscriptions first.",
+        max,
+        current.len()
+    )))
+}
+
+/// Error text if waiting for `requirement` of `krate` takes the chat over `max` waits (0 for no
+/// limit) or over [`MAX_WAITS_PER_CRATE`], `waits` are the current ones
+fn waits_error(
+    waits: &[(String, String)],
+    krate: &str,
+    requirement: &str,
+    max: usize,
+) -> Option<String> {
+    if waits
+        .iter()
+        .any(|(k, req)| k == krate && req == requirement)
+    {
+        return None;
+    }
+    let of_crate = waits.iter().filter(|(k, _)| k == krate).count();
+    if of_crate >= MAX_WAITS_PER_CRATE {
+        return Some(format!(
+            "Error: a chat can wait for at most {} releases of a crate. Use /unnotify_when to remove the waits first.",
+            MAX_WAITS_PER_CRATE
+        ));
+    }
+    if max != 0 && waits.len() >= max {
+        return Some(format!(
+            "Error: a chat can wait for at most {} releases, this one waits for {}. Use /unnotify_when to remove some waits first.",
+            max,
+            waits.len()
+        ));
+    }
+
+    None
+}
+
+/// Error text if waiting for `requirement` of `krate` takes the chat over `max_version_waits`
+pub async fn check_version_waits(
+    db: &Database,
+    cfg: &Config,
+    chat_id: i64,
+    krate: &str,
+    requirement: &str,
+) -> Result<Option<String>, tokio_postgres::Error> {
+    let waits = db.list_version_waits(chat_id).await?;
+    let error = waits_error(&waits, krate, requirement, cfg.limits.max_version_waits);
+    if error.is_some() {
+        metrics::LIMITED.with_label_values(&["waits"]).inc();
+    }
+
+    Ok(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg() -> LimitsConfig {
+        LimitsConfig {
+            max_subscriptions: 10,
+            max_version_waits: 2,
+            commands_per_minute: 2,
+            max_subscription_changes: 3,
+            churn_window_secs: 600,
+            churn_block_secs: 3600,
+        }
+    }
+
+    #[test]
+    fn commands() {
+        let limiter = Limiter::default();
+        let start = Instant::now();
+        let verdicts: Vec<Verdict> = (0..4).map(|_| limiter.command(1, &cfg(), start)).collect();
+        assert_eq!(
+            verdicts,
+            [
+                Verdict::Allowed,
+                Verdict::Allowed,
+                Verdict::Warn,
+                Verdict::Ignore
+            ]
+        );
+        // other chats aren't affected
+        assert_eq!(limiter.command(2, &cfg(), start), Verdict::Allowed);
+        assert_eq!(limiter.command(1, &cfg(), start + MINUTE), Verdict::Allowed);
+    }
+
+    #[test]
+    fn churn() {
+        let limiter = Limiter::default();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        for secs in 0..3 {
+            assert_eq!(limiter.change(1, &cfg(), at(secs)), Ok(()));
+        }
+        // changes out of the window don't count
+        assert_eq!(limiter.change(1, &cfg(), at(600)), Ok(()));
+        assert_eq!(limiter.change(1, &cfg(), at(601)), Ok(()));
+        assert_eq!(limiter.change(1, &cfg(), at(602)), Ok(()));
+        assert_eq!(
+            limiter.change(1, &cfg(), at(603)),
+            Err(Duration::from_secs(3600))
+        );
+        assert_eq!(
+            limiter.change(1, &cfg(), at(1203)),
+            Err(Duration::from_secs(3000))
+        );
+        assert_eq!(limiter.change(1, &cfg(), at(4203)), Ok(()));
+
+        assert_eq!(
+            blocked_text(Duration::from_secs(3000)),
+            "Error: subscriptions of this chat were changed too often. Try again in 50 minutes."
+        );
+    }
+
+    #[test]
+    fn waits() {
+        let wait = |krate: &str, req: &str| (krate.to_owned(), req.to_owned());
+        let waits = [wait("serde", ">=2.0")];
+        assert_eq!(waits_error(&waits, "tokio", "^1.5", 2), None);
+        let waits = [wait("serde", ">=2.0"), wait("tokio", "^1.5")];
+        assert!(waits_error(&waits, "rand", "^0.9", 2).is_some());
+        // waiting again for the same release doesn't add a wait
+        assert_eq!(waits_error(&waits, "serde", ">=2.0", 2), None);
+        assert_eq!(waits_error(&waits, "rand", "^0.9", 0), None);
+
+        let waits: Vec<_> = (0..MAX_WAITS_PER_CRATE)
+            .map(|minor| wait("serde", &format!(">=1.{}", minor)))
+            .collect();
+        assert!(waits_error(&waits, "serde", ">=2.0", 0).is_some());
+        assert_eq!(waits_error(&waits, "tokio", "^1.5", 0), None);
+    }
+}
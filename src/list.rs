@@ -0,0 +1,157 @@
+//! Paginated `/list`: subscriptions of a chat with buttons to unsubscribe from a crate or
+//! change its filter. Callback data of the buttons carries the page and the `--include-yanked` flag.
+use carapax::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use crate::{cfg::Config, db::Database, filter::Bump, history::button, krate::Versions};
+
+/// Number of subscriptions per page
+pub const PAGE_SIZE: usize = 10;
+
+/// `--include-yanked` flag in callback data
+fn flag(include_yanked: bool) -> u8 {
+    include_yanked as u8
+}
+
+/// Text and keyboard of the `page` (0-based) of subscriptions of the chat
+pub async fn page(
+    db: &Database,
+    cfg: &Config,
+    chat_id: i64,
+    page: usize,
+    include_yanked: bool,
+) -> Result<(String, Option<InlineKeyboardMarkup>), tokio_postgres::Error> {
+    let subscriptions = db.list_subscriptions(chat_id).await?;
+    if subscriptions.is_empty() {
+        return Ok((
+            String::from("Currently you aren't subscribed to anything. Use /subscribe to subscribe to some crate."),
+            None,
+        ));
+    }
+
+    let pages = (subscriptions.len() + PAGE_SIZE - 1) / PAGE_SIZE;
+    let page = page.min(pages - 1);
+    let y = flag(include_yanked);
+    let mut lines = Vec::new();
+    let mut keyboard = Vec::new();
+    for krate in subscriptions.iter().skip(page * PAGE_SIZE).take(PAGE_SIZE) {
+        lines.push(match Versions::read(krate, cfg).await {
+            Ok(versions) => format!("— <code>{}</code> {}", krate, versions.html(include_yanked)),
+            // silently ignore error & just don't add links
+            Err(_) => format!("— <code>{}</code>", krate),
+        });
+
+        let mut row = Vec::new();
+        row.extend(button(
+            &format!("❌ {}", krate),
+            format!("list_unsub {} {} {}", page, y, krate),
+        ));
+        row.extend(button(
+            "⚙ filter",
+            format!("list_filter {} {} {}", page, y, krate),
+        ));
+        keyboard.push(row);
+    }
+
+    let mut navigation = Vec::new();
+    if page > 0 {
+        navigation.extend(button("◀", format!("list {} {}", page - 1, y)));
+    }
+    if page + 1 < pages {
+        navigation.extend(button("▶", format!("list {} {}", page + 1, y)));
+    }
+    keyboard.push(navigation);
+
+    let title = if pages > 1 {
+        format!(
+            "You are currently subscribed to (page {}/{}):",
+            page + 1,
+            pages
+        )
+    } else {
+        String::from("You are currently subscribed to:")
+    };
+    Ok((
+        format!("{}\n{}", title, lines.join("\n")),
+        Some(InlineKeyboardMarkup::from(keyboard)),
+    ))
+}
+
+/// Text and keyboard changing the filter of the subscription, `page` is the page to go back to.
+/// `None` if the chat isn't subscribed to the crate.
+pub async fn filter_page(
+    db: &Database,
+    chat_id: i64,
+    krate: &str,
+    page: usize,
+    include_yanked: bool,
+) -> Result<Option<(String, InlineKeyboardMarkup)>, tokio_postgres::Error> {
+    let filter = match db.get_filter(chat_id, krate).await? {
+        Some(filter) => filter,
+        None => return Ok(None),
+    };
+
+    let y = flag(include_yanked);
+    let set = |text: &str, word: &str| {
+        button(text, format!("list_set {} {} {} {}", page, y, krate, word))
+    };
+    let bump = |bump: Bump| {
+        let text = if filter.min_bump == bump {
+            format!("✓ {}", bump.as_str())
+        } else {
+            bump.as_str().to_owned()
+        };
+        set(&text, bump.as_str())
+    };
+    let bumps: Vec<InlineKeyboardButton> = [Bump::Patch, Bump::Minor, Bump::Major]
+        .iter()
+        .copied()
+        .filter_map(bump)
+        .collect();
+    let prerelease = if filter.skip_prerelease {
+        set("include prereleases", "include-prerelease")
+    } else {
+        set("skip prereleases", "skip-prerelease")
+    };
+    let deps = if filter.show_deps {
+        set("hide dependency changes", "hide-deps")
+    } else {
+        set("show dependency changes", "show-deps")
+    };
+    let readme = if filter.readme {
+        set("hide README changes", "no-readme")
+    } else {
+        set("show README changes", "readme")
+    };
+    let back = button("◀ back", format!("list {} {}", page, y));
+
+    let keyboard = vec![
+        bumps,
+        prerelease.into_iter().collect(),
+        deps.into_iter().collect(),
+        readme.into_iter().collect(),
+        back.into_iter().collect(),
+    ];
+    Ok(Some((
+        format!(
+            "You are notified about {} of <code>{}</code>.",
+            filter, krate
+        ),
+        InlineKeyboardMarkup::from(keyboard),
+    )))
+}
+
+/// Applies a word of [`Filter::WORDS`](crate::filter::Filter::WORDS) to the filter of the subscription
+pub async fn set_filter(
+    db: &Database,
+    chat_id: i64,
+    krate: &str,
+    word: &str,
+) -> Result<(), tokio_postgres::Error> {
+    if let Some(mut filter) = db.get_filter(chat_id, krate).await? {
+        if filter.apply(word) {
+            db.set_filter(chat_id, krate, &filter).await?;
+        }
+    }
+
+    Ok(())
+}
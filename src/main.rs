@@ -1,25 +1,121 @@
-// TODO: somehow better handle rate-limits (https://core.telegram.org/bots/faq#broadcasting-to-users)
-//       maybe concat many messages into one (in channel) + queues to properly handle limits
-use std::sync::Arc;
-
-use arraylib::Slice;
-use carapax::{methods::SendMessage, types::ParseMode, Api};
-use fntools::{self, value::ValueExt};
-use git2::{Delta, Diff, DiffOptions, Repository, Sort};
-use log::info;
-use std::str;
+use std::{
+    cmp,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use carapax::Api;
+use crate_upd_core::{filter, ActionKind, Storage};
+use kacl_parser::VersionScheme;
 use tokio_postgres::NoTls;
+use tracing::{info, Instrument};
+use versions::SemVer;
 
-use crate::{bot::setup, db::Database, krate::Crate, util::tryn};
+use crate::{
+    cfg::{LogFormat, RegistryConfig, SharedConfig},
+    compat::Change,
+    db::{Database, Subscriber},
+    discord::DiscordQueue,
+    filter::Filter,
+    index::{git::GitIndex, sparse::SparseIndex, IndexEvent, IndexKind},
+    krate::{normalize_name, split_key, Crate},
+    matrix::MatrixQueue,
+    notifier::{Notifier, Notifiers},
+    pipeline::Pipeline,
+    send::{Receipt, SendQueue},
+    shutdown::Shutdown,
+    template::Template,
+    train::Trains,
+    translate::{self, Translations},
+    util::http_client,
+    verbosity::Verbosity,
+    workspace::Collector,
+};
 
+mod admin;
+mod api;
+mod bootstrap;
 mod bot;
+mod branches;
+mod cache;
 mod cfg;
+mod changelog;
+mod channels;
+mod cluster;
+mod compare;
+mod compat;
+mod cratesio;
+mod crypt;
 mod db;
+mod deps;
+mod digest;
+mod discord;
+mod email;
+mod extract;
+mod features;
+mod feed;
+mod firehose;
+mod groups;
+#[cfg(test)]
+mod harness;
+mod health;
+mod history;
+mod hooks;
+mod http;
+mod index;
+mod inline;
 mod krate;
+mod late_notes;
+mod license;
+mod limits;
+mod list;
+mod maintenance;
+mod manifest;
+mod matrix;
+mod metrics;
+mod migrate;
+mod msrv;
+mod notifier;
+mod onboarding;
+mod owners;
+mod pipeline;
+mod preview;
+mod privacy;
+mod readme;
+mod releases;
+mod reload;
+mod render;
+mod replay;
+mod repo;
+mod rerender;
+mod rules;
+mod security;
+mod send;
+mod share;
+mod shutdown;
+mod summary;
+mod tags;
+mod template;
+mod threads;
+mod train;
+mod translate;
+mod trending;
 mod util;
+mod verbosity;
+mod waits;
+mod watchlist;
+mod weekly;
+mod why;
+mod workspace;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Id of the next index event, for correlating logs
+static NEXT_EVENT_ID: AtomicU64 = AtomicU64::new(0);
+
 #[tokio::main]
 async fn main() {
     unsafe {
@@ -29,14 +125,23 @@ async fn main() {
         ))
     };
 
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    // runs without the database and the bot
+    if args.first().map(String::as_str) == Some("extract") {
+        std::process::exit(extract::run(&args[1..]).await);
+    }
+
     let config = Arc::new(cfg::Config::read().expect("couldn't read config"));
 
-    simple_logger::SimpleLogger::new()
-        .with_level(config.loglevel)
-        .init()
-        .expect("Failed to initialize logger");
+    // `log` records of dependencies are converted to `tracing` events too
+    let subscriber = tracing_subscriber::fmt().with_max_level(config.tracing_level());
+    match config.log_format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
 
     info!("starting");
+    cache::init(&config.cache);
 
     let db = {
         let (d, conn) = Database::connect(&config.db.cfg(), NoTls)
@@ -46,241 +151,1595 @@ async fn main() {
         // docs says to do so
         tokio::spawn(async move {
             if let Err(e) = conn.await {
-                eprintln!("Database connection error: {}", e);
+                tracing::error!("database connection error: {}", e);
             }
         });
 
         info!("connected to db");
-        d
+        let cipher = crypt::Cipher::from_config(&config).expect("invalid storage key");
+        d.with_cipher(cipher)
     };
 
-    let index_url = &config.index_url; // Closures still borrow full struct :|
-    let index_path = &config.index_path;
-    let repo = Repository::open(index_path).unwrap_or_else(move |_| {
-        info!("start cloning");
-        Repository::clone(&index_url, index_path)
-            .unwrap()
-            .also(|_| info!("cloning finished"))
-    });
+    // Operator subcommands
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["export-chat", chat_id] => {
+            let chat_id = chat_id.parse().expect("invalid chat id");
+            let bundle = migrate::export(&db, &config, chat_id)
+                .await
+                .expect("couldn't export chat");
+            println!("{}", bundle);
+            return;
+        }
+        ["import-chat", path] => {
+            let bundle = std::fs::read_to_string(path).expect("couldn't read bundle");
+            let bundle = migrate::import(&db, &config, &bundle)
+                .await
+                .expect("couldn't import chat");
+            info!(
+                "imported {} subscriptions of chat {}",
+                bundle.subscriptions(),
+                bundle.chat_id
+            );
+            return;
+        }
+        ["encrypt-storage"] => {
+            let cipher = crypt::Cipher::from_config(&config)
+                .expect("invalid storage key")
+                .expect("`storage_key` (or CRATE_UPD_BOT_STORAGE_KEY) must be set to encrypt");
+            let encrypted = crypt::encrypt_storage(&db, &cipher)
+                .await
+                .expect("couldn't encrypt the storage");
+            info!("encrypted {} e-mail addresses", encrypted);
+            return;
+        }
+        ["preview", ref rest @ ..] => {
+            preview::run(rest, &db, &config).await;
+            return;
+        }
+        ["bootstrap"] => {
+            bootstrap::run(&db, &config, &Shutdown::listen()).await;
+            return;
+        }
+        ["events", "replay", ref rest @ ..] => {
+            replay::run(rest, &db, config).await;
+            return;
+        }
+        [] => {}
+        _ => panic!("unknown arguments: {:?}", args),
+    }
 
+    lazy_static::initialize(&metrics::STARTED);
     let bot = Api::new(carapax::Config::new(&config.bot_token)).expect("Can't crate Api");
+    let queue = SendQueue::start(bot.clone(), Arc::clone(&config), db.clone());
+    let notifiers = Notifiers {
+        telegram: queue.clone(),
+        matrix: MatrixQueue::start(Arc::clone(&config)),
+        discord: DiscordQueue::start(Arc::clone(&config)),
+        jobs: config.cluster.is_some(),
+        collector: None,
+    };
+    let shutdown = Shutdown::listen();
+    let worker = tokio::spawn(cluster::work(
+        db.clone(),
+        queue.clone(),
+        Arc::clone(&config),
+        shutdown.clone(),
+    ));
 
-    let lp = setup(bot.clone(), db.clone(), Arc::clone(&config));
-    tokio::spawn(lp.run());
+    // other instances of the cluster only send delivery jobs
+    if config
+        .cluster
+        .as_ref()
+        .map_or(false, |cluster| !cluster.watch)
+    {
+        let _ = worker.await;
+        info!("stopped");
+        return;
+    }
 
-    loop {
-        log::info!("start pulling updates");
-        pull(&repo, &bot, &db, &config).await.expect("pull failed");
-        log::info!("pulling updates finished");
+    let shared = SharedConfig::new(Arc::clone(&config));
+    let (pipeline, lanes) = Pipeline::start(
+        &config.pipeline,
+        notifiers,
+        db.clone(),
+        shared.clone(),
+        shutdown.clone(),
+    );
+    {
+        let shared = shared.clone();
+        std::thread::spawn(move || reload::watch(shared));
+    }
 
-        tokio::time::delay_for(config.pull_delay).await; // delay for 5 min
+    tokio::spawn(late_notes::run(bot.clone(), db.clone(), shared.clone()));
+    tokio::spawn(bot::run(bot, db.clone(), shared.clone(), queue.clone()));
+    tokio::spawn(digest::run(queue.clone(), db.clone()));
+    tokio::spawn(weekly::run(queue.clone(), db.clone(), shared.clone()));
+    tokio::spawn(owners::run(db.clone()));
+    tokio::spawn(tags::run(db.clone()));
+    tokio::spawn(releases::run(shared.clone()));
+    if config.metrics.enabled {
+        tokio::spawn(metrics::serve(config.metrics.listen, db.clone()));
+    }
+    if http::is_enabled(&config) {
+        tokio::spawn(http::serve(db.clone(), Arc::clone(&config)));
+    }
+    if config.email.is_some() {
+        tokio::spawn(email::run(db.clone(), Arc::clone(&config)));
     }
+    if config.trending.is_some() {
+        tokio::spawn(trending::run(
+            queue.clone(),
+            db.clone(),
+            Arc::clone(&config),
+        ));
+    }
+
+    // entries of the crates.io index known to the bot are seeded before watching it
+    bootstrap::run(&db, &config, &shutdown).await;
+    let watchers: Vec<_> = config
+        .registries
+        .iter()
+        .map(|registry| {
+            tokio::spawn(watch(
+                Some(Arc::clone(registry)),
+                pipeline.clone(),
+                db.clone(),
+                shared.clone(),
+                shutdown.clone(),
+            ))
+        })
+        .collect();
+    watch(None, pipeline, db, shared, shutdown).await;
+    for watcher in watchers {
+        let _ = watcher.await;
+    }
+    futures::future::join_all(lanes).await;
+    let _ = worker.await;
+
+    info!("sending queued messages");
+    queue.flush().await;
+    info!("stopped");
 }
 
-// from https://stackoverflow.com/a/58778350
-fn fast_forward(repo: &Repository, commit: &git2::Commit) -> Result<(), git2::Error> {
-    let fetch_commit = repo.find_annotated_commit(commit.id())?;
-    let analysis = repo.merge_analysis(&[&fetch_commit])?;
-    if analysis.0.is_up_to_date() {
-        Ok(())
-    } else if analysis.0.is_fast_forward() {
-        let mut reference = repo.find_reference("refs/heads/master")?;
-        reference.set_target(fetch_commit.id(), "Fast-Forward")?;
-        repo.set_head(reference.name().unwrap())?;
-        repo.checkout_head(Some(git2::build::CheckoutBuilder::default().force()))
-    } else {
-        Err(git2::Error::from_str("Fast-forward only!"))
+/// Watches the index of crates.io or of an alternative registry until shutdown.
+/// Each poll uses the current config, the index itself is set up once.
+async fn watch(
+    registry: Option<Arc<RegistryConfig>>,
+    mut pipeline: Pipeline,
+    db: Database,
+    shared: SharedConfig,
+    mut shutdown: Shutdown,
+) {
+    let cfg = shared.get();
+    let name = registry
+        .as_ref()
+        .map_or("crates.io", |registry| registry.name.as_str())
+        .to_owned();
+    match registry
+        .as_ref()
+        .map_or(cfg.index.kind, |registry| registry.kind)
+    {
+        IndexKind::Git => {
+            let index = match &registry {
+                Some(r) => GitIndex::open_or_clone(&r.index_url, &r.index_path(), registry.clone()),
+                None => GitIndex::open_or_clone(&cfg.index_url, &cfg.index_path, None),
+            };
+            let prefix = registry.as_ref().map(|registry| registry.name.as_str());
+            let mut trains = Trains::default();
+            resume_pending(prefix, &mut trains, &mut pipeline, &db, &cfg).await;
+
+            loop {
+                tracing::info!("start pulling updates of {}", name);
+                let timer = metrics::INDEX_POLL_DURATION
+                    .with_label_values(&[&name, "git"])
+                    .start_timer();
+                let cfg = shared.get();
+                pull(
+                    &index,
+                    &name,
+                    &mut trains,
+                    &mut pipeline,
+                    &db,
+                    &cfg,
+                    &shutdown,
+                )
+                .await
+                .expect("pull failed");
+                depart(&mut trains, &mut pipeline, &cfg).await;
+                timer.observe_duration();
+                health::polled();
+                tracing::info!("pulling updates of {} finished", name);
+
+                tokio::select! {
+                    _ = tokio::time::delay_for(cfg.pull_delay) => {} // delay for 5 min
+                    _ = shutdown.requested() => {}
+                }
+                if shutdown.is_requested() {
+                    return;
+                }
+            }
+        }
+        IndexKind::Sparse => {
+            let url = match &registry {
+                Some(registry) => registry.index_url.clone(),
+                None => cfg.index.sparse_url.clone(),
+            };
+            let mut index = SparseIndex::new(url, registry.clone());
+            let mut trains = Trains::default();
+
+            loop {
+                tracing::info!("start polling sparse index of {}", name);
+                let timer = metrics::INDEX_POLL_DURATION
+                    .with_label_values(&[&name, "sparse"])
+                    .start_timer();
+                let cfg = shared.get();
+                let prefix = registry.as_ref().map(|registry| registry.name.as_str());
+                pull_sparse(
+                    &mut index,
+                    prefix,
+                    &name,
+                    &mut trains,
+                    &mut pipeline,
+                    &db,
+                    &cfg,
+                    &shutdown,
+                )
+                .await;
+                depart(&mut trains, &mut pipeline, &cfg).await;
+                timer.observe_duration();
+                health::polled();
+                tracing::info!("polling sparse index of {} finished", name);
+
+                tokio::select! {
+                    _ = tokio::time::delay_for(cfg.pull_delay) => {}
+                    _ = shutdown.requested() => {}
+                }
+                if shutdown.is_requested() {
+                    return;
+                }
+            }
+        }
     }
 }
 
-async fn pull(
-    repo: &Repository,
-    bot: &Api,
-    db: &Database,
+/// Announces releases left pending by the previous run (e.g. queued in the pipeline or held in a
+/// train) again, new versions are held in their trains if `train_window_secs` is set. Only the git
+/// index needs it, the sparse one finds them by comparing with the recorded releases.
+async fn resume_pending<S: Storage>(
+    prefix: Option<&str>,
+    trains: &mut Trains,
+    pipeline: &mut Pipeline,
+    db: &S,
     cfg: &cfg::Config,
-) -> Result<(), git2::Error> {
-    // fetch changes from remote index
-    repo.find_remote("origin")
-        .expect("couldn't find 'origin' remote")
-        .fetch(&["master"], None, None)
-        .expect("couldn't fetch new version of the index");
-
-    let mut walk = repo.revwalk()?;
-    walk.push_range("HEAD~1..FETCH_HEAD")?;
-    walk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
-    let commits: Result<Vec<_>, _> = walk.map(|oid| repo.find_commit(oid?)).collect();
-    let mut opts = DiffOptions::default();
-    let opts = opts.context_lines(0).minimal(true);
-    for [prev, next] in Slice::array_windows::<[_; 2]>(&commits?[..]) {
-        if next.author().name() != Some("bors") {
-            log::warn!(
-                "Skip commit#{} from non-bors user@{}: {}",
-                next.id(),
-                next.author().name().unwrap_or("<invalid utf-8>"),
-                next.message()
-                    .unwrap_or("<invalid utf-8>")
-                    .trim_end_matches('\n'),
-            );
-
-            continue;
+) {
+    let pending = db
+        .pending_releases()
+        .await
+        .map_err(|err| tracing::error!("db error while getting pending releases: {}", err))
+        .unwrap_or_default();
+    for (key, version, action, published_at) in pending {
+        let kind = match ActionKind::parse(&action) {
+            Some(kind) if split_key(&key).0 == prefix => kind,
+            _ => continue,
+        };
+        let krate = Crate::read_all(&key, cfg)
+            .await
+            .map_err(|err| tracing::warn!("couldn't read versions of {}: {}", key, err))
+            .ok()
+            .and_then(|all| all.into_iter().find(|krate| krate.id.vers == version));
+        let event = match krate {
+            // the firehose (`/subscribe_new`) isn't resumed
+            Some(krate) => IndexEvent {
+                krate,
+                kind,
+                published_at,
+                created: false,
+            },
+            None => continue,
+        };
+        if matches!(event.kind, ActionKind::NewVersion) && cfg.train_window_secs > 0 {
+            trains.push(event);
+        } else {
+            pipeline.submit(vec![event]).await;
         }
+    }
+}
 
-        let diff: Diff =
-            repo.diff_tree_to_tree(Some(&prev.tree()?), Some(&next.tree()?), Some(opts))?;
-        let (krate, action) = diff_one(diff)?;
-        notify(krate, action, bot, db, cfg).await;
-        fast_forward(repo, next)?;
-        // Try to prevent "too many requests" error from telegram
-        tokio::time::delay_for(cfg.update_delay_millis.into()).await;
+/// Announces trains which haven't got new versions for `train_window_secs` and groups of
+/// crates of a repository which haven't got them for `[workspace] window_secs`
+async fn depart(trains: &mut Trains, pipeline: &mut Pipeline, cfg: &cfg::Config) {
+    for train in trains.departed(Duration::from_secs(cfg.train_window_secs)) {
+        pipeline.submit(train).await;
+    }
+    pipeline.depart().await;
+}
+
+/// Handles new commits of the git index, acknowledging each one after its releases are recorded
+/// and queued in the pipeline (or held in a version train). Stops early on shutdown, releases left
+/// pending are announced after a restart.
+#[tracing::instrument(
+    name = "index_poll",
+    skip(index, trains, pipeline, db, cfg, shutdown),
+    fields(kind = "git")
+)]
+async fn pull<S: Storage>(
+    index: &GitIndex,
+    registry: &str,
+    trains: &mut Trains,
+    pipeline: &mut Pipeline,
+    db: &S,
+    cfg: &cfg::Config,
+    shutdown: &Shutdown,
+) -> Result<(), git2::Error> {
+    for (commit, event) in index.fetch()? {
+        if shutdown.is_requested() {
+            break;
+        }
+        handle_event(event, trains, pipeline, db, cfg).await;
+        index.ack(commit)?;
+        health::committed();
     }
 
     Ok(())
 }
 
-enum ActionKind {
-    NewVersion,
-    Yanked,
-    Unyanked,
-}
+/// Polls files of all crates with subscribers in the sparse index of crates.io
+/// (`prefix` is `None`) or of the alternative registry. Stops early on shutdown, releases
+/// which aren't handled are found after a restart by comparing with the recorded ones.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "index_poll",
+    skip(index, prefix, trains, pipeline, db, cfg, shutdown),
+    fields(kind = "sparse")
+)]
+async fn pull_sparse<S: Storage>(
+    index: &mut SparseIndex,
+    prefix: Option<&str>,
+    registry: &str,
+    trains: &mut Trains,
+    pipeline: &mut Pipeline,
+    db: &S,
+    cfg: &cfg::Config,
+    shutdown: &Shutdown,
+) {
+    let krates = db
+        .list_subscribed_crates()
+        .await
+        .map_err(|err| tracing::error!("db error while getting subscribed crates: {}", err))
+        .unwrap_or_default();
 
-fn diff_one(diff: Diff) -> Result<(Crate, ActionKind), git2::Error> {
-    let mut prev = None;
-    let mut next = None;
+    for key in krates {
+        let name = match split_key(&key) {
+            (of, name) if of == prefix => name,
+            _ => continue,
+        };
+        if shutdown.is_requested() {
+            return;
+        }
+        // the first poll after a start compares with the releases recorded before
+        let recorded = if index.is_cached(name) {
+            None
+        } else {
+            db.release_states(&key)
+                .await
+                .map_err(|err| tracing::error!("db error while getting recorded releases: {}", err))
+                .ok()
+        };
+        let events = match index.poll(&name, recorded.as_deref()).await {
+            Ok(events) => events,
+            Err(err) => {
+                tracing::warn!("couldn't poll {} in the sparse index: {}", name, err);
+                continue;
+            }
+        };
 
-    diff.foreach(
-        &mut |_, _| true,
-        None,
-        None,
-        Some(&mut |delta, _hunk, line| {
-            match delta.status() {
-                // New version of a crate or (un)yanked old version
-                Delta::Modified | Delta::Added => {
-                    assert!(delta.nfiles() == 2 || delta.nfiles() == 1);
-                    match line.origin() {
-                        '-' => {
-                            assert!(
-                                prev.is_none(),
-                                "Expected number of deletions <= 1 per commit"
-                            );
-                            let krate = str::from_utf8(line.content()).expect("non-utf8 diff");
-                            let krate = serde_json::from_str::<Crate>(krate)
-                                .expect("cound't deserialize crate");
-
-                            prev = Some(krate);
-                        }
-                        '+' => {
-                            assert!(
-                                next.is_none(),
-                                "Expected number of additions = 1 per commit"
-                            );
-                            let krate = str::from_utf8(line.content()).expect("non-utf8 diff");
-                            let krate = serde_json::from_str::<Crate>(krate)
-                                .expect("cound't deserialize crate");
-
-                            next = Some(krate);
-                        }
-                        _ => { /* don't care */ }
-                    }
-                }
-                delta => {
-                    log::warn!("Unexpected delta: {:?}", delta);
-                }
+        for event in events {
+            if shutdown.is_requested() {
+                return;
+            }
+            handle_event(event, trains, pipeline, db, cfg).await;
+            health::committed();
+        }
+    }
+}
+
+/// Records the release and queues it in the pipeline, new versions are held in their trains if
+/// `train_window_secs` is set. The release stays pending in the database until its telegram
+/// notifications are sent, so after a crash it's handled again (chats which got the
+/// notification are skipped).
+async fn handle_event<S: Storage>(
+    event: IndexEvent,
+    trains: &mut Trains,
+    pipeline: &mut Pipeline,
+    db: &S,
+    cfg: &cfg::Config,
+) {
+    // messages sent because of the event are logged inside of its span, `id` correlates them
+    let key = event.krate.key();
+    let span = tracing::info_span!(
+        "event",
+        id = NEXT_EVENT_ID.fetch_add(1, Ordering::Relaxed),
+        krate = %key,
+        version = %event.krate.id.vers,
+        action = event.kind.as_str(),
+    );
+    async move {
+        metrics::EVENTS
+            .with_label_values(&[event.kind.as_str()])
+            .inc();
+        let seen = db
+            .see_version(
+                &key,
+                &event.krate.id.vers,
+                event.krate.yanked,
+                &event.krate.content_hash(),
+            )
+            .await
+            .unwrap_or_else(|err| {
+                tracing::error!("db error while recording the index entry: {}", err);
+                None
+            });
+        // the index rewrote the entry without changing the version or its yanked status
+        match (&event.kind, seen) {
+            (ActionKind::NewVersion, Some(_)) => {
+                tracing::info!("the version was seen before, not announced again");
+                return;
             }
+            (ActionKind::Yanked, Some(true)) | (ActionKind::Unyanked, Some(false)) => {
+                tracing::info!("the yanked status hasn't changed, not announced again");
+                return;
+            }
+            _ => {}
+        }
 
-            true
-        }),
-    )?;
+        db.record_release(
+            &key,
+            &event.krate.id.vers,
+            event.krate.yanked,
+            event.published_at,
+            event.kind,
+        )
+        .await
+        .unwrap_or_else(|err| tracing::error!("db error while recording release: {}", err));
 
-    let next = next.expect("Expected number of additions = 1 per commit");
-    match (prev.as_ref().map(|c| c.yanked), next.yanked) {
-        /* was yanked, is yanked */
-        (None, false) => {
-            // There were no deleted line & crate is not yanked.
-            // New version.
-            Ok((next, ActionKind::NewVersion))
+        if matches!(event.kind, ActionKind::NewVersion) && cfg.train_window_secs > 0 {
+            tracing::debug!("held in the version train");
+            trains.push(event);
+            return;
         }
-        (Some(false), true) => {
-            // The crate was not yanked and now is yanked.
-            // Crate yanked.
-            Ok((next, ActionKind::Yanked))
+        // e.g. a yank of a version of the train comes after the train
+        if let Some(train) = trains.take(&key) {
+            pipeline.submit(train).await;
         }
-        (Some(true), false) => {
-            // The crate was yanked and now is not yanked.
-            // Crate unyanked.
-            Ok((next, ActionKind::Unyanked))
+        pipeline.submit(vec![event]).await;
+    }
+    .instrument(span)
+    .await;
+}
+
+/// Notifies about the releases of a train (oldest first, usually one release) in one message and
+/// finishes them once the message is sent
+async fn announce(train: Vec<IndexEvent>, notifiers: &Notifiers, db: &Database, cfg: &cfg::Config) {
+    if let Some(released) = notify_train(train, notifiers, db, cfg).await {
+        finish(&[released], notifiers, db, cfg).await;
+    }
+}
+
+/// Notifies about the releases of crates of the repository, a chat following several of the
+/// crates gets one message about all of them (see `workspace`)
+async fn announce_workspace(
+    repository: &str,
+    trains: Vec<Vec<IndexEvent>>,
+    notifiers: &Notifiers,
+    db: &Database,
+    cfg: &cfg::Config,
+) {
+    tracing::info!(
+        "announcing {} crates of {} at once",
+        trains.len(),
+        repository
+    );
+    let collector = Collector::default();
+    let collecting = Notifiers {
+        collector: Some(collector.clone()),
+        ..notifiers.clone()
+    };
+    let mut released = Vec::new();
+    for train in trains {
+        released.extend(notify_train(train, &collecting, db, cfg).await);
+    }
+    workspace::deliver(repository, &collector, notifiers, db).await;
+    finish(&released, notifiers, db, cfg).await;
+}
+
+/// Notifies about the releases of a train in one message, returns the key of the crate and the
+/// versions of the train
+async fn notify_train(
+    mut train: Vec<IndexEvent>,
+    notifiers: &Notifiers,
+    db: &Database,
+    cfg: &cfg::Config,
+) -> Option<(String, Vec<String>)> {
+    // the first version of a new crate goes to the firehose, even if the train has later ones
+    if let Some(first) = train.first().filter(|event| event.created) {
+        let krate = &first.krate;
+        if krate.registry.is_none() && !cfg.ban.crates.contains(krate.id.name.as_str()) {
+            firehose::announce(krate, notifiers, db).await;
         }
-        _unexpected => {
-            // Something unexpected happened
-            log::warn!("Unexpected diff_one input: {:?}, {:?}", next, prev);
-            Err(git2::Error::from_str("Unexpected diff"))
+    }
+    let IndexEvent {
+        krate,
+        kind,
+        published_at,
+        ..
+    } = train.pop()?;
+    let earlier: Vec<Crate> = train.into_iter().map(|event| event.krate).collect();
+    let key = krate.key();
+    let versions: Vec<String> = earlier
+        .iter()
+        .chain(Some(&krate))
+        .map(|krate| krate.id.vers.clone())
+        .collect();
+    if !earlier.is_empty() {
+        tracing::info!("announcing {} {} releases at once", key, versions.len());
+    }
+    notify(krate, kind, published_at, &earlier, notifiers, db, cfg).await;
+    Some((key, versions))
+}
+
+/// Finishes the released versions of crates once their messages are sent
+async fn finish(
+    released: &[(String, Vec<String>)],
+    notifiers: &Notifiers,
+    db: &Database,
+    cfg: &cfg::Config,
+) {
+    // a release or a yank may change the latest release and its dependencies
+    for (key, _) in released {
+        groups::refresh(key, db, cfg).await;
+        releases::update(key, cfg).await;
+    }
+
+    // delivery jobs outlive the process, there's nothing to wait for
+    if !notifiers.jobs {
+        notifiers.telegram.flush().await;
+    }
+    for (key, versions) in released {
+        for version in versions {
+            db.finish_release(key, version)
+                .await
+                .unwrap_or_else(|err| tracing::error!("db error while finishing release: {}", err));
         }
     }
+    // Try to prevent "too many requests" error from telegram
+    tokio::time::delay_for(cfg.update_delay_millis.into()).await;
 }
 
-async fn notify(krate: Crate, action: ActionKind, bot: &Api, db: &Database, cfg: &cfg::Config) {
-    let message = match action {
+/// Text of the notification about `action` on `krate`
+fn action_message(action: &ActionKind, krate: &Crate) -> String {
+    match action {
         ActionKind::NewVersion => format!(
             "Crate was updated: <code>{krate}#{version}</code> {links}",
-            krate = krate.id.name,
+            krate = krate.key(),
             version = krate.id.vers,
             links = krate.html_links(),
         ),
         ActionKind::Yanked => format!(
-            "Crate was yanked: <code>{krate}#{version}</code> {links}",
-            krate = krate.id.name,
+            "⚠ <code>{krate} {version}</code> was yanked {links}",
+            krate = krate.key(),
             version = krate.id.vers,
             links = krate.html_links(),
         ),
         ActionKind::Unyanked => format!(
-            "Crate was unyanked: <code>{krate}#{version}</code> {links}",
-            krate = krate.id.name,
+            "<code>{krate} {version}</code> was unyanked {links}",
+            krate = krate.key(),
             version = krate.id.vers,
             links = krate.html_links(),
         ),
+    }
+}
+
+/// Release notes of new versions, if they are enabled and found
+async fn release_notes(
+    krate: &Crate,
+    action: &ActionKind,
+    earlier: &[Crate],
+    previous: Option<&SemVer>,
+    cfg: &cfg::Config,
+) -> Option<String> {
+    // changelogs are found via crates.io metadata
+    if cfg.fetch_changelogs && krate.registry.is_none() && matches!(action, ActionKind::NewVersion)
+    {
+        let versions: Vec<&str> = earlier
+            .iter()
+            .chain(Some(krate))
+            .map(|krate| krate.id.vers.as_str())
+            .collect();
+        changelog::release_notes(&krate.id.name, &versions, previous, cfg).await
+    } else {
+        None
+    }
+}
+
+/// Compare view of release tags of a new version, if it's enabled and the tags are found
+async fn source_diff(
+    krate: &Crate,
+    action: &ActionKind,
+    previous: Option<&SemVer>,
+    cfg: &cfg::Config,
+) -> Option<String> {
+    // repositories are found via crates.io metadata
+    match (action, previous) {
+        (ActionKind::NewVersion, Some(previous))
+            if cfg.source_diffs
+                && krate.registry.is_none()
+                && !cfg.crates.is_ignored(&krate.id.name) =>
+        {
+            repo::source_diff(
+                &krate.id.name,
+                &previous.to_string(),
+                &krate.id.vers,
+                cfg.crates.tag_format(&krate.id.name),
+                cfg.github_token.as_deref(),
+            )
+            .await
+        }
+        _ => None,
+    }
+}
+
+/// Feature changes of a new version compared to the previous release, as telegram html
+fn feature_changes(krate: &Crate, action: &ActionKind, previous: Option<&Crate>) -> Option<String> {
+    match (action, previous) {
+        (ActionKind::NewVersion, Some(previous)) => features::diff(previous, krate).html(),
+        _ => None,
+    }
+}
+
+/// Warning about a new version raising the MSRV of the previous release, as telegram html
+fn msrv_change(krate: &Crate, action: &ActionKind, previous: Option<&Crate>) -> Option<String> {
+    match (action, previous) {
+        (ActionKind::NewVersion, Some(previous)) => msrv::raised(previous, krate),
+        _ => None,
+    }
+}
+
+/// Dependency changes of a new version compared to the previous release, as telegram html
+fn dependency_changes(
+    krate: &Crate,
+    action: &ActionKind,
+    previous: Option<&Crate>,
+) -> Option<String> {
+    match (action, previous) {
+        (ActionKind::NewVersion, Some(previous)) => deps::diff(previous, krate).html(),
+        _ => None,
+    }
+}
+
+/// Warning about a new version changing the license of the previous release, as telegram html
+async fn license_change(
+    krate: &Crate,
+    action: &ActionKind,
+    previous: Option<&Crate>,
+) -> Option<String> {
+    let previous = match (action, previous, &krate.registry) {
+        (ActionKind::NewVersion, Some(previous), None) => previous,
+        _ => return None,
     };
 
-    let users = db
-        .list_subscribers(&krate.id.name)
+    let client = http_client()
+        .map_err(|err| tracing::error!("couldn't create http client: {}", err))
+        .ok()?;
+    let (old, new) = cratesio::licenses(&client, &krate.id.name, &previous.id.vers, &krate.id.vers)
         .await
-        .map_err(|err| log::error!("db error while getting subscribers: {}", err))
-        .unwrap_or_default();
+        .map_err(|err| tracing::warn!("couldn't get licenses of {}: {}", krate.id.name, err))
+        .ok()??;
+    license::changed(old.as_deref(), new.as_deref())
+}
+
+/// Alerts about a suspicious new version (see [`security`]) as telegram html, for chats with
+/// `/security_alerts on`. Only crates.io crates are checked, `takeover` is the version's
+/// [`maintainer_change`](maintenance::maintainer_change).
+async fn security_alerts(
+    krate: &Crate,
+    action: &ActionKind,
+    previous: Option<&Crate>,
+    takeover: Option<(&str, i64)>,
+    db: &Database,
+) -> Option<String> {
+    if krate.registry.is_some() || !matches!(action, ActionKind::NewVersion) {
+        return None;
+    }
+
+    let lookalike = match previous {
+        Some(_) => None,
+        None => db
+            .top_crates(security::POPULAR_CRATES)
+            .await
+            .map_err(|err| tracing::error!("db error while getting popular crates: {}", err))
+            .ok()
+            .and_then(|top| {
+                let names: Vec<String> = top.into_iter().map(|(name, _)| name).collect();
+                security::lookalike(&krate.id.name, &names).map(str::to_owned)
+            }),
+    };
+    let versions = previous.and_then(|previous| {
+        Some((
+            SemVer::new(&previous.id.vers)?,
+            SemVer::new(&krate.id.vers)?,
+        ))
+    });
+    let jump = versions
+        .as_ref()
+        .map(|(previous, version)| (previous, version))
+        .filter(|(previous, version)| security::version_jump(previous, version));
+    security::html(lookalike.as_deref(), jump, takeover)
+}
+
+/// New publisher of a new version of a crates.io crate after years without releases and the
+/// years (see [`maintenance`])
+async fn maintainer_change(
+    krate: &Crate,
+    action: &ActionKind,
+    previous: Option<&Crate>,
+) -> Option<(String, i64)> {
+    if krate.registry.is_some() || previous.is_none() || !matches!(action, ActionKind::NewVersion) {
+        return None;
+    }
+
+    let client = http_client()
+        .map_err(|err| tracing::error!("couldn't create http client: {}", err))
+        .ok()?;
+    let publishes = cratesio::publishes(&client, &krate.id.name, &krate.id.vers)
+        .await
+        .map_err(|err| tracing::warn!("couldn't get publishes of {}: {}", krate.id.name, err))
+        .ok()?;
+    maintenance::maintainer_change(&publishes, &krate.id.vers)
+        .map(|(publisher, years)| (publisher.to_owned(), years))
+}
+
+/// Note about a new version of a crates.io crate saying that the crate is deprecated, in its
+/// description or in lines new to its README, as telegram html (see [`maintenance`])
+async fn deprecation(
+    krate: &Crate,
+    action: &ActionKind,
+    previous: Option<&Crate>,
+) -> Option<String> {
+    let previous = match (action, previous) {
+        (ActionKind::NewVersion, Some(previous)) if krate.registry.is_none() => previous,
+        _ => return None,
+    };
+
+    let client = http_client()
+        .map_err(|err| tracing::error!("couldn't create http client: {}", err))
+        .ok()?;
+    let description = cratesio::description(&client, &krate.id.name, &krate.id.vers)
+        .await
+        .map_err(|err| tracing::warn!("couldn't get description of {}: {}", krate.id.name, err))
+        .ok()
+        .flatten();
+    if let Some(description) = description.filter(|d| maintenance::is_deprecation(d)) {
+        return Some(maintenance::deprecation_html(&description));
+    }
+
+    let old = readme::lines(&client, previous).await.unwrap_or_default();
+    let new = readme::lines(&client, krate).await?;
+    maintenance::newly_deprecated(&old, &new).map(maintenance::deprecation_html)
+}
 
-    if let Some(ch) = cfg.channel {
-        if !cfg.ban.crates.contains(krate.id.name.as_str()) {
-            notify_inner(bot, ch, &message, cfg, &krate, true).await;
+/// Note about a materially changed README of a new version, as telegram html
+async fn readme_change(
+    krate: &Crate,
+    action: &ActionKind,
+    previous: Option<&Crate>,
+) -> Option<String> {
+    let previous = match (action, previous) {
+        (ActionKind::NewVersion, Some(previous)) => previous,
+        _ => return None,
+    };
+
+    let client = http_client()
+        .map_err(|err| tracing::error!("couldn't create http client: {}", err))
+        .ok()?;
+    readme::change(&client, previous, krate).await
+}
+
+/// License change, deprecation and maintainer change of a new version (see [`license_change`],
+/// [`deprecation`] and [`maintainer_change`]), looked up concurrently. Nothing is looked up unless
+/// the notes are `shown` to some chat, except the maintainer change needed by security `alerts`.
+async fn maintenance_notes(
+    krate: &Crate,
+    action: &ActionKind,
+    previous: Option<&Crate>,
+    shown: bool,
+    alerts: bool,
+) -> (Option<String>, Option<String>, Option<(String, i64)>) {
+    let license_note = async {
+        if shown {
+            license_change(krate, action, previous).await
+        } else {
+            None
+        }
+    };
+    let deprecation_note = async {
+        if shown {
+            deprecation(krate, action, previous).await
+        } else {
+            None
         }
+    };
+    let takeover = async {
+        if shown || alerts {
+            maintainer_change(krate, action, previous).await
+        } else {
+            None
+        }
+    };
+    futures::join!(license_note, deprecation_note, takeover)
+}
+
+/// Warning about a new version of a crates.io crate on a branch its maintainers declared EOL, as
+/// telegram html (see [`branches`])
+async fn branch_eol(krate: &Crate, action: &ActionKind) -> Option<String> {
+    if krate.registry.is_some() || !matches!(action, ActionKind::NewVersion) {
+        return None;
     }
 
-    for chat_id in users {
-        notify_inner(bot, chat_id, &message, cfg, &krate, false).await;
+    let client = http_client()
+        .map_err(|err| tracing::error!("couldn't create http client: {}", err))
+        .ok()?;
+    let manifest = branches::fetch(&client, &krate.id.name).await?;
+    match manifest.status(&krate.id.vers)? {
+        (branch, branches::Status::Eol) => Some(branches::eol_html(branch)),
+        (_, branches::Status::Maintained) => None,
     }
 }
 
-async fn notify_inner(
-    bot: &Api,
-    chat_id: i64,
-    msg: &str,
-    cfg: &cfg::Config,
+/// Lines shown between the first line of a notification and the release notes
+fn details(lines: &[Option<&str>]) -> Option<String> {
+    let lines: Vec<&str> = lines.iter().flatten().copied().collect();
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join("\n"))
+    }
+}
+
+/// crates.io metadata of a new version as telegram html, for verbose chats
+async fn metadata(krate: &Crate, action: &ActionKind) -> Option<String> {
+    if krate.registry.is_some() || !matches!(action, ActionKind::NewVersion) {
+        return None;
+    }
+
+    let client = http_client()
+        .map_err(|err| tracing::error!("couldn't create http client: {}", err))
+        .ok()?;
+    cratesio::metadata(&client, &krate.id.name, &krate.id.vers)
+        .await
+        .map_err(|err| tracing::warn!("couldn't get metadata of {}: {}", krate.id.name, err))
+        .ok()?
+        .map(|metadata| metadata.html())
+}
+
+/// Text of the notification. New versions are rendered with `template` if there is one,
+/// otherwise the diff link and release notes are appended to the default text.
+/// `details` (feature and dependency changes, metadata) go after the first line or the rendered template,
+/// `headline` (the summary of release notes) goes before everything.
+#[allow(clippy::too_many_arguments)]
+fn notification_text(
     krate: &Crate,
-    quiet: bool,
-) {
-    tryn(5, cfg.retry_delay.0, || {
-        bot.execute(
-            SendMessage::new(chat_id, msg)
-                .parse_mode(ParseMode::Html)
-                .disable_web_page_preview(true)
-                .disable_notification(quiet),
-        )
-    })
-    .await
-    .map(drop)
-    .unwrap_or_else(|err| {
-        log::error!(
-            "error while trying to send notification about {:?} to {}: {}",
+    action: &ActionKind,
+    releases: usize,
+    template: Option<&Template>,
+    notes: Option<&str>,
+    headline: Option<&str>,
+    previous: Option<&SemVer>,
+    source_diff: Option<&str>,
+    details: Option<&str>,
+) -> String {
+    let span = tracing::debug_span!("render", templated = template.is_some());
+    let _enter = span.enter();
+    let change = match (action, previous, SemVer::new(&krate.id.vers)) {
+        (ActionKind::NewVersion, Some(previous), Some(version)) => {
+            Some(Change::between(previous, &version))
+        }
+        _ => None,
+    };
+    let previous = previous.map(ToString::to_string);
+    let reserved = headline.map_or(0, |headline| render::text_len(headline) + 1);
+    let message = match (action, template) {
+        (ActionKind::NewVersion, Some(template)) => {
+            let mut vars = template::Vars {
+                krate,
+                previous: previous.as_deref(),
+                changelog: None,
+                source_diff,
+                change,
+            };
+            // the notes get the space left by the rest of the message
+            let rest = render::text_len(&template.render(&vars))
+                + details.map_or(0, |details| render::text_len(details) + 2)
+                + reserved;
+            let budget = render::MAX_LENGTH.saturating_sub(rest);
+            let notes = notes.map(|notes| verbosity::truncate(notes, budget).0);
+            vars.changelog = notes.as_deref();
+            let mut message = template.render(&vars);
+            if let Some(details) = details {
+                message.push_str("\n\n");
+                message.push_str(details);
+            }
+            message
+        }
+        _ => {
+            let mut message = match (action, &previous) {
+                (ActionKind::NewVersion, Some(previous)) if releases > 1 => format!(
+                    "Crate was updated: <code>{krate} {previous} → {version}</code> ({releases} releases) {links}",
+                    krate = krate.key(),
+                    previous = previous,
+                    version = krate.id.vers,
+                    releases = releases,
+                    links = krate.html_links(),
+                ),
+                _ => action_message(action, krate),
+            };
+            if let Some(change) = change {
+                message = format!("{} · {}", change.label(), message);
+            }
+            // diff.rs knows only crates.io
+            if let (ActionKind::NewVersion, Some(previous), None) =
+                (action, &previous, &krate.registry)
+            {
+                let url = source_diff.map_or_else(|| krate.diff_url(Some(previous)), str::to_owned);
+                message.push_str(&format!(" <a href='{}'>[diff]</a>", render::escape(&url)));
+            }
+            if let Some(details) = details {
+                message.push('\n');
+                message.push_str(details);
+            }
+            if let Some(notes) = notes {
+                // cut at a line break rather than by `fit_message` in the middle of an entry
+                let budget =
+                    render::MAX_LENGTH.saturating_sub(render::text_len(&message) + 2 + reserved);
+                let (notes, _) = verbosity::truncate(notes, budget);
+                message.push_str("\n\n");
+                message.push_str(&notes);
+            }
+            message
+        }
+    };
+
+    match headline {
+        Some(headline) => render::fit_message(&format!("{}\n{}", headline, message)),
+        None => render::fit_message(&message),
+    }
+}
+
+/// Text of the notification for a chat with `template` (or the default one), the chat's `filter`
+/// of the crate, Rust `toolchain` and `verbosity` of release notes. Release notes are cut to
+/// `notes_limit` characters, the flag tells whether they were cut.
+#[allow(clippy::too_many_arguments)]
+async fn notification(
+    krate: &Crate,
+    action: &ActionKind,
+    template: Option<&Template>,
+    verbose: bool,
+    verbosity: Verbosity,
+    filter: Filter,
+    toolchain: Option<&str>,
+    notes_limit: Option<usize>,
+    cfg: &cfg::Config,
+) -> (String, bool) {
+    let template = template.or_else(|| cfg.template.as_ref());
+    let (_, previous_release) = previous_release(&krate.key(), &krate.id.vers, cfg).await;
+    let previous = previous_release
+        .as_ref()
+        .and_then(|previous| SemVer::new(&previous.id.vers));
+    let notes = release_notes(krate, action, &[], previous.as_ref(), cfg).await;
+    let headline = notes.as_deref().and_then(summary::headline);
+    let mut notes = notes.and_then(|notes| verbosity.apply(&notes));
+    let mut cut = false;
+    if let (Some(full), Some(limit)) = (&notes, notes_limit) {
+        let (preview, was_cut) = verbosity::truncate(full, limit);
+        cut = was_cut;
+        notes = Some(preview);
+    }
+    let source_diff = source_diff(krate, action, previous.as_ref(), cfg).await;
+    let features = feature_changes(krate, action, previous_release.as_ref());
+    let deps = if filter.show_deps {
+        dependency_changes(krate, action, previous_release.as_ref())
+    } else {
+        None
+    };
+    let readme = if filter.readme {
+        readme_change(krate, action, previous_release.as_ref()).await
+    } else {
+        None
+    };
+    let toolchain = match (action, toolchain) {
+        (ActionKind::NewVersion, Some(toolchain)) => msrv::exceeds(krate, toolchain),
+        _ => None,
+    };
+    let metadata = if verbose {
+        metadata(krate, action).await
+    } else {
+        None
+    };
+    let msrv = msrv_change(krate, action, previous_release.as_ref());
+    // the chat gets them, security alerts aren't a part of this notification
+    let (license, deprecation, takeover) =
+        maintenance_notes(krate, action, previous_release.as_ref(), true, false).await;
+    let maintainers =
+        takeover.map(|(publisher, years)| maintenance::maintainers_html(&publisher, years));
+    let eol = branch_eol(krate, action).await;
+    let details = details(&[
+        deprecation.as_deref(),
+        eol.as_deref(),
+        maintainers.as_deref(),
+        license.as_deref(),
+        msrv.as_deref(),
+        toolchain.as_deref(),
+        features.as_deref(),
+        deps.as_deref(),
+        readme.as_deref(),
+        metadata.as_deref(),
+    ]);
+
+    let text = notification_text(
+        krate,
+        action,
+        1,
+        template,
+        notes.as_deref(),
+        headline.as_deref(),
+        previous.as_ref(),
+        source_diff.as_deref(),
+        details.as_deref(),
+    );
+    (text, cut)
+}
+
+/// Tells chats waiting for the crate's name (`/watch_name`) that it was published
+async fn first_publish(krate: &Crate, notifiers: &Notifiers, db: &Database) {
+    let watchers = db
+        .take_name_watchers(&normalize_name(&krate.id.name))
+        .await
+        .map_err(|err| tracing::error!("db error while getting name watchers: {}", err))
+        .unwrap_or_default();
+    if watchers.is_empty() {
+        return;
+    }
+
+    let text = format!(
+        "🆕 <code>{krate}</code> was just published: <code>{krate} {version}</code> {links}\nUse <code>/subscribe {krate}</code> to follow its releases.",
+        krate = krate.id.name,
+        version = krate.id.vers,
+        links = krate.html_links(),
+    );
+    let receipt = Receipt {
+        krate: krate.key(),
+        version: krate.id.vers.clone(),
+        action: String::from("watch"),
+        single: false,
+    };
+    for chat_id in watchers {
+        notifiers
+            .deliver(db, chat_id, text.clone(), false, &receipt)
+            .await;
+    }
+}
+
+/// Parts of notifications about an update which are the same for all chats, see
+/// [`Announcement::text`]
+struct Announcement<'a> {
+    krate: &'a Crate,
+    action: &'a ActionKind,
+    /// The other releases of the version train
+    earlier: &'a [Crate],
+    /// Template of the operator's routing rules or the config, chats' own templates win
+    template: Option<&'a Template>,
+    /// Release notes in telegram html
+    notes: Option<&'a str>,
+    headline: Option<String>,
+    previous: Option<&'a SemVer>,
+    source_diff: Option<String>,
+    features: Option<String>,
+    deps: Option<String>,
+    readme: Option<String>,
+    metadata: Option<String>,
+    msrv: Option<String>,
+    license: Option<String>,
+    security: Option<String>,
+    deprecation: Option<String>,
+    /// The release is on a branch its maintainers declared EOL
+    eol: Option<String>,
+    maintainers: Option<String>,
+}
+
+impl<'a> Announcement<'a> {
+    /// Looks up the parts, the ones only some chats get (READMEs, metadata, security alerts) only
+    /// if some of `users` (or of channels and rooms of the config) get them. License, deprecation,
+    /// EOL branch and maintainer notes are looked up if there is anyone to get the notification:
+    /// `users`, chats of `routes`, channels or rooms.
+    #[allow(clippy::too_many_arguments)]
+    async fn new(
+        krate: &'a Crate,
+        action: &'a ActionKind,
+        earlier: &'a [Crate],
+        previous_release: Option<&Crate>,
+        previous: Option<&'a SemVer>,
+        notes: Option<&'a str>,
+        template: Option<&'a Template>,
+        users: &[Subscriber],
+        routes: &[i64],
+        db: &Database,
+        cfg: &cfg::Config,
+    ) -> Announcement<'a> {
+        let readme = if users.iter().any(|s| s.filter.readme)
+            || cfg
+                .matrix
+                .iter()
+                .flat_map(|matrix| &matrix.rooms)
+                .any(|room| room.selector.filter.readme)
+            || cfg
+                .telegram_channels
+                .iter()
+                .any(|channel| channel.selector.filter.readme)
+        {
+            readme_change(krate, action, previous_release).await
+        } else {
+            None
+        };
+        let metadata = if users.iter().any(|s| s.verbose) {
+            metadata(krate, action).await
+        } else {
+            None
+        };
+        let shown = !users.is_empty()
+            || !routes.is_empty()
+            || cfg.channel.is_some()
+            || !cfg.telegram_channels.is_empty()
+            || cfg.matrix.iter().any(|matrix| !matrix.rooms.is_empty());
+        let alerts = users.iter().any(|s| s.security_alerts);
+        let (license, deprecation, takeover) =
+            maintenance_notes(krate, action, previous_release, shown, alerts).await;
+        let eol = if shown {
+            branch_eol(krate, action).await
+        } else {
+            None
+        };
+        let takeover = takeover
+            .as_ref()
+            .map(|(publisher, years)| (publisher.as_str(), *years));
+        let security = if alerts {
+            security_alerts(krate, action, previous_release, takeover, db).await
+        } else {
+            None
+        };
+
+        Announcement {
             krate,
-            chat_id,
-            err
+            action,
+            earlier,
+            template,
+            notes,
+            headline: notes.and_then(summary::headline),
+            previous,
+            source_diff: source_diff(krate, action, previous, cfg).await,
+            features: feature_changes(krate, action, previous_release),
+            deps: dependency_changes(krate, action, previous_release),
+            readme,
+            metadata,
+            msrv: msrv_change(krate, action, previous_release),
+            license,
+            security,
+            deprecation,
+            eol,
+            maintainers: takeover
+                .map(|(publisher, years)| maintenance::maintainers_html(publisher, years)),
+        }
+    }
+
+    /// Text of the notification for a chat with `template` (or the default one) and the chat's
+    /// `filter` of the crate.
+    /// `toolchain` is a warning that the release needs a newer Rust than the chat's one
+    /// `alerts` are shown to chats with `/security_alerts on`, they include the maintainer change
+    /// `summary` tells whether the chat gets the headline, see `summary::shown`
+    /// `translation` replaces the release notes for chats with `/translate on`
+    /// `language` of the notes is tagged for chats which read another one and don't translate them
+    #[allow(clippy::too_many_arguments)]
+    fn text(
+        &self,
+        template: Option<&Template>,
+        verbose: bool,
+        verbosity: Verbosity,
+        alerts: bool,
+        filter: Filter,
+        toolchain: Option<&str>,
+        summary: bool,
+        translation: Option<&str>,
+        language: Option<&str>,
+    ) -> String {
+        let details = details(&[
+            self.security.as_deref().filter(|_| alerts),
+            self.deprecation.as_deref(),
+            self.eol.as_deref(),
+            self.maintainers
+                .as_deref()
+                .filter(|_| !(alerts && self.security.is_some())),
+            self.license.as_deref(),
+            self.msrv.as_deref(),
+            toolchain,
+            self.features.as_deref(),
+            self.deps.as_deref().filter(|_| filter.show_deps),
+            self.readme.as_deref().filter(|_| filter.readme),
+            self.metadata.as_deref().filter(|_| verbose),
+        ]);
+        let notes = translation
+            .or(self.notes)
+            .and_then(|notes| verbosity.apply(notes))
+            .map(|notes| match language {
+                Some(language) => translate::tagged(language, &notes),
+                None => notes,
+            });
+        notification_text(
+            self.krate,
+            self.action,
+            self.earlier.len() + 1,
+            template.or(self.template),
+            notes.as_deref(),
+            self.headline.as_deref().filter(|_| summary),
+            self.previous,
+            self.source_diff.as_deref(),
+            details.as_deref(),
         )
-    });
-    tokio::time::delay_for(cfg.broadcast_delay_millis.into()).await;
+    }
+}
+
+/// `earlier` are the other releases of the version train, if `krate` is the last one of it
+async fn notify(
+    krate: Crate,
+    action: ActionKind,
+    published_at: i64,
+    earlier: &[Crate],
+    notifiers: &Notifiers,
+    db: &Database,
+    cfg: &cfg::Config,
+) {
+    let key = krate.key();
+    // the previous version is used by filters, release notes, diff links and the diffs of the index,
+    // a train is compared with the version before it
+    let first = earlier.first().unwrap_or(&krate);
+    let (scheme, previous_release) = previous_release(&key, &first.id.vers, cfg).await;
+    let previous_version = previous_release
+        .as_ref()
+        .map(|previous| previous.id.vers.as_str());
+    let previous = previous_version.and_then(SemVer::new);
+    if let (ActionKind::NewVersion, None, None) = (&action, &previous_release, &krate.registry) {
+        first_publish(first, notifiers, db).await;
+    }
+    let notes = release_notes(&krate, &action, earlier, previous.as_ref(), cfg).await;
+    if let Some(notes) = &notes {
+        db.set_release_notes(&key, &krate.id.vers, notes)
+            .await
+            .unwrap_or_else(|err| tracing::error!("db error while saving release notes: {}", err));
+    }
+    // the changelog is often pushed after publishing, notifications are edited once notes are
+    // found (see `late_notes`), combined notifications about a workspace aren't
+    if notes.is_none()
+        && earlier.is_empty()
+        && notifiers.collector.is_none()
+        && matches!(action, ActionKind::NewVersion)
+        && krate.registry.is_none()
+        && cfg.fetch_changelogs
+        && cfg.changelog.notes_lag_secs > 0
+        && !cfg.crates.is_ignored(&krate.id.name)
+    {
+        db.add_lagging_notes(&key, &krate.id.vers)
+            .await
+            .unwrap_or_else(|err| tracing::error!("db error while adding lagging notes: {}", err));
+    }
+    let routing = rules::evaluate(
+        cfg,
+        &krate,
+        &action,
+        scheme,
+        previous_version,
+        notes.as_deref(),
+    )
+    .await;
+
+    let users = db
+        .list_subscribers(&key)
+        .await
+        .map_err(|err| tracing::error!("db error while getting subscribers: {}", err))
+        .unwrap_or_default();
+    // non-empty if the release is replayed after a crash
+    let delivered = db
+        .delivered_chats(&key, &krate.id.vers, action.as_str())
+        .await
+        .map_err(|err| tracing::error!("db error while getting delivered chats: {}", err))
+        .unwrap_or_default();
+    let receipt = Receipt {
+        krate: key.clone(),
+        version: krate.id.vers.clone(),
+        action: action.as_str().to_owned(),
+        single: earlier.is_empty(),
+    };
+
+    // Chat templates are validated by `/set_template`
+    let templates: Vec<Option<Template>> = users
+        .iter()
+        .map(|s| s.template.as_deref().and_then(|t| Template::parse(t).ok()))
+        .collect();
+    let announcement = Announcement::new(
+        &krate,
+        &action,
+        earlier,
+        previous_release.as_ref(),
+        previous.as_ref(),
+        notes.as_deref(),
+        routing.template.or_else(|| cfg.template.as_ref()),
+        &users,
+        &routing.routes,
+        db,
+        cfg,
+    )
+    .await;
+    let message = announcement.text(
+        None,
+        false,
+        Verbosity::Full,
+        false,
+        Filter::default(),
+        None,
+        true,
+        None,
+        None,
+    );
+
+    // routes of the operator's rules get the update even if the rules drop it
+    for &chat_id in &routing.routes {
+        if !delivered.contains(&chat_id) {
+            notifiers
+                .deliver(db, chat_id, message.clone(), true, &receipt)
+                .await;
+        }
+    }
+    if routing.drop {
+        tracing::info!("dropped by a routing rule");
+        return;
+    }
+    // waits are kept for a release the operator's rules don't drop
+    if let ActionKind::NewVersion = action {
+        let versions: Vec<&Crate> = earlier.iter().chain(Some(&krate)).collect();
+        waits::fire(&key, &versions, notifiers, db).await;
+    }
+    // crates of alternative registries may be private, so they aren't posted to channels
+    if let (Some(ch), None) = (cfg.channel, &krate.registry) {
+        if !cfg.ban.crates.contains(krate.id.name.as_str()) && !delivered.contains(&ch) {
+            notifiers
+                .deliver(db, ch, message.clone(), true, &receipt)
+                .await;
+        }
+    }
+    if krate.registry.is_none() && !cfg.ban.crates.contains(krate.id.name.as_str()) {
+        let channels =
+            channels::selected(cfg, &krate, &action, scheme, previous_release.as_ref()).await;
+        for channel in channels {
+            if delivered.contains(&channel.id) {
+                continue;
+            }
+            let filter = channel.selector.filter;
+            let message = if filter.show_deps || filter.readme {
+                announcement.text(
+                    None,
+                    false,
+                    Verbosity::Full,
+                    false,
+                    filter,
+                    None,
+                    true,
+                    None,
+                    None,
+                )
+            } else {
+                message.clone()
+            };
+            notifiers
+                .deliver(db, channel.id, message, true, &receipt)
+                .await;
+        }
+    }
+
+    hooks::send(
+        &krate,
+        &action,
+        notes.as_deref(),
+        scheme,
+        previous_version,
+        cfg,
+    );
+
+    let is_yank = matches!(action, ActionKind::Yanked | ActionKind::Unyanked);
+    if let (Some(matrix), Some(matrix_cfg)) = (&notifiers.matrix, &cfg.matrix) {
+        for room in &matrix_cfg.rooms {
+            if room
+                .selector
+                .matches(&key, is_yank, scheme, &krate.id.vers, previous_version)
+            {
+                let message = if room.selector.filter.show_deps || room.selector.filter.readme {
+                    announcement.text(
+                        None,
+                        false,
+                        Verbosity::Full,
+                        false,
+                        room.selector.filter,
+                        None,
+                        true,
+                        None,
+                        None,
+                    )
+                } else {
+                    message.clone()
+                };
+                matrix.push(room.id.clone(), message, false);
+            }
+        }
+    }
+    if let (Some(discord), Some(discord_cfg)) = (&notifiers.discord, &cfg.discord) {
+        let card = discord::release_card(
+            &krate,
+            &action,
+            notes.as_deref(),
+            previous.as_ref(),
+            announcement.features.as_deref(),
+            published_at,
+        );
+        for channel in &discord_cfg.channels {
+            if let Some(target) = discord::Channel::of(channel) {
+                if channel
+                    .selector
+                    .matches(&key, is_yank, scheme, &krate.id.vers, previous_version)
+                {
+                    discord.push_message(target, card.clone());
+                }
+            }
+        }
+    }
+
+    let mut translations = Translations::new(notes.as_deref());
+    for (subscriber, template) in users.into_iter().zip(templates) {
+        if delivered.contains(&subscriber.chat_id) {
+            continue;
+        }
+        // tags are followed to discover new releases, yanks of such crates would be noise
+        if is_yank && (subscriber.mute_yanks || subscriber.tagged) {
+            continue;
+        }
+        if !subscriber
+            .filter
+            .matches(scheme, &krate.id.vers, previous_version)
+        {
+            continue;
+        }
+        let below_baseline = subscriber
+            .baseline
+            .as_deref()
+            .and_then(|baseline| scheme.compare(&krate.id.vers, baseline))
+            .map_or(false, |order| order != cmp::Ordering::Greater);
+        if matches!(action, ActionKind::NewVersion) && below_baseline {
+            continue;
+        }
+        // e-mails and digests list every release of a train
+        let versions = earlier
+            .iter()
+            .chain(Some(&krate))
+            .map(|krate| &krate.id.vers);
+        if subscriber.email {
+            for version in versions.clone() {
+                db.queue_email(subscriber.chat_id, &key, version, action.as_str())
+                    .await
+                    .unwrap_or_else(|err| {
+                        tracing::error!("db error while queueing e-mail: {}", err)
+                    });
+            }
+        }
+        // paused chats get the notifications queued during the pause in a catch-up digest
+        if subscriber.digest || subscriber.paused {
+            for version in versions {
+                db.queue_digest(subscriber.chat_id, &key, version, action.as_str())
+                    .await
+                    .unwrap_or_else(|err| {
+                        tracing::error!("db error while queueing digest: {}", err)
+                    });
+            }
+            continue;
+        }
+        let toolchain = match (&action, &subscriber.msrv) {
+            (ActionKind::NewVersion, Some(toolchain)) => msrv::exceeds(&krate, toolchain),
+            _ => None,
+        };
+        let language = subscriber.language.as_deref();
+        let tag = translations.foreign(language);
+        let translation = if subscriber.translate {
+            translations.get(language, cfg).await
+        } else {
+            None
+        };
+        let tag = tag.filter(|_| translation.is_none());
+        let summary = summary::shown(subscriber.chat_id, cfg.summary_share);
+        if announcement.headline.is_some() {
+            let variant = if summary { "summary" } else { "control" };
+            metrics::SUMMARY_VARIANTS
+                .with_label_values(&[variant])
+                .inc();
+        }
+        let message = match &template {
+            None if !subscriber.verbose
+                && subscriber.verbosity == Verbosity::Full
+                && !(subscriber.security_alerts && announcement.security.is_some())
+                && !subscriber.filter.show_deps
+                && !subscriber.filter.readme
+                && toolchain.is_none()
+                && translation.is_none()
+                && tag.is_none()
+                && summary =>
+            {
+                message.clone()
+            }
+            template => announcement.text(
+                template.as_ref(),
+                subscriber.verbose,
+                subscriber.verbosity,
+                subscriber.security_alerts,
+                subscriber.filter,
+                toolchain.as_deref(),
+                summary,
+                translation,
+                tag,
+            ),
+        };
+        if subscriber.quiet {
+            db.defer_notification(
+                subscriber.chat_id,
+                &key,
+                &krate.id.vers,
+                action.as_str(),
+                &message,
+            )
+            .await
+            .unwrap_or_else(|err| {
+                tracing::error!("db error while deferring notification: {}", err)
+            });
+            continue;
+        }
+        notifiers
+            .deliver(db, subscriber.chat_id, message, false, &receipt)
+            .await;
+    }
+}
+
+/// Version scheme of the crate and its newest release older than `version`
+/// (`name` is `myreg:name` for alternative registries)
+async fn previous_release(
+    name: &str,
+    version: &str,
+    cfg: &cfg::Config,
+) -> (VersionScheme, Option<Crate>) {
+    let all = Crate::read_all(name, cfg)
+        .await
+        .map_err(|err| tracing::debug!("couldn't read versions of {}: {}", name, err))
+        .unwrap_or_default();
+    let versions = all.iter().map(|krate| krate.id.vers.as_str());
+    let scheme = krate::version_scheme(name, versions.chain(Some(version)), cfg);
+
+    let previous = all
+        .into_iter()
+        .filter(|krate| scheme.compare(&krate.id.vers, version) == Some(cmp::Ordering::Less))
+        .max_by(|a, b| {
+            scheme
+                .compare(&a.id.vers, &b.id.vers)
+                .unwrap_or(cmp::Ordering::Equal)
+        });
+    (scheme, previous)
 }
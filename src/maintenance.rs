@@ -0,0 +1,143 @@
+//! Signs that a crate is deprecated or changed hands, noted in notifications about its new
+//! versions: the description or new lines of the README saying the crate is deprecated or
+//! unmaintained, and the first release of a new publisher after years without releases.
+//! They're hints, the checks work on data fetched by the caller.
+use crate::{cratesio::Publish, render::escape};
+
+const YEAR_SECS: i64 = 365 * 24 * 60 * 60;
+
+/// Releases after this long without releases are checked for a new publisher
+const DORMANT_SECS: i64 = 2 * YEAR_SECS;
+
+/// Lowercase phrases telling that a crate is deprecated or abandoned
+const PHRASES: [&str; 10] = [
+    "this crate is deprecated",
+    "this crate has been deprecated",
+    "deprecated in favor of",
+    "deprecated in favour of",
+    "no longer maintained",
+    "not maintained anymore",
+    "is unmaintained",
+    "this repository has been archived",
+    "looking for a new maintainer",
+    "looking for new maintainers",
+];
+
+/// Quoted lines are cut to this many characters
+const MAX_QUOTE: usize = 150;
+
+/// Whether the text (a description or a line of a README) says the crate is deprecated
+pub fn is_deprecation(text: &str) -> bool {
+    let text = text.trim().to_lowercase();
+    text.starts_with("deprecated") || PHRASES.iter().any(|phrase| text.contains(phrase))
+}
+
+/// The first line of the `new` README saying the crate is deprecated, if the `old` one had no
+/// such lines. Deprecations already known aren't noted again.
+pub fn newly_deprecated<'a>(old: &[String], new: &'a [String]) -> Option<&'a str> {
+    if old.iter().any(|line| is_deprecation(line)) {
+        return None;
+    }
+    new.iter()
+        .find(|line| is_deprecation(line))
+        .map(String::as_str)
+}
+
+/// New publisher of `version` and years since the previous release, if it came after a long
+/// pause and the publisher hasn't published any earlier version
+pub fn maintainer_change<'a>(publishes: &'a [Publish], version: &str) -> Option<(&'a str, i64)> {
+    let new = publishes.iter().find(|p| p.version == version)?;
+    let publisher = new.publisher.as_deref()?;
+    let earlier: Vec<&Publish> = publishes
+        .iter()
+        .filter(|p| p.created_at < new.created_at)
+        .collect();
+    let last = earlier.iter().map(|p| p.created_at).max()?;
+    let pause = new.created_at - last;
+    let known = earlier
+        .iter()
+        .any(|p| p.publisher.as_deref() == Some(publisher));
+    if pause >= DORMANT_SECS && !known {
+        Some((publisher, pause / YEAR_SECS))
+    } else {
+        None
+    }
+}
+
+/// Telegram html line about a deprecation, quoting the text saying so
+pub fn deprecation_html(quote: &str) -> String {
+    let quote = quote.trim();
+    let quote = match quote.char_indices().nth(MAX_QUOTE) {
+        Some((end, _)) => format!("{}…", &quote[..end]),
+        None => quote.to_owned(),
+    };
+    format!("🪦 Possibly deprecated: “{}”", escape(&quote))
+}
+
+/// Telegram html line about a maintainer change (see [`maintainer_change`])
+pub fn maintainers_html(publisher: &str, years: i64) -> String {
+    format!(
+        "👤 Maintainership possibly changed: first release by <code>{}</code> after {} years \
+         without releases",
+        escape(publisher),
+        years
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn publish(version: &str, years: i64, publisher: &str) -> Publish {
+        Publish {
+            version: version.to_owned(),
+            created_at: years * YEAR_SECS,
+            publisher: Some(publisher.to_owned()),
+        }
+    }
+
+    #[test]
+    fn deprecations() {
+        assert!(is_deprecation("DEPRECATED: use `bar` instead"));
+        assert!(is_deprecation(
+            "This crate is no longer maintained, see bar."
+        ));
+        assert!(!is_deprecation("Removes deprecated items"));
+
+        let lines = |lines: &[&str]| lines.iter().map(|&l| l.to_owned()).collect::<Vec<_>>();
+        let old = lines(&["foo", "A fast parser"]);
+        let new = lines(&["foo", "Deprecated in favor of bar", "A fast parser"]);
+        assert_eq!(
+            newly_deprecated(&old, &new),
+            Some("Deprecated in favor of bar")
+        );
+        assert_eq!(newly_deprecated(&new, &new), None);
+        assert_eq!(newly_deprecated(&old, &old), None);
+    }
+
+    #[test]
+    fn maintainer_changes() {
+        let publishes = vec![
+            publish("0.1.0", 0, "alice"),
+            publish("0.2.0", 1, "alice"),
+            publish("0.3.0", 4, "mallory"),
+            publish("0.3.1", 4, "alice"),
+        ];
+        assert_eq!(maintainer_change(&publishes, "0.3.0"), Some(("mallory", 3)));
+        // a known publisher
+        assert_eq!(maintainer_change(&publishes, "0.3.1"), None);
+        // no pause
+        assert_eq!(maintainer_change(&publishes, "0.2.0"), None);
+        assert_eq!(maintainer_change(&publishes, "0.1.0"), None);
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(
+            deprecation_html("  Use <bar> instead "),
+            "🪦 Possibly deprecated: “Use &lt;bar&gt; instead”"
+        );
+        let long = "a".repeat(MAX_QUOTE + 1);
+        assert!(deprecation_html(&long).ends_with("a…”"));
+    }
+}
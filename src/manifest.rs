@@ -0,0 +1,183 @@
+//! Dependencies from `Cargo.lock` or `Cargo.toml` sent to the bot
+use std::collections::BTreeMap;
+
+use toml::Value;
+use versions::SemVer;
+
+/// Dependency from crates.io
+#[derive(Debug, PartialEq, Eq)]
+pub struct Dependency {
+    pub name: String,
+    /// Version from `Cargo.lock` (the newest one, if there are several)
+    pub locked: Option<String>,
+}
+
+/// Tables of `Cargo.toml` with dependencies
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+fn is_crates_io(source: &str) -> bool {
+    source == "registry+https://github.com/rust-lang/crates.io-index"
+        || source == "sparse+https://index.crates.io/"
+}
+
+fn from_lock(packages: &[Value]) -> Vec<Dependency> {
+    let mut locked: BTreeMap<&str, &str> = BTreeMap::new();
+    for package in packages {
+        let field = |name| package.get(name).and_then(Value::as_str);
+        let (name, version) = match (field("name"), field("version"), field("source")) {
+            (Some(name), Some(version), Some(source)) if is_crates_io(source) => (name, version),
+            // workspace members, git and path dependencies
+            _ => continue,
+        };
+
+        let newer = locked
+            .get(name)
+            .map_or(true, |&old| SemVer::new(version) > SemVer::new(old));
+        if newer {
+            locked.insert(name, version);
+        }
+    }
+
+    locked
+        .into_iter()
+        .map(|(name, version)| Dependency {
+            name: name.to_owned(),
+            locked: Some(version.to_owned()),
+        })
+        .collect()
+}
+
+fn from_manifest(manifest: &Value) -> Vec<Dependency> {
+    let mut tables: Vec<&Value> = DEPENDENCY_TABLES
+        .iter()
+        .filter_map(|table| manifest.get(table))
+        .collect();
+    // [target.'cfg(...)'.dependencies]
+    if let Some(targets) = manifest.get("target").and_then(Value::as_table) {
+        for target in targets.values() {
+            tables.extend(
+                DEPENDENCY_TABLES
+                    .iter()
+                    .filter_map(|table| target.get(table)),
+            );
+        }
+    }
+    // [workspace.dependencies]
+    tables.extend(
+        manifest
+            .get("workspace")
+            .and_then(|w| w.get("dependencies")),
+    );
+
+    let mut names: Vec<String> = tables
+        .into_iter()
+        .filter_map(Value::as_table)
+        .flatten()
+        .filter_map(|(key, spec)| match spec {
+            Value::String(_) => Some(key.clone()),
+            Value::Table(spec) => {
+                if spec.contains_key("path") || spec.contains_key("git") {
+                    return None;
+                }
+                // `foo = { package = "bar", version = "1" }`
+                let name = spec.get("package").and_then(Value::as_str).unwrap_or(key);
+                Some(name.to_owned())
+            }
+            _ => None,
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+
+    names
+        .into_iter()
+        .map(|name| Dependency { name, locked: None })
+        .collect()
+}
+
+/// Parses `Cargo.lock` or `Cargo.toml`, `None` if `src` is neither
+pub fn parse(src: &str) -> Option<Vec<Dependency>> {
+    let value: Value = toml::from_str(src).ok()?;
+
+    if let Some(packages) = value.get("package").and_then(Value::as_array) {
+        Some(from_lock(packages))
+    } else if value.get("package").is_some() || value.get("workspace").is_some() {
+        Some(from_manifest(&value))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dep(name: &str, locked: Option<&str>) -> Dependency {
+        Dependency {
+            name: name.to_owned(),
+            locked: locked.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn lock() {
+        let src = r#"
+            version = 3
+
+            [[package]]
+            name = "app"
+            version = "0.1.0"
+
+            [[package]]
+            name = "syn"
+            version = "1.0.5"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "syn"
+            version = "2.0.1"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "fntools"
+            version = "0.1.0"
+            source = "git+https://github.com/WaffleLapkin/fntools.git?rev=8d59c82#8d59c82"
+        "#;
+
+        assert_eq!(parse(src), Some(vec![dep("syn", Some("2.0.1"))]));
+    }
+
+    #[test]
+    fn manifest() {
+        let src = r#"
+            [package]
+            name = "app"
+            version = "0.1.0"
+
+            [dependencies]
+            serde = { version = "1", features = ["derive"] }
+            log = "0.4"
+            local = { path = "../local" }
+            ser = { package = "serde_json", version = "1" }
+
+            [target.'cfg(unix)'.dev-dependencies]
+            libc = "0.2"
+        "#;
+
+        assert_eq!(
+            parse(src),
+            Some(vec![
+                dep("libc", None),
+                dep("log", None),
+                dep("serde", None),
+                dep("serde_json", None),
+            ])
+        );
+    }
+
+    #[test]
+    fn neither() {
+        assert_eq!(parse("just some text"), None);
+        assert_eq!(parse("a = 1"), None);
+    }
+}
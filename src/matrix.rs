@@ -0,0 +1,109 @@
+//! Matrix backend: rooms from the config get updates selected like for http hooks
+use std::{convert::TryFrom, sync::Arc};
+
+use matrix_sdk::{
+    events::{
+        room::message::{MessageEventContent, TextMessageEventContent},
+        AnyMessageEventContent,
+    },
+    identifiers::RoomId,
+    Client,
+};
+use tokio::sync::mpsc;
+
+use crate::{
+    cfg::{Config, MatrixConfig},
+    notifier::Notifier,
+    render,
+    util::tryn,
+};
+
+/// How many times sending is retried after the first attempt
+const RETRIES: usize = 3;
+
+/// Handle of the queue, messages are sent by a background task
+#[derive(Clone)]
+pub struct MatrixQueue {
+    /// Room id and telegram html
+    tx: mpsc::UnboundedSender<(String, String)>,
+}
+
+impl MatrixQueue {
+    /// Logs in and spawns the task sending queued messages, `None` if matrix isn't configured
+    pub fn start(cfg: Arc<Config>) -> Option<Self> {
+        cfg.matrix.as_ref()?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(run(cfg, rx));
+
+        Some(Self { tx })
+    }
+}
+
+impl Notifier for MatrixQueue {
+    type Target = String;
+
+    /// Rooms don't get quiet messages differently, `quiet` is ignored
+    fn push(&self, room: String, html: String, _quiet: bool) {
+        if self.tx.send((room, html)).is_err() {
+            tracing::error!("matrix queue is closed, message is lost");
+        }
+    }
+}
+
+async fn login(matrix: &MatrixConfig) -> matrix_sdk::Result<Client> {
+    let client = Client::new(matrix.homeserver.as_str())?;
+    client
+        .login(
+            matrix.user.as_str(),
+            matrix.password.as_str(),
+            None,
+            Some("crate_upd_bot"),
+        )
+        .await?;
+
+    Ok(client)
+}
+
+async fn run(cfg: Arc<Config>, mut rx: mpsc::UnboundedReceiver<(String, String)>) {
+    let matrix = match &cfg.matrix {
+        Some(matrix) => matrix,
+        None => return,
+    };
+    let client = match login(matrix).await {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::error!(
+                "couldn't log in to matrix, rooms won't be notified: {}",
+                err
+            );
+            return;
+        }
+    };
+    tracing::info!("logged in to matrix as {}", matrix.user);
+
+    while let Some((room, html)) = rx.recv().await {
+        let room_id = match RoomId::try_from(room.as_str()) {
+            Ok(room_id) => room_id,
+            Err(err) => {
+                tracing::error!("invalid matrix room id {}: {}", room, err);
+                continue;
+            }
+        };
+        // telegram html is a subset of html allowed in matrix messages
+        let content = AnyMessageEventContent::RoomMessage(MessageEventContent::Text(
+            TextMessageEventContent::html(render::plain(&html), html),
+        ));
+
+        let result = tryn(RETRIES, cfg.retry_delay.0, || {
+            client.room_send(&room_id, content.clone(), None)
+        })
+        .await;
+        if let Err(err) = result {
+            tracing::error!(
+                "error while sending message to matrix room {}: {}",
+                room,
+                err
+            );
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Prometheus metrics, served at `/metrics` if enabled in the config
+use std::{convert::Infallible, net::SocketAddr, time::Instant};
+
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    register_int_gauge, Encoder, Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    TextEncoder,
+};
+
+use crate::db::Database;
+
+lazy_static! {
+    /// When the bot was started, initialized in `main`
+    pub static ref STARTED: Instant = Instant::now();
+    /// Duration of a single pull of the index, by registry (`crates.io` or a configured name)
+    /// and index kind
+    pub static ref INDEX_POLL_DURATION: HistogramVec = register_histogram_vec!(
+        "crate_upd_index_poll_duration_seconds",
+        "Duration of a pull of the index",
+        &["registry", "kind"],
+        vec![0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0]
+    )
+    .unwrap();
+    /// Processed index events, by action
+    pub static ref EVENTS: IntCounterVec = register_int_counter_vec!(
+        "crate_upd_index_events_total",
+        "Processed index events",
+        &["action"]
+    )
+    .unwrap();
+    pub static ref NOTIFICATIONS_SENT: IntCounter = register_int_counter!(
+        "crate_upd_notifications_sent_total",
+        "Messages sent by the send queue"
+    )
+    .unwrap();
+    pub static ref NOTIFICATIONS_FAILED: IntCounter = register_int_counter!(
+        "crate_upd_notifications_failed_total",
+        "Messages dropped by the send queue after errors"
+    )
+    .unwrap();
+    pub static ref PIPELINE_QUEUE_DEPTH: IntGauge = register_int_gauge!(
+        "crate_upd_pipeline_queue_depth",
+        "Releases waiting in the lanes of the pipeline"
+    )
+    .unwrap();
+    pub static ref SEND_QUEUE_DEPTH: IntGauge = register_int_gauge!(
+        "crate_upd_send_queue_depth",
+        "Messages waiting in the send queue"
+    )
+    .unwrap();
+    pub static ref SEND_QUEUE_WAITS: IntCounter = register_int_counter!(
+        "crate_upd_send_queue_waits_total",
+        "Notifications which waited for a place in the full send queue"
+    )
+    .unwrap();
+    pub static ref DIGESTS_DEFERRED: IntCounter = register_int_counter!(
+        "crate_upd_digests_deferred_total",
+        "Checks of due digests and weekly reports put off because the send queue was busy"
+    )
+    .unwrap();
+    /// Latency of `sendMessage` requests of the send queue
+    pub static ref TELEGRAM_LATENCY: Histogram = register_histogram!(
+        "crate_upd_telegram_request_duration_seconds",
+        "Latency of telegram api requests"
+    )
+    .unwrap();
+    /// Notifications with release notes sent to subscribers, by whether they started with the
+    /// summary (`summary` or `control`)
+    pub static ref SUMMARY_VARIANTS: IntCounterVec = register_int_counter_vec!(
+        "crate_upd_summary_variant_total",
+        "Notifications with release notes by summary variant",
+        &["variant"]
+    )
+    .unwrap();
+    /// Changelog lookups, by result (`found` or `not_found`)
+    pub static ref CHANGELOG_FETCHES: IntCounterVec = register_int_counter_vec!(
+        "crate_upd_changelog_fetches_total",
+        "Release notes lookups",
+        &["result"]
+    )
+    .unwrap();
+    /// Sources tried by changelog lookups, by source (`file`, `github-releases`, `gitlab-releases`
+    /// or `commits`) and result (`found` or `not_found`)
+    pub static ref CHANGELOG_SOURCES: IntCounterVec = register_int_counter_vec!(
+        "crate_upd_changelog_sources_total",
+        "Sources tried by release notes lookups",
+        &["source", "result"]
+    )
+    .unwrap();
+    /// Parsing of changelogs down to the wanted release, crates are in the `changelog_parse` span
+    /// rather than labels
+    pub static ref CHANGELOG_PARSE_DURATION: Histogram = register_histogram!(
+        "crate_upd_changelog_parse_duration_seconds",
+        "Duration of parsing a changelog down to the wanted release",
+        vec![0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0]
+    )
+    .unwrap();
+    pub static ref CHANGELOG_RELEASES_PARSED: Histogram = register_histogram!(
+        "crate_upd_changelog_releases_parsed",
+        "Releases parsed in a changelog before the wanted one was found",
+        vec![1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 500.0]
+    )
+    .unwrap();
+    pub static ref CHANGELOG_SECTIONS: Histogram = register_histogram!(
+        "crate_upd_changelog_sections",
+        "Sections of releases found in changelogs",
+        vec![0.0, 1.0, 2.0, 3.0, 4.0, 6.0, 8.0, 12.0]
+    )
+    .unwrap();
+    /// Requests of cached apis, by source and result (`hit`, `revalidated` or `miss`)
+    pub static ref HTTP_CACHE: IntCounterVec = register_int_counter_vec!(
+        "crate_upd_http_cache_total",
+        "Requests of cached external apis",
+        &["source", "result"]
+    )
+    .unwrap();
+    /// Deliveries of payloads to hooks, by result (`ok` or `failed`)
+    pub static ref HOOK_DELIVERIES: IntCounterVec = register_int_counter_vec!(
+        "crate_upd_hook_deliveries_total",
+        "Deliveries of update payloads to http hooks",
+        &["result"]
+    )
+    .unwrap();
+    /// Commands and subscription changes refused by per-chat limits, by limit
+    /// (`subscriptions`, `commands` or `churn`)
+    pub static ref LIMITED: IntCounterVec = register_int_counter_vec!(
+        "crate_upd_limited_total",
+        "Requests refused by per-chat limits",
+        &["limit"]
+    )
+    .unwrap();
+    /// Release notes passed through the translation backend, by backend and result (`ok` or
+    /// `error`)
+    pub static ref TRANSLATIONS: IntCounterVec = register_int_counter_vec!(
+        "crate_upd_translations_total",
+        "Release notes passed through the translation backend",
+        &["backend", "result"]
+    )
+    .unwrap();
+    static ref SUBSCRIPTIONS: IntGauge = register_int_gauge!(
+        "crate_upd_subscriptions",
+        "Subscriptions to crates"
+    )
+    .unwrap();
+    static ref SUBSCRIBED_CHATS: IntGauge = register_int_gauge!(
+        "crate_upd_subscribed_chats",
+        "Chats with at least one subscription"
+    )
+    .unwrap();
+}
+
+async fn handle(db: Database, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if request.uri().path() != "/metrics" {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(response);
+    }
+
+    // counts are cheap to get from the database, so they are updated on scrape
+    match db.count_subscriptions().await {
+        Ok((subscriptions, chats)) => {
+            SUBSCRIPTIONS.set(subscriptions);
+            SUBSCRIBED_CHATS.set(chats);
+        }
+        Err(err) => tracing::error!("db error while counting subscriptions: {}", err),
+    }
+
+    let encoder = TextEncoder::new();
+    let mut buf = Vec::new();
+    if let Err(err) = encoder.encode(&prometheus::gather(), &mut buf) {
+        tracing::error!("couldn't encode metrics: {}", err);
+    }
+
+    let mut response = Response::new(Body::from(buf));
+    if let Ok(content_type) = encoder.format_type().parse() {
+        response.headers_mut().insert(CONTENT_TYPE, content_type);
+    }
+    Ok(response)
+}
+
+/// Serves `/metrics` on `listen`, forever
+pub async fn serve(listen: SocketAddr, db: Database) {
+    let make_service = make_service_fn(move |_| {
+        let db = db.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| handle(db.clone(), request))) }
+    });
+
+    tracing::info!("serving metrics on http://{}/metrics", listen);
+    if let Err(err) = Server::bind(&listen).serve(make_service).await {
+        tracing::error!("metrics server error: {}", err);
+    }
+}
@@ -0,0 +1,76 @@
+//! Export/import of a single chat's state, for moving between bot instances
+use std::error::Error;
+
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+
+use crate::{cfg::Config, db::Database};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Everything the bot stores about a chat
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct ChatBundle {
+    pub chat_id: i64,
+    /// `chat_data` of `db.sql`: settings, subscriptions, queued notifications and history, a key
+    /// per table
+    pub data: serde_json::Value,
+}
+
+impl ChatBundle {
+    /// Number of crate subscriptions in the bundle
+    pub fn subscriptions(&self) -> usize {
+        self.data["subscriptions"].as_array().map_or(0, Vec::len)
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct SignedBundle {
+    bundle: ChatBundle,
+    /// Hex-encoded HMAC-SHA256 of the json-serialized `bundle`
+    signature: String,
+}
+
+fn mac(cfg: &Config, bundle: &ChatBundle) -> Result<HmacSha256, Box<dyn Error>> {
+    let key = cfg
+        .migration_key
+        .as_ref()
+        .ok_or("`migration_key` must be set in the config to export/import chats")?;
+    let mut mac = HmacSha256::new_varkey(key.as_bytes()).map_err(|_| "invalid migration key")?;
+    mac.update(&serde_json::to_vec(bundle)?);
+    Ok(mac)
+}
+
+/// Returns signed json bundle with the state of the chat
+pub async fn export(db: &Database, cfg: &Config, chat_id: i64) -> Result<String, Box<dyn Error>> {
+    let bundle = ChatBundle {
+        chat_id,
+        data: serde_json::from_str(&db.chat_data(chat_id).await?)?,
+    };
+    let signature = hex::encode(mac(cfg, &bundle)?.finalize().into_bytes());
+
+    Ok(serde_json::to_string_pretty(&SignedBundle {
+        bundle,
+        signature,
+    })?)
+}
+
+/// Verifies signature of the bundle and restores the state of the chat (see `import_chat` in
+/// `db.sql` for what's left out).
+///
+/// Settings, subscriptions and other rows which the chat already has are kept.
+pub async fn import(
+    db: &Database,
+    cfg: &Config,
+    bundle: &str,
+) -> Result<ChatBundle, Box<dyn Error>> {
+    let SignedBundle { bundle, signature } = serde_json::from_str(bundle)?;
+    mac(cfg, &bundle)?
+        .verify(&hex::decode(signature)?)
+        .map_err(|_| "bundle signature mismatch")?;
+
+    db.import_chat(bundle.chat_id, &bundle.data.to_string())
+        .await?;
+
+    Ok(bundle)
+}
@@ -0,0 +1,81 @@
+//! Minimum supported Rust versions (`rust-version` of manifests, `rust_version` in the index)
+use crate::{krate::Crate, render::escape};
+
+/// `1.63` or `1.63.0`, the patch defaults to 0
+fn parse(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version.trim().split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    let patch = match parts.next() {
+        Some(patch) => patch.parse().ok()?,
+        None => 0,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some((major, minor, patch))
+}
+
+/// Whether `version` is a Rust version accepted by `/msrv`
+pub fn is_valid(version: &str) -> bool {
+    parse(version).is_some()
+}
+
+/// Warning about the release raising the MSRV, as telegram html
+pub fn raised(previous: &Crate, new: &Crate) -> Option<String> {
+    let (from, to) = (
+        previous.rust_version.as_deref()?,
+        new.rust_version.as_deref()?,
+    );
+    if parse(to)? <= parse(from)? {
+        return None;
+    }
+
+    Some(format!(
+        "⚠️ <b>MSRV raised</b> from <code>{}</code> to <code>{}</code>",
+        escape(from),
+        escape(to)
+    ))
+}
+
+/// Warning for a chat using the `toolchain` if the release requires a newer one, as telegram html
+pub fn exceeds(krate: &Crate, toolchain: &str) -> Option<String> {
+    let required = krate.rust_version.as_deref()?;
+    if parse(required)? <= parse(toolchain)? {
+        return None;
+    }
+
+    Some(format!(
+        "⚠️ <b>Requires Rust <code>{}</code></b>, newer than your toolchain (<code>{}</code>)",
+        escape(required),
+        escape(toolchain)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn krate(rust_version: Option<&str>) -> Crate {
+        let mut krate: Crate =
+            serde_json::from_str(r#"{"name":"foo","vers":"1.0.0","yanked":false}"#).unwrap();
+        krate.rust_version = rust_version.map(str::to_owned);
+        krate
+    }
+
+    #[test]
+    fn warnings() {
+        assert_eq!(
+            raised(&krate(Some("1.63")), &krate(Some("1.70.0"))).as_deref(),
+            Some("⚠️ <b>MSRV raised</b> from <code>1.63</code> to <code>1.70.0</code>")
+        );
+        assert_eq!(raised(&krate(Some("1.63")), &krate(Some("1.63.0"))), None);
+        assert_eq!(raised(&krate(None), &krate(Some("1.70"))), None);
+
+        assert!(exceeds(&krate(Some("1.70")), "1.65").is_some());
+        assert_eq!(exceeds(&krate(Some("1.65")), "1.65.1"), None);
+        assert_eq!(exceeds(&krate(None), "1.65"), None);
+        assert!(!is_valid("1.x") && !is_valid("stable") && is_valid("1.65"));
+    }
+}
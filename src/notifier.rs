@@ -0,0 +1,51 @@
+//! Backends delivering notifications: telegram chats, matrix rooms and discord channels
+use crate::{
+    db::Database,
+    discord::DiscordQueue,
+    matrix::MatrixQueue,
+    send::{Receipt, SendQueue},
+    workspace::Collector,
+};
+
+pub use crate_upd_core::Notifier;
+
+/// Configured backends, cheap to clone
+#[derive(Clone)]
+pub struct Notifiers {
+    /// Subscribers of the bot and the channel
+    pub telegram: SendQueue,
+    /// Rooms from the config, `None` if matrix isn't configured
+    pub matrix: Option<MatrixQueue>,
+    /// Channels from the config, `None` if discord isn't configured
+    pub discord: Option<DiscordQueue>,
+    /// Telegram notifications about releases are delivery jobs in the database, sent by any
+    /// instance of the cluster
+    pub jobs: bool,
+    /// Collects telegram notifications about new versions instead of sending them, to combine the
+    /// ones about crates of a workspace
+    pub collector: Option<Collector>,
+}
+
+impl Notifiers {
+    /// Sends a notification about the release to a telegram chat, or queues a delivery job for it
+    pub async fn deliver(
+        &self,
+        db: &Database,
+        chat_id: i64,
+        text: String,
+        quiet: bool,
+        receipt: &Receipt,
+    ) {
+        if let (Some(collector), "new") = (&self.collector, receipt.action.as_str()) {
+            collector.push(chat_id, text, quiet, receipt);
+        } else if self.jobs {
+            db.enqueue_delivery(chat_id, &text, quiet, receipt)
+                .await
+                .unwrap_or_else(|err| tracing::error!("db error while queueing delivery: {}", err));
+        } else {
+            self.telegram
+                .push_receipted(chat_id, text, quiet, receipt.clone())
+                .await;
+        }
+    }
+}
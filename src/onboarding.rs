@@ -0,0 +1,183 @@
+//! Guided setup after `/start`: verbosity of notifications, instant updates or a digest, the
+//! timezone and an import of `Cargo.lock`. The current step is stored in the database, so a setup
+//! left halfway continues after a restart of the bot.
+use carapax::types::InlineKeyboardMarkup;
+use tokio_postgres::Error;
+
+use crate::{db::Database, history::button};
+
+/// Time of the digest offered by the setup, `/digest` sets any other
+const DIGEST_TIME: &str = "09:00";
+
+/// Timezones offered by the setup, `/timezone` sets any other
+const TIMEZONES: [&str; 8] = [
+    "UTC",
+    "Europe/London",
+    "Europe/Berlin",
+    "Europe/Moscow",
+    "America/New_York",
+    "America/Los_Angeles",
+    "Asia/Kolkata",
+    "Asia/Tokyo",
+];
+
+pub const DONE: &str = "All set! Use /subscribe to follow more crates, /list to see your subscriptions and /start to go through the setup again.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Verbosity,
+    Delivery,
+    Timezone,
+    Import,
+}
+
+impl Step {
+    /// Name of the step in the database and in callback data
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Step::Verbosity => "verbosity",
+            Step::Delivery => "delivery",
+            Step::Timezone => "timezone",
+            Step::Import => "import",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "verbosity" => Some(Step::Verbosity),
+            "delivery" => Some(Step::Delivery),
+            "timezone" => Some(Step::Timezone),
+            "import" => Some(Step::Import),
+            _ => None,
+        }
+    }
+
+    fn next(self) -> Option<Self> {
+        match self {
+            Step::Verbosity => Some(Step::Delivery),
+            Step::Delivery => Some(Step::Timezone),
+            Step::Timezone => Some(Step::Import),
+            Step::Import => None,
+        }
+    }
+
+    /// Telegram html and keyboard of the step, buttons send `onb {step} {choice}`
+    pub fn render(self) -> (String, InlineKeyboardMarkup) {
+        let choice =
+            |text: &str, choice: &str| button(text, format!("onb {} {}", self.as_str(), choice));
+        let (text, keyboard) = match self {
+            Step::Verbosity => (
+                String::from("<b>1/4</b> How detailed should notifications about new versions be?"),
+                vec![
+                    choice("Short", "off").into_iter().collect(),
+                    choice("With downloads, license and MSRV", "on").into_iter().collect(),
+                ],
+            ),
+            Step::Delivery => (
+                String::from("<b>2/4</b> When should updates come?"),
+                vec![
+                    choice("Immediately", "instant").into_iter().collect(),
+                    choice(&format!("In a daily digest at {}", DIGEST_TIME), "digest")
+                        .into_iter()
+                        .collect(),
+                ],
+            ),
+            Step::Timezone => (
+                String::from("<b>3/4</b> What's your timezone? It's used for the digest and quiet hours, /timezone sets any other."),
+                TIMEZONES
+                    .chunks(2)
+                    .map(|row| row.iter().filter_map(|tz| choice(tz, tz)).collect())
+                    .collect(),
+            ),
+            Step::Import => (
+                String::from("<b>4/4</b> Send me a <code>Cargo.lock</code> or <code>Cargo.toml</code> to subscribe to its dependencies, or use /subscribe to pick crates one by one."),
+                vec![choice("Skip", "skip").into_iter().collect()],
+            ),
+        };
+
+        (text, InlineKeyboardMarkup::from(keyboard))
+    }
+}
+
+/// Starts the setup or resumes the one left halfway, returns its current step
+pub async fn start(db: &Database, chat_id: i64) -> Result<Step, Error> {
+    let step = match db.get_onboarding(chat_id).await? {
+        Some(step) => Step::parse(&step).unwrap_or(Step::Verbosity),
+        None => Step::Verbosity,
+    };
+    db.set_onboarding(chat_id, Some(step.as_str())).await?;
+
+    Ok(step)
+}
+
+/// Applies the choice made at the `step` and moves to the next one, returns the step the chat is
+/// at now (`None` when the setup is finished). A choice made at another step than the current one
+/// (e.g. with a button of an old message) or an unknown choice changes nothing.
+pub async fn choose(
+    db: &Database,
+    chat_id: i64,
+    step: Step,
+    choice: &str,
+) -> Result<Option<Step>, Error> {
+    let current = db
+        .get_onboarding(chat_id)
+        .await?
+        .as_deref()
+        .and_then(Step::parse);
+    if current != Some(step) {
+        return Ok(current);
+    }
+
+    match (step, choice) {
+        (Step::Verbosity, "on") | (Step::Verbosity, "off") => {
+            db.set_verbose(chat_id, choice == "on").await?
+        }
+        (Step::Delivery, "instant") => db.set_digest(chat_id, None).await?,
+        (Step::Delivery, "digest") => db.set_digest(chat_id, Some(DIGEST_TIME)).await?,
+        (Step::Timezone, timezone) if TIMEZONES.contains(&timezone) => {
+            // UTC is the default
+            let stored = Some(timezone).filter(|tz| *tz != "UTC");
+            db.set_timezone(chat_id, stored).await?
+        }
+        (Step::Import, "skip") => {}
+        _ => return Ok(current),
+    }
+
+    let next = step.next();
+    db.set_onboarding(chat_id, next.map(Step::as_str)).await?;
+    Ok(next)
+}
+
+/// Finishes the setup of a chat at the import step, called after a manifest was imported.
+/// `true` if the chat was at that step.
+pub async fn imported(db: &Database, chat_id: i64) -> Result<bool, Error> {
+    if db.get_onboarding(chat_id).await?.as_deref() != Some(Step::Import.as_str()) {
+        return Ok(false);
+    }
+
+    db.set_onboarding(chat_id, None).await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps() {
+        let mut step = Some(Step::Verbosity);
+        let mut names = Vec::new();
+        while let Some(current) = step {
+            assert_eq!(Step::parse(current.as_str()), Some(current));
+            names.push(current.as_str());
+            step = current.next();
+        }
+        assert_eq!(names, ["verbosity", "delivery", "timezone", "import"]);
+
+        // buttons with callback data over the telegram limit would be dropped
+        let (_, markup) = Step::Timezone.render();
+        let markup = serde_json::to_value(&markup).unwrap();
+        let rows = markup["inline_keyboard"].as_array().unwrap();
+        assert!(rows.iter().all(|row| row.as_array().unwrap().len() == 2));
+    }
+}
@@ -0,0 +1,100 @@
+//! Subscriptions to all crates of a crates.io user or team
+use std::time::Duration;
+
+use reqwest::{Client, StatusCode};
+
+use crate::{cratesio, db::Database, util::http_client};
+
+/// How often lists of owners' crates are refreshed
+const REFRESH_DELAY: Duration = Duration::from_secs(6 * 60 * 60);
+
+#[derive(serde::Deserialize)]
+struct OwnerResponse {
+    #[serde(alias = "team")]
+    user: Owner,
+}
+
+#[derive(serde::Deserialize)]
+struct Owner {
+    id: u64,
+}
+
+/// `true` for teams, which are named like `github:org:team`
+fn is_team(owner: &str) -> bool {
+    owner.contains(':')
+}
+
+/// Names of all crates owned by the user or team, `None` if there is no such owner
+pub async fn crates(client: &Client, owner: &str) -> reqwest::Result<Option<Vec<String>>> {
+    let (kind, filter) = if is_team(owner) {
+        ("teams", "team_id")
+    } else {
+        ("users", "user_id")
+    };
+    let response = client
+        .get(&format!("https://crates.io/api/v1/{}/{}", kind, owner))
+        .send()
+        .await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let id = response
+        .error_for_status()?
+        .json::<OwnerResponse>()
+        .await?
+        .user
+        .id;
+
+    let names = cratesio::list_crates(client, &format!("{}={}", filter, id)).await?;
+
+    Ok(Some(names))
+}
+
+async fn refresh(client: &Client, db: &Database) {
+    let owners = match db.list_owners().await {
+        Ok(owners) => owners,
+        Err(err) => {
+            tracing::error!("db error while getting watched owners: {}", err);
+            return;
+        }
+    };
+
+    for owner in owners {
+        let names = match crates(client, &owner).await {
+            Ok(Some(names)) => names,
+            Ok(None) => {
+                tracing::warn!("owner {} doesn't exist anymore", owner);
+                continue;
+            }
+            Err(err) => {
+                tracing::warn!("couldn't get crates of {}: {}", owner, err);
+                continue;
+            }
+        };
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        db.refresh_owner(&owner, &names)
+            .await
+            .unwrap_or_else(|err| tracing::error!("db error while refreshing {}: {}", owner, err));
+    }
+}
+
+/// Subscribes watchers of owners to their newly published crates, forever
+pub async fn run(db: Database) {
+    let client = match http_client() {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::error!(
+                "couldn't create http client, owners won't be refreshed: {}",
+                err
+            );
+            return;
+        }
+    };
+
+    loop {
+        tokio::time::delay_for(REFRESH_DELAY).await;
+        tracing::info!("start refreshing crates of owners");
+        refresh(&client, &db).await;
+        tracing::info!("refreshing crates of owners finished");
+    }
+}
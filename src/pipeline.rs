@@ -0,0 +1,202 @@
+//! Bounded pipeline between the index watchers and the notifiers. Releases (trains of them) are
+//! announced — release notes and api lookups, rendering, queueing messages for the paced senders —
+//! by `[pipeline] lanes` tasks at once. Releases of a crate always go to the same lane, so they're
+//! announced in order. A watcher waits while the lane's queue is full, so a flood of releases
+//! (e.g. an index backfill) neither piles up in memory nor multiplies requests to the apis.
+//! New versions of crates sharing a repository are held before the lanes to be announced together
+//! (see `workspace`).
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    time::Duration,
+};
+
+use tokio::{sync::mpsc, task::JoinHandle};
+use tracing::{Instrument, Span};
+
+use crate::{
+    cfg::{PipelineConfig, SharedConfig},
+    db::Database,
+    index::IndexEvent,
+    metrics,
+    notifier::Notifiers,
+    shutdown::Shutdown,
+    workspace::Workspaces,
+    ActionKind,
+};
+
+/// Releases announced by a lane at once
+enum Batch {
+    /// Releases of a crate, oldest first
+    Train(Vec<IndexEvent>),
+    /// Trains of crates sharing the repository
+    Workspace {
+        repository: String,
+        trains: Vec<Vec<IndexEvent>>,
+    },
+}
+
+/// Handle of the lanes, cloned by every watcher
+pub struct Pipeline {
+    /// Batches with the spans they were submitted in, e.g. of the index event
+    lanes: Vec<mpsc::Sender<(Batch, Span)>>,
+    /// Trains held by repository, each watcher holds its own
+    workspaces: Workspaces,
+    shared: SharedConfig,
+}
+
+impl Clone for Pipeline {
+    fn clone(&self) -> Self {
+        Self {
+            lanes: self.lanes.clone(),
+            workspaces: Workspaces::default(),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Pipeline {
+    /// Spawns the lanes, they stop once all handles are dropped and their queues are empty, or on
+    /// shutdown. Releases left in the queues stay pending in the database and are announced after
+    /// a restart.
+    pub fn start(
+        cfg: &PipelineConfig,
+        notifiers: Notifiers,
+        db: Database,
+        shared: SharedConfig,
+        shutdown: Shutdown,
+    ) -> (Self, Vec<JoinHandle<()>>) {
+        let (lanes, tasks) = (0..cfg.lanes.max(1))
+            .map(|_| {
+                let (tx, rx) = mpsc::channel(cfg.queue.max(1));
+                let task = tokio::spawn(run(
+                    rx,
+                    notifiers.clone(),
+                    db.clone(),
+                    shared.clone(),
+                    shutdown.clone(),
+                ));
+                (tx, task)
+            })
+            .unzip();
+
+        let pipeline = Self {
+            lanes,
+            workspaces: Workspaces::default(),
+            shared,
+        };
+        (pipeline, tasks)
+    }
+
+    /// Queues the train (oldest release first) in the lane of its crate, waits while the lane is
+    /// full. New versions of crates.io crates are held with other crates of their repository if
+    /// `[workspace] window_secs` is set.
+    pub async fn submit(&mut self, train: Vec<IndexEvent>) {
+        let (key, hold) = match train.last() {
+            Some(last) => (
+                last.krate.key(),
+                matches!(last.kind, ActionKind::NewVersion) && last.krate.registry.is_none(),
+            ),
+            None => return,
+        };
+        // e.g. a yank of a held version comes after the group
+        if let Some(repository) = self.workspaces.holding(&key) {
+            self.depart_group(repository).await;
+        }
+
+        let cfg = self.shared.get();
+        if hold && cfg.workspace.window_secs > 0 {
+            let name = train.last().map(|last| last.krate.id.name.clone());
+            let repository = match name {
+                Some(name) => self.workspaces.repository(&name, &cfg.workspace).await,
+                None => None,
+            };
+            if let Some(repository) = repository {
+                tracing::debug!("held with other crates of {}", repository);
+                self.workspaces.push(repository, train);
+                return;
+            }
+        }
+        self.send(&key, Batch::Train(train)).await;
+    }
+
+    /// Queues groups of crates which haven't got new versions for `[workspace] window_secs` or
+    /// are held for `max_hold_secs`
+    pub async fn depart(&mut self) {
+        let cfg = self.shared.get();
+        let window = Duration::from_secs(cfg.workspace.window_secs);
+        let max_hold = Duration::from_secs(cfg.workspace.max_hold_secs);
+        for (repository, trains) in self.workspaces.departed(window, max_hold) {
+            self.send_group(repository, trains).await;
+        }
+    }
+
+    async fn depart_group(&mut self, repository: String) {
+        if let Some(trains) = self.workspaces.take(&repository) {
+            self.send_group(repository, trains).await;
+        }
+    }
+
+    /// Queues the group in the lane of its repository, a single crate is announced as usual
+    async fn send_group(&mut self, repository: String, mut trains: Vec<Vec<IndexEvent>>) {
+        if trains.len() == 1 {
+            let train = trains.remove(0);
+            if let Some(key) = train.last().map(|last| last.krate.key()) {
+                self.send(&key, Batch::Train(train)).await;
+            }
+            return;
+        }
+        let batch = Batch::Workspace {
+            repository: repository.clone(),
+            trains,
+        };
+        self.send(&repository, batch).await;
+    }
+
+    async fn send(&mut self, key: &str, batch: Batch) {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let lane = hasher.finish() as usize % self.lanes.len();
+
+        metrics::PIPELINE_QUEUE_DEPTH.inc();
+        let queued = self.lanes[lane].send((batch, Span::current())).await;
+        if queued.is_err() {
+            metrics::PIPELINE_QUEUE_DEPTH.dec();
+            tracing::info!("pipeline is stopped, {} is left pending", key);
+        }
+    }
+}
+
+async fn run(
+    mut rx: mpsc::Receiver<(Batch, Span)>,
+    notifiers: Notifiers,
+    db: Database,
+    shared: SharedConfig,
+    mut shutdown: Shutdown,
+) {
+    loop {
+        let batch = tokio::select! {
+            batch = rx.recv() => batch,
+            _ = shutdown.requested() => None,
+        };
+        let (batch, span) = match batch {
+            Some(batch) => batch,
+            None => return,
+        };
+        metrics::PIPELINE_QUEUE_DEPTH.dec();
+
+        let cfg = shared.get();
+        match batch {
+            Batch::Train(train) => {
+                crate::announce(train, &notifiers, &db, &cfg)
+                    .instrument(span)
+                    .await
+            }
+            Batch::Workspace { repository, trains } => {
+                crate::announce_workspace(&repository, trains, &notifiers, &db, &cfg)
+                    .instrument(span)
+                    .await
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! `/preview` and the `preview` subcommand: the notification about a version exactly as a chat
+//! would get it. Nothing is recorded or sent, for debugging templates and rendering of changelogs.
+use crate::{
+    cfg::Config, db::Database, krate::Crate, notification, template::Template,
+    verbosity::Verbosity, ActionKind,
+};
+
+/// Notification about `version` of `krate` rendered with the settings of the chat (the template,
+/// verbosity of metadata and release notes, the filter of the crate and the toolchain) or with the
+/// default ones if `chat_id` is `None`. `None` if there is no such version.
+pub async fn render(
+    db: &Database,
+    cfg: &Config,
+    chat_id: Option<i64>,
+    krate: &str,
+    version: &str,
+) -> Result<Option<String>, tokio_postgres::Error> {
+    let release = match Crate::read_all(krate, cfg).await {
+        Ok(all) => all.into_iter().find(|release| release.id.vers == version),
+        Err(_) => None,
+    };
+    let release = match release {
+        Some(release) => release,
+        None => return Ok(None),
+    };
+
+    let (template, verbose, verbosity, filter, toolchain) = match chat_id {
+        Some(chat_id) => (
+            db.get_template(chat_id)
+                .await?
+                .and_then(|template| Template::parse(&template).ok()),
+            db.get_verbose(chat_id).await?,
+            db.get_notes_verbosity(chat_id).await?,
+            db.get_filter(chat_id, krate).await?.unwrap_or_default(),
+            db.get_msrv(chat_id).await?,
+        ),
+        None => (None, false, Verbosity::Full, Default::default(), None),
+    };
+    let (text, _) = notification(
+        &release,
+        &ActionKind::NewVersion,
+        template.as_ref(),
+        verbose,
+        verbosity,
+        filter,
+        toolchain.as_deref(),
+        None,
+        cfg,
+    )
+    .await;
+
+    Ok(Some(text))
+}
+
+/// Runs the subcommand with arguments after `preview`: `<crate> <version> [--chat <id>]`,
+/// prints telegram html
+pub async fn run(args: &[&str], db: &Database, cfg: &Config) {
+    let (krate, version, chat_id) = match args {
+        [krate, version] => (krate, version, None),
+        [krate, version, "--chat", chat_id] => match chat_id.parse() {
+            Ok(chat_id) => (krate, version, Some(chat_id)),
+            Err(_) => {
+                eprintln!("error: invalid chat id `{}`", chat_id);
+                std::process::exit(2);
+            }
+        },
+        _ => {
+            eprintln!("usage: crate_upd_bot preview <crate> <version> [--chat <chat_id>]");
+            std::process::exit(2);
+        }
+    };
+
+    match render(db, cfg, chat_id, krate, version).await {
+        Ok(Some(text)) => println!("{}", text),
+        Ok(None) => {
+            eprintln!("error: there is no version {} of {}", version, krate);
+            std::process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("error: db error while getting chat settings: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
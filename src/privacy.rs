@@ -0,0 +1,33 @@
+//! `/my_data` and `/forget_me`: everything the bot stores about a chat as a JSON file, and
+//! erasing it. The tables are listed by `chat_data` and `forget_chat` in `db.sql` (and by
+//! `migrate_chat` and `import_chat`), a table getting rows of chats must be added to all of them.
+use carapax::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use crate::{bot::HErr, db::Database};
+
+/// Callback data of the `/forget_me` buttons
+pub const CONFIRM: &str = "forget_me";
+pub const CANCEL: &str = "forget_me_cancel";
+
+/// Question of `/forget_me` and its buttons
+pub fn confirmation() -> (&'static str, InlineKeyboardMarkup) {
+    let text =
+        "Delete everything stored about this chat: subscriptions, settings, e-mail address, \
+                tokens, queued and deferred notifications and the history of sent ones? \
+                This can't be undone, /my_data exports the data first.";
+    let markup = InlineKeyboardMarkup::from(vec![vec![
+        InlineKeyboardButton::with_callback_data("🗑 Delete", String::from(CONFIRM)),
+        InlineKeyboardButton::with_callback_data("Cancel", String::from(CANCEL)),
+    ]]);
+    (text, markup)
+}
+
+/// Everything stored about the chat as pretty JSON, with the e-mail address decrypted
+pub async fn export(db: &Database, chat_id: i64) -> Result<Vec<u8>, HErr> {
+    let mut data: serde_json::Value = serde_json::from_str(&db.chat_data(chat_id).await?)?;
+    if let Some(email) = db.get_email(chat_id).await? {
+        data["email"]["address"] = email.address.into();
+    }
+
+    Ok(serde_json::to_vec_pretty(&data)?)
+}
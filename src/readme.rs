@@ -0,0 +1,118 @@
+//! Changes of READMEs between versions, for subscriptions with the `readme` filter word
+use std::collections::HashMap;
+
+use reqwest::{Client, StatusCode};
+
+use crate::{
+    cache::{self, Source},
+    krate::Crate,
+};
+
+/// Smaller changes (e.g. a bumped version in an example) aren't announced
+const MIN_CHANGED_CHARS: usize = 200;
+
+/// README of the version rendered by crates.io, `None` if the version has none
+async fn fetch(client: &Client, name: &str, version: &str) -> Result<Option<String>, cache::Error> {
+    let request = client.get(&format!(
+        "https://static.crates.io/readmes/{name}/{name}-{version}.html",
+        name = name,
+        version = version
+    ));
+    let response = cache::get(client, request, Source::Changelog).await?;
+    // the bucket responds with 403 to missing files
+    if matches!(
+        response.status,
+        StatusCode::NOT_FOUND | StatusCode::FORBIDDEN
+    ) {
+        return Ok(None);
+    }
+
+    Ok(Some(response.error_for_status()?.body))
+}
+
+/// Text of the rendered README: tags are removed, lines are trimmed, empty ones are skipped
+fn text(html: &str) -> Vec<String> {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    text.lines()
+        .map(|line| {
+            line.trim()
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&amp;", "&")
+        })
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Characters of lines added or removed in `new` compared to `old`, the order of lines is ignored
+fn changed_chars(old: &[String], new: &[String]) -> usize {
+    let mut counts: HashMap<&str, isize> = HashMap::new();
+    for line in old {
+        *counts.entry(line).or_default() -= 1;
+    }
+    for line in new {
+        *counts.entry(line).or_default() += 1;
+    }
+
+    counts
+        .iter()
+        .map(|(line, count)| line.chars().count() * count.abs() as usize)
+        .sum()
+}
+
+/// Lines of the text of the version's README (see [`text`]), `None` if it has none or it couldn't
+/// be fetched. Only crates.io crates have READMEs rendered.
+pub async fn lines(client: &Client, krate: &Crate) -> Option<Vec<String>> {
+    if krate.registry.is_some() {
+        return None;
+    }
+
+    let (name, version) = (&krate.id.name, &krate.id.vers);
+    let html = fetch(client, name, version)
+        .await
+        .map_err(|err| tracing::warn!("couldn't get README of {} {}: {}", name, version, err))
+        .ok()??;
+    Some(text(&html))
+}
+
+/// Note about the README of `new` changing materially since `old`, as telegram html.
+/// Only crates.io crates have READMEs rendered.
+pub async fn change(client: &Client, old: &Crate, new: &Crate) -> Option<String> {
+    let old_text = lines(client, old).await.unwrap_or_default();
+    let new_text = lines(client, new).await?;
+    if changed_chars(&old_text, &new_text) < MIN_CHANGED_CHARS {
+        return None;
+    }
+
+    Some(format!(
+        "📘 <b>README updated</b> <a href='{}'>[readme]</a>",
+        new.cratesio()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes() {
+        let old = text("<h1>foo</h1>\n<p>Add <code>foo = &quot;1.0&quot;</code></p>\n<p>Usage</p>");
+        assert_eq!(old, ["foo", "Add foo = \"1.0\"", "Usage"]);
+
+        let new = text("<h1>foo</h1>\n<p>Usage</p>\n<p>Add <code>foo = &quot;2.0&quot;</code></p>");
+        assert_eq!(changed_chars(&old, &old), 0);
+        // one line was replaced, the moved one doesn't count
+        assert_eq!(changed_chars(&old, &new), 32);
+    }
+}
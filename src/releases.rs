@@ -0,0 +1,300 @@
+//! Persistent per-crate cache of releases: all versions from the index (with their checksums,
+//! features and dependencies) and publish dates from crates.io. Commands answer from it without
+//! network calls, a background task refetches crates used recently. When the index can't be
+//! reached, stale releases are answered from and the bot is in the degraded mode shown by
+//! `/status`; `cache.offline` stops refetching altogether.
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    cfg::{Config, SharedConfig},
+    cratesio,
+    krate::{split_key, Crate},
+    util::{http_client, rfc3339},
+};
+
+/// How often the cache is checked for releases to refetch
+const REFRESH_DELAY: Duration = Duration::from_secs(60);
+
+/// Releases not used by commands for this long aren't refetched anymore, but removed
+const MAX_IDLE_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Set when the index couldn't be reached, cleared by the next successful fetch
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Whether stale releases are answered from, because the index couldn't be reached
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Cached releases of a crate
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Releases {
+    /// `myreg:name` for crates of alternative registries
+    key: String,
+    /// All versions, oldest first
+    pub versions: Vec<Crate>,
+    /// Publish dates (unix time) by version, known for crates.io crates only
+    #[serde(default)]
+    pub published: HashMap<String, i64>,
+    /// Unix time of the last fetch
+    pub fetched_at: u64,
+    /// Unix time of the last use by a command, updated at most once per TTL
+    used_at: u64,
+    /// The index couldn't be reached when the releases were asked for after their TTL
+    #[serde(skip)]
+    stale: bool,
+}
+
+impl Releases {
+    /// Unix time of the fetch if the releases are stale
+    pub fn stale(&self) -> Option<u64> {
+        Some(self.fetched_at).filter(|_| self.stale)
+    }
+}
+
+/// Warning added to answers from stale releases
+pub fn note(fetched_at: u64) -> String {
+    let time = rfc3339(fetched_at as i64);
+    format!(
+        "⚠️ The index can't be reached, this is data as of {} UTC.",
+        time[..16].replace('T', " ")
+    )
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+fn dir(cfg: &Config) -> PathBuf {
+    Path::new(&cfg.cache.dir).join("releases")
+}
+
+/// File of the crate's releases, names are hashed since keys come from users
+fn path(key: &str, cfg: &Config) -> PathBuf {
+    dir(cfg).join(format!(
+        "{}.json",
+        hex::encode(Sha256::digest(key.as_bytes()))
+    ))
+}
+
+async fn read(path: &Path) -> Option<Releases> {
+    let bytes = tokio::fs::read(path).await.ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+async fn write(path: &Path, releases: &Releases) {
+    let result = async {
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        tokio::fs::write(path, serde_json::to_vec(releases)?).await
+    };
+    if let Err(err) = result.await {
+        tracing::warn!("couldn't write releases {}: {}", path.display(), err);
+    }
+}
+
+/// Reads the releases from the index and crates.io. Publish dates which couldn't be fetched are
+/// taken from `known`.
+async fn fetch(key: &str, cfg: &Config, known: HashMap<String, i64>) -> io::Result<Releases> {
+    let versions = match Crate::read_all(key, cfg).await {
+        Ok(versions) => versions,
+        Err(err) => {
+            if err.kind() != io::ErrorKind::NotFound {
+                OFFLINE.store(true, Ordering::Relaxed);
+            }
+            return Err(err);
+        }
+    };
+    OFFLINE.store(false, Ordering::Relaxed);
+
+    let mut published = known;
+    if let (Some(newest), (None, _)) = (versions.last(), split_key(key)) {
+        let publishes = match http_client() {
+            Ok(client) => cratesio::publishes(&client, key, &newest.id.vers)
+                .await
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        match publishes {
+            Ok(publishes) => published.extend(
+                publishes
+                    .into_iter()
+                    .map(|publish| (publish.version, publish.created_at)),
+            ),
+            Err(err) => tracing::warn!("couldn't get publish dates of {}: {}", key, err),
+        }
+    }
+
+    Ok(Releases {
+        key: key.to_owned(),
+        versions,
+        published,
+        fetched_at: now(),
+        used_at: now(),
+        stale: false,
+    })
+}
+
+/// Gives the cached releases their registry, which isn't stored
+fn attach_registry(releases: &mut Releases, cfg: &Config) {
+    let registry = split_key(&releases.key)
+        .0
+        .and_then(|registry| cfg.registry(registry))
+        .cloned();
+    for krate in &mut releases.versions {
+        krate.registry = registry.clone();
+    }
+}
+
+/// Releases of the crate (`myreg:name` for alternative registries): cached ones if they're
+/// younger than the TTL, otherwise fetched. Stale releases are given if the index can't be
+/// reached (or in the offline mode).
+pub async fn get(key: &str, cfg: &Config) -> io::Result<Releases> {
+    let path = path(key, cfg);
+    let cached = read(&path).await.filter(|releases| releases.key == key);
+    let ttl = cfg.cache.releases_ttl_secs;
+    let now = now();
+    let mut cached = match cached {
+        Some(mut cached) if cfg.cache.offline || now.saturating_sub(cached.fetched_at) < ttl => {
+            if now.saturating_sub(cached.used_at) >= ttl {
+                cached.used_at = now;
+                write(&path, &cached).await;
+            }
+            cached.stale = now.saturating_sub(cached.fetched_at) >= ttl;
+            attach_registry(&mut cached, cfg);
+            return Ok(cached);
+        }
+        cached => cached,
+    };
+
+    let known = cached
+        .as_mut()
+        .map(|cached| std::mem::take(&mut cached.published))
+        .unwrap_or_default();
+    match (fetch(key, cfg, known.clone()).await, cached) {
+        (Ok(releases), _) => {
+            write(&path, &releases).await;
+            Ok(releases)
+        }
+        (Err(err), Some(mut cached)) if err.kind() != io::ErrorKind::NotFound => {
+            tracing::warn!(
+                "couldn't fetch releases of {}, answering from the cache: {}",
+                key,
+                err
+            );
+            cached.published = known;
+            cached.stale = true;
+            attach_registry(&mut cached, cfg);
+            Ok(cached)
+        }
+        (Err(err), _) => Err(err),
+    }
+}
+
+/// Refetches cached releases of the crate after its new release or yank, so commands don't
+/// answer with the old versions until the TTL passes
+pub async fn update(key: &str, cfg: &Config) {
+    let path = path(key, cfg);
+    let cached = match read(&path).await.filter(|releases| releases.key == key) {
+        Some(cached) if !cfg.cache.offline => cached,
+        _ => return,
+    };
+    match fetch(key, cfg, cached.published).await {
+        Ok(releases) => {
+            write(
+                &path,
+                &Releases {
+                    used_at: cached.used_at,
+                    ..releases
+                },
+            )
+            .await
+        }
+        Err(err) => tracing::warn!("couldn't update releases of {}: {}", key, err),
+    }
+}
+
+/// Refetches expired releases which were used recently and removes ones idle for long
+async fn refresh(cfg: &Config) -> io::Result<()> {
+    let mut entries = match tokio::fs::read_dir(dir(cfg)).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    let ttl = cfg.cache.releases_ttl_secs;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let cached = match read(&path).await {
+            Some(cached) => cached,
+            None => continue,
+        };
+        let now = now();
+        if now.saturating_sub(cached.used_at) > MAX_IDLE_SECS {
+            tokio::fs::remove_file(&path).await?;
+            continue;
+        }
+        if now.saturating_sub(cached.fetched_at) < ttl {
+            continue;
+        }
+
+        match fetch(&cached.key, cfg, cached.published).await {
+            Ok(releases) => {
+                write(
+                    &path,
+                    &Releases {
+                        used_at: cached.used_at,
+                        ..releases
+                    },
+                )
+                .await
+            }
+            Err(err) => {
+                tracing::warn!("couldn't refresh releases of {}: {}", cached.key, err);
+                // the rest would fail too
+                if is_offline() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Keeps releases of crates used by commands fresh, forever
+pub async fn run(shared: SharedConfig) {
+    loop {
+        tokio::time::delay_for(REFRESH_DELAY).await;
+        let cfg = shared.get();
+        if cfg.cache.offline || cfg.cache.releases_ttl_secs == 0 {
+            continue;
+        }
+        if let Err(err) = refresh(&cfg).await {
+            tracing::error!("couldn't refresh the release cache: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notes() {
+        assert_eq!(
+            note(1_622_548_800),
+            "⚠️ The index can't be reached, this is data as of 2021-06-01 12:00 UTC."
+        );
+    }
+}
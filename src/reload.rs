@@ -0,0 +1,47 @@
+//! Hot reload: `config.toml` is watched and reloaded on changes (also by `/admin reload`)
+use std::{path::Path, sync::mpsc, time::Duration};
+
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+
+use crate::cfg::SharedConfig;
+
+/// Events of the file are collected for this long, editors may write it several times
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+fn is_config(path: &Path) -> bool {
+    path.file_name().map_or(false, |name| name == "config.toml")
+}
+
+/// Reloads the config whenever the file changes, blocks the thread forever
+pub fn watch(cfg: SharedConfig) {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = match watcher(tx, DEBOUNCE) {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            tracing::error!("couldn't watch the config, it won't be reloaded: {}", err);
+            return;
+        }
+    };
+    // editors often replace the file instead of writing to it, so the directory is watched
+    if let Err(err) = watcher.watch(".", RecursiveMode::NonRecursive) {
+        tracing::error!("couldn't watch the config, it won't be reloaded: {}", err);
+        return;
+    }
+
+    for event in rx {
+        match event {
+            DebouncedEvent::Write(path)
+            | DebouncedEvent::Create(path)
+            | DebouncedEvent::Rename(_, path)
+                if is_config(&path) =>
+            {
+                // rejected changes are logged by `reload`
+                let _ = cfg.reload();
+            }
+            DebouncedEvent::Error(err, _) => {
+                tracing::warn!("error while watching the config: {}", err)
+            }
+            _ => {}
+        }
+    }
+}
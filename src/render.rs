@@ -0,0 +1,257 @@
+//! Helpers for messages in telegram html format
+
+/// Maximum number of entities (formatting tags, links) telegram accepts in a message
+pub const MAX_ENTITIES: usize = 100;
+
+/// Maximum length of a message text (in UTF-16 code units, tags excluded)
+pub const MAX_LENGTH: usize = 4096;
+
+/// Makes telegram html fit into the message limits.
+///
+/// Tags after the `max_entities`-th are removed (their text is kept) and the text is
+/// truncated to `max_len` (UTF-16 code units, tags excluded) with `…`. Expects well-formed html.
+pub fn fit(html: &str, max_entities: usize, max_len: usize) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut entities = 0;
+    let mut len = 0;
+    // (tag name, whether the tag was kept)
+    let mut open: Vec<(&str, bool)> = Vec::new();
+
+    let mut rest = html;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '<' => {
+                let end = match rest.find('>') {
+                    Some(end) => end,
+                    None => break,
+                };
+                let tag = &rest[..=end];
+                rest = &rest[end + 1..];
+
+                if let Some(name) = tag.strip_prefix("</") {
+                    let name = name.trim_end_matches('>').trim();
+                    if let Some(idx) = open.iter().rposition(|&(n, _)| n == name) {
+                        let (_, kept) = open.remove(idx);
+                        if kept {
+                            out.push_str(tag);
+                        }
+                    }
+                } else {
+                    let name = tag[1..tag.len() - 1]
+                        .split_whitespace()
+                        .next()
+                        .unwrap_or_default();
+                    let kept = entities < max_entities;
+                    if kept {
+                        entities += 1;
+                        out.push_str(tag);
+                    }
+                    open.push((name, kept));
+                }
+            }
+            _ => {
+                // `&amp;`-like escapes are a single char
+                let token = match c {
+                    '&' => rest.find(';').map_or(&rest[..1], |end| &rest[..=end]),
+                    _ => &rest[..c.len_utf8()],
+                };
+                let token_len = if c == '&' { 1 } else { c.len_utf16() };
+
+                // reserve space for `…`
+                if len + token_len > max_len.saturating_sub(1) && rest.len() > token.len() {
+                    out.push('…');
+                    break;
+                }
+
+                len += token_len;
+                out.push_str(token);
+                rest = &rest[token.len()..];
+            }
+        }
+    }
+
+    // close tags left open by truncation
+    for (name, kept) in open.into_iter().rev() {
+        if kept {
+            out.push_str("</");
+            out.push_str(name);
+            out.push('>');
+        }
+    }
+
+    out
+}
+
+/// [`fit`] with telegram limits
+pub fn fit_message(html: &str) -> String {
+    fit(html, MAX_ENTITIES, MAX_LENGTH)
+}
+
+/// Length of telegram html as telegram counts it: UTF-16 code units of the text, tags excluded
+pub fn text_len(html: &str) -> usize {
+    plain(html).encode_utf16().count()
+}
+
+/// Escapes text for telegram html
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+/// Plain text of telegram html: tags are removed and entities are unescaped
+pub fn plain(html: &str) -> String {
+    unescape(&fit(html, 0, usize::MAX))
+}
+
+/// `href` of an `<a>` tag (`tag` is without `<` and `>`)
+fn href(tag: &str) -> String {
+    let value = match tag.find("href=") {
+        Some(start) => &tag[start + "href=".len()..],
+        None => return String::new(),
+    };
+    let quote = match value.chars().next() {
+        Some(quote @ '\'') | Some(quote @ '"') => quote,
+        _ => return String::new(),
+    };
+    let value = &value[1..];
+    unescape(&value[..value.find(quote).unwrap_or(value.len())])
+}
+
+/// Discord markdown of telegram html. Expects well-formed html.
+pub fn markdown(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    // (tag name, markdown closing the tag)
+    let mut open: Vec<(&str, String)> = Vec::new();
+    // markdown inside of code blocks is shown as is
+    let mut code = 0;
+
+    let mut rest = html;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '<' => {
+                let end = match rest.find('>') {
+                    Some(end) => end,
+                    None => break,
+                };
+                let tag = &rest[1..end];
+                rest = &rest[end + 1..];
+
+                if let Some(name) = tag.strip_prefix('/') {
+                    let name = name.trim();
+                    if let Some(idx) = open.iter().rposition(|(n, _)| *n == name) {
+                        let (name, closing) = open.remove(idx);
+                        if name == "code" || name == "pre" {
+                            code -= 1;
+                        }
+                        out.push_str(&closing);
+                    }
+                } else if !tag.ends_with('/') {
+                    let name = tag.split_whitespace().next().unwrap_or_default();
+                    let (opening, closing) = match name {
+                        "b" | "strong" => ("**".to_owned(), "**".to_owned()),
+                        "i" | "em" => ("*".to_owned(), "*".to_owned()),
+                        "u" | "ins" => ("__".to_owned(), "__".to_owned()),
+                        "s" | "strike" | "del" => ("~~".to_owned(), "~~".to_owned()),
+                        // `<pre><code>` is a single block
+                        "code" if code > 0 => (String::new(), String::new()),
+                        "code" => ("`".to_owned(), "`".to_owned()),
+                        "pre" => ("```\n".to_owned(), "\n```".to_owned()),
+                        "a" => ("[".to_owned(), format!("]({})", href(tag))),
+                        _ => (String::new(), String::new()),
+                    };
+                    if name == "code" || name == "pre" {
+                        code += 1;
+                    }
+                    out.push_str(&opening);
+                    open.push((name, closing));
+                }
+            }
+            '&' => {
+                let end = rest.find(';').unwrap_or(0);
+                out.push_str(&unescape(&rest[..=end]));
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                if code == 0 && "\\*_~`|".contains(c) {
+                    out.push('\\');
+                }
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entities() {
+        assert_eq!(
+            fit("<b>a</b> <a href='x'>b</a> <code>c</code>", 2, MAX_LENGTH),
+            "<b>a</b> <a href='x'>b</a> c"
+        );
+        assert_eq!(fit("<b><i>a</i> b</b>", 1, MAX_LENGTH), "<b>a b</b>");
+    }
+
+    #[test]
+    fn length() {
+        assert_eq!(fit("abc", 100, 3), "abc");
+        assert_eq!(fit("abcd", 100, 3), "ab…");
+        assert_eq!(fit("<b>abcd</b>", 100, 3), "<b>ab…</b>");
+        assert_eq!(fit("&lt;&lt;&lt;", 100, 3), "&lt;&lt;&lt;");
+    }
+
+    #[test]
+    fn many_links() {
+        // like `/list` with 50 subscriptions
+        let html: String = (0..50)
+            .map(|i| {
+                format!(
+                    "— <code>crate{i}</code> <a href='https://docs.rs/crate{i}'>[docs.rs]</a> \
+                     <a href='https://crates.io/crates/crate{i}'>[crates.io]</a>\n",
+                    i = i
+                )
+            })
+            .collect();
+        let fitted = fit_message(&html);
+
+        assert_eq!(
+            fitted.matches("<a ").count() + fitted.matches("<code>").count(),
+            100
+        );
+        assert_eq!(fitted.matches("<").count(), 200);
+        assert!(fitted.contains("crate49"));
+    }
+
+    #[test]
+    fn plain_text() {
+        assert_eq!(
+            plain("<b>a &amp;lt;</b> <a href='x'>&lt;b&gt;</a>"),
+            "a &lt; <b>"
+        );
+    }
+
+    #[test]
+    fn discord_markdown() {
+        assert_eq!(
+            markdown("<b>a</b> <a href='https://x.y/?a=1&amp;b=2'>b_c</a> <code>d_e</code> &lt;"),
+            "**a** [b\\_c](https://x.y/?a=1&b=2) `d_e` <"
+        );
+        assert_eq!(
+            markdown("<pre><code class=\"language-rust\">let *a = 1;</code></pre>"),
+            "```\nlet *a = 1;\n```"
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! `events replay` subcommand: re-emits index events through the notification pipeline, e.g. to
+//! resend notifications missed because of a bug or an outage. Chats which got a notification about
+//! the release before aren't notified again.
+use std::{collections::HashSet, sync::Arc};
+
+use carapax::Api;
+use kacl_parser::Date;
+
+use crate::{
+    cfg::Config,
+    db::Database,
+    discord::DiscordQueue,
+    index::{git::GitIndex, IndexEvent, IndexKind},
+    krate::{split_key, Crate},
+    matrix::MatrixQueue,
+    notifier::Notifiers,
+    send::SendQueue,
+    ActionKind,
+};
+
+const USAGE: &str =
+    "usage: crate_upd_bot events replay --since <commit|YYYY-MM-DD|unix time> [--send]
+
+Without --send, only lists the events and how many of their subscribers weren't notified.";
+
+/// Where a replay starts
+#[derive(Debug, PartialEq, Eq)]
+pub enum Since {
+    /// Commits of the git index after this one (a hash or a revision like `HEAD~10`)
+    Commit(String),
+    /// Unix time
+    Time(i64),
+}
+
+impl Since {
+    /// Parses unix time, `YYYY-MM-DD` (midnight UTC) or a commit of the git index
+    pub fn parse(s: &str) -> Self {
+        if let Ok(secs) = s.parse() {
+            return Since::Time(secs);
+        }
+        match Date::parse(s) {
+            Ok(("", date)) if date.is_valid() => Since::Time(unix_time(date)),
+            _ => Since::Commit(s.to_owned()),
+        }
+    }
+}
+
+/// Unix time of the midnight (UTC) of `date`, see
+/// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+pub(crate) fn unix_time(date: Date) -> i64 {
+    let (month, day) = (i64::from(date.month), i64::from(date.day));
+    let year = i64::from(date.year) - if month <= 2 { 1 } else { 0 };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    (era * 146_097 + day_of_era - 719_468) * 86400
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Args {
+    since: Since,
+    /// `false` for a dry run
+    send: bool,
+}
+
+fn parse_args(args: &[&str]) -> Result<Args, String> {
+    let mut since = None;
+    let mut send = false;
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        match arg {
+            "--since" => {
+                let value = args.next().ok_or("--since needs a value")?;
+                since = Some(Since::parse(value));
+            }
+            "--send" => send = true,
+            arg => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+
+    Ok(Args {
+        since: since.ok_or("--since is required")?,
+        send,
+    })
+}
+
+/// Events of crates with subscribers to replay, oldest first. Commits of the crates.io git index are diffed again, releases
+/// of the sparse index and of alternative registries are taken from the release archive (only new
+/// versions, yanks aren't archived).
+async fn events(since: &Since, db: &Database, cfg: &Config) -> Result<Vec<IndexEvent>, String> {
+    let git = cfg.index.kind == IndexKind::Git;
+    let mut events = Vec::new();
+    if git {
+        let index = GitIndex::open_or_clone(&cfg.index_url, &cfg.index_path, None);
+        events = index
+            .history(since)
+            .map_err(|err| format!("couldn't read the git index: {}", err))?
+            .into_iter()
+            .map(|(_, event)| event)
+            .collect();
+    }
+
+    let releases = match since {
+        Since::Time(time) => db
+            .releases_since(*time)
+            .await
+            .map_err(|err| format!("db error while getting releases: {}", err))?,
+        Since::Commit(_) if git => Vec::new(),
+        Since::Commit(_) => return Err(String::from("commits are known only for the git index")),
+    };
+    for (key, version, _yanked, published_at) in releases {
+        // replayed from the git index
+        if git && split_key(&key).0.is_none() {
+            continue;
+        }
+        let krate = Crate::read_all(&key, cfg)
+            .await
+            .map_err(|err| tracing::warn!("couldn't read versions of {}: {}", key, err))
+            .ok()
+            .and_then(|all| all.into_iter().find(|krate| krate.id.vers == version));
+        match krate {
+            Some(krate) => events.push(IndexEvent {
+                krate,
+                kind: ActionKind::NewVersion,
+                published_at,
+                created: false,
+            }),
+            None => tracing::warn!("{} {} isn't in the index, skipped", key, version),
+        }
+    }
+    events.sort_by_key(|event| event.published_at);
+
+    let subscribed: HashSet<String> = db
+        .list_subscribed_crates()
+        .await
+        .map_err(|err| format!("db error while getting subscribed crates: {}", err))?
+        .into_iter()
+        .collect();
+    events.retain(|event| subscribed.contains(&event.krate.key()));
+
+    Ok(events)
+}
+
+/// Prints the event and how many of its subscribers weren't notified
+async fn describe(event: &IndexEvent, db: &Database) -> Result<(), tokio_postgres::Error> {
+    let key = event.krate.key();
+    let subscribers = db.list_subscribers(&key).await?;
+    let delivered = db
+        .delivered_chats(&key, &event.krate.id.vers, event.kind.as_str())
+        .await?;
+    let missed = subscribers
+        .iter()
+        .filter(|subscriber| !delivered.contains(&subscriber.chat_id))
+        .count();
+    println!(
+        "{} {} {}: {} of {} subscribers not notified",
+        key,
+        event.krate.id.vers,
+        event.kind.as_str(),
+        missed,
+        subscribers.len()
+    );
+
+    Ok(())
+}
+
+/// Runs the subcommand with arguments after `events replay`
+pub async fn run(args: &[&str], db: &Database, cfg: Arc<Config>) {
+    let args = match parse_args(args) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let events = match events(&args.since, db, &cfg).await {
+        Ok(events) => events,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    };
+    tracing::info!("replaying {} events", events.len());
+
+    if !args.send {
+        for event in &events {
+            describe(event, db)
+                .await
+                .unwrap_or_else(|err| tracing::error!("db error while describing event: {}", err));
+        }
+        return;
+    }
+
+    let bot = Api::new(carapax::Config::new(&cfg.bot_token)).expect("couldn't create Api");
+    let notifiers = Notifiers {
+        telegram: SendQueue::start(bot, Arc::clone(&cfg), db.clone()),
+        matrix: MatrixQueue::start(Arc::clone(&cfg)),
+        discord: DiscordQueue::start(Arc::clone(&cfg)),
+        jobs: cfg.cluster.is_some(),
+        collector: None,
+    };
+    for event in events {
+        let key = event.krate.key();
+        // pending until the notifications are sent, like releases of the index
+        db.record_release(
+            &key,
+            &event.krate.id.vers,
+            event.krate.yanked,
+            event.published_at,
+            event.kind,
+        )
+        .await
+        .unwrap_or_else(|err| tracing::error!("db error while recording release: {}", err));
+        crate::announce(vec![event], &notifiers, db, &cfg).await;
+    }
+    notifiers.telegram.flush().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arguments() {
+        assert_eq!(
+            parse_args(&["--since", "2021-06-01", "--send"]),
+            Ok(Args {
+                since: Since::Time(1_622_505_600),
+                send: true,
+            })
+        );
+        assert_eq!(
+            parse_args(&["--since", "1622505600"]).map(|args| args.since),
+            Ok(Since::Time(1_622_505_600))
+        );
+        assert_eq!(
+            parse_args(&["--since", "4f1b2c3"]),
+            Ok(Args {
+                since: Since::Commit(String::from("4f1b2c3")),
+                send: false,
+            })
+        );
+        assert!(parse_args(&["--send"]).is_err());
+        assert_eq!(unix_time(Date::new(1970, 1, 1).unwrap()), 0);
+        assert_eq!(unix_time(Date::new(2000, 3, 1).unwrap()), 951_868_800);
+    }
+}
@@ -0,0 +1,365 @@
+//! Source repositories of crates: raw files and compare views of release tags on GitHub, GitLab
+//! (including self-hosted instances), Gitea/Forgejo and sourcehut
+use reqwest::{header::AUTHORIZATION, Client};
+
+use crate::{
+    cache::{self, Source},
+    cratesio::repository,
+    util::http_client,
+};
+
+/// Kind of the code forge a repository is hosted on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForgeKind {
+    GitHub,
+    GitLab,
+    /// Gitea or its fork Forgejo (e.g. codeberg.org)
+    Gitea,
+    /// sr.ht or a self-hosted sourcehut
+    SourceHut,
+}
+
+impl ForgeKind {
+    /// Guesses the forge from the host and the path of a repository url. Self-hosted instances are
+    /// recognized by `gitlab.`/`gitea.`/`forgejo.` hosts, GitLab's `/-/` and sourcehut's `~user`.
+    fn of(host: &str, path: &str) -> Option<Self> {
+        Some(match host {
+            "github.com" => ForgeKind::GitHub,
+            _ if path.starts_with('~') => ForgeKind::SourceHut,
+            "gitlab.com" | "framagit.org" | "salsa.debian.org" | "invent.kde.org" => {
+                ForgeKind::GitLab
+            }
+            _ if host.starts_with("gitlab.") || path.contains("/-/") => ForgeKind::GitLab,
+            "codeberg.org" | "gitea.com" => ForgeKind::Gitea,
+            _ if host.starts_with("gitea.") || host.starts_with("forgejo.") => ForgeKind::Gitea,
+            _ => return None,
+        })
+    }
+
+    fn forge(self) -> &'static (dyn Forge + Sync) {
+        match self {
+            ForgeKind::GitHub => &GitHub,
+            ForgeKind::GitLab => &GitLab,
+            ForgeKind::Gitea => &Gitea,
+            ForgeKind::SourceHut => &SourceHut,
+        }
+    }
+}
+
+/// Urls of a forge
+pub trait Forge {
+    /// Raw `file` from the default branch
+    fn raw_url(&self, repo: &Repo, file: &str) -> String;
+    /// Changes between the two tags
+    fn compare_url(&self, repo: &Repo, from: &str, to: &str) -> String;
+    /// Responds with an error status if there is no such tag
+    fn tag_url(&self, repo: &Repo, tag: &str) -> String;
+}
+
+pub struct GitHub;
+
+impl Forge for GitHub {
+    fn raw_url(&self, repo: &Repo, file: &str) -> String {
+        format!(
+            "https://raw.githubusercontent.com/{}/HEAD/{}",
+            repo.path, file
+        )
+    }
+
+    fn compare_url(&self, repo: &Repo, from: &str, to: &str) -> String {
+        format!("https://github.com/{}/compare/{}...{}", repo.path, from, to)
+    }
+
+    fn tag_url(&self, repo: &Repo, tag: &str) -> String {
+        format!(
+            "https://api.github.com/repos/{}/git/ref/tags/{}",
+            repo.path,
+            encode(tag)
+        )
+    }
+}
+
+pub struct GitLab;
+
+impl Forge for GitLab {
+    fn raw_url(&self, repo: &Repo, file: &str) -> String {
+        format!("https://{}/{}/-/raw/HEAD/{}", repo.host, repo.path, file)
+    }
+
+    fn compare_url(&self, repo: &Repo, from: &str, to: &str) -> String {
+        format!(
+            "https://{}/{}/-/compare/{}...{}",
+            repo.host, repo.path, from, to
+        )
+    }
+
+    fn tag_url(&self, repo: &Repo, tag: &str) -> String {
+        format!("{}/repository/tags/{}", repo.gitlab_api(), encode(tag))
+    }
+}
+
+pub struct Gitea;
+
+impl Forge for Gitea {
+    fn raw_url(&self, repo: &Repo, file: &str) -> String {
+        // the api serves the default branch if there is no `ref`
+        format!(
+            "https://{}/api/v1/repos/{}/raw/{}",
+            repo.host, repo.path, file
+        )
+    }
+
+    fn compare_url(&self, repo: &Repo, from: &str, to: &str) -> String {
+        format!(
+            "https://{}/{}/compare/{}...{}",
+            repo.host, repo.path, from, to
+        )
+    }
+
+    fn tag_url(&self, repo: &Repo, tag: &str) -> String {
+        format!(
+            "https://{}/api/v1/repos/{}/tags/{}",
+            repo.host,
+            repo.path,
+            encode(tag)
+        )
+    }
+}
+
+pub struct SourceHut;
+
+impl Forge for SourceHut {
+    fn raw_url(&self, repo: &Repo, file: &str) -> String {
+        format!("https://{}/{}/blob/HEAD/{}", repo.host, repo.path, file)
+    }
+
+    /// sourcehut has no compare view, the log of the new tag is the closest
+    fn compare_url(&self, repo: &Repo, _from: &str, to: &str) -> String {
+        format!("https://{}/{}/log/{}", repo.host, repo.path, to)
+    }
+
+    fn tag_url(&self, repo: &Repo, tag: &str) -> String {
+        format!("https://{}/{}/refs/{}", repo.host, repo.path, encode(tag))
+    }
+}
+
+/// Repository on a supported forge
+#[derive(Debug, PartialEq, Eq)]
+pub struct Repo {
+    pub kind: ForgeKind,
+    /// e.g. `github.com`
+    pub host: String,
+    /// `owner/name`, a GitLab path with (nested) groups or `~user/name` on sourcehut
+    pub path: String,
+}
+
+/// Escapes a path segment of an api url
+pub fn encode(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('/', "%2F")
+        .replace('+', "%2B")
+}
+
+/// Tags a release may have, in the order they are tried. `format` (with `{version}` and `{crate}`
+/// placeholders) from the crate's overrides replaces the guesses.
+pub fn tags(krate: &str, version: &str, format: Option<&str>) -> Vec<String> {
+    match format {
+        Some(format) => vec![format
+            .replace("{version}", version)
+            .replace("{crate}", krate)],
+        None => vec![
+            format!("v{}", version),
+            version.to_owned(),
+            format!("{}-v{}", krate, version),
+            format!("{}-{}", krate, version),
+        ],
+    }
+}
+
+impl Repo {
+    /// Parses the repository url from the crate's metadata
+    pub fn parse(url: &str) -> Option<Self> {
+        let url = url.trim_end_matches('/').trim_end_matches(".git");
+        let url = url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("http://"))?;
+        let (host, path) = url.split_once('/')?;
+        let kind = ForgeKind::of(host, path)?;
+
+        let path = match kind {
+            // nested groups, the project ends at `/-/tree/master`-like suffixes
+            ForgeKind::GitLab => path
+                .split("/-/")
+                .next()
+                .unwrap_or(path)
+                .trim_end_matches('/')
+                .to_owned(),
+            // drop `/tree/master/subdir`-like suffixes
+            _ => {
+                let path: Vec<_> = path.split('/').take(2).collect();
+                if path.len() != 2 || path.iter().any(|segment| segment.is_empty()) {
+                    return None;
+                }
+                path.join("/")
+            }
+        };
+        if path.is_empty() {
+            return None;
+        }
+
+        Some(Repo {
+            kind,
+            host: host.to_lowercase(),
+            path,
+        })
+    }
+
+    /// Raw `file` from the default branch
+    pub fn raw_url(&self, file: &str) -> String {
+        self.kind.forge().raw_url(self, file)
+    }
+
+    /// Changes between the two tags
+    pub fn compare_url(&self, from: &str, to: &str) -> String {
+        self.kind.forge().compare_url(self, from, to)
+    }
+
+    /// Api of the project on GitLab
+    pub fn gitlab_api(&self) -> String {
+        format!(
+            "https://{}/api/v4/projects/{}",
+            self.host,
+            encode(&self.path)
+        )
+    }
+
+    /// `false` if there is no such tag or it couldn't be checked
+    async fn has_tag(&self, client: &Client, tag: &str, github_token: Option<&str>) -> bool {
+        let request = client.get(&self.kind.forge().tag_url(self, tag));
+        let request = match (self.kind, github_token) {
+            (ForgeKind::GitHub, Some(token)) => {
+                request.header(AUTHORIZATION, format!("token {}", token))
+            }
+            _ => request,
+        };
+
+        match cache::get(client, request, Source::Forge).await {
+            Ok(response) => response.status.is_success(),
+            Err(err) => {
+                tracing::debug!("couldn't check tag {} of {:?}: {}", tag, self, err);
+                false
+            }
+        }
+    }
+}
+
+/// Compare view of the release tags of `previous` and `version` in the crate's repository.
+///
+/// `None` if the repository isn't on a supported forge or the tags aren't found (e.g. they aren't
+/// pushed yet), the tag naming (`v1.3.0`, `1.3.0`, `foo-v1.3.0` or `foo-1.3.0`) must be the
+/// same for both versions. `tag_format` from the crate's overrides replaces the guesses.
+#[tracing::instrument(name = "source_diff", skip(github_token))]
+pub async fn source_diff(
+    krate: &str,
+    previous: &str,
+    version: &str,
+    tag_format: Option<&str>,
+    github_token: Option<&str>,
+) -> Option<String> {
+    let client = http_client()
+        .map_err(|err| tracing::error!("couldn't create http client: {}", err))
+        .ok()?;
+    let repository = repository(&client, krate)
+        .await
+        .map_err(|err| tracing::warn!("couldn't get repository of {}: {}", krate, err))
+        .ok()??;
+    let repo = Repo::parse(&repository)?;
+
+    let (from_tags, to_tags) = (
+        tags(krate, previous, tag_format),
+        tags(krate, version, tag_format),
+    );
+    for (from, to) in from_tags.iter().zip(&to_tags) {
+        if repo.has_tag(&client, to, github_token).await
+            && repo.has_tag(&client, from, github_token).await
+        {
+            return Some(repo.compare_url(from, to));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tag_formats() {
+        assert_eq!(tags("foo", "1.0.0", None)[2], "foo-v1.0.0");
+        assert_eq!(
+            tags("foo", "1.0.0", Some("{crate}/release-{version}")),
+            ["foo/release-1.0.0"]
+        );
+    }
+
+    #[test]
+    fn compare_urls() {
+        let repo = Repo::parse("https://github.com/serde-rs/serde/tree/master/serde").unwrap();
+        assert_eq!(
+            repo.compare_url("v1.0.0", "v1.0.1"),
+            "https://github.com/serde-rs/serde/compare/v1.0.0...v1.0.1"
+        );
+        let repo = Repo::parse("https://gitlab.com/group/sub/project.git").unwrap();
+        assert_eq!(
+            repo,
+            Repo {
+                kind: ForgeKind::GitLab,
+                host: String::from("gitlab.com"),
+                path: String::from("group/sub/project"),
+            }
+        );
+        assert_eq!(
+            repo.compare_url("foo-1.0.0", "foo-1.1.0"),
+            "https://gitlab.com/group/sub/project/-/compare/foo-1.0.0...foo-1.1.0"
+        );
+    }
+
+    #[test]
+    fn forges() {
+        let repo = Repo::parse("https://gitlab.gnome.org/GNOME/gtk-rs/-/tree/main").unwrap();
+        assert_eq!(
+            repo.raw_url("CHANGELOG.md"),
+            "https://gitlab.gnome.org/GNOME/gtk-rs/-/raw/HEAD/CHANGELOG.md"
+        );
+        assert_eq!(
+            repo.gitlab_api(),
+            "https://gitlab.gnome.org/api/v4/projects/GNOME%2Fgtk-rs"
+        );
+
+        let repo = Repo::parse("https://codeberg.org/owner/name/src/branch/main").unwrap();
+        assert_eq!(repo.kind, ForgeKind::Gitea);
+        assert_eq!(
+            repo.raw_url("CHANGELOG.md"),
+            "https://codeberg.org/api/v1/repos/owner/name/raw/CHANGELOG.md"
+        );
+        assert_eq!(
+            repo.compare_url("v1.0.0", "v1.1.0"),
+            "https://codeberg.org/owner/name/compare/v1.0.0...v1.1.0"
+        );
+
+        let repo = Repo::parse("https://git.sr.ht/~user/name").unwrap();
+        assert_eq!(repo.kind, ForgeKind::SourceHut);
+        assert_eq!(
+            repo.raw_url("CHANGELOG.md"),
+            "https://git.sr.ht/~user/name/blob/HEAD/CHANGELOG.md"
+        );
+        assert_eq!(
+            repo.compare_url("v1.0.0", "v1.1.0"),
+            "https://git.sr.ht/~user/name/log/v1.1.0"
+        );
+
+        assert_eq!(Repo::parse("https://example.com/repo"), None);
+        assert_eq!(Repo::parse("https://github.com/owner"), None);
+    }
+}
@@ -0,0 +1,49 @@
+//! `/rerender [<n>]`: the last notifications of a chat about new versions are rendered again with
+//! the current settings of the chat (template, verbosity, ...) and the operator's templates, and
+//! edited in place. Release notes are the stored ones, so rendering twice gives the same text and
+//! the messages are left as they are.
+use carapax::Api;
+
+use crate::{cfg::Config, db::Database, krate::Crate, late_notes};
+
+/// Messages of a chat kept for re-rendering
+pub const KEPT: i32 = 20;
+
+/// Messages re-rendered if the chat doesn't say how many
+pub const DEFAULT: i64 = 5;
+
+/// Renders the newest `count` notifications of the chat again and edits them. Returns how many
+/// messages were edited and how many were found.
+pub async fn run(
+    bot: &Api,
+    db: &Database,
+    cfg: &Config,
+    chat_id: i64,
+    count: i64,
+) -> Result<(usize, usize), tokio_postgres::Error> {
+    let sent = db.last_notifications(chat_id, count).await?;
+    let mut edited = 0;
+    for (key, version, message_id) in &sent {
+        let krate = Crate::read_all(key, cfg)
+            .await
+            .map_err(|err| tracing::warn!("couldn't read versions of {}: {}", key, err))
+            .ok()
+            .and_then(|all| all.into_iter().find(|krate| krate.id.vers == *version));
+        let krate = match krate {
+            Some(krate) => krate,
+            None => continue,
+        };
+        let notes = db.get_release_notes(key, version).await?;
+        edited += late_notes::reannounce(
+            bot,
+            db,
+            cfg,
+            &krate,
+            notes.as_deref(),
+            vec![(chat_id, *message_id)],
+        )
+        .await;
+    }
+
+    Ok((edited, sent.len()))
+}
@@ -0,0 +1,340 @@
+//! Routing rules of the operator (`[[rule]]` of the config): a condition on the update and what
+//! to do with it, evaluated before the update is fanned out. Conditions are words combined with
+//! `and`, `or`, `not` and parentheses, e.g. `yanked and (crate tokio-* or keyword async)`:
+//!
+//! - `crate <glob>`: the crate's name matches the glob (`myreg:*` for an alternative registry)
+//! - `keyword <keyword>`: the crate has the keyword on crates.io
+//! - `bump <patch|minor|major>`: a new version with at least such a bump
+//! - `prerelease`, `new` (the first version), `yanked`, `unyanked`
+//! - `security`: release notes mention a security fix
+use std::{convert::TryFrom, iter::Peekable, vec::IntoIter};
+
+use kacl_parser::VersionScheme;
+
+use crate::{
+    cfg::{Config, RuleConfig},
+    cratesio,
+    filter::Bump,
+    krate::Crate,
+    summary,
+    template::Template,
+    util::{glob_match, http_client},
+    ActionKind,
+};
+
+/// Condition of a rule, parsed from a string of the config
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub enum Condition {
+    Crate(String),
+    Keyword(String),
+    /// A new version with at least this bump
+    Bump(Bump),
+    Prerelease,
+    New,
+    Yanked,
+    Unyanked,
+    Security,
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+/// Words and parentheses of a condition
+fn tokens(s: &str) -> Vec<String> {
+    s.replace('(', " ( ")
+        .replace(')', " ) ")
+        .split_whitespace()
+        .map(str::to_owned)
+        .collect()
+}
+
+type Tokens = Peekable<IntoIter<String>>;
+
+fn parse_or(tokens: &mut Tokens) -> Result<Condition, String> {
+    let mut condition = parse_and(tokens)?;
+    while tokens.peek().map(String::as_str) == Some("or") {
+        tokens.next();
+        condition = Condition::Or(Box::new(condition), Box::new(parse_and(tokens)?));
+    }
+
+    Ok(condition)
+}
+
+fn parse_and(tokens: &mut Tokens) -> Result<Condition, String> {
+    let mut condition = parse_not(tokens)?;
+    while tokens.peek().map(String::as_str) == Some("and") {
+        tokens.next();
+        condition = Condition::And(Box::new(condition), Box::new(parse_not(tokens)?));
+    }
+
+    Ok(condition)
+}
+
+/// Argument of the condition `word`, e.g. the glob of `crate`
+fn argument(tokens: &mut Tokens, word: &str) -> Result<String, String> {
+    tokens
+        .next()
+        .filter(|arg| !matches!(arg.as_str(), "(" | ")" | "and" | "or" | "not"))
+        .ok_or_else(|| format!("`{}` needs an argument", word))
+}
+
+fn parse_not(tokens: &mut Tokens) -> Result<Condition, String> {
+    let token = tokens
+        .next()
+        .ok_or_else(|| String::from("unexpected end of the condition"))?;
+    Ok(match token.as_str() {
+        "not" => Condition::Not(Box::new(parse_not(tokens)?)),
+        "(" => {
+            let condition = parse_or(tokens)?;
+            if tokens.next().as_deref() != Some(")") {
+                return Err(String::from("unclosed `(`"));
+            }
+            condition
+        }
+        "crate" => Condition::Crate(argument(tokens, "crate")?),
+        "keyword" => Condition::Keyword(argument(tokens, "keyword")?.to_lowercase()),
+        "bump" => {
+            let bump = argument(tokens, "bump")?;
+            let bump = Bump::parse(&bump).ok_or_else(|| format!("unknown bump: {}", bump))?;
+            Condition::Bump(bump)
+        }
+        "prerelease" => Condition::Prerelease,
+        "new" => Condition::New,
+        "yanked" => Condition::Yanked,
+        "unyanked" => Condition::Unyanked,
+        "security" => Condition::Security,
+        other => return Err(format!("unexpected `{}`", other)),
+    })
+}
+
+impl TryFrom<String> for Condition {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let mut tokens = tokens(&s).into_iter().peekable();
+        let condition = parse_or(&mut tokens)?;
+        match tokens.next() {
+            Some(token) => Err(format!("unexpected `{}`", token)),
+            None => Ok(condition),
+        }
+    }
+}
+
+/// What rules know about an update
+pub struct Event<'a> {
+    /// `myreg:name` for crates of alternative registries
+    pub key: &'a str,
+    pub action: &'a ActionKind,
+    /// Bump of a new version from the previous one, `None` if it can't be told
+    pub bump: Option<Bump>,
+    pub prerelease: bool,
+    /// The first version of the crate
+    pub is_new: bool,
+    pub security: bool,
+    /// Keywords on crates.io, looked up only if a rule needs them
+    pub keywords: Vec<String>,
+}
+
+impl Condition {
+    pub fn matches(&self, event: &Event<'_>) -> bool {
+        match self {
+            Condition::Crate(glob) => glob_match(glob, event.key),
+            Condition::Keyword(keyword) => event.keywords.contains(keyword),
+            Condition::Bump(min) => event.bump.map_or(false, |bump| bump >= *min),
+            Condition::Prerelease => event.prerelease,
+            Condition::New => event.is_new,
+            Condition::Yanked => matches!(event.action, ActionKind::Yanked),
+            Condition::Unyanked => matches!(event.action, ActionKind::Unyanked),
+            Condition::Security => event.security,
+            Condition::Not(condition) => !condition.matches(event),
+            Condition::And(a, b) => a.matches(event) && b.matches(event),
+            Condition::Or(a, b) => a.matches(event) || b.matches(event),
+        }
+    }
+
+    fn needs_keywords(&self) -> bool {
+        match self {
+            Condition::Keyword(_) => true,
+            Condition::Not(condition) => condition.needs_keywords(),
+            Condition::And(a, b) | Condition::Or(a, b) => a.needs_keywords() || b.needs_keywords(),
+            _ => false,
+        }
+    }
+}
+
+/// What matching rules do with an update
+#[derive(Debug, Default, PartialEq)]
+pub struct Outcome<'c> {
+    /// Not sent to subscribers, channels, hooks and rooms (only to `routes`)
+    pub drop: bool,
+    /// Chats and channels getting the update besides the usual ones
+    pub routes: Vec<i64>,
+    /// Template of the notifications (chats' own templates still win)
+    pub template: Option<&'c Template>,
+}
+
+/// Applies all matching rules in order: routes add up, the first template wins and any `drop`
+/// drops the update
+fn apply<'c>(rules: &'c [RuleConfig], event: &Event<'_>) -> Outcome<'c> {
+    let mut outcome = Outcome::default();
+    for rule in rules.iter().filter(|rule| rule.when.matches(event)) {
+        outcome.drop |= rule.drop;
+        for &chat_id in &rule.route {
+            if !outcome.routes.contains(&chat_id) {
+                outcome.routes.push(chat_id);
+            }
+        }
+        outcome.template = outcome.template.or_else(|| rule.template.as_ref());
+    }
+
+    outcome
+}
+
+/// Keywords of the crates.io crate, empty if they couldn't be fetched
+async fn keywords(krate: &Crate) -> Vec<String> {
+    let client = match http_client() {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::error!("couldn't create http client: {}", err);
+            return Vec::new();
+        }
+    };
+    cratesio::about(&client, &krate.id.name, &krate.id.vers)
+        .await
+        .map(|about| about.keywords)
+        .unwrap_or_else(|err| {
+            tracing::warn!("couldn't get keywords of {}: {}", krate.id.name, err);
+            Vec::new()
+        })
+}
+
+/// Evaluates the rules of the config for the update, `previous` is the newest version older than
+/// the crate's version and `notes` are its release notes (telegram html)
+pub async fn evaluate<'c>(
+    cfg: &'c Config,
+    krate: &Crate,
+    action: &ActionKind,
+    scheme: VersionScheme,
+    previous: Option<&str>,
+    notes: Option<&str>,
+) -> Outcome<'c> {
+    if cfg.rules.is_empty() {
+        return Outcome::default();
+    }
+
+    let key = krate.key();
+    let is_new_version = matches!(action, ActionKind::NewVersion);
+    let needs_keywords = cfg.rules.iter().any(|rule| rule.when.needs_keywords());
+    let keywords = if needs_keywords && krate.registry.is_none() {
+        keywords(krate).await
+    } else {
+        Vec::new()
+    };
+    let event = Event {
+        key: &key,
+        action,
+        bump: previous
+            .filter(|_| is_new_version)
+            .and_then(|previous| Bump::between(scheme, previous, &krate.id.vers)),
+        prerelease: scheme.is_prerelease(&krate.id.vers),
+        is_new: is_new_version && previous.is_none(),
+        security: notes.map_or(false, summary::mentions_security),
+        keywords,
+    };
+
+    apply(&cfg.rules, &event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<Condition, String> {
+        Condition::try_from(s.to_owned())
+    }
+
+    fn event<'a>(key: &'a str, action: &'a ActionKind) -> Event<'a> {
+        Event {
+            key,
+            action,
+            bump: Some(Bump::Patch),
+            prerelease: false,
+            is_new: false,
+            security: false,
+            keywords: vec![String::from("async")],
+        }
+    }
+
+    #[test]
+    fn conditions() {
+        assert_eq!(
+            parse("yanked and (crate tokio-* or keyword Async)"),
+            Ok(Condition::And(
+                Box::new(Condition::Yanked),
+                Box::new(Condition::Or(
+                    Box::new(Condition::Crate(String::from("tokio-*"))),
+                    Box::new(Condition::Keyword(String::from("async")))
+                ))
+            ))
+        );
+        // `and` binds tighter than `or`
+        assert_eq!(
+            parse("new or not security and bump minor"),
+            Ok(Condition::Or(
+                Box::new(Condition::New),
+                Box::new(Condition::And(
+                    Box::new(Condition::Not(Box::new(Condition::Security))),
+                    Box::new(Condition::Bump(Bump::Minor))
+                ))
+            ))
+        );
+        assert_eq!(
+            parse("crate"),
+            Err(String::from("`crate` needs an argument"))
+        );
+        assert_eq!(
+            parse("crate and new"),
+            Err(String::from("`crate` needs an argument"))
+        );
+        assert_eq!(parse("(new"), Err(String::from("unclosed `(`")));
+        assert_eq!(parse("new)"), Err(String::from("unexpected `)`")));
+        assert_eq!(parse("bump huge"), Err(String::from("unknown bump: huge")));
+        assert_eq!(
+            parse("sometimes"),
+            Err(String::from("unexpected `sometimes`"))
+        );
+        assert_eq!(
+            parse(""),
+            Err(String::from("unexpected end of the condition"))
+        );
+    }
+
+    #[test]
+    fn outcomes() {
+        let rule = |when: &str, route: Vec<i64>, drop: bool| RuleConfig {
+            when: parse(when).unwrap(),
+            route,
+            template: None,
+            drop,
+        };
+        let rules = vec![
+            rule("yanked and crate tokio-*", vec![-100], true),
+            rule("keyword async", vec![-200, -100], false),
+            rule("bump minor", vec![-300], false),
+        ];
+
+        let yank = apply(&rules, &event("tokio-util", &ActionKind::Yanked));
+        assert!(yank.drop);
+        assert_eq!(yank.routes, vec![-100, -200]);
+
+        let release = apply(&rules, &event("tokio-util", &ActionKind::NewVersion));
+        assert!(!release.drop);
+        assert_eq!(release.routes, vec![-200, -100]);
+
+        assert_eq!(
+            apply(&rules[2..], &event("serde", &ActionKind::NewVersion)),
+            Outcome::default()
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! Heuristics flagging suspicious releases, shown to chats with `/security_alerts on`: a new crate
+//! named like a popular followed one, a version far ahead of the previous one and the first release
+//! of a new publisher after years without releases (see
+//! [`maintainer_change`](crate::maintenance::maintainer_change)). They're hints, not verdicts.
+use versions::SemVer;
+
+use crate::{inline::levenshtein, krate::normalize_name, render::escape};
+
+/// Most followed crates new crates are compared with
+pub const POPULAR_CRATES: i64 = 500;
+
+/// A version whose major is this much bigger than the previous one is unusual
+const MAJOR_JUMP: u32 = 10;
+
+/// Popular name the new crate is one edit away from (e.g. `serde` for `sedre` or `serde1`)
+pub fn lookalike<'a>(name: &str, popular: &'a [String]) -> Option<&'a str> {
+    let name = normalize_name(name);
+    popular
+        .iter()
+        .find(|popular| {
+            let popular = normalize_name(popular);
+            popular != name && levenshtein(&popular, &name) == 1
+        })
+        .map(String::as_str)
+}
+
+/// Whether the version skips many majors, e.g. `0.1.0 → 99.0.0`
+pub fn version_jump(previous: &SemVer, version: &SemVer) -> bool {
+    version.major >= previous.major.saturating_add(MAJOR_JUMP)
+}
+
+/// Telegram html lines of alerts about a new version
+pub fn html(
+    lookalike: Option<&str>,
+    jump: Option<(&SemVer, &SemVer)>,
+    takeover: Option<(&str, i64)>,
+) -> Option<String> {
+    let mut alerts = Vec::new();
+    if let Some(popular) = lookalike {
+        alerts.push(format!(
+            "🚨 New crate named like the popular <code>{}</code>, check it's the one you want",
+            escape(popular)
+        ));
+    }
+    if let Some((previous, version)) = jump {
+        alerts.push(format!(
+            "🚨 Unusual version jump from {} to {}",
+            previous, version
+        ));
+    }
+    if let Some((publisher, years)) = takeover {
+        alerts.push(format!(
+            "🚨 First release by <code>{}</code>, after {} years without releases",
+            escape(publisher),
+            years
+        ));
+    }
+
+    if alerts.is_empty() {
+        None
+    } else {
+        Some(alerts.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookalikes() {
+        let popular = vec![String::from("serde"), String::from("tokio")];
+        assert_eq!(lookalike("serde1", &popular), Some("serde"));
+        assert_eq!(lookalike("tokoi", &popular), None);
+        assert_eq!(lookalike("Tokio_", &popular), Some("tokio"));
+        assert_eq!(lookalike("serde", &popular), None);
+    }
+
+    #[test]
+    fn jumps() {
+        let v = |s| SemVer::new(s).unwrap();
+        assert!(version_jump(&v("0.1.0"), &v("99.0.0")));
+        assert!(version_jump(&v("1.2.0"), &v("11.0.0")));
+        assert!(!version_jump(&v("1.2.0"), &v("2.0.0")));
+    }
+}
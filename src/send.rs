@@ -0,0 +1,536 @@
+//! Queue of outgoing notifications paced to telegram limits
+//! (<https://core.telegram.org/bots/faq#my-bot-is-hitting-limits-how-do-i-avoid-this>).
+//! Notifications about releases wait for a place in the queue (`[send] queue`), so while telegram
+//! is down or slow announcing slows down instead of piling messages up in memory. Digests and
+//! weekly reports are put off already when the queue is half full, they wait in the database.
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use carapax::{methods::SendMessage, types::ParseMode, Api, ExecuteError};
+use tokio::sync::{mpsc, Semaphore};
+use tracing::{Instrument, Span};
+
+use crate::{cfg::Config, db::Database, health, metrics, notifier::Notifier, rerender, threads};
+
+/// How many times a message is sent before it's dropped (429 responses aren't counted), the delay
+/// between attempts doubles starting from `retry_delay`
+const ATTEMPTS: usize = 5;
+
+/// How often the queue depth is logged while the queue isn't empty
+const REPORT_DELAY: Duration = Duration::from_secs(60);
+
+/// How often [`SendQueue::flush`] checks the queue
+const FLUSH_CHECK: Duration = Duration::from_millis(100);
+
+/// Release a message is about. The delivery is recorded, so if the release is replayed
+/// after a crash the chat isn't notified twice.
+#[derive(Debug, Clone)]
+pub struct Receipt {
+    pub krate: String,
+    pub version: String,
+    /// `new`, `yanked` or `unyanked`
+    pub action: String,
+    /// The message is about this release alone (not a train or crates of a workspace), so
+    /// `/rerender` may render it again
+    pub single: bool,
+}
+
+struct Outgoing {
+    chat_id: i64,
+    /// Telegram html
+    text: String,
+    /// Send without a notification sound
+    quiet: bool,
+    attempt: usize,
+    /// Span the message was queued in, e.g. of the index event
+    span: Span,
+    receipt: Option<Receipt>,
+    /// Took a place in the queue, which is given back once the message is sent or dropped
+    slot: bool,
+}
+
+/// Handle of the queue, messages are sent by a background task
+#[derive(Clone)]
+pub struct SendQueue {
+    tx: mpsc::UnboundedSender<Outgoing>,
+    /// Number of queued messages which aren't sent yet
+    depth: Arc<AtomicUsize>,
+    /// Free places for notifications about releases
+    slots: Arc<Semaphore>,
+    /// Digests are put off while at least this many messages are queued
+    busy: usize,
+}
+
+impl SendQueue {
+    /// Spawns the task sending queued messages
+    pub fn start(bot: Api, cfg: Arc<Config>, db: Database) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let depth = Arc::new(AtomicUsize::new(0));
+        let limit = cfg.send.queue.max(1);
+        let slots = Arc::new(Semaphore::new(limit));
+        let worker = Worker::new(bot, cfg, db, rx, Arc::clone(&depth), Arc::clone(&slots));
+        tokio::spawn(worker.run());
+
+        Self {
+            tx,
+            depth,
+            slots,
+            busy: (limit / 2).max(1),
+        }
+    }
+
+    /// Queues a notification about the release (see [`Receipt`]), waits while the queue is full
+    pub async fn push_receipted(&self, chat_id: i64, text: String, quiet: bool, receipt: Receipt) {
+        if self.slots.available_permits() == 0 {
+            metrics::SEND_QUEUE_WAITS.inc();
+            tracing::debug!("send queue is full, waiting");
+        }
+        self.slots.acquire().await.forget();
+        self.enqueue(chat_id, text, quiet, Some(receipt), true);
+    }
+
+    /// Whether low-priority messages (digests, weekly reports) should be put off
+    pub fn is_busy(&self) -> bool {
+        self.depth.load(Ordering::Relaxed) >= self.busy
+    }
+
+    fn enqueue(
+        &self,
+        chat_id: i64,
+        text: String,
+        quiet: bool,
+        receipt: Option<Receipt>,
+        slot: bool,
+    ) {
+        self.depth.fetch_add(1, Ordering::Relaxed);
+        metrics::SEND_QUEUE_DEPTH.inc();
+        let message = Outgoing {
+            chat_id,
+            text,
+            quiet,
+            attempt: 0,
+            span: Span::current(),
+            receipt,
+            slot,
+        };
+        if self.tx.send(message).is_err() {
+            self.depth.fetch_sub(1, Ordering::Relaxed);
+            metrics::SEND_QUEUE_DEPTH.dec();
+            if slot {
+                self.slots.add_permits(1);
+            }
+            tracing::error!("send queue is closed, message to {} is lost", chat_id);
+        }
+    }
+
+    /// Waits until all queued messages are sent (or dropped after failed attempts)
+    pub async fn flush(&self) {
+        while self.depth.load(Ordering::Relaxed) > 0 {
+            tokio::time::delay_for(FLUSH_CHECK).await;
+        }
+    }
+}
+
+impl Notifier for SendQueue {
+    type Target = i64;
+
+    /// Doesn't wait for a place, messages without a receipt are few (digests check
+    /// [`SendQueue::is_busy`] before they're taken from the database)
+    fn push(&self, chat_id: i64, text: String, quiet: bool) {
+        self.enqueue(chat_id, text, quiet, None, false);
+    }
+}
+
+/// Allows `rate` sends per second with bursts of at most `rate` sends
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            tokens: rate,
+            last: Instant::now(),
+        }
+    }
+
+    /// Waits until a send is allowed
+    async fn take(&mut self) {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(self.rate);
+        self.last = now;
+
+        if self.tokens < 1.0 {
+            let wait = Duration::from_secs_f64((1.0 - self.tokens) / self.rate);
+            tokio::time::delay_for(wait).await;
+            self.tokens = 1.0;
+            self.last = Instant::now();
+        }
+        self.tokens -= 1.0;
+    }
+}
+
+struct Worker {
+    bot: Api,
+    cfg: Arc<Config>,
+    db: Database,
+    rx: mpsc::UnboundedReceiver<Outgoing>,
+    depth: Arc<AtomicUsize>,
+    slots: Arc<Semaphore>,
+    /// Received messages, in order
+    pending: VecDeque<Outgoing>,
+    /// When the next message may be sent to the chat
+    next_send: HashMap<i64, Instant>,
+    /// Messages dropped in a row, by chat
+    failures: HashMap<i64, u32>,
+    bucket: TokenBucket,
+}
+
+impl Worker {
+    fn new(
+        bot: Api,
+        cfg: Arc<Config>,
+        db: Database,
+        rx: mpsc::UnboundedReceiver<Outgoing>,
+        depth: Arc<AtomicUsize>,
+        slots: Arc<Semaphore>,
+    ) -> Self {
+        let bucket = TokenBucket::new(cfg.send.messages_per_second.max(1) as f64);
+        Self {
+            bot,
+            cfg,
+            db,
+            rx,
+            depth,
+            slots,
+            pending: VecDeque::new(),
+            next_send: HashMap::new(),
+            failures: HashMap::new(),
+            bucket,
+        }
+    }
+
+    /// Telegram allows about one message per second to a private chat
+    /// and 20 messages per minute to a group or channel
+    fn interval(&self, chat_id: i64) -> Duration {
+        if chat_id < 0 {
+            Duration::from_millis(self.cfg.send.group_interval_millis)
+        } else {
+            Duration::from_millis(self.cfg.send.chat_interval_millis)
+        }
+    }
+
+    async fn run(mut self) {
+        let mut last_report = Instant::now();
+        loop {
+            if self.pending.is_empty() {
+                match self.rx.recv().await {
+                    Some(message) => self.pending.push_back(message),
+                    None => return,
+                }
+            }
+            while let Ok(message) = self.rx.try_recv() {
+                self.pending.push_back(message);
+            }
+
+            if last_report.elapsed() >= REPORT_DELAY {
+                tracing::info!("send queue depth: {}", self.depth.load(Ordering::Relaxed));
+                last_report = Instant::now();
+            }
+
+            // the first message of a chat which may be sent now, keeps the order inside chats
+            let now = Instant::now();
+            let next_send = &self.next_send;
+            let ready = self
+                .pending
+                .iter()
+                .position(|m| next_send.get(&m.chat_id).map_or(true, |at| *at <= now));
+            let message = match ready {
+                Some(idx) => self.pending.remove(idx).expect("index is in bounds"),
+                None => {
+                    let earliest = self
+                        .pending
+                        .iter()
+                        .filter_map(|m| next_send.get(&m.chat_id))
+                        .min()
+                        .copied()
+                        .unwrap_or(now);
+                    tokio::time::delay_until(earliest.into()).await;
+                    continue;
+                }
+            };
+
+            self.bucket.take().await;
+            let span =
+                tracing::info_span!(parent: &message.span, "send", chat_id = message.chat_id);
+            self.send(message).instrument(span).await;
+
+            if self.next_send.len() > 10_000 {
+                let now = Instant::now();
+                self.next_send.retain(|_, at| *at > now);
+            }
+        }
+    }
+
+    async fn record_delivery(&self, message: &Outgoing) {
+        if let Some(receipt) = &message.receipt {
+            self.db
+                .record_delivery(
+                    &receipt.krate,
+                    &receipt.version,
+                    &receipt.action,
+                    message.chat_id,
+                )
+                .await
+                .unwrap_or_else(|err| {
+                    tracing::error!("db error while recording delivery: {}", err)
+                });
+        }
+    }
+
+    /// Records the message announcing a new version, so it's edited if release notes are found
+    /// after it was sent (see `late_notes`)
+    async fn record_announcement(&self, message: &Outgoing, message_id: i64) {
+        let receipt = match &message.receipt {
+            Some(receipt) if receipt.action == "new" && self.cfg.changelog.notes_lag_secs > 0 => {
+                receipt
+            }
+            _ => return,
+        };
+        self.db
+            .record_announcement(
+                &receipt.krate,
+                &receipt.version,
+                message.chat_id,
+                message_id,
+            )
+            .await
+            .unwrap_or_else(|err| {
+                tracing::error!("db error while recording announcement: {}", err)
+            });
+    }
+
+    /// Records the message announcing a single new version, so `/rerender` can edit it
+    async fn record_notification(&self, message: &Outgoing, message_id: i64) {
+        let receipt = match &message.receipt {
+            Some(receipt) if receipt.action == "new" && receipt.single => receipt,
+            _ => return,
+        };
+        self.db
+            .record_notification(
+                message.chat_id,
+                &receipt.krate,
+                &receipt.version,
+                message_id,
+                rerender::KEPT,
+            )
+            .await
+            .unwrap_or_else(|err| {
+                tracing::error!("db error while recording notification: {}", err)
+            });
+    }
+
+    /// Records the dropped message as a dead letter, notifications of the chat are paused after
+    /// `max_failures` messages in a row are dropped
+    async fn failed(&mut self, message: &Outgoing, err: &ExecuteError) {
+        let error = err.to_string();
+        self.dead_letter(message, &error).await;
+
+        let max = self.cfg.send.max_failures;
+        let failures = self.failures.entry(message.chat_id).or_insert(0);
+        *failures += 1;
+        if max == 0 || *failures < max {
+            return;
+        }
+
+        self.failures.remove(&message.chat_id);
+        tracing::warn!(
+            "pausing notifications of {} after {} failed messages",
+            message.chat_id,
+            max
+        );
+        self.db
+            .disable_chat(message.chat_id, &error)
+            .await
+            .unwrap_or_else(|err| tracing::error!("db error while disabling chat: {}", err));
+
+        // the rest of messages to the chat would fail too
+        let (dropped, pending): (Vec<_>, VecDeque<_>) = self
+            .pending
+            .drain(..)
+            .partition(|m| m.chat_id == message.chat_id);
+        self.pending = pending;
+        for message in dropped {
+            self.dead_letter(&message, "notifications of the chat are paused")
+                .await;
+            metrics::NOTIFICATIONS_FAILED.inc();
+            self.done(&message);
+        }
+    }
+
+    /// The message left the queue, sent or dropped
+    fn done(&self, message: &Outgoing) {
+        self.depth.fetch_sub(1, Ordering::Relaxed);
+        metrics::SEND_QUEUE_DEPTH.dec();
+        if message.slot {
+            self.slots.add_permits(1);
+        }
+    }
+
+    async fn dead_letter(&self, message: &Outgoing, error: &str) {
+        self.db
+            .add_dead_letter(
+                message.chat_id,
+                message.receipt.as_ref(),
+                &message.text,
+                error,
+            )
+            .await
+            .unwrap_or_else(|err| tracing::error!("db error while recording dead letter: {}", err));
+    }
+
+    /// Thread of the crate the notification is about, see [`Database::get_thread`]
+    async fn thread(&self, message: &Outgoing) -> Option<Option<i64>> {
+        let receipt = message.receipt.as_ref()?;
+        self.db
+            .get_thread(message.chat_id, &receipt.krate)
+            .await
+            .unwrap_or_else(|err| {
+                tracing::error!("db error while getting thread: {}", err);
+                None
+            })
+    }
+
+    async fn send(&mut self, mut message: Outgoing) {
+        let thread = self.thread(&message).await;
+        let reply_to = thread.flatten();
+        let mut request = SendMessage::new(message.chat_id, message.text.as_str())
+            .parse_mode(ParseMode::Html)
+            .disable_web_page_preview(true)
+            .disable_notification(message.quiet);
+        if let Some(reply_to) = reply_to {
+            request = request.reply_to_message_id(reply_to);
+        }
+
+        let timer = metrics::TELEGRAM_LATENCY.start_timer();
+        let result = self.bot.execute(request).await;
+        timer.observe_duration();
+        let now = Instant::now();
+        self.next_send
+            .insert(message.chat_id, now + self.interval(message.chat_id));
+
+        match result {
+            Ok(sent) => {
+                metrics::NOTIFICATIONS_SENT.inc();
+                self.failures.remove(&message.chat_id);
+                self.record_delivery(&message).await;
+                self.record_announcement(&message, sent.id).await;
+                self.record_notification(&message, sent.id).await;
+                if let (Some(previous), Some(receipt)) = (thread, &message.receipt) {
+                    self.bucket.take().await;
+                    threads::record(
+                        &self.bot,
+                        &self.db,
+                        message.chat_id,
+                        &receipt.krate,
+                        sent.id,
+                        previous.is_none(),
+                    )
+                    .await;
+                }
+            }
+            Err(ExecuteError::Response(err)) if err.retry_after().is_some() => {
+                health::telegram_error(&err);
+                let wait = Duration::from_secs(err.retry_after().unwrap_or(1).max(1) as u64);
+                tracing::warn!(
+                    "hit telegram limits while sending to {}, pausing for {:?} (queue depth: {})",
+                    message.chat_id,
+                    wait,
+                    self.depth.load(Ordering::Relaxed)
+                );
+                // 429 means that limits are hit, so all sends are paused
+                self.pending.push_front(message);
+                tokio::time::delay_for(wait).await;
+                return;
+            }
+            Err(ExecuteError::Response(err)) if err.migrate_to_chat_id().is_some() => {
+                // the group was upgraded to a supergroup, which has another id
+                let (from, to) = (
+                    message.chat_id,
+                    err.migrate_to_chat_id().unwrap_or_default(),
+                );
+                tracing::info!("chat {} was migrated to {}", from, to);
+                self.db.migrate_chat(from, to).await.unwrap_or_else(|err| {
+                    tracing::error!("db error while migrating chat: {}", err)
+                });
+                for pending in self.pending.iter_mut().filter(|m| m.chat_id == from) {
+                    pending.chat_id = to;
+                }
+                message.chat_id = to;
+                self.pending.push_front(message);
+                return;
+            }
+            Err(ExecuteError::Response(err)) if reply_to.is_some() => {
+                // e.g. the previous message was deleted, the thread starts over
+                tracing::debug!(
+                    "couldn't reply in a thread of {}, sending without a reply: {}",
+                    message.chat_id,
+                    err
+                );
+                if let Some(receipt) = &message.receipt {
+                    self.db
+                        .delete_thread(message.chat_id, &receipt.krate)
+                        .await
+                        .unwrap_or_else(|err| {
+                            tracing::error!("db error while deleting thread: {}", err)
+                        });
+                }
+                self.pending.push_front(message);
+                return;
+            }
+            Err(err @ ExecuteError::Response(_)) => {
+                // e.g. the bot was blocked, retrying won't help
+                tracing::warn!("telegram rejected message to {}: {}", message.chat_id, err);
+                health::telegram_error(&err);
+                metrics::NOTIFICATIONS_FAILED.inc();
+                self.record_delivery(&message).await;
+                self.failed(&message, &err).await;
+            }
+            Err(err) if message.attempt + 1 < ATTEMPTS => {
+                tracing::warn!(
+                    "error while sending message to {}, retrying: {}",
+                    message.chat_id,
+                    err
+                );
+                health::telegram_error(&err);
+                let backoff = self.cfg.retry_delay.0 * 2u32.pow(message.attempt as u32);
+                message.attempt += 1;
+                self.next_send.insert(message.chat_id, now + backoff);
+                self.pending.push_front(message);
+                return;
+            }
+            Err(err) => {
+                tracing::error!(
+                    "error while sending message to {}: {}",
+                    message.chat_id,
+                    err
+                );
+                health::telegram_error(&err);
+                metrics::NOTIFICATIONS_FAILED.inc();
+                self.failed(&message, &err).await;
+            }
+        }
+
+        self.done(&message);
+    }
+}
@@ -0,0 +1,99 @@
+//! Deep links sharing a set of subscriptions: `https://t.me/<bot>?start=sub_tokio_serde_axum`.
+//! Opening the link sends `/start sub_tokio_serde_axum` to the bot, which asks to confirm
+//! subscribing to the crates.
+//!
+//! Telegram allows only `A-Za-z0-9_-` and 64 chars in the parameter, so names are separated by
+//! `_` and an `_` inside a name is written as `-` (crates.io treats them as the same name, the
+//! real name is looked up when decoding). Large sets are split into several links.
+use crate::krate::is_valid_name;
+
+/// Prefix of `/start` parameters with shared subscriptions, also the callback data of the
+/// confirmation button is the whole parameter
+pub const PREFIX: &str = "sub_";
+
+/// Telegram's limit of the `start` parameter
+const MAX_PAYLOAD: usize = 64;
+
+/// `start` parameters with the crates, names which can't be shared (of alternative registries or
+/// too long for a link) are skipped
+pub fn encode(krates: &[&str]) -> Vec<String> {
+    let mut payloads = Vec::new();
+    let mut payload = String::from(PREFIX);
+    for krate in krates {
+        if !is_valid_name(krate) || PREFIX.len() + krate.len() > MAX_PAYLOAD {
+            continue;
+        }
+        let name = krate.replace('_', "-");
+        if payload.len() > PREFIX.len() && payload.len() + 1 + name.len() > MAX_PAYLOAD {
+            payloads.push(std::mem::replace(&mut payload, String::from(PREFIX)));
+        }
+        if payload.len() > PREFIX.len() {
+            payload.push('_');
+        }
+        payload.push_str(&name);
+    }
+    if payload.len() > PREFIX.len() {
+        payloads.push(payload);
+    }
+    payloads
+}
+
+/// Crate names of a `start` parameter (with `-` for `_`, see [`candidates`]), `None` if it isn't
+/// a share link
+pub fn decode(payload: &str) -> Option<Vec<&str>> {
+    let names: Vec<&str> = payload.strip_prefix(PREFIX)?.split('_').collect();
+    if names.iter().all(|name| is_valid_name(name)) {
+        Some(names)
+    } else {
+        None
+    }
+}
+
+/// Names a decoded name could be published as, in the order to try them
+pub fn candidates(name: &str) -> Vec<String> {
+    let mut names = vec![name.to_owned()];
+    if name.contains('-') {
+        names.push(name.replace('-', "_"));
+    }
+    names
+}
+
+/// Link opening a chat with the bot with the parameter
+pub fn link(username: &str, payload: &str) -> String {
+    format!("https://t.me/{}?start={}", username, payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let payloads = encode(&["tokio", "serde_json", "myreg:internal", "axum"]);
+        assert_eq!(payloads, ["sub_tokio_serde-json_axum"]);
+        assert_eq!(
+            decode(&payloads[0]),
+            Some(vec!["tokio", "serde-json", "axum"])
+        );
+        assert_eq!(candidates("serde-json"), ["serde-json", "serde_json"]);
+        assert_eq!(candidates("tokio"), ["tokio"]);
+    }
+
+    #[test]
+    fn splitting() {
+        let names: Vec<String> = (0..20).map(|i| format!("crate{:02}", i)).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let payloads = encode(&names);
+        assert!(payloads.len() > 1);
+        assert!(payloads.iter().all(|p| p.len() <= MAX_PAYLOAD));
+        let decoded: Vec<&str> = payloads.iter().flat_map(|p| decode(p).unwrap()).collect();
+        assert_eq!(decoded, names);
+    }
+
+    #[test]
+    fn not_shares() {
+        assert_eq!(decode("ref_123"), None);
+        assert_eq!(decode("sub_"), None);
+        assert_eq!(decode("sub_a__b"), None);
+    }
+}
@@ -0,0 +1,71 @@
+//! Graceful shutdown: on SIGTERM (or Ctrl-C) watchers of the indexes stop after the current
+//! event and the bot exits once queued messages are sent. A second signal exits immediately.
+use tokio::{
+    signal::{
+        self,
+        unix::{signal, SignalKind},
+    },
+    sync::watch,
+};
+
+/// Handle telling tasks that the bot is stopping
+#[derive(Clone)]
+pub struct Shutdown {
+    rx: watch::Receiver<bool>,
+}
+
+async fn wait_signal() {
+    match signal(SignalKind::terminate()) {
+        Ok(mut term) => {
+            tokio::select! {
+                _ = term.recv() => {}
+                _ = signal::ctrl_c() => {}
+            }
+        }
+        Err(err) => {
+            tracing::warn!("couldn't listen for SIGTERM: {}", err);
+            let _ = signal::ctrl_c().await;
+        }
+    }
+}
+
+impl Shutdown {
+    /// Spawns the task waiting for the signals
+    pub fn listen() -> Self {
+        let (tx, rx) = watch::channel(false);
+        tokio::spawn(async move {
+            wait_signal().await;
+            tracing::info!(
+                "shutting down after the current events, queued messages are sent first"
+            );
+            let _ = tx.broadcast(true);
+
+            wait_signal().await;
+            tracing::warn!("exiting without sending queued messages");
+            std::process::exit(1);
+        });
+
+        Self { rx }
+    }
+
+    /// Handle of a shutdown which is never requested
+    #[cfg(test)]
+    pub fn never() -> Self {
+        let (_, rx) = watch::channel(false);
+        Self { rx }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// Waits until shutdown is requested
+    pub async fn requested(&mut self) {
+        while !self.is_requested() {
+            if self.rx.recv().await.is_none() {
+                // the sender lives as long as the process
+                futures::future::pending::<()>().await;
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! One-line summary of release notes ("3 fixes, 1 new feature, 1 security patch") shown at the
+//! top of notifications, so it's the preview of telegram's notification. It's an experiment:
+//! only `summary_share` percent of chats get it, the others are the control group.
+use std::collections::BTreeMap;
+
+use kacl_parser::EntryKind;
+
+use crate::render;
+
+/// Counts entries of release notes (telegram html: `<b>Section</b>` lines and `• entry` lines)
+/// by their kinds
+fn count(notes: &str) -> BTreeMap<EntryKind, usize> {
+    let mut section = String::new();
+    let mut counts = BTreeMap::new();
+    for line in notes.lines().map(str::trim) {
+        if let Some(entry) = line.strip_prefix("• ") {
+            let kind = EntryKind::classify(&section, &render::plain(entry));
+            *counts.entry(kind).or_insert(0) += 1;
+        } else if line.starts_with("<b>") && line.ends_with("</b>") {
+            section = render::plain(line);
+        }
+    }
+
+    counts
+}
+
+/// Summary of release notes, `None` if none of the entries is a fix, a feature, a breaking
+/// change or a security patch
+pub fn headline(notes: &str) -> Option<String> {
+    let parts: Vec<String> = count(notes)
+        .into_iter()
+        .filter_map(|(kind, n)| {
+            let (one, many) = match kind {
+                EntryKind::Breaking => ("breaking change", "breaking changes"),
+                EntryKind::Security => ("security patch", "security patches"),
+                EntryKind::Feature => ("new feature", "new features"),
+                EntryKind::Fix => ("fix", "fixes"),
+                EntryKind::Other => return None,
+            };
+            Some(format!("{} {}", n, if n == 1 { one } else { many }))
+        })
+        .collect();
+    if parts.is_empty() {
+        return None;
+    }
+
+    Some(format!("📋 <b>{}</b>", parts.join(", ")))
+}
+
+/// Whether one of the entries of release notes is a security fix
+pub fn mentions_security(notes: &str) -> bool {
+    count(notes).contains_key(&EntryKind::Security)
+}
+
+/// Whether the chat is in the `share` percent of chats getting summaries. Chats are spread by a
+/// hash of the id, so a chat stays in its group and the groups don't follow the order of ids.
+pub fn shown(chat_id: i64, share: u8) -> bool {
+    let hash = (chat_id as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32;
+    hash % 100 < u64::from(share)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headlines() {
+        let notes = "<b>Added</b>\n• <code>Vec::new</code> is const\n\n\
+                     <b>Fixed</b>\n• short reads\n• a leak\n• RUSTSEC-2021-0001\n\n\
+                     <b>Changed</b>\n• faster &amp; smaller";
+        assert_eq!(
+            headline(notes).as_deref(),
+            Some("📋 <b>1 security patch, 1 new feature, 2 fixes</b>")
+        );
+
+        let commits = "<i>No changelog, generated from commits:</i>\n• fix: typo\n• bump deps";
+        assert_eq!(headline(commits).as_deref(), Some("📋 <b>1 fix</b>"));
+        assert_eq!(headline("<b>Changed</b>\n• faster"), None);
+        assert!(mentions_security(notes));
+        assert!(!mentions_security(commits));
+    }
+
+    #[test]
+    fn groups() {
+        assert!((0..1000).all(|chat_id| shown(chat_id, 100)));
+        assert!((0..1000).all(|chat_id| !shown(chat_id, 0)));
+        let half = (0..1000).filter(|&chat_id| shown(-chat_id, 50)).count();
+        assert!((400..600).contains(&half), "{}", half);
+    }
+}
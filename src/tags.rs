@@ -0,0 +1,90 @@
+//! Subscriptions to all crates with a keyword or in a category.
+//!
+//! Crates of subscribed tags are cached in the database and refreshed periodically,
+//! so a crate is matched only after the first refresh since it got the tag.
+use std::time::Duration;
+
+use reqwest::Client;
+
+use crate::{cratesio, db::Database, util::http_client};
+
+/// How often lists of crates with subscribed tags are refreshed
+const REFRESH_DELAY: Duration = Duration::from_secs(6 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagKind {
+    Keyword,
+    Category,
+}
+
+impl TagKind {
+    /// Name of the kind stored in the database and used in the crates.io api
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TagKind::Keyword => "keyword",
+            TagKind::Category => "category",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "keyword" => Some(TagKind::Keyword),
+            "category" => Some(TagKind::Category),
+            _ => None,
+        }
+    }
+}
+
+/// Names of all crates with the tag
+pub async fn crates(client: &Client, kind: TagKind, tag: &str) -> reqwest::Result<Vec<String>> {
+    cratesio::list_crates(client, &format!("{}={}", kind.as_str(), tag)).await
+}
+
+async fn refresh(client: &Client, db: &Database) {
+    let tags = match db.list_tags().await {
+        Ok(tags) => tags,
+        Err(err) => {
+            tracing::error!("db error while getting subscribed tags: {}", err);
+            return;
+        }
+    };
+
+    for (kind, tag) in tags {
+        let kind = match TagKind::parse(&kind) {
+            Some(kind) => kind,
+            None => continue,
+        };
+        let names = match crates(client, kind, &tag).await {
+            Ok(names) => names,
+            Err(err) => {
+                tracing::warn!("couldn't get crates of {} {}: {}", kind.as_str(), tag, err);
+                continue;
+            }
+        };
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        db.set_tag_crates(kind.as_str(), &tag, &names)
+            .await
+            .unwrap_or_else(|err| tracing::error!("db error while refreshing {}: {}", tag, err));
+    }
+}
+
+/// Refreshes crates of subscribed tags, forever
+pub async fn run(db: Database) {
+    let client = match http_client() {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::error!(
+                "couldn't create http client, tags won't be refreshed: {}",
+                err
+            );
+            return;
+        }
+    };
+
+    loop {
+        tokio::time::delay_for(REFRESH_DELAY).await;
+        tracing::info!("start refreshing crates of tags");
+        refresh(&client, &db).await;
+        tracing::info!("refreshing crates of tags finished");
+    }
+}
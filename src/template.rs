@@ -0,0 +1,208 @@
+//! Templates of notifications about new versions: text with `{placeholder}`s
+use std::convert::TryFrom;
+
+use crate::{compat::Change, krate::Crate, render::escape};
+
+/// Value inserted into a template
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placeholder {
+    Crate,
+    Version,
+    /// docs.rs, crates.io and lib.rs links
+    Links,
+    DocsUrl,
+    CratesUrl,
+    /// Diff with the previous version: compare view of release tags in the repository or diff.rs
+    DiffUrl,
+    /// Release notes from the crate's changelog, empty if there are none
+    Changelog,
+    /// Semver compatibility with the previous version, e.g. `🟥 major`, empty for the first release
+    Change,
+}
+
+impl Placeholder {
+    pub const ALL: [Placeholder; 8] = [
+        Placeholder::Crate,
+        Placeholder::Version,
+        Placeholder::Links,
+        Placeholder::DocsUrl,
+        Placeholder::CratesUrl,
+        Placeholder::DiffUrl,
+        Placeholder::Changelog,
+        Placeholder::Change,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Placeholder::Crate => "crate",
+            Placeholder::Version => "version",
+            Placeholder::Links => "links",
+            Placeholder::DocsUrl => "docs_url",
+            Placeholder::CratesUrl => "crates_url",
+            Placeholder::DiffUrl => "diff_url",
+            Placeholder::Changelog => "changelog",
+            Placeholder::Change => "change",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|p| p.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Placeholder(Placeholder),
+}
+
+#[derive(Debug, PartialEq, Eq, derive_more::Display)]
+pub enum Error {
+    #[display(fmt = "unclosed `{{`")]
+    Unclosed,
+    #[display(fmt = "unmatched `}}`, use `}}}}` for a literal `}}`")]
+    Unmatched,
+    #[display(fmt = "unknown placeholder `{{{}}}`", _0)]
+    Unknown(String),
+}
+
+/// Parsed template. Text outside of placeholders is plain text (not html),
+/// `{{` and `}}` are literal braces.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+/// Values of placeholders
+pub struct Vars<'a> {
+    pub krate: &'a Crate,
+    /// Previous version of the crate, if known
+    pub previous: Option<&'a str>,
+    /// Release notes as telegram html
+    pub changelog: Option<&'a str>,
+    /// Compare view of release tags, diff.rs is used if there is none
+    pub source_diff: Option<&'a str>,
+    pub change: Option<Change>,
+}
+
+impl Template {
+    pub fn parse(src: &str) -> Result<Self, Error> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = src.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '}' => return Err(Error::Unmatched),
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(Error::Unclosed),
+                        }
+                    }
+                    let placeholder =
+                        Placeholder::parse(name.trim()).ok_or(Error::Unknown(name))?;
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Placeholder(placeholder));
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Ok(Self { parts })
+    }
+
+    /// `true` if the template contains the placeholder
+    pub fn uses(&self, placeholder: Placeholder) -> bool {
+        self.parts.contains(&Part::Placeholder(placeholder))
+    }
+
+    /// Renders the template as telegram html
+    pub fn render(&self, vars: &Vars) -> String {
+        let krate = vars.krate;
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.push_str(&escape(text)),
+                Part::Placeholder(placeholder) => out.push_str(&match placeholder {
+                    Placeholder::Crate => escape(&krate.key()),
+                    Placeholder::Version => escape(&krate.id.vers),
+                    Placeholder::Links => krate.html_links(),
+                    Placeholder::DocsUrl => escape(&krate.docsrs()),
+                    Placeholder::CratesUrl => escape(&krate.cratesio()),
+                    Placeholder::DiffUrl => escape(
+                        &vars
+                            .source_diff
+                            .map_or_else(|| krate.diff_url(vars.previous), str::to_owned),
+                    ),
+                    Placeholder::Changelog => vars.changelog.unwrap_or_default().to_owned(),
+                    Placeholder::Change => vars.change.map(Change::label).unwrap_or_default(),
+                }),
+            }
+        }
+
+        out
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = Error;
+
+    fn try_from(src: String) -> Result<Self, Self::Error> {
+        Self::parse(&src)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let krate: Crate =
+            serde_json::from_str(r#"{"name":"serde","vers":"1.0.1","yanked":false}"#).unwrap();
+        let template =
+            Template::parse("{change} {crate} {{{version}}} <new>: { diff_url }\n{changelog}")
+                .unwrap();
+
+        assert!(template.uses(Placeholder::DiffUrl));
+        assert!(!template.uses(Placeholder::Links));
+        assert_eq!(
+            template.render(&Vars {
+                krate: &krate,
+                previous: Some("1.0.0"),
+                changelog: Some("<b>Added</b>"),
+                source_diff: None,
+                change: Some(Change::Patch),
+            }),
+            "🟩 patch serde {1.0.1} &lt;new&gt;: https://diff.rs/serde/1.0.0/1.0.1\n<b>Added</b>"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Template::parse("{crate"), Err(Error::Unclosed));
+        assert_eq!(Template::parse("crate}"), Err(Error::Unmatched));
+        assert_eq!(
+            Template::parse("{name}"),
+            Err(Error::Unknown(String::from("name")))
+        );
+    }
+}
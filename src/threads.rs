@@ -0,0 +1,127 @@
+//! Threads of crates (`/threads on`): a notification replies to the previous notification about
+//! the same crate in the chat, and a pinned index message links the last message of every crate.
+//! Makes long-running group chats browsable per crate.
+use carapax::{
+    methods::{EditMessageText, PinChatMessage, SendMessage},
+    types::ParseMode,
+    Api,
+};
+
+use crate::{bot::HErr, db::Database, render};
+
+/// Ids of supergroups and channels are `-100` followed by the id used in links
+const SUPERGROUP_OFFSET: i64 = 1_000_000_000_000;
+
+/// Link to the message, only messages of supergroups and channels can be linked
+fn message_url(chat_id: i64, message_id: i64) -> Option<String> {
+    if chat_id < -SUPERGROUP_OFFSET {
+        Some(format!(
+            "https://t.me/c/{}/{}",
+            -chat_id - SUPERGROUP_OFFSET,
+            message_id
+        ))
+    } else {
+        None
+    }
+}
+
+/// Telegram html of the index message, `threads` are `(crate, last message)` sorted by crate
+fn index_html(chat_id: i64, threads: &[(String, i64)]) -> String {
+    let lines: Vec<String> = threads
+        .iter()
+        .map(
+            |(krate, message_id)| match message_url(chat_id, *message_id) {
+                Some(url) => format!("— <a href=\"{}\">{}</a>", url, render::escape(krate)),
+                None => format!("— <code>{}</code>", render::escape(krate)),
+            },
+        )
+        .collect();
+
+    render::fit_message(&format!(
+        "📌 <b>Threads of crates</b> (replies to the last update of a crate continue it)\n{}",
+        lines.join("\n")
+    ))
+}
+
+/// Edits the index message of the chat or sends and pins a new one if there is none
+async fn update_index(bot: &Api, db: &Database, chat_id: i64) -> Result<(), HErr> {
+    let text = index_html(chat_id, &db.list_threads(chat_id).await?);
+    if let Some(message_id) = db.get_index_message(chat_id).await? {
+        let edit = EditMessageText::new(chat_id, message_id, text.as_str())
+            .parse_mode(ParseMode::Html)
+            .disable_web_page_preview(true);
+        match bot.execute(edit).await {
+            Ok(_) => return Ok(()),
+            // e.g. the index was deleted, a new one is sent
+            Err(err) => {
+                tracing::debug!("couldn't edit the index of threads of {}: {}", chat_id, err)
+            }
+        }
+    }
+
+    let message = bot
+        .execute(
+            SendMessage::new(chat_id, text.as_str())
+                .parse_mode(ParseMode::Html)
+                .disable_web_page_preview(true)
+                .disable_notification(true),
+        )
+        .await?;
+    db.set_index_message(chat_id, message.id).await?;
+    // pinning needs admin rights in groups, the index is useful without it
+    if let Err(err) = bot
+        .execute(PinChatMessage::new(chat_id, message.id).disable_notification(true))
+        .await
+    {
+        tracing::debug!("couldn't pin the index of threads of {}: {}", chat_id, err);
+    }
+
+    Ok(())
+}
+
+/// Makes the sent message the last one of the crate's thread. `new` if the crate had no thread.
+pub async fn record(
+    bot: &Api,
+    db: &Database,
+    chat_id: i64,
+    krate: &str,
+    message_id: i64,
+    new: bool,
+) {
+    if let Err(err) = db.set_thread(chat_id, krate, message_id).await {
+        tracing::error!("db error while recording the thread of {}: {}", krate, err);
+        return;
+    }
+
+    // without links the index changes only when a crate is added
+    if new || message_url(chat_id, message_id).is_some() {
+        if let Err(err) = update_index(bot, db, chat_id).await {
+            tracing::warn!(
+                "couldn't update the index of threads of {}: {}",
+                chat_id,
+                err
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index() {
+        let threads = [(String::from("serde"), 17), (String::from("tokio"), 42)];
+        assert_eq!(
+            index_html(-1001234567890, &threads),
+            "📌 <b>Threads of crates</b> (replies to the last update of a crate continue it)\n\
+             — <a href=\"https://t.me/c/1234567890/17\">serde</a>\n\
+             — <a href=\"https://t.me/c/1234567890/42\">tokio</a>"
+        );
+        assert_eq!(
+            index_html(-4567, &threads[..1]),
+            "📌 <b>Threads of crates</b> (replies to the last update of a crate continue it)\n\
+             — <code>serde</code>"
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! Version trains: new versions of a crate published in a row within a short window (e.g. fixes
+//! of a botched publish) are held and announced by one notification. Held releases stay pending
+//! in the database, so they're announced after a restart too.
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::index::IndexEvent;
+
+struct Train {
+    /// New versions of the crate, in the order they came
+    events: Vec<IndexEvent>,
+    /// When the last of them came
+    last: Instant,
+}
+
+/// Trains being held, by crate key
+#[derive(Default)]
+pub struct Trains {
+    trains: HashMap<String, Train>,
+}
+
+impl Trains {
+    /// Holds the new version, the window of its train starts over
+    pub fn push(&mut self, event: IndexEvent) {
+        let train = self
+            .trains
+            .entry(event.krate.key())
+            .or_insert_with(|| Train {
+                events: Vec::new(),
+                last: Instant::now(),
+            });
+        train.last = Instant::now();
+        // a release is found twice if it's replayed after a restart
+        if !train
+            .events
+            .iter()
+            .any(|held| held.krate.id.vers == event.krate.id.vers)
+        {
+            train.events.push(event);
+        }
+    }
+
+    /// Releases of the crate held so far, e.g. to announce them before a yank
+    pub fn take(&mut self, key: &str) -> Option<Vec<IndexEvent>> {
+        self.trains.remove(key).map(|train| train.events)
+    }
+
+    /// Trains without new versions for `window`, they aren't held anymore
+    pub fn departed(&mut self, window: Duration) -> Vec<Vec<IndexEvent>> {
+        let keys: Vec<String> = self
+            .trains
+            .iter()
+            .filter(|(_, train)| train.last.elapsed() >= window)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        keys.iter().filter_map(|key| self.take(key)).collect()
+    }
+}
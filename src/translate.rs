@@ -0,0 +1,461 @@
+//! Translation of release notes: their language is guessed from the text and, for chats with
+//! `/translate on` and another `/language`, they're passed through the backend of `[translation]`
+//! of the config before the notification is rendered
+use std::collections::HashMap;
+
+use reqwest::{header::AUTHORIZATION, Client};
+
+use crate::{
+    cfg::{Config, TranslationConfig},
+    metrics,
+    render::plain,
+    util::http_client,
+};
+
+/// Languages of `/language` (ISO 639-1 codes), the ones [`detect`] tells apart
+pub const LANGUAGES: [&str; 11] = [
+    "de", "en", "es", "fr", "it", "ja", "ko", "pt", "ru", "uk", "zh",
+];
+
+/// Frequent short words of languages written in the latin script
+const STOPWORDS: [(&str, &[&str]); 6] = [
+    (
+        "en",
+        &[
+            "the", "and", "of", "to", "is", "in", "for", "with", "when", "now", "was", "are",
+            "this", "that", "be", "by", "from", "it", "an", "on",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "das", "und", "ist", "nicht", "mit", "für", "von", "den", "dem", "ein",
+            "eine", "wird", "werden", "auf", "zu", "auch", "bei", "im",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "la", "les", "et", "des", "est", "pour", "dans", "une", "du", "avec", "sur",
+            "ne", "pas", "qui", "au", "en", "lors", "sont", "cette",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "la", "los", "las", "y", "de", "que", "en", "para", "con", "una", "por", "del",
+            "se", "es", "al", "ahora", "cuando", "lo", "como",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "o", "a", "os", "as", "e", "de", "que", "em", "para", "com", "uma", "por", "do", "da",
+            "não", "é", "ao", "quando", "agora", "dos",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "il", "la", "le", "e", "di", "che", "per", "con", "una", "del", "della", "non", "è",
+            "sono", "gli", "nel", "alla", "anche", "quando", "ora",
+        ],
+    ),
+];
+
+/// Stopwords the most frequent language needs to be told
+const MIN_STOPWORDS: usize = 3;
+
+/// Letters of the text by script
+#[derive(Debug, Default)]
+struct Scripts {
+    latin: usize,
+    cyrillic: usize,
+    /// Letters of Ukrainian, but not of Russian
+    ukrainian: usize,
+    kana: usize,
+    han: usize,
+    hangul: usize,
+}
+
+impl Scripts {
+    fn count(text: &str) -> Self {
+        let mut scripts = Self::default();
+        for c in text.chars() {
+            match c {
+                'a'..='z' | 'A'..='Z' | 'À'..='ɏ' => scripts.latin += 1,
+                'і' | 'ї' | 'є' | 'ґ' | 'І' | 'Ї' | 'Є' | 'Ґ' => {
+                    scripts.cyrillic += 1;
+                    scripts.ukrainian += 1;
+                }
+                '\u{400}'..='\u{4ff}' => scripts.cyrillic += 1,
+                '\u{3040}'..='\u{30ff}' => scripts.kana += 1,
+                '\u{4e00}'..='\u{9fff}' => scripts.han += 1,
+                '\u{1100}'..='\u{11ff}' | '\u{ac00}'..='\u{d7af}' => scripts.hangul += 1,
+                _ => {}
+            }
+        }
+
+        scripts
+    }
+}
+
+/// Language of text in the latin script by its stopwords
+fn by_stopwords(text: &str) -> Option<&'static str> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let mut counts: Vec<(usize, &str)> = STOPWORDS
+        .iter()
+        .map(|(language, stopwords)| {
+            let count = words
+                .iter()
+                .filter(|word| stopwords.contains(&word.as_str()))
+                .count();
+            (count, *language)
+        })
+        .collect();
+    counts.sort_by(|a, b| b.0.cmp(&a.0));
+
+    match counts[..] {
+        [(first, language), (second, _), ..] if first >= MIN_STOPWORDS && first > second => {
+            Some(language)
+        }
+        _ => None,
+    }
+}
+
+/// Language of the text (ISO 639-1 code), `None` if it can't be told. Code in release notes is
+/// in the latin script, so another script wins even if it has fewer letters.
+pub fn detect(text: &str) -> Option<&'static str> {
+    let scripts = Scripts::count(text);
+    let cjk = scripts.kana + scripts.han;
+    let (other, language) = [
+        (scripts.cyrillic, "ru"),
+        (cjk, "zh"),
+        (scripts.hangul, "ko"),
+    ]
+    .iter()
+    .copied()
+    .max_by_key(|(count, _)| *count)?;
+
+    if other == 0 || other * 2 < scripts.latin {
+        return by_stopwords(text);
+    }
+    Some(match language {
+        "ru" if scripts.ukrainian > 0 => "uk",
+        // japanese mixes kana with han characters, chinese doesn't have kana
+        "zh" if scripts.kana * 4 >= cjk => "ja",
+        language => language,
+    })
+}
+
+/// Kind of [`Translator`] in the config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TranslatorKind {
+    /// DeepL api (free or pro)
+    DeepL,
+    /// LibreTranslate, self-hosted or the public instance
+    LibreTranslate,
+}
+
+impl TranslatorKind {
+    pub fn default_url(self) -> &'static str {
+        match self {
+            TranslatorKind::DeepL => "https://api-free.deepl.com",
+            TranslatorKind::LibreTranslate => "https://libretranslate.com",
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            TranslatorKind::DeepL => "deepl",
+            TranslatorKind::LibreTranslate => "libretranslate",
+        }
+    }
+}
+
+#[async_trait::async_trait]
+pub trait Translator {
+    /// Telegram html translated from `from` to `to` (ISO 639-1 codes), `None` if it couldn't be
+    async fn translate(&self, client: &Client, html: &str, from: &str, to: &str) -> Option<String>;
+}
+
+/// Gives the text as it is, used when no backend is configured
+pub struct NoTranslation;
+
+#[async_trait::async_trait]
+impl Translator for NoTranslation {
+    async fn translate(&self, _: &Client, html: &str, _: &str, _: &str) -> Option<String> {
+        Some(html.to_owned())
+    }
+}
+
+pub struct DeepL<'a> {
+    cfg: &'a TranslationConfig,
+}
+
+#[derive(serde::Deserialize)]
+struct DeepLResponse {
+    translations: Vec<DeepLTranslation>,
+}
+
+#[derive(serde::Deserialize)]
+struct DeepLTranslation {
+    text: String,
+}
+
+impl DeepL<'_> {
+    /// DeepL wants the variant of english and portuguese it translates to
+    fn target(language: &str) -> String {
+        match language {
+            "en" => String::from("EN-US"),
+            "pt" => String::from("PT-PT"),
+            language => language.to_uppercase(),
+        }
+    }
+
+    async fn request(
+        &self,
+        client: &Client,
+        html: &str,
+        from: &str,
+        to: &str,
+    ) -> reqwest::Result<DeepLResponse> {
+        let source = from.to_uppercase();
+        let target = Self::target(to);
+        let mut request = client
+            .post(&format!("{}/v2/translate", self.cfg.url()))
+            .form(&[
+                ("text", html),
+                ("source_lang", source.as_str()),
+                ("target_lang", target.as_str()),
+                ("tag_handling", "html"),
+                ("ignore_tags", "code,pre"),
+            ]);
+        if let Some(key) = &self.cfg.api_key {
+            request = request.header(AUTHORIZATION, format!("DeepL-Auth-Key {}", key));
+        }
+
+        request.send().await?.error_for_status()?.json().await
+    }
+}
+
+#[async_trait::async_trait]
+impl Translator for DeepL<'_> {
+    async fn translate(&self, client: &Client, html: &str, from: &str, to: &str) -> Option<String> {
+        let response = self
+            .request(client, html, from, to)
+            .await
+            .map_err(|err| tracing::warn!("couldn't translate with deepl: {}", err))
+            .ok()?;
+        response
+            .translations
+            .into_iter()
+            .next()
+            .map(|translation| translation.text)
+    }
+}
+
+pub struct LibreTranslate<'a> {
+    cfg: &'a TranslationConfig,
+}
+
+#[derive(serde::Serialize)]
+struct LibreTranslateRequest<'a> {
+    q: &'a str,
+    source: &'a str,
+    target: &'a str,
+    format: &'a str,
+    api_key: Option<&'a str>,
+}
+
+#[derive(serde::Deserialize)]
+struct LibreTranslateResponse {
+    #[serde(rename = "translatedText")]
+    translated_text: String,
+}
+
+impl LibreTranslate<'_> {
+    async fn request(
+        &self,
+        client: &Client,
+        html: &str,
+        from: &str,
+        to: &str,
+    ) -> reqwest::Result<LibreTranslateResponse> {
+        client
+            .post(&format!("{}/translate", self.cfg.url()))
+            .json(&LibreTranslateRequest {
+                q: html,
+                source: from,
+                target: to,
+                format: "html",
+                api_key: self.cfg.api_key.as_deref(),
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl Translator for LibreTranslate<'_> {
+    async fn translate(&self, client: &Client, html: &str, from: &str, to: &str) -> Option<String> {
+        self.request(client, html, from, to)
+            .await
+            .map(|response| response.translated_text)
+            .map_err(|err| tracing::warn!("couldn't translate with libretranslate: {}", err))
+            .ok()
+    }
+}
+
+/// Translator of the config, [`NoTranslation`] if there's none
+pub fn translator(cfg: &Config) -> Box<dyn Translator + Send + Sync + '_> {
+    match &cfg.translation {
+        Some(cfg) => match cfg.backend {
+            TranslatorKind::DeepL => Box::new(DeepL { cfg }),
+            TranslatorKind::LibreTranslate => Box::new(LibreTranslate { cfg }),
+        },
+        None => Box::new(NoTranslation),
+    }
+}
+
+/// Release notes (telegram html) in the language `from` translated to `to`, `None` if the backend
+/// failed
+async fn translated(cfg: &Config, html: &str, from: &str, to: &str) -> Option<String> {
+    let client = http_client()
+        .map_err(|err| tracing::error!("couldn't create http client: {}", err))
+        .ok()?;
+    let backend = cfg
+        .translation
+        .as_ref()
+        .map_or("none", |translation| translation.backend.as_str());
+    let translated = translator(cfg).translate(&client, html, from, to).await;
+    let result = if translated.is_some() { "ok" } else { "error" };
+    metrics::TRANSLATIONS
+        .with_label_values(&[backend, result])
+        .inc();
+
+    translated
+}
+
+/// Language of release notes (telegram html), see [`detect`]
+pub fn language(html: &str) -> Option<&'static str> {
+    detect(&plain(html))
+}
+
+/// Release notes (telegram html) tagged with their language, for chats reading another one
+pub fn tagged(language: &str, html: &str) -> String {
+    format!("🌐 <i>{}</i>\n{}", language, html)
+}
+
+/// Release notes of an update translated to languages of chats, each language is translated once
+pub struct Translations<'a> {
+    notes: Option<&'a str>,
+    /// Language of the notes
+    language: Option<&'static str>,
+    translated: HashMap<String, Option<String>>,
+}
+
+impl<'a> Translations<'a> {
+    pub fn new(notes: Option<&'a str>) -> Self {
+        Self {
+            notes,
+            language: notes.and_then(language),
+            translated: HashMap::new(),
+        }
+    }
+
+    /// Language of the notes if it isn't `to` (the language of a chat), `None` if either can't be
+    /// told
+    pub fn foreign(&self, to: Option<&str>) -> Option<&'static str> {
+        match (self.language, to) {
+            (Some(from), Some(to)) if from != to => Some(from),
+            _ => None,
+        }
+    }
+
+    /// The notes translated to `to` (the language of a chat with `/translate on`), `None` if
+    /// they're in it already, their language can't be told or they couldn't be translated
+    pub async fn get(&mut self, to: Option<&str>, cfg: &Config) -> Option<&str> {
+        let (notes, from, to) = match (self.notes, self.foreign(to), to) {
+            (Some(notes), Some(from), Some(to)) => (notes, from, to),
+            _ => return None,
+        };
+        if !self.translated.contains_key(to) {
+            let translation = translated(cfg, notes, from, to).await;
+            self.translated.insert(to.to_owned(), translation);
+        }
+        self.translated[to].as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn languages() {
+        assert_eq!(
+            detect("Fixed a panic in the parser when the input is empty. `Foo::bar` is now const."),
+            Some("en")
+        );
+        assert_eq!(
+            detect("Der Parser stürzt nicht mehr ab, wenn die Eingabe leer ist. `Foo::bar` ist jetzt const."),
+            Some("de")
+        );
+        assert_eq!(
+            detect("Le parseur ne plante plus lorsque l'entrée est vide, et `Foo::bar` est maintenant const."),
+            Some("fr")
+        );
+        assert_eq!(
+            detect("El analizador ya no falla cuando la entrada está vacía y `Foo::bar` ahora es const."),
+            Some("es")
+        );
+        assert_eq!(
+            detect("Исправлено падение парсера на пустом вводе, `Foo::bar` теперь const."),
+            Some("ru")
+        );
+        assert_eq!(
+            detect("Виправлено падіння парсера на порожньому вводі, `Foo::bar` тепер const."),
+            Some("uk")
+        );
+        assert_eq!(
+            detect("修复了解析器在输入为空时崩溃的问题。`Foo::bar` 现在是 const。"),
+            Some("zh")
+        );
+        assert_eq!(
+            detect("入力が空のときにパーサーがパニックする問題を修正しました。"),
+            Some("ja")
+        );
+        assert_eq!(
+            detect("입력이 비어 있을 때 파서가 패닉하는 문제를 수정했습니다."),
+            Some("ko")
+        );
+        assert_eq!(detect("Bump `syn` to 2.0"), None);
+        assert_eq!(detect(""), None);
+    }
+
+    #[test]
+    fn foreign_notes() {
+        let translations = Translations::new(Some(
+            "Der Parser stürzt nicht mehr ab, wenn die Eingabe leer ist.",
+        ));
+        assert_eq!(translations.foreign(Some("en")), Some("de"));
+        assert_eq!(translations.foreign(Some("de")), None);
+        assert_eq!(translations.foreign(None), None);
+        assert_eq!(Translations::new(None).foreign(Some("en")), None);
+    }
+
+    #[test]
+    fn deepl_targets() {
+        assert_eq!(DeepL::target("en"), "EN-US");
+        assert_eq!(DeepL::target("de"), "DE");
+    }
+}
@@ -0,0 +1,245 @@
+//! Trending notifications: total downloads of followed crates are sampled daily, chats with
+//! `/trending on` are notified when a crate reaches a download milestone or its weekly downloads
+//! surge
+use std::{sync::Arc, time::Duration};
+
+use reqwest::Client;
+
+use crate::{
+    cfg::{Config, TrendingConfig},
+    cratesio,
+    db::{Database, Sample},
+    notifier::Notifier,
+    send::SendQueue,
+    util::http_client,
+};
+
+/// How often crates not sampled today are looked for
+const CHECK_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// crates.io asks to make at most one request per second
+const REQUEST_DELAY: Duration = Duration::from_secs(1);
+
+/// Days after a surge during which the crate isn't announced as trending again
+const SURGE_COOLDOWN: i32 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    /// Total downloads reached the milestone
+    Milestone(u64),
+    /// Downloads of the last week, `growth` times the ones of the week before
+    Surge { weekly: u64, growth: f64 },
+}
+
+/// `1000000` → `1M`, milestones are usually round
+fn round(n: u64) -> String {
+    if n >= 1_000_000 && n % 1_000_000 == 0 {
+        format!("{}M", n / 1_000_000)
+    } else if n >= 1_000 && n % 1_000 == 0 {
+        format!("{}k", n / 1_000)
+    } else {
+        n.to_string()
+    }
+}
+
+impl Event {
+    /// Notification about the event as telegram html
+    pub fn html(&self, krate: &str) -> String {
+        match self {
+            Event::Milestone(downloads) => format!(
+                "🎉 <code>{}</code> reached {} downloads",
+                krate,
+                round(*downloads)
+            ),
+            Event::Surge { weekly, growth } => format!(
+                "📈 <code>{}</code> is trending: {} downloads in the last week, {:.1}× the week before",
+                krate,
+                cratesio::compact(*weekly),
+                growth
+            ),
+        }
+    }
+}
+
+/// Events of the crate with `downloads` now and `samples` of the previous days (the newest first).
+///
+/// Samples may be missing for some days (e.g. the bot was down), so downloads between samples are
+/// scaled to a week.
+pub fn events(cfg: &TrendingConfig, downloads: u64, samples: &[Sample]) -> Vec<Event> {
+    let mut events = Vec::new();
+    if let Some(last) = samples.first() {
+        let last = last.downloads as u64;
+        let milestone = cfg
+            .milestones
+            .iter()
+            .copied()
+            .filter(|&m| last < m && m <= downloads)
+            .max();
+        events.extend(milestone.map(Event::Milestone));
+    }
+
+    let announced = samples.iter().any(|s| s.trending && s.age < SURGE_COOLDOWN);
+    let week_ago = samples.iter().find(|s| (7..14).contains(&s.age));
+    let two_weeks_ago = samples.iter().find(|s| s.age >= 14);
+    if let (false, Some(week_ago), Some(two_weeks_ago)) = (announced, week_ago, two_weeks_ago) {
+        let weekly = (downloads as f64 - week_ago.downloads as f64) * 7.0 / week_ago.age as f64;
+        let previous = (week_ago.downloads - two_weeks_ago.downloads) as f64 * 7.0
+            / (two_weeks_ago.age - week_ago.age) as f64;
+        if weekly >= cfg.min_weekly as f64 && previous > 0.0 && weekly >= previous * cfg.growth {
+            events.push(Event::Surge {
+                weekly: weekly as u64,
+                growth: weekly / previous,
+            });
+        }
+    }
+
+    events
+}
+
+/// Samples downloads of the crate and notifies its trending subscribers about events,
+/// crates already sampled today are skipped
+async fn sample(
+    client: &Client,
+    queue: &SendQueue,
+    db: &Database,
+    cfg: &TrendingConfig,
+    krate: &str,
+) {
+    let samples = match db.list_download_samples(krate).await {
+        Ok(samples) => samples,
+        Err(err) => {
+            tracing::error!("db error while getting downloads of {}: {}", krate, err);
+            return;
+        }
+    };
+    if samples.first().map_or(false, |s| s.age == 0) {
+        return;
+    }
+
+    tokio::time::delay_for(REQUEST_DELAY).await;
+    let downloads = match cratesio::downloads(client, krate).await {
+        Ok(downloads) => downloads,
+        Err(err) => {
+            tracing::warn!("couldn't get downloads of {}: {}", krate, err);
+            return;
+        }
+    };
+
+    let events = events(cfg, downloads, &samples);
+    let surge = events.iter().any(|e| matches!(e, Event::Surge { .. }));
+    if let Err(err) = db.add_download_sample(krate, downloads as i64, surge).await {
+        tracing::error!("db error while saving downloads of {}: {}", krate, err);
+        return;
+    }
+    if events.is_empty() {
+        return;
+    }
+
+    let text = events
+        .iter()
+        .map(|e| e.html(krate))
+        .collect::<Vec<_>>()
+        .join("\n");
+    match db.list_trending_subscribers(krate).await {
+        Ok(chats) => {
+            for chat_id in chats {
+                queue.push(chat_id, text.clone(), false);
+            }
+        }
+        Err(err) => tracing::error!("db error while getting subscribers of {}: {}", krate, err),
+    }
+}
+
+/// Samples downloads of followed crates once a day and sends trending notifications, forever
+pub async fn run(queue: SendQueue, db: Database, config: Arc<Config>) {
+    let cfg = match &config.trending {
+        Some(cfg) => cfg,
+        None => return,
+    };
+    let client = match http_client() {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::error!(
+                "couldn't create http client, downloads won't be sampled: {}",
+                err
+            );
+            return;
+        }
+    };
+
+    loop {
+        match db.list_trending_crates().await {
+            Ok(krates) => {
+                for krate in krates {
+                    sample(&client, &queue, &db, cfg, &krate).await;
+                }
+            }
+            Err(err) => tracing::error!("db error while getting trending crates: {}", err),
+        }
+
+        tokio::time::delay_for(CHECK_DELAY).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg() -> TrendingConfig {
+        TrendingConfig {
+            growth: 2.0,
+            min_weekly: 1000,
+            milestones: vec![10_000, 100_000, 1_000_000],
+        }
+    }
+
+    fn sample(age: i32, downloads: i64) -> Sample {
+        Sample {
+            age,
+            downloads,
+            trending: false,
+        }
+    }
+
+    #[test]
+    fn milestones() {
+        let samples = [sample(1, 95_000)];
+        assert_eq!(
+            events(&cfg(), 100_500, &samples),
+            [Event::Milestone(100_000)]
+        );
+        assert!(events(&cfg(), 99_000, &samples).is_empty());
+        assert!(events(&cfg(), 100_500, &[]).is_empty());
+        assert_eq!(
+            Event::Milestone(1_000_000).html("serde"),
+            "🎉 <code>serde</code> reached 1M downloads"
+        );
+    }
+
+    #[test]
+    fn surges() {
+        // 1000 downloads the week before, 3000 the last one
+        let mut samples = vec![sample(1, 3500), sample(7, 1000), sample(14, 0)];
+        assert_eq!(
+            events(&cfg(), 4000, &samples),
+            [Event::Surge {
+                weekly: 3000,
+                growth: 3.0
+            }]
+        );
+        // too few downloads
+        assert!(events(&cfg(), 1900, &samples).is_empty());
+        // already announced
+        samples[0].trending = true;
+        assert!(events(&cfg(), 4000, &samples).is_empty());
+        // a missed day is scaled
+        let samples = [sample(8, 1000), sample(16, 0)];
+        assert_eq!(
+            events(&cfg(), 4000, &samples),
+            [Event::Surge {
+                weekly: 2625,
+                growth: 3.0
+            }]
+        );
+    }
+}
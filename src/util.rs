@@ -4,6 +4,54 @@ use std::{
 };
 use tokio::time::{delay_for, Duration};
 
+pub use crate_upd_core::glob_match;
+
+/// Http client for crates.io api (it requires a user agent) and other sites
+pub fn http_client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .user_agent(concat!(
+            "crate_upd_bot/",
+            env!("CARGO_PKG_VERSION"),
+            " (https://github.com/WaffleLapkin/crate_upd_bot)"
+        ))
+        .timeout(Duration::from_secs(10))
+        .build()
+}
+
+/// Random hex string of 32 characters, for secrets in urls
+pub fn random_token() -> std::io::Result<String> {
+    use std::io::Read;
+
+    let mut bytes = [0; 16];
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(hex::encode(bytes))
+}
+
+/// RFC 3339 date-time (UTC) of a unix timestamp
+pub fn rfc3339(timestamp: i64) -> String {
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let (days, secs) = (timestamp.div_euclid(86400), timestamp.rem_euclid(86400));
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
 /// Path to crate file in crates.io-index. Implementation is stolen from
 /// https://github.com/rust-lang/crates.io/blob/06bfd00ca4c2fce1e9c674d0d792a5ca56d32350/src/git.rs#L179-L187
 pub fn crate_path(name: &str) -> PathBuf {
@@ -0,0 +1,156 @@
+//! How much of the release notes notifications of a chat include (`/verbosity`), and truncation of
+//! notes at line breaks. Notes are telegram html made by [`changelog`](crate::changelog): blocks of
+//! a `<b>Section</b>` line and `• entry` lines, separated by empty lines.
+use crate::render::{self, text_len};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verbosity {
+    /// All notes, truncated to fit the message
+    Full,
+    /// The first section of the notes
+    FirstSection,
+    /// The first entry of the notes
+    HeadlineOnly,
+    /// No notes, only the first line of the notification with its links
+    LinkOnly,
+}
+
+impl Default for Verbosity {
+    fn default() -> Self {
+        Verbosity::Full
+    }
+}
+
+/// Marks notes which were shortened
+const ELLIPSIS: &str = "…";
+
+impl Verbosity {
+    /// Name of the setting in the database and in `/verbosity`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Verbosity::Full => "full",
+            Verbosity::FirstSection => "first-section",
+            Verbosity::HeadlineOnly => "headline-only",
+            Verbosity::LinkOnly => "link-only",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "full" => Some(Verbosity::Full),
+            "first-section" => Some(Verbosity::FirstSection),
+            "headline-only" => Some(Verbosity::HeadlineOnly),
+            "link-only" => Some(Verbosity::LinkOnly),
+            _ => None,
+        }
+    }
+
+    /// Part of the notes shown with this verbosity, `None` if none is. A shortened part ends
+    /// with `…`.
+    pub fn apply(self, notes: &str) -> Option<String> {
+        let notes = notes.trim();
+        let part = match self {
+            Verbosity::Full => return Some(notes.to_owned()),
+            Verbosity::LinkOnly => return None,
+            Verbosity::FirstSection => notes.split("\n\n").next().unwrap_or_default(),
+            Verbosity::HeadlineOnly => notes
+                .lines()
+                .find(|line| !line.is_empty() && !line.starts_with("<b>"))
+                .unwrap_or_default(),
+        };
+
+        if part.is_empty() {
+            None
+        } else if part.len() < notes.len() {
+            Some(format!("{}\n{}", part, ELLIPSIS))
+        } else {
+            Some(part.to_owned())
+        }
+    }
+}
+
+/// Cuts the notes to `max_len` (UTF-16 code units of the text, tags excluded) after a whole
+/// line, so neither an entry nor a tag or an escape is cut in the middle, and marks them with
+/// `…`.
+/// Section headings left without entries are dropped. Only a first line longer than `max_len` is
+/// cut inside of it (by [`render::fit`]). The flag tells whether the notes were cut.
+pub fn truncate(notes: &str, max_len: usize) -> (String, bool) {
+    if text_len(notes) <= max_len {
+        return (notes.to_owned(), false);
+    }
+
+    // the line break and `…`
+    let budget = max_len.saturating_sub(2);
+    let mut lines: Vec<&str> = Vec::new();
+    let mut len = 0;
+    for line in notes.lines() {
+        let line_len = text_len(line) + 1;
+        if len + line_len > budget {
+            break;
+        }
+        len += line_len;
+        lines.push(line);
+    }
+    while let Some(last) = lines.last() {
+        if last.is_empty() || last.starts_with("<b>") {
+            lines.pop();
+        } else {
+            break;
+        }
+    }
+
+    if lines.is_empty() {
+        (render::fit(notes, usize::MAX, max_len), true)
+    } else {
+        (format!("{}\n{}", lines.join("\n"), ELLIPSIS), true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTES: &str = "<b>Added</b>\n• <code>a</code> &amp; b\n• c\n\n<b>Fixed</b>\n• d";
+
+    #[test]
+    fn names() {
+        for verbosity in &[
+            Verbosity::Full,
+            Verbosity::FirstSection,
+            Verbosity::HeadlineOnly,
+            Verbosity::LinkOnly,
+        ] {
+            assert_eq!(Verbosity::parse(verbosity.as_str()), Some(*verbosity));
+        }
+    }
+
+    #[test]
+    fn parts() {
+        assert_eq!(Verbosity::Full.apply(NOTES).as_deref(), Some(NOTES));
+        assert_eq!(
+            Verbosity::FirstSection.apply(NOTES).as_deref(),
+            Some("<b>Added</b>\n• <code>a</code> &amp; b\n• c\n…")
+        );
+        assert_eq!(
+            Verbosity::HeadlineOnly.apply(NOTES).as_deref(),
+            Some("• <code>a</code> &amp; b\n…")
+        );
+        assert_eq!(Verbosity::HeadlineOnly.apply("• a").as_deref(), Some("• a"));
+        assert_eq!(Verbosity::LinkOnly.apply(NOTES), None);
+    }
+
+    #[test]
+    fn truncation() {
+        assert_eq!(truncate(NOTES, 100), (NOTES.to_owned(), false));
+        // the `Fixed` heading fits, but its entry doesn't
+        assert_eq!(
+            truncate(NOTES, 27),
+            (
+                String::from("<b>Added</b>\n• <code>a</code> &amp; b\n• c\n…"),
+                true
+            )
+        );
+        // the first line doesn't fit
+        assert_eq!(truncate(NOTES, 4), (String::from("<b>Add…</b>"), true));
+    }
+}
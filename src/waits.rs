@@ -0,0 +1,59 @@
+//! Waits for versions (`/notify_when foo >=2.0`): a chat is told once about the first release of a
+//! crate matching a semver requirement, e.g. a version with an announced fix, and the wait is
+//! removed. Requirements are read like cargo reads the ones of dependencies (see
+//! [`VersionReq`]).
+use versions::SemVer;
+
+use crate::{db::Database, krate::Crate, notifier::Notifiers, render::escape, send::Receipt};
+
+pub use crate_upd_core::version_req::VersionReq;
+
+/// Longest requirement stored
+pub const MAX_LENGTH: usize = 64;
+
+/// Tells chats waiting for a release of the crate matching their requirement that one of the
+/// `versions` (oldest first, e.g. a version train) matches, the newest matching one
+pub async fn fire(key: &str, versions: &[&Crate], notifiers: &Notifiers, db: &Database) {
+    let waiters = db
+        .list_version_waiters(key)
+        .await
+        .map_err(|err| tracing::error!("db error while getting version waiters: {}", err))
+        .unwrap_or_default();
+
+    for (chat_id, requirement) in waiters {
+        let req = match VersionReq::parse(&requirement) {
+            Some(req) => req,
+            None => continue,
+        };
+        let krate = match versions.iter().rev().find(|krate| {
+            SemVer::new(&krate.id.vers).map_or(false, |version| req.matches(&version))
+        }) {
+            Some(krate) => krate,
+            None => continue,
+        };
+        // the chat may have stopped waiting meanwhile (`/unnotify_when`)
+        match db.take_version_wait(chat_id, key, &requirement).await {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(err) => {
+                tracing::error!("db error while removing version wait: {}", err);
+                continue;
+            }
+        }
+
+        let text = format!(
+            "⏰ <code>{krate} {version}</code> matching <code>{req}</code> was just published {links}\nYou won't be notified about it again, use <code>/subscribe {krate}</code> to follow all releases.",
+            krate = krate.id.name,
+            version = krate.id.vers,
+            req = escape(&requirement),
+            links = krate.html_links(),
+        );
+        let receipt = Receipt {
+            krate: key.to_owned(),
+            version: krate.id.vers.clone(),
+            action: String::from("wait"),
+            single: false,
+        };
+        notifiers.deliver(db, chat_id, text, false, &receipt).await;
+    }
+}
@@ -0,0 +1,159 @@
+//! Subscriptions of a chat as a file: `/export` sends it, sending it back (`/import`) subscribes
+//! to the listed crates. Handy for moving to another account, a backup or a team's watch list.
+use serde::{Deserialize, Serialize};
+
+use crate::{cfg::Config, db::Database, filter::Filter, krate::Crate};
+
+/// Largest number of crates imported at once
+pub const MAX_CRATES: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Watchlist {
+    /// Required, so that other TOML files (e.g. `Cargo.toml`) aren't taken for a watch list
+    pub crates: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    /// Words of `/filter`, e.g. `["minor", "skip-prerelease"]`
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub filter: Filter,
+}
+
+fn is_default(filter: &Filter) -> bool {
+    *filter == Filter::default()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Toml,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "json" => Some(Format::Json),
+            "toml" => Some(Format::Toml),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Toml => "toml",
+        }
+    }
+}
+
+impl Watchlist {
+    pub async fn of_chat(db: &Database, chat_id: i64) -> Result<Self, tokio_postgres::Error> {
+        let crates = db
+            .list_filters(chat_id)
+            .await?
+            .into_iter()
+            .map(|(name, filter)| Entry { name, filter })
+            .collect();
+
+        Ok(Watchlist { crates })
+    }
+
+    pub fn to_text(&self, format: Format) -> String {
+        match format {
+            // serialization of these types can't fail
+            Format::Json => serde_json::to_string_pretty(self).unwrap_or_default(),
+            Format::Toml => toml::to_string(self).unwrap_or_default(),
+        }
+    }
+
+    /// Watch list in either format, `None` if `src` isn't one
+    pub fn parse(src: &str) -> Option<Self> {
+        serde_json::from_str(src)
+            .ok()
+            .or_else(|| toml::from_str(src).ok())
+    }
+
+    /// Subscribes the chat to the listed crates and sets their filters. Returns names of the
+    /// subscribed crates and of the ones which don't exist.
+    pub async fn import(
+        &self,
+        db: &Database,
+        cfg: &Config,
+        chat_id: i64,
+    ) -> Result<(Vec<&str>, Vec<&str>), tokio_postgres::Error> {
+        let mut found = Vec::new();
+        let mut unknown = Vec::new();
+        for entry in self.crates.iter().take(MAX_CRATES) {
+            if Crate::exists(&entry.name, cfg).await {
+                found.push(entry);
+            } else {
+                unknown.push(entry.name.as_str());
+            }
+        }
+
+        let names: Vec<&str> = found.iter().map(|entry| entry.name.as_str()).collect();
+        if !names.is_empty() {
+            db.subscribe_many(chat_id, &names).await?;
+        }
+        for entry in found.iter().filter(|entry| !is_default(&entry.filter)) {
+            db.set_filter(chat_id, &entry.name, &entry.filter).await?;
+        }
+
+        Ok((names, unknown))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::Bump;
+
+    #[test]
+    fn roundtrip() {
+        let list = Watchlist {
+            crates: vec![
+                Entry {
+                    name: String::from("serde"),
+                    filter: Filter::default(),
+                },
+                Entry {
+                    name: String::from("tokio"),
+                    filter: Filter {
+                        min_bump: Bump::Minor,
+                        skip_prerelease: true,
+                        show_deps: false,
+                        readme: false,
+                    },
+                },
+            ],
+        };
+        for &format in &[Format::Json, Format::Toml] {
+            assert_eq!(Watchlist::parse(&list.to_text(format)), Some(list.clone()));
+        }
+        // default filters are omitted
+        assert_eq!(list.to_text(Format::Json).matches("filter").count(), 1);
+    }
+
+    #[test]
+    fn not_a_watchlist() {
+        assert_eq!(
+            Watchlist::parse("[package]\nname = \"foo\"\n\n[dependencies]\nserde = \"1\"\n"),
+            None
+        );
+        assert_eq!(Watchlist::parse("tokio serde"), None);
+        assert_eq!(
+            Watchlist::parse("[[crates]]\nname = \"serde\"\nfilter = [\"major\"]\n"),
+            Some(Watchlist {
+                crates: vec![Entry {
+                    name: String::from("serde"),
+                    filter: Filter {
+                        min_bump: Bump::Major,
+                        ..Filter::default()
+                    },
+                }],
+            })
+        );
+    }
+}
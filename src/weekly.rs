@@ -0,0 +1,221 @@
+//! Weekly reports (`/weekly on`): releases of the chat's crates in the last 7 days in one message,
+//! grouped by their semver impact. Every crate links to its `/history`, releases the chat's
+//! filters skip are only counted.
+use std::time::Duration;
+
+use kacl_parser::VersionScheme;
+use versions::SemVer;
+
+use crate::{
+    cfg::SharedConfig,
+    compat::Change,
+    db::{Database, WeeklyRelease},
+    metrics,
+    notifier::Notifier,
+    render::{self, escape},
+    send::SendQueue,
+    share,
+};
+
+/// How often due reports are checked
+const CHECK_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// `/start` parameter prefix of links showing the history of a crate
+pub const HISTORY_PREFIX: &str = "history_";
+
+/// Groups of the report in the order they are shown
+const GROUPS: [&str; 5] = ["Major", "Minor", "Patch", "Prerelease", "New"];
+
+/// Group of a crate's releases of the week: by the change from the version before the first
+/// one to the last one
+fn group(previous: Option<&str>, last: &str) -> &'static str {
+    let previous = match previous {
+        Some(previous) => previous,
+        None => return "New",
+    };
+    match (SemVer::new(previous), SemVer::new(last)) {
+        (Some(previous), Some(last)) => match Change::between(&previous, &last) {
+            Change::Major => "Major",
+            Change::Minor => "Minor",
+            Change::Patch | Change::Build => "Patch",
+            Change::Prerelease => "Prerelease",
+        },
+        // versions which aren't semver
+        _ => "Patch",
+    }
+}
+
+/// Whether the chat's filter lets the release through, versions are compared by the scheme
+/// detected from the release and the previous one
+fn notified(release: &WeeklyRelease) -> bool {
+    let previous = release.previous.as_deref();
+    let scheme = VersionScheme::detect(previous.into_iter().chain(Some(&*release.version)));
+    release.filter.matches(scheme, &release.version, previous)
+}
+
+/// Runs of releases of the same crate, `releases` are sorted by crate
+fn by_crate(releases: &[WeeklyRelease]) -> Vec<&[WeeklyRelease]> {
+    let mut runs = Vec::new();
+    let mut rest = releases;
+    while let Some(first) = rest.first() {
+        let end = rest
+            .iter()
+            .position(|r| r.krate != first.krate)
+            .unwrap_or(rest.len());
+        let (run, tail) = rest.split_at(end);
+        runs.push(run);
+        rest = tail;
+    }
+    runs
+}
+
+/// Telegram html of the report, `releases` are sorted by crate, oldest first. `None` if there
+/// were no releases. Crates link to `/start history_<crate>` of the bot named `username`.
+pub fn html(releases: &[WeeklyRelease], username: &str) -> Option<String> {
+    if releases.is_empty() {
+        return None;
+    }
+
+    let skipped = releases.iter().filter(|r| !notified(r)).count();
+    let mut groups: Vec<Vec<String>> = vec![Vec::new(); GROUPS.len()];
+    let mut shown = 0;
+    let mut crates = 0;
+    for krate in by_crate(releases) {
+        let passed: Vec<&WeeklyRelease> = krate.iter().filter(|r| notified(r)).collect();
+        let (first, last) = match (passed.first(), passed.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => continue,
+        };
+        shown += passed.len();
+        crates += 1;
+
+        let link = share::link(username, &format!("{}{}", HISTORY_PREFIX, last.krate));
+        let versions = match &first.previous {
+            Some(previous) => format!("{} → {}", previous, last.version),
+            None => last.version.clone(),
+        };
+        let mut line = format!(
+            "— <a href='{}'>{}</a> {}",
+            escape(&link),
+            escape(&last.krate),
+            escape(&versions)
+        );
+        if passed.len() > 1 {
+            line.push_str(&format!(" ({} releases)", passed.len()));
+        }
+        let yanked = passed.iter().filter(|r| r.yanked).count();
+        if yanked > 0 {
+            line.push_str(&format!(" ⚠ {} yanked", yanked));
+        }
+        let idx = GROUPS
+            .iter()
+            .position(|g| *g == group(first.previous.as_deref(), &last.version))
+            .expect("groups are listed");
+        groups[idx].push(line);
+    }
+
+    let mut text = format!(
+        "📅 This week in your stack: {} releases of {} crates.",
+        shown, crates
+    );
+    for (name, lines) in GROUPS.iter().zip(&groups) {
+        if !lines.is_empty() {
+            text.push_str(&format!("\n\n<b>{}</b>\n{}", name, lines.join("\n")));
+        }
+    }
+    if skipped > 0 {
+        text.push_str(&format!(
+            "\n\nReleases skipped by your filters (see /filter): {}.",
+            skipped
+        ));
+    }
+    Some(text)
+}
+
+async fn send(queue: &SendQueue, db: &Database, shared: &SharedConfig, chat_id: i64) {
+    let releases = match db.take_weekly_report(chat_id).await {
+        Ok(releases) => releases,
+        Err(err) => {
+            tracing::error!(
+                "db error while taking weekly report of {}: {}",
+                chat_id,
+                err
+            );
+            return;
+        }
+    };
+    if let Some(text) = html(&releases, &shared.get().bot.username) {
+        queue.push(chat_id, render::fit_message(&text), true);
+    }
+}
+
+/// Sends weekly reports when they are due, forever. They wait in the database while the send queue
+/// is busy.
+pub async fn run(queue: SendQueue, db: Database, shared: SharedConfig) {
+    loop {
+        if queue.is_busy() {
+            metrics::DIGESTS_DEFERRED.inc();
+            tracing::info!("send queue is busy, weekly reports are put off");
+            tokio::time::delay_for(CHECK_DELAY).await;
+            continue;
+        }
+
+        match db.due_weekly_reports().await {
+            Ok(chats) => {
+                for chat_id in chats {
+                    send(&queue, &db, &shared, chat_id).await;
+                }
+            }
+            Err(err) => tracing::error!("db error while getting due weekly reports: {}", err),
+        }
+
+        tokio::time::delay_for(CHECK_DELAY).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{Bump, Filter};
+
+    fn release(krate: &str, previous: Option<&str>, version: &str) -> WeeklyRelease {
+        WeeklyRelease {
+            krate: krate.to_owned(),
+            version: version.to_owned(),
+            previous: previous.map(str::to_owned),
+            yanked: false,
+            filter: Filter::default(),
+        }
+    }
+
+    #[test]
+    fn groups() {
+        assert_eq!(group(Some("1.2.3"), "2.0.0"), "Major");
+        assert_eq!(group(Some("0.3.1"), "0.3.2"), "Patch");
+        assert_eq!(group(Some("1.0.0"), "1.1.0-rc.1"), "Prerelease");
+        assert_eq!(group(None, "0.1.0"), "New");
+    }
+
+    #[test]
+    fn report() {
+        let mut minor_only = release("serde", Some("1.0.1"), "1.0.2");
+        minor_only.filter.min_bump = Bump::Minor;
+        let releases = [
+            release("axum", None, "0.1.0"),
+            minor_only,
+            release("tokio", Some("1.1.0"), "1.2.0"),
+            release("tokio", Some("1.2.0"), "1.2.1"),
+        ];
+
+        assert_eq!(
+            html(&releases, "bot").unwrap(),
+            "📅 This week in your stack: 3 releases of 2 crates.\n\n\
+             <b>Minor</b>\n\
+             — <a href='https://t.me/bot?start=history_tokio'>tokio</a> 1.1.0 → 1.2.1 (2 releases)\n\n\
+             <b>New</b>\n\
+             — <a href='https://t.me/bot?start=history_axum'>axum</a> 0.1.0\n\n\
+             Releases skipped by your filters (see /filter): 1."
+        );
+        assert_eq!(html(&[], "bot"), None);
+    }
+}
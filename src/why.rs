@@ -0,0 +1,498 @@
+//! `/why <crate>`: what brings updates of a crate to the chat and what becomes of its latest
+//! release there. Both are resolved in the order `notify` applies them: the firehose of new
+//! crates and name watches, the operator's rules, `/notify_when`, and then the one subscription
+//! of the chat (its filter, yanks, the baseline, digests, pauses and quiet hours).
+use std::cmp;
+
+use kacl_parser::VersionScheme;
+use tokio_postgres::Error;
+
+use crate::{
+    cfg::Config,
+    db::{Database, Subscriber},
+    filter::Filter,
+    krate::Crate,
+    previous_release, release_notes,
+    render::escape,
+    rules, ActionKind,
+};
+
+/// What brings updates of a crate to a chat, in the order `notify` applies them. Of
+/// `Subscription`, `Owner`, `DepGroup` and `Tag` the first one wins, a release is announced to a
+/// chat once.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Source {
+    /// `/subscribe_new` with its topics (all new crates if there are none)
+    NewCrates(Vec<String>),
+    /// `/watch_name`, the crate isn't published yet
+    NameWatch,
+    /// `/notify_when` with the requirement
+    Wait(String),
+    /// `/subscribe`
+    Subscription,
+    /// `/subscribe_owner`, the subscription was added with the crates of the owner
+    Owner(String),
+    /// `/subscribe_deps`, the subscription was added with the dependencies of the crate
+    DepGroup(String),
+    /// `/subscribe_keyword` or `/subscribe_category`, kind and the tag
+    Tag(String, String),
+}
+
+impl Source {
+    /// Source from a row of `subscription_sources`, `None` for unknown kinds
+    fn from_row(kind: &str, name: String) -> Option<Self> {
+        Some(match kind {
+            "new_crates" => Source::NewCrates(name.split_whitespace().map(str::to_owned).collect()),
+            "name_watch" => Source::NameWatch,
+            "wait" => Source::Wait(name),
+            "subscription" => Source::Subscription,
+            "owner" => Source::Owner(name),
+            "dep_group" => Source::DepGroup(name),
+            "tag" => {
+                let (kind, tag) = name.split_once(':')?;
+                Source::Tag(kind.to_owned(), tag.to_owned())
+            }
+            _ => return None,
+        })
+    }
+
+    /// A subscription to the crate's releases, not a one-off announcement
+    fn is_subscription(&self) -> bool {
+        matches!(
+            self,
+            Source::Subscription | Source::Owner(_) | Source::DepGroup(_) | Source::Tag(..)
+        )
+    }
+
+    fn html(&self) -> String {
+        match self {
+            Source::NewCrates(topics) if topics.is_empty() => {
+                String::from("🌱 first publishes of all crates (/subscribe_new)")
+            }
+            Source::NewCrates(topics) => format!(
+                "🌱 first publishes of crates about {} (/subscribe_new)",
+                topics
+                    .iter()
+                    .map(|topic| format!("<code>{}</code>", escape(topic)))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Source::NameWatch => String::from("👀 the first publish of the name (/watch_name)"),
+            Source::Wait(requirement) => format!(
+                "⏳ the first release matching <code>{}</code> (/notify_when)",
+                escape(requirement)
+            ),
+            Source::Subscription => String::from("🔔 your subscription to it (/subscribe)"),
+            Source::Owner(owner) => format!(
+                "👤 your subscription to it, added with the crates of <code>{}</code> \
+                 (/subscribe_owner)",
+                escape(owner)
+            ),
+            Source::DepGroup(parent) => format!(
+                "📦 your subscription to it, added with the dependencies of <code>{}</code> \
+                 (/subscribe_deps)",
+                escape(parent)
+            ),
+            Source::Tag(kind, tag) => format!(
+                "🏷 the {} <code>{}</code> (/subscribe_{})",
+                escape(kind),
+                escape(tag),
+                escape(kind)
+            ),
+        }
+    }
+}
+
+/// Sources of the rows of `subscription_sources`, in the order of [`Source`]
+fn sources(rows: Vec<(String, String)>) -> Vec<Source> {
+    let mut sources: Vec<Source> = rows
+        .into_iter()
+        .filter_map(|(kind, name)| Source::from_row(&kind, name))
+        .collect();
+    sources.sort();
+    sources.dedup();
+    sources
+}
+
+/// The latest release of a crate and what the operator's rules do with it
+#[derive(Debug)]
+pub struct Latest {
+    pub version: String,
+    pub action: ActionKind,
+    pub scheme: VersionScheme,
+    /// The newest version older than `version`
+    pub previous: Option<String>,
+    /// A rule routes the release to the chat
+    pub routed: bool,
+    /// A rule drops the release
+    pub dropped: bool,
+}
+
+/// What the chat's subscription does with a release, the first step of `notify` deciding it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// A rule of the operator dropped the release
+    Dropped,
+    /// No subscription of the chat covers the crate
+    NotSubscribed,
+    /// `/mute_yanks`
+    MutedYank,
+    /// Yanks of crates followed by a keyword or category aren't sent
+    TagYank,
+    /// The filter of the subscription skips the release
+    Filtered(Filter),
+    /// The version isn't newer than the baseline from the chat's lockfile
+    BelowBaseline(String),
+    /// Queued for the catch-up digest after `/pause`
+    Paused,
+    /// Queued for the daily digest
+    Digest,
+    /// Deferred until quiet hours end
+    Quiet,
+    /// Sent as a notification
+    Sent,
+}
+
+/// Decides the release for the chat's subscription like `notify` does
+pub fn verdict(latest: &Latest, subscriber: Option<&Subscriber>) -> Verdict {
+    if latest.dropped {
+        return Verdict::Dropped;
+    }
+    let subscriber = match subscriber {
+        Some(subscriber) => subscriber,
+        None => return Verdict::NotSubscribed,
+    };
+    let is_yank = matches!(latest.action, ActionKind::Yanked | ActionKind::Unyanked);
+    if is_yank && subscriber.mute_yanks {
+        return Verdict::MutedYank;
+    }
+    if is_yank && subscriber.tagged {
+        return Verdict::TagYank;
+    }
+    let previous = latest.previous.as_deref();
+    if !subscriber
+        .filter
+        .matches(latest.scheme, &latest.version, previous)
+    {
+        return Verdict::Filtered(subscriber.filter);
+    }
+    if let (ActionKind::NewVersion, Some(baseline)) = (&latest.action, &subscriber.baseline) {
+        let order = latest.scheme.compare(&latest.version, baseline);
+        if order.map_or(false, |order| order != cmp::Ordering::Greater) {
+            return Verdict::BelowBaseline(baseline.clone());
+        }
+    }
+    if subscriber.paused {
+        Verdict::Paused
+    } else if subscriber.digest {
+        Verdict::Digest
+    } else if subscriber.quiet {
+        Verdict::Quiet
+    } else {
+        Verdict::Sent
+    }
+}
+
+impl Verdict {
+    fn html(&self) -> String {
+        match self {
+            Verdict::Dropped => String::from(
+                "🚫 a rule of the bot's operator drops it, so subscriptions don't get it",
+            ),
+            Verdict::NotSubscribed => String::from("🔕 no subscription of this chat covers it"),
+            Verdict::MutedYank => String::from("🔇 yanks are muted in this chat (/mute_yanks)"),
+            Verdict::TagYank => {
+                String::from("🔇 yanks of crates followed by a keyword or category aren't sent")
+            }
+            Verdict::Filtered(filter) => format!(
+                "🔕 the subscription gets only {} (/filter)",
+                escape(&filter.to_string())
+            ),
+            Verdict::BelowBaseline(baseline) => format!(
+                "🔕 it isn't newer than <code>{}</code> from your lockfile",
+                escape(baseline)
+            ),
+            Verdict::Paused => String::from(
+                "⏸ notifications are paused, it's queued for the catch-up digest (/resume)",
+            ),
+            Verdict::Digest => String::from("📰 it goes to the daily digest (/digest)"),
+            Verdict::Quiet => {
+                String::from("🌙 it's deferred until the quiet hours are over (/quiet)")
+            }
+            Verdict::Sent => String::from("✅ it's sent as a notification"),
+        }
+    }
+}
+
+/// Telegram html of the explanation, `latest` is `None` if the crate has no releases
+pub fn html(
+    krate: &str,
+    sources: &[Source],
+    latest: Option<&Latest>,
+    subscriber: Option<&Subscriber>,
+) -> String {
+    let krate = escape(krate);
+    let mut text = if sources.is_empty() {
+        format!(
+            "Nothing brings <code>{}</code> updates to this chat: you aren't subscribed to it, \
+             its owners, keywords or categories. Use /subscribe to subscribe.",
+            krate
+        )
+    } else {
+        let mut text = format!(
+            "<code>{}</code> updates come to this chat from (a release is announced once, by \
+             the first subscription):",
+            krate
+        );
+        for (i, source) in sources.iter().enumerate() {
+            text.push_str(&format!("\n{}. {}", i + 1, source.html()));
+        }
+        text
+    };
+
+    let latest = match latest {
+        Some(latest) => latest,
+        None => return text,
+    };
+    let what = match latest.action {
+        ActionKind::NewVersion => "",
+        ActionKind::Yanked => ", a yank",
+        ActionKind::Unyanked => ", an unyank",
+    };
+    text.push_str(&format!(
+        "\n\nWith the current settings the latest release (<code>{} {}</code>{}):",
+        krate,
+        escape(&latest.version),
+        what
+    ));
+    let first_publish =
+        latest.previous.is_none() && matches!(latest.action, ActionKind::NewVersion);
+    if first_publish {
+        if let Some(Source::NewCrates(topics)) = sources.first() {
+            text.push_str(if topics.is_empty() {
+                "\n• 🌱 is announced as a new crate (/subscribe_new)"
+            } else {
+                "\n• 🌱 is announced as a new crate if it's about your topics (/subscribe_new)"
+            });
+        }
+    }
+    if latest.routed {
+        text.push_str("\n• 📬 is routed here by a rule of the bot's operator");
+    }
+    let verdict = verdict(latest, subscriber);
+    let covered = sources.iter().any(Source::is_subscription);
+    if verdict != Verdict::NotSubscribed || covered {
+        text.push_str(&format!("\n• {}", verdict.html()));
+    }
+    let mailed = matches!(
+        verdict,
+        Verdict::Paused | Verdict::Digest | Verdict::Quiet | Verdict::Sent
+    );
+    if mailed && subscriber.map_or(false, |subscriber| subscriber.email) {
+        text.push_str("\n• 📧 is e-mailed too (/email)");
+    }
+    text
+}
+
+/// Explanation of what brings updates of the crate to the chat (`key` is `myreg:name` for
+/// crates of alternative registries)
+pub async fn explain(
+    db: &Database,
+    cfg: &Config,
+    chat_id: i64,
+    key: &str,
+) -> Result<String, Error> {
+    let sources = sources(db.subscription_sources(chat_id, key).await?);
+    let subscriber = db
+        .list_subscribers(key)
+        .await?
+        .into_iter()
+        .find(|subscriber| subscriber.chat_id == chat_id);
+
+    let latest = match Crate::read_all(key, cfg).await {
+        Ok(mut all) => all.pop(),
+        Err(err) => {
+            tracing::debug!("couldn't read versions of {}: {}", key, err);
+            None
+        }
+    };
+    let latest = match latest {
+        Some(krate) => Some(resolve(krate, chat_id, cfg).await),
+        None => None,
+    };
+
+    Ok(html(key, &sources, latest.as_ref(), subscriber.as_ref()))
+}
+
+/// The latest release (the last one in the index) and what the rules do with it for the chat
+async fn resolve(krate: Crate, chat_id: i64, cfg: &Config) -> Latest {
+    let key = krate.key();
+    let action = if krate.yanked {
+        ActionKind::Yanked
+    } else {
+        ActionKind::NewVersion
+    };
+    let (scheme, previous) = previous_release(&key, &krate.id.vers, cfg).await;
+    let previous = previous.map(|previous| previous.id.vers);
+    // notes are fetched only for rules, e.g. `security` ones
+    let notes = if cfg.rules.is_empty() {
+        None
+    } else {
+        let semver = previous.as_deref().and_then(versions::SemVer::new);
+        release_notes(&krate, &action, &[], semver.as_ref(), cfg).await
+    };
+    let routing = rules::evaluate(
+        cfg,
+        &krate,
+        &action,
+        scheme,
+        previous.as_deref(),
+        notes.as_deref(),
+    )
+    .await;
+
+    Latest {
+        version: krate.id.vers.clone(),
+        action,
+        scheme,
+        previous,
+        routed: routing.routes.contains(&chat_id),
+        dropped: routing.drop,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{filter::Bump, verbosity::Verbosity};
+
+    fn subscriber() -> Subscriber {
+        Subscriber {
+            chat_id: 1,
+            filter: Filter::default(),
+            mute_yanks: false,
+            digest: false,
+            baseline: None,
+            template: None,
+            tagged: false,
+            email: false,
+            verbose: false,
+            quiet: false,
+            msrv: None,
+            security_alerts: false,
+            verbosity: Verbosity::Full,
+            paused: false,
+            language: None,
+            translate: false,
+        }
+    }
+
+    fn latest(version: &str, action: ActionKind) -> Latest {
+        Latest {
+            version: version.to_owned(),
+            action,
+            scheme: VersionScheme::Semver,
+            previous: Some(String::from("1.0.0")),
+            routed: false,
+            dropped: false,
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        let row = |kind: &str, name: &str| (kind.to_owned(), name.to_owned());
+        let sources = sources(vec![
+            row("tag", "keyword:serialization"),
+            row("owner", "dtolnay"),
+            row("new_crates", "cli async"),
+            row("wait", "^2"),
+            row("unknown", ""),
+        ]);
+        assert_eq!(
+            sources,
+            [
+                Source::NewCrates(vec![String::from("cli"), String::from("async")]),
+                Source::Wait(String::from("^2")),
+                Source::Owner(String::from("dtolnay")),
+                Source::Tag(String::from("keyword"), String::from("serialization")),
+            ]
+        );
+    }
+
+    #[test]
+    fn verdicts() {
+        let release = latest("1.0.1", ActionKind::NewVersion);
+        let yank = latest("1.0.1", ActionKind::Yanked);
+        let with = |f: fn(&mut Subscriber)| {
+            let mut subscriber = subscriber();
+            f(&mut subscriber);
+            subscriber
+        };
+
+        assert_eq!(verdict(&release, None), Verdict::NotSubscribed);
+        assert_eq!(verdict(&release, Some(&subscriber())), Verdict::Sent);
+        let dropped = Latest {
+            dropped: true,
+            ..latest("1.0.1", ActionKind::NewVersion)
+        };
+        assert_eq!(verdict(&dropped, Some(&subscriber())), Verdict::Dropped);
+
+        // yanks are muted before the filter applies
+        let muted = with(|s| {
+            s.mute_yanks = true;
+            s.filter.min_bump = Bump::Major;
+        });
+        assert_eq!(verdict(&yank, Some(&muted)), Verdict::MutedYank);
+        assert_eq!(
+            verdict(&release, Some(&muted)),
+            Verdict::Filtered(muted.filter)
+        );
+        let tagged = with(|s| s.tagged = true);
+        assert_eq!(verdict(&yank, Some(&tagged)), Verdict::TagYank);
+        assert_eq!(verdict(&release, Some(&tagged)), Verdict::Sent);
+
+        let locked = with(|s| s.baseline = Some(String::from("1.0.1")));
+        assert_eq!(
+            verdict(&release, Some(&locked)),
+            Verdict::BelowBaseline(String::from("1.0.1"))
+        );
+        assert_eq!(verdict(&yank, Some(&locked)), Verdict::Sent);
+
+        // a pause wins over the digest, both over quiet hours
+        let paused = with(|s| {
+            s.paused = true;
+            s.digest = true;
+            s.quiet = true;
+        });
+        assert_eq!(verdict(&release, Some(&paused)), Verdict::Paused);
+        let digest = with(|s| {
+            s.digest = true;
+            s.quiet = true;
+        });
+        assert_eq!(verdict(&release, Some(&digest)), Verdict::Digest);
+        assert_eq!(
+            verdict(&release, Some(&with(|s| s.quiet = true))),
+            Verdict::Quiet
+        );
+    }
+
+    #[test]
+    fn explanation() {
+        let sources = [Source::Subscription];
+        let text = html("a<b", &sources, None, Some(&subscriber()));
+        assert!(text.starts_with("<code>a&lt;b</code> updates come"));
+        assert!(text.contains("\n1. 🔔 your subscription to it (/subscribe)"));
+
+        let routed = Latest {
+            routed: true,
+            dropped: true,
+            ..latest("1.0.1", ActionKind::NewVersion)
+        };
+        let text = html("demo", &[], Some(&routed), None);
+        assert!(text.starts_with("Nothing brings <code>demo</code> updates"));
+        assert!(text.ends_with(
+            "(<code>demo 1.0.1</code>):\n• 📬 is routed here by a rule of the bot's operator\n\
+             • 🚫 a rule of the bot's operator drops it, so subscriptions don't get it"
+        ));
+    }
+}
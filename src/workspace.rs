@@ -0,0 +1,326 @@
+//! Workspace releases: crates sharing a repository (e.g. the `futures-*` family) are usually
+//! published together. With `[workspace] window_secs` set, their new versions published within the
+//! window are held and announced by one telegram message per chat, with a section for each crate.
+//! Held releases stay pending in the database, so they're announced after a restart too.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    cfg::WorkspaceConfig,
+    cratesio,
+    db::Database,
+    index::IndexEvent,
+    notifier::Notifiers,
+    render::{self, escape},
+    repo::Repo,
+    send::Receipt,
+    util::http_client,
+};
+
+/// Repositories of crates remembered before expired ones are forgotten
+const MAX_REPOSITORIES: usize = 10_000;
+
+/// Host and path of the repository url on a supported forge, e.g.
+/// `github.com/rust-lang/futures-rs`. Crates of a workspace often link different trees of the
+/// repository, the identity doesn't depend on it.
+pub fn identity(url: &str) -> Option<String> {
+    let repo = Repo::parse(url)?;
+    Some(format!("{}/{}", repo.host, repo.path).to_lowercase())
+}
+
+struct Group {
+    /// Trains of the crates, in the order they came
+    trains: Vec<Vec<IndexEvent>>,
+    /// When the first and the last of them came
+    first: Instant,
+    last: Instant,
+}
+
+/// Releases being held by repository, and the repositories of crates
+#[derive(Default)]
+pub struct Workspaces {
+    groups: HashMap<String, Group>,
+    /// Identity of the crate's repository (`None` if it has none) and when it was looked up
+    repositories: HashMap<String, (Option<String>, Instant)>,
+}
+
+impl Workspaces {
+    /// Identity of the repository of the crates.io crate, looked up once per `repository_ttl_secs`
+    pub async fn repository(&mut self, name: &str, cfg: &WorkspaceConfig) -> Option<String> {
+        let ttl = Duration::from_secs(cfg.repository_ttl_secs);
+        if let Some((repository, at)) = self.repositories.get(name) {
+            if at.elapsed() < ttl {
+                return repository.clone();
+            }
+        }
+
+        let client = http_client()
+            .map_err(|err| tracing::error!("couldn't create http client: {}", err))
+            .ok()?;
+        // errors aren't remembered, the repository is looked up again with the next release
+        let repository = match cratesio::repository(&client, name).await {
+            Ok(url) => url.as_deref().and_then(identity),
+            Err(err) => {
+                tracing::warn!("couldn't get repository of {}: {}", name, err);
+                return None;
+            }
+        };
+        if self.repositories.len() >= MAX_REPOSITORIES {
+            self.repositories.retain(|_, (_, at)| at.elapsed() < ttl);
+        }
+        self.repositories
+            .insert(name.to_owned(), (repository.clone(), Instant::now()));
+        repository
+    }
+
+    /// Holds the train, the window of the repository's group starts over
+    pub fn push(&mut self, repository: String, train: Vec<IndexEvent>) {
+        let group = self.groups.entry(repository).or_insert_with(|| Group {
+            trains: Vec::new(),
+            first: Instant::now(),
+            last: Instant::now(),
+        });
+        group.last = Instant::now();
+        group.trains.push(train);
+    }
+
+    /// Repository of the group holding releases of the crate
+    pub fn holding(&self, key: &str) -> Option<String> {
+        self.groups
+            .iter()
+            .find(|(_, group)| {
+                group
+                    .trains
+                    .iter()
+                    .any(|train| train.last().map_or(false, |event| event.krate.key() == key))
+            })
+            .map(|(repository, _)| repository.clone())
+    }
+
+    /// Trains held for the repository
+    pub fn take(&mut self, repository: &str) -> Option<Vec<Vec<IndexEvent>>> {
+        self.groups.remove(repository).map(|group| group.trains)
+    }
+
+    /// Groups without new releases for `window` or held for `max_hold` with their repositories,
+    /// they aren't held anymore
+    pub fn departed(
+        &mut self,
+        window: Duration,
+        max_hold: Duration,
+    ) -> Vec<(String, Vec<Vec<IndexEvent>>)> {
+        let repositories: Vec<String> = self
+            .groups
+            .iter()
+            .filter(|(_, group)| {
+                group.last.elapsed() >= window || group.first.elapsed() >= max_hold
+            })
+            .map(|(repository, _)| repository.clone())
+            .collect();
+
+        repositories
+            .into_iter()
+            .filter_map(|repository| {
+                let trains = self.take(&repository)?;
+                Some((repository, trains))
+            })
+            .collect()
+    }
+}
+
+struct Collected {
+    chat_id: i64,
+    text: String,
+    quiet: bool,
+    receipt: Receipt,
+}
+
+/// Telegram notifications about the releases of a workspace, collected instead of sent to be
+/// combined by chat
+#[derive(Clone, Default)]
+pub struct Collector {
+    messages: Arc<Mutex<Vec<Collected>>>,
+}
+
+impl Collector {
+    pub fn push(&self, chat_id: i64, text: String, quiet: bool, receipt: &Receipt) {
+        let collected = Collected {
+            chat_id,
+            text,
+            quiet,
+            receipt: receipt.clone(),
+        };
+        self.messages
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(collected);
+    }
+
+    /// Collected messages by chat, in the order the chats got their first message
+    fn take(&self) -> Vec<(i64, Vec<Collected>)> {
+        let mut messages = self.messages.lock().unwrap_or_else(|err| err.into_inner());
+        let messages = std::mem::take(&mut *messages);
+        let mut chats: Vec<(i64, Vec<Collected>)> = Vec::new();
+        for message in messages {
+            match chats
+                .iter_mut()
+                .find(|(chat_id, _)| *chat_id == message.chat_id)
+            {
+                Some((_, chat)) => chat.push(message),
+                None => chats.push((message.chat_id, vec![message])),
+            }
+        }
+        chats
+    }
+}
+
+/// One message with a section (fitted into an equal share of the telegram limits) per crate
+fn combined(repository: &str, sections: &[String]) -> String {
+    let path = repository
+        .split_once('/')
+        .map_or(repository, |(_, path)| path);
+    let header = format!(
+        "📦 <b>{} crates</b> of <a href=\"https://{}\">{}</a> were released:",
+        sections.len(),
+        escape(repository),
+        escape(path),
+    );
+    let n = sections.len().max(1);
+    // two entities of the header, two line breaks before each section
+    let entities = (render::MAX_ENTITIES - 2) / n;
+    let len = render::MAX_LENGTH.saturating_sub(render::text_len(&header)) / n;
+
+    let mut text = header;
+    for section in sections {
+        text.push_str("\n\n");
+        text.push_str(&render::fit(section, entities, len.saturating_sub(2)));
+    }
+    text
+}
+
+/// Delivers the collected notifications, a chat notified about several crates gets them combined
+/// into one message. It's receipted as the notification about the last crate, the other crates
+/// are recorded as delivered to the chat along with it.
+pub async fn deliver(
+    repository: &str,
+    collector: &Collector,
+    notifiers: &Notifiers,
+    db: &Database,
+) {
+    for (chat_id, mut messages) in collector.take() {
+        let last = match messages.pop() {
+            Some(last) => last,
+            None => continue,
+        };
+        if messages.is_empty() {
+            notifiers
+                .deliver(db, chat_id, last.text, last.quiet, &last.receipt)
+                .await;
+            continue;
+        }
+
+        for Collected { receipt, .. } in &messages {
+            db.record_delivery(&receipt.krate, &receipt.version, &receipt.action, chat_id)
+                .await
+                .unwrap_or_else(|err| {
+                    tracing::error!("db error while recording delivery: {}", err)
+                });
+        }
+        let quiet = last.quiet && messages.iter().all(|message| message.quiet);
+        let receipt = Receipt {
+            single: false,
+            ..last.receipt
+        };
+        let sections: Vec<String> = messages
+            .into_iter()
+            .map(|message| message.text)
+            .chain(Some(last.text))
+            .collect();
+        notifiers
+            .deliver(
+                db,
+                chat_id,
+                combined(repository, &sections),
+                quiet,
+                &receipt,
+            )
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{krate::Crate, ActionKind};
+
+    fn event(name: &str) -> IndexEvent {
+        let krate: Crate = serde_json::from_str(&format!(
+            r#"{{"name": "{}", "vers": "0.3.0", "yanked": false}}"#,
+            name
+        ))
+        .unwrap();
+        IndexEvent {
+            krate,
+            kind: ActionKind::NewVersion,
+            published_at: 0,
+            created: false,
+        }
+    }
+
+    #[test]
+    fn identities() {
+        assert_eq!(
+            identity("https://github.com/rust-lang/futures-rs").as_deref(),
+            Some("github.com/rust-lang/futures-rs")
+        );
+        assert_eq!(
+            identity("https://github.com/rust-lang/Futures-rs/tree/master/futures-util").as_deref(),
+            Some("github.com/rust-lang/futures-rs")
+        );
+        assert_eq!(identity("https://example.com"), None);
+    }
+
+    #[test]
+    fn groups() {
+        let mut workspaces = Workspaces::default();
+        let repository = String::from("github.com/rust-lang/futures-rs");
+        workspaces.push(repository.clone(), vec![event("futures-core")]);
+        workspaces.push(repository.clone(), vec![event("futures-util")]);
+        assert_eq!(workspaces.holding("futures-util"), Some(repository.clone()));
+        assert_eq!(workspaces.holding("futures"), None);
+        let (minute, hour) = (Duration::from_secs(60), Duration::from_secs(60 * 60));
+        assert!(workspaces.departed(minute, hour).is_empty());
+
+        let departed = workspaces.departed(Duration::from_secs(0), hour);
+        assert_eq!(departed.len(), 1);
+        assert_eq!(departed[0].0, repository);
+        assert_eq!(departed[0].1.len(), 2);
+        assert_eq!(workspaces.holding("futures-util"), None);
+
+        // new releases keep coming within the window
+        workspaces.push(repository.clone(), vec![event("futures-core")]);
+        workspaces.push(repository.clone(), vec![event("futures-util")]);
+        let departed = workspaces.departed(minute, Duration::from_secs(0));
+        assert_eq!(departed.len(), 1);
+        assert_eq!(departed[0].1.len(), 2);
+    }
+
+    #[test]
+    fn combined_text() {
+        let sections = [
+            String::from("<b>futures-core</b> 0.3.0"),
+            String::from("<b>futures-util</b> 0.3.0"),
+        ];
+        assert_eq!(
+            combined("github.com/rust-lang/futures-rs", &sections),
+            "📦 <b>2 crates</b> of <a href=\"https://github.com/rust-lang/futures-rs\">rust-lang/futures-rs</a> were released:\n\n<b>futures-core</b> 0.3.0\n\n<b>futures-util</b> 0.3.0"
+        );
+
+        let long = String::from("x").repeat(render::MAX_LENGTH);
+        let text = combined("github.com/a/b", &[long.clone(), long]);
+        assert!(render::text_len(&text) <= render::MAX_LENGTH);
+    }
+}